@@ -1,6 +1,8 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
-        .build_server(false)
+        .build_server(std::env::var_os("CARGO_FEATURE_TEST_UTIL").is_some())
+        // tonic has no transport on wasm32
+        .build_transport(std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("wasm32"))
         .compile_protos(&["proto/sql.proto"], &["proto"])?;
     Ok(())
 }
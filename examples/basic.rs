@@ -31,7 +31,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Execute an update
-    let rows_affected = conn
+    let inserted = conn
         .execute(
             "INSERT INTO users (name, email) VALUES (?, ?)",
             &[
@@ -40,7 +40,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ],
         )
         .await?;
-    println!("Rows affected: {}", rows_affected);
+    println!(
+        "Rows affected: {}, new id: {}",
+        inserted.rows_affected, inserted.last_insert_rowid
+    );
 
     // Transaction example
     conn.begin_transaction().await?;
@@ -0,0 +1,70 @@
+/*
+ * C ABI for litesql-ha. Build the litesql-ha-ffi crate to produce the shared
+ * library. See src/lib.rs for ownership rules.
+ */
+
+#ifndef LITESQL_HA_H
+#define LITESQL_HA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LITESQL_OK 0
+#define LITESQL_ERROR 1
+#define LITESQL_INVALID_ARGUMENT 2
+#define LITESQL_CONNECTION_CLOSED 3
+#define LITESQL_TIMEOUT 4
+#define LITESQL_QUERY_ERROR 5
+#define LITESQL_TRANSPORT_ERROR 6
+#define LITESQL_PANIC 7
+
+#define LITESQL_ROW 100
+#define LITESQL_DONE 101
+
+#define LITESQL_NULL 0
+#define LITESQL_INTEGER 1
+#define LITESQL_FLOAT 2
+#define LITESQL_TEXT 3
+#define LITESQL_BLOB 4
+
+typedef struct LitesqlDatasource LitesqlDatasource;
+typedef struct LitesqlConnection LitesqlConnection;
+typedef struct LitesqlResult LitesqlResult;
+
+const char *litesql_last_error(void);
+
+int litesql_datasource_open(const char *url, const char *token, LitesqlDatasource **out);
+void litesql_datasource_free(LitesqlDatasource *ds);
+
+int litesql_connection_open(const LitesqlDatasource *ds, LitesqlConnection **out);
+void litesql_connection_free(LitesqlConnection *conn);
+
+int litesql_query(const LitesqlConnection *conn, const char *sql, const char *params_json,
+                  LitesqlResult **out);
+int litesql_execute(const LitesqlConnection *conn, const char *sql, const char *params_json,
+                    int64_t *rows_affected);
+
+void litesql_result_free(LitesqlResult *result);
+size_t litesql_result_column_count(const LitesqlResult *result);
+const char *litesql_result_column_name(const LitesqlResult *result, size_t column);
+size_t litesql_result_row_count(const LitesqlResult *result);
+int64_t litesql_result_rows_affected(const LitesqlResult *result);
+int64_t litesql_result_last_insert_rowid(const LitesqlResult *result);
+int litesql_result_next(LitesqlResult *result);
+int litesql_result_value_type(const LitesqlResult *result, size_t column);
+int64_t litesql_result_int64(const LitesqlResult *result, size_t column);
+double litesql_result_double(const LitesqlResult *result, size_t column);
+/* NUL-terminated; *len (if len is not NULL) is the length without the
+ * terminator and also covers text containing NUL bytes. */
+const char *litesql_result_text(const LitesqlResult *result, size_t column, size_t *len);
+const uint8_t *litesql_result_blob(const LitesqlResult *result, size_t column, size_t *len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LITESQL_HA_H */
@@ -0,0 +1,680 @@
+//! C ABI for the litesql-ha blocking client.
+//!
+//! Built as a shared library. All handles are opaque pointers created and
+//! destroyed by this library:
+//!
+//! - `litesql_datasource_open` / `litesql_datasource_free`
+//! - `litesql_connection_open` / `litesql_connection_free`
+//! - `litesql_query` / `litesql_result_free`
+//!
+//! A connection must be freed before the data source it was opened from.
+//! Strings returned by this library are owned by the handle they came from
+//! and must not be freed by the caller. Every fallible function returns a
+//! status code; on failure, `litesql_last_error` returns a message describing
+//! the last error raised on the calling thread.
+//!
+//! Query parameters are passed as a JSON array (`[1, "alice", null]`), or a
+//! null pointer for no parameters. The matching C header is
+//! `include/litesql_ha.h` in this crate.
+
+use litesql_ha::blocking::{HAConnection, HADataSource};
+use litesql_ha::client::ExecutionResult;
+use litesql_ha::{Error, HADataSourceOptions, Value};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Success.
+pub const LITESQL_OK: c_int = 0;
+/// Unclassified error.
+pub const LITESQL_ERROR: c_int = 1;
+/// A null pointer or malformed argument was passed.
+pub const LITESQL_INVALID_ARGUMENT: c_int = 2;
+/// The connection is closed.
+pub const LITESQL_CONNECTION_CLOSED: c_int = 3;
+/// The operation timed out.
+pub const LITESQL_TIMEOUT: c_int = 4;
+/// The server rejected the query.
+pub const LITESQL_QUERY_ERROR: c_int = 5;
+/// The gRPC transport failed.
+pub const LITESQL_TRANSPORT_ERROR: c_int = 6;
+/// A Rust panic was caught at the FFI boundary.
+pub const LITESQL_PANIC: c_int = 7;
+
+/// `litesql_result_next` returned a row.
+pub const LITESQL_ROW: c_int = 100;
+/// `litesql_result_next` reached the end of the result.
+pub const LITESQL_DONE: c_int = 101;
+
+/// Column value types returned by `litesql_result_value_type`.
+pub const LITESQL_NULL: c_int = 0;
+/// Integer value.
+pub const LITESQL_INTEGER: c_int = 1;
+/// Floating point value.
+pub const LITESQL_FLOAT: c_int = 2;
+/// Text value.
+pub const LITESQL_TEXT: c_int = 3;
+/// Binary value.
+pub const LITESQL_BLOB: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque data source handle.
+pub struct LitesqlDatasource(HADataSource);
+
+/// Opaque connection handle.
+pub struct LitesqlConnection(HAConnection);
+
+/// Opaque query result handle with a row cursor.
+pub struct LitesqlResult {
+    result: ExecutionResult,
+    columns: Vec<CString>,
+    cursor: Option<usize>,
+    /// Text of each column of the current row, NUL-terminated
+    text: Vec<Option<Vec<u8>>>,
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn error_code(error: &Error) -> c_int {
+    set_last_error(error.to_string());
+    match error {
+        Error::ConnectionClosed => LITESQL_CONNECTION_CLOSED,
+        Error::Timeout => LITESQL_TIMEOUT,
+        Error::Query(_) | Error::Status(_) => LITESQL_QUERY_ERROR,
+        Error::Transport(_) => LITESQL_TRANSPORT_ERROR,
+        Error::InvalidParameter(_) | Error::TypeConversion(_) | Error::UrlParse(_) => {
+            LITESQL_INVALID_ARGUMENT
+        }
+        _ => LITESQL_ERROR,
+    }
+}
+
+fn invalid_argument(message: &str) -> c_int {
+    set_last_error(message);
+    LITESQL_INVALID_ARGUMENT
+}
+
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => code,
+        Err(_) => {
+            set_last_error("panic in litesql-ha");
+            LITESQL_PANIC
+        }
+    }
+}
+
+unsafe fn optional_str<'a>(s: *const c_char) -> Result<Option<&'a str>, c_int> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(Some)
+        .map_err(|_| invalid_argument("string is not valid UTF-8"))
+}
+
+fn parse_params(json: Option<&str>) -> Result<Vec<Value>, c_int> {
+    let json = match json {
+        Some(j) => j,
+        None => return Ok(vec![]),
+    };
+
+    let parsed: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| invalid_argument(&format!("invalid parameters JSON: {}", e)))?;
+
+    let items = match parsed {
+        serde_json::Value::Array(items) => items,
+        _ => return Err(invalid_argument("parameters must be a JSON array")),
+    };
+
+    items
+        .into_iter()
+        .map(|item| match item {
+            serde_json::Value::Null => Ok(Value::Null),
+            serde_json::Value::Bool(b) => Ok(Value::Bool(b)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Ok(Value::Int64(i)),
+                None => Ok(Value::Double(n.as_f64().unwrap_or_default())),
+            },
+            serde_json::Value::String(s) => Ok(Value::String(s)),
+            other => Ok(Value::String(other.to_string())),
+        })
+        .collect()
+}
+
+/// Return the message of the last error raised on the calling thread, or null.
+///
+/// The returned string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn litesql_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match *e.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Open a data source.
+///
+/// # Safety
+///
+/// `url` must be a valid NUL-terminated string, `token` must be null or a
+/// valid NUL-terminated string, and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_datasource_open(
+    url: *const c_char,
+    token: *const c_char,
+    out: *mut *mut LitesqlDatasource,
+) -> c_int {
+    guard(|| {
+        if url.is_null() || out.is_null() {
+            return invalid_argument("url and out must not be null");
+        }
+        let url = match optional_str(url) {
+            Ok(Some(u)) => u.to_string(),
+            Ok(None) => unreachable!(),
+            Err(code) => return code,
+        };
+        let token = match optional_str(token) {
+            Ok(t) => t.map(str::to_string),
+            Err(code) => return code,
+        };
+
+        match HADataSource::new(HADataSourceOptions {
+            url,
+            password: token,
+            ..Default::default()
+        }) {
+            Ok(ds) => {
+                *out = Box::into_raw(Box::new(LitesqlDatasource(ds)));
+                LITESQL_OK
+            }
+            Err(e) => error_code(&e),
+        }
+    })
+}
+
+/// Free a data source.
+///
+/// # Safety
+///
+/// `ds` must be null or a handle returned by `litesql_datasource_open` that
+/// has not been freed, and all connections opened from it must be freed.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_datasource_free(ds: *mut LitesqlDatasource) {
+    if !ds.is_null() {
+        guard(|| {
+            drop(Box::from_raw(ds));
+            LITESQL_OK
+        });
+    }
+}
+
+/// Open a connection from a data source.
+///
+/// # Safety
+///
+/// `ds` must be a live data source handle and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_connection_open(
+    ds: *const LitesqlDatasource,
+    out: *mut *mut LitesqlConnection,
+) -> c_int {
+    guard(|| {
+        if ds.is_null() || out.is_null() {
+            return invalid_argument("ds and out must not be null");
+        }
+        match (*ds).0.get_connection() {
+            Ok(conn) => {
+                *out = Box::into_raw(Box::new(LitesqlConnection(conn)));
+                LITESQL_OK
+            }
+            Err(e) => error_code(&e),
+        }
+    })
+}
+
+/// Close and free a connection.
+///
+/// # Safety
+///
+/// `conn` must be null or a live connection handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_connection_free(conn: *mut LitesqlConnection) {
+    if !conn.is_null() {
+        guard(|| {
+            let conn = Box::from_raw(conn);
+            let _ = conn.0.close();
+            LITESQL_OK
+        });
+    }
+}
+
+/// Execute a query and return a result handle.
+///
+/// # Safety
+///
+/// `conn` must be a live connection handle, `sql` a valid NUL-terminated
+/// string, `params_json` null or a valid NUL-terminated string, and `out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_query(
+    conn: *const LitesqlConnection,
+    sql: *const c_char,
+    params_json: *const c_char,
+    out: *mut *mut LitesqlResult,
+) -> c_int {
+    guard(|| {
+        if conn.is_null() || sql.is_null() || out.is_null() {
+            return invalid_argument("conn, sql and out must not be null");
+        }
+        let (sql, params) = match (optional_str(sql), optional_str(params_json)) {
+            (Ok(Some(sql)), Ok(params)) => match parse_params(params) {
+                Ok(p) => (sql, p),
+                Err(code) => return code,
+            },
+            (Err(code), _) | (_, Err(code)) => return code,
+            (Ok(None), _) => unreachable!(),
+        };
+
+        match (*conn).0.query(sql, &params) {
+            Ok(result) => {
+                let columns = result
+                    .columns
+                    .iter()
+                    .map(|c| CString::new(c.replace('\0', " ")).unwrap_or_default())
+                    .collect();
+                *out = Box::into_raw(Box::new(LitesqlResult {
+                    result,
+                    columns,
+                    cursor: None,
+                    text: vec![],
+                }));
+                LITESQL_OK
+            }
+            Err(e) => error_code(&e),
+        }
+    })
+}
+
+/// Execute an INSERT/UPDATE/DELETE statement.
+///
+/// # Safety
+///
+/// `conn` must be a live connection handle, `sql` a valid NUL-terminated
+/// string, `params_json` null or a valid NUL-terminated string, and
+/// `rows_affected` null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_execute(
+    conn: *const LitesqlConnection,
+    sql: *const c_char,
+    params_json: *const c_char,
+    rows_affected: *mut i64,
+) -> c_int {
+    guard(|| {
+        if conn.is_null() || sql.is_null() {
+            return invalid_argument("conn and sql must not be null");
+        }
+        let (sql, params) = match (optional_str(sql), optional_str(params_json)) {
+            (Ok(Some(sql)), Ok(params)) => match parse_params(params) {
+                Ok(p) => (sql, p),
+                Err(code) => return code,
+            },
+            (Err(code), _) | (_, Err(code)) => return code,
+            (Ok(None), _) => unreachable!(),
+        };
+
+        match (*conn).0.execute(sql, &params) {
+            Ok(result) => {
+                if !rows_affected.is_null() {
+                    *rows_affected = result.rows_affected;
+                }
+                LITESQL_OK
+            }
+            Err(e) => error_code(&e),
+        }
+    })
+}
+
+/// Free a result.
+///
+/// # Safety
+///
+/// `result` must be null or a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_free(result: *mut LitesqlResult) {
+    if !result.is_null() {
+        guard(|| {
+            drop(Box::from_raw(result));
+            LITESQL_OK
+        });
+    }
+}
+
+/// Number of columns in a result.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_column_count(result: *const LitesqlResult) -> usize {
+    (*result).columns.len()
+}
+
+/// Name of a column, or null if out of range.
+///
+/// # Safety
+///
+/// `result` must be a live result handle. The returned string is owned by the
+/// result.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_column_name(
+    result: *const LitesqlResult,
+    column: usize,
+) -> *const c_char {
+    let result = &*result;
+    result
+        .columns
+        .get(column)
+        .map(|c| c.as_ptr())
+        .unwrap_or(ptr::null())
+}
+
+/// Number of rows in a result.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_row_count(result: *const LitesqlResult) -> usize {
+    (*result).result.row_count()
+}
+
+/// Number of rows affected by the statement.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_rows_affected(result: *const LitesqlResult) -> i64 {
+    (*result).result.rows_affected
+}
+
+/// Rowid of the last row inserted on the server session after the statement.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_last_insert_rowid(result: *const LitesqlResult) -> i64 {
+    (*result).result.last_insert_rowid
+}
+
+/// Advance to the next row. Returns `LITESQL_ROW` or `LITESQL_DONE`.
+///
+/// # Safety
+///
+/// `result` must be a live result handle. Strings and blobs returned for the
+/// previous row are invalidated.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_next(result: *mut LitesqlResult) -> c_int {
+    let result = &mut *result;
+    let next = result.cursor.map(|c| c + 1).unwrap_or(0);
+    result.cursor = Some(next);
+
+    match result.result.rows.get(next) {
+        Some(row) => {
+            result.text = row
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => Some(nul_terminated(s)),
+                    #[cfg(feature = "json")]
+                    Value::Json(j) => Some(nul_terminated(&j.to_string())),
+                    #[cfg(feature = "decimal")]
+                    Value::Decimal(d) => Some(nul_terminated(&d.to_string())),
+                    _ => None,
+                })
+                .collect();
+            LITESQL_ROW
+        }
+        None => {
+            result.text.clear();
+            LITESQL_DONE
+        }
+    }
+}
+
+fn nul_terminated(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len() + 1);
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.push(0);
+    bytes
+}
+
+unsafe fn current_value<'a>(result: *const LitesqlResult, column: usize) -> Option<&'a Value> {
+    let result = &*result;
+    result
+        .cursor
+        .and_then(|c| result.result.rows.get(c))
+        .and_then(|row| row.get(column))
+}
+
+/// Type of a column in the current row.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_value_type(
+    result: *const LitesqlResult,
+    column: usize,
+) -> c_int {
+    match current_value(result, column) {
+        None | Some(Value::Null) => LITESQL_NULL,
+        Some(Value::Bool(_) | Value::Int32(_) | Value::Int64(_) | Value::Timestamp(_)) => {
+            LITESQL_INTEGER
+        }
+        Some(Value::Float(_) | Value::Double(_)) => LITESQL_FLOAT,
+        Some(Value::String(_)) => LITESQL_TEXT,
+        #[cfg(feature = "json")]
+        Some(Value::Json(_)) => LITESQL_TEXT,
+        #[cfg(feature = "decimal")]
+        Some(Value::Decimal(_)) => LITESQL_TEXT,
+        Some(Value::Bytes(_)) => LITESQL_BLOB,
+        // Types enabled in litesql-ha by other crates of the build
+        #[allow(unreachable_patterns)]
+        Some(_) => LITESQL_NULL,
+    }
+}
+
+/// Integer value of a column in the current row; 0 for non-integer values.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_int64(result: *const LitesqlResult, column: usize) -> i64 {
+    match current_value(result, column) {
+        Some(Value::Bool(v)) => *v as i64,
+        Some(Value::Int32(v)) => *v as i64,
+        Some(Value::Int64(v)) => *v,
+        Some(Value::Timestamp(v)) => v
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// Floating point value of a column in the current row; 0 for non-numeric values.
+///
+/// # Safety
+///
+/// `result` must be a live result handle.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_double(result: *const LitesqlResult, column: usize) -> f64 {
+    match current_value(result, column) {
+        Some(Value::Float(v)) => *v as f64,
+        Some(Value::Double(v)) => *v,
+        Some(Value::Int32(v)) => *v as f64,
+        Some(Value::Int64(v)) => *v as f64,
+        _ => 0.0,
+    }
+}
+
+/// Text value of a column in the current row, or null for non-text values.
+///
+/// The text is NUL-terminated. Its length in bytes, without the terminator,
+/// is written to `len`; text may itself contain NUL bytes, which only the
+/// length shows.
+///
+/// # Safety
+///
+/// `result` must be a live result handle and `len` null or a valid pointer.
+/// The returned string is owned by the result and valid until the next call
+/// to `litesql_result_next`.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_text(
+    result: *const LitesqlResult,
+    column: usize,
+    len: *mut usize,
+) -> *const c_char {
+    let result = &*result;
+    let (data, size) = match result.text.get(column).and_then(|t| t.as_ref()) {
+        Some(t) => (t.as_ptr().cast(), t.len() - 1),
+        None => (ptr::null(), 0),
+    };
+    if !len.is_null() {
+        *len = size;
+    }
+    data
+}
+
+/// Blob value of a column in the current row, or null for non-blob values.
+///
+/// # Safety
+///
+/// `result` must be a live result handle and `len` null or a valid pointer.
+/// The returned bytes are owned by the result and valid until it is freed.
+#[no_mangle]
+pub unsafe extern "C" fn litesql_result_blob(
+    result: *const LitesqlResult,
+    column: usize,
+    len: *mut usize,
+) -> *const u8 {
+    let (data, size) = match current_value(result, column) {
+        Some(Value::Bytes(v)) => (v.as_ptr(), v.len()),
+        _ => (ptr::null(), 0),
+    };
+    if !len.is_null() {
+        *len = size;
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use litesql_ha::test_util::MockServer;
+    use std::slice;
+
+    fn c_string(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn text(result: *const LitesqlResult, column: usize) -> Option<Vec<u8>> {
+        let mut len = 0;
+        let data = litesql_result_text(result, column, &mut len);
+        (!data.is_null()).then(|| slice::from_raw_parts(data.cast::<u8>(), len).to_vec())
+    }
+
+    #[test]
+    fn round_trip() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start()).unwrap();
+        server
+            .execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+            .unwrap();
+
+        unsafe {
+            let url = c_string(&server.url());
+            let mut ds = ptr::null_mut();
+            assert_eq!(
+                litesql_datasource_open(url.as_ptr(), ptr::null(), &mut ds),
+                LITESQL_OK
+            );
+            let mut conn = ptr::null_mut();
+            assert_eq!(litesql_connection_open(ds, &mut conn), LITESQL_OK);
+
+            let insert = c_string("INSERT INTO users (name) VALUES (?), (?)");
+            let params = c_string(r#"["a\u0000b", "bob"]"#);
+            let mut rows_affected = 0;
+            assert_eq!(
+                litesql_execute(conn, insert.as_ptr(), params.as_ptr(), &mut rows_affected),
+                LITESQL_OK
+            );
+            assert_eq!(rows_affected, 2);
+
+            let select = c_string("SELECT id, name, X'00ff', 1.5, NULL FROM users ORDER BY id");
+            let mut result = ptr::null_mut();
+            assert_eq!(
+                litesql_query(conn, select.as_ptr(), ptr::null(), &mut result),
+                LITESQL_OK
+            );
+            assert_eq!(litesql_result_column_count(result), 5);
+            assert_eq!(
+                CStr::from_ptr(litesql_result_column_name(result, 1)).to_str(),
+                Ok("name")
+            );
+            assert!(litesql_result_column_name(result, 5).is_null());
+            assert_eq!(litesql_result_row_count(result), 2);
+
+            assert_eq!(litesql_result_next(result), LITESQL_ROW);
+            assert_eq!(litesql_result_value_type(result, 0), LITESQL_INTEGER);
+            assert_eq!(litesql_result_int64(result, 0), 1);
+            assert_eq!(litesql_result_value_type(result, 1), LITESQL_TEXT);
+            assert_eq!(text(result, 1).as_deref(), Some(&b"a\0b"[..]));
+            assert_eq!(litesql_result_value_type(result, 2), LITESQL_BLOB);
+            let mut len = 0;
+            let blob = litesql_result_blob(result, 2, &mut len);
+            assert_eq!(slice::from_raw_parts(blob, len), [0x00, 0xff]);
+            assert_eq!(litesql_result_value_type(result, 3), LITESQL_FLOAT);
+            assert_eq!(litesql_result_double(result, 3), 1.5);
+            assert_eq!(litesql_result_value_type(result, 4), LITESQL_NULL);
+            assert_eq!(text(result, 4), None);
+
+            assert_eq!(litesql_result_next(result), LITESQL_ROW);
+            assert_eq!(text(result, 1).as_deref(), Some(&b"bob"[..]));
+            assert_eq!(
+                CStr::from_ptr(litesql_result_text(result, 1, ptr::null_mut())).to_str(),
+                Ok("bob")
+            );
+            assert_eq!(litesql_result_next(result), LITESQL_DONE);
+            assert_eq!(text(result, 1), None);
+            litesql_result_free(result);
+
+            let params = c_string("{}");
+            assert_eq!(
+                litesql_query(conn, select.as_ptr(), params.as_ptr(), &mut result),
+                LITESQL_INVALID_ARGUMENT
+            );
+            let invalid = c_string("SELECT * FROM missing");
+            assert_eq!(
+                litesql_query(conn, invalid.as_ptr(), ptr::null(), &mut result),
+                LITESQL_QUERY_ERROR
+            );
+            assert!(!litesql_last_error().is_null());
+
+            litesql_connection_free(conn);
+            litesql_datasource_free(ds);
+        }
+        runtime.block_on(server.shutdown());
+    }
+}
@@ -0,0 +1,123 @@
+//! Procedural macros for litesql-ha.
+//!
+//! Use these through the `litesql-ha` crate rather than depending on this
+//! crate directly.
+
+mod row;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use std::path::{Path, PathBuf};
+use syn::{parse_macro_input, DeriveInput, LitStr};
+
+/// Embed a directory of SQL migrations and build a `Migrator` from them.
+///
+/// See `litesql_ha::migrations` for the file naming rules.
+#[proc_macro]
+pub fn include_migrations(input: TokenStream) -> TokenStream {
+    let dir = parse_macro_input!(input as LitStr);
+    match expand(&dir) {
+        Ok(tokens) => tokens.into(),
+        Err(message) => syn::Error::new(dir.span(), message)
+            .to_compile_error()
+            .into(),
+    }
+}
+
+/// Derive `litesql_ha::row::FromRow`, reading each field from the column
+/// of the same name or the one given by `#[litesql(rename = "...")]`.
+#[proc_macro_derive(FromRow, attributes(litesql))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    row::expand_from_row(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `litesql_ha::row::ToParams`, producing one parameter per field in
+/// declaration order.
+#[proc_macro_derive(ToParams, attributes(litesql))]
+pub fn derive_to_params(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    row::expand_to_params(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct MigrationFile {
+    version: i64,
+    name: String,
+    up: PathBuf,
+    down: Option<PathBuf>,
+}
+
+fn expand(dir: &LitStr) -> Result<proc_macro2::TokenStream, String> {
+    let root = std::env::var("CARGO_MANIFEST_DIR").map_err(|e| e.to_string())?;
+    let path = Path::new(&root).join(dir.value());
+    let entries = std::fs::read_dir(&path)
+        .map_err(|e| format!("cannot read migrations from {}: {}", path.display(), e))?;
+
+    let mut ups = Vec::new();
+    let mut downs = Vec::new();
+    for entry in entries {
+        let file = entry.map_err(|e| e.to_string())?.path();
+        let Some(file_name) = file.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if let Some(stem) = file_name.strip_suffix(".down.sql") {
+            downs.push((parse_stem(stem)?, file));
+        } else if let Some(stem) = file_name.strip_suffix(".sql") {
+            ups.push((parse_stem(stem)?, file));
+        }
+    }
+
+    let mut migrations: Vec<MigrationFile> = Vec::new();
+    for ((version, name), up) in ups {
+        if migrations.iter().any(|m| m.version == version) {
+            return Err(format!("duplicate migration version {}", version));
+        }
+        migrations.push(MigrationFile {
+            version,
+            name,
+            up,
+            down: None,
+        });
+    }
+    for ((version, _), down) in downs {
+        let migration = migrations
+            .iter_mut()
+            .find(|m| m.version == version)
+            .ok_or_else(|| format!("down migration {} has no up migration", down.display()))?;
+        migration.down = Some(down);
+    }
+    migrations.sort_by_key(|m| m.version);
+
+    let items = migrations.iter().map(|m| {
+        let version = m.version;
+        let name = &m.name;
+        let up = m.up.to_string_lossy();
+        let down = m.down.as_ref().map(|down| {
+            let down = down.to_string_lossy();
+            quote!(.with_down(include_str!(#down)))
+        });
+        quote! {
+            ::litesql_ha::migrations::Migration::new(#version, #name, include_str!(#up))#down
+        }
+    });
+
+    Ok(quote! {
+        ::litesql_ha::migrations::Migrator::new([#(#items),*])
+    })
+}
+
+/// Split `0001_create_users` into its version and name.
+fn parse_stem(stem: &str) -> Result<(i64, String), String> {
+    let (version, name) = stem.split_once('_').unwrap_or((stem, ""));
+    let version = version.parse().map_err(|_| {
+        format!(
+            "migration file '{}' must start with a numeric version, e.g. 0001_create_users.sql",
+            stem
+        )
+    })?;
+    Ok((version, name.to_string()))
+}
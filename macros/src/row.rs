@@ -0,0 +1,91 @@
+//! `FromRow` and `ToParams` derives.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, Ident, LitStr};
+
+/// A struct field and the column it maps to.
+struct FieldColumn {
+    ident: Ident,
+    column: String,
+}
+
+pub fn expand_from_row(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = field_columns(input)?;
+
+    let assignments = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let column = &f.column;
+        quote!(#ident: row.get(#column)?)
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::litesql_ha::row::FromRow for #name #ty_generics #where_clause {
+            fn from_row(row: &::litesql_ha::row::Row<'_>) -> ::litesql_ha::Result<Self> {
+                Ok(Self {
+                    #(#assignments),*
+                })
+            }
+        }
+    })
+}
+
+pub fn expand_to_params(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = field_columns(input)?;
+
+    let values = fields.iter().map(|f| {
+        let ident = &f.ident;
+        quote!(::litesql_ha::Value::from(::std::clone::Clone::clone(&self.#ident)))
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::litesql_ha::row::ToParams for #name #ty_generics #where_clause {
+            fn to_params(&self) -> ::std::vec::Vec<::litesql_ha::Value> {
+                vec![#(#values),*]
+            }
+        }
+    })
+}
+
+fn field_columns(input: &DeriveInput) -> syn::Result<Vec<FieldColumn>> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "expected a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "expected a struct with named fields",
+            ))
+        }
+    };
+
+    fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.clone().expect("named field");
+            let mut column = ident.to_string().trim_start_matches("r#").to_string();
+            for attr in field.attrs.iter().filter(|a| a.path().is_ident("litesql")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        column = meta.value()?.parse::<LitStr>()?.value();
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported litesql attribute, expected `rename`"))
+                    }
+                })?;
+            }
+            Ok(FieldColumn { ident, column })
+        })
+        .collect()
+}
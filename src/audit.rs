@@ -0,0 +1,284 @@
+//! Query audit logging with parameter masking.
+//!
+//! An [`Auditor`] receives an [`AuditEvent`] for every statement executed
+//! through an [`HAConnection`](crate::HAConnection), after the statement has
+//! completed. Parameters are passed through the configured masking function
+//! before the hook sees them, and with a masking function the literals in the
+//! SQL text are replaced with `?`, so sensitive values never reach the audit
+//! sink.
+
+use crate::client::{ExecuteResult, ExecutionResult, RowStream};
+use crate::error::Result;
+use crate::fingerprint::mask_literals;
+use crate::routing::RoutingDecision;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Caller-supplied context attached to audit events.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditContext {
+    /// Identifier of the user on whose behalf the statement runs
+    pub user_id: Option<String>,
+    /// Identifier of the request that issued the statement
+    pub request_id: Option<String>,
+    /// Additional free-form attributes
+    pub attributes: HashMap<String, String>,
+}
+
+impl AuditContext {
+    /// Create an empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the user id.
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Set the request id.
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Add a free-form attribute.
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Outcome of an audited statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditOutcome {
+    /// The statement completed successfully
+    Success {
+        /// Number of rows affected
+        rows_affected: i64,
+        /// Number of rows returned
+        row_count: usize,
+    },
+    /// The statement failed
+    Failure {
+        /// Error message
+        error: String,
+    },
+}
+
+impl AuditOutcome {
+    /// Check if the statement succeeded.
+    pub fn is_success(&self) -> bool {
+        matches!(self, AuditOutcome::Success { .. })
+    }
+}
+
+impl From<&Result<ExecutionResult>> for AuditOutcome {
+    fn from(result: &Result<ExecutionResult>) -> Self {
+        match result {
+            Ok(r) => AuditOutcome::Success {
+                rows_affected: r.rows_affected,
+                row_count: r.row_count(),
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+/// Rows of a streamed query are not counted.
+impl From<&Result<RowStream>> for AuditOutcome {
+    fn from(result: &Result<RowStream>) -> Self {
+        match result {
+            Ok(_) => AuditOutcome::Success {
+                rows_affected: 0,
+                row_count: 0,
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+/// A failed batch call is recorded against each of its statements.
+impl From<&Result<Vec<ExecutionResult>>> for AuditOutcome {
+    fn from(result: &Result<Vec<ExecutionResult>>) -> Self {
+        match result {
+            Ok(results) => AuditOutcome::Success {
+                rows_affected: results.iter().map(|r| r.rows_affected).sum(),
+                row_count: results.iter().map(|r| r.row_count()).sum(),
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+/// A failed `execute_many` call is recorded against each of its parameter
+/// sets.
+impl From<&Result<Vec<ExecuteResult>>> for AuditOutcome {
+    fn from(result: &Result<Vec<ExecuteResult>>) -> Self {
+        match result {
+            Ok(results) => AuditOutcome::Success {
+                rows_affected: results.iter().map(|r| r.rows_affected).sum(),
+                row_count: 0,
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<&Result<ExecuteResult>> for AuditOutcome {
+    fn from(result: &Result<ExecuteResult>) -> Self {
+        match result {
+            Ok(r) => AuditOutcome::Success {
+                rows_affected: r.rows_affected,
+                row_count: 0,
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<&Result<i64>> for AuditOutcome {
+    fn from(result: &Result<i64>) -> Self {
+        match result {
+            Ok(rows_affected) => AuditOutcome::Success {
+                rows_affected: *rows_affected,
+                row_count: 0,
+            },
+            Err(e) => AuditOutcome::Failure {
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+/// A single audited statement.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    /// SQL text, with its literals replaced with `?` if parameters are
+    /// masked
+    pub sql: String,
+    /// Parameters after masking
+    pub parameters: Vec<Value>,
+    /// Caller-supplied context
+    pub context: AuditContext,
+    /// Where the statement was executed and why
+    pub routing: RoutingDecision,
+    /// Outcome of the statement
+    pub outcome: AuditOutcome,
+    /// Time taken to execute the statement
+    pub duration: Duration,
+}
+
+/// Receives audit events.
+pub trait AuditHook: Send + Sync {
+    /// Called after every executed statement.
+    fn on_statement(&self, event: &AuditEvent);
+}
+
+impl<F> AuditHook for F
+where
+    F: Fn(&AuditEvent) + Send + Sync,
+{
+    fn on_statement(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// Function used to mask a parameter, given its zero-based position and value.
+pub type ParameterMasker = Arc<dyn Fn(usize, &Value) -> Value + Send + Sync>;
+
+/// Audit configuration: a hook plus an optional parameter masker.
+#[derive(Clone)]
+pub struct Auditor {
+    hook: Arc<dyn AuditHook>,
+    masker: Option<ParameterMasker>,
+}
+
+impl Auditor {
+    /// Create an auditor that passes parameters to the hook unmasked.
+    pub fn new(hook: impl AuditHook + 'static) -> Self {
+        Self {
+            hook: Arc::new(hook),
+            masker: None,
+        }
+    }
+
+    /// Set the parameter masking function.
+    ///
+    /// The string, blob and numeric literals written in the SQL text are
+    /// replaced with `?` as well, since they may hold the same values.
+    pub fn with_masker<F>(mut self, masker: F) -> Self
+    where
+        F: Fn(usize, &Value) -> Value + Send + Sync + 'static,
+    {
+        self.masker = Some(Arc::new(masker));
+        self
+    }
+
+    /// Mask parameters using the configured masker.
+    pub fn mask(&self, parameters: &[Value]) -> Vec<Value> {
+        match self.masker {
+            Some(ref masker) => parameters
+                .iter()
+                .enumerate()
+                .map(|(i, v)| masker(i, v))
+                .collect(),
+            None => parameters.to_vec(),
+        }
+    }
+
+    /// Build an event from a completed statement and deliver it to the hook.
+    pub fn record(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        context: &AuditContext,
+        routing: RoutingDecision,
+        outcome: AuditOutcome,
+        duration: Duration,
+    ) {
+        let sql = match self.masker {
+            Some(_) => mask_literals(sql),
+            None => sql.to_string(),
+        };
+        let event = AuditEvent {
+            sql,
+            parameters: self.mask(parameters),
+            context: context.clone(),
+            routing,
+            outcome,
+            duration,
+        };
+        self.hook.on_statement(&event);
+    }
+}
+
+impl fmt::Debug for Auditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auditor")
+            .field("masked", &self.masker.is_some())
+            .finish()
+    }
+}
+
+/// Masker that replaces every non-null parameter with `"***"`.
+pub fn redact_all(_index: usize, value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        _ => Value::String("***".to_string()),
+    }
+}
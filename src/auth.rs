@@ -0,0 +1,104 @@
+//! Bearer token authentication.
+//!
+//! A fixed token can be set with [`HAClientOptions::token`]. Short-lived
+//! tokens such as JWTs are supplied by a [`TokenProvider`] instead, which is
+//! asked for the token before every call. When the server rejects a call as
+//! unauthenticated, the provider is asked to [`refresh`](TokenProvider::refresh)
+//! and the call is retried once; pipelined queries are not retried.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAClientOptions, TokenProvider};
+//! use std::sync::Arc;
+//!
+//! async fn fetch_jwt() -> litesql_ha::Result<String> {
+//!     Ok("eyJhbGciOi...".to_string())
+//! }
+//!
+//! let provider: Arc<dyn TokenProvider> = Arc::new(|| fetch_jwt());
+//! let options = HAClientOptions {
+//!     url: "litesql://localhost:8080".to_string(),
+//!     token_provider: Some(provider),
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! [`HAClientOptions::token`]: crate::HAClientOptions::token
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tonic::{Code, Request};
+
+/// Future returned by a [`TokenProvider`].
+pub type TokenFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+/// Supplies the bearer token sent with every call.
+pub trait TokenProvider: Send + Sync {
+    /// Get the current token.
+    ///
+    /// Called before every call, so implementations should cache the token
+    /// until it nears expiry.
+    fn token(&self) -> TokenFuture<'_>;
+
+    /// Get a new token after the server rejected the current one.
+    fn refresh(&self) -> TokenFuture<'_> {
+        self.token()
+    }
+}
+
+impl<F, Fut> TokenProvider for F
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String>> + Send + 'static,
+{
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(self())
+    }
+}
+
+impl fmt::Debug for dyn TokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenProvider")
+    }
+}
+
+/// The token, or provider of tokens, attached to every call.
+#[derive(Clone, Default)]
+pub(crate) struct Credentials {
+    token: Option<String>,
+    provider: Option<Arc<dyn TokenProvider>>,
+}
+
+impl Credentials {
+    /// Use `provider` if set, otherwise the fixed `token`.
+    pub(crate) fn new(token: Option<String>, provider: Option<Arc<dyn TokenProvider>>) -> Self {
+        Self { token, provider }
+    }
+
+    /// Attach the bearer token to a request, refreshing it first if asked to.
+    pub(crate) async fn authorize<T>(&self, request: &mut Request<T>, refresh: bool) -> Result<()> {
+        let token = match self.provider {
+            Some(ref provider) if refresh => Some(provider.refresh().await?),
+            Some(ref provider) => Some(provider.token().await?),
+            None => self.token.clone(),
+        };
+        if let Some(token) = token {
+            let value = format!("Bearer {}", token).parse().map_err(|_| {
+                Error::InvalidParameter("token is not a valid header value".to_string())
+            })?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        Ok(())
+    }
+
+    /// Check if a failed call should be retried with a refreshed token.
+    pub(crate) fn should_refresh(&self, error: &Error, refreshed: bool) -> bool {
+        !refreshed
+            && self.provider.is_some()
+            && matches!(error, Error::Status(status) if status.code() == Code::Unauthenticated)
+    }
+}
@@ -0,0 +1,86 @@
+//! bb8 connection manager.
+//!
+//! Enabled by the `bb8` feature. [`HAConnectionManager`] opens
+//! [`HAConnection`]s for a [`bb8`](::bb8) pool and recycles them the way
+//! [`HADataSource`](crate::HADataSource) recycles its own: a connection that
+//! was closed, or returned with a transaction open or in read-only mode, is
+//! dropped; any other is switched back to its first catalog and its audit
+//! context is cleared. When the pool tests connections on checkout, the
+//! default, the server must also answer `SELECT 1`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::bb8::{HAConnectionManager, Pool};
+//! use litesql_ha::HAConnectionOptions;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let manager = HAConnectionManager::new(HAConnectionOptions {
+//!     url: "litesql://localhost:8080/app.db".to_string(),
+//!     timeout: 30,
+//!     ..Default::default()
+//! });
+//! let pool = Pool::builder().max_size(16).build(manager).await?;
+//!
+//! let conn = pool.get().await?;
+//! let rows = conn.query("SELECT name FROM users", &[]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::connection::{HAConnection, HAConnectionOptions};
+use crate::error::Error;
+use crate::pool;
+use ::bb8::ManageConnection;
+use std::sync::OnceLock;
+
+/// bb8 pool of [`HAConnection`]s.
+pub type Pool = ::bb8::Pool<HAConnectionManager>;
+
+/// bb8 manager opening [`HAConnection`]s.
+#[derive(Debug)]
+pub struct HAConnectionManager {
+    options: HAConnectionOptions,
+    /// Catalog of the first connection opened, restored on checkout
+    catalog: OnceLock<String>,
+}
+
+impl HAConnectionManager {
+    /// Open connections with `options`.
+    pub fn new(options: HAConnectionOptions) -> Self {
+        Self {
+            options,
+            catalog: OnceLock::new(),
+        }
+    }
+
+    /// Get the options connections are opened with.
+    pub fn options(&self) -> &HAConnectionOptions {
+        &self.options
+    }
+}
+
+impl ManageConnection for HAConnectionManager {
+    type Connection = HAConnection;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<HAConnection, Error> {
+        let conn = HAConnection::new(self.options.clone()).await?;
+        self.catalog.get_or_init(|| conn.catalog());
+        Ok(conn)
+    }
+
+    async fn is_valid(&self, conn: &mut HAConnection) -> Result<(), Error> {
+        if let Some(catalog) = self.catalog.get() {
+            pool::reset(conn, catalog)?;
+        }
+        if !conn.is_valid().await {
+            return Err(Error::ConnectionClosed);
+        }
+        Ok(())
+    }
+
+    fn has_broken(&self, conn: &mut HAConnection) -> bool {
+        !pool::is_recyclable(conn)
+    }
+}
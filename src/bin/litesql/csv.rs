@@ -0,0 +1,61 @@
+//! Minimal RFC 4180 CSV reading.
+
+use std::io::{self, BufRead};
+
+/// Read all records from a CSV source.
+///
+/// Quoted fields may contain separators, doubled quotes and line breaks.
+pub fn read_records<R: BufRead>(reader: R) -> io::Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+
+    for line in reader.lines() {
+        let line = line?;
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                if c == '"' {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        in_quotes = false;
+                    }
+                } else {
+                    field.push(c);
+                }
+            } else {
+                match c {
+                    '"' => in_quotes = true,
+                    ',' => record.push(std::mem::take(&mut field)),
+                    '\r' if chars.peek().is_none() => {}
+                    _ => field.push(c),
+                }
+            }
+        }
+
+        if in_quotes {
+            field.push('\n');
+            continue;
+        }
+
+        record.push(std::mem::take(&mut field));
+        if !(record.len() == 1 && record[0].is_empty()) {
+            records.push(std::mem::take(&mut record));
+        } else {
+            record.clear();
+        }
+    }
+
+    if in_quotes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unterminated quoted field",
+        ));
+    }
+
+    Ok(records)
+}
@@ -0,0 +1,588 @@
+//! `litesql` command line client.
+//!
+//! Built with `--features cli`. Every command goes through the blocking
+//! client, so queries are routed and authenticated the same way as in
+//! applications using this crate.
+
+mod csv;
+mod table;
+
+use litesql_ha::blocking::{HAConnection, HADataSource};
+use litesql_ha::client::ExecutionResult;
+use litesql_ha::codegen::Generator;
+use litesql_ha::{
+    DownloadOptions, DownloadProgress, Error, HADataSourceOptions, Recorder, Replay, Result,
+    TlsOptions, TransactionBehavior, Value,
+};
+use rusqlite::{Connection, OpenFlags};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: litesql [OPTIONS] [COMMAND] [ARGS]
+
+Options:
+  --url <URL>        Server URL (default: $LITESQL_URL)
+  --token <TOKEN>    Authentication token (default: $LITESQL_TOKEN)
+  --ssl              Use TLS
+  --ca-cert <FILE>   PEM root certificates to trust instead of the system's
+  --tls-domain <NAME>
+                     Name to verify the server certificate against
+  --client-cert <FILE> --client-key <FILE>
+                     PEM client certificate and key for mutual TLS
+  --timeout <SECS>   Query timeout in seconds
+  --record <FILE>    Record queries and responses to FILE (parameters redacted)
+  --replay <FILE>    Answer queries from a recording instead of the server
+  -h, --help         Print this help
+
+Commands:
+  repl                                 Interactive shell (default)
+  query <SQL> [PARAMS...]              Run a query and print the rows
+  exec <SQL> [PARAMS...]               Run a statement and print rows affected
+  download-replicas <DIR> [--force] [--progress] [--max-bytes-per-sec N]
+                    [--integrity-check]
+                                       Download all replica files into DIR
+  upload <FILE> [--table NAME]...      Copy tables from a local SQLite file
+  import-csv <FILE> <TABLE> [--create] Insert CSV rows; the first line names the columns
+  export <TABLE|SQL> [--output FILE]   Write rows as CSV
+  status [--replicas-dir DIR]          Show server health and replica lag
+  codegen [--table NAME]... [--output FILE]
+                                       Generate Rust structs from the schema
+
+Parameters are bound in order. Integers and decimals are sent as numbers,
+NULL as null, anything else as text.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<()> {
+    if take_flag(&mut args, "-h") || take_flag(&mut args, "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let mut options = HADataSourceOptions {
+        url: take_option(&mut args, "--url")?
+            .or_else(|| std::env::var("LITESQL_URL").ok())
+            .ok_or_else(|| usage("missing --url (or LITESQL_URL)"))?,
+        password: take_option(&mut args, "--token")?
+            .or_else(|| std::env::var("LITESQL_TOKEN").ok()),
+        enable_ssl: take_flag(&mut args, "--ssl"),
+        ..Default::default()
+    };
+    if let Some(timeout) = take_option(&mut args, "--timeout")? {
+        options.timeout = timeout
+            .parse()
+            .map_err(|_| usage("--timeout expects a number of seconds"))?;
+    }
+    let mut tls = TlsOptions::default();
+    if let Some(path) = take_option(&mut args, "--ca-cert")? {
+        tls = tls.with_ca_cert_file(path)?;
+    }
+    if let Some(domain) = take_option(&mut args, "--tls-domain")? {
+        tls = tls.with_domain_override(domain);
+    }
+    let client_cert = take_option(&mut args, "--client-cert")?;
+    let client_key = take_option(&mut args, "--client-key")?;
+    if client_cert.is_some() || client_key.is_some() {
+        let (Some(cert), Some(key)) = (client_cert, client_key) else {
+            return Err(usage(
+                "--client-cert and --client-key must be given together",
+            ));
+        };
+        tls = tls.with_client_identity_files(cert, key)?;
+    }
+    if tls != TlsOptions::default() {
+        options.tls = Some(tls);
+    }
+    if let Some(path) = take_option(&mut args, "--record")? {
+        options.recorder = Some(Recorder::create(path)?);
+    }
+    if let Some(path) = take_option(&mut args, "--replay")? {
+        options.replay = Some(Replay::open(path)?);
+    }
+
+    let command = if args.is_empty() {
+        "repl".to_string()
+    } else {
+        args.remove(0)
+    };
+
+    let ds = HADataSource::new(options)?;
+
+    match command.as_str() {
+        "repl" => repl(&ds),
+        "query" => {
+            let (sql, params) = statement(args)?;
+            let result = ds.get_connection()?.query(&sql, &params)?;
+            print!("{}", table::render(&result));
+            Ok(())
+        }
+        "exec" => {
+            let (sql, params) = statement(args)?;
+            let result = ds.get_connection()?.execute(&sql, &params)?;
+            println!("{} rows affected", result.rows_affected);
+            Ok(())
+        }
+        "download-replicas" => {
+            let force = take_flag(&mut args, "--force");
+            let mut download = DownloadOptions::new()
+                .with_integrity_check(take_flag(&mut args, "--integrity-check"));
+            if take_flag(&mut args, "--progress") {
+                download = download.with_progress(print_progress);
+            }
+            if let Some(rate) = take_option(&mut args, "--max-bytes-per-sec")? {
+                download = download.with_max_bytes_per_sec(
+                    rate.parse()
+                        .map_err(|_| usage("--max-bytes-per-sec expects a number"))?,
+                );
+            }
+            let [dir] = positional::<1>(args, "download-replicas <DIR>")?;
+            ds.download_replicas_with(Path::new(&dir), force, &download)
+        }
+        "upload" => {
+            let mut tables = Vec::new();
+            while let Some(table) = take_option(&mut args, "--table")? {
+                tables.push(table);
+            }
+            let [file] = positional::<1>(args, "upload <FILE>")?;
+            upload(&ds.get_connection()?, Path::new(&file), tables)
+        }
+        "import-csv" => {
+            let create = take_flag(&mut args, "--create");
+            let [file, table] = positional::<2>(args, "import-csv <FILE> <TABLE>")?;
+            import_csv(&ds.get_connection()?, Path::new(&file), &table, create)
+        }
+        "export" => {
+            let output = take_option(&mut args, "--output")?;
+            let [source] = positional::<1>(args, "export <TABLE|SQL>")?;
+            export(&ds.get_connection()?, &source, output.as_deref())
+        }
+        "status" => {
+            let replicas_dir = take_option(&mut args, "--replicas-dir")?;
+            positional::<0>(args, "status")?;
+            status(&ds.get_connection()?, replicas_dir.as_deref())
+        }
+        "codegen" => {
+            let mut generator = Generator::new();
+            while let Some(table) = take_option(&mut args, "--table")? {
+                generator = generator.with_table(table);
+            }
+            let output = take_option(&mut args, "--output")?;
+            positional::<0>(args, "codegen")?;
+            let code = generator.generate_blocking(&ds.get_connection()?)?;
+            match output {
+                Some(path) => std::fs::write(path, code)?,
+                None => print!("{}", code),
+            }
+            Ok(())
+        }
+        other => Err(usage(&format!("unknown command '{}'", other))),
+    }
+}
+
+fn usage(message: &str) -> Error {
+    Error::InvalidParameter(format!("{}\n\n{}", message, USAGE))
+}
+
+/// Remove a boolean flag from the arguments, returning whether it was given.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|a| a == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove the first `name value` pair from the arguments.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>> {
+    let Some(i) = args.iter().position(|a| a == name) else {
+        return Ok(None);
+    };
+    if i + 1 >= args.len() {
+        return Err(usage(&format!("{} expects a value", name)));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// Require exactly `N` positional arguments.
+fn positional<const N: usize>(args: Vec<String>, expected: &str) -> Result<[String; N]> {
+    args.try_into()
+        .map_err(|_| usage(&format!("usage: litesql {}", expected)))
+}
+
+fn statement(mut args: Vec<String>) -> Result<(String, Vec<Value>)> {
+    if args.is_empty() {
+        return Err(usage("missing SQL statement"));
+    }
+    let sql = args.remove(0);
+    let params = args.iter().map(|a| parse_param(a)).collect();
+    Ok((sql, params))
+}
+
+fn parse_param(arg: &str) -> Value {
+    if arg.eq_ignore_ascii_case("null") {
+        Value::Null
+    } else if let Ok(v) = arg.parse::<i64>() {
+        Value::Int64(v)
+    } else if let Ok(v) = arg.parse::<f64>() {
+        Value::Double(v)
+    } else {
+        Value::String(arg.to_string())
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn repl(ds: &HADataSource) -> Result<()> {
+    let conn = ds.get_connection()?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut buffer = String::new();
+
+    println!("Connected. Enter SQL terminated by ';', or .help for commands.");
+    loop {
+        print!(
+            "{}",
+            if buffer.is_empty() {
+                "litesql> "
+            } else {
+                "    ...> "
+            }
+        );
+        io::stdout().flush()?;
+
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        let line = line?;
+        let trimmed = line.trim();
+
+        if buffer.is_empty() && trimmed.starts_with('.') {
+            match dot_command(&conn, trimmed) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if !trimmed.ends_with(';') {
+            continue;
+        }
+
+        let sql = std::mem::take(&mut buffer);
+        if let Err(e) = run_statement(&conn, sql.trim().trim_end_matches(';')) {
+            eprintln!("error: {}", e);
+        }
+    }
+
+    conn.close()
+}
+
+/// Handle a REPL dot command, returning `false` when the shell should exit.
+fn dot_command(conn: &HAConnection, line: &str) -> Result<bool> {
+    let mut parts = line.split_whitespace();
+    match parts.next().unwrap_or_default() {
+        ".quit" | ".exit" => return Ok(false),
+        ".help" => {
+            println!(".tables           List tables");
+            println!(".schema [TABLE]   Show CREATE statements");
+            println!(".quit             Exit");
+        }
+        ".tables" => {
+            let result = conn.query(
+                "SELECT name FROM sqlite_master WHERE type = 'table' \
+                 AND name NOT LIKE 'sqlite_%' ORDER BY name",
+                &[],
+            )?;
+            print!("{}", table::render(&result));
+        }
+        ".schema" => {
+            let result = match parts.next() {
+                Some(name) => conn.query(
+                    "SELECT sql FROM sqlite_master WHERE name = ? AND sql IS NOT NULL",
+                    &[Value::from(name)],
+                )?,
+                None => conn.query(
+                    "SELECT sql FROM sqlite_master WHERE sql IS NOT NULL \
+                     AND name NOT LIKE 'sqlite_%' ORDER BY name",
+                    &[],
+                )?,
+            };
+            for row in &result.rows {
+                println!("{};", table::format_value(&row[0]));
+            }
+        }
+        other => eprintln!("unknown command '{}'; try .help", other),
+    }
+    Ok(true)
+}
+
+fn run_statement(conn: &HAConnection, sql: &str) -> Result<()> {
+    // Transaction control goes through the connection so reads inside the
+    // transaction are routed to the server.
+    match sql.to_ascii_uppercase().as_str() {
+        "BEGIN" | "BEGIN TRANSACTION" => return conn.begin_transaction(),
+        "BEGIN IMMEDIATE" | "BEGIN IMMEDIATE TRANSACTION" => {
+            return conn.begin_transaction_with(TransactionBehavior::Immediate)
+        }
+        "BEGIN EXCLUSIVE" | "BEGIN EXCLUSIVE TRANSACTION" => {
+            return conn.begin_transaction_with(TransactionBehavior::Exclusive)
+        }
+        "COMMIT" | "END" | "COMMIT TRANSACTION" => return conn.commit(),
+        "ROLLBACK" | "ROLLBACK TRANSACTION" => return conn.rollback(),
+        _ => {}
+    }
+
+    let result = conn.run(sql, &[])?;
+    if result.columns.is_empty() {
+        println!("{} rows affected", result.rows_affected);
+    } else {
+        print!("{}", table::render(&result));
+    }
+    Ok(())
+}
+
+/// Run `f` inside a transaction, rolling back on error.
+fn in_transaction<T>(conn: &HAConnection, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    conn.begin_transaction()?;
+    match f() {
+        Ok(value) => {
+            conn.commit()?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = conn.rollback();
+            Err(e)
+        }
+    }
+}
+
+/// Overwrite the progress line of a replica download on stderr.
+fn print_progress(progress: &DownloadProgress) {
+    let rate = progress.bytes_per_second() / 1024.0 / 1024.0;
+    let mut stderr = io::stderr().lock();
+    let _ = match progress.total {
+        Some(total) => write!(
+            stderr,
+            "\r{}: {} of {} bytes ({:.1} MiB/s)",
+            progress.replication_id, progress.downloaded, total, rate
+        ),
+        None => write!(
+            stderr,
+            "\r{}: {} bytes ({:.1} MiB/s)",
+            progress.replication_id, progress.downloaded, rate
+        ),
+    };
+    if progress.total == Some(progress.downloaded) {
+        let _ = writeln!(stderr);
+    }
+}
+
+fn upload(conn: &HAConnection, file: &Path, tables: Vec<String>) -> Result<()> {
+    let local = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let tables = if tables.is_empty() {
+        let mut stmt = local.prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' \
+             AND name NOT LIKE 'sqlite_%' AND name != 'ha_stats' \
+             AND name NOT LIKE '\\_\\_litesql\\_%' ESCAPE '\\' ORDER BY name",
+        )?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        names
+    } else {
+        tables
+    };
+
+    for name in &tables {
+        let create: String = local.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get(0),
+        )?;
+        let create = match create.get(..12) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("CREATE TABLE") => {
+                format!("CREATE TABLE IF NOT EXISTS{}", &create[12..])
+            }
+            _ => create,
+        };
+
+        let mut stmt = local.prepare(&format!("SELECT * FROM {}", quote_identifier(name)))?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let insert = insert_statement(name, &columns);
+
+        let count = in_transaction(conn, || {
+            conn.execute(&create, &[])?;
+            let mut rows = stmt.query([])?;
+            let mut count = 0;
+            while let Some(row) = rows.next()? {
+                let mut params = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    params.push(sqlite_to_value(row.get(i)?));
+                }
+                conn.execute(&insert, &params)?;
+                count += 1;
+            }
+            Ok(count)
+        })?;
+        println!("{}: {} rows", name, count);
+    }
+    Ok(())
+}
+
+fn sqlite_to_value(value: rusqlite::types::Value) -> Value {
+    match value {
+        rusqlite::types::Value::Null => Value::Null,
+        rusqlite::types::Value::Integer(v) => Value::Int64(v),
+        rusqlite::types::Value::Real(v) => Value::Double(v),
+        rusqlite::types::Value::Text(v) => Value::String(v),
+        rusqlite::types::Value::Blob(v) => Value::Bytes(v),
+    }
+}
+
+fn insert_statement(table: &str, columns: &[String]) -> String {
+    let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_identifier(table),
+        names.join(", "),
+        placeholders
+    )
+}
+
+fn import_csv(conn: &HAConnection, file: &Path, table: &str, create: bool) -> Result<()> {
+    let mut records = csv::read_records(BufReader::new(File::open(file)?))?.into_iter();
+    let columns = records
+        .next()
+        .ok_or_else(|| Error::InvalidParameter(format!("{} is empty", file.display())))?;
+    let insert = insert_statement(table, &columns);
+
+    let count = in_transaction(conn, || {
+        if create {
+            let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+            conn.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} ({})",
+                    quote_identifier(table),
+                    names.join(", ")
+                ),
+                &[],
+            )?;
+        }
+
+        let mut count = 0;
+        for (line, record) in records.enumerate() {
+            if record.len() != columns.len() {
+                return Err(Error::InvalidParameter(format!(
+                    "record {} has {} fields, expected {}",
+                    line + 2,
+                    record.len(),
+                    columns.len()
+                )));
+            }
+            let params: Vec<Value> = record.into_iter().map(Value::String).collect();
+            conn.execute(&insert, &params)?;
+            count += 1;
+        }
+        Ok(count)
+    })?;
+    println!("{}: {} rows", table, count);
+    Ok(())
+}
+
+fn export(conn: &HAConnection, source: &str, output: Option<&str>) -> Result<()> {
+    let sql = if source.contains(char::is_whitespace) {
+        source.to_string()
+    } else {
+        format!("SELECT * FROM {}", quote_identifier(source))
+    };
+    let result: ExecutionResult = conn.query(&sql, &[])?;
+
+    match output {
+        Some(path) => result.write_csv(BufWriter::new(File::create(path)?)),
+        None => result.write_csv(BufWriter::new(io::stdout().lock())),
+    }
+}
+
+fn status(conn: &HAConnection, replicas_dir: Option<&str>) -> Result<()> {
+    if !conn.is_valid() {
+        return Err(Error::ConnectionClosed);
+    }
+    println!("server: ok");
+
+    let client = conn.inner().client();
+    let current = client.replication_id();
+    let replication_ids = conn.replication_ids()?;
+
+    let mut rows = Vec::new();
+    for id in &replication_ids {
+        client.set_replication_id(id);
+        conn.query("SELECT 1", &[])?;
+        let leader = client.txseq();
+
+        let mut row = vec![Value::from(id.as_str()), Value::Int64(leader)];
+        if let Some(dir) = replicas_dir {
+            match local_txseq(&Path::new(dir).join(id)) {
+                Some(local) => {
+                    row.push(Value::Int64(local));
+                    row.push(Value::Int64((leader - local).max(0)));
+                }
+                None => row.extend([Value::Null, Value::Null]),
+            }
+        }
+        rows.push(row);
+    }
+    client.set_replication_id(&current);
+
+    let mut columns = vec!["replication_id".to_string(), "leader_txseq".to_string()];
+    if replicas_dir.is_some() {
+        columns.push("replica_txseq".to_string());
+        columns.push("behind".to_string());
+    }
+    let result = ExecutionResult {
+        columns,
+        rows,
+        rows_affected: 0,
+        last_insert_rowid: 0,
+        txseq: 0,
+        routing: None,
+    };
+    print!("{}", table::render(&result));
+    Ok(())
+}
+
+/// Read the txseq recorded in a local replica file.
+fn local_txseq(path: &Path) -> Option<i64> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    conn.query_row(
+        "SELECT received_seq FROM ha_stats ORDER BY updated_at DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .ok()
+}
@@ -0,0 +1,89 @@
+//! Table rendering for query results.
+
+use litesql_ha::client::ExecutionResult;
+use litesql_ha::Value;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+/// Format a value for display.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::Int32(v) => v.to_string(),
+        Value::Int64(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        Value::String(v) => v.clone(),
+        Value::Bytes(v) => {
+            let mut out = String::with_capacity(v.len() * 2 + 3);
+            out.push_str("x'");
+            for byte in v {
+                let _ = write!(out, "{:02x}", byte);
+            }
+            out.push('\'');
+            out
+        }
+        Value::Timestamp(v) => v
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs().to_string())
+            .unwrap_or_default(),
+        #[cfg(feature = "json")]
+        Value::Json(v) => v.to_string(),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(v) => v.to_string(),
+    }
+}
+
+/// Render a result as a boxed table followed by a row count.
+pub fn render(result: &ExecutionResult) -> String {
+    let cells: Vec<Vec<String>> = result
+        .rows
+        .iter()
+        .map(|row| row.iter().map(format_value).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = result.columns.iter().map(|c| c.chars().count()).collect();
+    for row in &cells {
+        for (i, cell) in row.iter().enumerate() {
+            if i < widths.len() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+    }
+
+    let mut border = String::from("+");
+    for width in &widths {
+        border.push_str(&"-".repeat(width + 2));
+        border.push('+');
+    }
+
+    let mut out = String::new();
+    out.push_str(&border);
+    out.push('\n');
+    push_line(&mut out, &result.columns, &widths);
+    out.push_str(&border);
+    out.push('\n');
+    for row in &cells {
+        push_line(&mut out, row, &widths);
+    }
+    out.push_str(&border);
+    out.push('\n');
+
+    let count = result.row_count();
+    let _ = writeln!(out, "({} row{})", count, if count == 1 { "" } else { "s" });
+    out
+}
+
+fn push_line(out: &mut String, cells: &[String], widths: &[usize]) {
+    out.push('|');
+    for (i, width) in widths.iter().enumerate() {
+        let cell = cells.get(i).map(String::as_str).unwrap_or("");
+        let padding = width - cell.chars().count();
+        out.push(' ');
+        out.push_str(cell);
+        out.push_str(&" ".repeat(padding + 1));
+        out.push('|');
+    }
+    out.push('\n');
+}
@@ -0,0 +1,755 @@
+//! Blocking (synchronous) API.
+//!
+//! The types in this module wrap their async counterparts and drive them on a
+//! runtime owned by the data source, so they can be used from code that has
+//! no async runtime of its own. Connections share the runtime of the data
+//! source that created them.
+//!
+//! These types must not be used from within an async context: blocking on the
+//! internal runtime from inside another runtime panics.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::blocking::HADataSource;
+//! use litesql_ha::HADataSourceOptions;
+//!
+//! fn main() -> litesql_ha::Result<()> {
+//!     let ds = HADataSource::new(HADataSourceOptions {
+//!         url: "litesql://localhost:8080".to_string(),
+//!         ..Default::default()
+//!     })?;
+//!
+//!     let conn = ds.get_connection()?;
+//!     let result = conn.query("SELECT * FROM users", &[])?;
+//!     println!("{:?}", result.rows);
+//!     conn.close()
+//! }
+//! ```
+
+use crate::audit::AuditContext;
+use crate::cache::QueryCache;
+use crate::client::{ExecuteResult, ExecutionResult, QueryOptions, RowStream};
+use crate::coalesce::QueryCoalescer;
+use crate::connection::{self, HAConnectionOptions};
+use crate::datasource::{self, HADataSourceOptions};
+use crate::download::DownloadOptions;
+#[cfg(feature = "embedded-replicas")]
+use crate::embedded_replicas::StatementCacheStats;
+use crate::error::Result;
+use crate::pool::{PoolStatus, PooledConnection};
+use crate::routing::{Consistency, RoutingStats};
+use crate::row::{FromRow, FromValue, OwnedRow};
+use crate::runtime::{self, Runtime};
+use crate::statement;
+use crate::transaction::{self, TransactionBehavior, TransactionOptions};
+use crate::value::Value;
+#[cfg(feature = "codegen")]
+use std::future::Future;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+/// Blocking data source for managing HA database connections.
+pub struct HADataSource {
+    inner: datasource::HADataSource,
+    runtime: Arc<Runtime>,
+}
+
+impl HADataSource {
+    /// Create a new data source with options.
+    pub fn new(options: HADataSourceOptions) -> Result<Self> {
+        Ok(Self {
+            inner: datasource::HADataSource::new(options),
+            runtime: Arc::new(new_runtime()?),
+        })
+    }
+
+    /// Get a connection from the pool; it is returned when dropped.
+    pub fn get_connection(&self) -> Result<HAConnection> {
+        let inner = self.runtime.block_on(self.inner.get_connection())?;
+        Ok(HAConnection {
+            inner: Inner::Pooled(inner),
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Download all replicas from the HA server.
+    pub fn download_replicas(&self, directory: &Path, override_existing: bool) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.download_replicas(directory, override_existing))
+    }
+
+    /// Download all replicas from the HA server with `options`.
+    pub fn download_replicas_with(
+        &self,
+        directory: &Path,
+        override_existing: bool,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        self.runtime.block_on(self.inner.download_replicas_with(
+            directory,
+            override_existing,
+            options,
+        ))
+    }
+
+    /// Get the pool counters.
+    pub fn pool_status(&self) -> PoolStatus {
+        self.inner.pool_status()
+    }
+
+    /// Get the wrapped async data source.
+    pub fn inner(&self) -> &datasource::HADataSource {
+        &self.inner
+    }
+
+    /// Get the wrapped async data source mutably.
+    pub fn inner_mut(&mut self) -> &mut datasource::HADataSource {
+        &mut self.inner
+    }
+}
+
+/// Build the runtime driving blocking calls.
+fn new_runtime() -> Result<Runtime> {
+    Ok(runtime::new_runtime("litesql-ha-blocking")?)
+}
+
+/// Blocking connection to the HA database, from a data source's pool or
+/// opened alone with [`connect`](Self::connect).
+pub struct HAConnection {
+    inner: Inner,
+    runtime: Arc<Runtime>,
+}
+
+/// The async connection behind a blocking one.
+enum Inner {
+    Pooled(PooledConnection),
+    Owned(connection::HAConnection),
+}
+
+impl Deref for Inner {
+    type Target = connection::HAConnection;
+
+    fn deref(&self) -> &connection::HAConnection {
+        match self {
+            Inner::Pooled(conn) => conn,
+            Inner::Owned(conn) => conn,
+        }
+    }
+}
+
+impl HAConnection {
+    /// Open a connection outside any pool, on a runtime of its own.
+    pub fn connect(options: HAConnectionOptions) -> Result<Self> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(connection::HAConnection::new(options))?;
+        Ok(Self {
+            inner: Inner::Owned(inner),
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Execute a SELECT query.
+    pub fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.query(sql, params))
+    }
+
+    /// Execute a SELECT query and map each row to `T` by column name.
+    pub fn query_as<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
+        self.runtime.block_on(self.inner.query_as(sql, params))
+    }
+
+    /// Execute a SELECT query that must return exactly one row.
+    pub fn query_one(&self, sql: &str, params: &[Value]) -> Result<OwnedRow> {
+        self.runtime.block_on(self.inner.query_one(sql, params))
+    }
+
+    /// Execute a SELECT query that returns at most one row.
+    pub fn query_optional(&self, sql: &str, params: &[Value]) -> Result<Option<OwnedRow>> {
+        self.runtime
+            .block_on(self.inner.query_optional(sql, params))
+    }
+
+    /// Execute a SELECT query that must return exactly one row and get its
+    /// first column as `T`.
+    pub fn query_scalar<T: FromValue>(&self, sql: &str, params: &[Value]) -> Result<T> {
+        self.runtime.block_on(self.inner.query_scalar(sql, params))
+    }
+
+    /// Execute a SELECT query and deserialize each row into `T` with serde.
+    #[cfg(feature = "serde")]
+    pub fn query_de<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<Vec<T>> {
+        self.runtime.block_on(self.inner.query_de(sql, params))
+    }
+
+    /// Execute a SELECT query with per-call options.
+    pub fn query_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        self.runtime
+            .block_on(self.inner.query_with(sql, params, options))
+    }
+
+    /// Execute a SELECT query and iterate over its rows as they arrive.
+    pub fn query_stream(&self, sql: &str, params: &[Value]) -> Result<RowIter<'_>> {
+        let inner = self
+            .runtime
+            .block_on(self.inner.query_stream(sql, params))?;
+        Ok(RowIter {
+            inner,
+            runtime: &self.runtime,
+        })
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement.
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner.execute(sql, params))
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with per-call options.
+    pub fn execute_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecuteResult> {
+        self.runtime
+            .block_on(self.inner.execute_with(sql, params, options))
+    }
+
+    /// Execute any SQL statement.
+    pub fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.run(sql, params))
+    }
+
+    /// Execute any SQL statement with per-call options.
+    pub fn run_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        self.runtime
+            .block_on(self.inner.run_with(sql, params, options))
+    }
+
+    /// Execute several statements in one round trip.
+    pub fn execute_batch(&self, statements: &[(&str, &[Value])]) -> Result<Vec<ExecutionResult>> {
+        self.runtime.block_on(self.inner.execute_batch(statements))
+    }
+
+    /// Execute one statement once per parameter set, in one round trip.
+    pub fn execute_many<I, P>(&self, sql: &str, param_sets: I) -> Result<Vec<ExecuteResult>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[Value]>,
+    {
+        self.runtime
+            .block_on(self.inner.execute_many(sql, param_sets))
+    }
+
+    /// Execute a SELECT query with named parameters.
+    pub fn query_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.query_named(sql, params))
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with named parameters.
+    pub fn execute_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner.execute_named(sql, params))
+    }
+
+    /// Execute any SQL statement with named parameters.
+    pub fn run_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.run_named(sql, params))
+    }
+
+    /// Prepare a statement for repeated execution.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        Ok(Statement {
+            inner: self.inner.prepare(sql)?,
+            runtime: &self.runtime,
+        })
+    }
+
+    /// Wait until the embedded replica has applied transaction `txseq`.
+    ///
+    /// See [`connection::HAConnection::wait_for_replication`].
+    pub fn wait_for_replication(&self, txseq: i64, timeout: Duration) -> bool {
+        self.runtime
+            .block_on(self.inner.wait_for_replication(txseq, timeout))
+    }
+
+    /// Begin a transaction that rolls back unless committed.
+    pub fn transaction(&self) -> Result<Transaction<'_>> {
+        self.transaction_with(TransactionBehavior::default())
+    }
+
+    /// Begin a transaction with the given locking behavior that rolls back
+    /// unless committed.
+    pub fn transaction_with(&self, behavior: TransactionBehavior) -> Result<Transaction<'_>> {
+        let inner = self
+            .runtime
+            .block_on(self.inner.transaction_with(behavior))?;
+        Ok(Transaction {
+            inner: Some(inner),
+            runtime: &self.runtime,
+        })
+    }
+
+    /// Run `f` in a transaction and commit it, retrying with the default
+    /// [`TransactionOptions`].
+    pub fn run_transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnMut(&Transaction<'_>) -> Result<T>,
+    {
+        self.run_transaction_with(&TransactionOptions::default(), f)
+    }
+
+    /// Run `f` in a transaction and commit it, running it again in a new
+    /// transaction after a retryable error as
+    /// [`connection::HAConnection::run_transaction_with`] does.
+    pub fn run_transaction_with<T, F>(&self, options: &TransactionOptions, mut f: F) -> Result<T>
+    where
+        F: FnMut(&Transaction<'_>) -> Result<T>,
+    {
+        let mut delay = options.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let error = match self.transaction_with(options.behavior) {
+                Ok(tx) => match f(&tx) {
+                    Ok(value) => match tx.commit() {
+                        Ok(()) => return Ok(value),
+                        Err(e) => e,
+                    },
+                    Err(e) => {
+                        let _ = tx.rollback();
+                        e
+                    }
+                },
+                Err(e) => e,
+            };
+            if attempt >= options.max_retries || !error.is_retryable() {
+                return Err(error);
+            }
+            attempt += 1;
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+
+    /// Set a savepoint, beginning a transaction if none is open.
+    pub fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        let inner = self.runtime.block_on(self.inner.savepoint(name))?;
+        Ok(Savepoint {
+            inner: Some(inner),
+            runtime: &self.runtime,
+        })
+    }
+
+    /// Begin a transaction.
+    pub fn begin_transaction(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.begin_transaction())
+    }
+
+    /// Begin a transaction with the given locking behavior.
+    pub fn begin_transaction_with(&self, behavior: TransactionBehavior) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.begin_transaction_with(behavior))
+    }
+
+    /// Commit the current transaction.
+    pub fn commit(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.commit())
+    }
+
+    /// Rollback the current transaction.
+    pub fn rollback(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.rollback())
+    }
+
+    /// Set auto-commit mode.
+    pub fn set_auto_commit(&self, auto_commit: bool) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.set_auto_commit(auto_commit))
+    }
+
+    /// Get auto-commit mode.
+    pub fn auto_commit(&self) -> bool {
+        self.inner.auto_commit()
+    }
+
+    /// Set read-only mode.
+    pub fn set_read_only(&self, read_only: bool) -> Result<()> {
+        self.runtime.block_on(self.inner.set_read_only(read_only))
+    }
+
+    /// Get read-only mode.
+    pub fn read_only(&self) -> bool {
+        self.inner.read_only()
+    }
+
+    /// Get the consistency of reads whose options do not set one.
+    pub fn consistency(&self) -> Consistency {
+        self.inner.consistency()
+    }
+
+    /// Check if the connection is valid.
+    pub fn is_valid(&self) -> bool {
+        self.runtime.block_on(self.inner.is_valid())
+    }
+
+    /// Get the current catalog (database name).
+    pub fn catalog(&self) -> String {
+        self.inner.catalog()
+    }
+
+    /// Set the current catalog (database name).
+    pub fn set_catalog(&self, catalog: &str) -> Result<()> {
+        self.inner.set_catalog(catalog)
+    }
+
+    /// Set the current catalog, first downloading its replica if it has
+    /// none and downloading on switch is enabled.
+    pub fn switch_catalog(&self, catalog: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.switch_catalog(catalog))
+    }
+
+    /// Set the caller-supplied context attached to audit events.
+    pub fn set_audit_context(&self, context: AuditContext) {
+        self.inner.set_audit_context(context)
+    }
+
+    /// Get the routing counters for this connection.
+    pub fn routing_stats(&self) -> &Arc<RoutingStats> {
+        self.inner.routing_stats()
+    }
+
+    /// Get the read cache shared with this connection.
+    pub fn query_cache(&self) -> Option<&Arc<QueryCache>> {
+        self.inner.query_cache()
+    }
+
+    /// Get the prepared statement cache counters of reads on the embedded
+    /// replica.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn replica_statement_stats(&self) -> &Arc<StatementCacheStats> {
+        self.inner.replica_statement_stats()
+    }
+
+    /// Get the coalescer of reads in flight shared with this connection.
+    pub fn query_coalescer(&self) -> Option<&Arc<QueryCoalescer>> {
+        self.inner.query_coalescer()
+    }
+
+    /// Download a replica database file.
+    pub fn download_replica(
+        &self,
+        directory: &Path,
+        replication_id: &str,
+        override_existing: bool,
+    ) -> Result<()> {
+        self.runtime.block_on(self.inner.client().download_replica(
+            directory,
+            replication_id,
+            override_existing,
+        ))
+    }
+
+    /// Download a replica database file with `options`.
+    pub fn download_replica_with(
+        &self,
+        directory: &Path,
+        replication_id: &str,
+        override_existing: bool,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.client().download_replica_with(
+                directory,
+                replication_id,
+                override_existing,
+                options,
+            ))
+    }
+
+    /// Get all available replication IDs.
+    pub fn replication_ids(&self) -> Result<Vec<String>> {
+        self.runtime
+            .block_on(self.inner.client().get_replication_ids())
+    }
+
+    /// Get the wrapped async connection.
+    pub fn inner(&self) -> &connection::HAConnection {
+        &self.inner
+    }
+
+    /// Drive a future on the runtime of this connection.
+    #[cfg(feature = "codegen")]
+    pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Check if the connection is closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Close the connection.
+    pub fn close(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.close())
+    }
+}
+
+/// Blocking transaction that rolls back when dropped without being committed.
+pub struct Transaction<'conn> {
+    inner: Option<transaction::Transaction<'conn>>,
+    runtime: &'conn Runtime,
+}
+
+impl Transaction<'_> {
+    /// Execute a SELECT query.
+    pub fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner().query(sql, params))
+    }
+
+    /// Execute a SELECT query and map each row to `T` by column name.
+    pub fn query_as<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
+        self.runtime.block_on(self.inner().query_as(sql, params))
+    }
+
+    /// Execute a SELECT query that must return exactly one row.
+    pub fn query_one(&self, sql: &str, params: &[Value]) -> Result<OwnedRow> {
+        self.runtime.block_on(self.inner().query_one(sql, params))
+    }
+
+    /// Execute a SELECT query that returns at most one row.
+    pub fn query_optional(&self, sql: &str, params: &[Value]) -> Result<Option<OwnedRow>> {
+        self.runtime
+            .block_on(self.inner().query_optional(sql, params))
+    }
+
+    /// Execute a SELECT query that must return exactly one row and get its
+    /// first column as `T`.
+    pub fn query_scalar<T: FromValue>(&self, sql: &str, params: &[Value]) -> Result<T> {
+        self.runtime
+            .block_on(self.inner().query_scalar(sql, params))
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement.
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner().execute(sql, params))
+    }
+
+    /// Execute any SQL statement.
+    pub fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner().run(sql, params))
+    }
+
+    /// Execute several statements in one round trip.
+    pub fn execute_batch(&self, statements: &[(&str, &[Value])]) -> Result<Vec<ExecutionResult>> {
+        self.runtime
+            .block_on(self.inner().execute_batch(statements))
+    }
+
+    /// Execute one statement once per parameter set, in one round trip.
+    pub fn execute_many<I, P>(&self, sql: &str, param_sets: I) -> Result<Vec<ExecuteResult>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[Value]>,
+    {
+        self.runtime
+            .block_on(self.inner().execute_many(sql, param_sets))
+    }
+
+    /// Execute a SELECT query with named parameters.
+    pub fn query_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner().query_named(sql, params))
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with named parameters.
+    pub fn execute_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecuteResult> {
+        self.runtime
+            .block_on(self.inner().execute_named(sql, params))
+    }
+
+    /// Set a savepoint inside the transaction.
+    pub fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        let inner = self.runtime.block_on(self.inner().savepoint(name))?;
+        Ok(Savepoint {
+            inner: Some(inner),
+            runtime: self.runtime,
+        })
+    }
+
+    /// Commit the transaction; it is rolled back if the commit fails.
+    pub fn commit(mut self) -> Result<()> {
+        let inner = self.inner.take().expect("transaction open until finished");
+        self.runtime.block_on(inner.commit())
+    }
+
+    /// Roll back the transaction.
+    pub fn rollback(mut self) -> Result<()> {
+        let inner = self.inner.take().expect("transaction open until finished");
+        self.runtime.block_on(inner.rollback())
+    }
+
+    fn inner(&self) -> &transaction::Transaction<'_> {
+        self.inner
+            .as_ref()
+            .expect("transaction open until finished")
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            let _ = self.runtime.block_on(inner.rollback());
+        }
+    }
+}
+
+/// Blocking savepoint that is rolled back to when dropped without being released.
+pub struct Savepoint<'a> {
+    inner: Option<transaction::Savepoint<'a>>,
+    runtime: &'a Runtime,
+}
+
+impl Savepoint<'_> {
+    /// Get the savepoint name.
+    pub fn name(&self) -> &str {
+        self.inner().name()
+    }
+
+    /// Execute a SELECT query.
+    pub fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner().query(sql, params))
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement.
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner().execute(sql, params))
+    }
+
+    /// Execute any SQL statement.
+    pub fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner().run(sql, params))
+    }
+
+    /// Set a nested savepoint.
+    pub fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        let inner = self.runtime.block_on(self.inner().savepoint(name))?;
+        Ok(Savepoint {
+            inner: Some(inner),
+            runtime: self.runtime,
+        })
+    }
+
+    /// Release the savepoint, keeping its changes.
+    pub fn release(mut self) -> Result<()> {
+        let inner = self.inner.take().expect("savepoint open until finished");
+        self.runtime.block_on(inner.release())
+    }
+
+    /// Undo the changes made since the savepoint and release it.
+    pub fn rollback(mut self) -> Result<()> {
+        let inner = self.inner.take().expect("savepoint open until finished");
+        self.runtime.block_on(inner.rollback())
+    }
+
+    fn inner(&self) -> &transaction::Savepoint<'_> {
+        self.inner.as_ref().expect("savepoint open until finished")
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            let _ = self.runtime.block_on(inner.rollback());
+        }
+    }
+}
+
+/// Blocking iterator over the rows of a streamed query.
+pub struct RowIter<'conn> {
+    inner: RowStream,
+    runtime: &'conn Runtime,
+}
+
+impl RowIter<'_> {
+    /// Get the column names.
+    pub fn columns(&self) -> &[String] {
+        self.inner.columns()
+    }
+}
+
+impl Iterator for RowIter<'_> {
+    type Item = Result<OwnedRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.inner.next())
+    }
+}
+
+/// Blocking prepared statement.
+pub struct Statement<'conn> {
+    inner: statement::Statement<'conn>,
+    runtime: &'conn Runtime,
+}
+
+impl Statement<'_> {
+    /// Get the SQL text.
+    pub fn sql(&self) -> &str {
+        self.inner.sql()
+    }
+
+    /// Get the number of parameters the statement expects.
+    pub fn parameter_count(&self) -> usize {
+        self.inner.parameter_count()
+    }
+
+    /// Get the column names, once the statement has returned a result.
+    pub fn columns(&self) -> Option<Vec<String>> {
+        self.inner.columns()
+    }
+
+    /// Execute the statement as a SELECT query.
+    pub fn query(&self, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.query(params))
+    }
+
+    /// Execute the statement as an INSERT/UPDATE/DELETE statement.
+    pub fn execute(&self, params: &[Value]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner.execute(params))
+    }
+
+    /// Execute the statement as any SQL statement.
+    pub fn run(&self, params: &[Value]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.run(params))
+    }
+
+    /// Execute the statement as a SELECT query with named parameters.
+    pub fn query_named(&self, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.query_named(params))
+    }
+
+    /// Execute the statement as an INSERT/UPDATE/DELETE statement with named
+    /// parameters.
+    pub fn execute_named(&self, params: &[(&str, Value)]) -> Result<ExecuteResult> {
+        self.runtime.block_on(self.inner.execute_named(params))
+    }
+
+    /// Execute the statement as any SQL statement with named parameters.
+    pub fn run_named(&self, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.runtime.block_on(self.inner.run_named(params))
+    }
+}
@@ -0,0 +1,257 @@
+//! Bulk loading of rows.
+//!
+//! A [`BulkInserter`] reads rows from a stream and writes them with
+//! multi-row `INSERT` statements, committing a transaction every
+//! [batch](BulkInserter::with_batch_size) of rows. The statements of a batch
+//! are sent in one round trip, so loading is bound by batch count rather
+//! than row count.
+//!
+//! Loading is not atomic as a whole: a failing batch is rolled back and
+//! fails the insert, but earlier batches stay committed. The
+//! [progress callback](BulkInserter::with_progress) sees every committed
+//! batch.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::bulk::{BulkInserter, OnConflict};
+//! use litesql_ha::{HAConnection, Value};
+//!
+//! async fn load(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let rows = tokio_stream::iter(
+//!         (0..100_000).map(|i| vec![Value::Int64(i), Value::String(format!("user{}", i))]),
+//!     );
+//!     let progress = BulkInserter::new("users", ["id", "name"])
+//!         .with_batch_size(5_000)
+//!         .with_on_conflict(OnConflict::Ignore)
+//!         .with_progress(|p| println!("{} rows, {:.0} rows/s", p.rows, p.rows_per_second()))
+//!         .insert(conn, rows)
+//!         .await?;
+//!     println!("inserted {} of {} rows", progress.inserted, progress.rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::runtime::Instant;
+use crate::script::quote_identifier;
+use crate::value::Value;
+use std::fmt;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
+
+/// Most parameters SQLite binds to one statement by default.
+const MAX_PARAMETERS: usize = 32_766;
+
+/// What happens to a row that conflicts with an existing one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Fail the batch
+    #[default]
+    Abort,
+    /// Skip the row (`INSERT OR IGNORE`)
+    Ignore,
+    /// Replace the existing row (`INSERT OR REPLACE`)
+    Replace,
+}
+
+impl OnConflict {
+    fn verb(self) -> &'static str {
+        match self {
+            OnConflict::Abort => "INSERT",
+            OnConflict::Ignore => "INSERT OR IGNORE",
+            OnConflict::Replace => "INSERT OR REPLACE",
+        }
+    }
+}
+
+/// Progress of a bulk insert.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BulkProgress {
+    /// Rows written
+    pub rows: u64,
+    /// Rows inserted or replaced; fewer than `rows` when conflicting rows
+    /// were ignored
+    pub inserted: u64,
+    /// Batches committed
+    pub batches: u64,
+    /// Time since the insert started
+    pub elapsed: Duration,
+}
+
+impl BulkProgress {
+    /// Rows written per second.
+    pub fn rows_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.rows as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+type ProgressFn = Arc<dyn Fn(&BulkProgress) + Send + Sync>;
+
+/// Writes rows from a stream in batched multi-row `INSERT`s.
+#[derive(Clone)]
+pub struct BulkInserter {
+    table: String,
+    columns: Vec<String>,
+    batch_size: usize,
+    on_conflict: OnConflict,
+    progress: Option<ProgressFn>,
+}
+
+impl BulkInserter {
+    /// Create an inserter writing `columns` of `table`.
+    pub fn new<I>(table: impl Into<String>, columns: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            table: table.into(),
+            columns: columns.into_iter().map(Into::into).collect(),
+            batch_size: 1000,
+            on_conflict: OnConflict::default(),
+            progress: None,
+        }
+    }
+
+    /// Commit a transaction every `batch_size` rows; 1000 by default.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set what happens to rows that conflict with existing ones.
+    pub fn with_on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Call `progress` after each committed batch.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&BulkProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Insert every row of `rows`, each holding one value per column.
+    ///
+    /// Returns the progress after the last batch.
+    pub async fn insert<S>(&self, conn: &HAConnection, rows: S) -> Result<BulkProgress>
+    where
+        S: Stream<Item = Vec<Value>>,
+    {
+        if self.columns.is_empty() {
+            return Err(Error::InvalidParameter(
+                "bulk insert needs at least one column".to_string(),
+            ));
+        }
+
+        let started = Instant::now();
+        let mut progress = BulkProgress::default();
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let mut rows = pin!(rows);
+        while let Some(row) = rows.next().await {
+            if row.len() != self.columns.len() {
+                return Err(Error::InvalidParameter(format!(
+                    "bulk insert row for {} has {} values, expected {}",
+                    self.table,
+                    row.len(),
+                    self.columns.len()
+                )));
+            }
+            batch.push(row);
+            if batch.len() >= self.batch_size {
+                self.commit_batch(conn, std::mem::take(&mut batch), &mut progress, started)
+                    .await?;
+            }
+        }
+        if !batch.is_empty() {
+            self.commit_batch(conn, batch, &mut progress, started)
+                .await?;
+        }
+
+        progress.elapsed = started.elapsed();
+        Ok(progress)
+    }
+
+    /// Insert `rows` in one transaction and report the progress.
+    pub(crate) async fn commit_batch(
+        &self,
+        conn: &HAConnection,
+        rows: Vec<Vec<Value>>,
+        progress: &mut BulkProgress,
+        started: Instant,
+    ) -> Result<()> {
+        let count = rows.len() as u64;
+
+        conn.begin_transaction().await?;
+        let inserted = match self.write(conn, rows).await {
+            Ok(inserted) => {
+                conn.commit().await?;
+                inserted
+            }
+            Err(e) => {
+                let _ = conn.rollback().await;
+                return Err(e);
+            }
+        };
+
+        progress.rows += count;
+        progress.inserted += inserted;
+        progress.batches += 1;
+        progress.elapsed = started.elapsed();
+        if let Some(ref report) = self.progress {
+            report(progress);
+        }
+        Ok(())
+    }
+
+    /// Write a batch as few statements as the parameter limit allows.
+    async fn write(&self, conn: &HAConnection, rows: Vec<Vec<Value>>) -> Result<u64> {
+        let per_statement = (MAX_PARAMETERS / self.columns.len()).max(1);
+        let statements: Vec<(String, Vec<Value>)> = rows
+            .chunks(per_statement)
+            .map(|chunk| (self.sql(chunk.len()), chunk.concat()))
+            .collect();
+        let statements: Vec<(&str, &[Value])> = statements
+            .iter()
+            .map(|(sql, params)| (sql.as_str(), params.as_slice()))
+            .collect();
+
+        let results = conn.execute_batch(&statements).await?;
+        Ok(results.iter().map(|r| r.rows_affected.max(0) as u64).sum())
+    }
+
+    fn sql(&self, rows: usize) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|c| quote_identifier(c)).collect();
+        let row = format!("({})", vec!["?"; self.columns.len()].join(", "));
+        format!(
+            "{} INTO {} ({}) VALUES {}",
+            self.on_conflict.verb(),
+            quote_identifier(&self.table),
+            columns.join(", "),
+            vec![row; rows].join(", ")
+        )
+    }
+}
+
+impl fmt::Debug for BulkInserter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkInserter")
+            .field("table", &self.table)
+            .field("columns", &self.columns)
+            .field("batch_size", &self.batch_size)
+            .field("on_conflict", &self.on_conflict)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,367 @@
+//! Client-side read cache with txseq-based invalidation.
+//!
+//! A [`QueryCache`] shared by the connections of a data source stores the
+//! results of `SELECT` statements read from the HA server, keyed by catalog,
+//! a whitespace-normalized fingerprint of the SQL, and the parameters. Each
+//! entry remembers the txseq the client had seen when it was read. Every
+//! response observed by a connection raises the cache's txseq for its
+//! catalog, and entries read before that point are dropped, so a write made
+//! through any connection sharing the cache invalidates what it may have
+//! changed.
+//!
+//! Writes by other clients are only noticed once a response (or a fresher
+//! embedded replica) reveals a newer txseq. Set a [TTL](QueryCacheOptions::ttl)
+//! to bound how stale a cached result can get in that case, and don't cache
+//! queries whose results depend on the clock or on `random()`.
+//!
+//! With the `nats` feature, a data source with embedded replicas also feeds
+//! the cache the changes that replication applies. A replicated change drops
+//! only the results of queries naming the changed table, or a view over it,
+//! and a result stays valid past a newer txseq once replication has caught
+//! up to it without touching its tables. A dashboard repeating the same
+//! aggregate then keeps hitting the cache while other tables are written.
+//! Replicated schema changes drop every result of the catalog.
+//!
+//! Reads inside a transaction, statements that are not `SELECT`s, and reads
+//! served by an embedded replica are never cached.
+
+use crate::client::ExecutionResult;
+use crate::runtime::Instant;
+use crate::value::Value;
+use parking_lot::Mutex;
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Options for [`QueryCache`].
+#[derive(Debug, Clone)]
+pub struct QueryCacheOptions {
+    /// Maximum number of cached results
+    pub max_entries: usize,
+    /// Maximum age of a cached result; unlimited when not set
+    pub ttl: Option<Duration>,
+}
+
+impl Default for QueryCacheOptions {
+    fn default() -> Self {
+        Self {
+            max_entries: 1024,
+            ttl: None,
+        }
+    }
+}
+
+/// Point-in-time cache counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounts {
+    /// Reads answered from the cache
+    pub hits: u64,
+    /// Cacheable reads sent to the server
+    pub misses: u64,
+    /// Entries dropped because the txseq advanced or the TTL expired
+    pub invalidations: u64,
+    /// Entries dropped to make room
+    pub evictions: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    catalog: String,
+    fingerprint: String,
+    params: Vec<u8>,
+}
+
+impl CacheKey {
+    /// Build the key for a statement, or `None` if it must not be cached.
+    pub(crate) fn new(catalog: &str, sql: &str, params: &[Value]) -> Option<Self> {
+        let fingerprint = fingerprint(sql);
+        let is_select = fingerprint
+            .get(..6)
+            .is_some_and(|head| head.eq_ignore_ascii_case("SELECT"));
+        if !is_select {
+            return None;
+        }
+
+        let mut encoded = Vec::new();
+        for param in params {
+            param
+                .to_any()
+                .encode_length_delimited(&mut encoded)
+                .expect("Vec<u8> has unlimited capacity");
+        }
+        Some(Self {
+            catalog: catalog.to_string(),
+            fingerprint,
+            params: encoded,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    result: ExecutionResult,
+    txseq: i64,
+    stored_at: Instant,
+    /// Lowercased identifiers of the statement, covering every table it reads
+    names: HashSet<String>,
+}
+
+/// Read-through cache of query results shared by connections.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    options: QueryCacheOptions,
+    entries: Mutex<HashMap<CacheKey, Entry>>,
+    txseqs: Mutex<HashMap<String, i64>>,
+    /// Txseq up to which replicated changes have invalidated each catalog
+    replicated: Mutex<HashMap<String, i64>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl QueryCache {
+    /// Create an empty cache.
+    pub fn new(options: QueryCacheOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Get the number of cached results.
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Check if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    /// Drop every cached result.
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+
+    /// Drop every cached result of a catalog.
+    pub fn invalidate(&self, catalog: &str) {
+        self.entries.lock().retain(|k, _| k.catalog != catalog);
+    }
+
+    /// Drop the cached results of a catalog whose statements name `table`.
+    pub fn invalidate_table(&self, catalog: &str, table: &str) {
+        let table = table.to_lowercase();
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|k, e| k.catalog != catalog || !e.names.contains(&table));
+        self.invalidations
+            .fetch_add((before - entries.len()) as u64, Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the counters.
+    pub fn snapshot(&self) -> CacheCounts {
+        CacheCounts {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get a cached result that is still valid.
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<ExecutionResult> {
+        let txseq = self.txseq(&key.catalog);
+        let mut entries = self.entries.lock();
+        match entries.get(key) {
+            Some(entry) if self.is_valid(&key.catalog, entry, txseq) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.result.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                self.invalidations.fetch_add(1, Ordering::Relaxed);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Store a result read at `txseq`.
+    pub(crate) fn insert(&self, key: CacheKey, result: &ExecutionResult, txseq: i64) {
+        if self.options.max_entries == 0 || txseq < self.txseq(&key.catalog) {
+            return;
+        }
+
+        let mut entries = self.entries.lock();
+        // Replicated changes up to a newer txseq were not checked against it
+        if txseq < self.replicated_txseq(&key.catalog) {
+            return;
+        }
+        if entries.len() >= self.options.max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.stored_at)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut result = result.clone();
+        result.routing = None;
+        let names = identifiers(&key.fingerprint);
+        entries.insert(
+            key,
+            Entry {
+                result,
+                txseq,
+                stored_at: Instant::now(),
+                names,
+            },
+        );
+    }
+
+    /// Record a txseq seen for a catalog, dropping results read before it.
+    ///
+    /// Results of a catalog fed by replication are kept, as replication may
+    /// still show them unchanged at the new txseq.
+    pub(crate) fn observe(&self, catalog: &str, txseq: i64) {
+        {
+            let mut txseqs = self.txseqs.lock();
+            let current = txseqs.entry(catalog.to_string()).or_insert(0);
+            if txseq <= *current {
+                return;
+            }
+            *current = txseq;
+        }
+        if self.replicated.lock().contains_key(catalog) {
+            return;
+        }
+
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|k, e| k.catalog != catalog || e.txseq >= txseq);
+        self.invalidations
+            .fetch_add((before - entries.len()) as u64, Ordering::Relaxed);
+    }
+
+    /// Drop the results of a catalog read before a replicated transaction at
+    /// `txseq` that changed `tables`, or changed the schema when `None`.
+    ///
+    /// `tables` must include the views reading the changed tables.
+    #[cfg(feature = "nats")]
+    pub(crate) fn replicated_change(
+        &self,
+        catalog: &str,
+        tables: Option<&HashSet<String>>,
+        txseq: i64,
+    ) {
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|k, e| {
+            k.catalog != catalog
+                || e.txseq >= txseq
+                || tables.is_some_and(|tables| e.names.is_disjoint(tables))
+        });
+        self.invalidations
+            .fetch_add((before - entries.len()) as u64, Ordering::Relaxed);
+
+        let mut replicated = self.replicated.lock();
+        let current = replicated.entry(catalog.to_string()).or_insert(0);
+        *current = (*current).max(txseq);
+    }
+
+    fn txseq(&self, catalog: &str) -> i64 {
+        self.txseqs.lock().get(catalog).copied().unwrap_or(0)
+    }
+
+    fn replicated_txseq(&self, catalog: &str) -> i64 {
+        self.replicated.lock().get(catalog).copied().unwrap_or(0)
+    }
+
+    fn is_valid(&self, catalog: &str, entry: &Entry, txseq: i64) -> bool {
+        let current = entry.txseq >= txseq
+            || (!entry.names.is_empty() && self.replicated_txseq(catalog) >= txseq);
+        current
+            && self
+                .options
+                .ttl
+                .is_none_or(|ttl| entry.stored_at.elapsed() < ttl)
+    }
+}
+
+/// Lowercased identifiers of a statement, bare or quoted, outside string
+/// literals. A superset of the tables and views it reads.
+pub(crate) fn identifiers(sql: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                let name: String = chars.by_ref().take_while(|&c| c != close).collect();
+                names.insert(name.to_lowercase());
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = c.to_lowercase().to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                        break;
+                    }
+                    name.extend(c.to_lowercase());
+                    chars.next();
+                }
+                names.insert(name);
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Collapse whitespace outside quoted strings and identifiers and drop a
+/// trailing semicolon, so formatting differences share a cache entry.
+fn fingerprint(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut quote: Option<char> = None;
+    let mut pending_space = false;
+
+    for c in sql.trim().trim_end_matches(';').trim_end().chars() {
+        match quote {
+            Some(q) => {
+                out.push(c);
+                if c == q {
+                    quote = None;
+                }
+            }
+            None if c.is_whitespace() => pending_space = true,
+            None => {
+                if pending_space && !out.is_empty() {
+                    out.push(' ');
+                }
+                pending_space = false;
+                if matches!(c, '\'' | '"' | '`') {
+                    quote = Some(c);
+                } else if c == '[' {
+                    quote = Some(']');
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,178 @@
+//! Query cancellation.
+//!
+//! A [`CancelHandle`] is passed to a query through
+//! [`QueryOptions::cancel`](crate::QueryOptions::cancel) and cancelled from
+//! anywhere else, for example when the HTTP request the query serves is
+//! dropped. Cancelling aborts the `Query` stream of a query sent to the
+//! server, and interrupts a read running on an embedded replica with
+//! `sqlite3_interrupt`. The cancelled query fails with
+//! [`Error::Cancelled`](crate::Error::Cancelled).
+//!
+//! A write that was cancelled may still have been applied by the server.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{CancelHandle, HAConnection, QueryOptions};
+//!
+//! async fn dashboard(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let cancel = CancelHandle::new();
+//!     // Cancels the query if this future is dropped before it finishes.
+//!     let _guard = cancel.cancel_on_drop();
+//!     let options = QueryOptions::default().with_cancel(cancel.clone());
+//!     let result = conn
+//!         .query_with("SELECT day, COUNT(*) FROM events GROUP BY day", &[], &options)
+//!         .await?;
+//!     println!("{:?}", result.rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::runtime;
+#[cfg(feature = "embedded-replicas")]
+use parking_lot::Mutex;
+use std::future::Future;
+use std::pin::pin;
+#[cfg(feature = "embedded-replicas")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Cancels the queries it was passed to; clones cancel the same queries.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+    #[cfg(feature = "embedded-replicas")]
+    interrupts: Mutex<Vec<(u64, rusqlite::InterruptHandle)>>,
+    #[cfg(feature = "embedded-replicas")]
+    next_id: AtomicU64,
+}
+
+impl CancelHandle {
+    /// Create a handle that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the queries running with this handle, and any started with it
+    /// later.
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.inner.notify.notify_waiters();
+        #[cfg(feature = "embedded-replicas")]
+        for (_, interrupt) in self.inner.interrupts.lock().iter() {
+            interrupt.interrupt();
+        }
+    }
+
+    /// Check if the handle has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Wait until the handle is cancelled.
+    pub async fn cancelled(&self) {
+        let mut notified = pin!(self.inner.notify.notified());
+        // Register for the notification before checking, so a cancel in
+        // between is not missed.
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// Get a guard that cancels the handle when dropped.
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            handle: Some(self.clone()),
+        }
+    }
+
+    /// Fail with [`Error::Cancelled`] if the handle was cancelled before the
+    /// query started.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Run `future` until it completes or the handle is cancelled; dropping
+    /// the future aborts the call it was making.
+    pub(crate) async fn run<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        self.check()?;
+        runtime::select! {
+            result = future => result,
+            _ = self.cancelled() => Err(Error::Cancelled),
+        }
+    }
+
+    /// Interrupt the SQLite connection if the handle is cancelled while the
+    /// returned guard is alive.
+    #[cfg(feature = "embedded-replicas")]
+    pub(crate) fn interrupt_on_cancel(&self, conn: &rusqlite::Connection) -> InterruptGuard<'_> {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let interrupt = conn.get_interrupt_handle();
+        self.inner.interrupts.lock().push((id, interrupt));
+        InterruptGuard { handle: self, id }
+    }
+}
+
+impl std::fmt::Debug for CancelHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Cancels its [`CancelHandle`] when dropped, unless
+/// [`disarm`](Self::disarm)ed.
+#[derive(Debug)]
+pub struct CancelOnDrop {
+    handle: Option<CancelHandle>,
+}
+
+impl CancelOnDrop {
+    /// Keep the handle from being cancelled when the guard is dropped.
+    pub fn disarm(mut self) -> CancelHandle {
+        self.handle.take().expect("guard is armed until dropped")
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(ref handle) = self.handle {
+            handle.cancel();
+        }
+    }
+}
+
+/// Unregisters an SQLite interrupt handle from a [`CancelHandle`].
+#[cfg(feature = "embedded-replicas")]
+pub(crate) struct InterruptGuard<'a> {
+    handle: &'a CancelHandle,
+    id: u64,
+}
+
+#[cfg(feature = "embedded-replicas")]
+impl Drop for InterruptGuard<'_> {
+    fn drop(&mut self) {
+        self.handle
+            .inner
+            .interrupts
+            .lock()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
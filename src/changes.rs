@@ -0,0 +1,289 @@
+//! Subscriptions to the row changes replicated to embedded replicas.
+//!
+//! Enabled by the `nats` feature. Every change set the replicator applies is
+//! split into one [`ChangeEvent`] per changed row and offered to the
+//! subscriptions whose [`ChangeFilter`] accepts it. Statements replicated as
+//! SQL, such as DDL, change no single table and are not published.
+//!
+//! Each subscription buffers up to its
+//! [capacity](ChangeFilter::with_capacity) of events. A subscriber that falls
+//! behind does not hold up replication or other subscribers: events that do
+//! not fit are dropped and counted in [`ChangeSubscription::missed`], so the
+//! subscriber knows to reload what it shows.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::changes::ChangeFilter;
+//! use litesql_ha::{EmbeddedReplicasManager, Value};
+//!
+//! async fn watch(manager: &EmbeddedReplicasManager) {
+//!     let filter = ChangeFilter::new()
+//!         .with_tables(["orders"])
+//!         .with_predicate(|event| event.get("status") == Some(&Value::from("shipped")));
+//!     let mut orders = manager.subscribe_changes(filter);
+//!     while let Some(event) = orders.recv().await {
+//!         println!("order {} shipped", event.rowid);
+//!     }
+//! }
+//! ```
+
+use crate::replication::{Change, ChangeSet};
+use crate::value::Value;
+use parking_lot::Mutex;
+use rusqlite::types::Value as SqliteValue;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Kind of a row change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOperation {
+    /// The row was inserted
+    Insert,
+    /// The row was updated
+    Update,
+    /// The row was deleted
+    Delete,
+}
+
+/// A row changed by a replicated transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    /// Replica the change was applied to
+    pub database: String,
+    /// Changed table
+    pub table: String,
+    /// Kind of the change
+    pub operation: ChangeOperation,
+    /// Rowid of the row after the change; before it for deletes
+    pub rowid: i64,
+    /// Columns of `values`
+    pub columns: Vec<String>,
+    /// Values of the row after the change; empty for deletes
+    pub values: Vec<Value>,
+    /// Txseq of the transaction that made the change
+    pub txseq: i64,
+}
+
+impl ChangeEvent {
+    /// Get the new value of a column.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(column))
+            .and_then(|i| self.values.get(i))
+    }
+}
+
+type Predicate = Arc<dyn Fn(&ChangeEvent) -> bool + Send + Sync>;
+
+/// Which changes a subscription receives.
+#[derive(Clone)]
+pub struct ChangeFilter {
+    tables: Vec<String>,
+    predicate: Option<Predicate>,
+    capacity: usize,
+}
+
+impl ChangeFilter {
+    /// Accept changes to every table.
+    pub fn new() -> Self {
+        Self {
+            tables: Vec::new(),
+            predicate: None,
+            capacity: 1024,
+        }
+    }
+
+    /// Only accept changes to `tables`.
+    pub fn with_tables<I>(mut self, tables: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.tables = tables.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only accept changes for which `predicate` returns `true`.
+    ///
+    /// The predicate runs on the replicator task, so it should be cheap.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&ChangeEvent) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Buffer up to `capacity` events; 1024 by default.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    fn accepts(&self, event: &ChangeEvent) -> bool {
+        let table_matches = self.tables.is_empty()
+            || self
+                .tables
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&event.table));
+        table_matches
+            && match self.predicate {
+                Some(ref predicate) => predicate(event),
+                None => true,
+            }
+    }
+}
+
+impl Default for ChangeFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ChangeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChangeFilter")
+            .field("tables", &self.tables)
+            .field("predicate", &self.predicate.is_some())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+/// Receives the changes accepted by a [`ChangeFilter`].
+///
+/// Dropping the subscription unsubscribes it.
+#[derive(Debug)]
+pub struct ChangeSubscription {
+    events: mpsc::Receiver<ChangeEvent>,
+    missed: Arc<AtomicU64>,
+}
+
+impl ChangeSubscription {
+    /// Wait for the next change; `None` once replication has stopped.
+    pub async fn recv(&mut self) -> Option<ChangeEvent> {
+        self.events.recv().await
+    }
+
+    /// Take the next change if one is buffered.
+    pub fn try_recv(&mut self) -> Option<ChangeEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Get the number of changes dropped because the buffer was full since
+    /// the last call, and reset it.
+    pub fn missed(&self) -> u64 {
+        self.missed.swap(0, Ordering::Relaxed)
+    }
+}
+
+struct Subscriber {
+    filter: ChangeFilter,
+    events: mpsc::Sender<ChangeEvent>,
+    missed: Arc<AtomicU64>,
+}
+
+/// The subscriptions of a replicas manager.
+#[derive(Clone, Default)]
+pub(crate) struct ChangeSubscribers(Arc<Mutex<Vec<Subscriber>>>);
+
+impl ChangeSubscribers {
+    pub(crate) fn subscribe(&self, filter: ChangeFilter) -> ChangeSubscription {
+        let (sender, events) = mpsc::channel(filter.capacity);
+        let missed = Arc::new(AtomicU64::new(0));
+        self.0.lock().push(Subscriber {
+            filter,
+            events: sender,
+            missed: missed.clone(),
+        });
+        ChangeSubscription { events, missed }
+    }
+
+    /// Offer the row changes of a change set applied to `database`.
+    pub(crate) fn publish(&self, database: &str, changes: &ChangeSet, txseq: i64) {
+        let mut subscribers = self.0.lock();
+        subscribers.retain(|s| !s.events.is_closed());
+        if subscribers.is_empty() {
+            return;
+        }
+
+        for change in &changes.changes {
+            let event = match event(database, change, txseq) {
+                Some(event) => event,
+                None => continue,
+            };
+            for subscriber in subscribers.iter() {
+                if !subscriber.filter.accepts(&event) {
+                    continue;
+                }
+                if let Err(mpsc::error::TrySendError::Full(_)) =
+                    subscriber.events.try_send(event.clone())
+                {
+                    subscriber.missed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Drop every subscription, ending their streams.
+    pub(crate) fn clear(&self) {
+        self.0.lock().clear();
+    }
+}
+
+fn event(database: &str, change: &Change, txseq: i64) -> Option<ChangeEvent> {
+    let (operation, table, rowid, columns, values) = match change {
+        Change::Insert {
+            table,
+            columns,
+            rowid,
+            values,
+        } => (
+            ChangeOperation::Insert,
+            table,
+            *rowid,
+            columns.as_slice(),
+            values.as_slice(),
+        ),
+        Change::Update {
+            table,
+            columns,
+            new_rowid,
+            values,
+            ..
+        } => (
+            ChangeOperation::Update,
+            table,
+            *new_rowid,
+            columns.as_slice(),
+            values.as_slice(),
+        ),
+        Change::Delete { table, rowid } => {
+            (ChangeOperation::Delete, table, *rowid, &[][..], &[][..])
+        }
+        Change::Sql { .. } => return None,
+    };
+    Some(ChangeEvent {
+        database: database.to_string(),
+        table: table.clone(),
+        operation,
+        rowid,
+        columns: columns.to_vec(),
+        values: values.iter().map(to_value).collect(),
+        txseq,
+    })
+}
+
+fn to_value(value: &SqliteValue) -> Value {
+    match value {
+        SqliteValue::Null => Value::Null,
+        SqliteValue::Integer(v) => Value::Int64(*v),
+        SqliteValue::Real(v) => Value::Double(*v),
+        SqliteValue::Text(v) => Value::String(v.clone()),
+        SqliteValue::Blob(v) => Value::Bytes(v.clone()),
+    }
+}
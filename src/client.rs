@@ -1,32 +1,95 @@
 //! HA Client for communicating with the SQLite HA server via gRPC.
 
-use crate::error::{Error, Result};
+use crate::auth::{Credentials, TokenProvider};
+use crate::cancel::CancelHandle;
+use crate::cluster::{ClusterInfo, DatabaseStatus, NodeInfo, NodeRole};
+use crate::datetime::TimestampStorage;
+use crate::deadline;
+use crate::discovery::{self, DiscoveryOptions};
+use crate::download::{self, DownloadOptions};
+use crate::error::{Error, ErrorKind, Result};
+#[cfg(target_arch = "wasm32")]
+use crate::grpc_web::{Channel, Endpoint};
+use crate::load_balancing::{LoadBalancer, ReadEndpoint, ReadEndpoints, RoundRobin};
+use crate::metadata::{self, MetadataMap, MetadataProvider};
+use crate::middleware::{self, Middleware, Transport};
+use crate::pipeline::Pipeline;
 use crate::proto::{
-    database_service_client::DatabaseServiceClient, DownloadRequest, NamedValue, QueryRequest,
-    QueryResponse, QueryType,
+    self, database_service_client::DatabaseServiceClient, NamedValue, QueryRequest, QueryResponse,
+    QueryType,
 };
+#[cfg(feature = "embedded-replicas")]
+use crate::proto::{DownloadRequest, DownloadResponse, FramesRequest, FramesResponse};
+use crate::recording::{Recorder, Replay};
+use crate::routing::{Consistency, RoutingDecision};
+use crate::row::{FromRow, FromValue, OwnedRow, Row};
+use crate::runtime::{self, Instant};
+use crate::telemetry::{self, Peer};
 use crate::value::Value;
+#[cfg(feature = "websocket")]
+use crate::websocket;
 use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::future::Future;
 use std::path::Path;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::fs;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+#[cfg(all(any(feature = "gzip", feature = "zstd"), feature = "embedded-replicas"))]
+use tonic::codec::CompressionEncoding;
+use tonic::codegen::http::uri::{Authority, Uri};
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+#[cfg(not(target_arch = "wasm32"))]
 use tonic::transport::{Channel, Endpoint};
-use tonic::{Request, Streaming};
+use tonic::{Code, Request, Status, Streaming};
+use tracing::{debug, info, warn, Instrument};
 use url::Url;
 
 /// Options for HAClient configuration.
 #[derive(Debug, Clone)]
 pub struct HAClientOptions {
-    /// The URL of the HA server (e.g., "litesql://localhost:8080")
+    /// The URL of the HA server (e.g., "litesql://localhost:8080"); if it
+    /// is a follower, statements it redirects are sent on to the leader
     pub url: String,
     /// Authentication token
     pub token: Option<String>,
+    /// Supplies a token for every call, taking precedence over `token`
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Supplies metadata sent with every call
+    pub metadata_provider: Option<Arc<dyn MetadataProvider>>,
+    /// Interceptors or tower layers wrapping the gRPC channel
+    pub middleware: Option<Middleware>,
+    /// URLs of follower nodes serving reads, each labelled with its zone by
+    /// an optional `zone` parameter; reads go to the server at `url` when
+    /// empty
+    pub read_endpoints: Vec<String>,
+    /// Spreads reads over `read_endpoints`; round-robin when not set
+    pub load_balancer: Option<Arc<dyn LoadBalancer>>,
+    /// Resolve the servers of `url` from DNS periodically, following the
+    /// server pool as it scales
+    pub discovery: Option<DiscoveryOptions>,
     /// Enable SSL/TLS
     pub enable_ssl: bool,
-    /// Query timeout in seconds
+    /// Certificates for TLS; setting them enables TLS
+    pub tls: Option<TlsOptions>,
+    /// Deadline of each call in seconds, unless overridden by
+    /// [`QueryOptions::timeout`]
     pub timeout: u64,
+    /// Records `Query` traffic to a file
+    pub recorder: Option<Recorder>,
+    /// Serves recorded responses instead of calling the server
+    pub replay: Option<Replay>,
+    /// Run each transaction over a single `Query` stream
+    pub sticky_transactions: bool,
+    /// HTTP/2 keepalive pings
+    pub keepalive: KeepaliveOptions,
+    /// How `Value::Timestamp` parameters are stored
+    pub timestamp_storage: TimestampStorage,
 }
 
 impl Default for HAClientOptions {
@@ -34,12 +97,270 @@ impl Default for HAClientOptions {
         Self {
             url: String::new(),
             token: None,
+            token_provider: None,
+            metadata_provider: None,
+            middleware: None,
+            read_endpoints: Vec::new(),
+            load_balancer: None,
+            discovery: None,
             enable_ssl: false,
+            tls: None,
             timeout: 30,
+            recorder: None,
+            replay: None,
+            sticky_transactions: false,
+            keepalive: KeepaliveOptions::default(),
+            timestamp_storage: TimestampStorage::default(),
         }
     }
 }
 
+/// Certificates for a TLS connection to the HA server.
+///
+/// Requires the `tls` feature. Certificates and keys are PEM encoded.
+///
+/// # Example
+///
+/// A server with a certificate from an internal CA, reached by IP address:
+///
+/// ```no_run
+/// use litesql_ha::{HAClientOptions, TlsOptions};
+///
+/// # fn example() -> litesql_ha::Result<()> {
+/// let options = HAClientOptions {
+///     url: "litesqls://10.0.0.5:8080/mydb".to_string(),
+///     tls: Some(
+///         TlsOptions::default()
+///             .with_ca_cert_file("/etc/litesql/ca.pem")?
+///             .with_domain_override("litesql.internal"),
+///     ),
+///     ..Default::default()
+/// };
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// Root certificates to trust instead of the system's; may hold several
+    pub ca_cert: Option<Vec<u8>>,
+    /// Client certificate presented for mutual TLS
+    pub client_cert: Option<Vec<u8>>,
+    /// Private key of the client certificate
+    pub client_key: Option<Vec<u8>>,
+    /// Name sent for SNI and verified against the server certificate instead
+    /// of the URL host
+    pub domain_override: Option<String>,
+}
+
+impl TlsOptions {
+    /// Trust the root certificates of a PEM bundle instead of the system's.
+    pub fn with_ca_cert(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.ca_cert = Some(pem.into());
+        self
+    }
+
+    /// Trust the root certificates of a PEM bundle file instead of the
+    /// system's.
+    pub fn with_ca_cert_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(self.with_ca_cert(std::fs::read(path)?))
+    }
+
+    /// Present a client certificate for mutual TLS.
+    pub fn with_client_identity(
+        mut self,
+        cert: impl Into<Vec<u8>>,
+        key: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = Some(key.into());
+        self
+    }
+
+    /// Present a client certificate read from files for mutual TLS.
+    pub fn with_client_identity_files(
+        self,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self> {
+        Ok(self.with_client_identity(std::fs::read(cert)?, std::fs::read(key)?))
+    }
+
+    /// Verify the server certificate against `domain`, and send it for SNI,
+    /// instead of the URL host.
+    pub fn with_domain_override(mut self, domain: impl Into<String>) -> Self {
+        self.domain_override = Some(domain.into());
+        self
+    }
+}
+
+#[cfg(feature = "tls")]
+impl TlsOptions {
+    fn client_config(&self) -> Result<ClientTlsConfig> {
+        let mut config = match self.ca_cert {
+            Some(ref pem) => {
+                // rustls skips anything that isn't a certificate, which would
+                // otherwise surface as an unknown issuer on connect.
+                if !String::from_utf8_lossy(pem).contains("-----BEGIN CERTIFICATE-----") {
+                    return Err(Error::InvalidParameter(
+                        "ca_cert holds no PEM certificate".to_string(),
+                    ));
+                }
+                ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem))
+            }
+            None => ClientTlsConfig::new().with_native_roots(),
+        };
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => config = config.identity(Identity::from_pem(cert, key)),
+            (None, None) => {}
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "client_cert and client_key must be set together".to_string(),
+                ))
+            }
+        }
+        if let Some(ref domain) = self.domain_override {
+            if domain.is_empty() {
+                return Err(Error::InvalidParameter(
+                    "domain_override cannot be empty".to_string(),
+                ));
+            }
+            config = config.domain_name(domain);
+        }
+        Ok(config)
+    }
+}
+
+/// Only whether certificates and keys are set is printed.
+impl std::fmt::Debug for TlsOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsOptions")
+            .field("ca_cert", &self.ca_cert.is_some())
+            .field("client_cert", &self.client_cert.is_some())
+            .field("client_key", &self.client_key.is_some())
+            .field("domain_override", &self.domain_override)
+            .finish()
+    }
+}
+
+/// HTTP/2 keepalive settings.
+///
+/// Pings let the client notice a connection that a NAT or load balancer has
+/// dropped without closing it. They are off by default: servers limit how
+/// often clients may ping and close connections that ping more often, so
+/// the interval should match the server's policy.
+#[derive(Debug, Clone)]
+pub struct KeepaliveOptions {
+    /// Interval between pings; `None` disables keepalive
+    pub interval: Option<Duration>,
+    /// How long to wait for a ping to be acknowledged before closing the
+    /// connection
+    pub timeout: Duration,
+    /// Ping while no call is in progress
+    pub while_idle: bool,
+}
+
+impl Default for KeepaliveOptions {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            timeout: Duration::from_secs(20),
+            while_idle: false,
+        }
+    }
+}
+
+/// Per-call overrides of the client options.
+///
+/// # Example
+///
+/// ```no_run
+/// use litesql_ha::{HAConnection, QueryOptions};
+/// use std::time::Duration;
+///
+/// async fn example(conn: &HAConnection) -> litesql_ha::Result<()> {
+///     let report = QueryOptions::default().with_timeout(Duration::from_secs(300));
+///     let result = conn
+///         .query_with("SELECT region, SUM(total) FROM orders GROUP BY region", &[], &report)
+///         .await?;
+///     println!("{:?}", result.rows);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Deadline of the call, replacing [`HAClientOptions::timeout`]
+    ///
+    /// Sent to the server as the gRPC deadline. Statements inside a sticky
+    /// transaction share one stream, so their deadline is enforced by the
+    /// client alone; reads served by an embedded replica are not bound by it.
+    pub timeout: Option<Duration>,
+    /// Time by which the call must finish, cutting the timeout short
+    ///
+    /// Calls inside a [`with_deadline`](crate::deadline::with_deadline) scope
+    /// also finish by the scope's deadline.
+    pub deadline: Option<Instant>,
+    /// Aborts the call when cancelled
+    pub cancel: Option<CancelHandle>,
+    /// Consistency of the read, replacing the connection's
+    pub consistency: Option<Consistency>,
+}
+
+impl QueryOptions {
+    /// Set the deadline of the call.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time by which the call must finish.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set the handle that cancels the call.
+    pub fn with_cancel(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Set the consistency of the read.
+    pub fn with_consistency(mut self, consistency: Consistency) -> Self {
+        self.consistency = Some(consistency);
+        self
+    }
+
+    /// Fail with [`Error::Cancelled`] if the call was cancelled before it
+    /// started.
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.cancel {
+            Some(ref cancel) => cancel.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Run a call, aborting it if the cancel handle is cancelled.
+    pub(crate) async fn run<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.cancel {
+            Some(ref cancel) => cancel.run(call).await,
+            None => call.await,
+        }
+    }
+}
+
+/// Result of an INSERT/UPDATE/DELETE statement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecuteResult {
+    /// Number of rows affected
+    pub rows_affected: i64,
+    /// Rowid of the last row inserted on the server session, as SQLite's
+    /// `last_insert_rowid()` returns it after the statement
+    pub last_insert_rowid: i64,
+    /// Transaction sequence number on the leader after the statement; see
+    /// [`HAConnection::wait_for_replication`](crate::HAConnection::wait_for_replication)
+    pub txseq: i64,
+}
+
 /// Result of a query execution.
 #[derive(Debug, Clone)]
 pub struct ExecutionResult {
@@ -49,6 +370,15 @@ pub struct ExecutionResult {
     pub rows: Vec<Vec<Value>>,
     /// Number of rows affected (for INSERT/UPDATE/DELETE)
     pub rows_affected: i64,
+    /// Rowid of the last row inserted on the server session, as SQLite's
+    /// `last_insert_rowid()` returns it after the statement
+    pub last_insert_rowid: i64,
+    /// Transaction sequence number the result was read at, or 0 when unknown,
+    /// as for reads from an embedded replica or from a follower behind the
+    /// txseq the client has seen
+    pub txseq: i64,
+    /// Where the query was routed and why, when executed through a connection
+    pub routing: Option<RoutingDecision>,
 }
 
 impl ExecutionResult {
@@ -58,6 +388,9 @@ impl ExecutionResult {
             columns: vec![],
             rows: vec![],
             rows_affected: 0,
+            last_insert_rowid: 0,
+            txseq: 0,
+            routing: None,
         }
     }
 
@@ -70,121 +403,1385 @@ impl ExecutionResult {
     pub fn column_count(&self) -> usize {
         self.columns.len()
     }
+
+    /// Get a row by position.
+    pub fn row(&self, index: usize) -> Option<Row<'_>> {
+        self.rows
+            .get(index)
+            .map(|values| Row::new(&self.columns, values))
+    }
+
+    /// Iterate over the rows.
+    pub fn iter(&self) -> Rows<'_> {
+        Rows {
+            columns: &self.columns,
+            rows: self.rows.iter(),
+        }
+    }
+
+    /// Map every row to `T`.
+    pub fn rows_as<T: FromRow>(&self) -> Result<Vec<T>> {
+        self.iter().map(|row| T::from_row(&row)).collect()
+    }
+
+    /// Deserialize every row into `T` with serde.
+    #[cfg(feature = "serde")]
+    pub fn rows_de<T: serde::de::DeserializeOwned>(&self) -> Result<Vec<T>> {
+        self.iter().map(Row::deserialize).collect()
+    }
+
+    /// Take the rows, sharing the column names between them.
+    pub fn into_rows(self) -> Vec<OwnedRow> {
+        let columns: Arc<[String]> = self.columns.into();
+        self.rows
+            .into_iter()
+            .map(|values| OwnedRow::new(columns.clone(), values))
+            .collect()
+    }
+
+    /// Take the only row, failing with [`Error::RowCount`] unless there is
+    /// exactly one.
+    pub fn into_one(self) -> Result<OwnedRow> {
+        self.into_optional()?.ok_or(Error::RowCount(0))
+    }
+
+    /// Take the only row, or `None` if there are no rows, failing with
+    /// [`Error::RowCount`] if there are several.
+    pub fn into_optional(self) -> Result<Option<OwnedRow>> {
+        match self.rows.len() {
+            0 | 1 => Ok(self.into_rows().pop()),
+            n => Err(Error::RowCount(n)),
+        }
+    }
+
+    /// Get the first column of the only row as `T`, failing with
+    /// [`Error::RowCount`] unless there is exactly one row.
+    pub fn into_scalar<T: FromValue>(self) -> Result<T> {
+        self.into_one()?.get_index(0)
+    }
+}
+
+impl<'a> IntoIterator for &'a ExecutionResult {
+    type Item = Row<'a>;
+    type IntoIter = Rows<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the rows of an [`ExecutionResult`].
+#[derive(Debug, Clone)]
+pub struct Rows<'a> {
+    columns: &'a [String],
+    rows: std::slice::Iter<'a, Vec<Value>>,
 }
 
+impl<'a> Iterator for Rows<'a> {
+    type Item = Row<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let values = self.rows.next()?;
+        Some(Row::new(self.columns, values))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
+
+/// Rows of a query, yielded as the server's responses arrive.
+///
+/// Returned by [`HAClient::execute_query_stream`] and
+/// [`HAConnection::query_stream`](crate::HAConnection::query_stream). Only
+/// the rows of one response message are held in memory at a time.
+pub struct RowStream {
+    columns: Arc<[String]>,
+    rows: std::vec::IntoIter<proto::Row>,
+    responses: Option<Streaming<QueryResponse>>,
+    cancelled: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl RowStream {
+    fn new(response: QueryResponse, responses: Option<Streaming<QueryResponse>>) -> Result<Self> {
+        if !response.error.is_empty() {
+            return Err(Error::Query(response.error));
+        }
+        let result_set = response.result_set.unwrap_or_default();
+        Ok(Self {
+            columns: result_set.columns.into(),
+            rows: result_set.rows.into_iter(),
+            responses,
+            cancelled: None,
+        })
+    }
+
+    /// End the stream with [`Error::Cancelled`] once `cancel` is cancelled.
+    fn with_cancel(mut self, cancel: Option<CancelHandle>) -> Self {
+        self.cancelled = cancel.map(|cancel| -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async move { cancel.cancelled().await })
+        });
+        self
+    }
+
+    /// Get the column names.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn decode(&self, row: proto::Row) -> Result<OwnedRow> {
+        let values = row
+            .values
+            .iter()
+            .map(Value::from_any)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(OwnedRow::new(self.columns.clone(), values))
+    }
+}
+
+impl Stream for RowStream {
+    type Item = Result<OwnedRow>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Poll::Ready(Some(self.decode(row)));
+            }
+
+            if self.responses.is_none() {
+                return Poll::Ready(None);
+            }
+            if let Some(cancelled) = self.cancelled.as_mut() {
+                if cancelled.as_mut().poll(cx).is_ready() {
+                    self.responses = None;
+                    return Poll::Ready(Some(Err(Error::Cancelled)));
+                }
+            }
+            let Some(responses) = self.responses.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match ready!(Pin::new(responses).poll_next(cx)) {
+                Some(Ok(response)) if response.error.is_empty() => {
+                    if let Some(result_set) = response.result_set {
+                        self.rows = result_set.rows.into_iter();
+                    }
+                }
+                Some(Ok(response)) => {
+                    self.responses = None;
+                    return Poll::Ready(Some(Err(Error::Query(response.error))));
+                }
+                Some(Err(status)) => {
+                    self.responses = None;
+                    return Poll::Ready(Some(Err(status.into())));
+                }
+                None => {
+                    self.responses = None;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for RowStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RowStream")
+            .field("columns", &self.columns)
+            .field("buffered", &self.rows.len())
+            .finish()
+    }
+}
+
+/// A `Query` stream kept open for the statements of one transaction.
+///
+/// Statements are answered in order, one response message each.
+struct Session {
+    requests: mpsc::Sender<QueryRequest>,
+    responses: Streaming<QueryResponse>,
+}
+
+impl Session {
+    /// Exchange a request, giving up after `timeout`.
+    async fn exchange_within(
+        &mut self,
+        timeout: Duration,
+        request: QueryRequest,
+    ) -> Result<QueryResponse> {
+        runtime::timeout(timeout, self.exchange(request))
+            .await
+            .unwrap_or(Err(Error::Timeout))
+    }
+
+    async fn exchange(&mut self, request: QueryRequest) -> Result<QueryResponse> {
+        self.requests
+            .send(request)
+            .await
+            .map_err(|_| Error::ConnectionClosed)?;
+        self.responses
+            .message()
+            .await?
+            .ok_or_else(|| Error::Query("Session stream ended".to_string()))
+    }
+}
+
+/// Append the rows of a follow-up message to a split result.
+fn merge(response: &mut QueryResponse, next: QueryResponse) {
+    if let Some(rows) = next.result_set.map(|rs| rs.rows) {
+        response
+            .result_set
+            .get_or_insert_with(Default::default)
+            .rows
+            .extend(rows);
+    }
+    response.rows_affected += next.rows_affected;
+    if next.last_insert_rowid != 0 {
+        response.last_insert_rowid = next.last_insert_rowid;
+    }
+    response.txseq = response.txseq.max(next.txseq);
+}
+
+/// Parse a `litesql://` or `litesqls://` URL.
+fn parse_url(url: &str) -> Result<Url> {
+    let url = url
+        .replace("litesql://", "http://")
+        .replace("litesqls://", "https://");
+    Ok(Url::parse(&url)?)
+}
+
+/// Metadata key of the leader address in a follower's refusal of a write.
+pub(crate) const LEADER_METADATA_KEY: &str = "x-litesql-leader";
+
+/// Get the leader a follower redirected a call to, from the metadata of its
+/// refusal or its "not leader, leader is X" message, where the address ends
+/// at the first space or `,`, `;` or `)`.
+fn leader_address(status: &Status) -> Option<String> {
+    if status.code() != Code::Unavailable {
+        return None;
+    }
+    if let Some(leader) = status
+        .metadata()
+        .get(LEADER_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+    {
+        return is_address(leader).then(|| leader.to_string());
+    }
+    let (_, leader) = status.message().split_once("leader is ")?;
+    let leader = leader
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ')'))
+        .next()?;
+    is_address(leader).then(|| leader.to_string())
+}
+
+/// Check if `leader` is a `host:port` address, or a URL with one.
+fn is_address(leader: &str) -> bool {
+    if leader.contains("://") {
+        leader
+            .parse::<Uri>()
+            .is_ok_and(|uri| uri.authority().is_some())
+    } else {
+        leader
+            .parse::<Authority>()
+            .is_ok_and(|authority| authority.port().is_some())
+    }
+}
+
+/// Check if a call failed because a follower redirected it to the leader.
+fn redirected(e: &Error) -> bool {
+    matches!(e, Error::Status(status) if leader_address(status).is_some())
+}
+
+/// Settings of the endpoints of a client's servers.
+#[derive(Clone)]
+pub(crate) struct EndpointConfig {
+    enable_ssl: bool,
+    tls: Option<TlsOptions>,
+    #[cfg(not(target_arch = "wasm32"))]
+    keepalive: KeepaliveOptions,
+}
+
+impl EndpointConfig {
+    fn new(options: &HAClientOptions) -> Self {
+        Self {
+            enable_ssl: options.enable_ssl,
+            tls: options.tls.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            keepalive: options.keepalive.clone(),
+        }
+    }
+
+    /// Build the endpoint of the server at `url`.
+    fn endpoint(&self, url: &Url) -> Result<Endpoint> {
+        self.endpoint_for(url, None)
+    }
+
+    /// Build the endpoint of the server at `url`, verifying its certificate
+    /// against `domain` rather than the URL's host, e.g. for a server found
+    /// by its address.
+    pub(crate) fn endpoint_for(&self, url: &Url, domain: Option<&str>) -> Result<Endpoint> {
+        if matches!(url.scheme(), "ws" | "wss") {
+            return self.websocket_endpoint(url);
+        }
+        let host = url.host_str().unwrap_or("localhost");
+        let port = url.port().unwrap_or(8080);
+
+        let use_tls = self.enable_ssl || self.tls.is_some() || url.scheme() == "https";
+        let scheme = if use_tls { "https" } else { "http" };
+        let endpoint_url = format!("{}://{}:{}", scheme, host, port);
+        self.build_endpoint(endpoint_url, use_tls, domain)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn build_endpoint(
+        &self,
+        endpoint_url: String,
+        use_tls: bool,
+        domain: Option<&str>,
+    ) -> Result<Endpoint> {
+        // The timeout is set per call rather than on the endpoint, which would
+        // cap longer per-query timeouts.
+        let mut endpoint = Endpoint::from_shared(endpoint_url)?
+            .keep_alive_timeout(self.keepalive.timeout)
+            .keep_alive_while_idle(self.keepalive.while_idle);
+        if let Some(interval) = self.keepalive.interval {
+            endpoint = endpoint.http2_keep_alive_interval(interval);
+        }
+        #[cfg(feature = "tls")]
+        if use_tls {
+            let tls = self.tls.clone().unwrap_or_default();
+            let mut client_config = tls.client_config()?;
+            if let (Some(domain), None) = (domain, &tls.domain_override) {
+                client_config = client_config.domain_name(domain);
+            }
+            endpoint = endpoint.tls_config(client_config)?;
+        }
+        #[cfg(not(feature = "tls"))]
+        if use_tls {
+            let _ = domain;
+            return Err(Error::InvalidParameter(
+                "TLS requires the `tls` feature".to_string(),
+            ));
+        }
+        Ok(endpoint)
+    }
+
+    /// `fetch` verifies certificates and keeps connections alive itself.
+    #[cfg(target_arch = "wasm32")]
+    fn build_endpoint(
+        &self,
+        endpoint_url: String,
+        _use_tls: bool,
+        _domain: Option<&str>,
+    ) -> Result<Endpoint> {
+        if self.tls.is_some() {
+            return Err(Error::InvalidParameter(
+                "TlsOptions do not apply to gRPC-web calls".to_string(),
+            ));
+        }
+        Endpoint::from_shared(endpoint_url)
+    }
+
+    /// Build the endpoint of the server behind the WebSocket gateway at
+    /// `url`.
+    #[cfg(feature = "websocket")]
+    fn websocket_endpoint(&self, url: &Url) -> Result<Endpoint> {
+        if self.tls.is_some() {
+            return Err(Error::InvalidParameter(
+                "TlsOptions do not apply to WebSocket URLs".to_string(),
+            ));
+        }
+        websocket::endpoint(url, self.enable_ssl, &self.keepalive)
+    }
+
+    #[cfg(not(feature = "websocket"))]
+    fn websocket_endpoint(&self, _url: &Url) -> Result<Endpoint> {
+        Err(Error::InvalidParameter(
+            "WebSocket URLs require the `websocket` feature".to_string(),
+        ))
+    }
+}
+
+/// Open a channel to `endpoint`, dialled on its first call.
+fn connect_lazy(endpoint: &Endpoint) -> Channel {
+    #[cfg(feature = "websocket")]
+    if websocket::is_websocket(endpoint.uri()) {
+        return websocket::connect_lazy(endpoint);
+    }
+    endpoint.connect_lazy()
+}
+
+/// Open a channel to `endpoint`.
+async fn connect(endpoint: &Endpoint) -> Result<Channel> {
+    #[cfg(feature = "websocket")]
+    if websocket::is_websocket(endpoint.uri()) {
+        return websocket::connect(endpoint).await;
+    }
+    Ok(endpoint.connect().await?)
+}
+
+/// The gRPC channel, replaced when its connection breaks or, on the
+/// leader's connector, when a follower redirects a call to the leader.
+pub(crate) struct Connector {
+    /// Endpoint the client was created with, dialled again when the current
+    /// server cannot be reached
+    origin: Endpoint,
+    /// Channel over the discovered servers, used instead of `origin`
+    balanced: Option<Channel>,
+    endpoint: Mutex<Endpoint>,
+    middleware: Option<Middleware>,
+    client: Mutex<DatabaseServiceClient<Transport>>,
+    /// Set if redirects to the leader are followed
+    redirects: Option<EndpointConfig>,
+}
+
+impl Connector {
+    pub(crate) fn new(
+        endpoint: Endpoint,
+        middleware: Option<Middleware>,
+        channel: Channel,
+    ) -> Self {
+        let transport = middleware::transport(middleware.as_ref(), channel);
+        Self {
+            origin: endpoint.clone(),
+            balanced: None,
+            endpoint: Mutex::new(endpoint),
+            middleware,
+            client: Mutex::new(DatabaseServiceClient::new(transport)),
+            redirects: None,
+        }
+    }
+
+    /// Send calls over `channel`, spreading them over the discovered
+    /// servers, whenever they go to the server the client was created with.
+    fn balanced(mut self, channel: Channel) -> Self {
+        self.balanced = Some(channel);
+        self
+    }
+
+    /// Follow redirects to the leader, building its endpoint from `config`.
+    fn following_leader(mut self, config: EndpointConfig) -> Self {
+        self.redirects = Some(config);
+        self
+    }
+
+    fn client(&self) -> DatabaseServiceClient<Transport> {
+        self.client.lock().clone()
+    }
+
+    /// Get the URI of the server calls are sent to.
+    fn uri(&self) -> String {
+        self.endpoint.lock().uri().to_string()
+    }
+
+    /// Client for `Download` calls, accepting the compressions enabled by
+    /// the `gzip` and `zstd` features.
+    #[cfg(all(any(feature = "gzip", feature = "zstd"), feature = "embedded-replicas"))]
+    fn download_client(&self) -> DatabaseServiceClient<Transport> {
+        let mut client = self.client();
+        #[cfg(feature = "gzip")]
+        {
+            client = client.accept_compressed(CompressionEncoding::Gzip);
+        }
+        #[cfg(feature = "zstd")]
+        {
+            client = client.accept_compressed(CompressionEncoding::Zstd);
+        }
+        client
+    }
+
+    #[cfg(all(
+        feature = "embedded-replicas",
+        not(any(feature = "gzip", feature = "zstd"))
+    ))]
+    fn download_client(&self) -> DatabaseServiceClient<Transport> {
+        self.client()
+    }
+
+    /// Pass on the outcome of a call, first replacing the channel if the
+    /// server could not be reached so that the next call dials again: the
+    /// leader a follower redirected the call to, or else the server the
+    /// client was created with.
+    fn check<T>(&self, result: std::result::Result<T, Status>) -> Result<T> {
+        if let Err(ref status) = result {
+            // tonic reports a deadline that passed before the server answered
+            // as a cancellation.
+            if status.code() == Code::Cancelled && status.message() == "Timeout expired" {
+                return Err(Error::Timeout);
+            }
+            if status.code() == Code::Unavailable {
+                match self.redirects.as_ref().zip(leader_address(status)) {
+                    Some((config, leader)) => self.redirect(config, &leader),
+                    None => {
+                        warn!(
+                            "Reconnecting to {}: {}",
+                            self.origin.uri(),
+                            status.message()
+                        );
+                        let channel = match self.balanced {
+                            Some(ref channel) => channel.clone(),
+                            None => connect_lazy(&self.origin),
+                        };
+                        self.connect(self.origin.clone(), channel);
+                    }
+                }
+            }
+        }
+        Ok(result?)
+    }
+
+    /// Send later calls to `leader`, a `host:port` address or URL.
+    fn redirect(&self, config: &EndpointConfig, leader: &str) {
+        let current = self.uri();
+        let url = if leader.contains("://") {
+            leader.to_string()
+        } else {
+            let scheme = current
+                .split_once("://")
+                .map_or("http", |(scheme, _)| scheme);
+            format!("{}://{}", scheme, leader)
+        };
+        match parse_url(&url).and_then(|url| config.endpoint(&url)) {
+            Ok(endpoint) => {
+                info!("Leader moved from {} to {}", current, endpoint.uri());
+                let channel = connect_lazy(&endpoint);
+                self.connect(endpoint, channel);
+            }
+            Err(e) => warn!("Cannot follow the redirect to leader {}: {}", leader, e),
+        }
+    }
+
+    /// Send later calls over `channel` to the server of `endpoint`.
+    fn connect(&self, endpoint: Endpoint, channel: Channel) {
+        *self.client.lock() =
+            DatabaseServiceClient::new(middleware::transport(self.middleware.as_ref(), channel));
+        *self.endpoint.lock() = endpoint;
+    }
+}
+
+/// Most txseqs an [`HAClient`] remembers the time it first saw.
+const SEEN_TXSEQS: usize = 64;
+
 /// gRPC client for communicating with the SQLite HA server.
 pub struct HAClient {
     replication_id: Mutex<String>,
-    timeout: u64,
-    token: Option<String>,
-    client: DatabaseServiceClient<Channel>,
+    timeout: Duration,
+    credentials: Credentials,
+    metadata_provider: Option<Arc<dyn MetadataProvider>>,
+    connector: Arc<Connector>,
+    read_endpoints: Option<ReadEndpoints>,
+    peer: Peer,
     txseq: Mutex<i64>,
+    /// Txseqs seen, each with when it was first seen, oldest first
+    txseq_seen_at: Mutex<VecDeque<(i64, Instant)>>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    sticky_transactions: bool,
+    timestamp_storage: TimestampStorage,
+    session: tokio::sync::Mutex<Option<Session>>,
 }
 
 impl HAClient {
     /// Create a new HAClient.
     pub async fn new(options: HAClientOptions) -> Result<Self> {
-        let url = options
-            .url
-            .replace("litesql://", "http://")
-            .replace("litesqls://", "https://");
-        let parsed = Url::parse(&url)?;
-
+        let parsed = parse_url(&options.url)?;
         let replication_id = parsed.path().trim_start_matches('/').to_string();
         let host = parsed.host_str().unwrap_or("localhost");
         let port = parsed.port().unwrap_or(8080);
+        let config = EndpointConfig::new(&options);
+        let endpoint = config.endpoint(&parsed)?;
 
-        let scheme = if options.enable_ssl { "https" } else { "http" };
-        let endpoint_url = format!("{}://{}:{}", scheme, host, port);
-
-        let endpoint = Endpoint::from_shared(endpoint_url)?
-            .timeout(std::time::Duration::from_secs(options.timeout));
+        // Followers are dialled on their first read, so one that is down
+        // does not keep the client from starting.
+        let read_endpoints = if options.read_endpoints.is_empty() {
+            None
+        } else {
+            let endpoints = options
+                .read_endpoints
+                .iter()
+                .map(|url| {
+                    let parsed = parse_url(url)?;
+                    let zone = parsed
+                        .query_pairs()
+                        .find(|(key, _)| key == "zone")
+                        .map(|(_, zone)| zone.into_owned());
+                    let endpoint = config.endpoint(&parsed)?;
+                    let channel = connect_lazy(&endpoint);
+                    let connector = Connector::new(endpoint, options.middleware.clone(), channel);
+                    Ok(ReadEndpoint::new(url.clone(), zone, connector))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let balancer = options
+                .load_balancer
+                .clone()
+                .unwrap_or_else(|| Arc::new(RoundRobin::default()));
+            Some(ReadEndpoints::new(endpoints, balancer))
+        };
 
-        let channel = endpoint.connect().await?;
-        let client = DatabaseServiceClient::new(channel);
+        // A replaying client never calls the server, so don't require one.
+        let connector = match options.discovery {
+            _ if options.replay.is_some() => {
+                let channel = connect_lazy(&endpoint);
+                Connector::new(endpoint, options.middleware, channel)
+            }
+            Some(ref discovery) => {
+                let channel = discovery::channel(&parsed, config.clone(), discovery).await?;
+                Connector::new(endpoint, options.middleware, channel.clone()).balanced(channel)
+            }
+            None => {
+                let channel = connect(&endpoint).await?;
+                Connector::new(endpoint, options.middleware, channel)
+            }
+        };
+        let connector = Arc::new(connector.following_leader(config));
 
         Ok(Self {
             replication_id: Mutex::new(replication_id),
-            timeout: options.timeout,
-            token: options.token,
-            client,
+            timeout: Duration::from_secs(options.timeout),
+            credentials: Credentials::new(options.token, options.token_provider),
+            metadata_provider: options.metadata_provider,
+            connector,
+            read_endpoints,
+            peer: Peer {
+                name: host.to_string(),
+                port,
+            },
             txseq: Mutex::new(0),
+            txseq_seen_at: Mutex::new(VecDeque::new()),
+            recorder: options.recorder,
+            replay: options.replay,
+            sticky_transactions: options.sticky_transactions,
+            timestamp_storage: options.timestamp_storage,
+            session: tokio::sync::Mutex::new(None),
         })
     }
 
     /// Execute a SELECT query and return results.
     pub async fn execute_query(&self, sql: &str, parameters: &[Value]) -> Result<ExecutionResult> {
-        let response = self.send(sql, parameters, QueryType::ExecQuery).await?;
+        self.execute_query_with(sql, parameters, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a SELECT query with per-call options.
+    pub async fn execute_query_with(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        let response = self
+            .send(sql, parameters, QueryType::ExecQuery, options)
+            .await?;
         self.parse_response(response)
     }
 
     /// Execute an INSERT/UPDATE/DELETE statement.
-    pub async fn execute_update(&self, sql: &str, parameters: &[Value]) -> Result<i64> {
-        let response = self.send(sql, parameters, QueryType::ExecUpdate).await?;
+    pub async fn execute_update(&self, sql: &str, parameters: &[Value]) -> Result<ExecuteResult> {
+        self.execute_update_with(sql, parameters, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with per-call options.
+    pub async fn execute_update_with(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecuteResult> {
+        let response = self
+            .send(sql, parameters, QueryType::ExecUpdate, options)
+            .await?;
 
         if !response.error.is_empty() {
             return Err(Error::Query(response.error));
         }
 
-        Ok(response.rows_affected)
+        Ok(ExecuteResult {
+            rows_affected: response.rows_affected,
+            last_insert_rowid: response.last_insert_rowid,
+            txseq: response.txseq,
+        })
     }
 
     /// Execute any SQL statement.
     pub async fn execute(&self, sql: &str, parameters: &[Value]) -> Result<ExecutionResult> {
-        let response = self.send(sql, parameters, QueryType::Unspecified).await?;
+        self.execute_with(sql, parameters, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute any SQL statement with per-call options.
+    pub async fn execute_with(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        let response = self
+            .send(sql, parameters, QueryType::Unspecified, options)
+            .await?;
         self.parse_response(response)
     }
 
-    async fn send(
+    /// Execute several statements in one round trip.
+    ///
+    /// Every statement is written to a single `Query` stream before any
+    /// response is read. The outer error reports a failed call; each
+    /// statement has its own result. The server runs every statement even if
+    /// an earlier one fails, so run the batch in a transaction to make it
+    /// atomic. The whole batch must finish within the client timeout.
+    pub async fn execute_batch(
+        &self,
+        statements: &[(&str, &[Value])],
+    ) -> Result<Vec<Result<ExecutionResult>>> {
+        let requests: Vec<QueryRequest> = statements
+            .iter()
+            .map(|(sql, params)| self.request(sql, params, QueryType::Unspecified))
+            .collect();
+
+        Ok(self
+            .send_batch(&requests)
+            .await?
+            .into_iter()
+            .map(|response| self.parse_response(response))
+            .collect())
+    }
+
+    /// Execute one INSERT/UPDATE/DELETE statement once per parameter set, in
+    /// one round trip.
+    ///
+    /// The sets are sent over a single `Query` stream like
+    /// [`execute_batch`](Self::execute_batch), and the results are in the
+    /// order of the sets. The first failing set fails the call, but the server
+    /// still runs the sets after it; run them in a transaction to make the
+    /// call atomic.
+    pub async fn execute_many<I, P>(&self, sql: &str, param_sets: I) -> Result<Vec<ExecuteResult>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[Value]>,
+    {
+        let requests: Vec<QueryRequest> = param_sets
+            .into_iter()
+            .map(|params| self.request(sql, params.as_ref(), QueryType::ExecUpdate))
+            .collect();
+
+        self.send_batch(&requests)
+            .await?
+            .into_iter()
+            .map(|response| {
+                if !response.error.is_empty() {
+                    return Err(Error::Query(response.error));
+                }
+                Ok(ExecuteResult {
+                    rows_affected: response.rows_affected,
+                    last_insert_rowid: response.last_insert_rowid,
+                    txseq: response.txseq,
+                })
+            })
+            .collect()
+    }
+
+    async fn send_batch(&self, requests: &[QueryRequest]) -> Result<Vec<QueryResponse>> {
+        let span = telemetry::batch_span(
+            &self.peer,
+            &self.replication_id.lock(),
+            requests.iter().map(|request| request.sql.as_str()),
+        );
+        #[cfg(feature = "metrics")]
+        let sent: Vec<_> = requests
+            .iter()
+            .map(crate::metrics::Request::start)
+            .collect();
+        let responses = match self.replay {
+            Some(ref replay) => requests
+                .iter()
+                .map(|request| replay.respond(request))
+                .collect::<Result<Vec<_>>>(),
+            None => self.dispatch_batch(requests).instrument(span.clone()).await,
+        };
+        #[cfg(feature = "metrics")]
+        for (i, sent) in sent.into_iter().enumerate() {
+            sent.finish(responses.as_ref().ok().and_then(|r| r.get(i)));
+        }
+        match &responses {
+            Ok(responses) => responses
+                .iter()
+                .for_each(|response| telemetry::record_response(&span, Ok(response))),
+            Err(e) => telemetry::fail(&span, e),
+        }
+        let responses = responses?;
+        if let Some(ref recorder) = self.recorder {
+            for (request, response) in requests.iter().zip(&responses) {
+                recorder.record(request, &Ok(response.clone()));
+            }
+        }
+        for response in &responses {
+            self.observe(response);
+        }
+        Ok(responses)
+    }
+
+    /// Execute a SELECT query and stream its rows as they arrive.
+    ///
+    /// Waits for the first response so that errors in the statement are
+    /// returned here; later failures are yielded by the stream. Streamed
+    /// queries are not recorded by a [`Recorder`].
+    pub async fn execute_query_stream(&self, sql: &str, parameters: &[Value]) -> Result<RowStream> {
+        self.execute_query_stream_with(sql, parameters, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a SELECT query with per-call options and stream its rows.
+    ///
+    /// The timeout covers the whole stream, not just the first response.
+    pub async fn execute_query_stream_with(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        options: &QueryOptions,
+    ) -> Result<RowStream> {
+        options.check()?;
+        let request = self.request(sql, parameters, QueryType::ExecQuery);
+
+        if let Some(ref replay) = self.replay {
+            let response = replay.respond(&request)?;
+            self.observe(&response);
+            return RowStream::new(response, None);
+        }
+
+        let span = telemetry::query_span(&self.peer, &request.replication_id, sql);
+        #[cfg(feature = "metrics")]
+        let sent = crate::metrics::Request::start(&request);
+        if let Some(response) = self
+            .dispatch_in_session(&request, options)
+            .instrument(span.clone())
+            .await
+        {
+            #[cfg(feature = "metrics")]
+            sent.finish(response.as_ref().ok());
+            telemetry::record_response(&span, response.as_ref());
+            let response = response?;
+            self.observe(&response);
+            return RowStream::new(response, None);
+        }
+
+        let deadline = Instant::now() + self.time_left(options)?;
+        let opened = self
+            .on_leader(|| {
+                options.run(async {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    let mut responses = self.open(&self.connector, &request, timeout).await?;
+                    let response = self
+                        .connector
+                        .check(responses.message().await)?
+                        .ok_or_else(|| Error::Query("No response received".to_string()))?;
+                    Ok((response, responses))
+                })
+            })
+            .instrument(span.clone())
+            .await;
+        #[cfg(feature = "metrics")]
+        sent.finish(opened.as_ref().ok().map(|(response, _)| response));
+        telemetry::record_response(&span, opened.as_ref().map(|(response, _)| response));
+        let (response, responses) = opened?;
+        self.observe(&response);
+        Ok(RowStream::new(response, Some(responses))?.with_cancel(options.cancel.clone()))
+    }
+
+    /// Execute the statement that begins a transaction.
+    ///
+    /// With sticky transactions the statement opens a `Query` stream that
+    /// carries every later statement until [`end_session`](Self::end_session),
+    /// so the whole transaction runs on one server session.
+    pub async fn begin_session(&self, sql: &str) -> Result<i64> {
+        if !self.sticky_transactions || self.replay.is_some() {
+            return self.execute_update(sql, &[]).await.map(|r| r.rows_affected);
+        }
+
+        let mut session = self.session.lock().await;
+        if session.is_some() {
+            return Err(Error::Query("A session is already open".to_string()));
+        }
+
+        let request = self.request(sql, &[], QueryType::ExecUpdate);
+        let timeout = self.time_left(&QueryOptions::default())?;
+        let start = runtime::timeout(
+            timeout,
+            self.on_leader(|| self.start_session(request.clone())),
+        );
+        let (started, result) = match start.await.unwrap_or(Err(Error::Timeout)) {
+            Ok((started, response)) => (Some(started), Ok(response)),
+            Err(e) => (None, Err(e)),
+        };
+        if let Some(ref recorder) = self.recorder {
+            recorder.record(&request, &result);
+        }
+        let response = result?;
+        self.observe(&response);
+        if !response.error.is_empty() {
+            return Err(Error::Query(response.error));
+        }
+
+        *session = started;
+        Ok(response.rows_affected)
+    }
+
+    /// Close the session opened by [`begin_session`](Self::begin_session);
+    /// later statements open a stream each again.
+    pub async fn end_session(&self) {
+        self.session.lock().await.take();
+    }
+
+    /// Check if statements are sent over a session stream.
+    pub async fn in_session(&self) -> bool {
+        self.session.lock().await.is_some()
+    }
+
+    async fn start_session(&self, request: QueryRequest) -> Result<(Session, QueryResponse)> {
+        // The first statement is queued before the stream opens: a server may
+        // not send response headers until it has a statement to answer.
+        let (requests, responses) = self.open_queued(&self.connector, &[request], None).await?;
+        let mut session = Session {
+            requests,
+            responses,
+        };
+        let response = self
+            .connector
+            .check(session.responses.message().await)?
+            .ok_or_else(|| Error::Query("No response received".to_string()))?;
+        Ok((session, response))
+    }
+
+    /// Open a pipeline for sending queries without waiting for the responses
+    /// of earlier ones.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn pipeline(&self) -> Pipeline<'_> {
+        Pipeline::new(self)
+    }
+
+    pub(crate) fn request(
         &self,
         sql: &str,
         parameters: &[Value],
         query_type: QueryType,
-    ) -> Result<QueryResponse> {
+    ) -> QueryRequest {
         let params: Vec<NamedValue> = parameters
             .iter()
             .enumerate()
             .map(|(i, v)| NamedValue {
                 name: String::new(),
                 ordinal: (i + 1) as i64,
-                value: Some(v.to_any()),
+                value: Some(self.timestamp_storage.apply(v).to_any()),
             })
             .collect();
 
-        let request = QueryRequest {
+        QueryRequest {
             replication_id: self.replication_id.lock().clone(),
             sql: sql.to_string(),
             r#type: query_type.into(),
             params,
+        }
+    }
+
+    async fn send(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        query_type: QueryType,
+        options: &QueryOptions,
+    ) -> Result<QueryResponse> {
+        options.check()?;
+        let request = self.request(sql, parameters, query_type);
+        let span = telemetry::query_span(&self.peer, &request.replication_id, sql);
+        #[cfg(feature = "metrics")]
+        let sent = crate::metrics::Request::start(&request);
+        let response = self
+            .exchange(request, options)
+            .instrument(span.clone())
+            .await;
+        #[cfg(feature = "metrics")]
+        sent.finish(response.as_ref().ok());
+        telemetry::record_response(&span, response.as_ref());
+        let response = response?;
+        self.observe(&response);
+        Ok(response)
+    }
+
+    /// Answer a request from the replay, or dispatch it and record the
+    /// response.
+    async fn exchange(
+        &self,
+        request: QueryRequest,
+        options: &QueryOptions,
+    ) -> Result<QueryResponse> {
+        match (&self.replay, &self.recorder) {
+            (Some(replay), _) => replay.respond(&request),
+            (None, Some(recorder)) => {
+                let result = self.dispatch(request.clone(), options).await;
+                recorder.record(&request, &result);
+                result
+            }
+            (None, None) => self.dispatch(request, options).await,
+        }
+    }
+
+    /// The time a call may take: its own timeout or the client's, cut short
+    /// by its deadline and that of the enclosing
+    /// [`with_deadline`](deadline::with_deadline) scope.
+    pub(crate) fn time_left(&self, options: &QueryOptions) -> Result<Duration> {
+        let timeout = options.timeout.unwrap_or(self.timeout);
+        let deadline = match (options.deadline, deadline::current()) {
+            (Some(own), Some(scope)) => own.min(scope),
+            (Some(deadline), None) | (None, Some(deadline)) => deadline,
+            (None, None) => return Ok(timeout),
         };
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(timeout.min(left)),
+            _ => Err(Error::Timeout),
+        }
+    }
 
-        let (tx, rx) = mpsc::channel(1);
-        tx.send(request).await.map_err(|_| Error::ConnectionClosed)?;
-        drop(tx);
+    pub(crate) fn observe(&self, response: &QueryResponse) {
+        if response.txseq > 0 {
+            *self.txseq.lock() = response.txseq;
+            self.record_seen(response.txseq);
+        }
+    }
 
-        let stream = ReceiverStream::new(rx);
-        let mut request = Request::new(stream);
+    /// Remember when `txseq` was first seen.
+    fn record_seen(&self, txseq: i64) {
+        let mut seen = self.txseq_seen_at.lock();
+        while seen.back().is_some_and(|&(last, _)| last > txseq) {
+            seen.pop_back();
+        }
+        if seen.back().is_some_and(|&(last, _)| last == txseq) {
+            return;
+        }
+        if seen.len() == SEEN_TXSEQS {
+            // Merging the two oldest keeps the earlier time, so a replica
+            // between them is taken for staler, never fresher, than it is
+            if let Some((_, first_at)) = seen.pop_front() {
+                if let Some(next) = seen.front_mut() {
+                    next.1 = first_at;
+                }
+            }
+        }
+        seen.push_back((txseq, Instant::now()));
+    }
+
+    /// Get when a txseq past `txseq` was first seen: since then, a replica
+    /// at `txseq` has been missing transactions this client knows of.
+    pub(crate) fn seen_past(&self, txseq: i64) -> Option<Instant> {
+        self.txseq_seen_at
+            .lock()
+            .iter()
+            .find(|&&(seen, _)| seen > txseq)
+            .map(|&(_, at)| at)
+    }
 
-        if let Some(ref token) = self.token {
-            request
-                .metadata_mut()
-                .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+    /// Send a request over the open session, or on a stream of its own.
+    async fn dispatch(
+        &self,
+        request: QueryRequest,
+        options: &QueryOptions,
+    ) -> Result<QueryResponse> {
+        if let Some(result) = self.dispatch_in_session(&request, options).await {
+            return result;
         }
+        let timeout = self.time_left(options)?;
+        let started = Instant::now();
+        if let Some(result) = self.dispatch_read(&request, options, timeout).await {
+            return result;
+        }
+        let timeout = timeout.saturating_sub(started.elapsed());
+        if timeout.is_zero() {
+            return Err(Error::Timeout);
+        }
+        let deadline = Instant::now() + timeout;
+        self.on_leader(|| {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            options.run(self.call(&self.connector, &request, timeout))
+        })
+        .await
+    }
+
+    /// Run `attempt` against the leader, once more if a follower redirected
+    /// it: a follower refusing a statement has not run it.
+    async fn on_leader<T, F, Fut>(&self, attempt: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match attempt().await {
+            Err(e) if redirected(&e) => attempt().await,
+            result => result,
+        }
+    }
 
-        let mut response_stream: Streaming<QueryResponse> =
-            self.client.clone().query(request).await?.into_inner();
+    /// Send a SELECT to a follower picked by the load balancer, if the
+    /// client has read endpoints and the read's consistency lets a follower
+    /// serve it; `None` leaves the read to the leader.
+    ///
+    /// A follower missing transactions the client has seen answers a
+    /// `BoundedStaleness` read only if it has been missing them for less
+    /// than the bound, as an embedded replica would.
+    async fn dispatch_read(
+        &self,
+        request: &QueryRequest,
+        options: &QueryOptions,
+        timeout: Duration,
+    ) -> Option<Result<QueryResponse>> {
+        let endpoints = self.read_endpoints.as_ref()?;
+        let consistency = options.consistency.unwrap_or_default();
+        if request.r#type != QueryType::ExecQuery as i32 || consistency == Consistency::Strong {
+            return None;
+        }
+        let endpoint = endpoints.pick()?;
 
-        if let Some(response) = response_stream.message().await? {
-            if response.txseq > 0 {
-                *self.txseq.lock() = response.txseq;
+        let in_flight = endpoint.start();
+        let result = options
+            .run(self.call(&endpoint.connector, request, timeout))
+            .await;
+        match result {
+            Ok(mut response) => {
+                in_flight.succeeded();
+                let seen = self.txseq();
+                if response.txseq >= seen {
+                    return Some(Ok(response));
+                }
+                let within_bound = match consistency {
+                    Consistency::Eventual => true,
+                    Consistency::BoundedStaleness(bound) => self
+                        .seen_past(response.txseq)
+                        .is_some_and(|at| at.elapsed() < bound),
+                    Consistency::Strong => false,
+                };
+                if !within_bound {
+                    debug!(
+                        endpoint = endpoint.url(),
+                        txseq = response.txseq,
+                        seen,
+                        "follower behind, reading from the leader"
+                    );
+                    return None;
+                }
+                // Keep a lagging follower from rewinding the txseq seen
+                response.txseq = 0;
+                Some(Ok(response))
             }
-            Ok(response)
-        } else {
-            Err(Error::Query("No response received".to_string()))
+            Err(e) if e.kind() == ErrorKind::Unavailable => {
+                warn!(
+                    "Follower {} unavailable, reading from the leader: {}",
+                    endpoint.url(),
+                    e
+                );
+                endpoint.set_unavailable();
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Send a request over the open session, if there is one.
+    ///
+    /// A session whose exchange fails is closed: either the server has ended
+    /// the transaction it carried, or a late response would answer the next
+    /// request.
+    async fn dispatch_in_session(
+        &self,
+        request: &QueryRequest,
+        options: &QueryOptions,
+    ) -> Option<Result<QueryResponse>> {
+        let mut session = self.session.lock().await;
+        let open = session.as_mut()?;
+        let timeout = match self.time_left(options) {
+            Ok(timeout) => timeout,
+            Err(e) => return Some(Err(e)),
+        };
+        let exchange = open.exchange_within(timeout, request.clone());
+        let result = options.run(exchange).await;
+        if result.is_err() {
+            *session = None;
+        }
+        Some(result)
+    }
+
+    /// Send a request and collect its response, merging the rows of a result
+    /// split over several messages.
+    async fn call(
+        &self,
+        connector: &Arc<Connector>,
+        request: &QueryRequest,
+        timeout: Duration,
+    ) -> Result<QueryResponse> {
+        let mut responses = self.open(connector, request, timeout).await?;
+        let mut response = match connector.check(responses.message().await)? {
+            Some(response) => response,
+            None => return Err(Error::Query("No response received".to_string())),
+        };
+
+        while let Some(next) = connector.check(responses.message().await)? {
+            if !next.error.is_empty() {
+                response.error = next.error;
+                break;
+            }
+            merge(&mut response, next);
+        }
+        Ok(response)
+    }
+
+    /// Send requests over the open session, or all at once on a stream of
+    /// their own, and collect one response per request.
+    async fn dispatch_batch(&self, requests: &[QueryRequest]) -> Result<Vec<QueryResponse>> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let timeout = self.time_left(&QueryOptions::default())?;
+
+        let mut session = self.session.lock().await;
+        if let Some(open) = session.as_mut() {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                match open.exchange_within(timeout, request.clone()).await {
+                    Ok(response) => responses.push(response),
+                    Err(e) => {
+                        *session = None;
+                        return Err(e);
+                    }
+                }
+            }
+            return Ok(responses);
+        }
+        drop(session);
+
+        let deadline = Instant::now() + timeout;
+        self.on_leader(|| {
+            self.collect_batch(requests, deadline.saturating_duration_since(Instant::now()))
+        })
+        .await
+    }
+
+    /// Send requests all at once on a stream of their own and collect one
+    /// response per request.
+    async fn collect_batch(
+        &self,
+        requests: &[QueryRequest],
+        timeout: Duration,
+    ) -> Result<Vec<QueryResponse>> {
+        let (_, mut stream) = self
+            .open_queued(&self.connector, requests, Some(timeout))
+            .await?;
+        let mut responses: Vec<QueryResponse> = Vec::with_capacity(requests.len());
+        while let Some(next) = self.connector.check(stream.message().await)? {
+            // Only the first message of a split result carries its columns.
+            let continues = next.error.is_empty()
+                && next
+                    .result_set
+                    .as_ref()
+                    .is_some_and(|rs| rs.columns.is_empty());
+            match responses.last_mut() {
+                Some(last) if continues => merge(last, next),
+                _ => responses.push(next),
+            }
+        }
+
+        if responses.len() != requests.len() {
+            return Err(Error::Query(format!(
+                "expected {} responses, got {}",
+                requests.len(),
+                responses.len()
+            )));
+        }
+        Ok(responses)
+    }
+
+    async fn open(
+        &self,
+        connector: &Arc<Connector>,
+        request: &QueryRequest,
+        timeout: Duration,
+    ) -> Result<Streaming<QueryResponse>> {
+        let (_, stream) = self
+            .open_queued(connector, std::slice::from_ref(request), Some(timeout))
+            .await?;
+        Ok(stream)
+    }
+
+    /// Open a `Query` stream with `requests` queued on it, retrying once with
+    /// a refreshed token if the server rejects the current one.
+    ///
+    /// Later requests are sent on the returned sender; dropping it ends the
+    /// stream once the queued requests are answered. Streams that stay open
+    /// for later requests should not have a `timeout`.
+    async fn open_queued(
+        &self,
+        connector: &Arc<Connector>,
+        requests: &[QueryRequest],
+        timeout: Option<Duration>,
+    ) -> Result<(mpsc::Sender<QueryRequest>, Streaming<QueryResponse>)> {
+        let mut refreshed = false;
+        loop {
+            let (tx, rx) = mpsc::channel(requests.len().max(1));
+            for request in requests {
+                tx.try_send(request.clone())
+                    .map_err(|_| Error::ConnectionClosed)?;
+            }
+            match self.open_stream_on(connector, rx, refreshed, timeout).await {
+                Err(e) if self.credentials.should_refresh(&e, refreshed) => refreshed = true,
+                result => return result.map(|stream| (tx, stream)),
+            }
+        }
+    }
+
+    /// Open a `Query` stream fed by `requests`, with `timeout` as its gRPC
+    /// deadline; the returned future does not borrow the client, so it can be
+    /// driven by a background task.
+    pub(crate) fn open_stream(
+        &self,
+        requests: mpsc::Receiver<QueryRequest>,
+        refresh: bool,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<Streaming<QueryResponse>>> + Send + 'static {
+        self.open_stream_on(&self.connector, requests, refresh, timeout)
+    }
+
+    /// Open a `Query` stream to the server of `connector`.
+    fn open_stream_on(
+        &self,
+        connector: &Arc<Connector>,
+        requests: mpsc::Receiver<QueryRequest>,
+        refresh: bool,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<Streaming<QueryResponse>>> + Send + 'static {
+        let credentials = self.credentials.clone();
+        let connector = connector.clone();
+        let metadata = self.call_metadata();
+        #[cfg(feature = "opentelemetry")]
+        let context = telemetry::context();
+        async move {
+            let mut request = Request::new(ReceiverStream::new(requests));
+            *request.metadata_mut() = metadata;
+            if let Some(timeout) = timeout {
+                request.set_timeout(timeout);
+            }
+            #[cfg(feature = "opentelemetry")]
+            telemetry::inject(&context, request.metadata_mut());
+            credentials.authorize(&mut request, refresh).await?;
+            let result = connector.client().query(request).await;
+            Ok(connector.check(result)?.into_inner())
         }
     }
 
-    fn parse_response(&self, response: QueryResponse) -> Result<ExecutionResult> {
+    /// Get the custom metadata of the next call.
+    fn call_metadata(&self) -> MetadataMap {
+        metadata::collect(self.metadata_provider.as_deref())
+    }
+
+    pub(crate) fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    pub(crate) fn parse_response(&self, response: QueryResponse) -> Result<ExecutionResult> {
         if !response.error.is_empty() {
             return Err(Error::Query(response.error));
         }
@@ -196,6 +1793,9 @@ impl HAClient {
                     columns: vec![],
                     rows: vec![],
                     rows_affected: response.rows_affected,
+                    last_insert_rowid: response.last_insert_rowid,
+                    txseq: response.txseq,
+                    routing: None,
                 })
             }
         };
@@ -215,15 +1815,44 @@ impl HAClient {
             columns,
             rows,
             rows_affected: response.rows_affected,
+            last_insert_rowid: response.last_insert_rowid,
+            txseq: response.txseq,
+            routing: None,
         })
     }
 
     /// Download a replica database file.
+    ///
+    /// The snapshot is fetched in chunks, several at a time, into the
+    /// `.partial` directory of `directory`, and moved into place once
+    /// complete. A download that fails resumes from the chunks already
+    /// fetched the next time it is started, while the server still holds
+    /// its snapshot.
+    ///
+    /// Fails without the `embedded-replicas` feature.
     pub async fn download_replica(
         &self,
         directory: &Path,
         replication_id: &str,
         override_existing: bool,
+    ) -> Result<()> {
+        self.download_replica_with(
+            directory,
+            replication_id,
+            override_existing,
+            &DownloadOptions::default(),
+        )
+        .await
+    }
+
+    /// Download a replica database file with `options` setting the chunk
+    /// size, parallelism, rate limit and progress callback.
+    pub async fn download_replica_with(
+        &self,
+        directory: &Path,
+        replication_id: &str,
+        override_existing: bool,
+        options: &DownloadOptions,
     ) -> Result<()> {
         let file_path = directory.join(replication_id);
 
@@ -231,29 +1860,68 @@ impl HAClient {
             return Ok(());
         }
 
-        fs::create_dir_all(directory).await?;
+        let span = telemetry::download_span(&self.peer, replication_id);
+        let result = download::download(self, directory, replication_id, options)
+            .instrument(span.clone())
+            .await;
+        if let Err(ref e) = result {
+            telemetry::fail(&span, e);
+        }
+        result
+    }
 
-        let request = DownloadRequest {
-            replication_id: replication_id.to_string(),
-        };
+    /// Start a `Download` call.
+    #[cfg(feature = "embedded-replicas")]
+    pub(crate) async fn open_download(
+        &self,
+        download: DownloadRequest,
+    ) -> Result<Streaming<DownloadResponse>> {
+        let mut refreshed = false;
+        loop {
+            let mut request = Request::new(download.clone());
+            *request.metadata_mut() = self.call_metadata();
+            #[cfg(feature = "opentelemetry")]
+            telemetry::inject(&telemetry::context(), request.metadata_mut());
+            self.credentials.authorize(&mut request, refreshed).await?;
 
-        let mut request = Request::new(request);
-        if let Some(ref token) = self.token {
-            request
-                .metadata_mut()
-                .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            // The deadline covers the server starting the download; the
+            // file itself may take longer to arrive.
+            let timeout = self.time_left(&QueryOptions::default())?;
+            let result =
+                runtime::timeout(timeout, self.connector.download_client().download(request))
+                    .await
+                    .map_err(|_| Error::Timeout)?;
+            match self.connector.check(result) {
+                Err(e) if self.credentials.should_refresh(&e, refreshed) => refreshed = true,
+                result => return Ok(result?.into_inner()),
+            }
         }
+    }
 
-        let mut stream = self.client.clone().download(request).await?.into_inner();
-        let mut file = fs::File::create(&file_path).await?;
+    /// Start a `Frames` call.
+    #[cfg(feature = "embedded-replicas")]
+    pub(crate) async fn open_frames(
+        &self,
+        frames: FramesRequest,
+    ) -> Result<Streaming<FramesResponse>> {
+        let mut refreshed = false;
+        loop {
+            let mut request = Request::new(frames.clone());
+            *request.metadata_mut() = self.call_metadata();
+            #[cfg(feature = "opentelemetry")]
+            telemetry::inject(&telemetry::context(), request.metadata_mut());
+            self.credentials.authorize(&mut request, refreshed).await?;
 
-        use tokio::io::AsyncWriteExt;
-        while let Some(response) = stream.message().await? {
-            file.write_all(&response.data).await?;
+            let timeout = self.time_left(&QueryOptions::default())?;
+            let result =
+                runtime::timeout(timeout, self.connector.download_client().frames(request))
+                    .await
+                    .map_err(|_| Error::Timeout)?;
+            match self.connector.check(result) {
+                Err(e) if self.credentials.should_refresh(&e, refreshed) => refreshed = true,
+                result => return Ok(result?.into_inner()),
+            }
         }
-
-        file.flush().await?;
-        Ok(())
     }
 
     /// Download all replica database files.
@@ -261,26 +1929,142 @@ impl HAClient {
         &self,
         directory: &Path,
         override_existing: bool,
+    ) -> Result<()> {
+        self.download_all_replicas_with(directory, override_existing, &DownloadOptions::default())
+            .await
+    }
+
+    /// Download all replica database files with `options`, one after the
+    /// other.
+    pub async fn download_all_replicas_with(
+        &self,
+        directory: &Path,
+        override_existing: bool,
+        options: &DownloadOptions,
     ) -> Result<()> {
         let ids = self.get_replication_ids().await?;
         for id in ids {
-            self.download_replica(directory, &id, override_existing)
+            self.download_replica_with(directory, &id, override_existing, options)
                 .await?;
         }
         Ok(())
     }
 
     /// Get all available replication IDs.
+    ///
+    /// Retried once on a new channel if the server could not be reached.
     pub async fn get_replication_ids(&self) -> Result<Vec<String>> {
-        let mut request = Request::new(());
-        if let Some(ref token) = self.token {
-            request
-                .metadata_mut()
-                .insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        match self.fetch_replication_ids().await {
+            Err(e) if e.kind() == ErrorKind::Unavailable => self.fetch_replication_ids().await,
+            result => result,
         }
+    }
+
+    async fn fetch_replication_ids(&self) -> Result<Vec<String>> {
+        self.replication_ids_on(&self.connector).await
+    }
+
+    /// Ask the server of `connector` for its replication IDs.
+    async fn replication_ids_on(&self, connector: &Arc<Connector>) -> Result<Vec<String>> {
+        let mut refreshed = false;
+        loop {
+            let mut request = Request::new(());
+            *request.metadata_mut() = self.call_metadata();
+            request.set_timeout(self.time_left(&QueryOptions::default())?);
+            #[cfg(feature = "opentelemetry")]
+            telemetry::inject(&telemetry::context(), request.metadata_mut());
+            self.credentials.authorize(&mut request, refreshed).await?;
+
+            let result = connector.client().replication_i_ds(request).await;
+            match connector.check(result) {
+                Err(e) if self.credentials.should_refresh(&e, refreshed) => refreshed = true,
+                result => return Ok(result?.into_inner().replication_id),
+            }
+        }
+    }
 
-        let response = self.client.clone().replication_i_ds(request).await?;
-        Ok(response.into_inner().replication_id)
+    /// Probe the leader and the followers in
+    /// [`HAClientOptions::read_endpoints`], one after the other.
+    pub async fn cluster_info(&self) -> ClusterInfo {
+        let mut leader = self.probe(&self.connector, NodeRole::Leader).await;
+        leader.url = self.leader();
+        let mut nodes = vec![leader];
+        if let Some(ref endpoints) = self.read_endpoints {
+            for endpoint in endpoints.all() {
+                let mut follower = self.probe(&endpoint.connector, NodeRole::Follower).await;
+                follower.url = endpoint.url().to_string();
+                follower.zone = endpoint.zone().map(str::to_string);
+                for database in &mut follower.databases {
+                    let leader_txseq = nodes[0]
+                        .databases
+                        .iter()
+                        .find(|d| d.replication_id == database.replication_id)
+                        .and_then(|d| d.txseq);
+                    database.behind_by = leader_txseq
+                        .zip(database.txseq)
+                        .map(|(leader, txseq)| (leader - txseq).max(0));
+                }
+                nodes.push(follower);
+            }
+        }
+        ClusterInfo { nodes }
+    }
+
+    /// List the databases of the server of `connector` and the last
+    /// transaction applied to each.
+    async fn probe(&self, connector: &Arc<Connector>, role: NodeRole) -> NodeInfo {
+        let mut node = NodeInfo {
+            url: String::new(),
+            zone: None,
+            role,
+            reachable: false,
+            latency: None,
+            databases: Vec::new(),
+            error: None,
+        };
+        let started = Instant::now();
+        let ids = match self.replication_ids_on(connector).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                node.reachable = !matches!(e.kind(), ErrorKind::Unavailable | ErrorKind::Timeout);
+                node.error = Some(e.to_string());
+                return node;
+            }
+        };
+        node.reachable = true;
+        node.latency = Some(started.elapsed());
+
+        for replication_id in ids {
+            let mut request = self.request("SELECT 1", &[], QueryType::ExecQuery);
+            request.replication_id = replication_id.clone();
+            let result = match self.time_left(&QueryOptions::default()) {
+                Ok(timeout) => self.call(connector, &request, timeout).await,
+                Err(e) => Err(e),
+            };
+            let txseq = match result {
+                Ok(response) if response.error.is_empty() => Some(response.txseq),
+                Ok(response) => {
+                    node.error.get_or_insert(response.error);
+                    None
+                }
+                Err(e) => {
+                    node.error.get_or_insert_with(|| e.to_string());
+                    None
+                }
+            };
+            node.databases.push(DatabaseStatus {
+                replication_id,
+                txseq,
+                behind_by: None,
+            });
+        }
+        node
+    }
+
+    /// Get the URI of the server statements are sent to: the leader the
+    /// client was last redirected to, or the server it was created with.
+    pub fn leader(&self) -> String {
+        self.connector.uri()
     }
 
     /// Get the current replication ID.
@@ -297,4 +2081,29 @@ impl HAClient {
     pub fn txseq(&self) -> i64 {
         *self.txseq.lock()
     }
+
+    /// Ask the leader for the current transaction sequence number of a
+    /// database.
+    ///
+    /// Unlike other calls, this does not update [`txseq`](Self::txseq), since
+    /// the database may not be the current one. It is never answered by a
+    /// follower, and never sent over the session of an open transaction.
+    pub async fn leader_txseq(&self, replication_id: &str) -> Result<i64> {
+        let mut request = self.request("SELECT 1", &[], QueryType::ExecQuery);
+        request.replication_id = replication_id.to_string();
+        let options = QueryOptions::default().with_consistency(Consistency::Strong);
+        let timeout = self.time_left(&options)?;
+        let response = self
+            .on_leader(|| options.run(self.call(&self.connector, &request, timeout)))
+            .await?;
+        if !response.error.is_empty() {
+            return Err(Error::Query(response.error));
+        }
+        Ok(response.txseq)
+    }
+
+    /// Get how `Value::Timestamp` parameters are stored.
+    pub fn timestamp_storage(&self) -> TimestampStorage {
+        self.timestamp_storage
+    }
 }
@@ -0,0 +1,109 @@
+//! Cluster topology.
+//!
+//! [`HAClient::cluster_info`](crate::HAClient::cluster_info) probes every
+//! node the client knows of: the leader its statements are sent to and the
+//! followers in
+//! [`HAClientOptions::read_endpoints`](crate::HAClientOptions::read_endpoints).
+//! Each is reported as a [`NodeInfo`]: its role, whether it answered and how
+//! fast, and the last transaction applied to each database it serves, with
+//! how far a follower is behind the leader.
+//!
+//! The server protocol has no topology or version call, so nodes the client
+//! was not configured with are not listed and server versions are not
+//! reported.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::HAClient;
+//!
+//! # async fn example(client: &HAClient) {
+//! let cluster = client.cluster_info().await;
+//! for node in &cluster.nodes {
+//!     println!("{} {} reachable={}", node.role, node.url, node.reachable);
+//!     for database in &node.databases {
+//!         println!("  {} behind by {:?}", database.replication_id, database.behind_by);
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+/// The nodes of a cluster, as seen by a client.
+#[derive(Debug, Clone, Default)]
+pub struct ClusterInfo {
+    /// The leader, then the followers
+    pub nodes: Vec<NodeInfo>,
+}
+
+/// Role of a node in the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    /// The node statements are sent to
+    Leader,
+    /// A node serving reads
+    Follower,
+}
+
+/// Status of one node.
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    /// Address of the node
+    pub url: String,
+    /// Zone the node is labelled with in its URL, if any
+    pub zone: Option<String>,
+    /// Role of the node
+    pub role: NodeRole,
+    /// Whether the node answered
+    pub reachable: bool,
+    /// Time the node took to list its databases, if it did
+    pub latency: Option<Duration>,
+    /// Databases served by the node
+    pub databases: Vec<DatabaseStatus>,
+    /// First error met probing the node, if any
+    pub error: Option<String>,
+}
+
+/// Replication status of a database on one node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseStatus {
+    /// Replication ID of the database
+    pub replication_id: String,
+    /// Last transaction applied to the database on the node, if it answered
+    pub txseq: Option<i64>,
+    /// Transactions the node is missing compared to the leader, on
+    /// followers whose database and leader both answered
+    pub behind_by: Option<i64>,
+}
+
+impl ClusterInfo {
+    /// Get the leader.
+    pub fn leader(&self) -> Option<&NodeInfo> {
+        self.nodes.iter().find(|node| node.role == NodeRole::Leader)
+    }
+
+    /// Get the followers.
+    pub fn followers(&self) -> impl Iterator<Item = &NodeInfo> {
+        self.nodes
+            .iter()
+            .filter(|node| node.role == NodeRole::Follower)
+    }
+
+    /// Check if every node answered without error.
+    pub fn is_healthy(&self) -> bool {
+        self.nodes
+            .iter()
+            .all(|node| node.reachable && node.error.is_none())
+    }
+}
+
+impl fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeRole::Leader => f.write_str("leader"),
+            NodeRole::Follower => f.write_str("follower"),
+        }
+    }
+}
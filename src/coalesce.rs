@@ -0,0 +1,131 @@
+//! Coalescing of identical reads in flight.
+//!
+//! A [`QueryCoalescer`] shared by the connections of a data source lets
+//! concurrent reads of the same `SELECT` with the same parameters share one
+//! call to the HA server. The first read sends the query; reads that arrive
+//! while it is in flight wait for its result instead of sending their own,
+//! and are routed as [`RouteReason::Coalesced`](crate::RouteReason::Coalesced).
+//! Reads are matched by catalog, a whitespace-normalized fingerprint of the
+//! SQL, and the parameters, like the [query cache](crate::cache).
+//!
+//! A read only joins a query sent after its connection's last seen txseq, so
+//! it still sees its own writes. Reads inside a transaction, reads asking for
+//! [`Consistency::Strong`](crate::Consistency::Strong), statements that are
+//! not `SELECT`s, and reads served by an embedded replica are never
+//! coalesced. Errors are not shared: when the shared query fails, each
+//! waiting read sends its own.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HADataSource, QueryCoalescer};
+//! use std::sync::Arc;
+//!
+//! let mut ds = HADataSource::default();
+//! ds.set_url("litesql://localhost:8080")
+//!     .set_query_coalescer(Arc::new(QueryCoalescer::new()));
+//! ```
+
+use crate::cache::CacheKey;
+use crate::client::ExecutionResult;
+use crate::error::Result;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// Result of a read in flight; `None` until it succeeds.
+type Shared = watch::Receiver<Option<ExecutionResult>>;
+
+#[derive(Debug)]
+struct Flight {
+    id: u64,
+    txseq: i64,
+    result: Shared,
+}
+
+/// Reads in flight shared by connections.
+#[derive(Debug, Default)]
+pub struct QueryCoalescer {
+    flights: Mutex<HashMap<CacheKey, Flight>>,
+    next_id: AtomicU64,
+}
+
+/// How a read takes part in coalescing.
+pub(crate) enum Join<'a> {
+    /// Send the query and share its result
+    Leader(Leader<'a>),
+    /// Wait for the result of the identical read in flight
+    Follower(Shared),
+    /// Send the query without sharing it
+    Alone,
+}
+
+/// Shares the result of a read with its followers; dropping it without a
+/// result lets them send their own.
+pub(crate) struct Leader<'a> {
+    coalescer: &'a QueryCoalescer,
+    key: CacheKey,
+    id: u64,
+    result: watch::Sender<Option<ExecutionResult>>,
+}
+
+impl QueryCoalescer {
+    /// Create a coalescer with no reads in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of distinct reads in flight.
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().len()
+    }
+
+    /// Join the identical read in flight if it was sent at or after `txseq`,
+    /// or lead a new one.
+    pub(crate) fn join(&self, key: CacheKey, txseq: i64) -> Join<'_> {
+        let mut flights = self.flights.lock();
+        if let Some(flight) = flights.get(&key) {
+            return if flight.txseq >= txseq {
+                Join::Follower(flight.result.clone())
+            } else {
+                Join::Alone
+            };
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = watch::channel(None);
+        flights.insert(
+            key.clone(),
+            Flight {
+                id,
+                txseq,
+                result: receiver,
+            },
+        );
+        Join::Leader(Leader {
+            coalescer: self,
+            key,
+            id,
+            result: sender,
+        })
+    }
+}
+
+impl Leader<'_> {
+    /// Share a successful result with the reads waiting for it.
+    pub(crate) fn finish(self, result: &Result<ExecutionResult>) {
+        if let Ok(result) = result {
+            self.result.send_replace(Some(result.clone()));
+        }
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        let mut flights = self.coalescer.flights.lock();
+        if flights.get(&self.key).is_some_and(|f| f.id == self.id) {
+            flights.remove(&self.key);
+        }
+    }
+}
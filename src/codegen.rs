@@ -0,0 +1,330 @@
+//! Rust struct generation from a database schema.
+//!
+//! Enabled by the `codegen` feature. A [`Generator`] reads the tables and
+//! columns of the current database through the client and renders one struct
+//! per table, deriving [`FromRow`] and [`ToParams`], with [`Column`]
+//! constants carrying each column's Rust type. The generated code needs the
+//! `derive` feature.
+//!
+//! Column types follow SQLite's affinity rules: `INT` maps to `i64`, `CHAR`,
+//! `CLOB` and `TEXT` to `String`, `BLOB` to `Vec<u8>`, `REAL`, `FLOA` and
+//! `DOUB` to `f64` and `BOOL` to `bool`. Anything else, including columns
+//! without a declared type, maps to [`Value`]. Nullable columns that are not
+//! part of the primary key are wrapped in `Option`.
+//!
+//! The `litesql codegen` command prints the generated code. From a build
+//! script, use the blocking client:
+//!
+//! ```no_run
+//! # #[cfg(feature = "blocking")]
+//! # fn main() -> litesql_ha::Result<()> {
+//! use litesql_ha::blocking::HADataSource;
+//! use litesql_ha::codegen::Generator;
+//! use litesql_ha::HADataSourceOptions;
+//!
+//! let ds = HADataSource::new(HADataSourceOptions {
+//!     url: "litesql://localhost:8080".to_string(),
+//!     ..Default::default()
+//! })?;
+//! let code = Generator::new().generate_blocking(&ds.get_connection()?)?;
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("models.rs");
+//! std::fs::write(out, code)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "blocking"))]
+//! # fn main() {}
+//! ```
+//!
+//! [`FromRow`]: crate::row::FromRow
+//! [`ToParams`]: crate::row::ToParams
+//! [`Column`]: crate::row::Column
+//! [`Value`]: crate::Value
+
+#[cfg(feature = "blocking")]
+use crate::blocking;
+use crate::connection::HAConnection;
+use crate::error::Result;
+use crate::value::Value;
+use std::fmt::Write;
+
+/// A table read from the database schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    /// Table name
+    pub name: String,
+    /// Columns, in declaration order
+    pub columns: Vec<ColumnSchema>,
+}
+
+/// A column read from the database schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    /// Column name
+    pub name: String,
+    /// Declared type, empty when none was declared
+    pub declared_type: String,
+    /// Whether the column has a NOT NULL constraint
+    pub not_null: bool,
+    /// Whether the column is part of the primary key
+    pub primary_key: bool,
+}
+
+impl ColumnSchema {
+    /// Get the Rust type used for this column.
+    pub fn rust_type(&self) -> String {
+        let declared = self.declared_type.to_uppercase();
+        let base = if declared.contains("INT") {
+            "i64"
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|t| declared.contains(t))
+        {
+            "String"
+        } else if declared.contains("BLOB") {
+            "Vec<u8>"
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|t| declared.contains(t))
+        {
+            "f64"
+        } else if declared.contains("BOOL") {
+            "bool"
+        } else {
+            "Value"
+        };
+
+        if base == "Value" || self.not_null || self.primary_key {
+            base.to_string()
+        } else {
+            format!("Option<{}>", base)
+        }
+    }
+}
+
+/// Generates Rust structs from a database schema.
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    tables: Vec<String>,
+}
+
+impl Generator {
+    /// Create a generator for every table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only generate the given table; may be called several times.
+    pub fn with_table(mut self, table: impl Into<String>) -> Self {
+        self.tables.push(table.into());
+        self
+    }
+
+    /// Read the schema of the selected tables.
+    pub async fn introspect(&self, conn: &HAConnection) -> Result<Vec<TableSchema>> {
+        // Read from the server: a lagging replica could miss new columns.
+        let client = conn.client();
+        let names = client
+            .execute_query(
+                "SELECT name FROM sqlite_master WHERE type = 'table' \
+                 AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+                 AND name NOT LIKE '\\_\\_litesql\\_%' ESCAPE '\\' ORDER BY name",
+                &[],
+            )
+            .await?;
+
+        let mut tables = Vec::new();
+        for row in names.rows {
+            let Some(Value::String(name)) = row.into_iter().next() else {
+                continue;
+            };
+            if !self.tables.is_empty() && !self.tables.contains(&name) {
+                continue;
+            }
+
+            let info = client
+                .execute_query(
+                    "SELECT name, type, \"notnull\", pk FROM pragma_table_info(?) ORDER BY cid",
+                    &[Value::String(name.clone())],
+                )
+                .await?;
+            let columns = info
+                .rows
+                .into_iter()
+                .map(|row| match row.as_slice() {
+                    [Value::String(name), declared_type, not_null, pk] => ColumnSchema {
+                        name: name.clone(),
+                        declared_type: match declared_type {
+                            Value::String(t) => t.clone(),
+                            _ => String::new(),
+                        },
+                        not_null: is_set(not_null),
+                        primary_key: is_set(pk),
+                    },
+                    _ => ColumnSchema {
+                        name: String::new(),
+                        declared_type: String::new(),
+                        not_null: false,
+                        primary_key: false,
+                    },
+                })
+                .filter(|c| !c.name.is_empty())
+                .collect();
+            tables.push(TableSchema { name, columns });
+        }
+
+        Ok(tables)
+    }
+
+    /// Read the schema through a blocking connection.
+    #[cfg(feature = "blocking")]
+    pub fn introspect_blocking(&self, conn: &blocking::HAConnection) -> Result<Vec<TableSchema>> {
+        conn.block_on(self.introspect(conn.inner()))
+    }
+
+    /// Read the schema and render the structs.
+    pub async fn generate(&self, conn: &HAConnection) -> Result<String> {
+        Ok(self.render(&self.introspect(conn).await?))
+    }
+
+    /// Read the schema through a blocking connection and render the structs.
+    #[cfg(feature = "blocking")]
+    pub fn generate_blocking(&self, conn: &blocking::HAConnection) -> Result<String> {
+        Ok(self.render(&self.introspect_blocking(conn)?))
+    }
+
+    /// Render structs for the given tables.
+    pub fn render(&self, tables: &[TableSchema]) -> String {
+        let mut out = String::new();
+        out.push_str("// Generated by litesql-ha codegen from the database schema. Do not edit.\n");
+
+        for table in tables {
+            let mut fields: Vec<String> = Vec::with_capacity(table.columns.len());
+            for column in &table.columns {
+                let mut field = field_name(&column.name);
+                while fields.contains(&field) {
+                    field.push('_');
+                }
+                fields.push(field);
+            }
+
+            let _ = writeln!(out);
+            let _ = writeln!(out, "/// Row of the `{}` table.", table.name);
+            let _ = writeln!(
+                out,
+                "#[derive(Debug, Clone, PartialEq, ::litesql_ha::row::FromRow, ::litesql_ha::row::ToParams)]"
+            );
+            let _ = writeln!(out, "pub struct {} {{", struct_name(&table.name));
+            for (column, field) in table.columns.iter().zip(&fields) {
+                if field.trim_start_matches("r#") != column.name {
+                    let _ = writeln!(out, "    #[litesql(rename = {:?})]", column.name);
+                }
+                let _ = writeln!(out, "    pub {}: {},", field, qualified_type(column));
+            }
+            let _ = writeln!(out, "}}");
+
+            let _ = writeln!(out);
+            let _ = writeln!(out, "impl {} {{", struct_name(&table.name));
+            let _ = writeln!(out, "    /// Table name.");
+            let _ = writeln!(out, "    pub const TABLE: &'static str = {:?};", table.name);
+            let _ = writeln!(out, "    /// Column names, in field order.");
+            let names: Vec<String> = table
+                .columns
+                .iter()
+                .map(|c| format!("{:?}", c.name))
+                .collect();
+            let _ = writeln!(
+                out,
+                "    pub const COLUMNS: &'static [&'static str] = &[{}];",
+                names.join(", ")
+            );
+            for (column, field) in table.columns.iter().zip(&fields) {
+                let mut constant = field.trim_start_matches("r#").to_uppercase();
+                if constant == "TABLE" || constant == "COLUMNS" {
+                    constant.push_str("_COLUMN");
+                }
+                let _ = writeln!(out, "    /// `{}` column.", column.name);
+                let _ = writeln!(
+                    out,
+                    "    pub const {}: ::litesql_ha::row::Column<{}> = \
+                     ::litesql_ha::row::Column::new({:?});",
+                    constant,
+                    qualified_type(column),
+                    column.name
+                );
+            }
+            let _ = writeln!(out, "}}");
+        }
+
+        out
+    }
+}
+
+/// Get the Rust type of a column with `Value` spelled out in full.
+fn qualified_type(column: &ColumnSchema) -> String {
+    match column.rust_type().as_str() {
+        "Value" => "::litesql_ha::Value".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn is_set(value: &Value) -> bool {
+    match value {
+        Value::Int64(v) => *v != 0,
+        Value::Int32(v) => *v != 0,
+        Value::Bool(v) => *v,
+        _ => false,
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// Turn a column name into a snake_case field name.
+fn field_name(column: &str) -> String {
+    let mut name: String = column
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    match name.as_str() {
+        "_" | "self" | "super" | "crate" => {
+            name.push('_');
+            name
+        }
+        n if KEYWORDS.contains(&n) => format!("r#{}", name),
+        _ => name,
+    }
+}
+
+/// Turn a table name into a CamelCase struct name.
+fn struct_name(table: &str) -> String {
+    let mut name: String = table
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase());
+            first.into_iter().chain(chars).collect::<String>()
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, 'T');
+    }
+    if name == "Self" {
+        name.push('_');
+    }
+    name
+}
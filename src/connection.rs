@@ -1,12 +1,46 @@
 //! HA Connection for managing database connections.
 
-use crate::client::{ExecutionResult, HAClient, HAClientOptions};
-use crate::embedded_replicas::EmbeddedReplicasManager;
+use crate::audit::{AuditContext, AuditOutcome, Auditor};
+use crate::auth::TokenProvider;
+use crate::cache::{CacheKey, QueryCache};
+use crate::cancel::CancelHandle;
+use crate::client::{
+    ExecuteResult, ExecutionResult, HAClient, HAClientOptions, KeepaliveOptions, QueryOptions,
+    RowStream, TlsOptions,
+};
+use crate::coalesce::{Join, QueryCoalescer};
+use crate::datetime::TimestampStorage;
+use crate::discovery::DiscoveryOptions;
+#[cfg(feature = "embedded-replicas")]
+use crate::embedded_replicas::{
+    is_corrupt, EmbeddedReplicasManager, ReplicaConnection, StatementCacheStats, StatementLru,
+    STATEMENT_CACHE_CAPACITY,
+};
 use crate::error::{Error, Result};
+use crate::hooks::{self, QueryEvent, QueryHook, QueryOutcome, Summarize};
+use crate::load_balancing::LoadBalancer;
+use crate::metadata::MetadataProvider;
+use crate::middleware::Middleware;
+use crate::params::Placeholders;
+use crate::recording::{Recorder, Replay};
+use crate::routing::{Consistency, Route, RouteReason, RoutingDecision, RoutingStats};
+use crate::row::{FromRow, FromValue, OwnedRow};
+use crate::runtime::{self, Instant, JoinHandle};
+use crate::slow_query::SlowQueryLog;
+use crate::statement::Statement;
+use crate::transaction::{
+    Savepoint, Transaction, TransactionBehavior, TransactionFuture, TransactionOptions,
+};
 use crate::value::Value;
 use parking_lot::Mutex;
+#[cfg(feature = "embedded-replicas")]
 use rusqlite::{params_from_iter, Connection as SqliteConnection, ToSql};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
 
 /// Options for HAConnection configuration.
 #[derive(Debug, Clone, Default)]
@@ -15,11 +49,26 @@ pub struct HAConnectionOptions {
     pub url: String,
     /// Authentication token
     pub token: Option<String>,
+    /// Supplies a token for every call, taking precedence over `token`
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Supplies metadata sent with every call
+    pub metadata_provider: Option<Arc<dyn MetadataProvider>>,
+    /// Interceptors or tower layers wrapping the gRPC channel
+    pub middleware: Option<Middleware>,
+    /// URLs of follower nodes serving reads sent to the server
+    pub read_endpoints: Vec<String>,
+    /// Spreads reads over `read_endpoints`; round-robin when not set
+    pub load_balancer: Option<Arc<dyn LoadBalancer>>,
+    /// Resolve the servers of `url` from DNS periodically, following the
+    /// server pool as it scales
+    pub discovery: Option<DiscoveryOptions>,
     /// Enable SSL/TLS
     pub enable_ssl: bool,
+    /// Certificates for TLS; setting them enables TLS
+    pub tls: Option<TlsOptions>,
     /// Query timeout in seconds
     pub timeout: u64,
-    /// Embedded replicas directory
+    /// Embedded replicas directory (ignored without the `embedded-replicas` feature)
     pub embedded_replicas_dir: Option<String>,
     /// NATS replication URL
     pub replication_url: Option<String>,
@@ -27,141 +76,1225 @@ pub struct HAConnectionOptions {
     pub replication_stream: Option<String>,
     /// Durable consumer name
     pub replication_durable: Option<String>,
+    /// Audit hook receiving every executed statement
+    pub auditor: Option<Auditor>,
+    /// Hooks called around every statement, in order
+    pub query_hooks: Vec<Arc<dyn QueryHook>>,
+    /// Reports statements slower than its threshold, after the query hooks
+    pub slow_query_log: Option<SlowQueryLog>,
+    /// Shared routing counters; a private set is created when not provided
+    pub routing_stats: Option<Arc<RoutingStats>>,
+    /// Shared read cache; reads are not cached when not provided
+    pub query_cache: Option<Arc<QueryCache>>,
+    /// Shares identical reads in flight; reads are not coalesced when not
+    /// provided
+    pub query_coalescer: Option<Arc<QueryCoalescer>>,
+    /// Records `Query` traffic to a file
+    pub recorder: Option<Recorder>,
+    /// Serves recorded responses instead of calling the server
+    pub replay: Option<Replay>,
+    /// Run each transaction over a single `Query` stream, so that a load
+    /// balancer cannot send its statements to different server sessions
+    pub sticky_transactions: bool,
+    /// HTTP/2 keepalive pings
+    pub keepalive: KeepaliveOptions,
+    /// How `Value::Timestamp` parameters are stored, on the server and on
+    /// embedded replicas
+    pub timestamp_storage: TimestampStorage,
+    /// How long a read waits for a stale embedded replica to catch up with
+    /// this connection's writes before going to the HA server; reads do not
+    /// wait when `None`
+    pub replication_wait: Option<Duration>,
+    /// How long a read waits for an answer before it is hedged: sent to the
+    /// HA server again, or besides the embedded replica serving it, taking
+    /// the first successful answer; reads are not hedged when `None`
+    pub hedge_delay: Option<Duration>,
+    /// Consistency of reads whose [`QueryOptions`] do not set one
+    pub consistency: Consistency,
+    /// A stale embedded replica still serves `BoundedStaleness` reads when
+    /// it has been missing transactions for less than this time: since the
+    /// connection first saw a txseq past the replica's, or since the
+    /// replication feed went down
+    pub max_replica_lag: Duration,
+    /// A stale embedded replica still serves `BoundedStaleness` reads when
+    /// it is at most this many transactions behind the last seen txseq
+    pub max_replica_lag_txseq: i64,
+    /// Prepared statements cached for reads on the embedded replica; 16
+    /// when not set (ignored without the `embedded-replicas` feature)
+    pub replica_statement_cache_capacity: Option<usize>,
+    /// Have the replicas manager download the replica of a catalog without
+    /// one in the background when it is read (ignored without the
+    /// `embedded-replicas` feature)
+    pub download_missing_replicas: bool,
+    /// Have the replicas manager download the replica of a catalog without
+    /// one when the connection switches to it: before
+    /// [`HAConnection::switch_catalog`] returns, in the background for
+    /// [`HAConnection::set_catalog`] (ignored without the
+    /// `embedded-replicas` feature)
+    pub download_replica_on_switch: bool,
+    /// Shared prepared statement cache counters of replica reads; a private
+    /// set is created when not provided
+    #[cfg(feature = "embedded-replicas")]
+    pub replica_statement_stats: Option<Arc<StatementCacheStats>>,
+    /// Loaded replicas shared with other connections; an empty manager is
+    /// created when not provided
+    #[cfg(feature = "embedded-replicas")]
+    pub replicas_manager: Option<Arc<EmbeddedReplicasManager>>,
 }
 
 /// Represents a connection to the HA database.
 pub struct HAConnection {
     client: Arc<HAClient>,
-    embedded_replica: Mutex<Option<SqliteConnection>>,
+    #[cfg(feature = "embedded-replicas")]
+    embedded_replica: Mutex<Option<(SqliteConnection, StatementLru, Arc<ReplicaConnection>)>>,
+    #[cfg(feature = "embedded-replicas")]
     replicas_manager: Option<Arc<EmbeddedReplicasManager>>,
+    #[cfg(feature = "embedded-replicas")]
+    statement_cache_capacity: usize,
+    #[cfg(feature = "embedded-replicas")]
+    statement_stats: Arc<StatementCacheStats>,
+    #[cfg(feature = "embedded-replicas")]
+    download_missing_replicas: bool,
+    #[cfg(feature = "embedded-replicas")]
+    download_replica_on_switch: bool,
     closed: Mutex<bool>,
     auto_commit: Mutex<bool>,
     read_only: Mutex<bool>,
+    auditor: Option<Auditor>,
+    audit_context: Mutex<AuditContext>,
+    query_hooks: Mutex<Arc<[Arc<dyn QueryHook>]>>,
+    routing_stats: Arc<RoutingStats>,
+    query_cache: Option<Arc<QueryCache>>,
+    query_coalescer: Option<Arc<QueryCoalescer>>,
+    replication_wait: Option<Duration>,
+    hedge_delay: Option<Duration>,
+    consistency: Consistency,
+    max_replica_lag: Duration,
+    max_replica_lag_txseq: i64,
+    pending_rollback: Mutex<Option<JoinHandle<Result<ExecuteResult>>>>,
 }
 
+/// How often [`HAConnection::wait_for_replication`] checks the replica.
+const REPLICATION_POLL: Duration = Duration::from_millis(10);
+
 impl HAConnection {
     /// Create a new connection.
     pub async fn new(options: HAConnectionOptions) -> Result<Self> {
         let client_options = HAClientOptions {
             url: options.url.clone(),
             token: options.token.clone(),
+            token_provider: options.token_provider.clone(),
+            metadata_provider: options.metadata_provider.clone(),
+            middleware: options.middleware.clone(),
+            read_endpoints: options.read_endpoints.clone(),
+            load_balancer: options.load_balancer.clone(),
+            discovery: options.discovery.clone(),
             enable_ssl: options.enable_ssl,
+            tls: options.tls,
             timeout: options.timeout,
+            recorder: options.recorder,
+            replay: options.replay,
+            sticky_transactions: options.sticky_transactions,
+            keepalive: options.keepalive,
+            timestamp_storage: options.timestamp_storage,
         };
 
         let client = Arc::new(HAClient::new(client_options).await?);
 
+        #[cfg(feature = "embedded-replicas")]
+        let statement_cache_capacity = options
+            .replica_statement_cache_capacity
+            .unwrap_or(STATEMENT_CACHE_CAPACITY);
+        #[cfg(feature = "embedded-replicas")]
         let (embedded_replica, replicas_manager) =
             if options.embedded_replicas_dir.is_some() && options.replication_url.is_some() {
-                let manager = Arc::new(EmbeddedReplicasManager::new());
-                let conn = manager.create_connection(&client.replication_id());
+                let manager = options.replicas_manager.unwrap_or_default();
+                let catalog = client.replication_id();
+                let conn = Self::open_replica(&manager, &catalog, statement_cache_capacity);
                 (Mutex::new(conn), Some(manager))
             } else {
                 (Mutex::new(None), None)
             };
 
+        let mut query_hooks = options.query_hooks;
+        if let Some(slow_query_log) = options.slow_query_log {
+            query_hooks.push(Arc::new(slow_query_log));
+        }
+
         Ok(Self {
             client,
+            #[cfg(feature = "embedded-replicas")]
             embedded_replica,
+            #[cfg(feature = "embedded-replicas")]
             replicas_manager,
+            #[cfg(feature = "embedded-replicas")]
+            statement_cache_capacity,
+            #[cfg(feature = "embedded-replicas")]
+            statement_stats: options.replica_statement_stats.unwrap_or_default(),
+            #[cfg(feature = "embedded-replicas")]
+            download_missing_replicas: options.download_missing_replicas,
+            #[cfg(feature = "embedded-replicas")]
+            download_replica_on_switch: options.download_replica_on_switch,
             closed: Mutex::new(false),
             auto_commit: Mutex::new(true),
             read_only: Mutex::new(false),
+            auditor: options.auditor,
+            audit_context: Mutex::new(AuditContext::default()),
+            query_hooks: Mutex::new(query_hooks.into()),
+            routing_stats: options.routing_stats.unwrap_or_default(),
+            query_cache: options.query_cache,
+            query_coalescer: options.query_coalescer,
+            replication_wait: options.replication_wait,
+            hedge_delay: options.hedge_delay,
+            consistency: options.consistency,
+            max_replica_lag: options.max_replica_lag,
+            max_replica_lag_txseq: options.max_replica_lag_txseq,
+            pending_rollback: Mutex::new(None),
         })
     }
 
     /// Execute a SELECT query.
     pub async fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
-        self.check_closed()?;
+        self.query_with(sql, params, &QueryOptions::default()).await
+    }
+
+    /// Execute a SELECT query with per-call options.
+    pub async fn query_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        self.ready().await?;
+        let started = self.start(sql, params);
+
+        // Answer from the cache if it holds a result at the last seen txseq
+        let consistency = options.consistency.unwrap_or(self.consistency);
+        let cache_key = self.cache_key(sql, params);
+        if consistency != Consistency::Strong {
+            if let Some(result) = self.cached(&cache_key) {
+                return self.finish_read(
+                    sql,
+                    params,
+                    RoutingDecision::cache(),
+                    Ok(result),
+                    started,
+                );
+            }
+        }
 
-        // Use embedded replica for read queries if available and up-to-date
-        if self.should_use_replica(sql) {
-            if let Some(result) = self.execute_on_replica(sql, params)? {
-                return Ok(result);
+        // Use embedded replica for read queries if the consistency allows it
+        let mut decision = self.route_read_after_wait(sql, consistency).await;
+        if decision.route == Route::Replica {
+            if let Some((result, decision)) =
+                self.read_replica(sql, params, options, decision).await
+            {
+                return self.finish_read(sql, params, decision, result, started);
             }
+            decision = RoutingDecision::primary(RouteReason::NoReplica);
         }
 
-        self.client.execute_query(sql, params).await
+        let hedged = AtomicBool::new(false);
+        let call = self.hedge(decision, options, &hedged, |mut options| async move {
+            // Lets the client tell whether a follower may serve the read
+            options.consistency = Some(consistency);
+            self.client.execute_query_with(sql, params, &options).await
+        });
+        let result = self
+            .read_primary(sql, params, options, consistency, &mut decision, call)
+            .await;
+        if hedged.into_inner() {
+            decision = RoutingDecision::primary(RouteReason::Hedged);
+        }
+        self.remember(cache_key, &result);
+        self.finish_read(sql, params, decision, result, started)
+    }
+
+    /// Execute a SELECT query and stream its rows as they arrive.
+    ///
+    /// Streamed reads always go to the HA server and bypass the query cache.
+    pub async fn query_stream(&self, sql: &str, params: &[Value]) -> Result<RowStream> {
+        self.query_stream_with(sql, params, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a SELECT query with per-call options and stream its rows.
+    pub async fn query_stream_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<RowStream> {
+        self.ready().await?;
+        let started = self.start(sql, params);
+        let decision = RoutingDecision::primary(RouteReason::Streamed);
+        debug!(route = ?decision.route, reason = %decision.reason, "routed read");
+        self.routing_stats.record(&decision);
+
+        let result = self
+            .client
+            .execute_query_stream_with(sql, params, options)
+            .await;
+        self.observe_txseq();
+        self.complete(sql, params, decision, &result, started);
+        result
     }
 
     /// Execute an INSERT/UPDATE/DELETE statement.
-    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<i64> {
-        self.check_closed()?;
-        self.client.execute_update(sql, params).await
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.execute_with(sql, params, &QueryOptions::default())
+            .await
+    }
+
+    /// Execute a sea-query INSERT, UPDATE or DELETE, built in the SQLite
+    /// dialect.
+    #[cfg(feature = "sea-query")]
+    pub async fn execute_sea<S: ::sea_query::QueryStatementWriter>(
+        &self,
+        statement: &S,
+    ) -> Result<ExecuteResult> {
+        let (sql, params) = crate::sea_query::build(statement)?;
+        self.execute(&sql, &params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with per-call options.
+    pub async fn execute_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecuteResult> {
+        self.ready().await?;
+        self.execute_on_primary(sql, params, options).await
     }
 
     /// Execute any SQL statement.
     pub async fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.run_with(sql, params, &QueryOptions::default()).await
+    }
+
+    /// Execute any SQL statement with per-call options.
+    pub async fn run_with(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecutionResult> {
+        self.ready().await?;
+        let started = self.start(sql, params);
+
+        // Answer from the cache if it holds a result at the last seen txseq
+        let consistency = options.consistency.unwrap_or(self.consistency);
+        let cache_key = self.cache_key(sql, params);
+        if consistency != Consistency::Strong {
+            if let Some(result) = self.cached(&cache_key) {
+                return self.finish_read(
+                    sql,
+                    params,
+                    RoutingDecision::cache(),
+                    Ok(result),
+                    started,
+                );
+            }
+        }
+
+        // Use embedded replica for read queries if the consistency allows it
+        let mut decision = self.route_read_after_wait(sql, consistency).await;
+        if decision.route == Route::Replica {
+            if let Some((result, decision)) =
+                self.read_replica(sql, params, options, decision).await
+            {
+                return self.finish_read(sql, params, decision, result, started);
+            }
+            decision = RoutingDecision::primary(RouteReason::NoReplica);
+        }
+
+        let hedged = AtomicBool::new(false);
+        let call = self.hedge(decision, options, &hedged, |options| async move {
+            self.client.execute_with(sql, params, &options).await
+        });
+        let result = self
+            .read_primary(sql, params, options, consistency, &mut decision, call)
+            .await;
+        if hedged.into_inner() {
+            decision = RoutingDecision::primary(RouteReason::Hedged);
+        }
+        self.remember(cache_key, &result);
+        self.finish_read(sql, params, decision, result, started)
+    }
+
+    /// Execute several statements in one round trip, returning a result per
+    /// statement.
+    ///
+    /// Statements always go to the HA server and are sent together, so bulk
+    /// loads are not bound by per-statement latency. The first failing
+    /// statement fails the batch, but the server still runs the statements
+    /// after it; use a transaction to make the batch atomic.
+    pub async fn execute_batch(
+        &self,
+        statements: &[(&str, &[Value])],
+    ) -> Result<Vec<ExecutionResult>> {
+        self.ready().await?;
+        for (sql, params) in statements {
+            self.start(sql, params);
+        }
+        let started = Instant::now();
+        let decision = RoutingDecision::primary(RouteReason::Write);
+
+        let results = match self.client.execute_batch(statements).await {
+            Ok(results) => results,
+            Err(e) => {
+                self.observe_txseq();
+                let failed = Err(e);
+                for (sql, params) in statements {
+                    self.complete(sql, params, decision, &failed, started);
+                }
+                return failed;
+            }
+        };
+        self.observe_txseq();
+
+        for ((sql, params), result) in statements.iter().zip(&results) {
+            self.complete(sql, params, decision, result, started);
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result.map(|mut r| {
+                    r.routing = Some(decision);
+                    r
+                })
+            })
+            .collect()
+    }
+
+    /// Execute one INSERT/UPDATE/DELETE statement once per parameter set, in
+    /// one round trip.
+    ///
+    /// Like [`execute_batch`](Self::execute_batch), the sets always go to the
+    /// HA server and the server runs every set even if an earlier one fails;
+    /// use a transaction to make the call atomic. Returns the result of each
+    /// set in order.
+    pub async fn execute_many<I, P>(&self, sql: &str, param_sets: I) -> Result<Vec<ExecuteResult>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[Value]>,
+    {
+        self.ready().await?;
+        let param_sets: Vec<P> = param_sets.into_iter().collect();
+        for params in &param_sets {
+            self.start(sql, params.as_ref());
+        }
+        let started = Instant::now();
+        let decision = RoutingDecision::primary(RouteReason::Write);
+
+        let results = match self.client.execute_many(sql, &param_sets).await {
+            Ok(results) => results,
+            Err(e) => {
+                self.observe_txseq();
+                let failed = Err(e);
+                for params in &param_sets {
+                    self.complete(sql, params.as_ref(), decision, &failed, started);
+                }
+                return failed;
+            }
+        };
+        self.observe_txseq();
+
+        for (params, result) in param_sets.iter().zip(&results) {
+            let result: Result<ExecuteResult> = Ok(*result);
+            self.complete(sql, params.as_ref(), decision, &result, started);
+        }
+        Ok(results)
+    }
+
+    /// Execute a SELECT query and map each row to `T` by column name.
+    pub async fn query_as<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
+        self.query(sql, params).await?.rows_as()
+    }
+
+    /// Execute a SELECT query that must return exactly one row.
+    pub async fn query_one(&self, sql: &str, params: &[Value]) -> Result<OwnedRow> {
+        self.query(sql, params).await?.into_one()
+    }
+
+    /// Execute a SELECT query that returns at most one row.
+    pub async fn query_optional(&self, sql: &str, params: &[Value]) -> Result<Option<OwnedRow>> {
+        self.query(sql, params).await?.into_optional()
+    }
+
+    /// Execute a SELECT query that must return exactly one row and get its
+    /// first column as `T`.
+    pub async fn query_scalar<T: FromValue>(&self, sql: &str, params: &[Value]) -> Result<T> {
+        self.query(sql, params).await?.into_scalar()
+    }
+
+    /// Execute a SELECT query and deserialize each row into `T` with serde.
+    #[cfg(feature = "serde")]
+    pub async fn query_de<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<Vec<T>> {
+        self.query(sql, params).await?.rows_de()
+    }
+
+    /// Execute a SELECT query and convert the result to a Polars data frame.
+    #[cfg(feature = "polars")]
+    pub async fn query_df(
+        &self,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<::polars::prelude::DataFrame> {
+        self.query(sql, params).await?.to_dataframe()
+    }
+
+    /// Execute a sea-query SELECT, built in the SQLite dialect.
+    #[cfg(feature = "sea-query")]
+    pub async fn query_sea<S: ::sea_query::QueryStatementWriter>(
+        &self,
+        statement: &S,
+    ) -> Result<ExecutionResult> {
+        let (sql, params) = crate::sea_query::build(statement)?;
+        self.query(&sql, &params).await
+    }
+
+    /// Execute a SELECT query and write its rows to `writer` as they arrive.
+    ///
+    /// Returns the number of rows written. Like [`query_stream`], this
+    /// always reads from the HA server.
+    ///
+    /// [`query_stream`]: HAConnection::query_stream
+    #[cfg(feature = "csv")]
+    pub async fn query_export<W>(
+        &self,
+        sql: &str,
+        params: &[Value],
+        format: crate::export::ExportFormat,
+        writer: W,
+    ) -> Result<u64>
+    where
+        W: runtime::AsyncWrite + Unpin,
+    {
+        let rows = self.query_stream(sql, params).await?;
+        crate::export::write_stream(rows, format, writer).await
+    }
+
+    /// Execute a SELECT query and write its rows to `writer` as Parquet.
+    ///
+    /// Returns the number of rows written. Like [`query_stream`], this
+    /// always reads from the HA server.
+    ///
+    /// [`query_stream`]: HAConnection::query_stream
+    #[cfg(feature = "parquet")]
+    pub async fn query_to_parquet<W>(
+        &self,
+        sql: &str,
+        params: &[Value],
+        writer: W,
+        options: &crate::parquet::ParquetOptions,
+    ) -> Result<u64>
+    where
+        W: std::io::Write + Send,
+    {
+        let rows = self.query_stream(sql, params).await?;
+        crate::parquet::write_stream(rows, writer, options).await
+    }
+
+    /// Execute a SELECT query with named parameters.
+    ///
+    /// Names may be given with or without their `:`, `@` or `$` prefix. Every
+    /// placeholder must be bound.
+    pub async fn query_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecutionResult> {
+        let params = Placeholders::parse(sql).bind(params)?;
+        self.query(sql, &params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with named parameters.
+    pub async fn execute_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecuteResult> {
+        let params = Placeholders::parse(sql).bind(params)?;
+        self.execute(sql, &params).await
+    }
+
+    /// Execute any SQL statement with named parameters.
+    pub async fn run_named(&self, sql: &str, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        let params = Placeholders::parse(sql).bind(params)?;
+        self.run(sql, &params).await
+    }
+
+    /// Prepare a statement for repeated execution.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
         self.check_closed()?;
+        Statement::new(self, sql)
+    }
+
+    async fn execute_on_primary(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+    ) -> Result<ExecuteResult> {
+        let started = self.start(sql, params);
+        let result = self.client.execute_update_with(sql, params, options).await;
+        let decision = RoutingDecision::primary(RouteReason::Write);
+        self.observe_txseq();
+        self.complete(sql, params, decision, &result, started);
+        result
+    }
+
+    /// Execute the statement that begins a transaction, pinning the client
+    /// to one stream when sticky transactions are enabled.
+    async fn begin_on_primary(&self, sql: &str) -> Result<i64> {
+        let started = self.start(sql, &[]);
+        let result = self.client.begin_session(sql).await;
+        let decision = RoutingDecision::primary(RouteReason::Write);
+        self.observe_txseq();
+        self.complete(sql, &[], decision, &result, started);
+        result
+    }
 
-        // Use embedded replica for read queries if available and up-to-date
-        if self.should_use_replica(sql) {
-            if let Some(result) = self.execute_on_replica(sql, params)? {
-                return Ok(result);
+    fn cache_key(&self, sql: &str, params: &[Value]) -> Option<CacheKey> {
+        if self.query_cache.is_none() || !*self.auto_commit.lock() {
+            return None;
+        }
+        CacheKey::new(&self.catalog(), sql, params)
+    }
+
+    /// Send a read to the HA server, or share the result of an identical
+    /// read in flight when coalescing is enabled.
+    async fn read_primary(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+        consistency: Consistency,
+        decision: &mut RoutingDecision,
+        call: impl Future<Output = Result<ExecutionResult>>,
+    ) -> Result<ExecutionResult> {
+        let key = match self.query_coalescer {
+            Some(_) if consistency == Consistency::Strong || !*self.auto_commit.lock() => None,
+            Some(_) => CacheKey::new(&self.catalog(), sql, params),
+            None => None,
+        };
+        let join = match (&self.query_coalescer, key) {
+            (Some(coalescer), Some(key)) => coalescer.join(key, self.client.txseq()),
+            _ => Join::Alone,
+        };
+
+        match join {
+            Join::Leader(leader) => {
+                let result = call.await;
+                leader.finish(&result);
+                result
             }
+            Join::Follower(mut shared) => {
+                options.check()?;
+                let time_left = self.client.time_left(options)?;
+                let wait = options.run(async {
+                    runtime::timeout(time_left, shared.wait_for(Option::is_some))
+                        .await
+                        .map_err(|_| Error::Timeout)
+                        .map(|result| result.ok().and_then(|result| result.clone()))
+                });
+                match wait.await? {
+                    Some(result) => {
+                        *decision = RoutingDecision::primary(RouteReason::Coalesced);
+                        Ok(result)
+                    }
+                    // The shared read failed; send our own
+                    None => call.await,
+                }
+            }
+            Join::Alone => call.await,
         }
+    }
 
-        self.client.execute(sql, params).await
+    /// Send a read routed to the HA server with `call`, and send it again
+    /// if it has not been answered after the hedge delay, taking the first
+    /// successful answer; the slower call is dropped, which aborts it.
+    /// Sets `hedged` if the second call answered.
+    ///
+    /// Writes and statements inside a transaction are never hedged.
+    async fn hedge<F, Fut>(
+        &self,
+        decision: RoutingDecision,
+        options: &QueryOptions,
+        hedged: &AtomicBool,
+        call: F,
+    ) -> Result<ExecutionResult>
+    where
+        F: Fn(QueryOptions) -> Fut,
+        Fut: Future<Output = Result<ExecutionResult>>,
+    {
+        let idempotent = !matches!(
+            decision.reason,
+            RouteReason::Write | RouteReason::InTransaction
+        );
+        let delay = match self.hedge_delay {
+            Some(delay) if idempotent => delay,
+            _ => return call(options.clone()).await,
+        };
+        // Both calls finish by the deadline of the first
+        let options = QueryOptions {
+            deadline: Some(Instant::now() + self.client.time_left(options)?),
+            ..options.clone()
+        };
+
+        let mut first = pin!(call(options.clone()));
+        runtime::select! {
+            result = &mut first => return result,
+            _ = runtime::sleep(delay) => {}
+        }
+        debug!(?delay, "hedging read");
+        let mut second = pin!(call(options));
+        runtime::select! {
+            result = &mut first => match result {
+                Ok(_) => result,
+                Err(_) => {
+                    let result = second.await;
+                    hedged.store(result.is_ok(), Ordering::Relaxed);
+                    result
+                }
+            },
+            result = &mut second => match result {
+                Ok(_) => {
+                    hedged.store(true, Ordering::Relaxed);
+                    result
+                }
+                Err(_) => first.await,
+            },
+        }
     }
 
-    fn should_use_replica(&self, sql: &str) -> bool {
-        if self.embedded_replica.lock().is_none() || self.replicas_manager.is_none() {
-            return false;
+    /// Execute a read on the embedded replica, returning the decision to
+    /// report with its result, or `None` if no replica is open.
+    ///
+    /// With a hedge delay, the read is also sent to the HA server by a
+    /// background task if the replica has not answered after the delay; the
+    /// replica read is interrupted if the server answers first. The replica
+    /// read blocks the thread it runs on, so the server is only asked in
+    /// time on a multi-threaded runtime.
+    async fn read_replica(
+        &self,
+        sql: &str,
+        params: &[Value],
+        options: &QueryOptions,
+        decision: RoutingDecision,
+    ) -> Option<(Result<ExecutionResult>, RoutingDecision)> {
+        let delay = match self.hedge_delay {
+            Some(delay) => delay,
+            None => {
+                return self
+                    .execute_on_replica(sql, params, options.cancel.as_ref())
+                    .transpose()
+                    .map(|result| (result, decision))
+            }
+        };
+        if let Err(e) = options.check() {
+            return Some((Err(e), decision));
         }
 
+        // Cancelled when the server answers, or the read is cancelled
+        let interrupt = CancelHandle::new();
+        let hedge = {
+            let client = self.client.clone();
+            let sql = sql.to_string();
+            let params = params.to_vec();
+            let cancel = options.cancel.clone();
+            let options = options.clone();
+            let interrupt = interrupt.clone();
+            runtime::spawn_named(runtime::HEDGE, async move {
+                let read = async {
+                    runtime::sleep(delay).await;
+                    debug!(?delay, "hedging replica read");
+                    let result = client.execute_query_with(&sql, &params, &options).await;
+                    if result.is_ok() {
+                        interrupt.cancel();
+                    }
+                    result
+                };
+                match cancel {
+                    Some(cancel) => runtime::select! {
+                        result = read => result,
+                        _ = cancel.cancelled() => {
+                            interrupt.cancel();
+                            Err(Error::Cancelled)
+                        }
+                    },
+                    None => read.await,
+                }
+            })
+        };
+
+        let result = self.execute_on_replica(sql, params, Some(&interrupt));
+        let cancelled = options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelHandle::is_cancelled);
+        match result {
+            Err(Error::Cancelled) if !cancelled => {
+                let result = hedge.await.unwrap_or_else(|e| {
+                    Err(Error::Query(format!("Hedged read did not run: {}", e)))
+                });
+                Some((result, RoutingDecision::primary(RouteReason::Hedged)))
+            }
+            result => {
+                hedge.abort();
+                result.transpose().map(|result| (result, decision))
+            }
+        }
+    }
+
+    fn cached(&self, key: &Option<CacheKey>) -> Option<ExecutionResult> {
+        match (&self.query_cache, key) {
+            (Some(cache), Some(key)) => cache.get(key),
+            _ => None,
+        }
+    }
+
+    /// Cache a result read at or after the last seen txseq. Reads served by
+    /// a lagging follower carry txseq 0 and are never cached, so a later
+    /// read cannot be answered with their older data.
+    fn remember(&self, key: Option<CacheKey>, result: &Result<ExecutionResult>) {
+        if let (Some(cache), Some(key), Ok(result)) = (&self.query_cache, key, result) {
+            if result.txseq > 0 && result.txseq >= self.client.txseq() {
+                cache.insert(key, result, result.txseq);
+            }
+        }
+    }
+
+    /// Let the cache drop results older than the newest txseq seen.
+    fn observe_txseq(&self) {
+        if let Some(ref cache) = self.query_cache {
+            let txseq = self.client.txseq().max(self.replica_txseq().unwrap_or(0));
+            cache.observe(&self.catalog(), txseq);
+        }
+    }
+
+    fn finish_read(
+        &self,
+        sql: &str,
+        params: &[Value],
+        decision: RoutingDecision,
+        result: Result<ExecutionResult>,
+        started: Instant,
+    ) -> Result<ExecutionResult> {
+        debug!(route = ?decision.route, reason = %decision.reason, "routed read");
+        self.routing_stats.record(&decision);
+        #[cfg(feature = "metrics")]
+        crate::metrics::replica_read(&self.client.replication_id(), &decision);
+        self.observe_txseq();
+
+        let result = result.map(|mut r| {
+            r.routing = Some(decision);
+            r
+        });
+        self.complete(sql, params, decision, &result, started);
+        result
+    }
+
+    /// Call the query hooks before `sql` runs, returning when it started.
+    fn start(&self, sql: &str, params: &[Value]) -> Instant {
+        let hooks = self.query_hooks.lock().clone();
+        let query = QueryEvent {
+            sql,
+            parameters: params,
+        };
+        for hook in hooks.iter() {
+            hook.before_query(&query);
+        }
+        Instant::now()
+    }
+
+    /// Pass a completed statement to the query hooks and the auditor.
+    fn complete<'a, T: Summarize>(
+        &self,
+        sql: &str,
+        params: &[Value],
+        decision: RoutingDecision,
+        result: &'a Result<T>,
+        started: Instant,
+    ) where
+        AuditOutcome: From<&'a Result<T>>,
+    {
+        let hooks = self.query_hooks.lock().clone();
+        if !hooks.is_empty() {
+            let query = QueryEvent {
+                sql,
+                parameters: params,
+            };
+            let outcome = QueryOutcome {
+                routing: decision,
+                result: hooks::summarize(result),
+                latency: started.elapsed(),
+            };
+            for hook in hooks.iter() {
+                hook.after_query(&query, &outcome);
+            }
+        }
+        if let Some(ref auditor) = self.auditor {
+            let context = self.audit_context.lock().clone();
+            auditor.record(
+                sql,
+                params,
+                &context,
+                decision,
+                AuditOutcome::from(result),
+                started.elapsed(),
+            );
+        }
+    }
+
+    fn route_read(&self, sql: &str, consistency: Consistency) -> RoutingDecision {
         if !Self::is_select_query(sql) {
-            return false;
+            return RoutingDecision::primary(RouteReason::Write);
         }
 
-        if let Some(ref manager) = self.replicas_manager {
-            return manager.is_replica_updated(&self.client.replication_id(), self.client.txseq());
+        if !*self.auto_commit.lock() {
+            return RoutingDecision::primary(RouteReason::InTransaction);
+        }
+
+        if consistency == Consistency::Strong {
+            return RoutingDecision::primary(RouteReason::Strong);
+        }
+
+        self.attach_replica();
+        let replica_txseq = match self.replica_txseq() {
+            Some(txseq) => txseq,
+            None => return RoutingDecision::primary(RouteReason::NoReplica),
+        };
+
+        // While the replication feed is down the txseq only tells how far
+        // the replica has got, not that it has everything up to it
+        let connected = self.replication_connected();
+        let txseq = self.client.txseq();
+        if replica_txseq >= txseq && connected {
+            return RoutingDecision::replica();
+        }
+
+        let behind = (txseq - replica_txseq).max(0);
+        let within_bound = match consistency {
+            Consistency::Eventual => true,
+            Consistency::BoundedStaleness(bound) => {
+                let bound = bound.max(self.max_replica_lag);
+                (connected && behind <= self.max_replica_lag_txseq)
+                    || self
+                        .replica_staleness(replica_txseq, connected)
+                        .is_some_and(|age| age < bound)
+            }
+            Consistency::Strong => false,
+        };
+        if within_bound {
+            RoutingDecision::stale_replica(behind)
+        } else if !connected {
+            RoutingDecision::primary(RouteReason::ReplicationDown)
+        } else {
+            RoutingDecision::primary(RouteReason::ReplicaStale { behind })
+        }
+    }
+
+    /// Route a read, first waiting up to `replication_wait` for a stale
+    /// replica to catch up.
+    async fn route_read_after_wait(&self, sql: &str, consistency: Consistency) -> RoutingDecision {
+        let decision = self.route_read(sql, consistency);
+        if let (RouteReason::ReplicaStale { .. }, Some(wait)) =
+            (decision.reason, self.replication_wait)
+        {
+            if self.wait_for_replication(self.client.txseq(), wait).await {
+                return RoutingDecision::replica();
+            }
+        }
+        decision
+    }
+
+    /// Open the replica of the catalog if one was attached since the last
+    /// read or the open one was detached, or have the manager download
+    /// it if `download_missing_replicas` is set.
+    #[cfg(feature = "embedded-replicas")]
+    fn attach_replica(&self) {
+        let manager = match self.replicas_manager {
+            Some(ref manager) => manager,
+            None => return,
+        };
+        let mut replica = self.embedded_replica.lock();
+        if matches!(*replica, Some((.., ref r)) if !r.is_detached()) {
+            return;
+        }
+        let catalog = self.client.replication_id();
+        *replica = Self::open_replica(manager, &catalog, self.statement_cache_capacity);
+        if replica.is_none() && self.download_missing_replicas {
+            manager.request_replica(&catalog);
+        }
+    }
+
+    /// Open the replica of the catalog if one was attached since the last
+    /// read or the open one was detached, or have the manager download
+    /// it if `download_missing_replicas` is set.
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn attach_replica(&self) {}
+
+    /// Get the txseq of the embedded replica, if one is open.
+    #[cfg(feature = "embedded-replicas")]
+    fn replica_txseq(&self) -> Option<i64> {
+        let manager = match self.replicas_manager {
+            Some(ref m) if self.embedded_replica.lock().is_some() => m,
+            _ => return None,
+        };
+        manager
+            .get_replica(&self.client.replication_id())
+            .map(|r| r.get_txseq())
+    }
+
+    /// Get the txseq of the embedded replica, if one is open.
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn replica_txseq(&self) -> Option<i64> {
+        None
+    }
+
+    /// Check if the embedded replicas are receiving changes.
+    #[cfg(feature = "embedded-replicas")]
+    fn replication_connected(&self) -> bool {
+        match self.replicas_manager {
+            Some(ref manager) => manager.is_replication_connected(),
+            None => true,
+        }
+    }
+
+    /// Check if the embedded replicas are receiving changes.
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn replication_connected(&self) -> bool {
+        true
+    }
+
+    /// Get how long the embedded replica, at `replica_txseq`, has been
+    /// missing transactions: since this connection first saw a later txseq,
+    /// or since the replication feed went down if that was earlier. `None`
+    /// when it is behind since a time this connection does not know.
+    #[cfg(feature = "embedded-replicas")]
+    fn replica_staleness(&self, replica_txseq: i64, connected: bool) -> Option<Duration> {
+        let behind_since = if replica_txseq < self.client.txseq() {
+            Some(self.client.seen_past(replica_txseq)?)
+        } else {
+            None
+        };
+        let down_since = if connected {
+            None
+        } else {
+            Some(self.replicas_manager.as_ref()?.replication_down_since()?)
+        };
+        let since = match (behind_since, down_since) {
+            (Some(behind), Some(down)) => behind.min(down),
+            (since, None) | (None, since) => since?,
+        };
+        Some(since.elapsed())
+    }
+
+    /// Get how long the embedded replica, at `replica_txseq`, has been
+    /// missing transactions: since this connection first saw a later txseq,
+    /// or since the replication feed went down if that was earlier. `None`
+    /// when it is behind since a time this connection does not know.
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn replica_staleness(&self, _replica_txseq: i64, _connected: bool) -> Option<Duration> {
+        None
+    }
+
+    /// Read the txseq of the embedded replica from its file, if one is open.
+    #[cfg(feature = "embedded-replicas")]
+    fn refresh_replica_txseq(&self) -> Option<i64> {
+        let manager = match self.replicas_manager {
+            Some(ref m) if self.embedded_replica.lock().is_some() => m,
+            _ => return None,
+        };
+        manager
+            .get_replica(&self.client.replication_id())
+            .map(|r| r.refresh_txseq())
+    }
+
+    /// Read the txseq of the embedded replica from its file, if one is open.
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn refresh_replica_txseq(&self) -> Option<i64> {
+        None
+    }
+
+    /// Wait until the embedded replica has applied transaction `txseq`, such
+    /// as the [`txseq`](ExecuteResult::txseq) of a write, so that reads
+    /// served from it see the write.
+    ///
+    /// Returns `false` if no embedded replica is open or it did not catch up
+    /// within `timeout`; reads then keep going to the HA server until it
+    /// does.
+    pub async fn wait_for_replication(&self, txseq: i64, timeout: Duration) -> bool {
+        let started = Instant::now();
+        loop {
+            match self.refresh_replica_txseq() {
+                Some(replica_txseq) if replica_txseq >= txseq => return true,
+                Some(_) => {}
+                None => return false,
+            }
+            let left = timeout.saturating_sub(started.elapsed());
+            if left.is_zero() {
+                return false;
+            }
+            runtime::sleep(left.min(REPLICATION_POLL)).await;
         }
+    }
 
-        false
+    /// Execute a read on the embedded replica, if one is open.
+    ///
+    /// A replica SQLite finds corrupt is quarantined by the manager and the
+    /// read left to the server.
+    #[cfg(feature = "embedded-replicas")]
+    fn execute_on_replica(
+        &self,
+        sql: &str,
+        params: &[Value],
+        cancel: Option<&CancelHandle>,
+    ) -> Result<Option<ExecutionResult>> {
+        let span = crate::telemetry::replica_read_span(&self.client.replication_id(), sql);
+        let result = span.in_scope(|| self.query_replica(sql, params, cancel));
+        if let Err(ref e) = result {
+            crate::telemetry::fail(&span, e);
+        }
+        match result {
+            Err(Error::Sqlite(e)) if is_corrupt(&e) => {
+                let replica = self.embedded_replica.lock().take();
+                if let (Some(manager), Some((.., replica))) = (&self.replicas_manager, replica) {
+                    manager.quarantine(&self.client.replication_id(), &replica, &e.to_string());
+                }
+                Ok(None)
+            }
+            result => result,
+        }
     }
 
-    fn execute_on_replica(&self, sql: &str, params: &[Value]) -> Result<Option<ExecutionResult>> {
-        let guard = self.embedded_replica.lock();
-        let conn = match guard.as_ref() {
-            Some(c) => c,
+    #[cfg(feature = "embedded-replicas")]
+    fn query_replica(
+        &self,
+        sql: &str,
+        params: &[Value],
+        cancel: Option<&CancelHandle>,
+    ) -> Result<Option<ExecutionResult>> {
+        let mut guard = self.embedded_replica.lock();
+        let (conn, statements) = match guard.as_mut() {
+            Some((c, statements, replica)) => {
+                replica.touch();
+                (&*c, statements)
+            }
             None => return Ok(None),
         };
 
+        // Dropped before the connection is released, so a late cancel cannot
+        // interrupt the next read.
+        let _interrupt = cancel.map(|cancel| cancel.interrupt_on_cancel(conn));
+        let cancelled = || cancel.is_some_and(CancelHandle::is_cancelled);
+        let interrupted = |e: rusqlite::Error| {
+            if cancelled() {
+                Error::Cancelled
+            } else {
+                e.into()
+            }
+        };
+        if cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let storage = self.client.timestamp_storage();
         let sqlite_params: Vec<Box<dyn ToSql>> = params
             .iter()
-            .map(|v| Self::value_to_sqlite(v))
+            .map(|v| Self::value_to_sqlite(&storage.apply(v)))
             .collect();
 
-        let mut stmt = conn.prepare(sql)?;
+        let mut stmt = statements
+            .prepare(conn, sql, &self.statement_stats)
+            .map_err(interrupted)?;
         let column_count = stmt.column_count();
         let columns: Vec<String> = (0..column_count)
             .map(|i| stmt.column_name(i).unwrap_or("").to_string())
             .collect();
+        #[cfg(feature = "json")]
+        let json_columns: Vec<usize> = stmt
+            .columns()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.decl_type()
+                    .is_some_and(|t| t.eq_ignore_ascii_case("JSON"))
+            })
+            .map(|(i, _)| i)
+            .collect();
 
         let param_refs: Vec<&dyn ToSql> = sqlite_params.iter().map(|p| p.as_ref()).collect();
 
-        let rows_result = stmt.query_map(params_from_iter(param_refs.iter()), |row| {
-            let mut row_data = Vec::new();
-            for i in 0..column_count {
-                let value: rusqlite::types::Value = row.get(i)?;
-                row_data.push(Self::sqlite_to_value(value));
-            }
-            Ok(row_data)
-        })?;
+        let rows_result = stmt
+            .query_map(params_from_iter(param_refs.iter()), |row| {
+                let mut row_data = Vec::new();
+                for i in 0..column_count {
+                    let value: rusqlite::types::Value = row.get(i)?;
+                    #[cfg(feature = "json")]
+                    if json_columns.contains(&i) {
+                        row_data.push(Self::sqlite_json_to_value(value));
+                        continue;
+                    }
+                    row_data.push(Self::sqlite_to_value(value));
+                }
+                Ok(row_data)
+            })
+            .map_err(interrupted)?;
 
         let mut rows = Vec::new();
         for row in rows_result {
-            rows.push(row?);
+            // An interrupt is a no-op until the statement runs, so check
+            // between rows too.
+            if cancelled() {
+                return Err(Error::Cancelled);
+            }
+            rows.push(row.map_err(interrupted)?);
         }
 
         Ok(Some(ExecutionResult {
             columns,
             rows,
             rows_affected: 0,
+            last_insert_rowid: 0,
+            txseq: 0,
+            routing: None,
         }))
     }
 
-    fn value_to_sqlite(value: &Value) -> Box<dyn ToSql> {
+    #[cfg(not(feature = "embedded-replicas"))]
+    fn execute_on_replica(
+        &self,
+        _sql: &str,
+        _params: &[Value],
+        _cancel: Option<&CancelHandle>,
+    ) -> Result<Option<ExecutionResult>> {
+        Ok(None)
+    }
+
+    /// Open a connection to the replica of `catalog` with a statement cache
+    /// of `capacity`.
+    #[cfg(feature = "embedded-replicas")]
+    fn open_replica(
+        manager: &EmbeddedReplicasManager,
+        catalog: &str,
+        capacity: usize,
+    ) -> Option<(SqliteConnection, StatementLru, Arc<ReplicaConnection>)> {
+        let replica = manager.get_replica(catalog)?;
+        let conn = replica.create_connection().ok()?;
+        let statements = StatementLru::new(&conn, capacity);
+        Some((conn, statements, replica))
+    }
+
+    #[cfg(feature = "embedded-replicas")]
+    pub(crate) fn value_to_sqlite(value: &Value) -> Box<dyn ToSql> {
         match value {
             Value::Null => Box::new(Option::<i64>::None),
             Value::Bool(v) => Box::new(*v),
@@ -171,16 +1304,16 @@ impl HAConnection {
             Value::Double(v) => Box::new(*v),
             Value::String(v) => Box::new(v.clone()),
             Value::Bytes(v) => Box::new(v.clone()),
-            Value::Timestamp(v) => {
-                let duration = v
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap_or_default();
-                Box::new(duration.as_secs() as i64)
-            }
+            Value::Timestamp(v) => Box::new(crate::datetime::unix_seconds(v)),
+            #[cfg(feature = "json")]
+            Value::Json(v) => Box::new(v.to_string()),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(v) => Box::new(v.to_string()),
         }
     }
 
-    fn sqlite_to_value(value: rusqlite::types::Value) -> Value {
+    #[cfg(feature = "embedded-replicas")]
+    pub(crate) fn sqlite_to_value(value: rusqlite::types::Value) -> Value {
         match value {
             rusqlite::types::Value::Null => Value::Null,
             rusqlite::types::Value::Integer(v) => Value::Int64(v),
@@ -190,6 +1323,19 @@ impl HAConnection {
         }
     }
 
+    /// Convert a value from a column declared `JSON`, keeping text that does
+    /// not parse as a plain string.
+    #[cfg(all(feature = "embedded-replicas", feature = "json"))]
+    fn sqlite_json_to_value(value: rusqlite::types::Value) -> Value {
+        match value {
+            rusqlite::types::Value::Text(v) => match serde_json::from_str(&v) {
+                Ok(json) => Value::Json(json),
+                Err(_) => Value::String(v),
+            },
+            other => Self::sqlite_to_value(other),
+        }
+    }
+
     fn is_select_query(sql: &str) -> bool {
         let trimmed = sql.trim().to_uppercase();
         trimmed.starts_with("SELECT")
@@ -198,33 +1344,130 @@ impl HAConnection {
             || trimmed.starts_with("WITH")
     }
 
+    /// Begin a transaction that rolls back unless committed.
+    pub async fn transaction(&self) -> Result<Transaction<'_>> {
+        self.transaction_with(TransactionBehavior::default()).await
+    }
+
+    /// Begin a transaction with the given locking behavior that rolls back
+    /// unless committed.
+    pub async fn transaction_with(&self, behavior: TransactionBehavior) -> Result<Transaction<'_>> {
+        self.begin_transaction_with(behavior).await?;
+        Ok(Transaction::new(self))
+    }
+
+    /// Run `f` in a transaction and commit it, retrying with the default
+    /// [`TransactionOptions`].
+    pub async fn run_transaction<'a, T, F>(&'a self, f: F) -> Result<T>
+    where
+        F: for<'t> FnMut(&'t Transaction<'a>) -> TransactionFuture<'t, T>,
+    {
+        self.run_transaction_with(&TransactionOptions::default(), f)
+            .await
+    }
+
+    /// Run `f` in a transaction and commit it.
+    ///
+    /// When `f`, beginning or committing fails with an error that
+    /// [is retryable](Error::is_retryable), the transaction is rolled back
+    /// and `f` runs again in a new one, up to `options.max_retries` times.
+    /// Any other error from `f` rolls back and is returned.
+    pub async fn run_transaction_with<'a, T, F>(
+        &'a self,
+        options: &TransactionOptions,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: for<'t> FnMut(&'t Transaction<'a>) -> TransactionFuture<'t, T>,
+    {
+        let mut delay = options.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let error = match self.transaction_with(options.behavior).await {
+                Ok(tx) => match f(&tx).await {
+                    Ok(value) => match tx.commit().await {
+                        Ok(()) => return Ok(value),
+                        Err(e) => e,
+                    },
+                    Err(e) => {
+                        let _ = tx.rollback().await;
+                        e
+                    }
+                },
+                Err(e) => e,
+            };
+            if attempt >= options.max_retries || !error.is_retryable() {
+                return Err(error);
+            }
+            attempt += 1;
+            debug!(attempt, error = %error, "retrying transaction");
+            runtime::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    /// Set a savepoint, beginning a transaction if none is open.
+    ///
+    /// Releasing the outermost savepoint commits the transaction it began.
+    pub async fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        self.ready().await?;
+        let outermost = self.auto_commit();
+        let savepoint = Savepoint::new(self, name, outermost)?;
+        let sql = format!("SAVEPOINT {}", savepoint.quoted_name());
+        if outermost {
+            self.begin_on_primary(&sql).await?;
+        } else {
+            self.execute_on_primary(&sql, &[], &QueryOptions::default())
+                .await?;
+        }
+        *self.auto_commit.lock() = false;
+        Ok(savepoint.started())
+    }
+
     /// Begin a transaction.
     pub async fn begin_transaction(&self) -> Result<()> {
-        self.check_closed()?;
-        self.client.execute_update("BEGIN", &[]).await?;
+        self.begin_transaction_with(TransactionBehavior::default())
+            .await
+    }
+
+    /// Begin a transaction with the given locking behavior.
+    ///
+    /// Write-heavy code should use [`TransactionBehavior::Immediate`] to
+    /// avoid failing with a busy error when a read lock is upgraded.
+    pub async fn begin_transaction_with(&self, behavior: TransactionBehavior) -> Result<()> {
+        self.ready().await?;
+        self.begin_on_primary(behavior.begin_sql()).await?;
         *self.auto_commit.lock() = false;
         Ok(())
     }
 
     /// Commit the current transaction.
     pub async fn commit(&self) -> Result<()> {
-        self.check_closed()?;
-        self.client.execute_update("COMMIT", &[]).await?;
-        *self.auto_commit.lock() = true;
+        self.ready().await?;
+        self.execute_on_primary("COMMIT", &[], &QueryOptions::default())
+            .await?;
+        self.end_transaction().await;
         Ok(())
     }
 
     /// Rollback the current transaction.
     pub async fn rollback(&self) -> Result<()> {
-        self.check_closed()?;
-        self.client.execute_update("ROLLBACK", &[]).await?;
-        *self.auto_commit.lock() = true;
+        self.ready().await?;
+        self.execute_on_primary("ROLLBACK", &[], &QueryOptions::default())
+            .await?;
+        self.end_transaction().await;
         Ok(())
     }
 
+    /// Mark the transaction as ended by a statement sent on its behalf.
+    pub(crate) async fn end_transaction(&self) {
+        self.client.end_session().await;
+        *self.auto_commit.lock() = true;
+    }
+
     /// Set auto-commit mode.
     pub async fn set_auto_commit(&self, auto_commit: bool) -> Result<()> {
-        self.check_closed()?;
+        self.ready().await?;
 
         let current = *self.auto_commit.lock();
         if auto_commit == current {
@@ -234,7 +1477,7 @@ impl HAConnection {
         if auto_commit {
             self.commit().await?;
         } else {
-            self.client.execute_update("BEGIN", &[]).await?;
+            self.begin_on_primary("BEGIN").await?;
         }
 
         *self.auto_commit.lock() = auto_commit;
@@ -248,13 +1491,14 @@ impl HAConnection {
 
     /// Set read-only mode.
     pub async fn set_read_only(&self, read_only: bool) -> Result<()> {
-        self.check_closed()?;
+        self.ready().await?;
         let pragma = if read_only {
             "PRAGMA query_only = 1"
         } else {
             "PRAGMA query_only = 0"
         };
-        self.client.execute_update(pragma, &[]).await?;
+        self.execute_on_primary(pragma, &[], &QueryOptions::default())
+            .await?;
         *self.read_only.lock() = read_only;
         Ok(())
     }
@@ -264,6 +1508,11 @@ impl HAConnection {
         *self.read_only.lock()
     }
 
+    /// Get the consistency of reads whose options do not set one.
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+
     /// Check if the connection is valid.
     pub async fn is_valid(&self) -> bool {
         if *self.closed.lock() {
@@ -278,21 +1527,96 @@ impl HAConnection {
     }
 
     /// Set the current catalog (database name).
+    ///
+    /// If `download_replica_on_switch` is set and the catalog has no
+    /// replica, the replica is downloaded in the background and reads go to
+    /// the server until it is attached; [`switch_catalog`](Self::switch_catalog)
+    /// waits for the download instead.
     pub fn set_catalog(&self, catalog: &str) -> Result<()> {
         if catalog.is_empty() {
-            return Err(Error::InvalidParameter("Catalog cannot be empty".to_string()));
+            return Err(Error::InvalidParameter(
+                "Catalog cannot be empty".to_string(),
+            ));
         }
 
         self.client.set_replication_id(catalog);
 
+        #[cfg(feature = "embedded-replicas")]
         if let Some(ref manager) = self.replicas_manager {
-            let new_conn = manager.create_connection(catalog);
+            let new_conn = Self::open_replica(manager, catalog, self.statement_cache_capacity);
+            if new_conn.is_none() && self.download_replica_on_switch {
+                manager.request_replica(catalog);
+            }
             *self.embedded_replica.lock() = new_conn;
         }
 
         Ok(())
     }
 
+    /// Set the current catalog like [`set_catalog`](Self::set_catalog),
+    /// first downloading its replica if it has none and
+    /// `download_replica_on_switch` is set.
+    ///
+    /// The catalog is left unchanged if the download fails.
+    pub async fn switch_catalog(&self, catalog: &str) -> Result<()> {
+        #[cfg(feature = "embedded-replicas")]
+        if let Some(ref manager) = self.replicas_manager {
+            if self.download_replica_on_switch
+                && !catalog.is_empty()
+                && manager.get_replica(catalog).is_none()
+            {
+                manager.download_replica(catalog).await?;
+            }
+        }
+
+        self.set_catalog(catalog)
+    }
+
+    /// Set the caller-supplied context attached to audit events.
+    pub fn set_audit_context(&self, context: AuditContext) {
+        *self.audit_context.lock() = context;
+    }
+
+    /// Get the caller-supplied context attached to audit events.
+    pub fn audit_context(&self) -> AuditContext {
+        self.audit_context.lock().clone()
+    }
+
+    /// Add a hook called around every statement, after those already
+    /// added.
+    ///
+    /// The hook stays with the connection when a pool recycles it.
+    pub fn add_query_hook(&self, hook: impl QueryHook + 'static) {
+        let mut hooks = self.query_hooks.lock();
+        *hooks = hooks
+            .iter()
+            .cloned()
+            .chain([Arc::new(hook) as Arc<dyn QueryHook>])
+            .collect();
+    }
+
+    /// Get the read cache shared with this connection.
+    pub fn query_cache(&self) -> Option<&Arc<QueryCache>> {
+        self.query_cache.as_ref()
+    }
+
+    /// Get the prepared statement cache counters of reads on the embedded
+    /// replica.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn replica_statement_stats(&self) -> &Arc<StatementCacheStats> {
+        &self.statement_stats
+    }
+
+    /// Get the coalescer of reads in flight shared with this connection.
+    pub fn query_coalescer(&self) -> Option<&Arc<QueryCoalescer>> {
+        self.query_coalescer.as_ref()
+    }
+
+    /// Get the routing counters for this connection.
+    pub fn routing_stats(&self) -> &Arc<RoutingStats> {
+        &self.routing_stats
+    }
+
     /// Get the underlying HAClient.
     pub fn client(&self) -> &Arc<HAClient> {
         &self.client
@@ -310,10 +1634,76 @@ impl HAConnection {
         Ok(())
     }
 
+    /// Check the connection is open and wait for a pending rollback.
+    async fn ready(&self) -> Result<()> {
+        self.check_closed()?;
+        let pending = self.pending_rollback.lock().take();
+        if let Some(handle) = pending {
+            match handle.await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Rollback of dropped transaction failed: {}", e),
+                Err(e) => warn!("Rollback of dropped transaction did not run: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Roll back the open transaction, or to a savepoint, without waiting,
+    /// for use from `Drop`.
+    ///
+    /// The rollback runs on a background task that the next statement on this
+    /// connection waits for. Outside a runtime the transaction is left open
+    /// and the pool closes the connection instead of recycling it.
+    pub(crate) fn rollback_in_background(&self, savepoint: Option<&Savepoint<'_>>) {
+        if *self.auto_commit.lock() || *self.closed.lock() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let statements = match savepoint {
+            Some(savepoint) => vec![
+                format!("ROLLBACK TO {}", savepoint.quoted_name()),
+                format!("RELEASE {}", savepoint.quoted_name()),
+            ],
+            None => vec!["ROLLBACK".to_string()],
+        };
+        let ends_transaction = savepoint.is_none_or(|s| s.is_outermost());
+
+        let mut pending = self.pending_rollback.lock();
+        // Earlier rollbacks must reach the server first.
+        let previous = pending.take();
+        let handle = runtime::try_spawn_named(runtime::ROLLBACK, async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            let mut result = Ok(ExecuteResult::default());
+            for sql in statements {
+                result = client.execute_update(&sql, &[]).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            if ends_transaction {
+                client.end_session().await;
+            }
+            result
+        });
+        if let Some(handle) = handle {
+            *pending = Some(handle);
+            if ends_transaction {
+                *self.auto_commit.lock() = true;
+            }
+        }
+    }
+
     /// Close the connection.
     pub async fn close(&self) -> Result<()> {
         *self.closed.lock() = true;
-        *self.embedded_replica.lock() = None;
+        self.client.end_session().await;
+        #[cfg(feature = "embedded-replicas")]
+        {
+            *self.embedded_replica.lock() = None;
+        }
         Ok(())
     }
 }
@@ -1,11 +1,40 @@
 //! HA DataSource for managing database connections.
 
-use crate::client::{HAClient, HAClientOptions};
+use crate::audit::Auditor;
+use crate::auth::TokenProvider;
+use crate::cache::QueryCache;
+use crate::client::{HAClient, HAClientOptions, KeepaliveOptions, TlsOptions};
+use crate::coalesce::QueryCoalescer;
 use crate::connection::{HAConnection, HAConnectionOptions};
-use crate::embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
-use crate::error::Result;
+use crate::datetime::TimestampStorage;
+use crate::discovery::DiscoveryOptions;
+use crate::download::DownloadOptions;
+#[cfg(feature = "embedded-replicas")]
+use crate::embedded_replicas::{
+    EmbeddedReplicasManager, NatsTlsOptions, ReplicaOptions, StatementCacheStats,
+};
+#[cfg(feature = "sqlcipher")]
+use crate::encryption::ReplicaKeyProvider;
+use crate::error::{Error, ErrorKind, Result};
+use crate::health::Health;
+#[cfg(feature = "embedded-replicas")]
+use crate::health::ReplicaHealth;
+use crate::hooks::QueryHook;
+use crate::load_balancing::LoadBalancer;
+use crate::metadata::MetadataProvider;
+use crate::middleware::Middleware;
+use crate::pool::{Pool, PoolOptions, PoolStatus, PooledConnection};
+use crate::recording::{Recorder, Replay};
+use crate::routing::{Consistency, RoutingStats};
+use crate::runtime::{self, Instant};
+use crate::slow_query::SlowQueryLog;
+#[cfg(feature = "embedded-replicas")]
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "embedded-replicas")]
+use tokio::sync::OnceCell;
+use tracing::debug;
 
 /// Options for HADataSource configuration.
 #[derive(Debug, Clone, Default)]
@@ -14,13 +43,28 @@ pub struct HADataSourceOptions {
     pub url: String,
     /// Authentication password/token
     pub password: Option<String>,
+    /// Supplies a token for every call, taking precedence over `password`
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Supplies metadata sent with every call
+    pub metadata_provider: Option<Arc<dyn MetadataProvider>>,
+    /// Interceptors or tower layers wrapping the gRPC channel
+    pub middleware: Option<Middleware>,
+    /// URLs of follower nodes serving reads sent to the server
+    pub read_endpoints: Vec<String>,
+    /// Spreads reads over `read_endpoints`; round-robin when not set
+    pub load_balancer: Option<Arc<dyn LoadBalancer>>,
+    /// Resolve the servers of `url` from DNS periodically, following the
+    /// server pool as it scales
+    pub discovery: Option<DiscoveryOptions>,
     /// Enable SSL/TLS
     pub enable_ssl: bool,
+    /// Certificates for TLS; setting them enables TLS
+    pub tls: Option<TlsOptions>,
     /// Query timeout in seconds
     pub timeout: u64,
     /// Login timeout in seconds
     pub login_timeout: u64,
-    /// Embedded replicas directory
+    /// Embedded replicas directory (ignored without the `embedded-replicas` feature)
     pub embedded_replicas_dir: Option<String>,
     /// NATS replication URL
     pub replication_url: Option<String>,
@@ -28,20 +72,139 @@ pub struct HADataSourceOptions {
     pub replication_stream: Option<String>,
     /// Durable consumer name
     pub replication_durable: Option<String>,
+    /// TLS settings of the NATS connection
+    #[cfg(feature = "embedded-replicas")]
+    pub replication_tls: Option<NatsTlsOptions>,
+    /// Copy the replica files into memory when loading them, and read and
+    /// replicate there
+    pub replicas_in_memory: bool,
+    /// Download the replica of a catalog missing from the replicas
+    /// directory when it is first read
+    pub download_missing_replicas: bool,
+    /// Download the replica of a catalog missing from the replicas
+    /// directory when a connection switches to it, in the background for
+    /// [`HAConnection::set_catalog`]
+    pub download_replica_on_switch: bool,
+    /// Run `PRAGMA integrity_check` on downloaded replicas before using them
+    pub check_replica_integrity: bool,
+    /// How often replicas whose WAL outgrew `max_replica_wal_size` are
+    /// compacted; never when not set
+    pub replica_maintenance_interval: Option<Duration>,
+    /// Size in bytes a replica's WAL may reach before it is compacted; 64 MiB
+    /// when not set
+    pub max_replica_wal_size: Option<u64>,
+    /// Compact replicas by downloading a new snapshot into them
+    pub resnapshot_replicas: bool,
+    /// Bytes the replica files may take up before the least recently read
+    /// are evicted; unlimited when not set
+    pub max_replicas_disk_usage: Option<u64>,
+    /// Replicas never evicted
+    pub pinned_replicas: Vec<String>,
+    /// Load the replica files added to the replicas directory at runtime
+    /// and detach those removed from it
+    #[cfg(feature = "watch")]
+    pub watch_replicas_dir: bool,
+    /// Encrypts the replica files with the keys it supplies
+    #[cfg(feature = "sqlcipher")]
+    pub replica_key_provider: Option<Arc<dyn ReplicaKeyProvider>>,
+    /// Coordinate with other processes using the same replicas directory,
+    /// so only one of them replicates
+    pub shared_replicas_dir: bool,
+    /// Audit hook receiving every executed statement
+    pub auditor: Option<Auditor>,
+    /// Hooks called around every statement, in order
+    pub query_hooks: Vec<Arc<dyn QueryHook>>,
+    /// Reports statements slower than its threshold
+    pub slow_query_log: Option<SlowQueryLog>,
+    /// Records `Query` traffic to a file
+    pub recorder: Option<Recorder>,
+    /// Serves recorded responses instead of calling the server
+    pub replay: Option<Replay>,
+    /// Run each transaction over a single `Query` stream
+    pub sticky_transactions: bool,
+    /// HTTP/2 keepalive pings
+    pub keepalive: KeepaliveOptions,
+    /// How `Value::Timestamp` parameters are stored
+    pub timestamp_storage: TimestampStorage,
+    /// How long a read waits for a stale embedded replica to catch up
+    pub replication_wait: Option<Duration>,
+    /// How long a read waits for an answer before it is hedged
+    pub hedge_delay: Option<Duration>,
+    /// Consistency of reads that do not set their own
+    pub consistency: Consistency,
+    /// Time for which a stale replica may have been missing transactions and
+    /// still serve `BoundedStaleness` reads
+    pub max_replica_lag: Duration,
+    /// Transactions a stale replica may be behind and still serve
+    /// `BoundedStaleness` reads
+    pub max_replica_lag_txseq: i64,
+    /// Prepared statements cached per connection for embedded replica
+    /// reads; 16 when not set
+    pub replica_statement_cache_capacity: Option<usize>,
+    /// Read cache shared by all connections
+    pub query_cache: Option<Arc<QueryCache>>,
+    /// Coalescer of identical reads in flight shared by all connections
+    pub query_coalescer: Option<Arc<QueryCoalescer>>,
+    /// Connection pool sizing and recycling
+    pub pool: PoolOptions,
 }
 
 /// Data source for managing HA database connections.
 pub struct HADataSource {
     url: String,
     password: Option<String>,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    metadata_provider: Option<Arc<dyn MetadataProvider>>,
+    middleware: Option<Middleware>,
+    read_endpoints: Vec<String>,
+    load_balancer: Option<Arc<dyn LoadBalancer>>,
+    discovery: Option<DiscoveryOptions>,
     enable_ssl: bool,
+    tls: Option<TlsOptions>,
     timeout: u64,
     login_timeout: u64,
     embedded_replicas_dir: Option<String>,
     replication_url: Option<String>,
     replication_stream: Option<String>,
     replication_durable: Option<String>,
-    replicas_manager: Option<Arc<EmbeddedReplicasManager>>,
+    #[cfg(feature = "embedded-replicas")]
+    replication_tls: Option<NatsTlsOptions>,
+    replicas_in_memory: bool,
+    download_missing_replicas: bool,
+    download_replica_on_switch: bool,
+    check_replica_integrity: bool,
+    replica_maintenance_interval: Option<Duration>,
+    max_replica_wal_size: Option<u64>,
+    resnapshot_replicas: bool,
+    max_replicas_disk_usage: Option<u64>,
+    pinned_replicas: Vec<String>,
+    #[cfg(feature = "watch")]
+    watch_replicas_dir: bool,
+    #[cfg(feature = "sqlcipher")]
+    replica_key_provider: Option<Arc<dyn ReplicaKeyProvider>>,
+    shared_replicas_dir: bool,
+    auditor: Option<Auditor>,
+    query_hooks: Vec<Arc<dyn QueryHook>>,
+    slow_query_log: Option<SlowQueryLog>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    sticky_transactions: bool,
+    keepalive: KeepaliveOptions,
+    timestamp_storage: TimestampStorage,
+    replication_wait: Option<Duration>,
+    hedge_delay: Option<Duration>,
+    consistency: Consistency,
+    max_replica_lag: Duration,
+    max_replica_lag_txseq: i64,
+    replica_statement_cache_capacity: Option<usize>,
+    routing_stats: Arc<RoutingStats>,
+    #[cfg(feature = "embedded-replicas")]
+    replica_statement_stats: Arc<StatementCacheStats>,
+    query_cache: Option<Arc<QueryCache>>,
+    query_coalescer: Option<Arc<QueryCoalescer>>,
+    pool: Arc<Pool>,
+    #[cfg(feature = "embedded-replicas")]
+    replicas_manager: OnceCell<Arc<EmbeddedReplicasManager>>,
 }
 
 impl HADataSource {
@@ -50,8 +213,19 @@ impl HADataSource {
         Self {
             url: options.url,
             password: options.password,
+            token_provider: options.token_provider,
+            metadata_provider: options.metadata_provider,
+            middleware: options.middleware,
+            read_endpoints: options.read_endpoints,
+            load_balancer: options.load_balancer,
+            discovery: options.discovery,
             enable_ssl: options.enable_ssl,
-            timeout: if options.timeout > 0 { options.timeout } else { 30 },
+            tls: options.tls,
+            timeout: if options.timeout > 0 {
+                options.timeout
+            } else {
+                30
+            },
             login_timeout: if options.login_timeout > 0 {
                 options.login_timeout
             } else {
@@ -61,63 +235,334 @@ impl HADataSource {
             replication_url: options.replication_url,
             replication_stream: options.replication_stream,
             replication_durable: options.replication_durable,
-            replicas_manager: None,
+            #[cfg(feature = "embedded-replicas")]
+            replication_tls: options.replication_tls,
+            replicas_in_memory: options.replicas_in_memory,
+            download_missing_replicas: options.download_missing_replicas,
+            download_replica_on_switch: options.download_replica_on_switch,
+            check_replica_integrity: options.check_replica_integrity,
+            replica_maintenance_interval: options.replica_maintenance_interval,
+            max_replica_wal_size: options.max_replica_wal_size,
+            resnapshot_replicas: options.resnapshot_replicas,
+            max_replicas_disk_usage: options.max_replicas_disk_usage,
+            pinned_replicas: options.pinned_replicas,
+            #[cfg(feature = "watch")]
+            watch_replicas_dir: options.watch_replicas_dir,
+            #[cfg(feature = "sqlcipher")]
+            replica_key_provider: options.replica_key_provider,
+            shared_replicas_dir: options.shared_replicas_dir,
+            auditor: options.auditor,
+            query_hooks: options.query_hooks,
+            slow_query_log: options.slow_query_log,
+            recorder: options.recorder,
+            replay: options.replay,
+            sticky_transactions: options.sticky_transactions,
+            keepalive: options.keepalive,
+            timestamp_storage: options.timestamp_storage,
+            replication_wait: options.replication_wait,
+            hedge_delay: options.hedge_delay,
+            consistency: options.consistency,
+            max_replica_lag: options.max_replica_lag,
+            max_replica_lag_txseq: options.max_replica_lag_txseq,
+            replica_statement_cache_capacity: options.replica_statement_cache_capacity,
+            routing_stats: Arc::new(RoutingStats::new()),
+            #[cfg(feature = "embedded-replicas")]
+            replica_statement_stats: Arc::new(StatementCacheStats::new()),
+            query_cache: options.query_cache,
+            query_coalescer: options.query_coalescer,
+            pool: Arc::new(Pool::new(options.pool)),
+            #[cfg(feature = "embedded-replicas")]
+            replicas_manager: OnceCell::new(),
+        }
+    }
+
+    /// Get a connection from the pool, opening one if none is idle.
+    ///
+    /// Waits up to the pool's acquire timeout (the login timeout by default)
+    /// when `max_size` connections are already in use, then fails with
+    /// [`Error::Timeout`](crate::Error::Timeout). The connection goes back to
+    /// the pool when the returned guard is dropped.
+    pub async fn get_connection(&self) -> Result<PooledConnection> {
+        self.pool
+            .acquire(Duration::from_secs(self.login_timeout), || {
+                self.open_connection()
+            })
+            .await
+    }
+
+    /// Get the pool counters.
+    pub fn pool_status(&self) -> PoolStatus {
+        self.pool.status()
+    }
+
+    /// Get the embedded replicas manager, once the first connection has
+    /// loaded the replicas.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn replicas_manager(&self) -> Option<&Arc<EmbeddedReplicasManager>> {
+        self.replicas_manager.get()
+    }
+
+    /// Check the health of the data source and the services it depends on.
+    ///
+    /// Opens a connection if none is idle, loading the embedded replicas on
+    /// the first one, and asks the server for the lag of each replica.
+    /// Problems are reported in the returned status rather than as errors.
+    pub async fn health(&self) -> Health {
+        let mut health = Health::default();
+        let client = self.check_server(&mut health).await;
+        self.check_replicas(&mut health, client.as_deref()).await;
+        health
+    }
+
+    /// Wait until the data source is [ready](Health::is_ready): the first
+    /// connection is open and the embedded replicas, if any, are loaded and
+    /// replicating.
+    ///
+    /// Checks again with a growing delay of up to a second, and fails with
+    /// [`Error::Timeout`] if the data source is still not ready after
+    /// `timeout`.
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<Health> {
+        let deadline = Instant::now() + timeout;
+        let mut delay = Duration::from_millis(50);
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let health = runtime::timeout(left, self.health())
+                .await
+                .map_err(|_| Error::Timeout)?;
+            if health.is_ready() {
+                return Ok(health);
+            }
+            debug!("Data source not ready: {:?}", health.error);
+            runtime::sleep(delay.min(deadline.saturating_duration_since(Instant::now()))).await;
+            delay = (delay * 2).min(Duration::from_secs(1));
         }
     }
 
-    /// Get a connection from the data source.
-    pub async fn get_connection(&self) -> Result<HAConnection> {
-        // Initialize embedded replicas if configured
-        if let (Some(ref dir), Some(ref nats_url), Some(ref durable)) = (
+    /// Check that the server answers and accepts the credentials, through a
+    /// pooled connection or, if none can be opened, a client of its own.
+    /// Returns the client if the server answered.
+    async fn check_server(&self, health: &mut Health) -> Option<Arc<HAClient>> {
+        let client = match self.get_connection().await {
+            Ok(conn) => {
+                health.connected = true;
+                Ok(conn.client().clone())
+            }
+            Err(e) => {
+                health.error = Some(e.to_string());
+                self.replica_client().await.map(Arc::new)
+            }
+        };
+        let checked = match client {
+            Ok(client) => client.get_replication_ids().await.map(|_| client),
+            Err(e) => Err(e),
+        };
+        match checked {
+            Ok(client) => {
+                health.server_reachable = true;
+                health.authenticated = true;
+                Some(client)
+            }
+            Err(e) => {
+                let kind = e.kind();
+                health.server_reachable =
+                    !matches!(kind, ErrorKind::Unavailable | ErrorKind::Timeout);
+                health.authenticated =
+                    health.server_reachable && kind != ErrorKind::PermissionDenied;
+                health.error.get_or_insert_with(|| e.to_string());
+                None
+            }
+        }
+    }
+
+    /// Check the embedded replicas, measuring their lag through `client`.
+    #[cfg(feature = "embedded-replicas")]
+    async fn check_replicas(&self, health: &mut Health, client: Option<&HAClient>) {
+        if self.embedded_replicas_dir.is_none()
+            || self.replication_url.is_none()
+            || self.replication_durable.is_none()
+        {
+            return;
+        }
+        let Some(manager) = self.replicas_manager.get() else {
+            health.replicas_loaded = Some(false);
+            health.replication_connected = Some(false);
+            return;
+        };
+        health.replicas_loaded = Some(true);
+        health.replication_connected = Some(manager.is_replication_connected());
+        for name in manager.replica_names() {
+            let failed = manager
+                .get_replica(&name)
+                .is_some_and(|replica| replica.replication_failed());
+            let lag = match client {
+                Some(client) => manager.lag(&name, client).await.ok(),
+                None => None,
+            };
+            health.replicas.push(ReplicaHealth { name, failed, lag });
+        }
+    }
+
+    #[cfg(not(feature = "embedded-replicas"))]
+    async fn check_replicas(&self, _health: &mut Health, _client: Option<&HAClient>) {}
+
+    async fn open_connection(&self) -> Result<HAConnection> {
+        // Initialize embedded replicas once if configured
+        #[cfg(feature = "embedded-replicas")]
+        let replicas_manager = match (
             &self.embedded_replicas_dir,
             &self.replication_url,
             &self.replication_durable,
         ) {
-            let manager = EmbeddedReplicasManager::new();
-            manager
-                .load(ReplicaOptions {
-                    directory: PathBuf::from(dir),
-                    nats_url: nats_url.clone(),
-                    stream: self
-                        .replication_stream
-                        .clone()
-                        .unwrap_or_else(|| "ha".to_string()),
-                    durable: durable.clone(),
-                })
-                .await?;
-        }
+            (Some(dir), Some(nats_url), Some(durable)) => Some(
+                self.replicas_manager
+                    .get_or_try_init(|| async {
+                        let manager = Arc::new(EmbeddedReplicasManager::new());
+                        #[cfg(feature = "nats")]
+                        if let Some(ref cache) = self.query_cache {
+                            manager.set_query_cache(cache.clone());
+                        }
+                        if self.download_missing_replicas || self.download_replica_on_switch {
+                            manager.set_download_client(Arc::new(self.replica_client().await?));
+                        }
+                        for db_name in &self.pinned_replicas {
+                            manager.pin_replica(db_name);
+                        }
+                        manager
+                            .load(ReplicaOptions {
+                                directory: PathBuf::from(dir),
+                                nats_url: nats_url.clone(),
+                                stream: self
+                                    .replication_stream
+                                    .clone()
+                                    .unwrap_or_else(|| "ha".to_string()),
+                                durable: durable.clone(),
+                                tls: self.replication_tls.clone(),
+                                in_memory: self.replicas_in_memory,
+                                integrity_check: self.check_replica_integrity,
+                                maintenance_interval: self.replica_maintenance_interval,
+                                max_wal_size: self
+                                    .max_replica_wal_size
+                                    .unwrap_or(ReplicaOptions::default().max_wal_size),
+                                resnapshot: self.resnapshot_replicas,
+                                max_disk_usage: self.max_replicas_disk_usage,
+                                #[cfg(feature = "sqlcipher")]
+                                key_provider: self.replica_key_provider.clone(),
+                                shared: self.shared_replicas_dir,
+                                ..ReplicaOptions::default()
+                            })
+                            .await?;
+                        manager.start_maintenance();
+                        manager.start_takeover();
+                        #[cfg(feature = "watch")]
+                        if self.watch_replicas_dir {
+                            manager.watch_directory()?;
+                        }
+                        Ok::<_, crate::Error>(manager)
+                    })
+                    .await?
+                    .clone(),
+            ),
+            _ => None,
+        };
 
         let options = HAConnectionOptions {
             url: self.url.clone(),
             token: self.password.clone(),
+            token_provider: self.token_provider.clone(),
+            metadata_provider: self.metadata_provider.clone(),
+            middleware: self.middleware.clone(),
+            read_endpoints: self.read_endpoints.clone(),
+            load_balancer: self.load_balancer.clone(),
+            discovery: self.discovery.clone(),
             enable_ssl: self.enable_ssl,
+            tls: self.tls.clone(),
             timeout: self.timeout,
             embedded_replicas_dir: self.embedded_replicas_dir.clone(),
             replication_url: self.replication_url.clone(),
             replication_stream: self.replication_stream.clone(),
             replication_durable: self.replication_durable.clone(),
+            auditor: self.auditor.clone(),
+            query_hooks: self.query_hooks.clone(),
+            slow_query_log: self.slow_query_log.clone(),
+            routing_stats: Some(self.routing_stats.clone()),
+            query_cache: self.query_cache.clone(),
+            query_coalescer: self.query_coalescer.clone(),
+            recorder: self.recorder.clone(),
+            replay: self.replay.clone(),
+            sticky_transactions: self.sticky_transactions,
+            keepalive: self.keepalive.clone(),
+            timestamp_storage: self.timestamp_storage,
+            replication_wait: self.replication_wait,
+            hedge_delay: self.hedge_delay,
+            consistency: self.consistency,
+            max_replica_lag: self.max_replica_lag,
+            max_replica_lag_txseq: self.max_replica_lag_txseq,
+            replica_statement_cache_capacity: self.replica_statement_cache_capacity,
+            download_missing_replicas: self.download_missing_replicas,
+            download_replica_on_switch: self.download_replica_on_switch,
+            #[cfg(feature = "embedded-replicas")]
+            replica_statement_stats: Some(self.replica_statement_stats.clone()),
+            #[cfg(feature = "embedded-replicas")]
+            replicas_manager,
         };
 
         HAConnection::new(options).await
     }
 
+    /// Get the routing counters aggregated over all connections.
+    pub fn routing_stats(&self) -> &Arc<RoutingStats> {
+        &self.routing_stats
+    }
+
+    /// Get the prepared statement cache counters of embedded replica reads,
+    /// aggregated over all connections.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn replica_statement_stats(&self) -> &Arc<StatementCacheStats> {
+        &self.replica_statement_stats
+    }
+
     /// Download all replicas from the HA server.
     pub async fn download_replicas(
         &self,
         directory: &std::path::Path,
         override_existing: bool,
     ) -> Result<()> {
-        let client = HAClient::new(HAClientOptions {
+        let options = DownloadOptions::new();
+        #[cfg(feature = "embedded-replicas")]
+        let options = options.with_integrity_check(self.check_replica_integrity);
+        self.download_replicas_with(directory, override_existing, &options)
+            .await
+    }
+
+    /// Download all replicas from the HA server with `options` setting the
+    /// chunk size, parallelism, rate limit and progress callback.
+    pub async fn download_replicas_with(
+        &self,
+        directory: &std::path::Path,
+        override_existing: bool,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        self.replica_client()
+            .await?
+            .download_all_replicas_with(directory, override_existing, options)
+            .await
+    }
+
+    /// Create a client for downloading replicas.
+    async fn replica_client(&self) -> Result<HAClient> {
+        HAClient::new(HAClientOptions {
             url: self.url.clone(),
             token: self.password.clone(),
+            token_provider: self.token_provider.clone(),
+            metadata_provider: self.metadata_provider.clone(),
+            middleware: self.middleware.clone(),
             enable_ssl: self.enable_ssl,
+            tls: self.tls.clone(),
             timeout: self.timeout,
+            keepalive: self.keepalive.clone(),
+            ..Default::default()
         })
-        .await?;
-
-        client
-            .download_all_replicas(directory, override_existing)
-            .await
+        .await
     }
 
     // Getters and setters
@@ -129,6 +574,7 @@ impl HADataSource {
 
     /// Set the server URL.
     pub fn set_url(&mut self, url: impl Into<String>) -> &mut Self {
+        self.pool.clear();
         self.url = url.into();
         self
     }
@@ -140,10 +586,88 @@ impl HADataSource {
 
     /// Set the password.
     pub fn set_password(&mut self, password: impl Into<String>) -> &mut Self {
+        self.pool.clear();
         self.password = Some(password.into());
         self
     }
 
+    /// Get the token provider.
+    pub fn token_provider(&self) -> Option<&Arc<dyn TokenProvider>> {
+        self.token_provider.as_ref()
+    }
+
+    /// Set the token provider, asked for a token before every call.
+    pub fn set_token_provider(&mut self, provider: Arc<dyn TokenProvider>) -> &mut Self {
+        self.pool.clear();
+        self.token_provider = Some(provider);
+        self
+    }
+
+    /// Get the metadata provider.
+    pub fn metadata_provider(&self) -> Option<&Arc<dyn MetadataProvider>> {
+        self.metadata_provider.as_ref()
+    }
+
+    /// Set the metadata provider, asked for metadata before every call.
+    pub fn set_metadata_provider(&mut self, provider: Arc<dyn MetadataProvider>) -> &mut Self {
+        self.pool.clear();
+        self.metadata_provider = Some(provider);
+        self
+    }
+
+    /// Get the middleware wrapping the gRPC channel.
+    pub fn middleware(&self) -> Option<&Middleware> {
+        self.middleware.as_ref()
+    }
+
+    /// Set the middleware wrapping the gRPC channel.
+    pub fn set_middleware(&mut self, middleware: Middleware) -> &mut Self {
+        self.pool.clear();
+        self.middleware = Some(middleware);
+        self
+    }
+
+    /// Get the URLs of the follower nodes serving reads.
+    pub fn read_endpoints(&self) -> &[String] {
+        &self.read_endpoints
+    }
+
+    /// Spread reads sent to the server over the follower nodes at `urls`;
+    /// writes keep going to the server at the data source URL.
+    pub fn set_read_endpoints<I>(&mut self, urls: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.pool.clear();
+        self.read_endpoints = urls.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Get the policy spreading reads over the read endpoints.
+    pub fn load_balancer(&self) -> Option<&Arc<dyn LoadBalancer>> {
+        self.load_balancer.as_ref()
+    }
+
+    /// Set the policy spreading reads over the read endpoints.
+    pub fn set_load_balancer(&mut self, balancer: Arc<dyn LoadBalancer>) -> &mut Self {
+        self.pool.clear();
+        self.load_balancer = Some(balancer);
+        self
+    }
+
+    /// Get how the servers are discovered from DNS.
+    pub fn discovery(&self) -> Option<&DiscoveryOptions> {
+        self.discovery.as_ref()
+    }
+
+    /// Resolve the servers of the data source URL from DNS periodically.
+    pub fn set_discovery(&mut self, discovery: DiscoveryOptions) -> &mut Self {
+        self.pool.clear();
+        self.discovery = Some(discovery);
+        self
+    }
+
     /// Get SSL enabled status.
     pub fn enable_ssl(&self) -> bool {
         self.enable_ssl
@@ -151,10 +675,23 @@ impl HADataSource {
 
     /// Set SSL enabled status.
     pub fn set_enable_ssl(&mut self, enable: bool) -> &mut Self {
+        self.pool.clear();
         self.enable_ssl = enable;
         self
     }
 
+    /// Get the TLS certificates.
+    pub fn tls(&self) -> Option<&TlsOptions> {
+        self.tls.as_ref()
+    }
+
+    /// Set the TLS certificates, enabling TLS.
+    pub fn set_tls(&mut self, tls: TlsOptions) -> &mut Self {
+        self.pool.clear();
+        self.tls = Some(tls);
+        self
+    }
+
     /// Get the query timeout.
     pub fn timeout(&self) -> u64 {
         self.timeout
@@ -162,6 +699,7 @@ impl HADataSource {
 
     /// Set the query timeout.
     pub fn set_timeout(&mut self, timeout: u64) -> &mut Self {
+        self.pool.clear();
         self.timeout = timeout;
         self
     }
@@ -184,6 +722,7 @@ impl HADataSource {
 
     /// Set the embedded replicas directory.
     pub fn set_embedded_replicas_dir(&mut self, dir: impl Into<String>) -> &mut Self {
+        self.reset_replicas();
         self.embedded_replicas_dir = Some(dir.into());
         self
     }
@@ -195,6 +734,7 @@ impl HADataSource {
 
     /// Set the NATS replication URL.
     pub fn set_replication_url(&mut self, url: impl Into<String>) -> &mut Self {
+        self.reset_replicas();
         self.replication_url = Some(url.into());
         self
     }
@@ -206,6 +746,7 @@ impl HADataSource {
 
     /// Set the NATS stream name.
     pub fn set_replication_stream(&mut self, stream: impl Into<String>) -> &mut Self {
+        self.reset_replicas();
         self.replication_stream = Some(stream.into());
         self
     }
@@ -217,9 +758,437 @@ impl HADataSource {
 
     /// Set the durable consumer name.
     pub fn set_replication_durable(&mut self, durable: impl Into<String>) -> &mut Self {
+        self.reset_replicas();
         self.replication_durable = Some(durable.into());
         self
     }
+
+    /// Get the TLS settings of the NATS connection.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn replication_tls(&self) -> Option<&NatsTlsOptions> {
+        self.replication_tls.as_ref()
+    }
+
+    /// Connect to NATS over TLS.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn set_replication_tls(&mut self, tls: NatsTlsOptions) -> &mut Self {
+        self.reset_replicas();
+        self.replication_tls = Some(tls);
+        self
+    }
+
+    /// Check if the replicas are copied into memory when loaded.
+    pub fn replicas_in_memory(&self) -> bool {
+        self.replicas_in_memory
+    }
+
+    /// Copy the replica files into memory when loading them, trading memory
+    /// for reads without disk I/O.
+    pub fn set_replicas_in_memory(&mut self, in_memory: bool) -> &mut Self {
+        self.reset_replicas();
+        self.replicas_in_memory = in_memory;
+        self
+    }
+
+    /// Check if replicas missing from the directory are downloaded when
+    /// their catalog is first read.
+    pub fn download_missing_replicas(&self) -> bool {
+        self.download_missing_replicas
+    }
+
+    /// Download the replica of a catalog missing from the replicas
+    /// directory in the background when it is first read, instead of
+    /// requiring [`download_replicas`](Self::download_replicas) beforehand.
+    /// Reads go to the server until the replica is attached.
+    pub fn set_download_missing_replicas(&mut self, download: bool) -> &mut Self {
+        self.reset_replicas();
+        self.download_missing_replicas = download;
+        self
+    }
+
+    /// Check if switching catalogs downloads the replica of a catalog
+    /// missing from the replicas directory.
+    pub fn download_replica_on_switch(&self) -> bool {
+        self.download_replica_on_switch
+    }
+
+    /// Have [`HAConnection::switch_catalog`] download the replica of a
+    /// catalog missing from the replicas directory and attach it before
+    /// returning, so reads of the new catalog can use it right away;
+    /// [`HAConnection::set_catalog`] downloads it in the background.
+    pub fn set_download_replica_on_switch(&mut self, download: bool) -> &mut Self {
+        self.reset_replicas();
+        self.download_replica_on_switch = download;
+        self
+    }
+
+    /// Check if downloaded replicas are checked with `PRAGMA
+    /// integrity_check` before they are used.
+    pub fn check_replica_integrity(&self) -> bool {
+        self.check_replica_integrity
+    }
+
+    /// Run `PRAGMA integrity_check` on replicas downloaded by
+    /// [`download_replicas`](Self::download_replicas) or on demand before
+    /// using them, deleting those SQLite finds corrupt.
+    pub fn set_check_replica_integrity(&mut self, check: bool) -> &mut Self {
+        self.reset_replicas();
+        self.check_replica_integrity = check;
+        self
+    }
+
+    /// Get how often replicas whose WAL grew too large are compacted.
+    pub fn replica_maintenance_interval(&self) -> Option<Duration> {
+        self.replica_maintenance_interval
+    }
+
+    /// Compact the replicas whose WAL outgrew
+    /// [`max_replica_wal_size`](Self::max_replica_wal_size) every
+    /// `interval` in the background; never when `None`.
+    pub fn set_replica_maintenance_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.reset_replicas();
+        self.replica_maintenance_interval = interval;
+        self
+    }
+
+    /// Get the size in bytes a replica's WAL may reach before it is
+    /// compacted.
+    pub fn max_replica_wal_size(&self) -> Option<u64> {
+        self.max_replica_wal_size
+    }
+
+    /// Set the size in bytes a replica's WAL may reach before it is
+    /// compacted.
+    pub fn set_max_replica_wal_size(&mut self, size: u64) -> &mut Self {
+        self.reset_replicas();
+        self.max_replica_wal_size = Some(size);
+        self
+    }
+
+    /// Check if replicas are compacted by downloading a new snapshot into
+    /// them.
+    pub fn resnapshot_replicas(&self) -> bool {
+        self.resnapshot_replicas
+    }
+
+    /// Compact a replica by downloading a new snapshot into it and catching
+    /// it up with replication before truncating its WAL, which also
+    /// reclaims the space of deleted rows. Needs the `nats` feature and
+    /// replica downloads.
+    pub fn set_resnapshot_replicas(&mut self, resnapshot: bool) -> &mut Self {
+        self.reset_replicas();
+        self.resnapshot_replicas = resnapshot;
+        self
+    }
+
+    /// Get the bytes the replica files may take up before the least
+    /// recently read are evicted.
+    pub fn max_replicas_disk_usage(&self) -> Option<u64> {
+        self.max_replicas_disk_usage
+    }
+
+    /// Evict the least recently read replicas that are not pinned once the
+    /// replica files take up more than `bytes`; unlimited when `None`.
+    /// Evicted replicas are downloaded again when read, so this needs
+    /// [`set_download_missing_replicas`](Self::set_download_missing_replicas).
+    pub fn set_max_replicas_disk_usage(&mut self, bytes: Option<u64>) -> &mut Self {
+        self.reset_replicas();
+        self.max_replicas_disk_usage = bytes;
+        self
+    }
+
+    /// Get the replicas never evicted.
+    pub fn pinned_replicas(&self) -> &[String] {
+        &self.pinned_replicas
+    }
+
+    /// Never evict the replicas of `db_names`.
+    pub fn set_pinned_replicas<I>(&mut self, db_names: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.reset_replicas();
+        self.pinned_replicas = db_names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Check if the replicas directory is watched for files added or
+    /// removed at runtime.
+    #[cfg(feature = "watch")]
+    pub fn watch_replicas_dir(&self) -> bool {
+        self.watch_replicas_dir
+    }
+
+    /// Load the replica files dropped into the replicas directory while
+    /// running, and detach the replicas whose file is removed, instead of
+    /// only reading the directory when the replicas are first loaded.
+    #[cfg(feature = "watch")]
+    pub fn set_watch_replicas_dir(&mut self, watch: bool) -> &mut Self {
+        self.reset_replicas();
+        self.watch_replicas_dir = watch;
+        self
+    }
+
+    /// Get the provider of the keys encrypting the replica files.
+    #[cfg(feature = "sqlcipher")]
+    pub fn replica_key_provider(&self) -> Option<&Arc<dyn ReplicaKeyProvider>> {
+        self.replica_key_provider.as_ref()
+    }
+
+    /// Encrypt the replica files with SQLCipher, using the key `provider`
+    /// returns for each database. Plaintext replicas are encrypted as they
+    /// are loaded; see [`encryption`](crate::encryption).
+    #[cfg(feature = "sqlcipher")]
+    pub fn set_replica_key_provider(&mut self, provider: Arc<dyn ReplicaKeyProvider>) -> &mut Self {
+        self.reset_replicas();
+        self.replica_key_provider = Some(provider);
+        self
+    }
+
+    /// Check if the replicas directory is shared with other processes.
+    pub fn shared_replicas_dir(&self) -> bool {
+        self.shared_replicas_dir
+    }
+
+    /// Share the replicas directory with other processes on the host: the
+    /// process holding its lock file replicates and downloads, and the
+    /// others read the replicas read-only, taking over when it exits.
+    pub fn set_shared_replicas_dir(&mut self, shared: bool) -> &mut Self {
+        self.reset_replicas();
+        self.shared_replicas_dir = shared;
+        self
+    }
+
+    /// Get the audit hook.
+    pub fn auditor(&self) -> Option<&Auditor> {
+        self.auditor.as_ref()
+    }
+
+    /// Set the audit hook.
+    pub fn set_auditor(&mut self, auditor: Auditor) -> &mut Self {
+        self.pool.clear();
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// Get the hooks called around every statement.
+    pub fn query_hooks(&self) -> &[Arc<dyn QueryHook>] {
+        &self.query_hooks
+    }
+
+    /// Add a hook called around every statement, after those already
+    /// added.
+    pub fn add_query_hook(&mut self, hook: impl QueryHook + 'static) -> &mut Self {
+        self.pool.clear();
+        self.query_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Get the slow query log.
+    pub fn slow_query_log(&self) -> Option<&SlowQueryLog> {
+        self.slow_query_log.as_ref()
+    }
+
+    /// Set the slow query log.
+    pub fn set_slow_query_log(&mut self, slow_query_log: SlowQueryLog) -> &mut Self {
+        self.pool.clear();
+        self.slow_query_log = Some(slow_query_log);
+        self
+    }
+
+    /// Get the read cache shared by all connections.
+    pub fn query_cache(&self) -> Option<&Arc<QueryCache>> {
+        self.query_cache.as_ref()
+    }
+
+    /// Set the read cache shared by all connections.
+    pub fn set_query_cache(&mut self, cache: Arc<QueryCache>) -> &mut Self {
+        self.pool.clear();
+        #[cfg(feature = "nats")]
+        if let Some(manager) = self.replicas_manager.get() {
+            manager.set_query_cache(cache.clone());
+        }
+        self.query_cache = Some(cache);
+        self
+    }
+
+    /// Get the coalescer of identical reads in flight shared by all
+    /// connections.
+    pub fn query_coalescer(&self) -> Option<&Arc<QueryCoalescer>> {
+        self.query_coalescer.as_ref()
+    }
+
+    /// Set the coalescer of identical reads in flight shared by all
+    /// connections.
+    pub fn set_query_coalescer(&mut self, coalescer: Arc<QueryCoalescer>) -> &mut Self {
+        self.pool.clear();
+        self.query_coalescer = Some(coalescer);
+        self
+    }
+
+    /// Get the traffic recorder.
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Set the traffic recorder.
+    pub fn set_recorder(&mut self, recorder: Recorder) -> &mut Self {
+        self.pool.clear();
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Get the replay serving recorded responses.
+    pub fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+
+    /// Set the replay serving recorded responses.
+    pub fn set_replay(&mut self, replay: Replay) -> &mut Self {
+        self.pool.clear();
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Check if transactions run over a single `Query` stream.
+    pub fn sticky_transactions(&self) -> bool {
+        self.sticky_transactions
+    }
+
+    /// Run each transaction over a single `Query` stream, so that a load
+    /// balancer in front of several servers cannot split it across sessions.
+    pub fn set_sticky_transactions(&mut self, sticky: bool) -> &mut Self {
+        self.pool.clear();
+        self.sticky_transactions = sticky;
+        self
+    }
+
+    /// Get the HTTP/2 keepalive settings.
+    pub fn keepalive(&self) -> &KeepaliveOptions {
+        &self.keepalive
+    }
+
+    /// Set the HTTP/2 keepalive settings.
+    pub fn set_keepalive(&mut self, keepalive: KeepaliveOptions) -> &mut Self {
+        self.pool.clear();
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Get how `Value::Timestamp` parameters are stored.
+    pub fn timestamp_storage(&self) -> TimestampStorage {
+        self.timestamp_storage
+    }
+
+    /// Store `Value::Timestamp` parameters as `storage`, on the server and on
+    /// embedded replicas.
+    pub fn set_timestamp_storage(&mut self, storage: TimestampStorage) -> &mut Self {
+        self.pool.clear();
+        self.timestamp_storage = storage;
+        self
+    }
+
+    /// Get how long a read waits for a stale embedded replica to catch up.
+    pub fn replication_wait(&self) -> Option<Duration> {
+        self.replication_wait
+    }
+
+    /// Let reads wait up to `wait` for a stale embedded replica to catch up
+    /// with the connection's writes, instead of going to the HA server.
+    pub fn set_replication_wait(&mut self, wait: Option<Duration>) -> &mut Self {
+        self.pool.clear();
+        self.replication_wait = wait;
+        self
+    }
+
+    /// Get how long a read waits for an answer before it is hedged.
+    pub fn hedge_delay(&self) -> Option<Duration> {
+        self.hedge_delay
+    }
+
+    /// Hedge reads not answered after `delay`: send them to the HA server
+    /// again, or besides the embedded replica serving them, and take the
+    /// first successful answer. Writes and reads inside a transaction are
+    /// never hedged.
+    pub fn set_hedge_delay(&mut self, delay: Option<Duration>) -> &mut Self {
+        self.pool.clear();
+        self.hedge_delay = delay;
+        self
+    }
+
+    /// Get the consistency of reads that do not set their own.
+    pub fn consistency(&self) -> Consistency {
+        self.consistency
+    }
+
+    /// Set the consistency of reads that do not set their own.
+    pub fn set_consistency(&mut self, consistency: Consistency) -> &mut Self {
+        self.pool.clear();
+        self.consistency = consistency;
+        self
+    }
+
+    /// Get the time for which a stale replica may have been missing
+    /// transactions and still serve reads.
+    pub fn max_replica_lag(&self) -> Duration {
+        self.max_replica_lag
+    }
+
+    /// Let a stale embedded replica serve `BoundedStaleness` reads if it has
+    /// been missing transactions for less than `lag`.
+    pub fn set_max_replica_lag(&mut self, lag: Duration) -> &mut Self {
+        self.pool.clear();
+        self.max_replica_lag = lag;
+        self
+    }
+
+    /// Get how many transactions a stale replica may be behind and still
+    /// serve reads.
+    pub fn max_replica_lag_txseq(&self) -> i64 {
+        self.max_replica_lag_txseq
+    }
+
+    /// Let a stale embedded replica serve `BoundedStaleness` reads if it is
+    /// at most `lag` transactions behind the last seen txseq.
+    pub fn set_max_replica_lag_txseq(&mut self, lag: i64) -> &mut Self {
+        self.pool.clear();
+        self.max_replica_lag_txseq = lag;
+        self
+    }
+
+    /// Get the number of prepared statements cached per connection for
+    /// embedded replica reads.
+    pub fn replica_statement_cache_capacity(&self) -> Option<usize> {
+        self.replica_statement_cache_capacity
+    }
+
+    /// Set the number of prepared statements cached per connection for
+    /// embedded replica reads; 0 disables the cache.
+    pub fn set_replica_statement_cache_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.pool.clear();
+        self.replica_statement_cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Get the connection pool options.
+    pub fn pool_options(&self) -> &PoolOptions {
+        self.pool.options()
+    }
+
+    /// Replace the connection pool; connections in use are closed when returned.
+    pub fn set_pool_options(&mut self, options: PoolOptions) -> &mut Self {
+        self.pool.clear();
+        self.pool = Arc::new(Pool::new(options));
+        self
+    }
+
+    /// Close pooled connections and reload the replicas on next use.
+    fn reset_replicas(&mut self) {
+        self.pool.clear();
+        #[cfg(feature = "embedded-replicas")]
+        self.replicas_manager.take();
+    }
 }
 
 impl Default for HADataSource {
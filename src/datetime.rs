@@ -0,0 +1,382 @@
+//! Date and time values.
+//!
+//! [`Value::Timestamp`] parameters are stored as [`TimestampStorage`] says,
+//! on the server and on embedded replicas alike. Reading a timestamp accepts
+//! each stored form: a protobuf timestamp, whole seconds since the Unix epoch
+//! or ISO-8601 text.
+//!
+//! With the `chrono` feature, `DateTime<Utc>`, `NaiveDate` and `NaiveTime`
+//! convert to and from [`Value`]; with the `time` feature, `OffsetDateTime`,
+//! `Date` and `Time` do. Dates and times of day have no epoch form, so they
+//! are always sent as `YYYY-MM-DD` and `HH:MM:SS.SSS` text, as returned by
+//! SQLite's `date()` and `strftime('%H:%M:%f')`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "chrono")]
+//! # mod example {
+//! use chrono::{DateTime, Utc};
+//! use litesql_ha::{HAConnection, Value};
+//!
+//! // With the data source created with `TimestampStorage::Iso8601`.
+//! async fn example(conn: &HAConnection, at: DateTime<Utc>) -> litesql_ha::Result<()> {
+//!     conn.execute("INSERT INTO events (at) VALUES (?)", &[Value::from(at)])
+//!         .await?;
+//!     let result = conn.query("SELECT at FROM events", &[]).await?;
+//!     for row in &result {
+//!         let at: Option<DateTime<Utc>> = row.try_get("at")?;
+//!         println!("{:?}", at);
+//!     }
+//!     Ok(())
+//! }
+//! # }
+//! ```
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+use crate::error::{Error, Result};
+#[cfg(any(feature = "chrono", feature = "time"))]
+use crate::row::{try_from_value, FromValue};
+use crate::value::Value;
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+/// Hour, minute, second and nanosecond.
+type TimeOfDay = (u32, u32, u32, u32);
+
+/// How [`Value::Timestamp`] parameters are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampStorage {
+    /// Sent to the server as a protobuf `Timestamp`, stored in the server's
+    /// own format; embedded replicas bind whole seconds since the Unix epoch
+    #[default]
+    Native,
+    /// Whole seconds since the Unix epoch, as an INTEGER
+    UnixEpoch,
+    /// ISO-8601 text in UTC with millisecond precision, such as
+    /// `2024-05-01T12:30:00.250Z`, which sorts in time order
+    Iso8601,
+}
+
+impl TimestampStorage {
+    /// Convert a parameter to the form it is stored in.
+    pub(crate) fn apply(self, value: &Value) -> Cow<'_, Value> {
+        match (self, value) {
+            (TimestampStorage::UnixEpoch, Value::Timestamp(v)) => {
+                Cow::Owned(Value::Int64(unix_seconds(v)))
+            }
+            (TimestampStorage::Iso8601, Value::Timestamp(v)) => {
+                Cow::Owned(Value::String(format_iso8601(v)))
+            }
+            _ => Cow::Borrowed(value),
+        }
+    }
+}
+
+/// Whole seconds since the Unix epoch, negative before it.
+pub(crate) fn unix_seconds(time: &SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+/// Seconds since the Unix epoch, rounded down, and the nanoseconds past them.
+pub(crate) fn unix_parts(time: &SystemTime) -> (i64, u32) {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(before) => {
+            let before = before.duration();
+            let seconds = -(before.as_secs() as i64);
+            match before.subsec_nanos() {
+                0 => (seconds, 0),
+                nanos => (seconds - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// The time `seconds` and `nanos` after the Unix epoch, if representable.
+pub(crate) fn from_unix(seconds: i64, nanos: u32) -> Option<SystemTime> {
+    let epoch = SystemTime::UNIX_EPOCH;
+    let whole = if seconds >= 0 {
+        epoch.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        epoch.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    };
+    whole?.checked_add(Duration::from_nanos(nanos as u64))
+}
+
+/// Format a time as `YYYY-MM-DDTHH:MM:SS.SSSZ`.
+pub(crate) fn format_iso8601(time: &SystemTime) -> String {
+    let (seconds, nanos) = unix_parts(time);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let seconds = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        nanos / 1_000_000
+    )
+}
+
+/// Parse `YYYY-MM-DD`, optionally followed by `T` or a space and a time of
+/// day, then `Z`, `±HH:MM` or nothing for UTC.
+pub(crate) fn parse_iso8601(text: &str) -> Option<SystemTime> {
+    let ((year, month, day), rest) = parse_date(text.trim())?;
+    let ((hour, minute, second, nanos), rest) = match rest.strip_prefix(['T', 't', ' ']) {
+        Some(rest) => parse_time(rest)?,
+        None => ((0, 0, 0, 0), rest),
+    };
+    let offset = parse_offset(rest)?;
+    let seconds = days_from_civil(year, month, day) * 86_400
+        + (hour * 3600 + minute * 60 + second) as i64
+        - offset;
+    from_unix(seconds, nanos)
+}
+
+/// Parse `YYYY-MM-DD` at the start of `text`.
+fn parse_date(text: &str) -> Option<((i64, u32, u32), &str)> {
+    let (year, rest) = digits(text, 4)?;
+    let (month, rest) = digits(rest.strip_prefix('-')?, 2)?;
+    let (day, rest) = digits(rest.strip_prefix('-')?, 2)?;
+    let date = (year as i64, month, day);
+    // Rejects days past the end of the month.
+    if !(1..=12).contains(&month) || civil_from_days(days_from_civil(date.0, month, day)) != date {
+        return None;
+    }
+    Some((date, rest))
+}
+
+/// Parse `HH:MM[:SS[.fff]]` at the start of `text`.
+fn parse_time(text: &str) -> Option<(TimeOfDay, &str)> {
+    let (hour, rest) = digits(text, 2)?;
+    let (minute, mut rest) = digits(rest.strip_prefix(':')?, 2)?;
+    let mut second = 0;
+    let mut nanos = 0;
+    if let Some(after) = rest.strip_prefix(':') {
+        (second, rest) = digits(after, 2)?;
+        if let Some(after) = rest.strip_prefix('.') {
+            let len = after.bytes().take_while(u8::is_ascii_digit).count();
+            if len == 0 {
+                return None;
+            }
+            // Digits past nanoseconds are dropped.
+            let (fraction, _) = digits(after, len.min(9))?;
+            nanos = fraction * 10u32.pow(9 - len.min(9) as u32);
+            rest = &after[len..];
+        }
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(((hour, minute, second, nanos), rest))
+}
+
+/// Parse a whole `Z`, `±HH:MM`, `±HHMM` or empty string as seconds east of
+/// UTC.
+fn parse_offset(text: &str) -> Option<i64> {
+    let sign = match text.as_bytes().first() {
+        None => return Some(0),
+        Some(b'Z' | b'z') if text.len() == 1 => return Some(0),
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return None,
+    };
+    let (hours, rest) = digits(&text[1..], 2)?;
+    let (minutes, rest) = digits(rest.strip_prefix(':').unwrap_or(rest), 2)?;
+    if !rest.is_empty() || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60) as i64)
+}
+
+/// Parse exactly `count` ASCII digits at the start of `text`.
+fn digits(text: &str, count: usize) -> Option<(u32, &str)> {
+    let head = text.get(..count)?;
+    if !head.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((head.parse().ok()?, &text[count..]))
+}
+
+/// Days since the Unix epoch of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Count years from March, so the leap day ends the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year as i64;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date of a count of days since the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// The time of day of a text value holding only a time, such as `12:30:00`.
+#[cfg(any(feature = "chrono", feature = "time"))]
+fn time_of_day(value: &Value) -> Option<TimeOfDay> {
+    match value {
+        Value::String(text) => match parse_time(text.trim())? {
+            (time, "") => Some(time),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+fn out_of_range(value: &Value) -> Error {
+    Error::TypeConversion(format!("time out of range: {:?}", value))
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Value {
+    fn from(v: chrono::DateTime<chrono::Utc>) -> Self {
+        Value::Timestamp(v.into())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveDate> for Value {
+    fn from(v: chrono::NaiveDate) -> Self {
+        use chrono::Datelike;
+        Value::String(format!("{:04}-{:02}-{:02}", v.year(), v.month(), v.day()))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveTime> for Value {
+    fn from(v: chrono::NaiveTime) -> Self {
+        use chrono::Timelike;
+        // A leap second carries more than a second of nanoseconds.
+        let millis = (v.nanosecond() / 1_000_000).min(999);
+        Value::String(format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            v.hour(),
+            v.minute(),
+            v.second(),
+            millis
+        ))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::DateTime<chrono::Utc> {
+    fn from_value(value: &Value) -> Result<Self> {
+        let (seconds, nanos) = unix_parts(&SystemTime::from_value(value)?);
+        chrono::DateTime::from_timestamp(seconds, nanos).ok_or_else(|| out_of_range(value))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::NaiveDate {
+    fn from_value(value: &Value) -> Result<Self> {
+        chrono::DateTime::<chrono::Utc>::from_value(value).map(|v| v.date_naive())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::NaiveTime {
+    fn from_value(value: &Value) -> Result<Self> {
+        match time_of_day(value) {
+            Some((hour, minute, second, nanos)) => {
+                chrono::NaiveTime::from_hms_nano_opt(hour, minute, second, nanos)
+                    .ok_or_else(|| out_of_range(value))
+            }
+            None => chrono::DateTime::<chrono::Utc>::from_value(value).map(|v| v.time()),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+try_from_value!(
+    chrono::DateTime<chrono::Utc>,
+    chrono::NaiveDate,
+    chrono::NaiveTime
+);
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Value {
+    fn from(v: time::OffsetDateTime) -> Self {
+        Value::Timestamp(v.into())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::Date> for Value {
+    fn from(v: time::Date) -> Self {
+        Value::String(format!(
+            "{:04}-{:02}-{:02}",
+            v.year(),
+            v.month() as u8,
+            v.day()
+        ))
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::Time> for Value {
+    fn from(v: time::Time) -> Self {
+        Value::String(format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            v.hour(),
+            v.minute(),
+            v.second(),
+            v.millisecond()
+        ))
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromValue for time::OffsetDateTime {
+    fn from_value(value: &Value) -> Result<Self> {
+        let (seconds, nanos) = unix_parts(&SystemTime::from_value(value)?);
+        let nanos = seconds as i128 * 1_000_000_000 + nanos as i128;
+        time::OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| out_of_range(value))
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromValue for time::Date {
+    fn from_value(value: &Value) -> Result<Self> {
+        time::OffsetDateTime::from_value(value).map(|v| v.date())
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromValue for time::Time {
+    fn from_value(value: &Value) -> Result<Self> {
+        match time_of_day(value) {
+            Some((hour, minute, second, nanos)) => {
+                time::Time::from_hms_nano(hour as u8, minute as u8, second as u8, nanos)
+                    .map_err(|_| out_of_range(value))
+            }
+            None => time::OffsetDateTime::from_value(value).map(|v| v.time()),
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+try_from_value!(time::OffsetDateTime, time::Date, time::Time);
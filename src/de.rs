@@ -0,0 +1,448 @@
+//! Deserializing result rows with serde.
+//!
+//! A [`Row`] is a `serde` deserializer: a struct or map takes the columns
+//! by name, a tuple or sequence takes them in order, and a single-column row
+//! also deserializes as its one value. Values are converted like
+//! [`FromValue`](crate::FromValue) does, so integers widen, `0` and `1` read
+//! as booleans and NULL reads as `None`.
+//!
+//! Text holding a JSON object or array is parsed as JSON when it is
+//! deserialized into a struct, map or sequence, or into a type that takes
+//! any value such as `serde_json::Value`. Timestamps deserialize as whole
+//! seconds since the Unix epoch.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::HAConnection;
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct Event {
+//!     id: i64,
+//!     kind: String,
+//!     note: Option<String>,
+//!     payload: serde_json::Value,
+//! }
+//!
+//! async fn example(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let events: Vec<Event> = conn.query_de("SELECT * FROM events", &[]).await?;
+//!     let (count,): (i64,) = conn.query_de("SELECT COUNT(*) FROM events", &[]).await?[0];
+//!     println!("{} {:?}", count, events);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::datetime::unix_seconds;
+use crate::error::{Error, Result};
+use crate::row::{FromValue, Row};
+use crate::value::Value;
+use serde::de::{
+    self, value::StrDeserializer, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess,
+    SeqAccess, Visitor,
+};
+use std::fmt::Display;
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::TypeConversion(msg.to_string())
+    }
+}
+
+/// Deserialize a row into `T`.
+pub fn from_row<'a, T: de::Deserialize<'a>>(row: Row<'a>) -> Result<T> {
+    T::deserialize(row)
+}
+
+impl<'a> Row<'a> {
+    /// Deserialize the row into `T` with serde.
+    pub fn deserialize<T: de::Deserialize<'a>>(self) -> Result<T> {
+        from_row(self)
+    }
+
+    fn single(self) -> Result<ValueDeserializer<'a>> {
+        match self.values() {
+            [value] => Ok(ValueDeserializer(value)),
+            values => Err(Error::TypeConversion(format!(
+                "expected one column, got {}",
+                values.len()
+            ))),
+        }
+    }
+}
+
+/// Forward to the value of a single-column row.
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+                self.single()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'a> Deserializer<'a> for Row<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Columns {
+            columns: self.columns().iter(),
+            values: self.values().iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Values(self.values().iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'a>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'a>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_option deserialize_unit deserialize_identifier
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single()?.deserialize_unit(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+/// The columns of a row as a map from name to value.
+struct Columns<'a> {
+    columns: std::slice::Iter<'a, String>,
+    values: std::slice::Iter<'a, Value>,
+    value: Option<(&'a str, &'a Value)>,
+}
+
+impl<'a> MapAccess<'a> for Columns<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'a>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let (Some(column), Some(value)) = (self.columns.next(), self.values.next()) else {
+            return Ok(None);
+        };
+        self.value = Some((column.as_str(), value));
+        let key: StrDeserializer<'_, Error> = column.as_str().into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'a>>(&mut self, seed: V) -> Result<V::Value> {
+        let (column, value) = self
+            .value
+            .take()
+            .ok_or_else(|| Error::TypeConversion("value requested before key".to_string()))?;
+        seed.deserialize(ValueDeserializer(value))
+            .map_err(|e| match e {
+                Error::TypeConversion(message) => {
+                    Error::TypeConversion(format!("column {}: {}", column, message))
+                }
+                e => e,
+            })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+/// The values of a row in order.
+struct Values<'a>(std::slice::Iter<'a, Value>);
+
+impl<'a> SeqAccess<'a> for Values<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'a>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        self.0
+            .next()
+            .map(|value| seed.deserialize(ValueDeserializer(value)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+/// Deserializes a single value.
+struct ValueDeserializer<'a>(&'a Value);
+
+impl ValueDeserializer<'_> {
+    fn convert<T: FromValue>(&self) -> Result<T> {
+        T::from_value(self.0)
+    }
+
+    /// The value as JSON, if it is text holding an object or array.
+    fn json(&self) -> Option<serde_json::Value> {
+        match self.0 {
+            Value::String(text) if text.trim_start().starts_with(['{', '[']) => {
+                serde_json::from_str(text).ok()
+            }
+            #[cfg(feature = "json")]
+            Value::Json(json) => Some(json.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn json_error(e: serde_json::Error) -> Error {
+    Error::TypeConversion(e.to_string())
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident $ty:ty,)*) => {
+        $(
+            fn $method<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+                let v = self.convert::<i64>()?;
+                let v = <$ty>::try_from(v).map_err(|_| {
+                    Error::TypeConversion(format!("{} out of range for {}", v, stringify!($ty)))
+                })?;
+                visitor.$visit(v)
+            }
+        )*
+    };
+}
+
+impl<'a> Deserializer<'a> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        if let Some(json) = self.json() {
+            return json.deserialize_any(visitor).map_err(json_error);
+        }
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(*v),
+            Value::Int32(v) => visitor.visit_i32(*v),
+            Value::Int64(v) => visitor.visit_i64(*v),
+            Value::Float(v) => visitor.visit_f32(*v),
+            Value::Double(v) => visitor.visit_f64(*v),
+            Value::String(v) => visitor.visit_borrowed_str(v),
+            Value::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            Value::Timestamp(_) => self.deserialize_i64(visitor),
+            #[cfg(feature = "json")]
+            Value::Json(v) => v.deserialize_any(visitor).map_err(json_error),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(v) => visitor.visit_string(v.to_string()),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bool(self.convert()?)
+    }
+
+    deserialize_integer! {
+        deserialize_i8 => visit_i8 i8,
+        deserialize_i16 => visit_i16 i16,
+        deserialize_i32 => visit_i32 i32,
+        deserialize_u8 => visit_u8 u8,
+        deserialize_u16 => visit_u16 u16,
+        deserialize_u32 => visit_u32 u32,
+        deserialize_u64 => visit_u64 u64,
+    }
+
+    fn deserialize_i64<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Timestamp(v) => visitor.visit_i64(unix_seconds(v)),
+            _ => visitor.visit_i64(self.convert()?),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f32(self.convert()?)
+    }
+
+    fn deserialize_f64<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f64(self.convert()?)
+    }
+
+    fn deserialize_char<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::String(v) => visitor.visit_borrowed_str(v),
+            other => Err(Error::TypeConversion(format!(
+                "expected text, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            Value::String(v) => visitor.visit_borrowed_bytes(v.as_bytes()),
+            other => Err(Error::TypeConversion(format!(
+                "expected blob, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            other => Err(Error::TypeConversion(format!(
+                "expected NULL, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.json() {
+            Some(json) => json.deserialize_seq(visitor).map_err(json_error),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'a>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'a>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        match self.json() {
+            Some(json) => json.deserialize_map(visitor).map_err(json_error),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'a>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.json() {
+            Some(json) => json
+                .deserialize_struct(name, fields, visitor)
+                .map_err(json_error),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'a>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        if let Some(json) = self.json() {
+            return json
+                .deserialize_enum(name, variants, visitor)
+                .map_err(json_error);
+        }
+        match self.0 {
+            Value::String(v) => {
+                let variant: StrDeserializer<'_, Error> = v.as_str().into_deserializer();
+                variant.deserialize_enum(name, variants, visitor)
+            }
+            other => Err(Error::TypeConversion(format!(
+                "expected text, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'a>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
@@ -0,0 +1,47 @@
+//! Request deadlines.
+//!
+//! A service that must answer its own caller by a deadline can run its work
+//! inside [`with_deadline`]. Every call the client makes within it is sent
+//! with a gRPC deadline no later than the scope's, so the server abandons
+//! work that nobody is waiting for, and a call made after the deadline fails
+//! with [`Error::Timeout`](crate::Error::Timeout) without being sent. Scopes
+//! nest, and the earliest deadline wins.
+//!
+//! The deadline belongs to the task running the scope; tasks it spawns do not
+//! inherit it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{deadline, HAConnection};
+//! use std::time::{Duration, Instant};
+//!
+//! async fn handle(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let deadline = Instant::now() + Duration::from_millis(800);
+//!     deadline::with_deadline(deadline, async {
+//!         let user = conn.query("SELECT * FROM users WHERE id = 1", &[]).await?;
+//!         let orders = conn.query("SELECT * FROM orders WHERE user_id = 1", &[]).await?;
+//!         println!("{:?} {:?}", user.rows, orders.rows);
+//!         Ok(())
+//!     })
+//!     .await
+//! }
+//! ```
+
+use crate::runtime::{self, Instant};
+use std::future::Future;
+
+runtime::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Run `future` with `deadline` applied to every call it makes.
+pub async fn with_deadline<F: Future>(deadline: Instant, future: F) -> F::Output {
+    let deadline = current().map_or(deadline, |outer| outer.min(deadline));
+    DEADLINE.scope(deadline, future).await
+}
+
+/// Get the deadline of the enclosing [`with_deadline`] scope.
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
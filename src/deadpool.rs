@@ -0,0 +1,83 @@
+//! deadpool connection manager.
+//!
+//! Enabled by the `deadpool` feature. [`HAConnectionManager`] opens
+//! [`HAConnection`]s for a [`deadpool`](::deadpool) pool and recycles them
+//! the way [`HADataSource`](crate::HADataSource) recycles its own: a
+//! connection that was closed, or returned with a transaction open or in
+//! read-only mode, is replaced by a new one; any other is switched back to
+//! its first catalog and its audit context is cleared.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::deadpool::{HAConnectionManager, Pool};
+//! use litesql_ha::HAConnectionOptions;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let manager = HAConnectionManager::new(HAConnectionOptions {
+//!     url: "litesql://localhost:8080/app.db".to_string(),
+//!     timeout: 30,
+//!     ..Default::default()
+//! });
+//! let pool = Pool::builder(manager).max_size(16).build()?;
+//!
+//! let conn = pool.get().await?;
+//! let rows = conn.query("SELECT name FROM users", &[]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::connection::{HAConnection, HAConnectionOptions};
+use crate::error::Error;
+use crate::pool;
+use ::deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+use std::sync::OnceLock;
+
+/// deadpool pool of [`HAConnection`]s.
+pub type Pool = managed::Pool<HAConnectionManager>;
+
+/// deadpool manager opening [`HAConnection`]s.
+#[derive(Debug)]
+pub struct HAConnectionManager {
+    options: HAConnectionOptions,
+    /// Catalog of the first connection opened, restored on recycling
+    catalog: OnceLock<String>,
+}
+
+impl HAConnectionManager {
+    /// Open connections with `options`.
+    pub fn new(options: HAConnectionOptions) -> Self {
+        Self {
+            options,
+            catalog: OnceLock::new(),
+        }
+    }
+
+    /// Get the options connections are opened with.
+    pub fn options(&self) -> &HAConnectionOptions {
+        &self.options
+    }
+}
+
+impl managed::Manager for HAConnectionManager {
+    type Type = HAConnection;
+    type Error = Error;
+
+    async fn create(&self) -> Result<HAConnection, Error> {
+        let conn = HAConnection::new(self.options.clone()).await?;
+        self.catalog.get_or_init(|| conn.catalog());
+        Ok(conn)
+    }
+
+    async fn recycle(&self, conn: &mut HAConnection, _: &Metrics) -> RecycleResult<Error> {
+        if !pool::is_recyclable(conn) {
+            return Err(RecycleError::message(
+                "Connection closed, in a transaction or read-only",
+            ));
+        }
+        if let Some(catalog) = self.catalog.get() {
+            pool::reset(conn, catalog)?;
+        }
+        Ok(())
+    }
+}
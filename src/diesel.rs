@@ -0,0 +1,783 @@
+//! Diesel backend and connection adapter.
+//!
+//! Enabled by the `diesel` feature. [`HADieselConnection`] implements diesel's
+//! `Connection` on top of the blocking client, so the usual routing applies:
+//! reads go to an up-to-date embedded replica when one is loaded, writes and
+//! reads inside a transaction go to the HA server. Queries are generated in
+//! the SQLite dialect for the [`HABackend`] backend.
+//!
+//! [`HAConnectionManager`] is an r2d2 connection manager for pooling.
+//!
+//! Limitations: `RETURNING` and `ON CONFLICT` clauses are not available, and
+//! batch inserts must be issued one row at a time.
+//!
+//! The blocking client drives its own runtime and cannot be called from an
+//! async task: async code uses the `diesel-async` connection of the
+//! `diesel_async` module instead, enabled by the `diesel-async` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use diesel::prelude::*;
+//! use diesel::sql_types::Text;
+//! use litesql_ha::diesel::HADieselConnection;
+//!
+//! #[derive(QueryableByName)]
+//! struct User {
+//!     #[diesel(sql_type = Text)]
+//!     name: String,
+//! }
+//!
+//! let mut conn = HADieselConnection::establish("litesql://localhost:8080").unwrap();
+//! let users: Vec<User> = diesel::sql_query("SELECT name FROM users")
+//!     .load(&mut conn)
+//!     .unwrap();
+//! ```
+
+use crate::blocking;
+use crate::client::ExecutionResult;
+use crate::datasource::HADataSourceOptions;
+use crate::error::Error;
+use crate::value::Value;
+use ::diesel::backend::{
+    sql_dialect, Backend, DieselReserveSpecialization, SqlDialect, TrustedBackend,
+};
+use ::diesel::connection::{
+    AnsiTransactionManager, CacheSize, Connection, ConnectionSealed, DefaultLoadingMode,
+    Instrumentation, LoadConnection, SimpleConnection, TransactionManager,
+};
+use ::diesel::deserialize::{self, FromSql, FromSqlRef};
+use ::diesel::expression::{AppearsOnTable, Expression, QueryMetadata};
+use ::diesel::insertable::{ColumnInsertValue, DefaultableColumnInsertValue, InsertValues};
+use ::diesel::query_builder::bind_collector::BindCollector;
+use ::diesel::query_builder::{
+    AstPass, BoxedLimitOffsetClause, IntoBoxedClause, LimitClause, LimitOffsetClause, NoFromClause,
+    NoLimitClause, NoOffsetClause, OffsetClause, Query, QueryBuilder, QueryFragment, QueryId,
+};
+use ::diesel::r2d2::{ConnectionManager, R2D2Connection};
+use ::diesel::result::{
+    ConnectionError, ConnectionResult, DatabaseErrorInformation, DatabaseErrorKind,
+    Error as DieselError, QueryResult,
+};
+use ::diesel::row::{Field, PartialRow, Row, RowIndex, RowSealed};
+use ::diesel::serialize::{self, IsNull, Output, ToSql};
+use ::diesel::sql_types::{self, HasSqlType, TypeMetadata};
+use ::diesel::Column;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// The LiteSQL HA diesel backend.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
+pub struct HABackend;
+
+/// Type metadata attached to bind parameters.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum HAType {
+    /// 16-bit integer
+    SmallInt,
+    /// 32-bit integer
+    Integer,
+    /// 64-bit integer
+    BigInt,
+    /// 32-bit float
+    Float,
+    /// 64-bit float
+    Double,
+    /// Text
+    Text,
+    /// Binary data
+    Binary,
+    /// Boolean
+    Bool,
+    /// Date
+    Date,
+    /// Time of day
+    Time,
+    /// Timestamp
+    Timestamp,
+}
+
+impl Backend for HABackend {
+    type QueryBuilder = HAQueryBuilder;
+    type RawValue<'a> = &'a Value;
+    type BindCollector<'a> = HABindCollector;
+}
+
+impl TypeMetadata for HABackend {
+    type TypeMetadata = HAType;
+    type MetadataLookup = ();
+}
+
+impl SqlDialect for HABackend {
+    type ReturningClause = sql_dialect::returning_clause::DoesNotSupportReturningClause;
+    type OnConflictClause = sql_dialect::on_conflict_clause::DoesNotSupportOnConflictClause;
+    type InsertWithDefaultKeyword =
+        sql_dialect::default_keyword_for_insert::DoesNotSupportDefaultKeyword;
+    type BatchInsertSupport = sql_dialect::batch_insert_support::DoesNotSupportBatchInsert;
+    type ConcatClause = sql_dialect::concat_clause::ConcatWithPipesClause;
+    type DefaultValueClauseForInsert = sql_dialect::default_value_clause::AnsiDefaultValueClause;
+    type EmptyFromClauseSyntax = sql_dialect::from_clause_syntax::AnsiSqlFromClauseSyntax;
+    type SelectStatementSyntax = sql_dialect::select_statement_syntax::AnsiSqlSelectStatement;
+    type ExistsSyntax = sql_dialect::exists_syntax::AnsiSqlExistsSyntax;
+    type ArrayComparison = sql_dialect::array_comparison::AnsiSqlArrayComparison;
+    type AliasSyntax = sql_dialect::alias_syntax::AsAliasSyntax;
+    type WindowFrameClauseGroupSupport =
+        sql_dialect::window_frame_clause_group_support::IsoGroupWindowFrameUnit;
+    type WindowFrameExclusionSupport =
+        sql_dialect::window_frame_exclusion_support::FrameExclusionSupport;
+    type AggregateFunctionExpressions =
+        sql_dialect::aggregate_function_expressions::PostgresLikeAggregateFunctionExpressions;
+    type BuiltInWindowFunctionRequireOrder =
+        sql_dialect::built_in_window_function_require_order::NoOrderRequired;
+}
+
+impl DieselReserveSpecialization for HABackend {}
+impl TrustedBackend for HABackend {}
+
+macro_rules! has_sql_type {
+    ($($sql_type:ident => $metadata:ident),* $(,)?) => {
+        $(
+            impl HasSqlType<sql_types::$sql_type> for HABackend {
+                fn metadata(_: &mut ()) -> HAType {
+                    HAType::$metadata
+                }
+            }
+        )*
+    };
+}
+
+has_sql_type! {
+    SmallInt => SmallInt,
+    Integer => Integer,
+    BigInt => BigInt,
+    Float => Float,
+    Double => Double,
+    Text => Text,
+    Binary => Binary,
+    Bool => Bool,
+    Date => Date,
+    Time => Time,
+    Timestamp => Timestamp,
+}
+
+/// Query builder producing SQLite-dialect SQL with `?` placeholders.
+#[derive(Debug, Default)]
+pub struct HAQueryBuilder {
+    sql: String,
+}
+
+impl HAQueryBuilder {
+    /// Create an empty query builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QueryBuilder<HABackend> for HAQueryBuilder {
+    fn push_sql(&mut self, sql: &str) {
+        self.sql.push_str(sql);
+    }
+
+    fn push_identifier(&mut self, identifier: &str) -> QueryResult<()> {
+        self.sql.push('`');
+        self.sql.push_str(&identifier.replace('`', "``"));
+        self.sql.push('`');
+        Ok(())
+    }
+
+    fn push_bind_param(&mut self) {
+        self.sql.push('?');
+    }
+
+    fn finish(self) -> String {
+        self.sql
+    }
+}
+
+/// Bind collector gathering parameters as [`Value`]s.
+#[derive(Debug, Default)]
+pub struct HABindCollector {
+    binds: Vec<Value>,
+}
+
+impl HABindCollector {
+    /// Create an empty bind collector.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consume the collector and return the collected parameters.
+    pub fn into_values(self) -> Vec<Value> {
+        self.binds
+    }
+}
+
+impl<'a> BindCollector<'a, HABackend> for HABindCollector {
+    type Buffer = Value;
+
+    fn push_bound_value<T, U>(&mut self, bind: &'a U, metadata_lookup: &mut ()) -> QueryResult<()>
+    where
+        HABackend: Backend + HasSqlType<T>,
+        U: ToSql<T, HABackend> + ?Sized + 'a,
+    {
+        let mut out = Output::<HABackend>::new(Value::Null, metadata_lookup);
+        let is_null = bind
+            .to_sql(&mut out)
+            .map_err(DieselError::SerializationError)?;
+        match is_null {
+            IsNull::Yes => self.binds.push(Value::Null),
+            IsNull::No => self.binds.push(out.into_inner()),
+        }
+        Ok(())
+    }
+
+    fn push_null_value(&mut self, _metadata: HAType) -> QueryResult<()> {
+        self.binds.push(Value::Null);
+        Ok(())
+    }
+}
+
+macro_rules! to_sql {
+    ($($rust_type:ty => $sql_type:ident),* $(,)?) => {
+        $(
+            impl ToSql<sql_types::$sql_type, HABackend> for $rust_type {
+                fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, HABackend>) -> serialize::Result {
+                    out.set_value(*self);
+                    Ok(IsNull::No)
+                }
+            }
+        )*
+    };
+}
+
+to_sql! {
+    i32 => Integer,
+    i64 => BigInt,
+    f32 => Float,
+    f64 => Double,
+    bool => Bool,
+}
+
+impl ToSql<sql_types::SmallInt, HABackend> for i16 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, HABackend>) -> serialize::Result {
+        out.set_value(*self as i32);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql<sql_types::Text, HABackend> for str {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, HABackend>) -> serialize::Result {
+        out.set_value(self);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql<sql_types::Binary, HABackend> for [u8] {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, HABackend>) -> serialize::Result {
+        out.set_value(self);
+        Ok(IsNull::No)
+    }
+}
+
+fn unexpected(expected: &str, value: &Value) -> Box<dyn std::error::Error + Send + Sync> {
+    format!("Expected {}, got {:?}", expected, value).into()
+}
+
+impl FromSql<sql_types::BigInt, HABackend> for i64 {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        match value {
+            Value::Int64(v) => Ok(*v),
+            Value::Int32(v) => Ok(*v as i64),
+            Value::Bool(v) => Ok(*v as i64),
+            other => Err(unexpected("integer", other)),
+        }
+    }
+}
+
+impl FromSql<sql_types::Integer, HABackend> for i32 {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        let v = <i64 as FromSql<sql_types::BigInt, HABackend>>::from_sql(value)?;
+        i32::try_from(v).map_err(|e| e.into())
+    }
+}
+
+impl FromSql<sql_types::SmallInt, HABackend> for i16 {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        let v = <i64 as FromSql<sql_types::BigInt, HABackend>>::from_sql(value)?;
+        i16::try_from(v).map_err(|e| e.into())
+    }
+}
+
+impl FromSql<sql_types::Bool, HABackend> for bool {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        let v = <i64 as FromSql<sql_types::BigInt, HABackend>>::from_sql(value)?;
+        Ok(v != 0)
+    }
+}
+
+impl FromSql<sql_types::Double, HABackend> for f64 {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        match value {
+            Value::Double(v) => Ok(*v),
+            Value::Float(v) => Ok(*v as f64),
+            Value::Int64(v) => Ok(*v as f64),
+            Value::Int32(v) => Ok(*v as f64),
+            other => Err(unexpected("number", other)),
+        }
+    }
+}
+
+impl FromSql<sql_types::Float, HABackend> for f32 {
+    fn from_sql(value: &Value) -> deserialize::Result<Self> {
+        let v = <f64 as FromSql<sql_types::Double, HABackend>>::from_sql(value)?;
+        Ok(v as f32)
+    }
+}
+
+// Diesel builds `String` and `Vec<u8>` from these borrowed impls.
+impl<'a> FromSqlRef<'a, sql_types::Text, HABackend> for &'a str {
+    fn from_sql(value: &'a mut &Value) -> deserialize::Result<Self> {
+        match *value {
+            Value::String(v) => Ok(v.as_str()),
+            other => Err(unexpected("text", other)),
+        }
+    }
+}
+
+impl<'a> FromSqlRef<'a, sql_types::Binary, HABackend> for &'a [u8] {
+    fn from_sql(value: &'a mut &Value) -> deserialize::Result<Self> {
+        match *value {
+            Value::Bytes(v) => Ok(v.as_slice()),
+            Value::String(v) => Ok(v.as_bytes()),
+            other => Err(unexpected("blob", other)),
+        }
+    }
+}
+
+// SQLite has no DEFAULT keyword in VALUES lists: columns left at their
+// default are omitted from the column list instead.
+impl<Col, Expr> InsertValues<HABackend, Col::Table>
+    for DefaultableColumnInsertValue<ColumnInsertValue<Col, Expr>>
+where
+    Col: Column,
+    Expr: Expression<SqlType = Col::SqlType> + AppearsOnTable<NoFromClause>,
+    Self: QueryFragment<HABackend>,
+{
+    fn column_names(&self, mut out: AstPass<'_, '_, HABackend>) -> QueryResult<()> {
+        if let Self::Expression(..) = *self {
+            out.push_identifier(Col::NAME)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Col, Expr>
+    QueryFragment<HABackend, sql_dialect::default_keyword_for_insert::DoesNotSupportDefaultKeyword>
+    for DefaultableColumnInsertValue<ColumnInsertValue<Col, Expr>>
+where
+    ColumnInsertValue<Col, Expr>: QueryFragment<HABackend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        if let Self::Expression(ref inner) = *self {
+            inner.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+impl QueryFragment<HABackend> for LimitOffsetClause<NoLimitClause, NoOffsetClause> {
+    fn walk_ast<'b>(&'b self, _out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<L> QueryFragment<HABackend> for LimitOffsetClause<LimitClause<L>, NoOffsetClause>
+where
+    LimitClause<L>: QueryFragment<HABackend>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        self.limit_clause.walk_ast(out)
+    }
+}
+
+// SQLite only accepts OFFSET after a LIMIT; `LIMIT -1` means no limit.
+impl<O> QueryFragment<HABackend> for LimitOffsetClause<NoLimitClause, OffsetClause<O>>
+where
+    OffsetClause<O>: QueryFragment<HABackend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        out.push_sql(" LIMIT -1 ");
+        self.offset_clause.walk_ast(out)
+    }
+}
+
+impl<L, O> QueryFragment<HABackend> for LimitOffsetClause<LimitClause<L>, OffsetClause<O>>
+where
+    LimitClause<L>: QueryFragment<HABackend>,
+    OffsetClause<O>: QueryFragment<HABackend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        self.limit_clause.walk_ast(out.reborrow())?;
+        self.offset_clause.walk_ast(out)
+    }
+}
+
+impl QueryFragment<HABackend> for BoxedLimitOffsetClause<'_, HABackend> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, HABackend>) -> QueryResult<()> {
+        match (self.limit.as_ref(), self.offset.as_ref()) {
+            (Some(limit), Some(offset)) => {
+                limit.walk_ast(out.reborrow())?;
+                offset.walk_ast(out)
+            }
+            (Some(limit), None) => limit.walk_ast(out),
+            (None, Some(offset)) => {
+                out.push_sql(" LIMIT -1 ");
+                offset.walk_ast(out)
+            }
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl<'a> IntoBoxedClause<'a, HABackend> for LimitOffsetClause<NoLimitClause, NoOffsetClause> {
+    type BoxedClause = BoxedLimitOffsetClause<'a, HABackend>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl<'a, L> IntoBoxedClause<'a, HABackend> for LimitOffsetClause<LimitClause<L>, NoOffsetClause>
+where
+    L: QueryFragment<HABackend> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, HABackend>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: Some(Box::new(self.limit_clause)),
+            offset: None,
+        }
+    }
+}
+
+impl<'a, O> IntoBoxedClause<'a, HABackend> for LimitOffsetClause<NoLimitClause, OffsetClause<O>>
+where
+    O: QueryFragment<HABackend> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, HABackend>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: None,
+            offset: Some(Box::new(self.offset_clause)),
+        }
+    }
+}
+
+impl<'a, L, O> IntoBoxedClause<'a, HABackend> for LimitOffsetClause<LimitClause<L>, OffsetClause<O>>
+where
+    L: QueryFragment<HABackend> + Send + 'a,
+    O: QueryFragment<HABackend> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, HABackend>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: Some(Box::new(self.limit_clause)),
+            offset: Some(Box::new(self.offset_clause)),
+        }
+    }
+}
+
+/// A row loaded through [`HADieselConnection`].
+#[derive(Debug, Clone)]
+pub struct HARow {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+/// A field of an [`HARow`].
+#[derive(Debug, Clone, Copy)]
+pub struct HAField<'a> {
+    name: &'a str,
+    value: &'a Value,
+}
+
+impl RowSealed for HARow {}
+
+impl<'a> Row<'a, HABackend> for HARow {
+    type Field<'f>
+        = HAField<'f>
+    where
+        'a: 'f,
+        Self: 'f;
+    type InnerPartialRow = Self;
+
+    fn field_count(&self) -> usize {
+        self.values.len()
+    }
+
+    fn get<'b, I>(&'b self, idx: I) -> Option<Self::Field<'b>>
+    where
+        'a: 'b,
+        Self: RowIndex<I>,
+    {
+        let idx = self.idx(idx)?;
+        Some(HAField {
+            name: &self.columns[idx],
+            value: &self.values[idx],
+        })
+    }
+
+    fn partial_row(&self, range: Range<usize>) -> PartialRow<'_, Self::InnerPartialRow> {
+        PartialRow::new(self, range)
+    }
+}
+
+impl RowIndex<usize> for HARow {
+    fn idx(&self, idx: usize) -> Option<usize> {
+        if idx < self.values.len() {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> RowIndex<&'a str> for HARow {
+    fn idx(&self, field_name: &'a str) -> Option<usize> {
+        self.columns.iter().position(|c| c == field_name)
+    }
+}
+
+impl<'a> Field<'a, HABackend> for HAField<'a> {
+    fn field_name(&self) -> Option<&str> {
+        Some(self.name)
+    }
+
+    fn value(&self) -> Option<&Value> {
+        match self.value {
+            Value::Null => None,
+            v => Some(v),
+        }
+    }
+}
+
+/// Cursor over the rows of a loaded query.
+pub struct HACursor {
+    columns: Arc<[String]>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl HACursor {
+    pub(crate) fn new(result: ExecutionResult) -> Self {
+        Self {
+            columns: result.columns.into(),
+            rows: result.rows.into_iter(),
+        }
+    }
+}
+
+impl Iterator for HACursor {
+    type Item = QueryResult<HARow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next().map(|values| {
+            Ok(HARow {
+                columns: self.columns.clone(),
+                values,
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+struct HADatabaseError(String);
+
+impl DatabaseErrorInformation for HADatabaseError {
+    fn message(&self) -> &str {
+        &self.0
+    }
+
+    fn details(&self) -> Option<&str> {
+        None
+    }
+
+    fn hint(&self) -> Option<&str> {
+        None
+    }
+
+    fn table_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn column_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn constraint_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn statement_position(&self) -> Option<i32> {
+        None
+    }
+}
+
+pub(crate) fn to_diesel_error(error: Error) -> DieselError {
+    let kind = match error {
+        Error::ConnectionClosed => DatabaseErrorKind::ClosedConnection,
+        Error::Query(ref message) if message.contains("UNIQUE constraint failed") => {
+            DatabaseErrorKind::UniqueViolation
+        }
+        Error::Query(ref message) if message.contains("FOREIGN KEY constraint failed") => {
+            DatabaseErrorKind::ForeignKeyViolation
+        }
+        Error::Query(ref message) if message.contains("NOT NULL constraint failed") => {
+            DatabaseErrorKind::NotNullViolation
+        }
+        Error::Query(ref message) if message.contains("CHECK constraint failed") => {
+            DatabaseErrorKind::CheckViolation
+        }
+        _ => DatabaseErrorKind::Unknown,
+    };
+    DieselError::DatabaseError(kind, Box::new(HADatabaseError(error.to_string())))
+}
+
+/// A diesel connection to the HA database.
+pub struct HADieselConnection {
+    inner: blocking::HAConnection,
+    transaction_state: AnsiTransactionManager,
+    instrumentation: Option<Box<dyn Instrumentation>>,
+}
+
+impl HADieselConnection {
+    /// Create a diesel connection from data source options.
+    pub fn with_options(options: HADataSourceOptions) -> ConnectionResult<Self> {
+        let ds = blocking::HADataSource::new(options)
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        let inner = ds
+            .get_connection()
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        Ok(Self::from_connection(inner))
+    }
+
+    /// Wrap an existing blocking connection.
+    pub fn from_connection(inner: blocking::HAConnection) -> Self {
+        Self {
+            inner,
+            transaction_state: AnsiTransactionManager::default(),
+            instrumentation: ::diesel::connection::get_default_instrumentation(),
+        }
+    }
+
+    /// Get the wrapped blocking connection.
+    pub fn inner(&self) -> &blocking::HAConnection {
+        &self.inner
+    }
+}
+
+/// Build the SQL and parameters of `source`.
+pub(crate) fn prepare<T>(source: &T) -> QueryResult<(String, Vec<Value>)>
+where
+    T: QueryFragment<HABackend>,
+{
+    let mut query_builder = HAQueryBuilder::new();
+    source.to_sql(&mut query_builder, &HABackend)?;
+
+    let mut binds = HABindCollector::new();
+    source.collect_binds(&mut binds, &mut (), &HABackend)?;
+
+    Ok((query_builder.finish(), binds.into_values()))
+}
+
+impl SimpleConnection for HADieselConnection {
+    fn batch_execute(&mut self, query: &str) -> QueryResult<()> {
+        // Transaction control goes through the connection so that routing
+        // knows a transaction is open.
+        let result = match query.trim().to_uppercase().as_str() {
+            "BEGIN" => self.inner.begin_transaction(),
+            "COMMIT" => self.inner.commit(),
+            "ROLLBACK" => self.inner.rollback(),
+            _ => self.inner.execute(query, &[]).map(|_| ()),
+        };
+        result.map_err(to_diesel_error)
+    }
+}
+
+impl ConnectionSealed for HADieselConnection {}
+
+impl Connection for HADieselConnection {
+    type Backend = HABackend;
+    type TransactionManager = AnsiTransactionManager;
+
+    /// Establish a connection to the given `litesql://` URL.
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        Self::with_options(HADataSourceOptions {
+            url: database_url.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn execute_returning_count<T>(&mut self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Self::Backend> + QueryId,
+    {
+        let (sql, params) = prepare(source)?;
+        let result = self.inner.execute(&sql, &params).map_err(to_diesel_error)?;
+        Ok(result.rows_affected.max(0) as usize)
+    }
+
+    fn transaction_state(&mut self) -> &mut AnsiTransactionManager {
+        &mut self.transaction_state
+    }
+
+    fn instrumentation(&mut self) -> &mut dyn Instrumentation {
+        &mut self.instrumentation
+    }
+
+    fn set_instrumentation(&mut self, instrumentation: impl Instrumentation) {
+        self.instrumentation = Some(Box::new(instrumentation));
+    }
+
+    /// Statements are not prepared client-side, so this is a no-op.
+    fn set_prepared_statement_cache_size(&mut self, _size: CacheSize) {}
+}
+
+impl LoadConnection<DefaultLoadingMode> for HADieselConnection {
+    type Cursor<'conn, 'query> = HACursor;
+    type Row<'conn, 'query> = HARow;
+
+    fn load<'conn, 'query, T>(&'conn mut self, source: T) -> QueryResult<HACursor>
+    where
+        T: Query + QueryFragment<Self::Backend> + QueryId + 'query,
+        Self::Backend: QueryMetadata<T::SqlType>,
+    {
+        let (sql, params) = prepare(&source)?;
+        let result = self.inner.query(&sql, &params).map_err(to_diesel_error)?;
+        Ok(HACursor::new(result))
+    }
+}
+
+impl R2D2Connection for HADieselConnection {
+    fn ping(&mut self) -> QueryResult<()> {
+        self.inner
+            .query("SELECT 1", &[])
+            .map(|_| ())
+            .map_err(to_diesel_error)
+    }
+
+    fn is_broken(&mut self) -> bool {
+        self.inner.is_closed() || AnsiTransactionManager::is_broken_transaction_manager(self)
+    }
+}
+
+/// r2d2 connection manager for [`HADieselConnection`].
+pub type HAConnectionManager = ConnectionManager<HADieselConnection>;
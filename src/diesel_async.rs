@@ -0,0 +1,169 @@
+//! diesel-async connection.
+//!
+//! Enabled by the `diesel-async` feature. [`HAAsyncDieselConnection`]
+//! implements diesel-async's `AsyncConnection` on top of the async client,
+//! with the [`HABackend`] backend of the [`diesel`](crate::diesel) module, so
+//! the usual routing applies: reads go to an up-to-date embedded replica when
+//! one is loaded, writes and reads inside a transaction go to the HA server.
+//! The limitations of the blocking backend apply too.
+//!
+//! Rows are loaded in full before the returned stream yields the first one.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use diesel::prelude::*;
+//! use diesel::sql_types::Text;
+//! use diesel_async::{AsyncConnection, RunQueryDsl};
+//! use litesql_ha::diesel_async::HAAsyncDieselConnection;
+//!
+//! #[derive(QueryableByName)]
+//! struct User {
+//!     #[diesel(sql_type = Text)]
+//!     name: String,
+//! }
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut conn = HAAsyncDieselConnection::establish("litesql://localhost:8080").await?;
+//! let users: Vec<User> = diesel::sql_query("SELECT name FROM users")
+//!     .load(&mut conn)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::connection::HAConnection;
+use crate::datasource::{HADataSource, HADataSourceOptions};
+use crate::diesel::{prepare, to_diesel_error, HABackend, HACursor, HARow};
+use ::diesel::connection::{CacheSize, Instrumentation};
+use ::diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use ::diesel::result::{ConnectionError, ConnectionResult, QueryResult};
+use ::diesel_async::{
+    AnsiTransactionManager, AsyncConnection, AsyncConnectionCore, SimpleAsyncConnection,
+};
+use std::future::Future;
+use std::pin::Pin;
+use tokio_stream::Stream;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+type BoxStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+
+/// A diesel-async connection to the HA database.
+pub struct HAAsyncDieselConnection {
+    inner: HAConnection,
+    transaction_state: AnsiTransactionManager,
+    instrumentation: Option<Box<dyn Instrumentation>>,
+}
+
+impl HAAsyncDieselConnection {
+    /// Create a diesel-async connection from data source options.
+    pub async fn with_options(options: HADataSourceOptions) -> ConnectionResult<Self> {
+        let inner = HADataSource::new(options)
+            .get_connection()
+            .await
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        Ok(Self::from_connection(inner.detach()))
+    }
+
+    /// Wrap an existing connection.
+    pub fn from_connection(inner: HAConnection) -> Self {
+        Self {
+            inner,
+            transaction_state: AnsiTransactionManager::default(),
+            instrumentation: ::diesel::connection::get_default_instrumentation(),
+        }
+    }
+
+    /// Get the wrapped connection.
+    pub fn inner(&self) -> &HAConnection {
+        &self.inner
+    }
+}
+
+impl SimpleAsyncConnection for HAAsyncDieselConnection {
+    async fn batch_execute(&mut self, query: &str) -> QueryResult<()> {
+        // Transaction control goes through the connection so that routing
+        // knows a transaction is open.
+        let result = match query.trim().to_uppercase().as_str() {
+            "BEGIN" => self.inner.begin_transaction().await,
+            "COMMIT" => self.inner.commit().await,
+            "ROLLBACK" => self.inner.rollback().await,
+            _ => self.inner.execute(query, &[]).await.map(|_| ()),
+        };
+        result.map_err(to_diesel_error)
+    }
+}
+
+impl AsyncConnectionCore for HAAsyncDieselConnection {
+    type ExecuteFuture<'conn, 'query> = BoxFuture<'conn, QueryResult<usize>>;
+    type LoadFuture<'conn, 'query> = BoxFuture<'conn, QueryResult<Self::Stream<'conn, 'query>>>;
+    type Stream<'conn, 'query> = BoxStream<QueryResult<HARow>>;
+    type Row<'conn, 'query> = HARow;
+    type Backend = HABackend;
+
+    fn load<'conn, 'query, T>(&'conn mut self, source: T) -> Self::LoadFuture<'conn, 'query>
+    where
+        T: AsQuery + 'query,
+        T::Query: QueryFragment<Self::Backend> + QueryId + 'query,
+    {
+        let prepared = prepare(&source.as_query());
+        Box::pin(async move {
+            let (sql, params) = prepared?;
+            let result = self
+                .inner
+                .query(&sql, &params)
+                .await
+                .map_err(to_diesel_error)?;
+            let rows: Self::Stream<'conn, 'query> =
+                Box::pin(tokio_stream::iter(HACursor::new(result)));
+            Ok(rows)
+        })
+    }
+
+    fn execute_returning_count<'conn, 'query, T>(
+        &'conn mut self,
+        source: T,
+    ) -> Self::ExecuteFuture<'conn, 'query>
+    where
+        T: QueryFragment<Self::Backend> + QueryId + 'query,
+    {
+        let prepared = prepare(&source);
+        Box::pin(async move {
+            let (sql, params) = prepared?;
+            let result = self
+                .inner
+                .execute(&sql, &params)
+                .await
+                .map_err(to_diesel_error)?;
+            Ok(result.rows_affected.max(0) as usize)
+        })
+    }
+}
+
+impl AsyncConnection for HAAsyncDieselConnection {
+    type TransactionManager = AnsiTransactionManager;
+
+    /// Establish a connection to the given `litesql://` URL.
+    async fn establish(database_url: &str) -> ConnectionResult<Self> {
+        Self::with_options(HADataSourceOptions {
+            url: database_url.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    fn transaction_state(&mut self) -> &mut AnsiTransactionManager {
+        &mut self.transaction_state
+    }
+
+    fn instrumentation(&mut self) -> &mut dyn Instrumentation {
+        &mut self.instrumentation
+    }
+
+    fn set_instrumentation(&mut self, instrumentation: impl Instrumentation) {
+        self.instrumentation = Some(Box::new(instrumentation));
+    }
+
+    /// Statements are not prepared client-side, so this is a no-op.
+    fn set_prepared_statement_cache_size(&mut self, _size: CacheSize) {}
+}
@@ -0,0 +1,267 @@
+//! DNS discovery of the servers.
+//!
+//! tonic resolves the host of the server URL once, when the channel first
+//! connects. With [`HAClientOptions::discovery`](crate::HAClientOptions::discovery)
+//! set, the client instead resolves it again every `refresh_interval` and
+//! spreads its calls over every address found, dialling servers that appear
+//! and dropping those that disappear, so a long-running client follows the
+//! server pool as it scales up or down. With the `dns-srv` feature, the
+//! servers can be discovered from the SRV records of a name instead, each
+//! record naming a host and port.
+//!
+//! A follower's redirect still sends calls to the leader alone; once the
+//! leader cannot be reached, calls go back to the discovered servers.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{DiscoveryOptions, HAClientOptions};
+//! use std::time::Duration;
+//!
+//! let options = HAClientOptions {
+//!     url: "litesql://litesql.default.svc.cluster.local:8080/app.db".to_string(),
+//!     discovery: Some(DiscoveryOptions {
+//!         refresh_interval: Duration::from_secs(10),
+//!         ..Default::default()
+//!     }),
+//!     ..Default::default()
+//! };
+//! ```
+
+use crate::client::EndpointConfig;
+use crate::error::{Error, Result};
+#[cfg(target_arch = "wasm32")]
+use crate::grpc_web::Channel;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::runtime;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::mpsc::Sender;
+#[cfg(not(target_arch = "wasm32"))]
+use tonic::transport::{Channel, Endpoint};
+#[cfg(not(target_arch = "wasm32"))]
+use tower::discover::Change;
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::{debug, warn};
+use url::Url;
+
+/// Changes to the discovered servers buffered for the channel.
+#[cfg(not(target_arch = "wasm32"))]
+const CHANGES_BUFFER: usize = 64;
+
+/// How servers are discovered and how often.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// How often the servers are resolved again
+    pub refresh_interval: Duration,
+    /// Discover the servers from the SRV records of this name, e.g.
+    /// `_litesql._tcp.example.com`, instead of the host of the URL
+    #[cfg(feature = "dns-srv")]
+    pub srv: Option<String>,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            refresh_interval: Duration::from_secs(30),
+            #[cfg(feature = "dns-srv")]
+            srv: None,
+        }
+    }
+}
+
+/// Resolve the servers of `url`, then again every refresh interval in the
+/// background, and return a channel spreading calls over them.
+///
+/// Fails if no server is found at first; later lookups that fail or find
+/// none keep the servers found before.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn channel(
+    url: &Url,
+    config: EndpointConfig,
+    options: &DiscoveryOptions,
+) -> Result<Channel> {
+    if matches!(url.scheme(), "ws" | "wss") {
+        return Err(Error::InvalidParameter(
+            "Servers behind a WebSocket gateway cannot be discovered".to_string(),
+        ));
+    }
+    let resolver = Resolver::new(url, options)?;
+    let found = resolver.resolve().await?;
+    if found.is_empty() {
+        return Err(Error::InvalidParameter(format!(
+            "No server found for {}",
+            resolver.name()
+        )));
+    }
+
+    let (channel, changes) = Channel::balance_channel(CHANGES_BUFFER);
+    let mut servers = Servers {
+        scheme: url.scheme().to_string(),
+        config,
+        changes,
+        current: HashMap::new(),
+    };
+    servers.update(found).await?;
+
+    let interval = options.refresh_interval;
+    runtime::spawn_named(runtime::DISCOVERY, async move {
+        // The channel's worker, and with it the receiving end, is gone once
+        // the client is dropped.
+        while !servers.changes.is_closed() {
+            runtime::sleep(interval).await;
+            match resolver.resolve().await {
+                Ok(found) if found.is_empty() => {
+                    warn!("No server found for {}", resolver.name());
+                }
+                Ok(found) => {
+                    if servers.update(found).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Cannot resolve {}: {}", resolver.name(), e),
+            }
+        }
+    });
+    Ok(channel)
+}
+
+/// Fails: DNS cannot be queried from wasm32.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn channel(
+    _url: &Url,
+    _config: EndpointConfig,
+    _options: &DiscoveryOptions,
+) -> Result<Channel> {
+    Err(Error::InvalidParameter(
+        "Servers cannot be discovered on wasm32".to_string(),
+    ))
+}
+
+/// Looks up the servers.
+#[cfg(not(target_arch = "wasm32"))]
+enum Resolver {
+    /// The addresses of a host name, all on the port of the URL
+    Host { host: String, port: u16 },
+    /// The targets of SRV records
+    #[cfg(feature = "dns-srv")]
+    Srv {
+        name: String,
+        resolver: Box<hickory_resolver::TokioAsyncResolver>,
+    },
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Resolver {
+    fn new(url: &Url, options: &DiscoveryOptions) -> Result<Self> {
+        #[cfg(feature = "dns-srv")]
+        if let Some(ref name) = options.srv {
+            let resolver = runtime::srv_resolver()
+                .map_err(|e| Error::InvalidParameter(format!("DNS resolver: {}", e)))?;
+            return Ok(Resolver::Srv {
+                name: name.clone(),
+                resolver: Box::new(resolver),
+            });
+        }
+        #[cfg(not(feature = "dns-srv"))]
+        let _ = options;
+        Ok(Resolver::Host {
+            host: url.host_str().unwrap_or("localhost").to_string(),
+            port: url.port().unwrap_or(8080),
+        })
+    }
+
+    /// Get the name looked up.
+    fn name(&self) -> &str {
+        match self {
+            Resolver::Host { host, .. } => host,
+            #[cfg(feature = "dns-srv")]
+            Resolver::Srv { name, .. } => name,
+        }
+    }
+
+    /// Get the address of every server, with the host name it was found
+    /// under.
+    async fn resolve(&self) -> Result<HashMap<SocketAddr, String>> {
+        match self {
+            Resolver::Host { host, port } => lookup(host, *port).await,
+            #[cfg(feature = "dns-srv")]
+            Resolver::Srv { name, resolver } => {
+                let records = resolver
+                    .srv_lookup(name.as_str())
+                    .await
+                    .map_err(|e| Error::Io(std::io::Error::other(e)))?;
+                let mut found = HashMap::new();
+                for record in records.iter() {
+                    let target = record.target().to_utf8();
+                    let target = target.trim_end_matches('.');
+                    match lookup(target, record.port()).await {
+                        Ok(addrs) => found.extend(addrs),
+                        Err(e) => warn!("Cannot resolve {}: {}", target, e),
+                    }
+                }
+                Ok(found)
+            }
+        }
+    }
+}
+
+/// Get the addresses of `host`.
+#[cfg(not(target_arch = "wasm32"))]
+async fn lookup(host: &str, port: u16) -> Result<HashMap<SocketAddr, String>> {
+    Ok(runtime::lookup_host((host, port))
+        .await?
+        .map(|addr| (addr, host.to_string()))
+        .collect())
+}
+
+/// The servers the channel spreads calls over.
+#[cfg(not(target_arch = "wasm32"))]
+struct Servers {
+    scheme: String,
+    config: EndpointConfig,
+    changes: Sender<Change<SocketAddr, Endpoint>>,
+    current: HashMap<SocketAddr, String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Servers {
+    /// Dial the servers in `found` that are new and drop those missing from
+    /// it; fails once the channel is gone.
+    async fn update(&mut self, found: HashMap<SocketAddr, String>) -> Result<()> {
+        let mut current = HashMap::with_capacity(found.len());
+        for (addr, host) in found {
+            if let Some(host) = self.current.remove(&addr) {
+                current.insert(addr, host);
+                continue;
+            }
+            let url = Url::parse(&format!("{}://{}", self.scheme, addr))?;
+            let endpoint = match self.config.endpoint_for(&url, Some(&host)) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    warn!("Cannot use server {} of {}: {}", addr, host, e);
+                    continue;
+                }
+            };
+            debug!("Server {} found for {}", addr, host);
+            self.changes
+                .send(Change::Insert(addr, endpoint))
+                .await
+                .map_err(|_| Error::ConnectionClosed)?;
+            current.insert(addr, host);
+        }
+        for addr in self.current.keys() {
+            debug!("Server {} is gone", addr);
+            self.changes
+                .send(Change::Remove(*addr))
+                .await
+                .map_err(|_| Error::ConnectionClosed)?;
+        }
+        self.current = current;
+        Ok(())
+    }
+}
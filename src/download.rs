@@ -0,0 +1,750 @@
+//! Downloading replica snapshots from the HA server.
+//!
+//! A snapshot is fetched in chunks, several at a time, into a partial file
+//! in the `.partial` directory of the replicas directory. Finished chunks
+//! are recorded in a state file next to it, so a download that fails
+//! resumes where it stopped the next time it is started, as long as the
+//! server still holds the snapshot; otherwise it starts over from a new one.
+//! The replica file only appears, by an atomic rename, once every chunk has
+//! arrived.
+//!
+//! Servers without ranged downloads answer the first request with the whole
+//! snapshot, which is then written as it streams; a failed download of one
+//! starts over.
+//!
+//! When the server sends SHA-256 checksums, each chunk is checked as it
+//! arrives and fetched again if it does not match, and the assembled file is
+//! checked before it is moved into place. A file that still does not match
+//! is deleted and the download fails with [`Error::ChecksumMismatch`].
+//!
+//! With the `gzip` or `zstd` feature the client accepts downloads compressed
+//! with them, and servers configured to compress send them so. Sizes, rates
+//! and progress count the data after decompression.
+//!
+//! Downloads write to the filesystem and need the `embedded-replicas`
+//! feature; without it they fail.
+//!
+//! [`DownloadOptions`] set the chunk size and parallelism, cap the transfer
+//! rate, report progress as the data arrives and run `PRAGMA integrity_check`
+//! on the file before it is moved into place.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::download::DownloadOptions;
+//! use litesql_ha::HAClient;
+//! use std::path::Path;
+//!
+//! async fn fetch(client: &HAClient) -> litesql_ha::Result<()> {
+//!     let options = DownloadOptions::new()
+//!         .with_max_bytes_per_sec(50 * 1024 * 1024)
+//!         .with_progress(|p| match p.total {
+//!             Some(total) => println!("{}: {} of {} bytes", p.replication_id, p.downloaded, total),
+//!             None => println!("{}: {} bytes", p.replication_id, p.downloaded),
+//!         });
+//!     client
+//!         .download_all_replicas_with(Path::new("replicas"), true, &options)
+//!         .await
+//! }
+//! ```
+
+use crate::client::HAClient;
+use crate::error::{Error, Result};
+#[cfg(feature = "embedded-replicas")]
+use crate::proto::{DownloadRequest, DownloadResponse};
+#[cfg(feature = "embedded-replicas")]
+use crate::runtime::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, File, OpenOptions};
+#[cfg(feature = "embedded-replicas")]
+use parking_lot::Mutex;
+#[cfg(feature = "embedded-replicas")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "embedded-replicas")]
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+#[cfg(feature = "embedded-replicas")]
+use std::future::Future;
+#[cfg(feature = "embedded-replicas")]
+use std::io::{self, SeekFrom};
+use std::path::Path;
+#[cfg(feature = "embedded-replicas")]
+use std::path::PathBuf;
+#[cfg(feature = "embedded-replicas")]
+use std::pin::Pin;
+#[cfg(feature = "embedded-replicas")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "embedded-replicas")]
+use std::task::Poll;
+use std::time::Duration;
+#[cfg(feature = "embedded-replicas")]
+use std::time::Instant;
+#[cfg(feature = "embedded-replicas")]
+use tonic::{Code, Streaming};
+#[cfg(feature = "embedded-replicas")]
+use tracing::warn;
+
+/// Attempts at a chunk failing with a transient error.
+#[cfg(feature = "embedded-replicas")]
+const CHUNK_ATTEMPTS: usize = 3;
+
+/// Directory of the replicas directory holding partial downloads.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const PARTIAL_DIR: &str = ".partial";
+
+/// Progress of a replica download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadProgress {
+    /// Replica being downloaded
+    pub replication_id: String,
+    /// Bytes downloaded, including those of a resumed partial download
+    pub downloaded: u64,
+    /// Size of the snapshot; `None` from servers without ranged downloads
+    pub total: Option<u64>,
+    /// Time since the download started
+    pub elapsed: Duration,
+}
+
+impl DownloadProgress {
+    /// Bytes downloaded per second since the download started.
+    pub fn bytes_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.downloaded as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+type ProgressFn = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// Options of a replica download.
+#[derive(Clone)]
+// Nothing is downloaded without embedded replicas.
+#[cfg_attr(not(feature = "embedded-replicas"), allow(dead_code))]
+pub struct DownloadOptions {
+    chunk_size: u64,
+    parallel_chunks: usize,
+    max_bytes_per_sec: Option<u64>,
+    progress: Option<ProgressFn>,
+    integrity_check: bool,
+}
+
+impl DownloadOptions {
+    /// Download 4 MiB chunks, 4 at a time, as fast as the link allows.
+    pub fn new() -> Self {
+        Self {
+            chunk_size: 4 * 1024 * 1024,
+            parallel_chunks: 4,
+            max_bytes_per_sec: None,
+            progress: None,
+            integrity_check: false,
+        }
+    }
+
+    /// Request `chunk_size` bytes at a time; 4 MiB by default.
+    ///
+    /// A partial download made with another chunk size starts over.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Download up to `parallel_chunks` chunks at the same time; 4 by
+    /// default.
+    pub fn with_parallel_chunks(mut self, parallel_chunks: usize) -> Self {
+        self.parallel_chunks = parallel_chunks.max(1);
+        self
+    }
+
+    /// Receive at most `max_bytes_per_sec` bytes per second over all the
+    /// chunks of a download.
+    pub fn with_max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> Self {
+        self.max_bytes_per_sec = Some(max_bytes_per_sec.max(1));
+        self
+    }
+
+    /// Call `progress` as the data arrives.
+    ///
+    /// It runs on the downloading task, so it should be cheap.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&DownloadProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Run `PRAGMA integrity_check` on the downloaded file before moving it
+    /// into place, deleting it if SQLite finds it corrupt.
+    #[cfg(feature = "embedded-replicas")]
+    pub fn with_integrity_check(mut self, integrity_check: bool) -> Self {
+        self.integrity_check = integrity_check;
+        self
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadOptions")
+            .field("chunk_size", &self.chunk_size)
+            .field("parallel_chunks", &self.parallel_chunks)
+            .field("max_bytes_per_sec", &self.max_bytes_per_sec)
+            .field("progress", &self.progress.is_some())
+            .field("integrity_check", &self.integrity_check)
+            .finish()
+    }
+}
+
+/// A snapshot being downloaded, as recorded in its state file.
+///
+/// The state file holds the size, chunk size, checksum and id of the
+/// snapshot on its first line, then the index of each chunk written in full.
+#[cfg(feature = "embedded-replicas")]
+struct Snapshot {
+    id: String,
+    size: u64,
+    chunk_size: u64,
+    /// Hex SHA-256 of the whole snapshot; empty if the server sent none
+    checksum: String,
+    done: BTreeSet<u64>,
+}
+
+#[cfg(feature = "embedded-replicas")]
+impl Snapshot {
+    /// Read the state of a partial download, if it can be resumed with
+    /// chunks of `chunk_size`.
+    async fn read(path: &Path, chunk_size: u64) -> Option<Self> {
+        let text = runtime::read_to_string(path).await.ok()?;
+        let mut lines = text.lines();
+        let mut header = lines.next()?.splitn(4, ' ');
+        let size = header.next()?.parse().ok()?;
+        let recorded: u64 = header.next()?.parse().ok()?;
+        let checksum = header.next()?.trim_start_matches('-').to_string();
+        let id = header.next()?.to_string();
+        if recorded != chunk_size || id.is_empty() {
+            return None;
+        }
+        let mut snapshot = Self {
+            id,
+            size,
+            chunk_size,
+            checksum,
+            done: BTreeSet::new(),
+        };
+        snapshot.done = lines
+            .filter_map(|line| line.parse().ok())
+            .filter(|&index| index < snapshot.chunks())
+            .collect();
+        Some(snapshot)
+    }
+
+    /// Start the state file of a new download.
+    async fn create(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path).await?;
+        let checksum = if self.checksum.is_empty() {
+            "-"
+        } else {
+            &self.checksum
+        };
+        let header = format!(
+            "{} {} {} {}\n",
+            self.size, self.chunk_size, checksum, self.id
+        );
+        file.write_all(header.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    fn chunks(&self) -> u64 {
+        self.size.div_ceil(self.chunk_size)
+    }
+
+    /// Offset and length of a chunk.
+    fn range(&self, index: u64) -> (u64, u64) {
+        let start = index * self.chunk_size;
+        (start, self.chunk_size.min(self.size - start))
+    }
+
+    /// Bytes of the chunks written in full.
+    fn downloaded(&self) -> u64 {
+        self.done.iter().map(|&index| self.range(index).1).sum()
+    }
+}
+
+/// Paces the data received by all the chunks of a download.
+#[cfg(feature = "embedded-replicas")]
+struct Throttle {
+    bytes_per_sec: u64,
+    next: Mutex<Instant>,
+}
+
+#[cfg(feature = "embedded-replicas")]
+impl Throttle {
+    /// Wait until `bytes` more may be received.
+    async fn take(&self, bytes: u64) {
+        let wait = {
+            let mut next = self.next.lock();
+            let now = Instant::now();
+            let start = (*next).max(now);
+            *next = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            start - now
+        };
+        if !wait.is_zero() {
+            runtime::sleep(wait).await;
+        }
+    }
+}
+
+/// One download of a replica.
+#[cfg(feature = "embedded-replicas")]
+struct Transfer<'a> {
+    client: &'a HAClient,
+    replication_id: &'a str,
+    options: &'a DownloadOptions,
+    part: PathBuf,
+    state: PathBuf,
+    throttle: Option<Throttle>,
+    started: Instant,
+    downloaded: AtomicU64,
+    total: Mutex<Option<u64>>,
+}
+
+/// Download the snapshot of `replication_id` into `directory`, resuming a
+/// partial download of it.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) async fn download(
+    client: &HAClient,
+    directory: &Path,
+    replication_id: &str,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let partial_dir = directory.join(PARTIAL_DIR);
+    runtime::create_dir_all(&partial_dir).await?;
+    let transfer = Transfer {
+        client,
+        replication_id,
+        options,
+        part: partial_dir.join(replication_id),
+        state: partial_dir.join(format!("{}.state", replication_id)),
+        throttle: options.max_bytes_per_sec.map(|bytes_per_sec| Throttle {
+            bytes_per_sec,
+            next: Mutex::new(Instant::now()),
+        }),
+        started: Instant::now(),
+        downloaded: AtomicU64::new(0),
+        total: Mutex::new(None),
+    };
+
+    let mut restarted = false;
+    let checksum = loop {
+        // Chunks recorded as done are lost with the partial file
+        let resumed = match Snapshot::read(&transfer.state, options.chunk_size).await {
+            Some(snapshot) if runtime::try_exists(&transfer.part).await? => Some(snapshot),
+            _ => None,
+        };
+        let snapshot = match resumed {
+            Some(snapshot) => {
+                transfer.start(Some(snapshot.size), snapshot.downloaded());
+                snapshot
+            }
+            None => match transfer.first_chunk().await? {
+                Some(snapshot) => snapshot,
+                None => break String::new(),
+            },
+        };
+        match transfer.fetch_chunks(&snapshot).await {
+            Err(Error::Status(ref status))
+                if status.code() == Code::FailedPrecondition && !restarted =>
+            {
+                warn!(
+                    "Snapshot {} of {} is gone, downloading a new one: {}",
+                    snapshot.id,
+                    replication_id,
+                    status.message()
+                );
+                runtime::remove_file(&transfer.state).await?;
+                restarted = true;
+            }
+            result => {
+                result?;
+                break snapshot.checksum;
+            }
+        }
+    };
+
+    if let Err(e) = transfer.verify(&checksum).await {
+        let _ = runtime::remove_file(&transfer.part).await;
+        let _ = runtime::remove_file(&transfer.state).await;
+        return Err(e);
+    }
+    runtime::rename(&transfer.part, directory.join(replication_id)).await?;
+    let _ = runtime::remove_file(&transfer.state).await;
+    #[cfg(feature = "metrics")]
+    crate::metrics::download_completed(replication_id, transfer.started.elapsed());
+    Ok(())
+}
+
+#[cfg(feature = "embedded-replicas")]
+impl Transfer<'_> {
+    /// Ask for the first chunk of a new snapshot and write it, returning the
+    /// snapshot, or `None` if the server sent the whole file instead.
+    async fn first_chunk(&self) -> Result<Option<Snapshot>> {
+        let chunk_size = self.options.chunk_size;
+        let mut stream = self
+            .client
+            .open_download(DownloadRequest {
+                replication_id: self.replication_id.to_string(),
+                offset: 0,
+                length: chunk_size as i64,
+                snapshot_id: String::new(),
+            })
+            .await?;
+        let mut file = File::create(&self.part).await?;
+        let first = match stream.message().await? {
+            Some(first) if !first.snapshot_id.is_empty() => first,
+            first => {
+                self.start(None, 0);
+                let mut hasher = Sha256::new();
+                let mut expected = Vec::new();
+                let mut next = first;
+                while let Some(response) = next {
+                    if expected.is_empty() {
+                        expected = response.snapshot_checksum;
+                    }
+                    self.received(response.data.len() as u64).await;
+                    hasher.update(&response.data);
+                    file.write_all(&response.data).await?;
+                    next = stream.message().await?;
+                }
+                let actual = hasher.finalize();
+                if !expected.is_empty() && actual.as_slice() != expected.as_slice() {
+                    return Err(Error::ChecksumMismatch(format!(
+                        "{} has SHA-256 {}, expected {}",
+                        self.replication_id,
+                        hex(&actual),
+                        hex(&expected)
+                    )));
+                }
+                file.sync_all().await?;
+                return Ok(None);
+            }
+        };
+
+        let mut snapshot = Snapshot {
+            id: first.snapshot_id.clone(),
+            size: u64::try_from(first.size).map_err(|_| invalid("negative snapshot size"))?,
+            chunk_size,
+            checksum: hex(&first.snapshot_checksum),
+            done: BTreeSet::new(),
+        };
+        self.start(Some(snapshot.size), 0);
+        file.set_len(snapshot.size).await?;
+        snapshot.create(&self.state).await?;
+        if snapshot.chunks() > 0 {
+            let (start, len) = snapshot.range(0);
+            let mut written = 0;
+            let result = self
+                .write_chunk(
+                    &mut file,
+                    &mut stream,
+                    Some(first),
+                    (start, len),
+                    &mut written,
+                )
+                .await;
+            match result {
+                Ok(()) => {
+                    let mut log = OpenOptions::new().append(true).open(&self.state).await?;
+                    log.write_all(b"0\n").await?;
+                    snapshot.done.insert(0);
+                }
+                // Fetched again with the other chunks
+                Err(Error::ChecksumMismatch(message)) => {
+                    warn!(
+                        "Fetching the first chunk of {} again: {}",
+                        self.replication_id, message
+                    );
+                    self.downloaded.fetch_sub(written, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Download the chunks of `snapshot` not written yet, several at a time.
+    async fn fetch_chunks(&self, snapshot: &Snapshot) -> Result<()> {
+        let pending: VecDeque<u64> = (0..snapshot.chunks())
+            .filter(|index| !snapshot.done.contains(index))
+            .collect();
+        let file = OpenOptions::new().write(true).open(&self.part).await?;
+        file.set_len(snapshot.size).await?;
+
+        let workers = self.options.parallel_chunks.min(pending.len());
+        let pending = Mutex::new(pending);
+        let log = OpenOptions::new().append(true).open(&self.state).await?;
+        let log = tokio::sync::Mutex::new(log);
+        let workers = (0..workers).map(|_| async {
+            loop {
+                let index = match pending.lock().pop_front() {
+                    Some(index) => index,
+                    None => return Ok(()),
+                };
+                self.fetch_chunk(snapshot, index).await?;
+                log.lock()
+                    .await
+                    .write_all(format!("{}\n", index).as_bytes())
+                    .await?;
+            }
+        });
+        try_join_all(workers.collect()).await?;
+
+        file.sync_all().await?;
+        Ok(())
+    }
+
+    /// Download one chunk, retrying transient failures and data not matching
+    /// its checksum.
+    async fn fetch_chunk(&self, snapshot: &Snapshot, index: u64) -> Result<()> {
+        let range = snapshot.range(index);
+        let mut attempt = 1;
+        loop {
+            let mut written = 0;
+            let result = async {
+                let mut stream = self
+                    .client
+                    .open_download(DownloadRequest {
+                        replication_id: self.replication_id.to_string(),
+                        offset: range.0 as i64,
+                        length: range.1 as i64,
+                        snapshot_id: snapshot.id.clone(),
+                    })
+                    .await?;
+                let mut file = OpenOptions::new().write(true).open(&self.part).await?;
+                self.write_chunk(&mut file, &mut stream, None, range, &mut written)
+                    .await
+            }
+            .await;
+            match result {
+                Err(e)
+                    if (e.is_transient() || matches!(e, Error::ChecksumMismatch(_)))
+                        && attempt < CHUNK_ATTEMPTS =>
+                {
+                    warn!(
+                        "Retrying chunk {} of {} snapshot {}: {}",
+                        index, self.replication_id, snapshot.id, e
+                    );
+                    // The chunk is received again from its start
+                    self.downloaded.fetch_sub(written, Ordering::Relaxed);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Write the bytes of `range`, an offset and length, streamed by the
+    /// server, beginning with `first` if it was already received, check them
+    /// against the checksum of the range and flush them to disk. `written`
+    /// counts the bytes written so far.
+    async fn write_chunk(
+        &self,
+        file: &mut File,
+        stream: &mut Streaming<DownloadResponse>,
+        mut first: Option<DownloadResponse>,
+        (start, len): (u64, u64),
+        written: &mut u64,
+    ) -> Result<()> {
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut hasher = Sha256::new();
+        let mut expected = Vec::new();
+        loop {
+            let mut response = match first.take() {
+                Some(response) => response,
+                None => match stream.message().await? {
+                    Some(response) => response,
+                    None => break,
+                },
+            };
+            let data_len = response.data.len() as u64;
+            if response.offset != (start + *written) as i64 || *written + data_len > len {
+                return Err(invalid(format!(
+                    "got {} bytes at {} for the chunk of {} bytes at {}",
+                    data_len, response.offset, len, start
+                )));
+            }
+            if expected.is_empty() {
+                expected = std::mem::take(&mut response.checksum);
+            }
+            self.received(data_len).await;
+            hasher.update(&response.data);
+            file.write_all(&response.data).await?;
+            *written += data_len;
+        }
+        if *written != len {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "chunk at {} ended after {} of {} bytes",
+                    start, written, len
+                ),
+            )));
+        }
+        let actual = hasher.finalize();
+        if !expected.is_empty() && actual.as_slice() != expected.as_slice() {
+            return Err(Error::ChecksumMismatch(format!(
+                "chunk of {} bytes at {} of {} has SHA-256 {}, expected {}",
+                len,
+                start,
+                self.replication_id,
+                hex(&actual),
+                hex(&expected)
+            )));
+        }
+        file.flush().await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// Check the assembled file against the checksum of the snapshot, if the
+    /// server sent one, and with SQLite's integrity check if asked to.
+    async fn verify(&self, checksum: &str) -> Result<()> {
+        if !checksum.is_empty() {
+            let mut file = File::open(&self.part).await?;
+            let mut hasher = Sha256::new();
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let read = file.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            let actual = hex(&hasher.finalize());
+            if actual != checksum {
+                return Err(Error::ChecksumMismatch(format!(
+                    "{} has SHA-256 {}, expected {}",
+                    self.replication_id, actual, checksum
+                )));
+            }
+        }
+        if self.options.integrity_check {
+            integrity_check(self.part.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Record the size of the snapshot and the bytes already on disk.
+    fn start(&self, total: Option<u64>, downloaded: u64) {
+        *self.total.lock() = total;
+        self.downloaded.store(downloaded, Ordering::Relaxed);
+        self.report();
+    }
+
+    /// Account for `bytes` received, first waiting while they would exceed
+    /// the rate.
+    async fn received(&self, bytes: u64) {
+        if let Some(ref throttle) = self.throttle {
+            throttle.take(bytes).await;
+        }
+        self.downloaded.fetch_add(bytes, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::metrics::download_bytes(self.replication_id, bytes);
+        self.report();
+    }
+
+    fn report(&self) {
+        if let Some(ref progress) = self.options.progress {
+            progress(&DownloadProgress {
+                replication_id: self.replication_id.to_string(),
+                downloaded: self.downloaded.load(Ordering::Relaxed),
+                total: *self.total.lock(),
+                elapsed: self.started.elapsed(),
+            });
+        }
+    }
+}
+
+/// Fails: replicas are written with the filesystem access of the
+/// `embedded-replicas` feature, which wasm32 does not have either.
+#[cfg(not(feature = "embedded-replicas"))]
+pub(crate) async fn download(
+    _client: &HAClient,
+    _directory: &Path,
+    _replication_id: &str,
+    _options: &DownloadOptions,
+) -> Result<()> {
+    Err(Error::InvalidParameter(
+        "Replicas can only be downloaded with the `embedded-replicas` feature".to_string(),
+    ))
+}
+
+/// Run `tasks` concurrently on the current task, stopping at the first
+/// error.
+#[cfg(feature = "embedded-replicas")]
+async fn try_join_all<F>(tasks: Vec<F>) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    let mut tasks: Vec<Pin<Box<F>>> = tasks.into_iter().map(Box::pin).collect();
+    std::future::poll_fn(|cx| {
+        let mut i = 0;
+        while i < tasks.len() {
+            match tasks[i].as_mut().poll(cx) {
+                Poll::Ready(Ok(())) => drop(tasks.swap_remove(i)),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => i += 1,
+            }
+        }
+        if tasks.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Run `PRAGMA integrity_check` on a downloaded file, off the async runtime.
+#[cfg(feature = "embedded-replicas")]
+async fn integrity_check(path: PathBuf) -> Result<()> {
+    runtime::spawn_blocking(move || {
+        let conn = rusqlite::Connection::open_with_flags(
+            &path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        let problems = conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if problems == ["ok"] {
+            return Ok(());
+        }
+        Err(Error::Sqlite(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some(format!("integrity check failed: {}", problems.join("; "))),
+        )))
+    })
+    .await
+    .map_err(|e| Error::Io(io::Error::other(e)))?
+}
+
+#[cfg(feature = "embedded-replicas")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(feature = "embedded-replicas")]
+fn invalid(message: impl Into<String>) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid snapshot download: {}", message.into()),
+    ))
+}
@@ -1,27 +1,357 @@
 //! Embedded replicas manager for local SQLite replicas with NATS synchronization.
 
+#[cfg(feature = "nats")]
+use crate::cache::QueryCache;
+#[cfg(feature = "nats")]
+use crate::changes::{ChangeFilter, ChangeSubscribers, ChangeSubscription};
+use crate::client::HAClient;
+use crate::download::DownloadOptions;
+#[cfg(feature = "sqlcipher")]
+use crate::encryption::{self, ReplicaKeyProvider};
 use crate::error::{Error, Result};
+use crate::frames::Frames;
+#[cfg(feature = "nats")]
+use crate::replication::ChangeSet;
+use crate::runtime::{self, Interval, JoinHandle};
+#[cfg(feature = "nats")]
+use crate::telemetry;
 use dashmap::DashMap;
 use parking_lot::Mutex;
-use rusqlite::{Connection, OpenFlags};
+use rusqlite::backup::Backup;
+use rusqlite::{CachedStatement, Connection, OpenFlags};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::fs;
+use std::future::Future;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::oneshot;
-use tracing::{error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::sync::watch;
+#[cfg(feature = "nats")]
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+
+/// TLS settings of the NATS connection.
+///
+/// Certificates are read from PEM files when connecting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NatsTlsOptions {
+    /// Root certificates to trust
+    pub ca_cert_file: Option<PathBuf>,
+    /// Client certificate presented for mutual TLS
+    pub client_cert_file: Option<PathBuf>,
+    /// Private key of the client certificate
+    pub client_key_file: Option<PathBuf>,
+    /// Refuse servers that do not offer TLS
+    pub require_tls: bool,
+}
+
+impl NatsTlsOptions {
+    /// Trust the root certificates of a PEM bundle file.
+    pub fn with_ca_cert_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_cert_file = Some(path.into());
+        self
+    }
+
+    /// Present a client certificate read from files for mutual TLS.
+    pub fn with_client_identity_files(
+        mut self,
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> Self {
+        self.client_cert_file = Some(cert.into());
+        self.client_key_file = Some(key.into());
+        self
+    }
+
+    /// Refuse servers that do not offer TLS.
+    pub fn with_require_tls(mut self, require_tls: bool) -> Self {
+        self.require_tls = require_tls;
+        self
+    }
+}
 
 /// Options for replica configuration.
 #[derive(Debug, Clone)]
 pub struct ReplicaOptions {
     /// Directory containing replica files
     pub directory: PathBuf,
-    /// NATS server URL
+    /// NATS server URL; replicas are not replicated when empty (unused
+    /// without the `nats` feature)
     pub nats_url: String,
     /// NATS stream name
     pub stream: String,
     /// Durable consumer name
     pub durable: String,
+    /// TLS settings of the NATS connection
+    pub tls: Option<NatsTlsOptions>,
+    /// Most replication messages delivered but not yet acknowledged
+    pub max_in_flight: i64,
+    /// How long the server waits for an acknowledgement before redelivering
+    pub ack_wait: Duration,
+    /// Delays between attempts to apply a message that fails; once they are
+    /// used up the message is dropped and the replica stops replicating
+    pub retry_backoff: Vec<Duration>,
+    /// Delay before the first attempt to reconnect to NATS, doubled after
+    /// each failed attempt
+    pub reconnect_delay: Duration,
+    /// Longest delay between attempts to reconnect to NATS
+    pub max_reconnect_delay: Duration,
+    /// Attempts to reconnect to NATS before giving up; unlimited when `None`
+    pub max_reconnects: Option<usize>,
+    /// Copy each replica file into memory when loading it, then read and
+    /// replicate there without touching the file again
+    pub in_memory: bool,
+    /// Run `PRAGMA integrity_check` on replicas downloaded on demand before
+    /// attaching them
+    pub integrity_check: bool,
+    /// How often [`start_maintenance`](EmbeddedReplicasManager::start_maintenance)
+    /// compacts the replicas whose WAL outgrew `max_wal_size` and evicts
+    /// those over `max_disk_usage`; never when `None`
+    pub maintenance_interval: Option<Duration>,
+    /// Size in bytes a replica's WAL may reach before it is compacted
+    pub max_wal_size: u64,
+    /// Compact a replica by downloading a new snapshot into it, instead of
+    /// only checkpointing its WAL (needs the `nats` feature and a download
+    /// client)
+    pub resnapshot: bool,
+    /// Bytes the replica files may take up before the least recently read
+    /// are evicted, to be downloaded again on demand; unlimited when `None`
+    /// (needs a download client)
+    pub max_disk_usage: Option<u64>,
+    /// Encrypt the replica files with the keys it supplies, encrypting
+    /// plaintext files as they are loaded (see [`encryption`])
+    #[cfg(feature = "sqlcipher")]
+    pub key_provider: Option<Arc<dyn ReplicaKeyProvider>>,
+    /// Share the directory with other processes: the one holding its lock
+    /// file replicates, downloads and compacts the replicas, and the others
+    /// open them read-only until they take over (ignored with `in_memory`,
+    /// where each process replicates its own copies)
+    pub shared: bool,
+}
+
+impl Default for ReplicaOptions {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::new(),
+            nats_url: String::new(),
+            stream: "ha".to_string(),
+            durable: String::new(),
+            tls: None,
+            max_in_flight: 256,
+            ack_wait: Duration::from_secs(30),
+            retry_backoff: vec![
+                Duration::from_secs(1),
+                Duration::from_secs(5),
+                Duration::from_secs(30),
+            ],
+            reconnect_delay: Duration::from_millis(100),
+            max_reconnect_delay: Duration::from_secs(8),
+            max_reconnects: None,
+            in_memory: false,
+            integrity_check: false,
+            maintenance_interval: None,
+            max_wal_size: 64 * 1024 * 1024,
+            resnapshot: false,
+            max_disk_usage: None,
+            #[cfg(feature = "sqlcipher")]
+            key_provider: None,
+            shared: false,
+        }
+    }
+}
+
+/// A change of the state of the NATS replication feed.
+#[cfg(feature = "nats")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationEvent {
+    /// The connection to NATS was established or restored, or the
+    /// replication stream delivers messages again after failing
+    Connected,
+    /// The connection to NATS was lost, or the replication stream keeps
+    /// failing; replicas stop advancing until it is restored
+    Disconnected,
+    /// NATS reported an error
+    Error(String),
+}
+
+/// How [`EmbeddedReplicasManager::sync_replica`] brought a replica up to
+/// date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaSync {
+    /// The pages changed on the server since its txseq were fetched and
+    /// applied, this many of them
+    Incremental(u64),
+    /// The server no longer held the history back to its txseq, or the
+    /// replica no longer matched the server's pages, so a new snapshot was
+    /// downloaded and copied into the replica
+    Snapshot,
+}
+
+/// A change of the state of a replica file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicaEvent {
+    /// SQLite found the replica of `database` corrupt, so it was detached
+    /// and its file moved to `path`; its catalog is read from the server
+    Quarantined {
+        database: String,
+        path: PathBuf,
+        reason: String,
+    },
+    /// A fresh snapshot of a quarantined replica was downloaded and attached
+    Reprovisioned { database: String },
+    /// A fresh snapshot of a quarantined replica could not be downloaded
+    ReprovisionFailed { database: String, error: String },
+}
+
+/// How far a replica is behind the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicaLag {
+    /// Last transaction applied to the replica
+    pub local_txseq: i64,
+    /// Last transaction on the leader
+    pub leader_txseq: i64,
+    /// Transactions the replica is missing
+    pub behind_by: i64,
+    /// When the replica last applied a transaction, if known
+    pub last_applied_at: Option<SystemTime>,
+}
+
+/// Numbers the in-memory replica databases of the process.
+static MEMORY_DATABASES: AtomicU64 = AtomicU64::new(0);
+
+/// Directory of the replicas directory receiving the snapshots downloaded and
+/// the copies patched by [`EmbeddedReplicasManager::sync_replica`] and the
+/// files installed by [`EmbeddedReplicasManager::replace_replica`].
+const SNAPSHOT_DIR: &str = ".snapshots";
+
+/// Directory of the replicas directory receiving the files of replicas
+/// found corrupt.
+const QUARANTINE_DIR: &str = ".quarantine";
+
+/// File of the replicas directory locked by the process replicating a
+/// [shared](ReplicaOptions::shared) directory.
+const LOCK_FILE: &str = ".leader.lock";
+
+/// How often a process following a shared directory tries to take it over.
+const TAKEOVER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a file in a watched replicas directory must go unchanged before
+/// it is attached, so files still being written are left alone.
+#[cfg(feature = "watch")]
+const WATCH_SETTLE: Duration = Duration::from_secs(1);
+
+/// How long a catalog whose replica failed to download is read from the
+/// server before the download is tried again.
+const DOWNLOAD_RETRY: Duration = Duration::from_secs(30);
+
+/// Table of a replica recording the stream sequence of the last replication
+/// message applied to it.
+pub(crate) const REPLICATION_TABLE: &str = "__litesql_replication";
+
+/// Consecutive replication stream errors after which the replicas are
+/// marked stale and the consumer subscribes again.
+#[cfg(feature = "nats")]
+const MAX_STREAM_ERRORS: usize = 5;
+
+/// Prepared statements cached per replica connection by default.
+pub(crate) const STATEMENT_CACHE_CAPACITY: usize = 16;
+
+/// Prepared statement cache counters of replica reads, shared by the
+/// connections of a data source.
+#[derive(Debug, Default)]
+pub struct StatementCacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Point-in-time copy of [`StatementCacheStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatementCacheCounts {
+    /// Reads that reused a prepared statement
+    pub hits: u64,
+    /// Reads that prepared their statement
+    pub misses: u64,
+}
+
+impl StatementCacheCounts {
+    /// Get the share of reads that reused a prepared statement, or 0 before
+    /// any read.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+impl StatementCacheStats {
+    /// Create zeroed counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a snapshot of the counters.
+    pub fn snapshot(&self) -> StatementCacheCounts {
+        StatementCacheCounts {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Least recently used prepared statements of a replica connection.
+///
+/// SQLite statements are cached by rusqlite's own LRU cache; this tracks the
+/// statements it holds, which rusqlite does not expose, to count hits.
+#[derive(Debug)]
+pub(crate) struct StatementLru {
+    capacity: usize,
+    cached: VecDeque<String>,
+}
+
+impl StatementLru {
+    /// Size the statement cache of `conn` to `capacity` and track it.
+    pub(crate) fn new(conn: &Connection, capacity: usize) -> Self {
+        conn.set_prepared_statement_cache_capacity(capacity);
+        Self {
+            capacity,
+            cached: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Prepare `sql` through the statement cache of `conn`, counting whether
+    /// it was cached.
+    pub(crate) fn prepare<'c>(
+        &mut self,
+        conn: &'c Connection,
+        sql: &str,
+        stats: &StatementCacheStats,
+    ) -> rusqlite::Result<CachedStatement<'c>> {
+        let stmt = conn.prepare_cached(sql)?;
+        let sql = sql.trim();
+        match self.cached.iter().position(|cached| cached == sql) {
+            Some(i) => {
+                stats.hits.fetch_add(1, Ordering::Relaxed);
+                if let Some(cached) = self.cached.remove(i) {
+                    self.cached.push_back(cached);
+                }
+            }
+            None => {
+                stats.misses.fetch_add(1, Ordering::Relaxed);
+                if self.capacity > 0 {
+                    if self.cached.len() == self.capacity {
+                        self.cached.pop_front();
+                    }
+                    self.cached.push_back(sql.to_string());
+                }
+            }
+        }
+        Ok(stmt)
+    }
 }
 
 /// A connection to a local replica.
@@ -32,29 +362,196 @@ pub struct ReplicaConnection {
     conn: Mutex<Connection>,
     /// Transaction sequence number
     pub txseq: Mutex<i64>,
+    /// When the replica last applied a transaction
+    applied_at: Mutex<Option<SystemTime>>,
+    /// Whether a replication message could not be applied
+    failed: Mutex<bool>,
+    /// URI of the in-memory copy, when the replica was loaded into memory
+    memory_uri: Option<String>,
+    /// Number of the file the replica was loaded from, among the files of
+    /// its manager
+    generation: u64,
+    /// Whether the replica was detached from its manager, being corrupt,
+    /// evicted or replaced
+    detached: AtomicBool,
+    /// When the replica was last read, or loaded
+    last_read: Mutex<Instant>,
+    /// Key of the replica, when it is encrypted
+    key: Option<String>,
 }
 
 impl ReplicaConnection {
     /// Create a new read-only connection to the replica.
     pub fn create_connection(&self) -> Result<Connection> {
-        let conn = Connection::open_with_flags(
-            &self.dsn,
-            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
-        )?;
-        Ok(conn)
+        match self.memory_uri {
+            Some(ref uri) => open_keyed(
+                uri,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                self.key.as_deref(),
+            ),
+            None => open_keyed(
+                &self.dsn,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                self.key.as_deref(),
+            ),
+        }
+    }
+
+    /// Check if the replica was detached from its manager, because it was
+    /// found corrupt, evicted or replaced; connections reading it should
+    /// reopen the replica of their catalog.
+    pub fn is_detached(&self) -> bool {
+        self.detached.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of the file the replica was loaded from; a file
+    /// installed by [`EmbeddedReplicasManager::replace_replica`] has a
+    /// higher number than the one it replaced.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Record a read of the replica, for eviction.
+    pub(crate) fn touch(&self) {
+        *self.last_read.lock() = Instant::now();
+    }
+
+    /// Get the disk space in bytes taken up by the replica's file and WAL.
+    pub fn disk_usage(&self) -> u64 {
+        fs::metadata(&self.dsn).map(|m| m.len()).unwrap_or(0) + self.wal_size()
+    }
+
+    /// Close the replica's connection, so closing it later cannot checkpoint
+    /// into the WAL of another file at its path.
+    fn close(&self) {
+        if let Ok(closed) = Connection::open_in_memory() {
+            drop(std::mem::replace(&mut *self.conn.lock(), closed));
+        }
+    }
+
+    /// Check if the replica is read and replicated in memory.
+    pub fn is_in_memory(&self) -> bool {
+        self.memory_uri.is_some()
+    }
+
+    /// Get the size in bytes of the replica's WAL; 0 in memory or when it
+    /// has none.
+    pub fn wal_size(&self) -> u64 {
+        if self.memory_uri.is_some() {
+            return 0;
+        }
+        let mut wal = self.dsn.clone().into_os_string();
+        wal.push("-wal");
+        fs::metadata(wal).map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// Copy the WAL into the database file and truncate it, returning
+    /// `false` if readers kept part of it from being copied.
+    fn checkpoint(&self) -> Result<bool> {
+        let busy: i64 =
+            self.conn
+                .lock()
+                .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        Ok(busy == 0)
     }
 
     /// Get the transaction sequence number.
     pub fn get_txseq(&self) -> i64 {
         *self.txseq.lock()
     }
+
+    /// Read the transaction sequence number from the replica file now,
+    /// rather than waiting for the background updater.
+    pub fn refresh_txseq(&self) -> i64 {
+        let (txseq, applied_at) = EmbeddedReplicasManager::get_replica_position(&self.conn.lock());
+        self.store_position(txseq, applied_at);
+        txseq
+    }
+
+    /// Get the time the replica last applied a transaction, if known.
+    pub fn last_applied_at(&self) -> Option<SystemTime> {
+        *self.applied_at.lock()
+    }
+
+    /// Check if replication stopped because a message could not be applied.
+    ///
+    /// The replica no longer advances and has to be downloaded again.
+    pub fn replication_failed(&self) -> bool {
+        *self.failed.lock()
+    }
+
+    fn store_position(&self, txseq: i64, applied_at: Option<SystemTime>) {
+        *self.txseq.lock() = txseq;
+        *self.applied_at.lock() = applied_at;
+    }
+
+    /// Apply the change set of message `seq`, returning `false` without
+    /// applying it if the replica already has it.
+    #[cfg(feature = "nats")]
+    fn apply(&self, changes: &ChangeSet, seq: u64) -> Result<bool> {
+        if self.get_txseq() >= seq as i64 {
+            return Ok(false);
+        }
+        changes.apply(&mut self.conn.lock(), seq)?;
+        self.store_position(seq as i64, Some(SystemTime::now()));
+        Ok(true)
+    }
 }
 
 /// Manager for embedded SQLite replicas with NATS synchronization.
 pub struct EmbeddedReplicasManager {
-    replicas: DashMap<String, Arc<ReplicaConnection>>,
+    replicas: Arc<DashMap<String, Arc<ReplicaConnection>>>,
+    /// Options of the last `load`, for replicas attached later
+    options: Mutex<Option<ReplicaOptions>>,
+    /// Client downloading missing replicas on demand
+    download_client: Mutex<Option<Arc<HAClient>>>,
+    /// Catalogs downloading in the background, with the time their last
+    /// download failed
+    downloads: Mutex<HashMap<String, Option<Instant>>>,
+    /// Held while downloading, so a replica is downloaded once
+    download_lock: tokio::sync::Mutex<()>,
+    /// Held while applying a message or attaching a replica
+    #[cfg(feature = "nats")]
+    apply_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Stream sequence of the last message skipped for lack of a replica,
+    /// by file name
+    #[cfg(feature = "nats")]
+    skipped: Arc<DashMap<String, i64>>,
+    #[cfg(feature = "nats")]
     nats_connection: Mutex<Option<async_nats::Client>>,
-    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+    #[cfg(feature = "nats")]
+    replicator: Mutex<Option<JoinHandle<()>>>,
+    #[cfg(feature = "nats")]
+    events: broadcast::Sender<ReplicationEvent>,
+    replica_events: broadcast::Sender<ReplicaEvent>,
+    #[cfg(feature = "nats")]
+    subscribers: ChangeSubscribers,
+    #[cfg(feature = "nats")]
+    query_cache: Arc<Mutex<Option<Arc<QueryCache>>>>,
+    /// When the replication feed went down, `None` while it is up
+    replication_down_since: Arc<Mutex<Option<Instant>>>,
+    /// Set by `close`; the background tasks stop once it is set or the
+    /// manager is dropped
+    shutdown: watch::Sender<bool>,
+    updater: Mutex<Option<JoinHandle<()>>>,
+    maintenance: Mutex<Option<JoinHandle<()>>>,
+    /// Replicas never evicted
+    pinned: Mutex<HashSet<String>>,
+    /// Generation of the next replica loaded
+    generations: AtomicU64,
+    /// Watcher of the replicas directory, with the task attaching the files
+    /// it reports
+    #[cfg(feature = "watch")]
+    watcher: Mutex<Option<(notify::RecommendedWatcher, JoinHandle<()>)>>,
+    /// Lock file of the shared directory, while this process replicates it
+    directory_lock: Mutex<Option<fs::File>>,
+    /// Whether another process replicates the shared directory
+    following: AtomicBool,
+    takeover: Mutex<Option<JoinHandle<()>>>,
+    /// Downloads and reprovisions in the background, awaited by `close`
+    background: Mutex<Vec<JoinHandle<()>>>,
     running: Mutex<bool>,
 }
 
@@ -62,17 +559,70 @@ impl EmbeddedReplicasManager {
     /// Create a new replicas manager.
     pub fn new() -> Self {
         Self {
-            replicas: DashMap::new(),
+            replicas: Arc::new(DashMap::new()),
+            options: Mutex::new(None),
+            download_client: Mutex::new(None),
+            downloads: Mutex::new(HashMap::new()),
+            download_lock: tokio::sync::Mutex::new(()),
+            #[cfg(feature = "nats")]
+            apply_lock: Arc::new(tokio::sync::Mutex::new(())),
+            #[cfg(feature = "nats")]
+            skipped: Arc::new(DashMap::new()),
+            #[cfg(feature = "nats")]
             nats_connection: Mutex::new(None),
-            shutdown_tx: Mutex::new(None),
+            #[cfg(feature = "nats")]
+            replicator: Mutex::new(None),
+            #[cfg(feature = "nats")]
+            events: broadcast::channel(16).0,
+            replica_events: broadcast::channel(16).0,
+            #[cfg(feature = "nats")]
+            subscribers: ChangeSubscribers::default(),
+            #[cfg(feature = "nats")]
+            query_cache: Arc::new(Mutex::new(None)),
+            replication_down_since: Arc::new(Mutex::new(None)),
+            shutdown: watch::channel(false).0,
+            updater: Mutex::new(None),
+            maintenance: Mutex::new(None),
+            pinned: Mutex::new(HashSet::new()),
+            generations: AtomicU64::new(0),
+            #[cfg(feature = "watch")]
+            watcher: Mutex::new(None),
+            directory_lock: Mutex::new(None),
+            following: AtomicBool::new(false),
+            takeover: Mutex::new(None),
+            background: Mutex::new(Vec::new()),
             running: Mutex::new(false),
         }
     }
 
     /// Load replicas from a directory and connect to NATS for replication.
+    ///
+    /// With the `nats` feature and a `nats_url`, a JetStream consumer named
+    /// `durable` on `stream` applies the change sets published by the HA
+    /// server to the replicas as they arrive. Without either the replicas are
+    /// only opened and their txseq tracked; keeping the files up to date is
+    /// left to the caller.
+    ///
+    /// With [`in_memory`](ReplicaOptions::in_memory) set, each file is copied
+    /// into an in-memory database that reads and replication use instead, so
+    /// the directory may sit on a disk that does not outlive the process.
+    ///
+    /// With a [download client](Self::set_download_client) set, the
+    /// directory is created if missing, and the replicas it lacks can be
+    /// downloaded later.
+    ///
+    /// With [`shared`](ReplicaOptions::shared) set, the process that locks
+    /// the directory's lock file leads: it replicates, downloads, compacts
+    /// and evicts. The other processes follow: they open the replicas
+    /// read-only, track their txseq as the leader advances it, and attach
+    /// the files it downloads, until [`start_takeover`](Self::start_takeover)
+    /// finds the lock released. All of them should use the same `durable`.
     pub async fn load(&self, options: ReplicaOptions) -> Result<()> {
         let directory = &options.directory;
 
+        if self.download_client.lock().is_some() {
+            runtime::create_dir_all(directory).await?;
+        }
         if !directory.exists() || !directory.is_dir() {
             return Err(Error::InvalidParameter(format!(
                 "Invalid directory: {:?}",
@@ -80,9 +630,27 @@ impl EmbeddedReplicasManager {
             )));
         }
 
+        if options.shared && !options.in_memory {
+            let lock = Self::try_lock_directory(directory)?;
+            if lock.is_none() {
+                info!(
+                    "Following the process replicating {:?}: replicas are read-only",
+                    directory
+                );
+            }
+            self.following.store(lock.is_none(), Ordering::Relaxed);
+            *self.directory_lock.lock() = lock;
+        }
+
         // Connect to NATS
-        let nats_client = async_nats::connect(&options.nats_url).await?;
-        *self.nats_connection.lock() = Some(nats_client);
+        #[cfg(feature = "nats")]
+        if self.is_leader() && !options.nats_url.is_empty() {
+            let nats_client = self
+                .connect_options(&options)
+                .connect(&options.nats_url)
+                .await?;
+            *self.nats_connection.lock() = Some(nats_client);
+        }
 
         for entry in fs::read_dir(directory)? {
             let entry = entry?;
@@ -101,11 +669,11 @@ impl EmbeddedReplicasManager {
                 continue;
             }
 
-            if !Self::is_sqlite_file(&path) {
+            if !Self::is_replica_file(&path, &file_name, &options) {
                 continue;
             }
 
-            match self.load_replica(&path, &file_name).await {
+            match self.load_replica(&path, &file_name, &options).await {
                 Ok(replica) => {
                     self.replicas.insert(file_name.clone(), Arc::new(replica));
                     info!("Loaded replica: {}", file_name);
@@ -116,64 +684,624 @@ impl EmbeddedReplicasManager {
             }
         }
 
+        *self.options.lock() = Some(options.clone());
+        self.shutdown.send_replace(false);
         *self.running.lock() = true;
         self.start_txseq_updater();
+        #[cfg(feature = "nats")]
+        if self.is_leader() {
+            self.start_replicator(&options).await?;
+        }
 
         Ok(())
     }
 
-    async fn load_replica(&self, path: &Path, _name: &str) -> Result<ReplicaConnection> {
-        let conn = Connection::open_with_flags(
-            path,
-            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
-        )?;
+    /// Lock the lock file of `directory`, or get `None` if another process
+    /// holds it.
+    fn try_lock_directory(directory: &Path) -> Result<Option<fs::File>> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(directory.join(LOCK_FILE))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(file)),
+            Err(fs::TryLockError::WouldBlock) => Ok(None),
+            Err(fs::TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Check if this process replicates its replicas: always, unless it
+    /// follows another process in a [shared](ReplicaOptions::shared)
+    /// directory.
+    pub fn is_leader(&self) -> bool {
+        !self.following.load(Ordering::Relaxed)
+    }
+
+    /// Try every few seconds to take over a [shared](ReplicaOptions::shared)
+    /// directory this process follows, until the leading process releases
+    /// it by closing or exiting, or until [`close`](Self::close). Does
+    /// nothing when this process leads or if the task is already running.
+    ///
+    /// Taking over reopens the replicas read-write, so connections reading
+    /// them reopen too, then starts replication.
+    pub fn start_takeover(self: &Arc<Self>) {
+        if self.is_leader() {
+            return;
+        }
+        let mut takeover = self.takeover.lock();
+        if takeover.is_some() {
+            return;
+        }
+
+        let manager = Arc::downgrade(self);
+        let mut shutdown = self.shutdown.subscribe();
+        let handle = runtime::spawn_named(runtime::REPLICA_TAKEOVER, async move {
+            let mut interval = Interval::new(TAKEOVER_INTERVAL);
+            loop {
+                runtime::select! {
+                    _ = interval.tick() => {}
+                    _ = closed(&mut shutdown) => break,
+                }
+
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                match manager.take_over().await {
+                    Ok(true) => break,
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to take over the replicas: {}", e),
+                }
+            }
+        });
+        *takeover = Some(handle);
+    }
+
+    /// Lead the shared directory if its lock is free, returning whether
+    /// this process leads.
+    async fn take_over(&self) -> Result<bool> {
+        if self.is_leader() {
+            return Ok(true);
+        }
+        let options = match self.options.lock().clone() {
+            Some(options) if self.is_running() => options,
+            _ => return Ok(false),
+        };
+        let lock = match Self::try_lock_directory(&options.directory)? {
+            Some(lock) => lock,
+            None => return Ok(false),
+        };
+
+        {
+            let _downloading = self.download_lock.lock().await;
+            #[cfg(feature = "nats")]
+            let _applying = self.apply_lock.lock().await;
+            *self.directory_lock.lock() = Some(lock);
+            self.following.store(false, Ordering::Relaxed);
+
+            let replicas: Vec<_> = self
+                .replicas
+                .iter()
+                .map(|e| (e.key().clone(), e.value().clone()))
+                .collect();
+            for (db_name, old) in replicas {
+                match self.load_replica(&old.dsn, &db_name, &options).await {
+                    Ok(replica) => {
+                        self.replicas.insert(db_name, Arc::new(replica));
+                        old.detached.store(true, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        error!("Failed to reopen replica {}: {}", db_name, e);
+                        self.detach(&db_name, &old);
+                    }
+                }
+            }
+        }
+        // Downloads failed as a follower can be tried right away
+        self.downloads
+            .lock()
+            .retain(|_, failed_at| failed_at.is_none());
+        info!("Took over the replicas in {:?}", options.directory);
+
+        #[cfg(feature = "nats")]
+        if !options.nats_url.is_empty() {
+            let nats_client = self
+                .connect_options(&options)
+                .connect(&options.nats_url)
+                .await?;
+            *self.nats_connection.lock() = Some(nats_client);
+            self.start_replicator(&options).await?;
+        }
+        Ok(true)
+    }
+
+    async fn load_replica(
+        &self,
+        path: &Path,
+        name: &str,
+        options: &ReplicaOptions,
+    ) -> Result<ReplicaConnection> {
+        let key = Self::replica_key(name, options)?;
+        #[cfg(feature = "sqlcipher")]
+        if let Some(ref key) = key {
+            if self.is_leader() && Self::is_sqlite_file(path) {
+                let staging = options.directory.join(SNAPSHOT_DIR);
+                runtime::create_dir_all(&staging).await?;
+                encryption::encrypt_file(path, &staging.join(format!("{}.encrypted", name)), key)?;
+                info!("Encrypted replica {}", name);
+            }
+        }
+
+        let (conn, memory_uri) = if options.in_memory {
+            // A memdb database whose name starts with '/' is shared by the
+            // connections of the process, and lives while one is open.
+            let id = MEMORY_DATABASES.fetch_add(1, Ordering::Relaxed);
+            let uri = format!(
+                "file:/litesql-ha-{}/{}?vfs=memdb",
+                id,
+                name.replace('%', "%25")
+                    .replace('?', "%3f")
+                    .replace('#', "%23")
+            );
+            let conn = open_keyed(
+                &uri,
+                OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                key.as_deref(),
+            )?;
+            // Unlike a page copy, VACUUM INTO leaves the copy out of WAL
+            // mode, which memdb cannot open. The copy of an encrypted file
+            // is encrypted with the same key.
+            let file = open_keyed(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                key.as_deref(),
+            )?;
+            file.execute("VACUUM INTO ?1", [&uri])?;
+            (conn, Some(uri))
+        } else if self.is_leader() {
+            let conn = open_keyed(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                key.as_deref(),
+            )?;
+            conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+            (conn, None)
+        } else {
+            let conn = open_keyed(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                key.as_deref(),
+            )?;
+            (conn, None)
+        };
 
         conn.execute_batch(
-            "PRAGMA journal_mode = WAL;
-             PRAGMA temp_store = MEMORY;
+            "PRAGMA temp_store = MEMORY;
              PRAGMA busy_timeout = 5000;",
         )?;
 
-        let txseq = Self::get_replica_txseq(&conn);
+        let (txseq, applied_at) = Self::get_replica_position(&conn);
 
         Ok(ReplicaConnection {
             dsn: path.to_path_buf(),
             conn: Mutex::new(conn),
             txseq: Mutex::new(txseq),
+            applied_at: Mutex::new(applied_at),
+            failed: Mutex::new(false),
+            memory_uri,
+            generation: self.generations.fetch_add(1, Ordering::Relaxed),
+            detached: AtomicBool::new(false),
+            last_read: Mutex::new(Instant::now()),
+            key,
         })
     }
 
-    fn get_replica_txseq(conn: &Connection) -> i64 {
+    /// Read the last received txseq and the time it was applied, in Unix
+    /// seconds, from the position replication recorded, or else from the
+    /// `ha_stats` table the replica was downloaded with.
+    fn get_replica_position(conn: &Connection) -> (i64, Option<SystemTime>) {
+        let position = |row: &rusqlite::Row| {
+            let updated_at: Option<i64> = row.get(1)?;
+            let applied_at = updated_at
+                .and_then(|secs| u64::try_from(secs).ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            Ok((row.get(0)?, applied_at))
+        };
         conn.query_row(
-            "SELECT received_seq FROM ha_stats ORDER BY updated_at DESC LIMIT 1",
+            &format!("SELECT stream_seq, applied_at FROM {}", REPLICATION_TABLE),
             [],
-            |row| row.get(0),
+            position,
         )
-        .unwrap_or(0)
+        .or_else(|_| {
+            conn.query_row(
+                "SELECT received_seq, updated_at FROM ha_stats ORDER BY updated_at DESC LIMIT 1",
+                [],
+                position,
+            )
+        })
+        .unwrap_or((0, None))
     }
 
     fn start_txseq_updater(&self) {
+        if self.updater.lock().is_some() {
+            return;
+        }
+
         let replicas = self.replicas.clone();
-        let running = Arc::new(self.running.lock().clone());
+        let mut shutdown = self.shutdown.subscribe();
 
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+        let handle = runtime::spawn_named(runtime::TXSEQ_UPDATER, async move {
+            let mut interval = Interval::new(std::time::Duration::from_secs(5));
 
             loop {
-                interval.tick().await;
-
-                if !*running {
-                    break;
+                runtime::select! {
+                    _ = interval.tick() => {}
+                    _ = closed(&mut shutdown) => break,
                 }
 
                 for entry in replicas.iter() {
                     let replica = entry.value();
-                    let conn = replica.conn.lock();
-                    let txseq = Self::get_replica_txseq(&conn);
-                    *replica.txseq.lock() = txseq;
+                    let (txseq, applied_at) = Self::get_replica_position(&replica.conn.lock());
+                    replica.store_position(txseq, applied_at);
+                }
+            }
+        });
+
+        *self.updater.lock() = Some(handle);
+    }
+
+    /// Options of the NATS connection.
+    ///
+    /// Connection state changes are published as [`ReplicationEvent`]s and
+    /// tracked for [`is_replication_connected`](Self::is_replication_connected).
+    #[cfg(feature = "nats")]
+    fn connect_options(&self, options: &ReplicaOptions) -> async_nats::ConnectOptions {
+        use async_nats::Event;
+
+        let events = self.events.clone();
+        let down_since = self.replication_down_since.clone();
+        let (first_delay, max_delay) = (options.reconnect_delay, options.max_reconnect_delay);
+        let mut connect = async_nats::ConnectOptions::new()
+            .max_reconnects(options.max_reconnects)
+            .reconnect_delay_callback(move |attempts| {
+                let doublings = attempts.saturating_sub(1).min(31) as u32;
+                first_delay.saturating_mul(1 << doublings).min(max_delay)
+            })
+            .event_callback(move |event| {
+                let event = match event {
+                    Event::Connected => {
+                        *down_since.lock() = None;
+                        ReplicationEvent::Connected
+                    }
+                    Event::Disconnected => {
+                        warn!("NATS replication feed {}", event);
+                        down_since.lock().get_or_insert_with(Instant::now);
+                        ReplicationEvent::Disconnected
+                    }
+                    Event::ServerError(e) => ReplicationEvent::Error(e.to_string()),
+                    Event::ClientError(e) => ReplicationEvent::Error(e.to_string()),
+                    _ => return std::future::ready(()),
+                };
+                let _ = events.send(event);
+                std::future::ready(())
+            });
+        if let Some(ref tls) = options.tls {
+            connect = connect.require_tls(tls.require_tls);
+            if let Some(ref ca_cert) = tls.ca_cert_file {
+                connect = connect.add_root_certificates(ca_cert.clone());
+            }
+            if let (Some(cert), Some(key)) = (&tls.client_cert_file, &tls.client_key_file) {
+                connect = connect.add_client_certificate(cert.clone(), key.clone());
+            }
+        }
+        connect
+    }
+
+    /// Subscribe to the replication stream and apply its messages in the
+    /// background.
+    ///
+    /// The durable pull consumer acknowledges every message explicitly, so
+    /// messages not acknowledged before a restart are delivered again. A new
+    /// consumer starts after the oldest replica's txseq.
+    ///
+    /// Stream errors are retried after the `retry_backoff` delays. After
+    /// [`MAX_STREAM_ERRORS`] in a row the replicas count as disconnected
+    /// until a message arrives, and the consumer subscribes again.
+    #[cfg(feature = "nats")]
+    async fn start_replicator(&self, options: &ReplicaOptions) -> Result<()> {
+        use async_nats::jetstream::consumer::{pull, AckPolicy, DeliverPolicy, PullConsumer};
+
+        if self.replicator.lock().is_some() {
+            return Ok(());
+        }
+        let client = match self.nats_connection.lock().clone() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        let oldest = self.replicas.iter().map(|e| e.value().get_txseq()).min();
+        let deliver_policy = match oldest {
+            Some(txseq) if txseq > 0 => DeliverPolicy::ByStartSequence {
+                start_sequence: txseq as u64 + 1,
+            },
+            _ => DeliverPolicy::All,
+        };
+
+        let jetstream = async_nats::jetstream::new(client);
+        let stream = jetstream
+            .get_stream(&options.stream)
+            .await
+            .map_err(nats_error)?;
+        let consumer: PullConsumer = stream
+            .get_or_create_consumer(
+                &options.durable,
+                pull::Config {
+                    durable_name: Some(options.durable.clone()),
+                    deliver_policy,
+                    ack_policy: AckPolicy::Explicit,
+                    ack_wait: options.ack_wait,
+                    max_ack_pending: options.max_in_flight,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(nats_error)?;
+        let mut messages = consumer.messages().await.map_err(nats_error)?;
+
+        let replicas = self.replicas.clone();
+        let subscribers = self.subscribers.clone();
+        let query_cache = self.query_cache.clone();
+        let apply_lock = self.apply_lock.clone();
+        // Messages for replicas not attached yet are remembered when they
+        // may be downloaded later, instead of going to a lone replica
+        let skipped = self
+            .download_client
+            .lock()
+            .as_ref()
+            .map(|_| self.skipped.clone());
+        let backoff = options.retry_backoff.clone();
+        let events = self.events.clone();
+        let down_since = self.replication_down_since.clone();
+        let mut shutdown = self.shutdown.subscribe();
+        let handle = runtime::spawn_named(runtime::REPLICATOR, async move {
+            // Consecutive stream errors, and whether they marked the replicas
+            // stale
+            let mut errors = 0;
+            let mut stalled = false;
+            loop {
+                let message = runtime::select! {
+                    message = messages.next() => message,
+                    _ = closed(&mut shutdown) => break,
+                };
+                match message {
+                    Some(Ok(message)) => {
+                        errors = 0;
+                        if std::mem::take(&mut stalled) {
+                            info!("Replication stream resumed");
+                            *down_since.lock() = None;
+                            let _ = events.send(ReplicationEvent::Connected);
+                        }
+                        let _applying = apply_lock.lock().await;
+                        runtime::select! {
+                            _ = Self::replicate(&replicas, &subscribers, &query_cache, skipped.as_deref(), &message, &backoff) => {}
+                            _ = closed(&mut shutdown) => break,
+                        }
+                    }
+                    Some(Err(e)) => {
+                        errors += 1;
+                        let _ = events.send(ReplicationEvent::Error(e.to_string()));
+                        let delay = backoff
+                            .get(errors - 1)
+                            .or(backoff.last())
+                            .copied()
+                            .unwrap_or(Duration::from_secs(1));
+                        warn!("Replication stream error, retrying in {:?}: {}", delay, e);
+                        runtime::select! {
+                            _ = runtime::sleep(delay) => {}
+                            _ = closed(&mut shutdown) => break,
+                        }
+                        if errors < MAX_STREAM_ERRORS {
+                            continue;
+                        }
+
+                        // Reads stop trusting the replicas until messages
+                        // flow again, and a new subscription replaces the
+                        // failing one
+                        if !stalled {
+                            error!(
+                                "Replication stream failed {} times in a row, resubscribing",
+                                errors
+                            );
+                            stalled = true;
+                            down_since.lock().get_or_insert_with(Instant::now);
+                            let _ = events.send(ReplicationEvent::Disconnected);
+                        }
+                        match consumer.messages().await {
+                            Ok(resubscribed) => {
+                                messages = resubscribed;
+                                errors = 0;
+                            }
+                            Err(e) => {
+                                warn!("Failed to resubscribe to the replication stream: {}", e)
+                            }
+                        }
+                    }
+                    None => break,
                 }
             }
         });
+
+        *self.replicator.lock() = Some(handle);
+        Ok(())
+    }
+
+    /// Invalidate the cached queries and notify the change subscribers of a
+    /// change set applied to `replica` as message `seq`.
+    #[cfg(feature = "nats")]
+    fn publish_applied(
+        replica: &ReplicaConnection,
+        changes: &ChangeSet,
+        seq: u64,
+        subscribers: &ChangeSubscribers,
+        query_cache: &Mutex<Option<Arc<QueryCache>>>,
+    ) {
+        let database = replica.dsn.file_name().unwrap_or_default();
+        let database = database.to_string_lossy();
+        let query_cache = query_cache.lock().clone();
+        if let Some(cache) = query_cache {
+            match changes.changed_tables(&replica.conn.lock()) {
+                Ok(tables) => cache.replicated_change(&database, tables.as_ref(), seq as i64),
+                Err(e) => {
+                    warn!("Invalidating the query cache of {}: {}", database, e);
+                    cache.replicated_change(&database, None, seq as i64);
+                }
+            }
+        }
+        subscribers.publish(&database, changes, seq as i64);
+    }
+
+    /// Apply one replication message and acknowledge it.
+    ///
+    /// Messages are applied one at a time in stream order. Messages at or
+    /// below the replica's txseq were already applied, as the txseq is
+    /// recorded in the same transaction, and are only acknowledged. A message
+    /// that fails is retried after each delay of `backoff`, extending its
+    /// acknowledgement deadline meanwhile; if it still fails it is dropped
+    /// and the replica stops replicating rather than skip a transaction.
+    ///
+    /// Messages for a file without a replica go to the only replica, if
+    /// there is one, unless `skipped` is given to record them in.
+    #[cfg(feature = "nats")]
+    async fn replicate(
+        replicas: &DashMap<String, Arc<ReplicaConnection>>,
+        subscribers: &ChangeSubscribers,
+        query_cache: &Mutex<Option<Arc<QueryCache>>>,
+        skipped: Option<&DashMap<String, i64>>,
+        message: &async_nats::jetstream::Message,
+        backoff: &[Duration],
+    ) {
+        use async_nats::jetstream::AckKind;
+
+        let seq = match message.info() {
+            Ok(info) => info.stream_sequence,
+            Err(e) => {
+                error!("Replication message without stream info: {}", e);
+                return;
+            }
+        };
+        let changes = match ChangeSet::parse(&message.payload) {
+            Ok(changes) => changes,
+            Err(e) => {
+                error!("Dropping replication message {}: {}", seq, e);
+                let _ = message.ack_with(AckKind::Term).await;
+                return;
+            }
+        };
+
+        let replica = replicas.get(changes.file_name()).map(|e| e.value().clone());
+        let replica = match replica {
+            Some(replica) => replica,
+            None if replicas.len() == 1 && skipped.is_none() => match replicas.iter().next() {
+                Some(entry) => entry.value().clone(),
+                None => return,
+            },
+            None => {
+                if let Some(skipped) = skipped {
+                    skipped.insert(changes.file_name().to_string(), seq as i64);
+                }
+                let _ = message.ack().await;
+                return;
+            }
+        };
+        if replica.replication_failed() || replica.get_txseq() >= seq as i64 {
+            let _ = message.ack().await;
+            return;
+        }
+
+        let span = telemetry::replicate_span(changes.file_name(), &message.subject, seq);
+        let changes = Arc::new(changes);
+        let mut delays = backoff.iter();
+        loop {
+            // The write holds the replica's connection, so keep it off the
+            // async workers
+            let result = {
+                let (replica, changes, span) = (replica.clone(), changes.clone(), span.clone());
+                runtime::spawn_blocking(move || span.in_scope(|| replica.apply(&changes, seq)))
+                    .await
+                    .unwrap_or_else(|e| Err(Error::Io(std::io::Error::other(e))))
+            };
+            let e = match result {
+                Ok(applied) => {
+                    if applied {
+                        Self::publish_applied(&replica, &changes, seq, subscribers, query_cache);
+                    }
+                    if let Err(e) = message.ack().await {
+                        error!("Failed to acknowledge replication message {}: {}", seq, e);
+                    }
+                    return;
+                }
+                Err(e) => e,
+            };
+            match delays.next() {
+                Some(delay) => {
+                    warn!("Retrying replication message {} in {:?}: {}", seq, delay, e);
+                    let _ = message.ack_with(AckKind::Progress).await;
+                    runtime::sleep(*delay).await;
+                }
+                None => {
+                    error!(
+                        "Replication of {} stopped at message {}: {}",
+                        replica.dsn.display(),
+                        seq,
+                        e
+                    );
+                    telemetry::fail(&span, &e);
+                    *replica.failed.lock() = true;
+                    let _ = message.ack_with(AckKind::Term).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Apply a replication message, the change set published as stream
+    /// sequence `seq`, to the replica of the file it names, as the NATS
+    /// consumer does. Returns `false` if the replica already has it.
+    ///
+    /// For replicas loaded without a NATS URL and kept up to date by other
+    /// means, such as tests.
+    #[cfg(feature = "nats")]
+    pub async fn apply_message(&self, payload: &[u8], seq: u64) -> Result<bool> {
+        let changes = Arc::new(ChangeSet::parse(payload)?);
+        let replica = self
+            .replicas
+            .get(changes.file_name())
+            .map(|e| e.value().clone())
+            .ok_or_else(|| {
+                Error::InvalidParameter(format!("No replica loaded for {:?}", changes.file_name()))
+            })?;
+        let _applying = self.apply_lock.lock().await;
+        let applied = {
+            let (replica, changes) = (replica.clone(), changes.clone());
+            runtime::spawn_blocking(move || replica.apply(&changes, seq))
+                .await
+                .map_err(|e| Error::Io(std::io::Error::other(e)))??
+        };
+        if applied {
+            Self::publish_applied(
+                &replica,
+                &changes,
+                seq,
+                &self.subscribers,
+                &self.query_cache,
+            );
+        }
+        Ok(applied)
     }
 
     /// Get a replica by database name.
@@ -184,6 +1312,32 @@ impl EmbeddedReplicasManager {
         self.replicas.get(db_name).map(|e| e.value().clone())
     }
 
+    /// Get the database names of the loaded replicas, sorted.
+    pub fn replica_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.replicas.iter().map(|e| e.key().clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// Measure how far the replica of `db_name` is behind the leader, asking
+    /// the server through `client` for the leader's txseq.
+    ///
+    /// The local txseq is read from the replica file, not from the last
+    /// background update.
+    pub async fn lag(&self, db_name: &str, client: &HAClient) -> Result<ReplicaLag> {
+        let replica = self.get_replica(db_name).ok_or_else(|| {
+            Error::InvalidParameter(format!("No replica loaded for {:?}", db_name))
+        })?;
+        let leader_txseq = client.leader_txseq(db_name).await?;
+        let local_txseq = replica.refresh_txseq();
+        Ok(ReplicaLag {
+            local_txseq,
+            leader_txseq,
+            behind_by: (leader_txseq - local_txseq).max(0),
+            last_applied_at: replica.last_applied_at(),
+        })
+    }
+
     /// Create a new read-only connection to a replica.
     pub fn create_connection(&self, db_name: &str) -> Option<Connection> {
         self.get_replica(db_name)
@@ -197,6 +1351,1001 @@ impl EmbeddedReplicasManager {
             .unwrap_or(false)
     }
 
+    /// Download replicas missing from the directory with `client` on
+    /// demand; call before [`load`](Self::load).
+    pub fn set_download_client(&self, client: Arc<HAClient>) {
+        *self.download_client.lock() = Some(client);
+    }
+
+    /// Download the replica of `db_name` into the replicas directory,
+    /// attach it and replicate to it.
+    ///
+    /// Needs a [download client](Self::set_download_client) and loaded
+    /// replicas. A replica already attached is returned as is. A download
+    /// older than changes replication has already skipped for lack of the
+    /// replica is downloaded again, once. A process following a
+    /// [shared](ReplicaOptions::shared) directory does not download: it
+    /// attaches the file the leading process downloaded, if any.
+    pub async fn download_replica(&self, db_name: &str) -> Result<Arc<ReplicaConnection>> {
+        if Path::new(db_name).file_name().and_then(|n| n.to_str()) != Some(db_name) {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid replica name: {:?}",
+                db_name
+            )));
+        }
+        let client = self.download_client.lock().clone();
+        let options = self.options.lock().clone();
+        let (client, options) = match (client, options) {
+            (Some(client), Some(options)) if self.is_running() => (client, options),
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "Replicas are not downloaded on demand".to_string(),
+                ))
+            }
+        };
+
+        let _downloading = self.download_lock.lock().await;
+        let path = options.directory.join(db_name);
+        let mut retried = false;
+        let replica = loop {
+            if let Some(replica) = self.replicas.get(db_name) {
+                return Ok(replica.value().clone());
+            }
+            if self.is_leader() {
+                client
+                    .download_replica_with(
+                        &options.directory,
+                        db_name,
+                        true,
+                        &DownloadOptions::new().with_integrity_check(options.integrity_check),
+                    )
+                    .await?;
+            } else if !path.is_file() || !Self::is_replica_file(&path, db_name, &options) {
+                return Err(Error::InvalidParameter(format!(
+                    "Replica {} is not downloaded yet by the process replicating {:?}",
+                    db_name, options.directory
+                )));
+            }
+
+            #[cfg(feature = "nats")]
+            let _applying = self.apply_lock.lock().await;
+            let replica = self.load_replica(&path, db_name, &options).await?;
+            match self.skipped_since(db_name, replica.get_txseq()) {
+                Some(seq) if !retried => {
+                    warn!(
+                        "Downloading replica {} again: it misses message {}",
+                        db_name, seq
+                    );
+                    retried = true;
+                }
+                Some(seq) => {
+                    return Err(Error::Nats(format!(
+                        "Downloaded replica {} misses replicated message {}",
+                        db_name, seq
+                    )))
+                }
+                None => {
+                    let replica = Arc::new(replica);
+                    self.replicas.insert(db_name.to_string(), replica.clone());
+                    info!("Downloaded replica: {}", db_name);
+                    break replica;
+                }
+            }
+        };
+        self.evict_replicas_except(Some(db_name)).await;
+        Ok(replica)
+    }
+
+    /// Install the database at `new_path` as the replica of `db_name`,
+    /// such as a refreshed snapshot, returning the new replica.
+    ///
+    /// The file is moved into the replicas directory, or copied when it is
+    /// on another file system, and swapped in under a new
+    /// [generation](ReplicaConnection::generation). Reads already running on
+    /// the replica it replaces finish there; connections move to the new
+    /// file at their next read. A file behind the replica it replaces lacks
+    /// the changes in between until [synced](Self::sync_replica).
+    ///
+    /// The file must not have a WAL; checkpoint it first. A process
+    /// following a [shared](ReplicaOptions::shared) directory cannot
+    /// replace replicas.
+    pub async fn replace_replica(
+        &self,
+        db_name: &str,
+        new_path: impl AsRef<Path>,
+    ) -> Result<Arc<ReplicaConnection>> {
+        let new_path = new_path.as_ref();
+        if Path::new(db_name).file_name().and_then(|n| n.to_str()) != Some(db_name) {
+            return Err(Error::InvalidParameter(format!(
+                "Invalid replica name: {:?}",
+                db_name
+            )));
+        }
+        let options = match self.options.lock().clone() {
+            Some(options) if self.is_running() => options,
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "Replicas are not loaded".to_string(),
+                ))
+            }
+        };
+        if !self.is_leader() {
+            return Err(Error::InvalidParameter(format!(
+                "Replicas are replaced by the process replicating {:?}",
+                options.directory
+            )));
+        }
+        if !Self::is_replica_file(new_path, db_name, &options) {
+            return Err(Error::InvalidParameter(format!(
+                "Not a SQLite database: {:?}",
+                new_path
+            )));
+        }
+        let mut wal = new_path.as_os_str().to_os_string();
+        wal.push("-wal");
+        if fs::metadata(&wal).is_ok_and(|m| m.len() > 0) {
+            return Err(Error::InvalidParameter(format!(
+                "{:?} has a WAL; checkpoint it first",
+                new_path
+            )));
+        }
+
+        // Stage the file next to the replicas, so the swap is a rename
+        let staging = options.directory.join(SNAPSHOT_DIR);
+        runtime::create_dir_all(&staging).await?;
+        let staged = staging.join(db_name);
+        if runtime::rename(new_path, &staged).await.is_err() {
+            runtime::copy(new_path, &staged).await?;
+        }
+
+        #[cfg(feature = "nats")]
+        let _applying = self.apply_lock.lock().await;
+        let path = options.directory.join(db_name);
+        let old = self.get_replica(db_name);
+        if let Some(ref old) = old {
+            old.close();
+        }
+        for suffix in ["-wal", "-shm"] {
+            let mut file = path.as_os_str().to_os_string();
+            file.push(suffix);
+            let _ = runtime::remove_file(&file).await;
+        }
+        runtime::rename(&staged, &path).await?;
+
+        let replica = match self.load_replica(&path, db_name, &options).await {
+            Ok(replica) => Arc::new(replica),
+            Err(e) => {
+                if let Some(ref old) = old {
+                    self.detach(db_name, old);
+                }
+                return Err(e);
+            }
+        };
+        self.replicas.insert(db_name.to_string(), replica.clone());
+        if let Some(old) = old {
+            old.detached.store(true, Ordering::Relaxed);
+        }
+        #[cfg(feature = "nats")]
+        {
+            let query_cache = self.query_cache.lock().clone();
+            if let Some(cache) = query_cache {
+                cache.replicated_change(db_name, None, replica.get_txseq());
+            }
+        }
+        info!(
+            "Replaced replica {} with generation {}",
+            db_name,
+            replica.generation()
+        );
+        Ok(replica)
+    }
+
+    /// Bring the replica of `db_name` up to date with the server, also
+    /// repairing a replica whose replication failed.
+    ///
+    /// Only the pages changed since the replica's txseq are fetched with the
+    /// [download client](Self::set_download_client), written over a copy of
+    /// the replica and checked against the server's checksum. When the
+    /// server no longer holds the history back to that txseq, or the patched
+    /// copy does not match it, a new snapshot is downloaded instead. Either
+    /// is copied into the replica in place, so connections reading it see
+    /// the changes without reopening it. Replication waits until the sync is
+    /// done.
+    pub async fn sync_replica(&self, db_name: &str) -> Result<ReplicaSync> {
+        let replica = self
+            .replicas
+            .get(db_name)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| Error::InvalidParameter(format!("No replica of {}", db_name)))?;
+        let options = self.options.lock().clone();
+        let options = match options {
+            Some(options) if self.is_running() => options,
+            _ => {
+                return Err(Error::InvalidParameter(
+                    "Replicas are not loaded".to_string(),
+                ))
+            }
+        };
+        let download_client = self.download_client.lock().clone().ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "Replica {} cannot be synced without a download client",
+                db_name
+            ))
+        })?;
+
+        #[cfg(feature = "nats")]
+        let _applying = self.apply_lock.lock().await;
+        let txseq = replica.refresh_txseq();
+        if let Some(pages) = self
+            .patch(&replica, db_name, &download_client, &options, txseq)
+            .await?
+        {
+            *replica.failed.lock() = false;
+            info!("Synced replica {}: {} pages", db_name, pages);
+            return Ok(ReplicaSync::Incremental(pages));
+        }
+
+        self.resync(&replica, db_name, &download_client, &options)
+            .await?;
+        *replica.failed.lock() = false;
+        info!("Synced replica {} from a snapshot", db_name);
+        Ok(ReplicaSync::Snapshot)
+    }
+
+    /// Download a snapshot of `db_name` and copy it into `replica`. The
+    /// caller holds the apply lock.
+    async fn resync(
+        &self,
+        replica: &ReplicaConnection,
+        db_name: &str,
+        download_client: &HAClient,
+        options: &ReplicaOptions,
+    ) -> Result<()> {
+        info!("Downloading a snapshot of replica {}", db_name);
+        let snapshots = options.directory.join(SNAPSHOT_DIR);
+        download_client
+            .download_replica_with(
+                &snapshots,
+                db_name,
+                true,
+                &DownloadOptions::new().with_integrity_check(options.integrity_check),
+            )
+            .await?;
+        let snapshot = snapshots.join(db_name);
+        let restored = Self::restore(replica, &snapshot, db_name, options);
+        let _ = runtime::remove_file(&snapshot).await;
+        restored?;
+        self.restored(replica, db_name);
+        Ok(())
+    }
+
+    /// Apply the pages of `db_name` changed on the server since `txseq` to
+    /// `replica`, returning how many, or `None` if the server cannot send
+    /// them, sends them without a checksum or at another page size, or they
+    /// do not turn the replica into the server's snapshot. The caller holds
+    /// the apply lock.
+    async fn patch(
+        &self,
+        replica: &Arc<ReplicaConnection>,
+        db_name: &str,
+        download_client: &HAClient,
+        options: &ReplicaOptions,
+        txseq: i64,
+    ) -> Result<Option<u64>> {
+        // Pages are copied out of the replica unencrypted
+        if replica.key.is_some() {
+            return Ok(None);
+        }
+        let frames = match Frames::fetch(download_client, db_name, txseq).await? {
+            Some(frames) if frames.txseq > txseq => frames,
+            Some(_) => return Ok(Some(0)),
+            None => {
+                info!("The server no longer holds {} at {}", db_name, txseq);
+                return Ok(None);
+            }
+        };
+
+        let pages = frames.len() as u64;
+        let staging = options.directory.join(SNAPSHOT_DIR);
+        runtime::create_dir_all(&staging).await?;
+        let staged = staging.join(db_name);
+        let _ = runtime::remove_file(&staged).await;
+        let patched = {
+            let (replica, staged) = (replica.clone(), staged.clone());
+            runtime::spawn_blocking(move || -> Result<bool> {
+                let conn = replica.conn.lock();
+                let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+                if !frames.verifiable(page_size) {
+                    return Ok(false);
+                }
+                let mut copy = Connection::open(&staged)?;
+                Backup::new(&conn, &mut copy)?.run_to_completion(1024, Duration::ZERO, None)?;
+                drop(copy);
+                drop(conn);
+                frames.write(&staged)?;
+                Ok(true)
+            })
+            .await
+            .map_err(|e| Error::Io(std::io::Error::other(e)))?
+        };
+        let restored = patched.and_then(|verifiable| {
+            if verifiable {
+                Self::restore(replica, &staged, db_name, options).map(|()| true)
+            } else {
+                Ok(false)
+            }
+        });
+        let _ = runtime::remove_file(&staged).await;
+        match restored {
+            Ok(true) => {}
+            Ok(false) => {
+                info!(
+                    "The server's pages of {} cannot be checked against the replica",
+                    db_name
+                );
+                return Ok(None);
+            }
+            Err(Error::ChecksumMismatch(e)) => {
+                info!(
+                    "Replica {} differs from the server's snapshot: {}",
+                    db_name, e
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+        self.restored(replica, db_name);
+        Ok(Some(pages))
+    }
+
+    /// Take the position of `replica` from the file copied into it, and drop
+    /// the cached queries of `db_name`.
+    fn restored(&self, replica: &ReplicaConnection, db_name: &str) {
+        let txseq = replica.refresh_txseq();
+        #[cfg(feature = "nats")]
+        {
+            let query_cache = self.query_cache.lock().clone();
+            if let Some(cache) = query_cache {
+                cache.replicated_change(db_name, None, txseq);
+            }
+        }
+        #[cfg(not(feature = "nats"))]
+        let _ = (db_name, txseq);
+    }
+
+    /// Copy the database at `snapshot` into `replica` with SQLite's backup,
+    /// so connections reading the replica see it without reopening it.
+    fn restore(
+        replica: &ReplicaConnection,
+        snapshot: &Path,
+        db_name: &str,
+        options: &ReplicaOptions,
+    ) -> Result<()> {
+        // Backup only copies between databases encrypted with the same key
+        #[cfg(feature = "sqlcipher")]
+        if let Some(ref key) = replica.key {
+            let staged = options
+                .directory
+                .join(SNAPSHOT_DIR)
+                .join(format!("{}.encrypted", db_name));
+            encryption::encrypt_file(snapshot, &staged, key)?;
+        }
+        #[cfg(not(feature = "sqlcipher"))]
+        let _ = (db_name, options);
+        let source = open_keyed(
+            snapshot,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            replica.key.as_deref(),
+        )?;
+        // The backup would copy a WAL header into an in-memory replica
+        source.execute_batch("PRAGMA journal_mode = DELETE;")?;
+        let mut conn = replica.conn.lock();
+        Backup::new(&source, &mut conn)?.run_to_completion(1024, Duration::ZERO, None)?;
+        Ok(())
+    }
+
+    /// Compact the replicas whose WAL outgrew
+    /// [`max_wal_size`](ReplicaOptions::max_wal_size) and
+    /// [evict](Self::evict_replicas) those over the disk budget every
+    /// [`maintenance_interval`](ReplicaOptions::maintenance_interval) until
+    /// [`close`](Self::close). Does nothing without an interval or if the
+    /// task is already running.
+    pub fn start_maintenance(self: &Arc<Self>) {
+        let period = match *self.options.lock() {
+            Some(ReplicaOptions {
+                maintenance_interval: Some(period),
+                ..
+            }) => period,
+            _ => return,
+        };
+        let mut maintenance = self.maintenance.lock();
+        if maintenance.is_some() {
+            return;
+        }
+
+        let manager = Arc::downgrade(self);
+        let mut shutdown = self.shutdown.subscribe();
+        let handle = runtime::spawn_named(runtime::REPLICA_MAINTENANCE, async move {
+            let mut interval = Interval::new(period);
+            // The first tick is immediate
+            interval.tick().await;
+
+            loop {
+                runtime::select! {
+                    _ = interval.tick() => {}
+                    _ = closed(&mut shutdown) => break,
+                }
+
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                if let Err(e) = manager.compact_replicas().await {
+                    warn!("Failed to compact replicas: {}", e);
+                }
+                manager.evict_replicas().await;
+            }
+        });
+        *maintenance = Some(handle);
+    }
+
+    /// Compact the replicas whose WAL outgrew
+    /// [`max_wal_size`](ReplicaOptions::max_wal_size), returning their
+    /// names.
+    ///
+    /// The WAL is copied into the database file and truncated, after a new
+    /// snapshot is downloaded into the replica when
+    /// [`resnapshot`](ReplicaOptions::resnapshot) is set. Both go through the
+    /// replica's own connection, so connections reading it carry on and see
+    /// it either before or after. A replica whose readers keep the WAL from
+    /// being truncated is left for the next call. Replicas in memory have no
+    /// WAL, and those of a [shared](ReplicaOptions::shared) directory are
+    /// compacted by the process replicating them.
+    pub async fn compact_replicas(&self) -> Result<Vec<String>> {
+        let options = self
+            .options
+            .lock()
+            .clone()
+            .ok_or_else(|| Error::InvalidParameter("Replicas are not loaded".to_string()))?;
+        if !self.is_leader() {
+            return Ok(Vec::new());
+        }
+        let replicas: Vec<_> = self
+            .replicas
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+
+        let mut compacted = Vec::new();
+        for (db_name, replica) in replicas {
+            let wal_size = replica.wal_size();
+            if wal_size <= options.max_wal_size {
+                continue;
+            }
+            info!("Compacting replica {}: WAL of {} bytes", db_name, wal_size);
+
+            #[cfg(feature = "nats")]
+            if options.resnapshot {
+                self.resnapshot(&replica, &db_name, &options).await;
+            }
+
+            let checkpointed = replica.clone();
+            let truncated = runtime::spawn_blocking(move || checkpointed.checkpoint())
+                .await
+                .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+            if truncated {
+                compacted.push(db_name);
+            } else {
+                warn!(
+                    "Readers kept the WAL of replica {} from being truncated",
+                    db_name
+                );
+            }
+        }
+        Ok(compacted)
+    }
+
+    /// Download a new snapshot into `replica` to compact it, if replicas
+    /// are replicating and downloaded.
+    #[cfg(feature = "nats")]
+    async fn resnapshot(
+        &self,
+        replica: &ReplicaConnection,
+        db_name: &str,
+        options: &ReplicaOptions,
+    ) {
+        let replicating = self.nats_connection.lock().is_some();
+        let download_client = match self.download_client.lock().clone() {
+            Some(download_client) if replicating && self.is_running() => download_client,
+            _ => return,
+        };
+
+        let _applying = self.apply_lock.lock().await;
+        if let Err(e) = self
+            .resync(replica, db_name, &download_client, options)
+            .await
+        {
+            warn!("Failed to download a new snapshot of {}: {}", db_name, e);
+        }
+    }
+
+    /// Download the replica of `db_name` in the background if it is
+    /// missing and a download client is set.
+    ///
+    /// After a failed download the catalog is left to the server for
+    /// [`DOWNLOAD_RETRY`].
+    pub(crate) fn request_replica(self: &Arc<Self>, db_name: &str) {
+        if db_name.is_empty()
+            || self.download_client.lock().is_none()
+            || self.replicas.contains_key(db_name)
+        {
+            return;
+        }
+        {
+            let mut downloads = self.downloads.lock();
+            match downloads.get(db_name) {
+                Some(None) => return,
+                Some(Some(failed_at)) if failed_at.elapsed() < DOWNLOAD_RETRY => return,
+                _ => {}
+            }
+            downloads.insert(db_name.to_string(), None);
+        }
+
+        let manager = self.clone();
+        let name = db_name.to_string();
+        let spawned = self.spawn_background(runtime::REPLICA_DOWNLOAD, async move {
+            let result = manager.download_replica(&name).await;
+            let mut downloads = manager.downloads.lock();
+            match result {
+                Ok(_) => {
+                    downloads.remove(&name);
+                }
+                Err(e) => {
+                    warn!("Failed to download replica {}: {}", name, e);
+                    downloads.insert(name, Some(Instant::now()));
+                }
+            }
+        });
+        if !spawned {
+            self.downloads.lock().remove(db_name);
+        }
+    }
+
+    /// Run `PRAGMA integrity_check` on the replica of `db_name`,
+    /// quarantining it if SQLite finds it corrupt.
+    ///
+    /// Returns `false` if the replica was quarantined.
+    pub async fn check_replica(self: &Arc<Self>, db_name: &str) -> Result<bool> {
+        let replica = self
+            .get_replica(db_name)
+            .ok_or_else(|| Error::InvalidParameter(format!("No replica of {}", db_name)))?;
+        let checked = replica.clone();
+        let problems = runtime::spawn_blocking(move || -> Result<Vec<String>> {
+            let conn = checked.create_connection()?;
+            let problems = conn
+                .prepare("PRAGMA integrity_check")?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(problems)
+        })
+        .await
+        .map_err(|e| Error::Io(std::io::Error::other(e)))?;
+        let reason = match problems {
+            Ok(problems) if problems == ["ok"] => return Ok(true),
+            Ok(problems) => format!("integrity check failed: {}", problems.join("; ")),
+            Err(Error::Sqlite(e)) if is_corrupt(&e) => e.to_string(),
+            Err(e) => return Err(e),
+        };
+        self.quarantine(db_name, &replica, &reason);
+        Ok(false)
+    }
+
+    /// Detach `replica`, found corrupt, from `db_name`, then move its files
+    /// to the quarantine directory and download a fresh snapshot in the
+    /// background if a download client is set. A process following a
+    /// [shared](ReplicaOptions::shared) directory only detaches it.
+    ///
+    /// Reads of the catalog go to the server meanwhile.
+    pub(crate) fn quarantine(
+        self: &Arc<Self>,
+        db_name: &str,
+        replica: &Arc<ReplicaConnection>,
+        reason: &str,
+    ) {
+        if !self.detach(db_name, replica) {
+            return;
+        }
+        error!("Replica {} is corrupt: {}", db_name, reason);
+        if !self.is_leader() {
+            warn!("Replica {} is left to the process replicating it", db_name);
+            return;
+        }
+
+        let manager = self.clone();
+        let name = db_name.to_string();
+        let replica = replica.clone();
+        let reason = reason.to_string();
+        let spawned = self.spawn_background(runtime::REPLICA_REPROVISION, async move {
+            manager.reprovision(&name, &replica, reason).await;
+        });
+        if !spawned {
+            warn!(
+                "Replica {} is left in place: no runtime to move it",
+                db_name
+            );
+        }
+    }
+
+    async fn reprovision(&self, db_name: &str, replica: &ReplicaConnection, reason: String) {
+        let quarantined = {
+            #[cfg(feature = "nats")]
+            let _applying = self.apply_lock.lock().await;
+            replica.close();
+            Self::move_to_quarantine(&replica.dsn)
+        };
+        let path = match quarantined {
+            Ok(path) => path,
+            Err(e) => {
+                error!("Failed to quarantine replica {}: {}", db_name, e);
+                return;
+            }
+        };
+        warn!("Quarantined replica {} in {:?}", db_name, path);
+        let _ = self.replica_events.send(ReplicaEvent::Quarantined {
+            database: db_name.to_string(),
+            path,
+            reason,
+        });
+
+        if self.download_client.lock().is_none() {
+            return;
+        }
+        let event = match self.download_replica(db_name).await {
+            Ok(_) => ReplicaEvent::Reprovisioned {
+                database: db_name.to_string(),
+            },
+            Err(e) => {
+                warn!("Failed to download replica {} again: {}", db_name, e);
+                ReplicaEvent::ReprovisionFailed {
+                    database: db_name.to_string(),
+                    error: e.to_string(),
+                }
+            }
+        };
+        let _ = self.replica_events.send(event);
+    }
+
+    /// Remove `replica` from `db_name` and flag it detached, unless another
+    /// replica of `db_name` has replaced it.
+    fn detach(&self, db_name: &str, replica: &Arc<ReplicaConnection>) -> bool {
+        if self
+            .replicas
+            .remove_if(db_name, |_, r| Arc::ptr_eq(r, replica))
+            .is_none()
+        {
+            return false;
+        }
+        replica.detached.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Watch the replicas directory until [`close`](Self::close), loading
+    /// the SQLite files added to it and detaching the replicas whose file
+    /// is removed, instead of only reading it in [`load`](Self::load).
+    ///
+    /// A file is loaded once it has gone unchanged for a second; files
+    /// should still be written elsewhere and moved into the directory.
+    /// Does nothing if the directory is already watched.
+    #[cfg(feature = "watch")]
+    pub fn watch_directory(self: &Arc<Self>) -> Result<()> {
+        use notify::{RecursiveMode, Watcher};
+
+        let directory = match *self.options.lock() {
+            Some(ref options) => options.directory.clone(),
+            None => {
+                return Err(Error::InvalidParameter(
+                    "Replicas are not loaded".to_string(),
+                ))
+            }
+        };
+        let mut watching = self.watcher.lock();
+        if watching.is_some() {
+            return Ok(());
+        }
+
+        let (paths_tx, mut paths) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            for path in watched_paths(event) {
+                let _ = paths_tx.send(path);
+            }
+        })
+        .map_err(watch_error)?;
+        watcher
+            .watch(&directory, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+
+        let manager = Arc::downgrade(self);
+        let mut shutdown = self.shutdown.subscribe();
+        let handle = runtime::spawn_named(runtime::REPLICA_WATCHER, async move {
+            // Paths with the time of their last event
+            let mut changed: HashMap<PathBuf, Instant> = HashMap::new();
+            let mut interval = Interval::new(WATCH_SETTLE / 2);
+
+            loop {
+                runtime::select! {
+                    path = paths.recv() => match path {
+                        Some(path) => {
+                            changed.insert(path, Instant::now());
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        let settled: Vec<PathBuf> = changed
+                            .iter()
+                            .filter(|(_, at)| at.elapsed() >= WATCH_SETTLE)
+                            .map(|(path, _)| path.clone())
+                            .collect();
+                        if settled.is_empty() {
+                            continue;
+                        }
+                        let Some(manager) = manager.upgrade() else {
+                            break;
+                        };
+                        for path in settled {
+                            changed.remove(&path);
+                            manager.watched_file_changed(&path).await;
+                        }
+                    }
+                    _ = closed(&mut shutdown) => break,
+                }
+            }
+        });
+        *watching = Some((watcher, handle));
+        Ok(())
+    }
+
+    /// Load the file at `path` in the watched directory if it is a new
+    /// replica, or detach the replica of `path` if it is gone.
+    #[cfg(feature = "watch")]
+    async fn watched_file_changed(&self, path: &Path) {
+        let db_name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return,
+        };
+        let options = match *self.options.lock() {
+            Some(ref options) if self.is_running() => options.clone(),
+            _ => return,
+        };
+        // Downloads write to the directory too; let them attach their files
+        let _downloading = self.download_lock.lock().await;
+
+        if !path.exists() {
+            let replica = match self.get_replica(db_name) {
+                Some(replica) if replica.dsn == path => replica,
+                _ => return,
+            };
+            #[cfg(feature = "nats")]
+            let _applying = self.apply_lock.lock().await;
+            if self.detach(db_name, &replica) {
+                replica.close();
+                info!("Detached replica {}: its file was removed", db_name);
+            }
+            return;
+        }
+        if self.replicas.contains_key(db_name)
+            || !path.is_file()
+            || !Self::is_replica_file(path, db_name, &options)
+        {
+            return;
+        }
+
+        #[cfg(feature = "nats")]
+        let _applying = self.apply_lock.lock().await;
+        match self.load_replica(path, db_name, &options).await {
+            Ok(replica) => {
+                if let Some(seq) = self.skipped_since(db_name, replica.get_txseq()) {
+                    warn!(
+                        "Replica {} misses replicated message {}; sync it to catch up",
+                        db_name, seq
+                    );
+                }
+                self.replicas.insert(db_name.to_string(), Arc::new(replica));
+                info!("Loaded replica: {}", db_name);
+            }
+            Err(e) => error!("Failed to load replica {}: {}", db_name, e),
+        }
+    }
+
+    /// Keep the replica of `db_name` from being evicted.
+    pub fn pin_replica(&self, db_name: &str) {
+        self.pinned.lock().insert(db_name.to_string());
+    }
+
+    /// Let the replica of `db_name` be evicted again.
+    pub fn unpin_replica(&self, db_name: &str) {
+        self.pinned.lock().remove(db_name);
+    }
+
+    /// Check if the replica of `db_name` is pinned.
+    pub fn is_pinned(&self, db_name: &str) -> bool {
+        self.pinned.lock().contains(db_name)
+    }
+
+    /// Get the disk space in bytes taken up by the replica files and their
+    /// WALs.
+    pub fn disk_usage(&self) -> u64 {
+        self.replicas.iter().map(|e| e.value().disk_usage()).sum()
+    }
+
+    /// Evict the least recently read replicas that are not pinned until the
+    /// replica files fit in [`max_disk_usage`](ReplicaOptions::max_disk_usage),
+    /// returning their names.
+    ///
+    /// Evicted replicas are detached and their files deleted; their
+    /// catalogs are read from the server until they are downloaded again on
+    /// demand. Nothing is evicted without a download client, nor by a
+    /// process following a [shared](ReplicaOptions::shared) directory.
+    pub async fn evict_replicas(&self) -> Vec<String> {
+        self.evict_replicas_except(None).await
+    }
+
+    async fn evict_replicas_except(&self, keep: Option<&str>) -> Vec<String> {
+        let budget = match *self.options.lock() {
+            Some(ReplicaOptions {
+                max_disk_usage: Some(budget),
+                ..
+            }) => budget,
+            _ => return Vec::new(),
+        };
+        if self.download_client.lock().is_none() || !self.is_leader() {
+            return Vec::new();
+        }
+        let mut usage = self.disk_usage();
+        if usage <= budget {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<_> = {
+            let pinned = self.pinned.lock();
+            self.replicas
+                .iter()
+                .filter(|e| !pinned.contains(e.key()) && Some(e.key().as_str()) != keep)
+                .map(|e| {
+                    (
+                        *e.value().last_read.lock(),
+                        e.key().clone(),
+                        e.value().clone(),
+                    )
+                })
+                .collect()
+        };
+        candidates.sort_by_key(|(last_read, ..)| *last_read);
+
+        #[cfg(feature = "nats")]
+        let _applying = self.apply_lock.lock().await;
+        let mut evicted = Vec::new();
+        for (_, db_name, replica) in candidates {
+            if usage <= budget {
+                break;
+            }
+            let size = replica.disk_usage();
+            if !self.detach(&db_name, &replica) {
+                continue;
+            }
+            replica.close();
+            if let Err(e) = Self::remove_replica_files(&replica.dsn) {
+                warn!("Failed to delete evicted replica {}: {}", db_name, e);
+            }
+            info!("Evicted replica {}: {} bytes", db_name, size);
+            usage = usage.saturating_sub(size);
+            evicted.push(db_name);
+        }
+        if usage > budget {
+            warn!(
+                "Replicas take up {} bytes, over the budget of {}, but the rest are pinned or in use",
+                usage, budget
+            );
+        }
+        evicted
+    }
+
+    /// Delete a replica file and its WAL.
+    fn remove_replica_files(path: &Path) -> Result<()> {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.as_os_str().to_os_string();
+            file.push(suffix);
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a replica file and its WAL to the quarantine directory next to
+    /// it, returning the new path of the file.
+    fn move_to_quarantine(path: &Path) -> Result<PathBuf> {
+        let directory = path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(QUARANTINE_DIR);
+        fs::create_dir_all(&directory)?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", stamp));
+        let target = directory.join(&name);
+        fs::rename(path, &target)?;
+
+        for suffix in ["-wal", "-shm"] {
+            let mut from = path.as_os_str().to_os_string();
+            from.push(suffix);
+            let mut to = target.as_os_str().to_os_string();
+            to.push(suffix);
+            match fs::rename(&from, &to) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(target)
+    }
+
+    /// Get the last message replication skipped for `db_name` after
+    /// `txseq`.
+    #[cfg(feature = "nats")]
+    fn skipped_since(&self, db_name: &str, txseq: i64) -> Option<i64> {
+        self.skipped
+            .get(db_name)
+            .map(|seq| *seq)
+            .filter(|&seq| seq > txseq)
+    }
+
+    /// Get the last message replication skipped for `db_name` after
+    /// `txseq`.
+    #[cfg(not(feature = "nats"))]
+    fn skipped_since(&self, _db_name: &str, _txseq: i64) -> Option<i64> {
+        None
+    }
+
+    /// Get the key of the replica of `db_name`, if replicas are encrypted.
+    #[cfg(feature = "sqlcipher")]
+    fn replica_key(db_name: &str, options: &ReplicaOptions) -> Result<Option<String>> {
+        options
+            .key_provider
+            .as_ref()
+            .map(|provider| provider.key(db_name))
+            .transpose()
+    }
+
+    /// Get the key of the replica of `db_name`, if replicas are encrypted.
+    #[cfg(not(feature = "sqlcipher"))]
+    fn replica_key(_db_name: &str, _options: &ReplicaOptions) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Check if `path` holds a replica of `db_name`: a SQLite database, or
+    /// one encrypted with its key.
+    fn is_replica_file(path: &Path, db_name: &str, options: &ReplicaOptions) -> bool {
+        if Self::is_sqlite_file(path) {
+            return true;
+        }
+        let key = match Self::replica_key(db_name, options) {
+            Ok(Some(key)) => key,
+            _ => return false,
+        };
+        open_keyed(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            Some(&key),
+        )
+        .and_then(|conn| {
+            conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+                .map_err(Error::from)
+        })
+        .is_ok()
+    }
+
     fn is_sqlite_file(path: &Path) -> bool {
         let metadata = match fs::metadata(path) {
             Ok(m) => m,
@@ -220,16 +2369,208 @@ impl EmbeddedReplicasManager {
         header.starts_with(b"SQLite format 3")
     }
 
+    /// Receive the row changes that replication applies to the replicas
+    /// and `filter` accepts.
+    #[cfg(feature = "nats")]
+    pub fn subscribe_changes(&self, filter: ChangeFilter) -> ChangeSubscription {
+        self.subscribers.subscribe(filter)
+    }
+
+    /// Invalidate the results of `cache` as replication applies changes,
+    /// instead of at every newer txseq.
+    #[cfg(feature = "nats")]
+    pub fn set_query_cache(&self, cache: Arc<QueryCache>) {
+        *self.query_cache.lock() = Some(cache);
+    }
+
+    /// Receive the state changes of the NATS replication feed.
+    #[cfg(feature = "nats")]
+    pub fn subscribe(&self) -> broadcast::Receiver<ReplicationEvent> {
+        self.events.subscribe()
+    }
+
+    /// Receive the replicas quarantined and downloaded again.
+    pub fn subscribe_replica_events(&self) -> broadcast::Receiver<ReplicaEvent> {
+        self.replica_events.subscribe()
+    }
+
+    /// Check if the replicas are receiving changes.
+    ///
+    /// `false` while the connection to NATS is down, when the replicas may
+    /// be missing recent transactions however fresh their txseq looks.
+    /// Always `true` without the `nats` feature.
+    pub fn is_replication_connected(&self) -> bool {
+        self.replication_down_since.lock().is_none()
+    }
+
+    /// Get when the connection to NATS went down, if it is down.
+    pub(crate) fn replication_down_since(&self) -> Option<Instant> {
+        *self.replication_down_since.lock()
+    }
+
+    /// Check if the background txseq updater is running.
+    pub fn is_running(&self) -> bool {
+        *self.running.lock()
+    }
+
+    /// Spawn a download or reprovision that [`close`](Self::close) cancels
+    /// and waits for, returning whether a runtime was there to run it.
+    fn spawn_background<F>(&self, name: &'static str, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut shutdown = self.shutdown.subscribe();
+        let spawned = runtime::try_spawn_named(name, async move {
+            runtime::select! {
+                _ = task => {}
+                _ = closed(&mut shutdown) => {}
+            }
+        });
+        match spawned {
+            Some(handle) => {
+                let mut background = self.background.lock();
+                background.retain(|handle| !handle.is_finished());
+                background.push(handle);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Close all replica connections.
+    ///
+    /// Stops the background tasks, cancelling downloads in progress, and
+    /// waits for them to exit, then checkpoints the replicas' WALs into
+    /// their files and releases a [shared](ReplicaOptions::shared) directory
+    /// to the processes following it. The manager can be loaded again
+    /// afterwards.
+    ///
+    /// A manager dropped without being closed signals its tasks to stop,
+    /// but neither waits for them nor checkpoints.
     pub async fn close(&self) {
         *self.running.lock() = false;
+        self.shutdown.send_replace(true);
+
+        let mut tasks: Vec<JoinHandle<()>> = Vec::new();
+        #[cfg(feature = "nats")]
+        tasks.extend(self.replicator.lock().take());
+        tasks.extend(self.updater.lock().take());
+        tasks.extend(self.maintenance.lock().take());
+        tasks.extend(self.takeover.lock().take());
+        #[cfg(feature = "watch")]
+        tasks.extend(self.watcher.lock().take().map(|(_watcher, handle)| handle));
+        tasks.append(&mut self.background.lock());
+        for task in tasks {
+            let _ = task.await;
+        }
+        self.downloads.lock().clear();
 
-        if let Some(tx) = self.shutdown_tx.lock().take() {
-            let _ = tx.send(());
+        if self.is_leader() {
+            let replicas: Vec<_> = self.replicas.iter().map(|e| e.value().clone()).collect();
+            for replica in replicas.into_iter().filter(|r| !r.is_in_memory()) {
+                let name = replica.dsn.clone();
+                match runtime::spawn_blocking(move || replica.checkpoint()).await {
+                    Ok(Ok(true)) => {}
+                    Ok(Ok(false)) => {
+                        warn!("Readers kept the WAL of {:?} from being truncated", name)
+                    }
+                    Ok(Err(e)) => warn!("Failed to checkpoint {:?}: {}", name, e),
+                    Err(e) => warn!("Failed to checkpoint {:?}: {}", name, e),
+                }
+            }
         }
 
         self.replicas.clear();
-        *self.nats_connection.lock() = None;
+        #[cfg(feature = "nats")]
+        {
+            *self.nats_connection.lock() = None;
+            self.subscribers.clear();
+        }
+        // Let a process following the directory take it over
+        *self.directory_lock.lock() = None;
+        self.following.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for EmbeddedReplicasManager {
+    fn drop(&mut self) {
+        // Stop the tasks still running, as close would, without waiting
+        self.shutdown.send_replace(true);
+    }
+}
+
+/// Wait until the manager closes or is dropped.
+async fn closed(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|&closed| closed).await;
+}
+
+/// Open a connection to a replica database, keyed with `key` when it is
+/// encrypted.
+fn open_keyed(path: impl AsRef<Path>, flags: OpenFlags, key: Option<&str>) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    #[cfg(feature = "sqlcipher")]
+    if let Some(key) = key {
+        encryption::apply_key(&conn, key)?;
+    }
+    #[cfg(not(feature = "sqlcipher"))]
+    let _ = key;
+    Ok(conn)
+}
+
+/// Check if SQLite failed because the database file is corrupt.
+pub(crate) fn is_corrupt(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+/// Paths of the files a watch event may have added or removed.
+#[cfg(feature = "watch")]
+fn watched_paths(event: notify::Result<notify::Event>) -> Vec<PathBuf> {
+    use notify::event::{AccessKind, AccessMode, EventKind};
+
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("Replicas directory watch error: {}", e);
+            return Vec::new();
+        }
+    };
+    match event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {}
+        EventKind::Access(_) => return Vec::new(),
+        _ => {}
+    }
+    // Replication keeps writing the WALs of the replicas
+    event
+        .paths
+        .into_iter()
+        .filter(|path| {
+            let name = path.as_os_str().to_string_lossy();
+            !["-wal", "-shm", "-journal"]
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+        })
+        .collect()
+}
+
+#[cfg(feature = "watch")]
+fn watch_error(e: notify::Error) -> Error {
+    Error::Io(std::io::Error::other(e))
+}
+
+#[cfg(feature = "nats")]
+fn nats_error(e: impl fmt::Display) -> Error {
+    Error::Nats(e.to_string())
+}
+
+impl fmt::Debug for EmbeddedReplicasManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedReplicasManager")
+            .field("replicas", &self.replicas.len())
+            .field("running", &self.is_running())
+            .finish()
     }
 }
 
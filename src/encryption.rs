@@ -0,0 +1,91 @@
+//! Encryption of embedded replica files at rest.
+//!
+//! Enabled by the `sqlcipher` feature, which builds SQLite as SQLCipher.
+//! With a [`ReplicaKeyProvider`] in
+//! [`ReplicaOptions::key_provider`](crate::ReplicaOptions::key_provider),
+//! every replica file is encrypted with the key the provider returns for its
+//! database, and every connection to it is keyed. Replicas loaded into
+//! memory stay encrypted there too.
+//!
+//! The HA server sends snapshots in plaintext. A downloaded replica is
+//! encrypted as soon as the download completes, before it is attached, and
+//! so is any plaintext replica found in the replicas directory, which
+//! migrates the replicas of a directory when encryption is turned on. Until
+//! then the snapshot is on disk in plaintext; the plaintext file is deleted,
+//! not wiped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HADataSource, ReplicaKeyProvider};
+//! use std::sync::Arc;
+//!
+//! let keys: Arc<dyn ReplicaKeyProvider> = Arc::new(|db_name: &str| {
+//!     std::env::var(format!("REPLICA_KEY_{}", db_name.replace('.', "_")))
+//!         .map_err(|e| litesql_ha::Error::InvalidParameter(e.to_string()))
+//! });
+//! let mut ds = HADataSource::default();
+//! ds.set_embedded_replicas_dir("/var/lib/app/replicas")
+//!     .set_replica_key_provider(keys);
+//! ```
+
+use crate::error::Result;
+use rusqlite::{params, Connection, OpenFlags};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Supplies the keys of encrypted replicas.
+pub trait ReplicaKeyProvider: Send + Sync {
+    /// Get the key of the replica of `db_name`: a passphrase, or a raw
+    /// 256-bit key written `x'…'` in hex.
+    ///
+    /// Called whenever a connection to the replica is opened, so
+    /// implementations should cache keys they fetch remotely.
+    fn key(&self, db_name: &str) -> Result<String>;
+}
+
+impl<F> ReplicaKeyProvider for F
+where
+    F: Fn(&str) -> Result<String> + Send + Sync,
+{
+    fn key(&self, db_name: &str) -> Result<String> {
+        self(db_name)
+    }
+}
+
+impl fmt::Debug for dyn ReplicaKeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReplicaKeyProvider")
+    }
+}
+
+/// Key a connection before its database is first read.
+pub(crate) fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    Ok(())
+}
+
+/// Encrypt the plaintext database at `path` with `key` in place, writing
+/// the encrypted copy to `staged` first.
+pub(crate) fn encrypt_file(path: &Path, staged: &Path, key: &str) -> Result<()> {
+    let _ = fs::remove_file(staged);
+    // ATTACH opens `staged` with the flags of the connection
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    // Fold in the WAL, so no plaintext one is left beside the encrypted file
+    conn.execute_batch("PRAGMA journal_mode = DELETE;")?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        params![staged.to_string_lossy(), key],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    conn.execute_batch("DETACH DATABASE encrypted;")?;
+    conn.close().map_err(|(_, e)| e)?;
+    fs::rename(staged, path)?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 //! Error types for the HA client.
 
 use thiserror::Error;
+use tonic::Code;
 
 /// Result type for HA operations.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -9,14 +10,21 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Error, Debug)]
 pub enum Error {
     /// gRPC transport error
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("gRPC transport error: {0}")]
     Transport(#[from] tonic::transport::Error),
 
-    /// gRPC status error
+    /// gRPC-web transport error
+    #[cfg(target_arch = "wasm32")]
+    #[error("gRPC-web transport error: {0}")]
+    Transport(#[from] tonic_web_wasm_client::Error),
+
+    /// gRPC status error, boxed to keep `Result`s small
     #[error("gRPC error: {0}")]
-    Status(#[from] tonic::Status),
+    Status(Box<tonic::Status>),
 
     /// SQLite error
+    #[cfg(feature = "embedded-replicas")]
     #[error("SQLite error: {0}")]
     Sqlite(#[from] rusqlite::Error),
 
@@ -48,19 +56,381 @@ pub enum Error {
     #[error("Operation timed out")]
     Timeout,
 
+    /// Cancelled through a [`CancelHandle`](crate::CancelHandle)
+    #[error("Operation was cancelled")]
+    Cancelled,
+
     /// Type conversion error
     #[error("Type conversion error: {0}")]
     TypeConversion(String),
+
+    /// Migration error
+    #[error("Migration error: {0}")]
+    Migration(String),
+
+    /// Test container error
+    #[error("Container error: {0}")]
+    Container(String),
+
+    /// Replay error
+    #[error("Replay error: {0}")]
+    Replay(String),
+
+    /// A query expected to return one row returned none or several
+    #[error("Expected one row, got {0}")]
+    RowCount(usize),
+
+    /// Parquet or Arrow error while exporting
+    #[error("Parquet error: {0}")]
+    Parquet(String),
+
+    /// Downloaded data does not match the checksum sent by the server
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+}
+
+/// Broad category of an [`Error`], for retry and fallback decisions.
+///
+/// Server errors are classified from their gRPC status code and, for SQLite
+/// errors reported by the server, from the error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server could not be reached or is not accepting requests
+    Unavailable,
+    /// The operation did not complete in time
+    Timeout,
+    /// The operation was cancelled
+    Cancelled,
+    /// The database was locked or busy
+    Busy,
+    /// A UNIQUE, NOT NULL, CHECK, FOREIGN KEY or PRIMARY KEY constraint failed
+    ConstraintViolation,
+    /// The SQL has a syntax error or refers to unknown tables or columns
+    InvalidStatement,
+    /// The database or connection is read-only
+    ReadOnly,
+    /// The caller is not authenticated or not allowed
+    PermissionDenied,
+    /// The requested database, replica or row does not exist
+    NotFound,
+    /// A parameter, URL or value was invalid
+    InvalidInput,
+    /// The connection was closed
+    ConnectionClosed,
+    /// A local IO error
+    Io,
+    /// Anything else
+    Other,
 }
 
+impl Error {
+    /// Classify the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Transport(_) => ErrorKind::Unavailable,
+            Error::Status(status) => match status.code() {
+                Code::Unavailable => ErrorKind::Unavailable,
+                Code::DeadlineExceeded => ErrorKind::Timeout,
+                Code::Cancelled => ErrorKind::Cancelled,
+                Code::Aborted | Code::ResourceExhausted => ErrorKind::Busy,
+                Code::Unauthenticated | Code::PermissionDenied => ErrorKind::PermissionDenied,
+                Code::NotFound => ErrorKind::NotFound,
+                Code::InvalidArgument | Code::OutOfRange => ErrorKind::InvalidInput,
+                _ => classify_message(status.message()),
+            },
+            #[cfg(feature = "embedded-replicas")]
+            Error::Sqlite(e) => match e.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::ConstraintViolation) => ErrorKind::ConstraintViolation,
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                    ErrorKind::Busy
+                }
+                Some(rusqlite::ErrorCode::ReadOnly) => ErrorKind::ReadOnly,
+                Some(rusqlite::ErrorCode::PermissionDenied) => ErrorKind::PermissionDenied,
+                _ => classify_message(&e.to_string()),
+            },
+            Error::Io(_) => ErrorKind::Io,
+            Error::UrlParse(_) | Error::InvalidParameter(_) | Error::TypeConversion(_) => {
+                ErrorKind::InvalidInput
+            }
+            Error::Query(message) => classify_message(message),
+            Error::ConnectionClosed => ErrorKind::ConnectionClosed,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::Cancelled => ErrorKind::Cancelled,
+            Error::RowCount(0) => ErrorKind::NotFound,
+            Error::Nats(_)
+            | Error::Migration(_)
+            | Error::Container(_)
+            | Error::Replay(_)
+            | Error::Parquet(_)
+            | Error::ChecksumMismatch(_)
+            | Error::RowCount(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Check if the condition may clear up on its own: the server was
+    /// unavailable, the operation timed out, or the database was busy.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Unavailable | ErrorKind::Timeout | ErrorKind::Busy
+        )
+    }
+
+    /// Check if the statement can be sent again as is.
+    ///
+    /// Like [`is_transient`](Self::is_transient), except for timeouts: a
+    /// statement that timed out may have been applied, so retrying a write
+    /// after one is only safe if the write is idempotent.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Unavailable | ErrorKind::Busy)
+    }
+
+    /// Check if a constraint failed.
+    pub fn is_constraint_violation(&self) -> bool {
+        self.kind() == ErrorKind::ConstraintViolation
+    }
+}
+
+/// Classify an SQLite error message, as reported by the server.
+fn classify_message(message: &str) -> ErrorKind {
+    let message = message.to_lowercase();
+    if message.contains("constraint failed") {
+        ErrorKind::ConstraintViolation
+    } else if message.contains("database is locked")
+        || message.contains("database table is locked")
+        || message.contains("database is busy")
+    {
+        ErrorKind::Busy
+    } else if message.contains("readonly database") || message.contains("read-only") {
+        ErrorKind::ReadOnly
+    } else if message.contains("syntax error")
+        || message.contains("no such table")
+        || message.contains("no such column")
+        || message.contains("no such function")
+    {
+        ErrorKind::InvalidStatement
+    } else {
+        ErrorKind::Other
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Status(Box::new(status))
+    }
+}
+
+#[cfg(feature = "nats")]
 impl From<async_nats::Error> for Error {
     fn from(e: async_nats::Error) -> Self {
         Error::Nats(e.to_string())
     }
 }
 
+#[cfg(feature = "nats")]
 impl From<async_nats::ConnectError> for Error {
     fn from(e: async_nats::ConnectError) -> Self {
         Error::Nats(e.to_string())
     }
 }
+
+#[cfg(feature = "testcontainers")]
+impl From<testcontainers::TestcontainersError> for Error {
+    fn from(e: testcontainers::TestcontainersError) -> Self {
+        Error::Container(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: Code, message: &str) -> Error {
+        tonic::Status::new(code, message).into()
+    }
+
+    #[test]
+    fn status_kind() {
+        let cases = [
+            (Code::Ok, "", ErrorKind::Other),
+            (Code::Cancelled, "", ErrorKind::Cancelled),
+            (Code::Unknown, "", ErrorKind::Other),
+            (Code::InvalidArgument, "", ErrorKind::InvalidInput),
+            (Code::DeadlineExceeded, "", ErrorKind::Timeout),
+            (Code::NotFound, "", ErrorKind::NotFound),
+            (Code::AlreadyExists, "", ErrorKind::Other),
+            (Code::PermissionDenied, "", ErrorKind::PermissionDenied),
+            (Code::ResourceExhausted, "", ErrorKind::Busy),
+            (Code::FailedPrecondition, "", ErrorKind::Other),
+            (Code::Aborted, "", ErrorKind::Busy),
+            (Code::OutOfRange, "", ErrorKind::InvalidInput),
+            (Code::Unimplemented, "", ErrorKind::Other),
+            (Code::Internal, "", ErrorKind::Other),
+            (Code::Unavailable, "", ErrorKind::Unavailable),
+            (Code::DataLoss, "", ErrorKind::Other),
+            (Code::Unauthenticated, "", ErrorKind::PermissionDenied),
+            // Codes without a kind of their own fall back to the message
+            (
+                Code::Unknown,
+                "UNIQUE constraint failed: users.id",
+                ErrorKind::ConstraintViolation,
+            ),
+            (Code::Internal, "database is locked", ErrorKind::Busy),
+            (
+                Code::FailedPrecondition,
+                "attempt to write a readonly database",
+                ErrorKind::ReadOnly,
+            ),
+            (
+                Code::Unknown,
+                "no such table: missing",
+                ErrorKind::InvalidStatement,
+            ),
+            // A code with a kind wins over the message
+            (
+                Code::InvalidArgument,
+                "near \"SELEC\": syntax error",
+                ErrorKind::InvalidInput,
+            ),
+        ];
+        for (code, message, kind) in cases {
+            assert_eq!(status(code, message).kind(), kind, "{:?} {}", code, message);
+        }
+    }
+
+    #[test]
+    fn transient_and_retryable() {
+        let cases = [
+            (status(Code::Unavailable, ""), true, true),
+            (status(Code::Aborted, ""), true, true),
+            (status(Code::ResourceExhausted, ""), true, true),
+            (status(Code::Unknown, "database is busy"), true, true),
+            (Error::Query("database table is locked".into()), true, true),
+            (status(Code::DeadlineExceeded, ""), true, false),
+            (Error::Timeout, true, false),
+            (status(Code::Cancelled, ""), false, false),
+            (Error::Cancelled, false, false),
+            (status(Code::Unauthenticated, ""), false, false),
+            (status(Code::NotFound, ""), false, false),
+            (status(Code::Internal, ""), false, false),
+            (
+                Error::Query("UNIQUE constraint failed: users.id".into()),
+                false,
+                false,
+            ),
+            (Error::ConnectionClosed, false, false),
+            (Error::InvalidParameter(String::new()), false, false),
+            (Error::Io(std::io::Error::other("disk")), false, false),
+            (Error::ChecksumMismatch(String::new()), false, false),
+        ];
+        for (error, transient, retryable) in cases {
+            assert_eq!(error.is_transient(), transient, "{:?}", error);
+            assert_eq!(error.is_retryable(), retryable, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn kind() {
+        let cases = [
+            (
+                Error::Query("CHECK constraint failed: age".into()),
+                ErrorKind::ConstraintViolation,
+            ),
+            (
+                Error::Query("near \"SELEC\": syntax error".into()),
+                ErrorKind::InvalidStatement,
+            ),
+            (
+                Error::Query("no such column: x".into()),
+                ErrorKind::InvalidStatement,
+            ),
+            (
+                Error::Query("no such function: f".into()),
+                ErrorKind::InvalidStatement,
+            ),
+            (
+                Error::Query("the database is READ-ONLY".into()),
+                ErrorKind::ReadOnly,
+            ),
+            (Error::Query("out of memory".into()), ErrorKind::Other),
+            (Error::ConnectionClosed, ErrorKind::ConnectionClosed),
+            (
+                Error::TypeConversion(String::new()),
+                ErrorKind::InvalidInput,
+            ),
+            (Error::RowCount(0), ErrorKind::NotFound),
+            (Error::RowCount(2), ErrorKind::Other),
+            (Error::Nats(String::new()), ErrorKind::Other),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.kind(), kind, "{:?}", error);
+        }
+        assert!(
+            Error::Query("NOT NULL constraint failed: users.name".into()).is_constraint_violation()
+        );
+    }
+
+    #[cfg(feature = "embedded-replicas")]
+    #[test]
+    fn sqlite_kind() {
+        use rusqlite::ffi;
+
+        fn failure(code: std::ffi::c_int, message: Option<&str>) -> Error {
+            rusqlite::Error::SqliteFailure(ffi::Error::new(code), message.map(String::from)).into()
+        }
+
+        let cases = [
+            (
+                failure(ffi::SQLITE_CONSTRAINT, None),
+                ErrorKind::ConstraintViolation,
+                false,
+            ),
+            (failure(ffi::SQLITE_BUSY, None), ErrorKind::Busy, true),
+            (failure(ffi::SQLITE_LOCKED, None), ErrorKind::Busy, true),
+            (
+                failure(ffi::SQLITE_READONLY, None),
+                ErrorKind::ReadOnly,
+                false,
+            ),
+            (
+                failure(ffi::SQLITE_PERM, None),
+                ErrorKind::PermissionDenied,
+                false,
+            ),
+            (
+                failure(ffi::SQLITE_ERROR, Some("no such table: missing")),
+                ErrorKind::InvalidStatement,
+                false,
+            ),
+            (failure(ffi::SQLITE_IOERR, None), ErrorKind::Other, false),
+            (
+                Error::Sqlite(rusqlite::Error::QueryReturnedNoRows),
+                ErrorKind::Other,
+                false,
+            ),
+        ];
+        for (error, kind, transient) in cases {
+            assert_eq!(error.kind(), kind, "{:?}", error);
+            assert_eq!(error.is_transient(), transient, "{:?}", error);
+        }
+
+        // Errors raised by SQLite itself
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY)")
+            .unwrap();
+        let error: Error = conn
+            .execute_batch("INSERT INTO t VALUES (1); INSERT INTO t VALUES (1)")
+            .unwrap_err()
+            .into();
+        assert_eq!(error.kind(), ErrorKind::ConstraintViolation);
+        let error: Error = conn.execute_batch("SELEC 1").unwrap_err().into();
+        assert_eq!(error.kind(), ErrorKind::InvalidStatement);
+        conn.pragma_update(None, "query_only", true).unwrap();
+        let error: Error = conn
+            .execute_batch("INSERT INTO t VALUES (2)")
+            .unwrap_err()
+            .into();
+        assert_eq!(error.kind(), ErrorKind::ReadOnly);
+    }
+}
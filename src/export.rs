@@ -0,0 +1,211 @@
+//! Exporting query results as CSV and NDJSON.
+//!
+//! Enabled by the `csv` feature; NDJSON also needs the `serde` feature.
+//! [`ExecutionResult::write_csv`] writes a result already in memory, and
+//! [`HAConnection::query_export`](crate::HAConnection::query_export) streams
+//! a query's rows to an async writer as they arrive, so large extracts never
+//! build a `Vec` of rows.
+//!
+//! CSV output starts with a header naming the columns. NULL is written as an
+//! empty field and the empty string as `""`, so the two survive a round trip
+//! through [`csv_to_table`](crate::import::csv_to_table). Blobs are written
+//! as lowercase hex and timestamps as ISO 8601 in UTC. NDJSON output has one
+//! object per row, encoded like [`ExecutionResult::to_json`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::export::ExportFormat;
+//! use litesql_ha::HAConnection;
+//!
+//! async fn export(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let file = tokio::fs::File::create("orders.csv").await?;
+//!     let rows = conn
+//!         .query_export("SELECT * FROM orders", &[], ExportFormat::Csv, file)
+//!         .await?;
+//!     println!("exported {} rows", rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::client::{ExecutionResult, RowStream};
+use crate::datetime::format_iso8601;
+use crate::error::Result;
+use crate::runtime::{AsyncWrite, AsyncWriteExt};
+use crate::value::Value;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use tokio_stream::StreamExt;
+
+/// Output format of [`HAConnection::query_export`](crate::HAConnection::query_export).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// RFC 4180 CSV with a header line
+    #[default]
+    Csv,
+    /// One JSON object per line
+    #[cfg(feature = "serde")]
+    Ndjson,
+}
+
+impl ExecutionResult {
+    /// Write the columns and rows as CSV.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        write_header(&mut writer, &self.columns)?;
+        for row in &self.rows {
+            write_record(&mut writer, row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Write the rows of `rows` to `writer` as they arrive, returning the number
+/// of rows written.
+pub(crate) async fn write_stream<W>(
+    mut rows: RowStream,
+    format: ExportFormat,
+    mut writer: W,
+) -> Result<u64>
+where
+    W: AsyncWrite + Unpin,
+{
+    let columns = rows.columns().to_vec();
+    let mut buffer = Vec::new();
+    if format == ExportFormat::Csv {
+        write_header(&mut buffer, &columns)?;
+    }
+
+    let mut count = 0;
+    while let Some(row) = rows.next().await {
+        let row = row?;
+        match format {
+            ExportFormat::Csv => write_record(&mut buffer, row.values())?,
+            #[cfg(feature = "serde")]
+            ExportFormat::Ndjson => {
+                let options = crate::json::JsonOptions::default();
+                let object = crate::json::row_to_json(&columns, row.values(), &options);
+                serde_json::to_writer(&mut buffer, &object).map_err(io::Error::from)?;
+                buffer.push(b'\n');
+            }
+        }
+        writer.write_all(&buffer).await?;
+        buffer.clear();
+        count += 1;
+    }
+    writer.write_all(&buffer).await?;
+    writer.flush().await?;
+    Ok(count)
+}
+
+fn write_header<W: Write>(writer: &mut W, columns: &[String]) -> io::Result<()> {
+    write_fields(
+        writer,
+        columns.iter().map(|c| Some(Cow::Borrowed(c.as_str()))),
+    )
+}
+
+fn write_record<W: Write>(writer: &mut W, values: &[Value]) -> io::Result<()> {
+    write_fields(writer, values.iter().map(field))
+}
+
+/// Write one record; `None` is written as an empty, unquoted field.
+fn write_fields<'a, W: Write>(
+    writer: &mut W,
+    fields: impl Iterator<Item = Option<Cow<'a, str>>>,
+) -> io::Result<()> {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        match field {
+            None => {}
+            Some(field) if field.is_empty() || field.contains([',', '"', '\n', '\r']) => {
+                write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+            }
+            Some(field) => writer.write_all(field.as_bytes())?,
+        }
+    }
+    writer.write_all(b"\n")
+}
+
+fn field(value: &Value) -> Option<Cow<'_, str>> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(v) => Cow::Borrowed(if *v { "1" } else { "0" }),
+        Value::Int32(v) => Cow::Owned(v.to_string()),
+        Value::Int64(v) => Cow::Owned(v.to_string()),
+        Value::Float(v) => Cow::Owned(v.to_string()),
+        Value::Double(v) => Cow::Owned(v.to_string()),
+        Value::String(v) => Cow::Borrowed(v.as_str()),
+        Value::Bytes(v) => Cow::Owned(v.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        })),
+        Value::Timestamp(v) => Cow::Owned(format_iso8601(v)),
+        #[cfg(feature = "json")]
+        Value::Json(v) => Cow::Owned(v.to_string()),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(v) => Cow::Owned(v.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn csv(columns: &[&str], rows: Vec<Vec<Value>>) -> String {
+        let result = ExecutionResult {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            rows,
+            ..ExecutionResult::empty()
+        };
+        let mut out = Vec::new();
+        result.write_csv(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn quoting() {
+        let cases = [
+            (Value::String("plain".into()), "plain"),
+            (Value::String("a,b".into()), "\"a,b\""),
+            (Value::String("say \"hi\"".into()), "\"say \"\"hi\"\"\""),
+            (Value::String("line\nbreak".into()), "\"line\nbreak\""),
+            (Value::String("cr\rlf\r\n".into()), "\"cr\rlf\r\n\""),
+            (Value::String(" spaced ".into()), " spaced "),
+            (Value::String(String::new()), "\"\""),
+            (Value::Null, ""),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                csv(&["v"], vec![vec![value.clone()]]),
+                format!("v\n{}\n", expected),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn values() {
+        let day = SystemTime::UNIX_EPOCH + Duration::from_secs(86_400);
+        assert_eq!(
+            csv(
+                &["a", "b,c", "d"],
+                vec![
+                    vec![
+                        Value::Int64(-1),
+                        Value::Double(1.5),
+                        Value::Bytes(vec![0x00, 0xab, 0xff])
+                    ],
+                    vec![Value::Bool(true), Value::Int32(7), Value::Bytes(vec![])],
+                    vec![Value::Timestamp(day), Value::Null, Value::Null],
+                ]
+            ),
+            "a,\"b,c\",d\n-1,1.5,00abff\n1,7,\"\"\n1970-01-02T00:00:00.000Z,,\n"
+        );
+    }
+}
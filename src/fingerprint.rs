@@ -0,0 +1,365 @@
+//! Statement fingerprints.
+//!
+//! A fingerprint is a statement with its literals and placeholders replaced
+//! by `?`, comments dropped, unquoted words lower-cased and spacing
+//! normalized, so statements that differ only in their values share one.
+//! Lists of values collapse to a single one, so `IN (1, 2, 3)` and `IN (?)`
+//! match, as do multi-row `VALUES` lists:
+//!
+//! ```
+//! use litesql_ha::fingerprint;
+//!
+//! assert_eq!(
+//!     fingerprint("SELECT * FROM users WHERE id IN (1, 2,3) AND name = 'bob' -- lookup"),
+//!     "select * from users where id in (?) and name = ?",
+//! );
+//! assert_eq!(
+//!     fingerprint("insert into t (a, b) values (?1, :b), (3, x'00')"),
+//!     "insert into t (a, b) values (?)",
+//! );
+//! ```
+
+/// Operators of two characters, kept as one token.
+const OPERATORS: [&str; 8] = ["<=", ">=", "<>", "!=", "==", "||", "<<", ">>"];
+
+/// Get the fingerprint of `sql`.
+pub fn fingerprint(sql: &str) -> String {
+    let mut tokens = collapse_lists(tokenize(sql));
+    while tokens.last().is_some_and(|t| t == ";") {
+        tokens.pop();
+    }
+
+    let mut out = String::with_capacity(sql.len());
+    let mut previous: Option<&str> = None;
+    for token in &tokens {
+        let spaced = match previous {
+            None => false,
+            Some("(" | ".") => false,
+            Some(_) => !matches!(token.as_str(), "," | ")" | "." | ";"),
+        };
+        if spaced {
+            out.push(' ');
+        }
+        out.push_str(token);
+        previous = Some(token);
+    }
+    out
+}
+
+/// Split a statement into words, quoted identifiers, `?` for each literal
+/// or placeholder, and punctuation, dropping comments.
+fn tokenize(sql: &str) -> Vec<String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            _ if c.is_whitespace() => {}
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                    i += 1;
+                }
+                i += 1;
+            }
+            '\'' => {
+                i = skip_string(&chars, i);
+                tokens.push("?".to_string());
+            }
+            'x' | 'X' if chars.get(i + 1) == Some(&'\'') => {
+                i = skip_string(&chars, i + 1);
+                tokens.push("?".to_string());
+            }
+            '"' | '`' | '[' => {
+                i = skip_identifier(&chars, i);
+                let end = (i + 1).min(chars.len());
+                tokens.push(chars[start..end].iter().collect());
+            }
+            '?' => {
+                while i + 1 < chars.len() && chars[i + 1].is_ascii_digit() {
+                    i += 1;
+                }
+                tokens.push("?".to_string());
+            }
+            ':' | '@' | '$' if chars.get(i + 1).is_some_and(|c| is_word_char(*c)) => {
+                while i + 1 < chars.len() && is_word_char(chars[i + 1]) {
+                    i += 1;
+                }
+                tokens.push("?".to_string());
+            }
+            _ if c.is_ascii_digit()
+                || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) =>
+            {
+                while i + 1 < chars.len() && is_number_char(chars[i], chars[i + 1]) {
+                    i += 1;
+                }
+                tokens.push("?".to_string());
+            }
+            _ if is_word_char(c) => {
+                // `$` continues an unquoted identifier, as in `a$b`
+                while i + 1 < chars.len() && (is_word_char(chars[i + 1]) || chars[i + 1] == '$') {
+                    i += 1;
+                }
+                tokens.push(chars[start..=i].iter().collect::<String>().to_lowercase());
+            }
+            _ => {
+                let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                if OPERATORS.contains(&pair.as_str()) {
+                    i += 1;
+                    tokens.push(pair);
+                } else {
+                    tokens.push(c.to_string());
+                }
+            }
+        }
+        i += 1;
+    }
+
+    tokens
+}
+
+/// Collapse `?, ?` to `?` and `(?), (?)` to `(?)`, until neither is left.
+fn collapse_lists(tokens: Vec<String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        out.push(token);
+        loop {
+            let n = out.len();
+            if n >= 3 && out[n - 3] == "?" && out[n - 2] == "," && out[n - 1] == "?" {
+                out.truncate(n - 2);
+            } else if n >= 7
+                && out[n - 7..n - 4] == ["(", "?", ")"]
+                && out[n - 4] == ","
+                && out[n - 3..] == ["(", "?", ")"]
+            {
+                out.truncate(n - 4);
+            } else {
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Replace the string, blob and numeric literals of `sql` with `?`, leaving
+/// everything else, comments included, as written.
+pub(crate) fn mask_literals(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let (end, literal) = match c {
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                let end = chars[i..].iter().position(|c| *c == '\n');
+                (end.map_or(chars.len(), |end| i + end), false)
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let mut end = i + 2;
+                while end + 1 < chars.len() && !(chars[end] == '*' && chars[end + 1] == '/') {
+                    end += 1;
+                }
+                (end + 2, false)
+            }
+            '\'' => (skip_string(&chars, i) + 1, true),
+            'x' | 'X' if chars.get(i + 1) == Some(&'\'') => (skip_string(&chars, i + 1) + 1, true),
+            '"' | '`' | '[' => (skip_identifier(&chars, i) + 1, false),
+            _ if c.is_ascii_digit()
+                || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) =>
+            {
+                let mut end = i;
+                while end + 1 < chars.len() && is_number_char(chars[end], chars[end + 1]) {
+                    end += 1;
+                }
+                (end + 1, true)
+            }
+            // Words, including placeholders such as `?1` and `:name`
+            _ if is_word_char(c) || matches!(c, '?' | ':' | '@' | '$') => {
+                let mut end = i + 1;
+                while end < chars.len() && (is_word_char(chars[end]) || chars[end] == '$') {
+                    end += 1;
+                }
+                (end, false)
+            }
+            _ => (i + 1, false),
+        };
+        let end = end.min(chars.len());
+        if literal {
+            out.push('?');
+        } else {
+            out.extend(&chars[i..end]);
+        }
+        i = end;
+    }
+
+    out
+}
+
+/// Get the index of the quote closing the identifier opened at `open`,
+/// taking a doubled `"` or `` ` `` as part of the identifier.
+fn skip_identifier(chars: &[char], open: usize) -> usize {
+    let close = if chars[open] == '[' { ']' } else { chars[open] };
+    let mut i = open + 1;
+    while i < chars.len() {
+        if chars[i] == close {
+            if close != ']' && chars.get(i + 1) == Some(&close) {
+                i += 1;
+            } else {
+                return i;
+            }
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Get the index of the quote closing the string opened at `open`, taking
+/// `''` as an escaped quote.
+fn skip_string(chars: &[char], open: usize) -> usize {
+    let mut i = open + 1;
+    while i < chars.len() {
+        if chars[i] == '\'' {
+            if chars.get(i + 1) == Some(&'\'') {
+                i += 1;
+            } else {
+                return i;
+            }
+        }
+        i += 1;
+    }
+    i
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `next` continues a number whose last character is `c`, such as
+/// `1.5e-3` or `0x1F`.
+fn is_number_char(c: char, next: char) -> bool {
+    next.is_ascii_alphanumeric()
+        || next == '.'
+        || ((next == '-' || next == '+') && (c == 'e' || c == 'E'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints() {
+        let cases = [
+            // Literals and placeholders
+            ("SELECT 1, 'a', x'00', ?, ?2, :a, @b, $c", "select ?"),
+            (
+                "SELECT * FROM t WHERE a = -1.5e-3",
+                "select * from t where a = - ?",
+            ),
+            (
+                "SELECT * FROM t WHERE a = 0x1F OR b = .5",
+                "select * from t where a = ? or b = ?",
+            ),
+            // Quotes and comment markers inside strings
+            (
+                "SELECT * FROM t WHERE a = 'it''s'",
+                "select * from t where a = ?",
+            ),
+            (
+                "SELECT * FROM t WHERE a = '-- not a comment'",
+                "select * from t where a = ?",
+            ),
+            (
+                "SELECT * FROM t WHERE a = '/* no */' /* yes */",
+                "select * from t where a = ?",
+            ),
+            (
+                "SELECT * FROM t WHERE a = 'x' -- trailing",
+                "select * from t where a = ?",
+            ),
+            ("SELECT 'unterminated", "select ?"),
+            // Quoted identifiers are kept as written
+            (
+                r#"SELECT "A ""b""" FROM [My T]"#,
+                r#"select "A ""b""" from [My T]"#,
+            ),
+            ("SELECT `c--d` FROM t", "select `c--d` from t"),
+            // Numbers and `$` inside identifiers
+            (
+                "SELECT t1.col2 FROM tab_9 WHERE c4 = 5",
+                "select t1.col2 from tab_9 where c4 = ?",
+            ),
+            (
+                "SELECT a$b FROM t WHERE c = $c",
+                "select a$b from t where c = ?",
+            ),
+            // Lists
+            (
+                "SELECT * FROM t WHERE a IN (1, 2,3)",
+                "select * from t where a in (?)",
+            ),
+            (
+                "SELECT * FROM t WHERE a IN (1, 2) AND b IN (SELECT c FROM u WHERE d IN (3, 4))",
+                "select * from t where a in (?) and b in (select c from u where d in (?))",
+            ),
+            (
+                "SELECT * FROM t WHERE (a, b) IN ((1, 2), (3, 4), (5, 6))",
+                "select * from t where (a, b) in ((?))",
+            ),
+            (
+                "INSERT INTO t (a, b) VALUES (1, 2), (3, 4)",
+                "insert into t (a, b) values (?)",
+            ),
+            // Case, whitespace, comments and trailing semicolons
+            ("  SELECT\n\ta ,b\r\nFROM  T ;;", "select a, b from t"),
+            ("select 1--x\n+ 2", "select ? + ?"),
+            ("SELECT a<=b, c != d, e||f", "select a <= b, c != d, e || f"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(fingerprint(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn masked_literals() {
+        let cases = [
+            (
+                "SELECT * FROM t1 WHERE email = 'bob@example.com' AND age > 42",
+                "SELECT * FROM t1 WHERE email = ? AND age > ?",
+            ),
+            (
+                "INSERT INTO t VALUES ('it''s', x'00ff', -1.5e3, .5, ?2, :name, @a1, $b)",
+                "INSERT INTO t VALUES (?, ?, -?, ?, ?2, :name, @a1, $b)",
+            ),
+            (
+                r#"SELECT "col 1", [t 2].c3, a$1 FROM `x'9'` -- 42"#,
+                r#"SELECT "col 1", [t 2].c3, a$1 FROM `x'9'` -- 42"#,
+            ),
+            ("SELECT /* 'a' */ 'b'", "SELECT /* 'a' */ ?"),
+            ("SELECT 'unterminated", "SELECT ?"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(mask_literals(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn values_do_not_change_the_fingerprint() {
+        assert_eq!(
+            fingerprint("SELECT * FROM t WHERE id IN (1) AND name = 'a'"),
+            fingerprint("select *\nfrom t where id in (?, ?, ?) and name = :name"),
+        );
+        assert_ne!(
+            fingerprint("SELECT * FROM t WHERE a = 1"),
+            fingerprint("SELECT * FROM t WHERE b = 1"),
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Fixture and seed data loading.
+//!
+//! Enabled by the `fixtures` feature. A [`FixtureLoader`] writes rows into a
+//! database through an [`HAConnection`], so it works the same against the
+//! mock server of the `test-util` feature and against a real cluster.
+//!
+//! Fixtures can be SQL scripts, CSV text whose first line names the columns,
+//! or any `serde`-serializable structs. Fixtures are loaded so that every
+//! table comes after the tables it [depends on](Fixture::depends_on), and
+//! with [truncation](FixtureLoader::with_truncate) enabled, existing rows are
+//! deleted in the reverse order first. Everything runs in one transaction.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::fixtures::{Fixture, FixtureLoader};
+//! # use litesql_ha::HAConnection;
+//!
+//! #[derive(serde::Serialize)]
+//! struct User {
+//!     id: i64,
+//!     name: &'static str,
+//! }
+//!
+//! # async fn example(conn: &HAConnection) -> litesql_ha::Result<()> {
+//! FixtureLoader::new()
+//!     .with_fixture(Fixture::rows("users", &[User { id: 1, name: "alice" }])?)
+//!     .with_fixture(Fixture::csv("orders", "id,user_id\n10,1\n").depends_on("users"))
+//!     .with_truncate(true)
+//!     .load(conn)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`HAConnection`]: crate::HAConnection
+
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::script::{quote_identifier, split_statements};
+use crate::value::Value;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Where a fixture's data comes from.
+#[derive(Debug, Clone)]
+enum Source {
+    Sql(Cow<'static, str>),
+    Rows {
+        columns: Vec<String>,
+        rows: Vec<Vec<Value>>,
+    },
+}
+
+/// Seed data for one table.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    table: String,
+    source: Source,
+    depends_on: Vec<String>,
+}
+
+impl Fixture {
+    /// Create a fixture from a SQL script that populates `table`.
+    pub fn sql(table: impl Into<String>, sql: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(table, Source::Sql(sql.into()))
+    }
+
+    /// Create a fixture from a SQL script file that populates `table`.
+    pub fn sql_file(table: impl Into<String>, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::sql(table, std::fs::read_to_string(path)?))
+    }
+
+    /// Create a fixture from CSV text whose first line names the columns.
+    ///
+    /// Fields are inserted as text and converted by the column affinity.
+    pub fn csv(table: impl Into<String>, csv: &str) -> Self {
+        let mut records = parse_csv(csv).into_iter();
+        let columns = records.next().unwrap_or_default();
+        let rows = records
+            .map(|record| record.into_iter().map(Value::String).collect())
+            .collect();
+        Self::new(table, Source::Rows { columns, rows })
+    }
+
+    /// Create a fixture from a CSV file whose first line names the columns.
+    pub fn csv_file(table: impl Into<String>, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::csv(table, &std::fs::read_to_string(path)?))
+    }
+
+    /// Create a fixture from structs serializing to maps of column values.
+    ///
+    /// Nested arrays and maps are stored as JSON text.
+    pub fn rows<T: Serialize>(table: impl Into<String>, rows: &[T]) -> Result<Self> {
+        let mut columns: Vec<String> = Vec::new();
+        let mut objects = Vec::with_capacity(rows.len());
+        for row in rows {
+            let value =
+                serde_json::to_value(row).map_err(|e| Error::TypeConversion(e.to_string()))?;
+            let serde_json::Value::Object(object) = value else {
+                return Err(Error::TypeConversion(format!(
+                    "fixture rows must serialize to maps, got {}",
+                    value
+                )));
+            };
+            for key in object.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+            objects.push(object);
+        }
+
+        let rows = objects
+            .into_iter()
+            .map(|mut object| {
+                columns
+                    .iter()
+                    .map(|c| object.remove(c).map(json_to_value).unwrap_or(Value::Null))
+                    .collect()
+            })
+            .collect();
+        Ok(Self::new(table, Source::Rows { columns, rows }))
+    }
+
+    /// Load this fixture after the fixture for `table`.
+    pub fn depends_on(mut self, table: impl Into<String>) -> Self {
+        self.depends_on.push(table.into());
+        self
+    }
+
+    /// Get the table this fixture populates.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    fn new(table: impl Into<String>, source: Source) -> Self {
+        Self {
+            table: table.into(),
+            source,
+            depends_on: Vec::new(),
+        }
+    }
+}
+
+/// Rows written for one fixture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedFixture {
+    /// Table name
+    pub table: String,
+    /// Rows inserted
+    pub rows: i64,
+}
+
+/// Loads fixtures in dependency order.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureLoader {
+    fixtures: Vec<Fixture>,
+    truncate: bool,
+}
+
+impl FixtureLoader {
+    /// Create an empty loader.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fixture.
+    pub fn with_fixture(mut self, fixture: Fixture) -> Self {
+        self.fixtures.push(fixture);
+        self
+    }
+
+    /// Delete existing rows from every fixture table before loading.
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Get the fixtures in the order they will be loaded.
+    ///
+    /// Dependencies on tables without a fixture are ignored.
+    pub fn ordered(&self) -> Result<Vec<&Fixture>> {
+        let mut ordered: Vec<&Fixture> = Vec::with_capacity(self.fixtures.len());
+        let mut remaining: Vec<&Fixture> = self.fixtures.iter().collect();
+
+        while !remaining.is_empty() {
+            let ready = remaining.iter().position(|f| {
+                f.depends_on.iter().all(|dep| {
+                    dep == &f.table
+                        || ordered.iter().any(|o| &o.table == dep)
+                        || !self.fixtures.iter().any(|o| &o.table == dep)
+                })
+            });
+            match ready {
+                Some(i) => ordered.push(remaining.remove(i)),
+                None => {
+                    let tables: Vec<&str> = remaining.iter().map(|f| f.table()).collect();
+                    return Err(Error::InvalidParameter(format!(
+                        "fixture dependency cycle between {}",
+                        tables.join(", ")
+                    )));
+                }
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// Load all fixtures in a single transaction.
+    pub async fn load(&self, conn: &HAConnection) -> Result<Vec<LoadedFixture>> {
+        let ordered = self.ordered()?;
+
+        conn.begin_transaction().await?;
+        match self.load_ordered(conn, &ordered).await {
+            Ok(loaded) => {
+                conn.commit().await?;
+                Ok(loaded)
+            }
+            Err(e) => {
+                let _ = conn.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn load_ordered(
+        &self,
+        conn: &HAConnection,
+        ordered: &[&Fixture],
+    ) -> Result<Vec<LoadedFixture>> {
+        if self.truncate {
+            let mut truncated: Vec<&str> = Vec::new();
+            for fixture in ordered.iter().rev() {
+                if !truncated.contains(&fixture.table()) {
+                    conn.execute(
+                        &format!("DELETE FROM {}", quote_identifier(&fixture.table)),
+                        &[],
+                    )
+                    .await?;
+                    truncated.push(fixture.table());
+                }
+            }
+        }
+
+        let mut loaded = Vec::with_capacity(ordered.len());
+        for fixture in ordered {
+            let mut rows = 0;
+            match fixture.source {
+                Source::Sql(ref sql) => {
+                    for statement in split_statements(sql) {
+                        rows += conn.execute(&statement, &[]).await?.rows_affected;
+                    }
+                }
+                Source::Rows {
+                    ref columns,
+                    rows: ref values,
+                } => {
+                    let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+                    let insert = format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        quote_identifier(&fixture.table),
+                        names.join(", "),
+                        vec!["?"; columns.len()].join(", ")
+                    );
+                    for row in values {
+                        if row.len() != columns.len() {
+                            return Err(Error::InvalidParameter(format!(
+                                "fixture row for {} has {} values, expected {}",
+                                fixture.table,
+                                row.len(),
+                                columns.len()
+                            )));
+                        }
+                        rows += conn.execute(&insert, row).await?.rows_affected;
+                    }
+                }
+            }
+            loaded.push(LoadedFixture {
+                table: fixture.table.clone(),
+                rows,
+            });
+        }
+
+        Ok(loaded)
+    }
+}
+
+fn json_to_value(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(v) => Value::Bool(v),
+        serde_json::Value::Number(v) => match v.as_i64() {
+            Some(i) => Value::Int64(i),
+            None => Value::Double(v.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(v) => Value::String(v),
+        other => Value::String(other.to_string()),
+    }
+}
+
+/// Parse CSV text into records. Quoted fields may contain separators,
+/// doubled quotes and line breaks; blank lines are skipped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                if !(record.len() == 1 && record[0].is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
@@ -0,0 +1,133 @@
+//! Incremental sync of embedded replicas from the pages changed on the server.
+//!
+//! The `Frames` call sends the pages of a database that differ between the
+//! snapshot at a txseq, such as the one a replica was downloaded at, and the
+//! latest snapshot, together with the size and SHA-256 of the latter. Writing
+//! them over a copy of the replica turns the copy into the latest snapshot,
+//! which is checked against the checksum before it replaces the replica.
+//!
+//! A replica only matches the server's snapshots page for page while it is
+//! written by downloads and syncs alone; once replication changed it, the
+//! patched copy no longer matches the checksum. When that happens, when the
+//! server no longer holds the snapshot at the replica's txseq, when it does
+//! not implement the call, or when the pages cannot be checked because the
+//! server sent no checksum or uses another page size than the replica, the
+//! replica is downloaded again instead.
+
+use crate::client::HAClient;
+use crate::error::{Error, Result};
+use crate::proto::FramesRequest;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use tonic::Code;
+
+/// The pages of a database changed on the server since a txseq.
+#[derive(Debug, Default)]
+pub(crate) struct Frames {
+    /// Transaction the pages bring the database to
+    pub(crate) txseq: i64,
+    page_size: u64,
+    page_count: u64,
+    /// Changed pages, by 1-based page number
+    pages: Vec<(u64, Vec<u8>)>,
+    /// SHA-256 of the database at `txseq`, empty if the server sent none
+    checksum: Vec<u8>,
+}
+
+impl Frames {
+    /// Fetch the pages of `replication_id` changed after `txseq`, or `None`
+    /// if the server cannot send them.
+    pub(crate) async fn fetch(
+        client: &HAClient,
+        replication_id: &str,
+        txseq: i64,
+    ) -> Result<Option<Self>> {
+        let request = FramesRequest {
+            replication_id: replication_id.to_string(),
+            txseq,
+        };
+        let mut stream = match client.open_frames(request).await {
+            Ok(stream) => stream,
+            Err(Error::Status(status)) if status.code() == Code::Unimplemented => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut frames = Self::default();
+        while let Some(response) = stream.message().await? {
+            if response.pruned {
+                return Ok(None);
+            }
+            frames.txseq = response.txseq;
+            frames.page_size = u64::try_from(response.page_size)
+                .ok()
+                .filter(|size| *size > 0)
+                .ok_or_else(|| invalid(format!("page size {}", response.page_size)))?;
+            frames.page_count = u64::try_from(response.page_count)
+                .map_err(|_| invalid(format!("page count {}", response.page_count)))?;
+            if !response.checksum.is_empty() {
+                frames.checksum = response.checksum;
+            }
+            for page in response.pages {
+                let number = u64::try_from(page.number)
+                    .ok()
+                    .filter(|n| (1..=frames.page_count).contains(n))
+                    .ok_or_else(|| invalid(format!("page number {}", page.number)))?;
+                if page.data.len() as u64 != frames.page_size {
+                    return Err(invalid(format!(
+                        "page {} has {} bytes, not {}",
+                        number,
+                        page.data.len(),
+                        frames.page_size
+                    )));
+                }
+                frames.pages.push((number, page.data));
+            }
+        }
+        Ok(Some(frames))
+    }
+
+    /// Get the number of changed pages.
+    pub(crate) fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Check if the pages can be written over a database of `page_size`
+    /// bytes a page and the result checked against the server's checksum.
+    pub(crate) fn verifiable(&self, page_size: u64) -> bool {
+        !self.checksum.is_empty() && self.page_size == page_size
+    }
+
+    /// Write the pages over the database file at `path`, resize it to the
+    /// size of the database at [`txseq`](Self::txseq) and check it against
+    /// the checksum, which must have been sent (see
+    /// [`verifiable`](Self::verifiable)).
+    pub(crate) fn write(&self, path: &Path) -> Result<()> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        for (number, data) in &self.pages {
+            file.seek(SeekFrom::Start((number - 1) * self.page_size))?;
+            file.write_all(data)?;
+        }
+        file.set_len(self.page_count * self.page_size)?;
+        file.sync_all()?;
+        drop(file);
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        if hasher.finalize().as_slice() != self.checksum.as_slice() {
+            return Err(Error::ChecksumMismatch(format!(
+                "{:?} does not match the snapshot at {} once patched",
+                path, self.txseq
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid frames: {}", message.into()),
+    ))
+}
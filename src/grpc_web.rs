@@ -0,0 +1,105 @@
+//! gRPC-web channel for WebAssembly.
+//!
+//! Used instead of the tonic transport when building for
+//! `wasm32-unknown-unknown` with the `wasm` feature. Browsers and edge
+//! runtimes cannot open the HTTP/2 connections gRPC needs, so calls go out
+//! as gRPC-web requests over `fetch`: the HA server, or a proxy in front of
+//! it, must accept gRPC-web. `fetch` verifies certificates and keeps
+//! connections alive itself, so [`TlsOptions`](crate::TlsOptions) and
+//! [`KeepaliveOptions`](crate::KeepaliveOptions) do not apply.
+//!
+//! A [`Middleware`](crate::Middleware) wraps a [`Channel`] on this target.
+
+use crate::error::{Error, Result};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Service};
+use tonic_web_wasm_client::{Client, ResponseBody};
+
+/// Address of a server, as a tonic endpoint on other targets.
+#[derive(Debug, Clone)]
+pub(crate) struct Endpoint {
+    uri: http::Uri,
+}
+
+impl Endpoint {
+    /// Parse the `http` or `https` URL of a server.
+    pub(crate) fn from_shared(url: String) -> Result<Self> {
+        let uri = url
+            .parse()
+            .map_err(|e| Error::InvalidParameter(format!("Invalid server URL {}: {}", url, e)))?;
+        Ok(Self { uri })
+    }
+
+    /// Get the URI of the server.
+    pub(crate) fn uri(&self) -> &http::Uri {
+        &self.uri
+    }
+
+    /// Open a channel to the server; requests are sent as calls are made.
+    pub(crate) fn connect_lazy(&self) -> Channel {
+        let url = self.uri.to_string();
+        Channel {
+            client: Client::new(url.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// Open a channel to the server. There is no connection to open ahead
+    /// of the first call.
+    pub(crate) async fn connect(
+        &self,
+    ) -> std::result::Result<Channel, tonic_web_wasm_client::Error> {
+        Ok(self.connect_lazy())
+    }
+}
+
+/// gRPC-web channel to a server.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    client: Client,
+}
+
+impl Service<http::Request<BoxBody>> for Channel {
+    type Response = http::Response<BoxBody>;
+    type Error = tonic_web_wasm_client::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.client.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        ResponseFuture(self.client.call(request))
+    }
+}
+
+type ClientFuture = Pin<
+    Box<
+        dyn Future<
+            Output = std::result::Result<
+                http::Response<ResponseBody>,
+                tonic_web_wasm_client::Error,
+            >,
+        >,
+    >,
+>;
+
+/// Response of a call over a [`Channel`].
+pub struct ResponseFuture(ClientFuture);
+
+// The `fetch` future holds JavaScript values, which are not `Send`; wasm32
+// runs the client on a single thread, so it never moves to another one.
+unsafe impl Send for ResponseFuture {}
+
+impl Future for ResponseFuture {
+    type Output = std::result::Result<http::Response<BoxBody>, tonic_web_wasm_client::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0
+            .as_mut()
+            .poll(cx)
+            .map_ok(|response| response.map(tonic::body::boxed))
+    }
+}
@@ -0,0 +1,84 @@
+//! Health checks for readiness and liveness probes.
+//!
+//! [`HADataSource::health`](crate::HADataSource::health) checks every part
+//! a data source depends on and reports it as a [`Health`]: whether the HA
+//! server answers and accepts the credentials, whether the embedded replicas
+//! are loaded and receiving changes, and how far each replica is behind.
+//! [`HADataSource::wait_until_ready`](crate::HADataSource::wait_until_ready)
+//! waits for the first healthy check, for startup and readiness probes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::HADataSource;
+//! use std::time::Duration;
+//!
+//! # async fn example(ds: &HADataSource) -> litesql_ha::Result<()> {
+//! ds.wait_until_ready(Duration::from_secs(60)).await?;
+//!
+//! // ... then, in the readiness probe handler:
+//! let health = ds.health().await;
+//! if !health.is_ready() {
+//!     eprintln!("not ready: {:?}", health.error);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "embedded-replicas")]
+use crate::embedded_replicas::ReplicaLag;
+
+/// Status of a data source and the services it depends on.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    /// Whether a connection could be opened
+    pub connected: bool,
+    /// Whether the HA server answered
+    pub server_reachable: bool,
+    /// Whether the HA server accepted the credentials
+    pub authenticated: bool,
+    /// Whether the embedded replicas are loaded, or `None` if the data
+    /// source has none configured
+    pub replicas_loaded: Option<bool>,
+    /// Whether the replicas are receiving changes from NATS, or `None` if
+    /// the data source has no embedded replicas configured
+    pub replication_connected: Option<bool>,
+    /// Status of each loaded replica, by database name
+    #[cfg(feature = "embedded-replicas")]
+    pub replicas: Vec<ReplicaHealth>,
+    /// First error met by the check, if any
+    pub error: Option<String>,
+}
+
+/// Status of one embedded replica.
+#[cfg(feature = "embedded-replicas")]
+#[derive(Debug, Clone)]
+pub struct ReplicaHealth {
+    /// Database name of the replica
+    pub name: String,
+    /// Whether replication to the replica stopped on an error
+    pub failed: bool,
+    /// How far the replica is behind the leader, or `None` if the server
+    /// could not tell
+    pub lag: Option<ReplicaLag>,
+}
+
+impl Health {
+    /// Check if the data source can serve queries: a connection could be
+    /// opened, the server is reachable and accepts the credentials, and the
+    /// embedded replicas, if any, are loaded, connected to NATS and none has
+    /// stopped replicating.
+    ///
+    /// Replica lag is reported but not checked.
+    pub fn is_ready(&self) -> bool {
+        #[cfg(feature = "embedded-replicas")]
+        if self.replicas.iter().any(|replica| replica.failed) {
+            return false;
+        }
+        self.connected
+            && self.server_reachable
+            && self.authenticated
+            && self.replicas_loaded != Some(false)
+            && self.replication_connected != Some(false)
+    }
+}
@@ -0,0 +1,153 @@
+//! Query hooks for custom logging, auditing and metrics.
+//!
+//! A [`QueryHook`] is called before and after every statement executed
+//! through an [`HAConnection`](crate::HAConnection), wherever it runs: the
+//! embedded replica, the query cache or the HA server. Hooks are registered
+//! with [`HAConnectionOptions::query_hooks`](crate::HAConnectionOptions::query_hooks),
+//! [`HAConnection::add_query_hook`](crate::HAConnection::add_query_hook) or
+//! [`HADataSource::add_query_hook`](crate::HADataSource::add_query_hook), and
+//! run in the order they were added.
+//!
+//! Hooks run inline with the statement, so they should hand slow work off
+//! rather than block.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HADataSource, QueryEvent, QueryHook, QueryOutcome};
+//!
+//! struct SlowLog;
+//!
+//! impl QueryHook for SlowLog {
+//!     fn after_query(&self, query: &QueryEvent<'_>, outcome: &QueryOutcome<'_>) {
+//!         if outcome.latency.as_millis() > 100 {
+//!             eprintln!("{:?} took {:?}", query.sql, outcome.latency);
+//!         }
+//!     }
+//! }
+//!
+//! let mut ds = HADataSource::default();
+//! ds.add_query_hook(SlowLog);
+//! ```
+
+use crate::client::{ExecuteResult, ExecutionResult, RowStream};
+use crate::error::{Error, Result};
+use crate::routing::RoutingDecision;
+use crate::value::Value;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A statement about to run, or that just ran.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryEvent<'a> {
+    /// SQL text
+    pub sql: &'a str,
+    /// Parameters, unmasked
+    pub parameters: &'a [Value],
+}
+
+/// What a successful statement returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuerySummary {
+    /// Number of rows affected
+    pub rows_affected: i64,
+    /// Number of rows returned; not counted for streamed queries
+    pub row_count: usize,
+}
+
+/// How a statement completed.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryOutcome<'a> {
+    /// Where the statement was executed and why
+    pub routing: RoutingDecision,
+    /// What the statement returned, or why it failed
+    pub result: std::result::Result<QuerySummary, &'a Error>,
+    /// Time taken to execute the statement
+    pub latency: Duration,
+}
+
+/// Called around every statement a connection executes.
+pub trait QueryHook: Send + Sync {
+    /// Called before the statement runs.
+    fn before_query(&self, query: &QueryEvent<'_>) {
+        let _ = query;
+    }
+
+    /// Called once the statement completed or failed.
+    fn after_query(&self, query: &QueryEvent<'_>, outcome: &QueryOutcome<'_>) {
+        let _ = (query, outcome);
+    }
+}
+
+impl<T: QueryHook + ?Sized> QueryHook for Arc<T> {
+    fn before_query(&self, query: &QueryEvent<'_>) {
+        (**self).before_query(query)
+    }
+
+    fn after_query(&self, query: &QueryEvent<'_>, outcome: &QueryOutcome<'_>) {
+        (**self).after_query(query, outcome)
+    }
+}
+
+impl fmt::Debug for dyn QueryHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QueryHook")
+    }
+}
+
+/// A result a statement can complete with.
+pub(crate) trait Summarize {
+    fn summarize(&self) -> QuerySummary;
+}
+
+impl Summarize for ExecutionResult {
+    fn summarize(&self) -> QuerySummary {
+        QuerySummary {
+            rows_affected: self.rows_affected,
+            row_count: self.row_count(),
+        }
+    }
+}
+
+impl Summarize for ExecuteResult {
+    fn summarize(&self) -> QuerySummary {
+        QuerySummary {
+            rows_affected: self.rows_affected,
+            row_count: 0,
+        }
+    }
+}
+
+impl Summarize for RowStream {
+    fn summarize(&self) -> QuerySummary {
+        QuerySummary::default()
+    }
+}
+
+impl Summarize for i64 {
+    fn summarize(&self) -> QuerySummary {
+        QuerySummary {
+            rows_affected: *self,
+            row_count: 0,
+        }
+    }
+}
+
+impl<T: Summarize> Summarize for Vec<T> {
+    fn summarize(&self) -> QuerySummary {
+        self.iter()
+            .map(Summarize::summarize)
+            .fold(QuerySummary::default(), |total, summary| QuerySummary {
+                rows_affected: total.rows_affected + summary.rows_affected,
+                row_count: total.row_count + summary.row_count,
+            })
+    }
+}
+
+/// Get the summary of `result`, or its error.
+pub(crate) fn summarize<T: Summarize>(
+    result: &Result<T>,
+) -> std::result::Result<QuerySummary, &Error> {
+    result.as_ref().map(Summarize::summarize)
+}
@@ -0,0 +1,320 @@
+//! Importing CSV data into tables.
+//!
+//! Enabled by the `csv` feature. [`csv_to_table`] reads RFC 4180 records from
+//! any async reader and inserts them in batches of multi-row `INSERT`s, one
+//! transaction per batch, the same way as a
+//! [`BulkInserter`](crate::bulk::BulkInserter). The reader is consumed a
+//! record at a time, so files larger than memory can be imported.
+//!
+//! Fields are inserted as text and converted by the column affinity. An
+//! unquoted field equal to the [NULL marker](CsvImportOptions::null) is
+//! inserted as NULL; quoting it keeps it as text.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::import::{csv_to_table, CsvImportOptions};
+//! use litesql_ha::HAConnection;
+//! use tokio::io::BufReader;
+//!
+//! async fn import(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let file = tokio::fs::File::open("users.csv").await?;
+//!     let options = CsvImportOptions {
+//!         null: Some("\\N".to_string()),
+//!         create_table: true,
+//!         ..CsvImportOptions::new("users")
+//!     };
+//!     let progress = csv_to_table(conn, BufReader::new(file), &options).await?;
+//!     println!("imported {} rows", progress.rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::bulk::{BulkInserter, BulkProgress, OnConflict};
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::runtime::{AsyncBufRead, AsyncBufReadExt};
+use crate::script::quote_identifier;
+use crate::value::Value;
+use std::time::Instant;
+
+/// Options for [`csv_to_table`].
+#[derive(Debug, Clone)]
+pub struct CsvImportOptions {
+    /// Table to insert into
+    pub table: String,
+    /// Table column for each field, in order; taken from the header when
+    /// empty
+    pub columns: Vec<String>,
+    /// Whether the first record names the columns
+    pub has_header: bool,
+    /// Field separator
+    pub delimiter: char,
+    /// Unquoted field value inserted as NULL; with `None` every field is text
+    pub null: Option<String>,
+    /// Rows per transaction
+    pub batch_size: usize,
+    /// What happens to rows that conflict with existing ones
+    pub on_conflict: OnConflict,
+    /// Create the table with the import's columns if it does not exist
+    pub create_table: bool,
+}
+
+impl CsvImportOptions {
+    /// Options importing into `table` with the defaults.
+    pub fn new(table: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        Self {
+            table: String::new(),
+            columns: Vec::new(),
+            has_header: true,
+            delimiter: ',',
+            null: None,
+            batch_size: 1000,
+            on_conflict: OnConflict::default(),
+            create_table: false,
+        }
+    }
+}
+
+/// Insert the CSV records of `reader` into a table.
+///
+/// Returns the progress after the last batch. A record with the wrong number
+/// of fields fails the import; batches before it stay committed.
+pub async fn csv_to_table<R>(
+    conn: &HAConnection,
+    reader: R,
+    options: &CsvImportOptions,
+) -> Result<BulkProgress>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut records = Records::new(reader, options.delimiter);
+
+    let mut columns = options.columns.clone();
+    if options.has_header {
+        let header = records.next().await?;
+        if columns.is_empty() {
+            columns = header
+                .ok_or_else(|| {
+                    Error::InvalidParameter(format!("CSV for {} has no header", options.table))
+                })?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+        }
+    }
+    if columns.is_empty() {
+        return Err(Error::InvalidParameter(format!(
+            "CSV import into {} needs columns",
+            options.table
+        )));
+    }
+
+    if options.create_table {
+        let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                quote_identifier(&options.table),
+                names.join(", ")
+            ),
+            &[],
+        )
+        .await?;
+    }
+
+    let inserter = BulkInserter::new(options.table.as_str(), columns.iter().cloned())
+        .with_on_conflict(options.on_conflict);
+    let batch_size = options.batch_size.max(1);
+    let started = Instant::now();
+    let mut progress = BulkProgress::default();
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while let Some(record) = records.next().await? {
+        if record.len() != columns.len() {
+            return Err(Error::InvalidParameter(format!(
+                "CSV record on line {} has {} fields, expected {}",
+                records.record_line,
+                record.len(),
+                columns.len()
+            )));
+        }
+        let row = record
+            .into_iter()
+            .map(|(field, quoted)| match options.null {
+                Some(ref null) if !quoted && field == *null => Value::Null,
+                _ => Value::String(field),
+            })
+            .collect();
+        batch.push(row);
+        if batch.len() >= batch_size {
+            inserter
+                .commit_batch(conn, std::mem::take(&mut batch), &mut progress, started)
+                .await?;
+        }
+    }
+    if !batch.is_empty() {
+        inserter
+            .commit_batch(conn, batch, &mut progress, started)
+            .await?;
+    }
+
+    progress.elapsed = started.elapsed();
+    Ok(progress)
+}
+
+/// Reads CSV records a line at a time.
+struct Records<R> {
+    reader: R,
+    delimiter: char,
+    line: String,
+    /// Lines read so far
+    lines: usize,
+    /// Line the last record started on
+    record_line: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> Records<R> {
+    fn new(reader: R, delimiter: char) -> Self {
+        Self {
+            reader,
+            delimiter,
+            line: String::new(),
+            lines: 0,
+            record_line: 0,
+        }
+    }
+
+    /// Read the next record as fields and whether each was quoted, skipping
+    /// blank lines.
+    ///
+    /// Quoted fields may contain separators, doubled quotes and line breaks.
+    async fn next(&mut self) -> Result<Option<Vec<(String, bool)>>> {
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line).await? == 0 {
+                if in_quotes {
+                    return Err(Error::InvalidParameter(format!(
+                        "CSV quoted field on line {} is not terminated",
+                        self.record_line
+                    )));
+                }
+                return Ok(None);
+            }
+            self.lines += 1;
+            if !in_quotes {
+                self.record_line = self.lines;
+            }
+
+            let mut chars = self.line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if c != '"' {
+                        field.push(c);
+                    } else if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        in_quotes = false;
+                    }
+                } else if c == '"' {
+                    in_quotes = true;
+                    quoted = true;
+                } else if c == self.delimiter {
+                    record.push((std::mem::take(&mut field), quoted));
+                    quoted = false;
+                } else if c != '\r' && c != '\n' {
+                    field.push(c);
+                }
+            }
+
+            if in_quotes {
+                continue;
+            }
+            if record.is_empty() && field.is_empty() && !quoted {
+                continue;
+            }
+            record.push((field, quoted));
+            return Ok(Some(record));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read every record of `csv`, with `-` marking quoted fields.
+    async fn records(csv: &str, delimiter: char) -> Result<Vec<Vec<String>>> {
+        let mut records = Records::new(csv.as_bytes(), delimiter);
+        let mut out = Vec::new();
+        while let Some(record) = records.next().await? {
+            out.push(
+                record
+                    .into_iter()
+                    .map(|(field, quoted)| if quoted { format!("-{}", field) } else { field })
+                    .collect(),
+            );
+        }
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn fields() -> Result<()> {
+        let cases: &[(&str, &[&[&str]])] = &[
+            ("a,b\n1,2\n", &[&["a", "b"], &["1", "2"]]),
+            // No final line break, CRLF, blank lines
+            ("a,b\r\n\r\n1,2", &[&["a", "b"], &["1", "2"]]),
+            // Separators, quotes and line breaks inside quotes
+            (r#""a,b","say ""hi""""#, &[&["-a,b", r#"-say "hi""#]]),
+            ("\"line 1\nline 2\",x\n", &[&["-line 1\nline 2", "x"]]),
+            ("\"crlf\r\nkept\",x\r\n", &[&["-crlf\r\nkept", "x"]]),
+            // Empty fields, unquoted and quoted
+            ("a,,\"\",\n", &[&["a", "", "-", ""]]),
+            (",\n", &[&["", ""]]),
+            ("\"\"\n", &[&["-"]]),
+            // Spaces are kept
+            (" a , b \n", &[&[" a ", " b "]]),
+        ];
+        for (csv, expected) in cases {
+            assert_eq!(records(csv, ',').await?, *expected, "{:?}", csv);
+        }
+
+        assert_eq!(
+            records("a;\"b;c\";d,e\n", ';').await?,
+            [["a", "-b;c", "d,e"]]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unterminated_quotes_fail() {
+        let error = records("a,b\n\"c,d\ne\n", ',').await.unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn record_lines() -> Result<()> {
+        let mut records = Records::new("a\n\"b\nc\"\n\nd\n".as_bytes(), ',');
+        let mut lines = Vec::new();
+        while records.next().await?.is_some() {
+            lines.push(records.record_line);
+        }
+        assert_eq!(lines, [1, 2, 5]);
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Converting results to JSON.
+//!
+//! [`ExecutionResult::to_json`] turns a result into an array of objects keyed
+//! by column name, for HTTP APIs that pass query output straight through.
+//! [`JsonOptions`] chooses how NULLs and blobs are written. [`Value`] also
+//! implements `Serialize`, writing blobs as byte arrays and timestamps as
+//! whole seconds since the Unix epoch.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::json::{BytesEncoding, JsonOptions, NullEncoding};
+//! use litesql_ha::HAConnection;
+//!
+//! async fn example(conn: &HAConnection) -> litesql_ha::Result<String> {
+//!     let result = conn.query("SELECT id, name, avatar FROM users", &[]).await?;
+//!     let options = JsonOptions {
+//!         nulls: NullEncoding::Omit,
+//!         bytes: BytesEncoding::Hex,
+//!     };
+//!     Ok(result.to_json_with(&options).to_string())
+//! }
+//! ```
+
+use crate::client::ExecutionResult;
+use crate::datetime::unix_seconds;
+use crate::value::Value;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Number};
+
+/// How NULL columns are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullEncoding {
+    /// As `null`
+    #[default]
+    Null,
+    /// Left out of the row's object
+    Omit,
+}
+
+/// How blob columns are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// As a standard base64 string
+    #[default]
+    Base64,
+    /// As a lowercase hex string
+    Hex,
+    /// As an array of byte values
+    Array,
+}
+
+/// Options for [`ExecutionResult::to_json_with`].
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    /// How NULL columns are written
+    pub nulls: NullEncoding,
+    /// How blob columns are written
+    pub bytes: BytesEncoding,
+}
+
+impl ExecutionResult {
+    /// Convert the rows to a JSON array of objects keyed by column name.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.to_json_with(&JsonOptions::default())
+    }
+
+    /// Convert the rows to a JSON array of objects, encoding NULLs and blobs
+    /// as `options` say.
+    pub fn to_json_with(&self, options: &JsonOptions) -> serde_json::Value {
+        self.rows
+            .iter()
+            .map(|values| row_to_json(&self.columns, values, options))
+            .collect()
+    }
+}
+
+/// Convert one row to a JSON object keyed by column name.
+pub(crate) fn row_to_json(
+    columns: &[String],
+    values: &[Value],
+    options: &JsonOptions,
+) -> serde_json::Value {
+    let mut object = Map::with_capacity(columns.len());
+    for (column, value) in columns.iter().zip(values) {
+        if *value == Value::Null && options.nulls == NullEncoding::Omit {
+            continue;
+        }
+        object.insert(column.clone(), to_json(value, options));
+    }
+    serde_json::Value::Object(object)
+}
+
+fn to_json(value: &Value, options: &JsonOptions) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(v) => serde_json::Value::Bool(*v),
+        Value::Int32(v) => serde_json::Value::from(*v),
+        Value::Int64(v) => serde_json::Value::from(*v),
+        // NaN and infinities have no JSON form.
+        Value::Float(v) => Number::from_f64(*v as f64).map_or(serde_json::Value::Null, Into::into),
+        Value::Double(v) => Number::from_f64(*v).map_or(serde_json::Value::Null, Into::into),
+        Value::String(v) => serde_json::Value::String(v.clone()),
+        Value::Bytes(v) => match options.bytes {
+            BytesEncoding::Base64 => serde_json::Value::String(base64(v)),
+            BytesEncoding::Hex => serde_json::Value::String(hex(v)),
+            BytesEncoding::Array => serde_json::Value::from(v.as_slice()),
+        },
+        Value::Timestamp(v) => serde_json::Value::from(unix_seconds(v)),
+        #[cfg(feature = "json")]
+        Value::Json(v) => v.clone(),
+        // As a string, so no digits are lost to a JSON reader's doubles.
+        #[cfg(feature = "decimal")]
+        Value::Decimal(v) => serde_json::Value::String(v.to_string()),
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Int32(v) => serializer.serialize_i32(*v),
+            Value::Int64(v) => serializer.serialize_i64(*v),
+            Value::Float(v) => serializer.serialize_f32(*v),
+            Value::Double(v) => serializer.serialize_f64(*v),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Bytes(v) => serializer.serialize_bytes(v),
+            Value::Timestamp(v) => serializer.serialize_i64(unix_seconds(v)),
+            #[cfg(feature = "json")]
+            Value::Json(v) => v.serialize(serializer),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(v) => serializer.collect_str(v),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -27,18 +27,143 @@
 //! }
 //! ```
 
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("building for wasm32 requires the `wasm` feature");
+#[cfg(all(target_arch = "wasm32", feature = "embedded-replicas"))]
+compile_error!("embedded replicas are not available on wasm32: disable the default features");
+
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "bb8")]
+pub mod bb8;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod bulk;
+pub mod cache;
+pub mod cancel;
+#[cfg(feature = "nats")]
+pub mod changes;
 pub mod client;
+pub mod cluster;
+pub mod coalesce;
+#[cfg(feature = "codegen")]
+pub mod codegen;
 pub mod connection;
 pub mod datasource;
+pub mod datetime;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod deadline;
+#[cfg(feature = "deadpool")]
+pub mod deadpool;
+#[cfg(feature = "diesel")]
+pub mod diesel;
+#[cfg(feature = "diesel-async")]
+pub mod diesel_async;
+pub mod discovery;
+pub mod download;
+#[cfg(feature = "embedded-replicas")]
 pub mod embedded_replicas;
+#[cfg(feature = "sqlcipher")]
+pub mod encryption;
 pub mod error;
+#[cfg(feature = "csv")]
+pub mod export;
+pub mod fingerprint;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+#[cfg(feature = "embedded-replicas")]
+mod frames;
+#[cfg(target_arch = "wasm32")]
+pub mod grpc_web;
+pub mod health;
+pub mod hooks;
+#[cfg(feature = "csv")]
+pub mod import;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod load_balancing;
+pub mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
+#[cfg(feature = "migrations")]
+pub mod migrations;
+mod params;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod pipeline;
+#[cfg(feature = "polars")]
+pub mod polars;
+pub mod pool;
+pub mod query_stats;
+pub mod recording;
+#[cfg(feature = "nats")]
+mod replication;
+pub mod routing;
+pub mod row;
+mod runtime;
+#[cfg(feature = "rusqlite-compat")]
+pub mod rusqlite_compat;
+mod script;
+#[cfg(feature = "sea-query")]
+pub mod sea_query;
+pub mod slow_query;
+pub mod statement;
+pub mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "testcontainers")]
+pub mod testcontainers;
+pub mod transaction;
 pub mod value;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
-pub use client::{HAClient, HAClientOptions};
+pub use audit::{AuditContext, AuditEvent, AuditHook, Auditor};
+pub use auth::{TokenFuture, TokenProvider};
+pub use bulk::{BulkInserter, BulkProgress, OnConflict};
+pub use cache::{QueryCache, QueryCacheOptions};
+pub use cancel::{CancelHandle, CancelOnDrop};
+pub use client::{
+    ExecuteResult, HAClient, HAClientOptions, KeepaliveOptions, QueryOptions, TlsOptions,
+};
+pub use cluster::{ClusterInfo, DatabaseStatus, NodeInfo, NodeRole};
+pub use coalesce::QueryCoalescer;
 pub use connection::{HAConnection, HAConnectionOptions};
 pub use datasource::{HADataSource, HADataSourceOptions};
-pub use embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
-pub use error::{Error, Result};
+pub use datetime::TimestampStorage;
+pub use discovery::DiscoveryOptions;
+pub use download::{DownloadOptions, DownloadProgress};
+#[cfg(feature = "nats")]
+pub use embedded_replicas::ReplicationEvent;
+#[cfg(feature = "embedded-replicas")]
+pub use embedded_replicas::{
+    EmbeddedReplicasManager, NatsTlsOptions, ReplicaEvent, ReplicaLag, ReplicaOptions, ReplicaSync,
+    StatementCacheCounts, StatementCacheStats,
+};
+#[cfg(feature = "sqlcipher")]
+pub use encryption::ReplicaKeyProvider;
+pub use error::{Error, ErrorKind, Result};
+pub use fingerprint::fingerprint;
+pub use health::Health;
+#[cfg(feature = "embedded-replicas")]
+pub use health::ReplicaHealth;
+pub use hooks::{QueryEvent, QueryHook, QueryOutcome, QuerySummary};
+pub use load_balancing::{
+    EndpointLoad, LatencyWeighted, LeastOutstanding, LoadBalancer, Nearest, RoundRobin, ZoneAware,
+};
+pub use metadata::{MetadataMap, MetadataProvider};
+pub use middleware::Middleware;
+pub use pipeline::Pipeline;
+pub use pool::{PoolOptions, PoolStatus, PooledConnection};
+pub use query_stats::{FingerprintStats, QueryStats};
+pub use recording::{RecordedExchange, Recorder, Replay};
+pub use routing::{Consistency, Route, RouteReason, RoutingDecision, RoutingStats};
+pub use row::{Column, FromRow, FromValue, OwnedRow, Row, ToParams};
+pub use slow_query::{SlowQuery, SlowQueryCallback, SlowQueryLog};
+pub use statement::Statement;
+pub use transaction::{Transaction, TransactionBehavior, TransactionFuture, TransactionOptions};
 pub use value::Value;
 
 /// Generated protobuf types
@@ -0,0 +1,518 @@
+//! Read load balancing across follower nodes.
+//!
+//! A cluster whose followers serve reads lists them in
+//! [`HAClientOptions::read_endpoints`](crate::HAClientOptions::read_endpoints).
+//! SELECTs sent to the server are then spread over the followers by a
+//! [`LoadBalancer`]: [`RoundRobin`] by default, [`LeastOutstanding`],
+//! [`LatencyWeighted`] or one of the application's own. Writes,
+//! transactions and streamed reads always go to the leader at `url`.
+//!
+//! A follower may lag behind the leader. Its answer is only used if it has
+//! applied every transaction the client has seen, unless the read's
+//! consistency is [`Eventual`](crate::Consistency::Eventual); otherwise the
+//! read is sent to the leader, as are `Strong` reads. A follower that cannot
+//! be reached is left out for a few seconds, and reads go to the leader
+//! while no follower is available.
+//!
+//! In a cluster spread over zones, a follower is labelled with its zone by a
+//! `zone` parameter in its URL, and [`ZoneAware`] keeps reads in the
+//! client's zone. With no follower there, or no labels at all, reads go to
+//! the [`Nearest`] follower: each is tried once to measure its latency, then
+//! the fastest serves the reads.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAClientOptions, LeastOutstanding};
+//! use std::sync::Arc;
+//!
+//! let options = HAClientOptions {
+//!     url: "litesql://leader:8080/app.db".to_string(),
+//!     read_endpoints: vec![
+//!         "litesql://follower-1:8080".to_string(),
+//!         "litesql://follower-2:8080".to_string(),
+//!     ],
+//!     load_balancer: Some(Arc::new(LeastOutstanding)),
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! Reading from the followers of the client's zone:
+//!
+//! ```no_run
+//! use litesql_ha::{HAClientOptions, ZoneAware};
+//! use std::sync::Arc;
+//!
+//! let options = HAClientOptions {
+//!     url: "litesql://leader:8080/app.db".to_string(),
+//!     read_endpoints: vec![
+//!         "litesql://follower-1:8080?zone=eu-west-1a".to_string(),
+//!         "litesql://follower-2:8080?zone=eu-west-1b".to_string(),
+//!     ],
+//!     load_balancer: Some(Arc::new(ZoneAware::new("eu-west-1a"))),
+//!     ..Default::default()
+//! };
+//! ```
+
+use crate::client::Connector;
+use crate::runtime::{Instant, SystemTime, UNIX_EPOCH};
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a follower that could not be reached is left out.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Weight of the latest read in a follower's latency average.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// A follower available for the next read, as seen by a [`LoadBalancer`].
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointLoad<'a> {
+    /// URL of the follower
+    pub url: &'a str,
+    /// Zone of the follower, from the `zone` parameter of its URL
+    pub zone: Option<&'a str>,
+    /// Reads in flight on the follower
+    pub outstanding: usize,
+    /// Moving average of the follower's read latency, once it has served one
+    pub latency: Option<Duration>,
+}
+
+/// Picks the follower serving each read.
+pub trait LoadBalancer: Send + Sync {
+    /// Pick the follower of the next read, returning its index in
+    /// `endpoints`, which is never empty.
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize;
+}
+
+impl fmt::Debug for dyn LoadBalancer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LoadBalancer")
+    }
+}
+
+/// Sends reads to each follower in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl LoadBalancer for RoundRobin {
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % endpoints.len()
+    }
+}
+
+/// Sends each read to the follower with the fewest reads in flight.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeastOutstanding;
+
+impl LoadBalancer for LeastOutstanding {
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize {
+        endpoints
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, endpoint)| endpoint.outstanding)
+            .map_or(0, |(i, _)| i)
+    }
+}
+
+/// Sends reads to followers at random, in inverse proportion to their
+/// latency; a follower that has not served a read yet is picked first.
+#[derive(Debug)]
+pub struct LatencyWeighted {
+    state: AtomicU64,
+}
+
+impl LatencyWeighted {
+    /// Create the policy.
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self {
+            state: AtomicU64::new(seed | 1),
+        }
+    }
+
+    /// Get a random number in `[0, 1)`.
+    fn random(&self) -> f64 {
+        // xorshift64*
+        let mut x = self.state.load(Ordering::Relaxed);
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.store(x, Ordering::Relaxed);
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for LatencyWeighted {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadBalancer for LatencyWeighted {
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize {
+        if let Some(i) = endpoints.iter().position(|e| e.latency.is_none()) {
+            return i;
+        }
+        let weights: Vec<f64> = endpoints
+            .iter()
+            .map(|e| 1.0 / e.latency.unwrap_or_default().as_secs_f64().max(1e-6))
+            .collect();
+        let mut target = self.random() * weights.iter().sum::<f64>();
+        for (i, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                return i;
+            }
+            target -= weight;
+        }
+        endpoints.len() - 1
+    }
+}
+
+/// Sends reads to the follower with the lowest latency; a follower that has
+/// not served a read yet is picked first, to measure it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Nearest;
+
+impl LoadBalancer for Nearest {
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize {
+        if let Some(i) = endpoints.iter().position(|e| e.latency.is_none()) {
+            return i;
+        }
+        endpoints
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, endpoint)| endpoint.latency)
+            .map_or(0, |(i, _)| i)
+    }
+}
+
+/// Keeps reads on the followers of the client's zone, spread over them
+/// round-robin by default; reads go to the [`Nearest`] follower while none
+/// of the zone is available.
+#[derive(Debug)]
+pub struct ZoneAware {
+    zone: String,
+    balancer: Arc<dyn LoadBalancer>,
+}
+
+impl ZoneAware {
+    /// Prefer the followers of `zone`, the client's own.
+    pub fn new(zone: impl Into<String>) -> Self {
+        Self {
+            zone: zone.into(),
+            balancer: Arc::new(RoundRobin::default()),
+        }
+    }
+
+    /// Spread reads over the followers of the zone with `balancer`.
+    pub fn with_balancer(mut self, balancer: Arc<dyn LoadBalancer>) -> Self {
+        self.balancer = balancer;
+        self
+    }
+
+    /// Get the client's zone.
+    pub fn zone(&self) -> &str {
+        &self.zone
+    }
+}
+
+impl LoadBalancer for ZoneAware {
+    fn pick(&self, endpoints: &[EndpointLoad<'_>]) -> usize {
+        let local: Vec<usize> = endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| endpoint.zone == Some(self.zone.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        if local.is_empty() {
+            return Nearest.pick(endpoints);
+        }
+        let loads: Vec<EndpointLoad<'_>> = local.iter().map(|&i| endpoints[i]).collect();
+        local
+            .get(self.balancer.pick(&loads))
+            .copied()
+            .unwrap_or(local[0])
+    }
+}
+
+/// The followers of a client and the policy spreading reads over them.
+pub(crate) struct ReadEndpoints {
+    endpoints: Vec<ReadEndpoint>,
+    balancer: Arc<dyn LoadBalancer>,
+}
+
+/// A follower and its load.
+pub(crate) struct ReadEndpoint {
+    url: String,
+    zone: Option<String>,
+    pub(crate) connector: Arc<Connector>,
+    outstanding: AtomicUsize,
+    /// Moving average of the latency in nanoseconds; 0 until a read is served
+    latency: AtomicU64,
+    unavailable_until: Mutex<Option<Instant>>,
+}
+
+/// Counts a read in flight on a follower until dropped.
+pub(crate) struct InFlight<'a> {
+    endpoint: &'a ReadEndpoint,
+    started: Instant,
+}
+
+impl ReadEndpoints {
+    pub(crate) fn new(endpoints: Vec<ReadEndpoint>, balancer: Arc<dyn LoadBalancer>) -> Self {
+        Self {
+            endpoints,
+            balancer,
+        }
+    }
+
+    /// Get every follower, available or not.
+    pub(crate) fn all(&self) -> &[ReadEndpoint] {
+        &self.endpoints
+    }
+
+    /// Pick the follower of the next read, or `None` if none is available.
+    pub(crate) fn pick(&self) -> Option<&ReadEndpoint> {
+        let now = Instant::now();
+        let available: Vec<&ReadEndpoint> = self
+            .endpoints
+            .iter()
+            .filter(|e| e.unavailable_until.lock().is_none_or(|until| until <= now))
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+        let loads: Vec<EndpointLoad<'_>> = available.iter().map(|e| e.load()).collect();
+        let i = self.balancer.pick(&loads);
+        available.get(i).copied()
+    }
+}
+
+impl ReadEndpoint {
+    pub(crate) fn new(url: String, zone: Option<String>, connector: Connector) -> Self {
+        Self {
+            url,
+            zone,
+            connector: Arc::new(connector),
+            outstanding: AtomicUsize::new(0),
+            latency: AtomicU64::new(0),
+            unavailable_until: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn zone(&self) -> Option<&str> {
+        self.zone.as_deref()
+    }
+
+    fn load(&self) -> EndpointLoad<'_> {
+        let latency = self.latency.load(Ordering::Relaxed);
+        EndpointLoad {
+            url: &self.url,
+            zone: self.zone.as_deref(),
+            outstanding: self.outstanding.load(Ordering::Relaxed),
+            latency: (latency > 0).then(|| Duration::from_nanos(latency)),
+        }
+    }
+
+    /// Count a read in flight until the returned guard is dropped.
+    pub(crate) fn start(&self) -> InFlight<'_> {
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        InFlight {
+            endpoint: self,
+            started: Instant::now(),
+        }
+    }
+
+    /// Leave the follower out of the next reads for a while.
+    pub(crate) fn set_unavailable(&self) {
+        *self.unavailable_until.lock() = Some(Instant::now() + RETRY_AFTER);
+    }
+}
+
+impl InFlight<'_> {
+    /// Add the time the read took to the follower's latency average.
+    pub(crate) fn succeeded(self) {
+        let sample = self.started.elapsed().as_nanos().max(1) as f64;
+        let _ =
+            self.endpoint
+                .latency
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                    Some(match average {
+                        0 => sample as u64,
+                        average => {
+                            (average as f64 * (1.0 - LATENCY_WEIGHT) + sample * LATENCY_WEIGHT)
+                                as u64
+                        }
+                    })
+                });
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.endpoint.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::transport::Endpoint;
+
+    fn loads(outstanding: &[usize], latencies: &[Option<u64>]) -> Vec<EndpointLoad<'static>> {
+        outstanding
+            .iter()
+            .zip(latencies)
+            .map(|(&outstanding, latency)| EndpointLoad {
+                url: "",
+                zone: None,
+                outstanding,
+                latency: latency.map(Duration::from_millis),
+            })
+            .collect()
+    }
+
+    /// Followers that are never dialled, since no read is sent.
+    fn followers(count: usize, balancer: Arc<dyn LoadBalancer>) -> ReadEndpoints {
+        let endpoints = (0..count)
+            .map(|i| {
+                let endpoint = Endpoint::from_shared(format!("http://follower-{i}:8080")).unwrap();
+                let channel = endpoint.connect_lazy();
+                let connector = Connector::new(endpoint, None, channel);
+                ReadEndpoint::new(format!("follower-{i}"), None, connector)
+            })
+            .collect();
+        ReadEndpoints::new(endpoints, balancer)
+    }
+
+    fn picks(endpoints: &ReadEndpoints, count: usize) -> Vec<Option<&str>> {
+        (0..count)
+            .map(|_| endpoints.pick().map(ReadEndpoint::url))
+            .collect()
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let balancer = RoundRobin::default();
+        let endpoints = loads(&[0, 0, 0], &[None, None, None]);
+        let picks: Vec<usize> = (0..7).map(|_| balancer.pick(&endpoints)).collect();
+        assert_eq!(picks, [0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn least_outstanding_picks_the_least_busy() {
+        let cases: &[(&[usize], usize)] =
+            &[(&[3, 0, 1], 1), (&[2, 2, 1], 2), (&[1, 1, 1], 0), (&[5], 0)];
+        for (outstanding, expected) in cases {
+            let endpoints = loads(outstanding, &[None; 4]);
+            assert_eq!(
+                LeastOutstanding.pick(&endpoints),
+                *expected,
+                "{outstanding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn latency_weighted_measures_new_followers_first() {
+        let balancer = LatencyWeighted::new();
+        let endpoints = loads(&[0, 0, 0], &[Some(1), None, Some(1)]);
+        for _ in 0..10 {
+            assert_eq!(balancer.pick(&endpoints), 1);
+        }
+    }
+
+    #[test]
+    fn latency_weighted_favours_the_fastest() {
+        let balancer = LatencyWeighted {
+            state: AtomicU64::new(0x9E37_79B9_7F4A_7C15),
+        };
+        let endpoints = loads(&[0, 0], &[Some(1), Some(9)]);
+        let mut counts = [0; 2];
+        for _ in 0..1000 {
+            counts[balancer.pick(&endpoints)] += 1;
+        }
+        // 1 / 1ms against 1 / 9ms: nine reads in ten go to the first
+        assert!((850..950).contains(&counts[0]), "{counts:?}");
+        assert_eq!(counts[0] + counts[1], 1000);
+    }
+
+    #[test]
+    fn nearest_picks_the_lowest_latency() {
+        let cases: &[(&[Option<u64>], usize)] = &[
+            (&[Some(5), Some(2), Some(9)], 1),
+            (&[Some(5), None, Some(1)], 1),
+            (&[Some(3), Some(3)], 0),
+        ];
+        for (latencies, expected) in cases {
+            let endpoints = loads(&[0; 3][..latencies.len()], latencies);
+            assert_eq!(Nearest.pick(&endpoints), *expected, "{latencies:?}");
+        }
+    }
+
+    #[test]
+    fn zone_aware_keeps_reads_in_the_zone() {
+        let mut endpoints = loads(&[0, 0, 0, 0], &[Some(1), Some(5), Some(5), Some(2)]);
+        endpoints[1].zone = Some("a");
+        endpoints[2].zone = Some("a");
+        endpoints[3].zone = Some("b");
+        let balancer = ZoneAware::new("a");
+        let picks: Vec<usize> = (0..4).map(|_| balancer.pick(&endpoints)).collect();
+        assert_eq!(picks, [1, 2, 1, 2]);
+
+        let balancer = ZoneAware::new("c");
+        assert_eq!(balancer.pick(&endpoints), 0);
+    }
+
+    #[tokio::test]
+    async fn unavailable_followers_are_skipped() {
+        let endpoints = followers(3, Arc::new(RoundRobin::default()));
+        endpoints.all()[1].set_unavailable();
+        assert_eq!(
+            picks(&endpoints, 4),
+            [
+                Some("follower-0"),
+                Some("follower-2"),
+                Some("follower-0"),
+                Some("follower-2")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn no_follower_is_picked_while_all_are_down() {
+        let endpoints = followers(2, Arc::new(RoundRobin::default()));
+        for endpoint in endpoints.all() {
+            endpoint.set_unavailable();
+        }
+        assert_eq!(picks(&endpoints, 2), [None, None]);
+
+        let endpoints = followers(0, Arc::new(LeastOutstanding));
+        assert!(endpoints.pick().is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_in_flight_steer_least_outstanding() {
+        let endpoints = followers(2, Arc::new(LeastOutstanding));
+        let first = endpoints.pick().unwrap();
+        assert_eq!(first.url(), "follower-0");
+        let read = first.start();
+        assert_eq!(endpoints.pick().unwrap().url(), "follower-1");
+        read.succeeded();
+        assert_eq!(endpoints.pick().unwrap().url(), "follower-0");
+        assert!(endpoints.all()[0].load().latency.is_some());
+        assert!(endpoints.all()[1].load().latency.is_none());
+    }
+}
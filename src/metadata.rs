@@ -0,0 +1,125 @@
+//! Custom gRPC metadata.
+//!
+//! Metadata such as `x-request-id`, tenant headers or feature flags is sent
+//! with calls to the HA server, so its logs can be correlated with the
+//! application's requests. It comes from two places:
+//!
+//! - a [`MetadataProvider`] set on the client, connection or data source,
+//!   asked for metadata before every call;
+//! - a [`with_metadata`] scope, adding metadata to every call made within it
+//!   and replacing the provider's values for the same keys. Scopes nest, and
+//!   the innermost value of a key wins.
+//!
+//! The `authorization` header is set from the token after both, when there
+//! is one. Like a [`with_deadline`](crate::deadline::with_deadline) scope, a
+//! metadata scope belongs to the task running it; tasks it spawns do not
+//! inherit it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{metadata, HAConnection, HADataSource, MetadataMap};
+//! use std::sync::Arc;
+//!
+//! let mut ds = HADataSource::default();
+//! ds.set_metadata_provider(Arc::new(|metadata: &mut MetadataMap| {
+//!     metadata.insert("x-tenant", "acme".parse().unwrap());
+//! }));
+//!
+//! async fn handle(conn: &HAConnection, request_id: &str) -> litesql_ha::Result<()> {
+//!     let mut scoped = MetadataMap::new();
+//!     if let Ok(value) = request_id.parse() {
+//!         scoped.insert("x-request-id", value);
+//!     }
+//!     metadata::with_metadata(scoped, async {
+//!         conn.query("SELECT * FROM users WHERE id = 1", &[]).await
+//!     })
+//!     .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::runtime;
+use std::fmt;
+use std::future::Future;
+use tonic::metadata::KeyAndValueRef;
+
+pub use tonic::metadata::{MetadataMap, MetadataValue};
+
+runtime::task_local! {
+    static METADATA: MetadataMap;
+}
+
+/// Supplies metadata sent with every call.
+pub trait MetadataProvider: Send + Sync {
+    /// Add the metadata of the next call to `metadata`.
+    ///
+    /// Called before every call, so implementations should not block.
+    fn metadata(&self, metadata: &mut MetadataMap);
+}
+
+impl<F> MetadataProvider for F
+where
+    F: Fn(&mut MetadataMap) + Send + Sync,
+{
+    fn metadata(&self, metadata: &mut MetadataMap) {
+        self(metadata)
+    }
+}
+
+impl fmt::Debug for dyn MetadataProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetadataProvider")
+    }
+}
+
+/// Run `future` with `metadata` sent with every call it makes.
+pub async fn with_metadata<F: Future>(metadata: MetadataMap, future: F) -> F::Output {
+    let metadata = match current() {
+        Some(mut outer) => {
+            merge(&mut outer, &metadata);
+            outer
+        }
+        None => metadata,
+    };
+    METADATA.scope(metadata, future).await
+}
+
+/// Get the metadata of the enclosing [`with_metadata`] scope.
+pub fn current() -> Option<MetadataMap> {
+    METADATA.try_with(MetadataMap::clone).ok()
+}
+
+/// Get the metadata of a call: the provider's, then the enclosing scope's.
+pub(crate) fn collect(provider: Option<&dyn MetadataProvider>) -> MetadataMap {
+    let mut metadata = MetadataMap::new();
+    if let Some(provider) = provider {
+        provider.metadata(&mut metadata);
+    }
+    let _ = METADATA.try_with(|scoped| merge(&mut metadata, scoped));
+    metadata
+}
+
+/// Copy `from` into `into`, replacing the values of keys in both.
+fn merge(into: &mut MetadataMap, from: &MetadataMap) {
+    for entry in from.iter() {
+        match entry {
+            KeyAndValueRef::Ascii(key, _) => {
+                into.remove(key);
+            }
+            KeyAndValueRef::Binary(key, _) => {
+                into.remove_bin(key);
+            }
+        }
+    }
+    for entry in from.iter() {
+        match entry {
+            KeyAndValueRef::Ascii(key, value) => {
+                into.append(key.clone(), value.clone());
+            }
+            KeyAndValueRef::Binary(key, value) => {
+                into.append_bin(key.clone(), value.clone());
+            }
+        }
+    }
+}
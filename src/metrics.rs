@@ -0,0 +1,155 @@
+//! Metrics recorded through the [`metrics`](::metrics) facade.
+//!
+//! With the `metrics` feature, the client records its traffic with
+//! whichever recorder the application installs, such as a Prometheus or
+//! StatsD exporter; without one the metrics cost next to nothing. Every
+//! metric is labelled with the `replication_id` (database) it concerns:
+//!
+//! | Metric | Kind | Labels | Description |
+//! |---|---|---|---|
+//! | [`QUERIES`] | counter | `operation` | Requests sent to the HA server |
+//! | [`QUERY_ERRORS`] | counter | `operation` | Requests that failed or returned an error |
+//! | [`BYTES_SENT`] | counter | `operation` | Encoded size of the requests |
+//! | [`BYTES_RECEIVED`] | counter | `operation` | Encoded size of the responses |
+//! | [`REPLICA_READS`] | counter | `outcome` | Reads that could use an embedded replica |
+//! | [`DOWNLOAD_BYTES`] | counter | | Replica snapshot bytes received |
+//! | [`DOWNLOAD_DURATION`] | histogram | | Seconds taken by completed downloads |
+//!
+//! `operation` is `query` for reads, `update` for writes and `execute` for
+//! statements sent unclassified. A read counts as a replica `hit` when an
+//! embedded replica served it, and as a `miss` when it went to the server
+//! because the replica was stale, missing or not replicating; reads that
+//! had to go to the server anyway, such as those in a transaction, are not
+//! counted. Streamed queries count their first response only. Download
+//! throughput is the rate of [`DOWNLOAD_BYTES`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! // With a recorder such as `metrics_exporter_prometheus` installed:
+//! litesql_ha::metrics::describe_metrics();
+//! ```
+
+use crate::proto::{QueryRequest, QueryResponse, QueryType};
+use crate::routing::{Route, RouteReason, RoutingDecision};
+#[cfg(feature = "embedded-replicas")]
+use ::metrics::histogram;
+use ::metrics::{counter, describe_counter, describe_histogram, Unit};
+use prost::Message;
+#[cfg(feature = "embedded-replicas")]
+use std::time::Duration;
+
+/// Requests sent to the HA server.
+pub const QUERIES: &str = "litesql_ha_queries_total";
+/// Requests that failed or returned an error.
+pub const QUERY_ERRORS: &str = "litesql_ha_query_errors_total";
+/// Bytes of the requests sent to the HA server.
+pub const BYTES_SENT: &str = "litesql_ha_bytes_sent_total";
+/// Bytes of the responses received from the HA server.
+pub const BYTES_RECEIVED: &str = "litesql_ha_bytes_received_total";
+/// Reads that could use an embedded replica, by `outcome`: `hit` or `miss`.
+pub const REPLICA_READS: &str = "litesql_ha_replica_reads_total";
+/// Bytes of replica snapshots received.
+pub const DOWNLOAD_BYTES: &str = "litesql_ha_download_bytes_total";
+/// Seconds taken by replica downloads that completed.
+pub const DOWNLOAD_DURATION: &str = "litesql_ha_download_duration_seconds";
+
+/// Register the description and unit of each metric with the installed
+/// recorder.
+pub fn describe_metrics() {
+    describe_counter!(QUERIES, "Requests sent to the HA server");
+    describe_counter!(QUERY_ERRORS, "Requests that failed or returned an error");
+    describe_counter!(BYTES_SENT, Unit::Bytes, "Bytes of the requests sent");
+    describe_counter!(
+        BYTES_RECEIVED,
+        Unit::Bytes,
+        "Bytes of the responses received"
+    );
+    describe_counter!(REPLICA_READS, "Reads that could use an embedded replica");
+    describe_counter!(
+        DOWNLOAD_BYTES,
+        Unit::Bytes,
+        "Bytes of replica snapshots received"
+    );
+    describe_histogram!(
+        DOWNLOAD_DURATION,
+        Unit::Seconds,
+        "Seconds taken by replica downloads that completed"
+    );
+}
+
+/// A request sent to the HA server, recorded once its response arrives.
+pub(crate) struct Request {
+    labels: [(&'static str, String); 2],
+    bytes: u64,
+}
+
+impl Request {
+    /// Take the labels and size of `request` as it is sent.
+    pub(crate) fn start(request: &QueryRequest) -> Self {
+        Self {
+            labels: [
+                ("replication_id", request.replication_id.clone()),
+                ("operation", operation(request).to_string()),
+            ],
+            bytes: request.encoded_len() as u64,
+        }
+    }
+
+    /// Record the request with its response, or `None` if it failed.
+    pub(crate) fn finish(self, response: Option<&QueryResponse>) {
+        let labels = &self.labels;
+        counter!(QUERIES, labels).increment(1);
+        counter!(BYTES_SENT, labels).increment(self.bytes);
+        match response {
+            Some(response) => {
+                counter!(BYTES_RECEIVED, labels).increment(response.encoded_len() as u64);
+                if !response.error.is_empty() {
+                    counter!(QUERY_ERRORS, labels).increment(1);
+                }
+            }
+            None => counter!(QUERY_ERRORS, labels).increment(1),
+        }
+    }
+}
+
+/// Record whether a read of `replication_id` was served by its replica.
+pub(crate) fn replica_read(replication_id: &str, decision: &RoutingDecision) {
+    let outcome = match (decision.route, decision.reason) {
+        (Route::Replica, _) => "hit",
+        (
+            Route::Primary,
+            RouteReason::ReplicaStale { .. }
+            | RouteReason::NoReplica
+            | RouteReason::ReplicationDown,
+        ) => "miss",
+        _ => return,
+    };
+    counter!(
+        REPLICA_READS,
+        "replication_id" => replication_id.to_string(),
+        "outcome" => outcome
+    )
+    .increment(1);
+}
+
+/// Record `bytes` of a snapshot of `replication_id` received.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) fn download_bytes(replication_id: &str, bytes: u64) {
+    counter!(DOWNLOAD_BYTES, "replication_id" => replication_id.to_string()).increment(bytes);
+}
+
+/// Record a download of `replication_id` that completed in `elapsed`.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) fn download_completed(replication_id: &str, elapsed: Duration) {
+    histogram!(DOWNLOAD_DURATION, "replication_id" => replication_id.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+fn operation(request: &QueryRequest) -> &'static str {
+    match QueryType::try_from(request.r#type) {
+        Ok(QueryType::ExecQuery) => "query",
+        Ok(QueryType::ExecUpdate) => "update",
+        _ => "execute",
+    }
+}
@@ -0,0 +1,94 @@
+//! Interceptors and tower middleware around the gRPC channel.
+//!
+//! A [`Middleware`] set in
+//! [`HAClientOptions::middleware`](crate::HAClientOptions::middleware) wraps
+//! the channel every call goes through, so an application's existing
+//! interceptors and tower layers for auth, retries or telemetry apply to
+//! this client too. A stack of layers is passed as one, built with
+//! `tower::ServiceBuilder`; interceptors see each call after the client has
+//! set its metadata, deadline and token.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAClientOptions, Middleware};
+//! use tonic::{Request, Status};
+//!
+//! let options = HAClientOptions {
+//!     url: "litesql://localhost:8080/app.db".to_string(),
+//!     middleware: Some(Middleware::interceptor(|mut request: Request<()>| {
+//!         request.metadata_mut().insert("x-service", "billing".parse().unwrap());
+//!         Ok::<_, Status>(request)
+//!     })),
+//!     ..Default::default()
+//! };
+//! ```
+
+#[cfg(target_arch = "wasm32")]
+use crate::grpc_web::Channel;
+use std::fmt;
+use std::sync::Arc;
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Body, Bytes, Service, StdError};
+use tonic::service::Interceptor;
+#[cfg(not(target_arch = "wasm32"))]
+use tonic::transport::Channel;
+use tonic::Status;
+use tower::util::{BoxCloneService, ServiceExt};
+use tower::Layer;
+
+/// The channel of a client, behind its middleware.
+pub(crate) type Transport =
+    BoxCloneService<http::Request<BoxBody>, http::Response<BoxBody>, Status>;
+
+/// Middleware wrapping the gRPC channel of a client.
+#[derive(Clone)]
+pub struct Middleware {
+    wrap: Arc<dyn Fn(Channel) -> Transport + Send + Sync>,
+}
+
+impl Middleware {
+    /// Wrap the channel in a tower `layer`.
+    pub fn layer<L, S, B>(layer: L) -> Self
+    where
+        L: Layer<Channel, Service = S> + Send + Sync + 'static,
+        S: Service<http::Request<BoxBody>, Response = http::Response<B>> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<StdError>,
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<StdError>,
+    {
+        Self {
+            wrap: Arc::new(move |channel| {
+                BoxCloneService::new(
+                    layer
+                        .layer(channel)
+                        .map_response(|response| response.map(tonic::body::boxed))
+                        .map_err(|e| Status::from_error(e.into())),
+                )
+            }),
+        }
+    }
+
+    /// Run `interceptor` on every call.
+    pub fn interceptor<I>(interceptor: I) -> Self
+    where
+        I: Interceptor + Clone + Send + Sync + 'static,
+    {
+        Self::layer(tonic::service::interceptor(interceptor))
+    }
+}
+
+impl fmt::Debug for Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Middleware")
+    }
+}
+
+/// Put `channel` behind `middleware`, if there is any.
+pub(crate) fn transport(middleware: Option<&Middleware>, channel: Channel) -> Transport {
+    match middleware {
+        Some(middleware) => (middleware.wrap)(channel),
+        None => BoxCloneService::new(channel.map_err(|e| Status::from_error(e.into()))),
+    }
+}
@@ -0,0 +1,394 @@
+//! Schema migrations.
+//!
+//! Enabled by the `migrations` feature. A [`Migrator`] applies versioned SQL
+//! scripts through an [`HAConnection`], so every change is written to the HA
+//! server. Applied versions are recorded in the `__litesql_migrations` table
+//! together with a checksum of the script; editing a migration after it has
+//! been applied is reported as an error.
+//!
+//! Each migration runs in its own transaction. Scripts may contain several
+//! statements separated by `;`.
+//!
+//! # Embedding migrations
+//!
+//! [`include_migrations!`] embeds a directory of SQL files at compile time.
+//! The path is relative to the crate's `Cargo.toml`. Files are named
+//! `<version>_<name>.sql`, with an optional `<version>_<name>.down.sql`
+//! holding the script that reverts it:
+//!
+//! ```text
+//! migrations/
+//!   0001_create_users.sql
+//!   0001_create_users.down.sql
+//!   0002_add_email.sql
+//! ```
+//!
+//! Cargo does not track new files in the directory, so touch a source file
+//! (or add a `cargo:rerun-if-changed` line to a build script) after adding
+//! one.
+//!
+//! ```ignore
+//! use litesql_ha::migrations::include_migrations;
+//! use litesql_ha::{HADataSource, HADataSourceOptions};
+//!
+//! # async fn example() -> litesql_ha::Result<()> {
+//! let ds = HADataSource::new(HADataSourceOptions {
+//!     url: "litesql://localhost:8080".to_string(),
+//!     ..Default::default()
+//! });
+//! let conn = ds.get_connection().await?;
+//!
+//! let report = include_migrations!("./migrations").run(&conn).await?;
+//! println!("applied {:?}", report.applied);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`HAConnection`]: crate::HAConnection
+
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::script::split_statements;
+use crate::value::Value;
+use std::borrow::Cow;
+use std::time::SystemTime;
+use tracing::info;
+
+pub use litesql_ha_macros::include_migrations;
+
+/// Name of the table recording applied migrations.
+pub const MIGRATIONS_TABLE: &str = "__litesql_migrations";
+
+/// A single versioned migration.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    version: i64,
+    name: Cow<'static, str>,
+    up: Cow<'static, str>,
+    down: Option<Cow<'static, str>>,
+}
+
+impl Migration {
+    /// Create a migration from its version, name and SQL script.
+    pub fn new(
+        version: i64,
+        name: impl Into<Cow<'static, str>>,
+        up: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            version,
+            name: name.into(),
+            up: up.into(),
+            down: None,
+        }
+    }
+
+    /// Set the script that reverts this migration.
+    pub fn with_down(mut self, down: impl Into<Cow<'static, str>>) -> Self {
+        self.down = Some(down.into());
+        self
+    }
+
+    /// Get the version.
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// Get the name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the SQL script.
+    pub fn up(&self) -> &str {
+        &self.up
+    }
+
+    /// Get the SQL script that reverts this migration.
+    pub fn down(&self) -> Option<&str> {
+        self.down.as_deref()
+    }
+
+    /// Get the checksum of the SQL script.
+    pub fn checksum(&self) -> String {
+        format!("{:016x}", fnv1a(self.up.as_bytes()))
+    }
+}
+
+/// A migration recorded as applied on the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+    /// Version
+    pub version: i64,
+    /// Name
+    pub name: String,
+    /// Checksum of the script when it was applied
+    pub checksum: String,
+    /// When the migration was applied, in seconds since the Unix epoch
+    pub applied_at: i64,
+}
+
+/// Outcome of a migration run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    /// Versions applied, in order
+    pub applied: Vec<i64>,
+    /// Versions reverted, in order
+    pub reverted: Vec<i64>,
+    /// Whether this was a dry run and nothing was executed
+    pub dry_run: bool,
+}
+
+/// Applies migrations through an HA connection.
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+    dry_run: bool,
+}
+
+impl Migrator {
+    /// Create a migrator from a set of migrations.
+    pub fn new(migrations: impl IntoIterator<Item = Migration>) -> Self {
+        let mut migrations: Vec<Migration> = migrations.into_iter().collect();
+        migrations.sort_by_key(|m| m.version);
+        Self {
+            migrations,
+            dry_run: false,
+        }
+    }
+
+    /// Report what would run without executing anything.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Get the migrations, ordered by version.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Get the migrations recorded as applied, ordered by version.
+    pub async fn applied(&self, conn: &HAConnection) -> Result<Vec<AppliedMigration>> {
+        // Read from the server: a lagging replica could report stale versions.
+        let client = conn.client();
+        let exists = client
+            .execute_query(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+                &[Value::from(MIGRATIONS_TABLE)],
+            )
+            .await?;
+        if exists.rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let result = client
+            .execute_query(
+                &format!(
+                    "SELECT version, name, checksum, applied_at FROM {} ORDER BY version",
+                    MIGRATIONS_TABLE
+                ),
+                &[],
+            )
+            .await?;
+
+        result
+            .rows
+            .into_iter()
+            .map(|row| match row.as_slice() {
+                [version, Value::String(name), Value::String(checksum), applied_at] => {
+                    Ok(AppliedMigration {
+                        version: as_i64(version)?,
+                        name: name.clone(),
+                        checksum: checksum.clone(),
+                        applied_at: as_i64(applied_at)?,
+                    })
+                }
+                _ => Err(Error::Migration(format!(
+                    "unexpected row in {}",
+                    MIGRATIONS_TABLE
+                ))),
+            })
+            .collect()
+    }
+
+    /// Get the migrations that have not been applied yet.
+    pub async fn pending(&self, conn: &HAConnection) -> Result<Vec<&Migration>> {
+        let applied = self.applied(conn).await?;
+        self.check_checksums(&applied)?;
+        Ok(self
+            .migrations
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .collect())
+    }
+
+    /// Apply all pending migrations.
+    pub async fn run(&self, conn: &HAConnection) -> Result<MigrationReport> {
+        let pending = self.pending(conn).await?;
+        let mut report = MigrationReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+
+        if !self.dry_run && !pending.is_empty() {
+            conn.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (\
+                     version INTEGER PRIMARY KEY, \
+                     name TEXT NOT NULL, \
+                     checksum TEXT NOT NULL, \
+                     applied_at INTEGER NOT NULL)",
+                    MIGRATIONS_TABLE
+                ),
+                &[],
+            )
+            .await?;
+        }
+
+        for migration in pending {
+            if !self.dry_run {
+                info!(
+                    "Applying migration {} ({})",
+                    migration.version, migration.name
+                );
+                let record = format!(
+                    "INSERT INTO {} (version, name, checksum, applied_at) VALUES (?, ?, ?, ?)",
+                    MIGRATIONS_TABLE
+                );
+                let params = [
+                    Value::Int64(migration.version),
+                    Value::from(migration.name()),
+                    Value::String(migration.checksum()),
+                    Value::Int64(unix_now()),
+                ];
+                run_script(conn, migration, &migration.up, &record, &params).await?;
+            }
+            report.applied.push(migration.version);
+        }
+
+        Ok(report)
+    }
+
+    /// Revert applied migrations newer than `target`, newest first.
+    ///
+    /// Pass `0` to revert everything.
+    pub async fn undo(&self, conn: &HAConnection, target: i64) -> Result<MigrationReport> {
+        let applied = self.applied(conn).await?;
+        self.check_checksums(&applied)?;
+        let mut report = MigrationReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+
+        for applied in applied.iter().rev().filter(|a| a.version > target) {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|m| m.version == applied.version)
+                .ok_or_else(|| {
+                    Error::Migration(format!(
+                        "migration {} ({}) is applied but unknown to this migrator",
+                        applied.version, applied.name
+                    ))
+                })?;
+            let down = migration.down().ok_or_else(|| {
+                Error::Migration(format!(
+                    "migration {} ({}) has no down script",
+                    migration.version, migration.name
+                ))
+            })?;
+
+            if !self.dry_run {
+                info!(
+                    "Reverting migration {} ({})",
+                    migration.version, migration.name
+                );
+                let record = format!("DELETE FROM {} WHERE version = ?", MIGRATIONS_TABLE);
+                let params = [Value::Int64(migration.version)];
+                run_script(conn, migration, down, &record, &params).await?;
+            }
+            report.reverted.push(migration.version);
+        }
+
+        Ok(report)
+    }
+
+    fn check_checksums(&self, applied: &[AppliedMigration]) -> Result<()> {
+        for applied in applied {
+            if let Some(migration) = self
+                .migrations
+                .iter()
+                .find(|m| m.version == applied.version)
+            {
+                if migration.checksum() != applied.checksum {
+                    return Err(Error::Migration(format!(
+                        "migration {} ({}) was modified after it was applied",
+                        migration.version, migration.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run a script and its bookkeeping statement in one transaction.
+async fn run_script(
+    conn: &HAConnection,
+    migration: &Migration,
+    script: &str,
+    record: &str,
+    params: &[Value],
+) -> Result<()> {
+    conn.begin_transaction().await?;
+
+    let mut result = Ok(());
+    for statement in split_statements(script) {
+        if let Err(e) = conn.execute(&statement, &[]).await {
+            result = Err(e);
+            break;
+        }
+    }
+    if result.is_ok() {
+        result = conn.execute(record, params).await.map(|_| ());
+    }
+
+    match result {
+        Ok(()) => conn.commit().await,
+        Err(e) => {
+            let _ = conn.rollback().await;
+            Err(Error::Migration(format!(
+                "migration {} ({}) failed: {}",
+                migration.version, migration.name, e
+            )))
+        }
+    }
+}
+
+fn as_i64(value: &Value) -> Result<i64> {
+    match value {
+        Value::Int64(v) => Ok(*v),
+        Value::Int32(v) => Ok(*v as i64),
+        other => Err(Error::TypeConversion(format!(
+            "expected integer, got {:?}",
+            other
+        ))),
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
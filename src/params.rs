@@ -0,0 +1,195 @@
+//! Statement placeholders and named parameter binding.
+//!
+//! Placeholders are numbered the way SQLite numbers them: `?` takes the next
+//! index, `?NNN` an explicit one, and each distinct `:name`, `@name` or
+//! `$name` the next index. Named parameters are resolved to those indexes on
+//! the client, so the same binding works on the HA server and on an embedded
+//! replica, and a statement with an unbound placeholder is rejected before it
+//! is sent.
+
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// The placeholders of a statement, by parameter index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Placeholders {
+    /// Name (with its prefix) of each parameter, `None` for `?` and `?NNN`
+    names: Vec<Option<String>>,
+}
+
+impl Placeholders {
+    /// Scan a statement, skipping quoted text and comments.
+    ///
+    /// `:`, `@` and `$` only start a placeholder outside an identifier, so
+    /// a name such as `a$b` is left alone.
+    pub(crate) fn parse(sql: &str) -> Self {
+        let chars: Vec<char> = sql.chars().collect();
+        let mut names: Vec<Option<String>> = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                q @ ('\'' | '"' | '`' | '[') => {
+                    let close = if q == '[' { ']' } else { q };
+                    i += 1;
+                    while i < chars.len() && chars[i] != close {
+                        i += 1;
+                    }
+                }
+                '-' if chars.get(i + 1) == Some(&'-') => {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                }
+                '/' if chars.get(i + 1) == Some(&'*') => {
+                    i += 2;
+                    while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                '?' => {
+                    let start = i + 1;
+                    while i + 1 < chars.len() && chars[i + 1].is_ascii_digit() {
+                        i += 1;
+                    }
+                    let digits: String = chars[start..=i].iter().collect();
+                    match digits.parse::<usize>() {
+                        Ok(index) if index > names.len() => names.resize(index, None),
+                        Ok(_) => {}
+                        Err(_) => names.push(None),
+                    }
+                }
+                ':' | '@' | '$'
+                    if chars.get(i + 1).is_some_and(|c| is_name_char(*c))
+                        && !(i > 0 && is_identifier_char(chars[i - 1])) =>
+                {
+                    let start = i;
+                    while i + 1 < chars.len() && is_name_char(chars[i + 1]) {
+                        i += 1;
+                    }
+                    let name: String = chars[start..=i].iter().collect();
+                    if !names.iter().any(|n| n.as_deref() == Some(name.as_str())) {
+                        names.push(Some(name));
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        Self { names }
+    }
+
+    /// Get the number of parameters.
+    pub(crate) fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Resolve named parameters to positional ones.
+    ///
+    /// A name may be given with or without its prefix; without one it matches
+    /// `:name`, `@name` and `$name`. Every placeholder must be bound and every
+    /// name must match a placeholder.
+    pub(crate) fn bind(&self, params: &[(&str, Value)]) -> Result<Vec<Value>> {
+        let mut values: Vec<Option<Value>> = vec![None; self.names.len()];
+
+        for (name, value) in params {
+            let mut matched = false;
+            for (index, placeholder) in self.names.iter().enumerate() {
+                let Some(placeholder) = placeholder else {
+                    continue;
+                };
+                if placeholder == name || &placeholder[1..] == *name {
+                    values[index] = Some(value.clone());
+                    matched = true;
+                }
+            }
+            if !matched {
+                return Err(Error::InvalidParameter(format!(
+                    "no placeholder named {}",
+                    name
+                )));
+            }
+        }
+
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                value.ok_or_else(|| {
+                    let placeholder = match self.names[index] {
+                        Some(ref name) => name.clone(),
+                        None => format!("?{}", index + 1),
+                    };
+                    Error::InvalidParameter(format!("parameter {} is not bound", placeholder))
+                })
+            })
+            .collect()
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Check if `c` can appear in an unquoted SQLite identifier.
+fn is_identifier_char(c: char) -> bool {
+    is_name_char(c) || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(sql: &str) -> Vec<Option<String>> {
+        Placeholders::parse(sql).names
+    }
+
+    #[test]
+    fn parse() {
+        let cases: &[(&str, &[Option<&str>])] = &[
+            ("SELECT 1", &[]),
+            ("SELECT ?, ?", &[None, None]),
+            ("SELECT ?3", &[None, None, None]),
+            ("SELECT ?2, ?", &[None, None, None]),
+            ("SELECT ?1, ?1", &[None]),
+            ("SELECT :a, @b, $c", &[Some(":a"), Some("@b"), Some("$c")]),
+            ("SELECT :a, :a, :b", &[Some(":a"), Some(":b")]),
+            ("SELECT ?, :a", &[None, Some(":a")]),
+            ("SELECT 'a ? :b', \"c ?\", `d ?`, [e ?]", &[]),
+            ("SELECT 'it''s ?', ?", &[None]),
+            ("SELECT 1 -- ? :a\n, ?", &[None]),
+            ("SELECT /* ? :a */ ?", &[None]),
+            ("SELECT a$b, $c FROM t", &[Some("$c")]),
+            ("SELECT a:b, x@y FROM t", &[]),
+            ("SELECT t.c FROM t WHERE c = :c", &[Some(":c")]),
+            ("SELECT : , @, $", &[]),
+            ("SELECT :é", &[Some(":é")]),
+        ];
+        for (sql, expected) in cases {
+            let expected: Vec<Option<String>> =
+                expected.iter().map(|n| n.map(String::from)).collect();
+            assert_eq!(names(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn bind() {
+        let placeholders = Placeholders::parse("SELECT :a, @b, :a, ?");
+        assert_eq!(placeholders.len(), 3);
+        assert!(placeholders
+            .bind(&[("a", 1.into()), ("@b", 2.into())])
+            .is_err());
+
+        let placeholders = Placeholders::parse("SELECT :a, @b, $a");
+        let values = placeholders
+            .bind(&[("a", 1.into()), ("@b", 2.into())])
+            .unwrap();
+        assert_eq!(values, [1.into(), 2.into(), 1.into()]);
+        assert!(placeholders.bind(&[("a", 1.into())]).is_err());
+        assert!(placeholders
+            .bind(&[("a", 1.into()), ("b", 2.into()), ("c", 3.into())])
+            .is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Exporting query results as Parquet.
+//!
+//! Enabled by the `parquet` feature.
+//! [`HAConnection::query_to_parquet`](crate::HAConnection::query_to_parquet)
+//! streams a query's rows into Parquet row groups, converting each group to
+//! an Arrow record batch, so only one row group is held in memory at a time.
+//!
+//! Column types are taken from the first row group: integers and booleans
+//! become `Int64` and `Boolean`, numbers mixing integers and floats become
+//! `Float64`, text becomes `Utf8`, blobs `Binary` and timestamps UTC
+//! microsecond timestamps. Columns that are NULL throughout the first group
+//! become `Utf8`. A later value that does not fit its column's type fails
+//! the export with [`Error::TypeConversion`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::parquet::{ParquetCompression, ParquetOptions};
+//! use litesql_ha::HAConnection;
+//! use std::fs::File;
+//!
+//! async fn extract(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let options = ParquetOptions {
+//!         row_group_size: 100_000,
+//!         compression: ParquetCompression::Zstd,
+//!     };
+//!     let file = File::create("orders.parquet")?;
+//!     let rows = conn
+//!         .query_to_parquet("SELECT * FROM orders", &[], file, &options)
+//!         .await?;
+//!     println!("exported {} rows", rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::client::RowStream;
+use crate::datetime::unix_parts;
+use crate::error::{Error, Result};
+use crate::value::Value;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use ::parquet::file::properties::WriterProperties;
+use arrow_array::builder::{
+    BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+    TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::io::Write;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+/// Compression of the Parquet column chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetCompression {
+    /// No compression
+    Uncompressed,
+    /// Snappy
+    #[default]
+    Snappy,
+    /// Gzip at the default level
+    Gzip,
+    /// Zstandard at the default level
+    Zstd,
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Self {
+        match compression {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+/// Options for [`HAConnection::query_to_parquet`](crate::HAConnection::query_to_parquet).
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Rows per row group
+    pub row_group_size: usize,
+    /// Compression of the column chunks
+    pub compression: ParquetCompression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            row_group_size: 64 * 1024,
+            compression: ParquetCompression::default(),
+        }
+    }
+}
+
+/// Write the rows of `rows` to `writer` as Parquet, returning the number of
+/// rows written.
+pub(crate) async fn write_stream<W>(
+    mut rows: RowStream,
+    writer: W,
+    options: &ParquetOptions,
+) -> Result<u64>
+where
+    W: Write + Send,
+{
+    let columns = rows.columns().to_vec();
+    let row_group_size = options.row_group_size.max(1);
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(row_group_size)
+        .set_compression(options.compression.into())
+        .build();
+
+    let mut group = Vec::with_capacity(row_group_size);
+    next_group(&mut rows, &mut group, row_group_size).await?;
+    let schema = infer_schema(&columns, &group);
+    let mut file =
+        ArrowWriter::try_new(writer, schema.clone(), Some(properties)).map_err(parquet_error)?;
+
+    let mut count = 0;
+    loop {
+        if !group.is_empty() {
+            let batch = record_batch(schema.clone(), &group)?;
+            file.write(&batch).map_err(parquet_error)?;
+            count += group.len() as u64;
+        }
+        if group.len() < row_group_size {
+            break;
+        }
+        group.clear();
+        next_group(&mut rows, &mut group, row_group_size).await?;
+    }
+
+    file.close().map_err(parquet_error)?;
+    Ok(count)
+}
+
+/// Fill `group` up to `size` rows, stopping early at the end of `rows`.
+async fn next_group(rows: &mut RowStream, group: &mut Vec<Vec<Value>>, size: usize) -> Result<()> {
+    while group.len() < size {
+        match rows.next().await {
+            Some(row) => group.push(row?.into_values()),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Column types from the first non-NULL values of `rows`.
+fn infer_schema(columns: &[String], rows: &[Vec<Value>]) -> SchemaRef {
+    let fields: Vec<Field> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let mut data_type = None;
+            for value in rows.iter().filter_map(|row| row.get(i)) {
+                data_type = match (data_type, value_type(value)) {
+                    (current, None) => current,
+                    (None, found) => found,
+                    (Some(DataType::Int64), Some(DataType::Float64))
+                    | (Some(DataType::Float64), Some(DataType::Int64)) => Some(DataType::Float64),
+                    (current, Some(_)) => current,
+                };
+            }
+            Field::new(name, data_type.unwrap_or(DataType::Utf8), true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn value_type(value: &Value) -> Option<DataType> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(_) => DataType::Boolean,
+        Value::Int32(_) | Value::Int64(_) => DataType::Int64,
+        Value::Float(_) | Value::Double(_) => DataType::Float64,
+        Value::String(_) => DataType::Utf8,
+        Value::Bytes(_) => DataType::Binary,
+        Value::Timestamp(_) => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        #[cfg(feature = "json")]
+        Value::Json(_) => DataType::Utf8,
+        #[cfg(feature = "decimal")]
+        Value::Decimal(_) => DataType::Utf8,
+    })
+}
+
+/// Convert `rows` to a record batch of `schema`.
+fn record_batch(schema: SchemaRef, rows: &[Vec<Value>]) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| column(field, rows.iter().map(|row| &row[i])))
+        .collect::<Result<Vec<_>>>()?;
+    RecordBatch::try_new(schema, columns).map_err(parquet_error)
+}
+
+fn column<'a>(field: &Field, values: impl ExactSizeIterator<Item = &'a Value>) -> Result<ArrayRef> {
+    let mismatch = |value: &Value| {
+        Error::TypeConversion(format!(
+            "cannot write {:?} to {} column {}",
+            value,
+            field.data_type(),
+            field.name()
+        ))
+    };
+
+    let array: ArrayRef = match field.data_type() {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Bool(v) => builder.append_value(*v),
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Bool(v) => builder.append_value(*v as i64),
+                    Value::Int32(v) => builder.append_value(*v as i64),
+                    Value::Int64(v) => builder.append_value(*v),
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Int32(v) => builder.append_value(*v as f64),
+                    Value::Int64(v) => builder.append_value(*v as f64),
+                    Value::Float(v) => builder.append_value(*v as f64),
+                    Value::Double(v) => builder.append_value(*v),
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::with_capacity(values.len(), 0);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::String(v) => builder.append_value(v),
+                    #[cfg(feature = "json")]
+                    Value::Json(v) => builder.append_value(v.to_string()),
+                    #[cfg(feature = "decimal")]
+                    Value::Decimal(v) => builder.append_value(v.to_string()),
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Binary => {
+            let mut builder = BinaryBuilder::with_capacity(values.len(), 0);
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Bytes(v) => builder.append_value(v),
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder =
+                TimestampMicrosecondBuilder::with_capacity(values.len()).with_timezone("UTC");
+            for value in values {
+                match value {
+                    Value::Null => builder.append_null(),
+                    Value::Timestamp(v) => {
+                        let (seconds, nanos) = unix_parts(v);
+                        builder.append_value(seconds * 1_000_000 + (nanos / 1_000) as i64);
+                    }
+                    other => return Err(mismatch(other)),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+    };
+    Ok(array)
+}
+
+fn parquet_error(e: impl std::fmt::Display) -> Error {
+    Error::Parquet(e.to_string())
+}
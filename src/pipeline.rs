@@ -0,0 +1,142 @@
+//! Pipelined query execution.
+//!
+//! A [`Pipeline`] keeps one `Query` stream open and writes each query to it
+//! as soon as it is submitted, without waiting for the responses of earlier
+//! ones. The server answers in order, and a background task hands each
+//! response to the query that is next in line, so independent reads issued
+//! together cost about one round trip instead of one each.
+//!
+//! The server must answer every query with a single response message;
+//! results it splits over several messages are not reassembled. Pipelined
+//! queries are not recorded by a [`Recorder`](crate::Recorder).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAClient, Value};
+//!
+//! async fn example(client: &HAClient) -> litesql_ha::Result<()> {
+//!     let pipeline = client.pipeline();
+//!     let (users, orders) = tokio::join!(
+//!         pipeline.query("SELECT * FROM users WHERE id = ?", &[Value::Int64(1)]),
+//!         pipeline.query("SELECT * FROM orders WHERE user_id = ?", &[Value::Int64(1)]),
+//!     );
+//!     println!("{:?} {:?}", users?.rows, orders?.rows);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::client::{ExecutionResult, HAClient};
+use crate::error::{Error, Result};
+use crate::proto::{QueryRequest, QueryResponse, QueryType};
+use crate::runtime;
+use crate::value::Value;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::debug;
+
+/// Requests written ahead of their responses.
+const PIPELINE_DEPTH: usize = 64;
+
+type Waiter = oneshot::Sender<Result<QueryResponse>>;
+
+/// A `Query` stream that queries are written to without waiting for earlier
+/// responses.
+pub struct Pipeline<'a> {
+    client: &'a HAClient,
+    /// Held while submitting, so requests and waiters stay in the same order
+    queue: Mutex<(mpsc::Sender<QueryRequest>, mpsc::UnboundedSender<Waiter>)>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub(crate) fn new(client: &'a HAClient) -> Self {
+        let (requests, rx) = mpsc::channel(PIPELINE_DEPTH);
+        let (waiters, mut pending) = mpsc::unbounded_channel::<Waiter>();
+        let open = client.open_stream(rx, false, None);
+
+        // The replay answers queries itself; the stream is never read.
+        if client.replay().is_none() {
+            runtime::spawn_named(runtime::PIPELINE, async move {
+                let mut responses = match open.await {
+                    Ok(responses) => responses,
+                    Err(e) => {
+                        if let Some(waiter) = pending.recv().await {
+                            let _ = waiter.send(Err(e));
+                        }
+                        return;
+                    }
+                };
+                while let Some(waiter) = pending.recv().await {
+                    let result = match responses.message().await {
+                        Ok(Some(response)) => Ok(response),
+                        Ok(None) => Err(Error::Query("Pipeline stream ended".to_string())),
+                        Err(status) => Err(status.into()),
+                    };
+                    let failed = result.is_err();
+                    let _ = waiter.send(result);
+                    if failed {
+                        // Queries still in line fail with `ConnectionClosed`.
+                        debug!("Pipeline stream failed");
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self {
+            client,
+            queue: Mutex::new((requests, waiters)),
+        }
+    }
+
+    /// Execute a SELECT query.
+    pub async fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        let response = self.submit(sql, params, QueryType::ExecQuery).await?;
+        self.client.parse_response(response)
+    }
+
+    /// Execute any SQL statement.
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        let response = self.submit(sql, params, QueryType::Unspecified).await?;
+        self.client.parse_response(response)
+    }
+
+    async fn submit(
+        &self,
+        sql: &str,
+        params: &[Value],
+        query_type: QueryType,
+    ) -> Result<QueryResponse> {
+        let request = self.client.request(sql, params, query_type);
+        if let Some(replay) = self.client.replay() {
+            let response = replay.respond(&request)?;
+            self.client.observe(&response);
+            return Ok(response);
+        }
+
+        #[cfg(feature = "metrics")]
+        let sent = crate::metrics::Request::start(&request);
+        let (waiter, response) = oneshot::channel();
+        {
+            let queue = self.queue.lock().await;
+            let (requests, waiters) = &*queue;
+            waiters.send(waiter).map_err(|_| Error::ConnectionClosed)?;
+            requests
+                .send(request)
+                .await
+                .map_err(|_| Error::ConnectionClosed)?;
+        }
+
+        let response = response.await.map_err(|_| Error::ConnectionClosed)?;
+        #[cfg(feature = "metrics")]
+        sent.finish(response.as_ref().ok());
+        let response = response?;
+        self.client.observe(&response);
+        Ok(response)
+    }
+}
+
+impl std::fmt::Debug for Pipeline<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,167 @@
+//! Converting query results to Polars data frames.
+//!
+//! Enabled by the `polars` feature. [`ExecutionResult::to_dataframe`] and
+//! [`HAConnection::query_df`](crate::HAConnection::query_df) build a
+//! [`DataFrame`] with one column per result column.
+//!
+//! Column types are taken from the values: integers and booleans become
+//! `Int64` and `Boolean`, numbers mixing integers and floats become
+//! `Float64`, text becomes `String`, blobs `Binary` and timestamps
+//! microsecond `Datetime`s holding UTC without a time zone. Columns that are
+//! NULL throughout become `String`. A column mixing other types fails the conversion with
+//! [`Error::TypeConversion`]; cast in SQL to give it one type.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::HAConnection;
+//!
+//! async fn scores(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let df = conn
+//!         .query_df("SELECT player, score FROM scores", &[])
+//!         .await?;
+//!     println!("{}", df);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::client::ExecutionResult;
+use crate::datetime::unix_parts;
+use crate::error::{Error, Result};
+use crate::value::Value;
+use ::polars::prelude::{
+    Column, DataFrame, Int64Chunked, IntoSeries, NamedFrom, NewChunkedArray, Series, TimeUnit,
+};
+
+/// Polars type of a result column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Boolean,
+    Int64,
+    Float64,
+    String,
+    Binary,
+    Datetime,
+}
+
+impl ExecutionResult {
+    /// Convert the rows to a data frame with one column per result column.
+    pub fn to_dataframe(&self) -> Result<DataFrame> {
+        let columns = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let values: Vec<&Value> = self.rows.iter().map(|row| &row[i]).collect();
+                series(name, &values).map(Column::from)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        DataFrame::new(columns).map_err(|e| Error::TypeConversion(e.to_string()))
+    }
+}
+
+fn series(name: &str, values: &[&Value]) -> Result<Series> {
+    let mut kind = None;
+    for value in values {
+        kind = match (kind, value_kind(value)) {
+            (current, None) => current,
+            (None, found) => found,
+            (Some(current), Some(found)) if current == found => kind,
+            (Some(Kind::Int64), Some(Kind::Float64)) | (Some(Kind::Float64), Some(Kind::Int64)) => {
+                Some(Kind::Float64)
+            }
+            _ => {
+                return Err(Error::TypeConversion(format!(
+                    "column {} mixes {:?} with other types",
+                    name, value
+                )))
+            }
+        };
+    }
+
+    // Every value has been checked against the kind above.
+    Ok(match kind.unwrap_or(Kind::String) {
+        Kind::Boolean => Series::new(name.into(), options(values, as_bool)),
+        Kind::Int64 => Series::new(name.into(), options(values, as_i64)),
+        Kind::Float64 => Series::new(name.into(), options(values, as_f64)),
+        Kind::String => Series::new(name.into(), options(values, as_text)),
+        Kind::Binary => Series::new(
+            name.into(),
+            options(values, |v| match v {
+                Value::Bytes(v) => Some(v.as_slice()),
+                _ => None,
+            }),
+        ),
+        Kind::Datetime => Int64Chunked::from_iter_options(
+            name.into(),
+            values.iter().map(|v| match v {
+                Value::Timestamp(v) => {
+                    let (seconds, nanos) = unix_parts(v);
+                    Some(seconds * 1_000_000 + (nanos / 1_000) as i64)
+                }
+                _ => None,
+            }),
+        )
+        .into_datetime(TimeUnit::Microseconds, None)
+        .into_series(),
+    })
+}
+
+fn options<'a, T>(
+    values: &[&'a Value],
+    convert: impl Fn(&'a Value) -> Option<T>,
+) -> Vec<Option<T>> {
+    values.iter().map(|v| convert(v)).collect()
+}
+
+fn value_kind(value: &Value) -> Option<Kind> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(_) => Kind::Boolean,
+        Value::Int32(_) | Value::Int64(_) => Kind::Int64,
+        Value::Float(_) | Value::Double(_) => Kind::Float64,
+        Value::String(_) => Kind::String,
+        Value::Bytes(_) => Kind::Binary,
+        Value::Timestamp(_) => Kind::Datetime,
+        #[cfg(feature = "json")]
+        Value::Json(_) => Kind::String,
+        #[cfg(feature = "decimal")]
+        Value::Decimal(_) => Kind::String,
+    })
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn as_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int32(v) => Some(*v as i64),
+        Value::Int64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int32(v) => Some(*v as f64),
+        Value::Int64(v) => Some(*v as f64),
+        Value::Float(v) => Some(*v as f64),
+        Value::Double(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.clone()),
+        #[cfg(feature = "json")]
+        Value::Json(v) => Some(v.to_string()),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,453 @@
+//! Connection pooling for [`HADataSource`](crate::HADataSource).
+//!
+//! Connections handed out by a data source are [`PooledConnection`] guards
+//! that dereference to [`HAConnection`] and go back to the pool when dropped,
+//! so the gRPC channel of each connection is reused. At most
+//! [`max_size`](PoolOptions::max_size) connections are open at a time;
+//! callers wait up to the acquire timeout for one to be returned.
+//!
+//! A returned connection is recycled: its catalog and audit context are
+//! reset. Connections that were closed, returned with a transaction open or
+//! left in read-only mode are closed instead, as are connections past their
+//! [`max_lifetime`](PoolOptions::max_lifetime) and, above
+//! [`min_size`](PoolOptions::min_size), connections idle for longer than the
+//! [`idle_timeout`](PoolOptions::idle_timeout).
+
+use crate::audit::AuditContext;
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::runtime::{self, Instant};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+/// Options for the connection pool of a data source.
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// Connections opened on first use and kept open while idle; opening
+    /// them is best effort and stops at the first failure
+    pub min_size: usize,
+    /// Maximum number of open connections
+    pub max_size: usize,
+    /// How long to wait for a free connection; the login timeout when not set
+    pub acquire_timeout: Option<Duration>,
+    /// Close connections idle for longer than this, down to `min_size`
+    pub idle_timeout: Option<Duration>,
+    /// Close connections older than this
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 0,
+            max_size: 10,
+            acquire_timeout: None,
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(1800)),
+        }
+    }
+}
+
+/// Point-in-time pool counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// Open connections, idle or in use
+    pub size: usize,
+    /// Idle connections
+    pub idle: usize,
+    /// Maximum number of open connections
+    pub max_size: usize,
+}
+
+struct IdleConnection {
+    conn: HAConnection,
+    catalog: String,
+    created_at: Instant,
+    idle_since: Instant,
+    generation: u64,
+}
+
+/// Pool of open connections owned by a data source.
+pub(crate) struct Pool {
+    options: PoolOptions,
+    semaphore: Arc<Semaphore>,
+    idle: Mutex<VecDeque<IdleConnection>>,
+    size: AtomicUsize,
+    /// Connections being opened, not yet counted in `size`
+    opening: AtomicUsize,
+    generation: AtomicU64,
+}
+
+impl Pool {
+    /// Create an empty pool.
+    pub(crate) fn new(options: PoolOptions) -> Self {
+        let max_size = options.max_size.max(1);
+        Self {
+            options: PoolOptions {
+                max_size,
+                min_size: options.min_size.min(max_size),
+                ..options
+            },
+            semaphore: Arc::new(Semaphore::new(max_size)),
+            idle: Mutex::new(VecDeque::new()),
+            size: AtomicUsize::new(0),
+            opening: AtomicUsize::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the pool options.
+    pub(crate) fn options(&self) -> &PoolOptions {
+        &self.options
+    }
+
+    /// Take an idle connection or open a new one with `connect`.
+    pub(crate) async fn acquire<F, Fut>(
+        self: &Arc<Self>,
+        timeout: Duration,
+        connect: F,
+    ) -> Result<PooledConnection>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<HAConnection>>,
+    {
+        let timeout = self.options.acquire_timeout.unwrap_or(timeout);
+        let permit = runtime::timeout(timeout, self.semaphore.clone().acquire_owned())
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map_err(|_| Error::ConnectionClosed)?;
+
+        let generation = self.generation.load(Ordering::Acquire);
+        if let Some(idle) = self.take_idle(generation) {
+            return Ok(PooledConnection {
+                conn: Some(idle.conn),
+                catalog: idle.catalog,
+                created_at: idle.created_at,
+                generation: idle.generation,
+                pool: self.clone(),
+                _permit: permit,
+            });
+        }
+
+        self.opening.fetch_add(1, Ordering::AcqRel);
+        let conn = connect().await;
+        if conn.is_ok() {
+            self.size.fetch_add(1, Ordering::AcqRel);
+        }
+        self.opening.fetch_sub(1, Ordering::AcqRel);
+        let conn = conn?;
+        let pooled = PooledConnection {
+            catalog: conn.catalog(),
+            conn: Some(conn),
+            created_at: Instant::now(),
+            generation,
+            pool: self.clone(),
+            _permit: permit,
+        };
+
+        self.fill(generation, &connect).await;
+        Ok(pooled)
+    }
+
+    /// Open idle connections up to `min_size`, stopping at the first that
+    /// fails to open or when every connection is in use.
+    ///
+    /// Connections being opened count toward the minimum, so concurrent
+    /// acquires do not open more than it between them, and each holds a
+    /// permit until it is idle, so they never take the pool past `max_size`.
+    async fn fill<F, Fut>(&self, generation: u64, connect: &F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<HAConnection>>,
+    {
+        loop {
+            let Ok(permit) = self.semaphore.clone().try_acquire_owned() else {
+                return;
+            };
+            let reserved =
+                self.opening
+                    .fetch_update(Ordering::AcqRel, Ordering::Acquire, |opening| {
+                        (self.size.load(Ordering::Acquire) + opening < self.options.min_size)
+                            .then_some(opening + 1)
+                    });
+            if reserved.is_err() {
+                return;
+            }
+
+            let conn = connect().await;
+            if conn.is_ok() {
+                self.size.fetch_add(1, Ordering::AcqRel);
+            }
+            self.opening.fetch_sub(1, Ordering::AcqRel);
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Could not open a connection up to the pool minimum: {}", e);
+                    return;
+                }
+            };
+            let now = Instant::now();
+            self.idle.lock().push_back(IdleConnection {
+                catalog: conn.catalog(),
+                conn,
+                created_at: now,
+                idle_since: now,
+                generation,
+            });
+            drop(permit);
+        }
+    }
+
+    /// Close every idle connection and retire the connections in use.
+    pub(crate) fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        let drained = self.idle.lock().drain(..).count();
+        self.size.fetch_sub(drained, Ordering::AcqRel);
+    }
+
+    /// Get the pool counters.
+    pub(crate) fn status(&self) -> PoolStatus {
+        PoolStatus {
+            size: self.size.load(Ordering::Acquire),
+            idle: self.idle.lock().len(),
+            max_size: self.options.max_size,
+        }
+    }
+
+    fn take_idle(&self, generation: u64) -> Option<IdleConnection> {
+        let mut idle = self.idle.lock();
+        while let Some(candidate) = idle.pop_back() {
+            if self.is_reusable(&candidate.conn, candidate.created_at, candidate.generation)
+                && candidate.generation == generation
+            {
+                self.reap(&mut idle);
+                return Some(candidate);
+            }
+            self.size.fetch_sub(1, Ordering::AcqRel);
+        }
+        None
+    }
+
+    /// Close idle connections past the idle timeout, keeping `min_size` open.
+    fn reap(&self, idle: &mut VecDeque<IdleConnection>) {
+        let Some(idle_timeout) = self.options.idle_timeout else {
+            return;
+        };
+        while self.size.load(Ordering::Acquire) > self.options.min_size {
+            match idle.front() {
+                Some(oldest) if oldest.idle_since.elapsed() >= idle_timeout => {
+                    idle.pop_front();
+                    self.size.fetch_sub(1, Ordering::AcqRel);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn is_reusable(&self, conn: &HAConnection, created_at: Instant, generation: u64) -> bool {
+        !conn.is_closed()
+            && generation == self.generation.load(Ordering::Acquire)
+            && self
+                .options
+                .max_lifetime
+                .is_none_or(|lifetime| created_at.elapsed() < lifetime)
+    }
+
+    fn release(&self, conn: HAConnection, catalog: String, created_at: Instant, generation: u64) {
+        if !self.is_reusable(&conn, created_at, generation) || !is_recyclable(&conn) {
+            debug!("Closing pooled connection instead of recycling it");
+            self.size.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+
+        if reset(&conn, &catalog).is_err() {
+            self.size.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+
+        self.idle.lock().push_back(IdleConnection {
+            conn,
+            catalog,
+            created_at,
+            idle_since: Instant::now(),
+            generation,
+        });
+    }
+}
+
+/// Check if a returned connection can be handed out again: it is open, with
+/// no transaction left open and not in read-only mode.
+pub(crate) fn is_recyclable(conn: &HAConnection) -> bool {
+    !conn.is_closed() && conn.auto_commit() && !conn.read_only()
+}
+
+/// Reset a recycled connection for its next user: switch it back to
+/// `catalog` and clear its audit context.
+pub(crate) fn reset(conn: &HAConnection, catalog: &str) -> Result<()> {
+    if conn.catalog() != catalog {
+        conn.set_catalog(catalog)?;
+    }
+    conn.set_audit_context(AuditContext::default());
+    Ok(())
+}
+
+/// A connection borrowed from a data source's pool.
+///
+/// Dereferences to [`HAConnection`] and returns to the pool when dropped.
+pub struct PooledConnection {
+    conn: Option<HAConnection>,
+    catalog: String,
+    created_at: Instant,
+    generation: u64,
+    pool: Arc<Pool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    /// Take the connection out of the pool; it is not returned on drop.
+    pub fn detach(mut self) -> HAConnection {
+        self.pool.size.fetch_sub(1, Ordering::AcqRel);
+        self.conn.take().expect("connection present until dropped")
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = HAConnection;
+
+    fn deref(&self) -> &HAConnection {
+        self.conn
+            .as_ref()
+            .expect("connection present until dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let catalog = std::mem::take(&mut self.catalog);
+            self.pool
+                .release(conn, catalog, self.created_at, self.generation);
+        }
+    }
+}
+
+impl fmt::Debug for PooledConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnection")
+            .field("catalog", &self.catalog())
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::connection::HAConnectionOptions;
+    use crate::test_util::MockServer;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn pool(min_size: usize, max_size: usize) -> Arc<Pool> {
+        Arc::new(Pool::new(PoolOptions {
+            min_size,
+            max_size,
+            ..Default::default()
+        }))
+    }
+
+    async fn connect(server: &MockServer) -> Result<HAConnection> {
+        HAConnection::new(HAConnectionOptions {
+            url: server.url(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    fn status(size: usize, idle: usize, max_size: usize) -> PoolStatus {
+        PoolStatus {
+            size,
+            idle,
+            max_size,
+        }
+    }
+
+    #[tokio::test]
+    async fn acquire_opens_the_minimum() -> Result<()> {
+        let server = MockServer::start().await?;
+        let pool = pool(3, 5);
+
+        let conn = pool.acquire(TIMEOUT, || connect(&server)).await?;
+        assert_eq!(pool.status(), status(3, 2, 5));
+        drop(conn);
+        assert_eq!(pool.status(), status(3, 3, 5));
+
+        // Idle connections are reused rather than topped up again
+        let conn = pool.acquire(TIMEOUT, || connect(&server)).await?;
+        assert_eq!(pool.status(), status(3, 2, 5));
+        drop(conn);
+
+        server.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failing_to_open_the_minimum_keeps_the_connection() -> Result<()> {
+        let server = MockServer::start().await?;
+        let pool = pool(3, 5);
+        let attempts = AtomicUsize::new(0);
+
+        let conn = pool
+            .acquire(TIMEOUT, || async {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 => connect(&server).await,
+                    _ => Err(Error::ConnectionClosed),
+                }
+            })
+            .await?;
+        assert!(!conn.is_closed());
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert_eq!(pool.status(), status(1, 0, 5));
+
+        // The failed connection was not counted, so the next new one tops up
+        let _other = pool.acquire(TIMEOUT, || connect(&server)).await?;
+        assert_eq!(pool.status(), status(3, 1, 5));
+
+        server.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_acquires_do_not_overshoot() -> Result<()> {
+        let server = MockServer::start().await?;
+        let pool = pool(3, 5);
+        let (a, b) = tokio::join!(
+            pool.acquire(TIMEOUT, || connect(&server)),
+            pool.acquire(TIMEOUT, || connect(&server)),
+        );
+        let (_a, _b) = (a?, b?);
+        assert_eq!(pool.status(), status(3, 1, 5));
+
+        // At `max_size` the minimum is only opened while a permit is free
+        let pool = self::pool(2, 2);
+        let (a, b) = tokio::join!(
+            pool.acquire(TIMEOUT, || connect(&server)),
+            pool.acquire(TIMEOUT, || connect(&server)),
+        );
+        let (a, b) = (a?, b?);
+        assert_eq!(pool.status(), status(2, 0, 2));
+        drop((a, b));
+        assert_eq!(pool.status(), status(2, 2, 2));
+
+        server.shutdown().await;
+        Ok(())
+    }
+}
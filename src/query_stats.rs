@@ -0,0 +1,170 @@
+//! Per-statement statistics, keyed by fingerprint.
+//!
+//! [`QueryStats`] is a [`QueryHook`] that groups the statements it sees by
+//! their [`fingerprint`], counting calls, errors and rows returned and
+//! timing them, to find the statements worth optimizing. It is opt-in:
+//! register it on a data source or connection, and share it to read it back.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HADataSource, QueryStats};
+//! use std::sync::Arc;
+//!
+//! let stats = Arc::new(QueryStats::new());
+//! let mut ds = HADataSource::default();
+//! ds.add_query_hook(stats.clone());
+//!
+//! // ... run statements, then list the slowest in total:
+//! for entry in stats.snapshot().iter().take(10) {
+//!     println!(
+//!         "{:>8} calls  mean {:?}  p99 {:?}  {}",
+//!         entry.calls, entry.mean_latency, entry.p99_latency, entry.fingerprint
+//!     );
+//! }
+//! ```
+
+use crate::fingerprint::fingerprint;
+use crate::hooks::{QueryEvent, QueryHook, QueryOutcome};
+use parking_lot::Mutex;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Fingerprints tracked by [`QueryStats::new`].
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// Latencies of each fingerprint kept for its percentiles.
+const LATENCY_SAMPLES: usize = 1000;
+
+/// Statistics of the statements executed, by fingerprint.
+#[derive(Debug)]
+pub struct QueryStats {
+    capacity: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+/// Point-in-time statistics of one fingerprint in [`QueryStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintStats {
+    /// The statements' fingerprint
+    pub fingerprint: String,
+    /// Statements executed
+    pub calls: u64,
+    /// Statements that failed
+    pub errors: u64,
+    /// Rows returned, not counting streamed queries
+    pub rows: u64,
+    /// Time taken by all calls
+    pub total_latency: Duration,
+    /// Mean time taken per call
+    pub mean_latency: Duration,
+    /// 99th percentile of the time taken by the most recent calls
+    pub p99_latency: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    calls: u64,
+    errors: u64,
+    rows: u64,
+    total_latency: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl QueryStats {
+    /// Create empty statistics tracking up to 1000 fingerprints.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create empty statistics tracking up to `capacity` fingerprints;
+    /// statements with a new fingerprint are not tracked once it is reached.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a call of a statement with fingerprint `fingerprint` that took
+    /// `latency` and returned `rows` rows, or `None` if it failed.
+    pub fn record(&self, fingerprint: &str, latency: Duration, rows: Option<usize>) {
+        let mut entries = self.entries.lock();
+        if !entries.contains_key(fingerprint) && entries.len() >= self.capacity {
+            return;
+        }
+        let entry = entries.entry(fingerprint.to_string()).or_default();
+        entry.calls += 1;
+        match rows {
+            Some(rows) => entry.rows += rows as u64,
+            None => entry.errors += 1,
+        }
+        entry.total_latency += latency;
+        if entry.recent.len() == LATENCY_SAMPLES {
+            entry.recent.pop_front();
+        }
+        entry.recent.push_back(latency);
+    }
+
+    /// Get the statistics of `fingerprint`.
+    pub fn get(&self, fingerprint: &str) -> Option<FingerprintStats> {
+        self.entries
+            .lock()
+            .get(fingerprint)
+            .map(|entry| entry.stats(fingerprint))
+    }
+
+    /// Take a snapshot of every fingerprint, the most time-consuming first.
+    pub fn snapshot(&self) -> Vec<FingerprintStats> {
+        let mut stats: Vec<_> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|(fingerprint, entry)| entry.stats(fingerprint))
+            .collect();
+        stats.sort_by_key(|s| Reverse(s.total_latency));
+        stats
+    }
+
+    /// Forget every fingerprint.
+    pub fn reset(&self) {
+        self.entries.lock().clear();
+    }
+}
+
+impl Default for QueryStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueryHook for QueryStats {
+    fn after_query(&self, query: &QueryEvent<'_>, outcome: &QueryOutcome<'_>) {
+        let rows = outcome.result.ok().map(|summary| summary.row_count);
+        self.record(&fingerprint(query.sql), outcome.latency, rows);
+    }
+}
+
+impl Entry {
+    fn stats(&self, fingerprint: &str) -> FingerprintStats {
+        let mut recent: Vec<Duration> = self.recent.iter().copied().collect();
+        recent.sort_unstable();
+        // Nearest rank
+        let p99_latency = match recent.len() {
+            0 => Duration::ZERO,
+            n => recent[(n * 99).div_ceil(100) - 1],
+        };
+        FingerprintStats {
+            fingerprint: fingerprint.to_string(),
+            calls: self.calls,
+            errors: self.errors,
+            rows: self.rows,
+            total_latency: self.total_latency,
+            mean_latency: Duration::from_nanos(
+                (self.total_latency.as_nanos() / self.calls.max(1) as u128) as u64,
+            ),
+            p99_latency,
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Recording and replay of `Query` traffic.
+//!
+//! A [`Recorder`] set on [`HAClientOptions`](crate::HAClientOptions) (or on a
+//! connection or data source) appends every `Query` request together with its
+//! response or error status to a file. A [`Replay`] loaded from that file
+//! serves the recorded responses instead of calling the server, so an
+//! anomaly seen against a real cluster can be reproduced offline and turned
+//! into a regression test.
+//!
+//! Secrets are redacted before anything is written: the authentication token
+//! is never recorded, parameters go through a [masker](Recorder::with_masker)
+//! that replaces every non-null value by default, and values of
+//! [redacted columns](Recorder::with_redacted_column) are replaced in result
+//! sets. A replay masks incoming parameters the same way before matching, so
+//! a recorder and a replay using the same masker always agree.
+//!
+//! Only `Query` calls are recorded. Downloads and replication ID listings are
+//! not, and fail while replaying because no server is contacted.
+//!
+//! The file is a sequence of length-delimited [`RecordedExchange`] protobuf
+//! messages.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::recording::{Recorder, Replay};
+//! use litesql_ha::{HADataSource, HADataSourceOptions};
+//!
+//! # async fn example() -> litesql_ha::Result<()> {
+//! let ds = HADataSource::new(HADataSourceOptions {
+//!     url: "litesql://localhost:8080".to_string(),
+//!     recorder: Some(Recorder::create("traffic.bin")?.with_redacted_column("password")),
+//!     ..Default::default()
+//! });
+//! ds.get_connection().await?.query("SELECT * FROM users", &[]).await?;
+//!
+//! // Later, without a server:
+//! let ds = HADataSource::new(HADataSourceOptions {
+//!     url: "litesql://localhost:8080".to_string(),
+//!     replay: Some(Replay::open("traffic.bin")?),
+//!     ..Default::default()
+//! });
+//! let result = ds.get_connection().await?.query("SELECT * FROM users", &[]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::audit::{redact_all, ParameterMasker};
+use crate::error::{Error, Result};
+use crate::proto::{NamedValue, QueryRequest, QueryResponse};
+use crate::value::Value;
+use parking_lot::Mutex;
+use prost::Message;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use tonic::{Code, Status};
+use tracing::warn;
+
+/// One recorded `Query` call.
+#[derive(Clone, PartialEq, Message)]
+pub struct RecordedExchange {
+    /// Request, with parameters masked
+    #[prost(message, optional, tag = "1")]
+    pub request: Option<QueryRequest>,
+    /// Response, when the call succeeded
+    #[prost(message, optional, tag = "2")]
+    pub response: Option<QueryResponse>,
+    /// gRPC status code, `0` when the call succeeded
+    #[prost(int32, tag = "3")]
+    pub code: i32,
+    /// Error message, when the call failed
+    #[prost(string, tag = "4")]
+    pub message: String,
+}
+
+impl RecordedExchange {
+    /// Get the outcome of the call.
+    pub fn result(&self) -> Result<QueryResponse> {
+        if self.code != Code::Ok as i32 {
+            return Err(Status::new(Code::from(self.code), self.message.clone()).into());
+        }
+        Ok(self.response.clone().unwrap_or_default())
+    }
+}
+
+/// Appends `Query` traffic to a recording file.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+    masker: ParameterMasker,
+    redacted_columns: Vec<String>,
+}
+
+impl Recorder {
+    /// Create a recording file, truncating an existing one.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_file(File::create(path)?))
+    }
+
+    /// Open a recording file for appending, creating it if needed.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::from_file(file))
+    }
+
+    fn from_file(file: File) -> Self {
+        Self {
+            file: Arc::new(Mutex::new(file)),
+            masker: Arc::new(redact_all),
+            redacted_columns: Vec::new(),
+        }
+    }
+
+    /// Set the parameter masking function; defaults to [`redact_all`].
+    pub fn with_masker<F>(mut self, masker: F) -> Self
+    where
+        F: Fn(usize, &Value) -> Value + Send + Sync + 'static,
+    {
+        self.masker = Arc::new(masker);
+        self
+    }
+
+    /// Replace the values of a result column, matched case-insensitively.
+    pub fn with_redacted_column(mut self, column: impl Into<String>) -> Self {
+        self.redacted_columns.push(column.into());
+        self
+    }
+
+    /// Append a completed call to the file.
+    ///
+    /// Write failures are logged rather than returned so that recording
+    /// never breaks the traffic being recorded.
+    pub(crate) fn record(&self, request: &QueryRequest, result: &Result<QueryResponse>) {
+        let (response, code, message) = match result {
+            Ok(response) => (
+                Some(self.redact_response(response)),
+                Code::Ok,
+                String::new(),
+            ),
+            Err(Error::Status(status)) => (None, status.code(), status.message().to_string()),
+            Err(e) => (None, Code::Unknown, e.to_string()),
+        };
+        let exchange = RecordedExchange {
+            request: Some(mask_request(request, &self.masker)),
+            response,
+            code: code as i32,
+            message,
+        };
+
+        if let Err(e) = self
+            .file
+            .lock()
+            .write_all(&exchange.encode_length_delimited_to_vec())
+        {
+            warn!("Failed to record query: {}", e);
+        }
+    }
+
+    fn redact_response(&self, response: &QueryResponse) -> QueryResponse {
+        let mut response = response.clone();
+        if let Some(ref mut result_set) = response.result_set {
+            let redacted: Vec<usize> = result_set
+                .columns
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| {
+                    self.redacted_columns
+                        .iter()
+                        .any(|r| r.eq_ignore_ascii_case(c))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            for row in result_set.rows.iter_mut() {
+                for &i in &redacted {
+                    if let Some(value) = row.values.get_mut(i) {
+                        *value = redact_value(value);
+                    }
+                }
+            }
+        }
+        response
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("redacted_columns", &self.redacted_columns)
+            .finish()
+    }
+}
+
+/// Serves recorded responses instead of calling the server.
+///
+/// Each incoming request is answered by the first unused exchange with the
+/// same replication ID, SQL, query type and masked parameters, so repeated
+/// statements get their responses in recorded order. A request with no
+/// exchange left fails with [`Error::Replay`]. Clones share the same
+/// position in the recording.
+#[derive(Clone)]
+pub struct Replay {
+    exchanges: Arc<Vec<RecordedExchange>>,
+    used: Arc<Mutex<Vec<bool>>>,
+    masker: ParameterMasker,
+}
+
+impl Replay {
+    /// Load a recording file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut buf = bytes.as_slice();
+        let mut exchanges = Vec::new();
+        while !buf.is_empty() {
+            let exchange = RecordedExchange::decode_length_delimited(&mut buf)
+                .map_err(|e| Error::Replay(format!("invalid recording: {}", e)))?;
+            exchanges.push(exchange);
+        }
+        Ok(Self::new(exchanges))
+    }
+
+    /// Create a replay from exchanges.
+    pub fn new(exchanges: Vec<RecordedExchange>) -> Self {
+        Self {
+            used: Arc::new(Mutex::new(vec![false; exchanges.len()])),
+            exchanges: Arc::new(exchanges),
+            masker: Arc::new(redact_all),
+        }
+    }
+
+    /// Set the parameter masking function; must match the recorder's.
+    pub fn with_masker<F>(mut self, masker: F) -> Self
+    where
+        F: Fn(usize, &Value) -> Value + Send + Sync + 'static,
+    {
+        self.masker = Arc::new(masker);
+        self
+    }
+
+    /// Get the recorded exchanges, in recorded order.
+    pub fn exchanges(&self) -> &[RecordedExchange] {
+        &self.exchanges
+    }
+
+    /// Get the number of exchanges not served yet.
+    pub fn remaining(&self) -> usize {
+        self.used.lock().iter().filter(|used| !**used).count()
+    }
+
+    /// Answer a request from the recording.
+    pub(crate) fn respond(&self, request: &QueryRequest) -> Result<QueryResponse> {
+        let request = mask_request(request, &self.masker);
+        let mut used = self.used.lock();
+        let index = self
+            .exchanges
+            .iter()
+            .zip(used.iter())
+            .position(|(e, used)| !used && e.request.as_ref() == Some(&request))
+            .ok_or_else(|| {
+                Error::Replay(format!("no recorded response left for: {}", request.sql))
+            })?;
+        used[index] = true;
+        self.exchanges[index].result()
+    }
+}
+
+impl fmt::Debug for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replay")
+            .field("exchanges", &self.exchanges.len())
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+fn mask_request(request: &QueryRequest, masker: &ParameterMasker) -> QueryRequest {
+    let params = request
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let value = param
+                .value
+                .as_ref()
+                .and_then(|v| Value::from_any(v).ok())
+                .map(|v| masker(i, &v).to_any());
+            NamedValue {
+                name: param.name.clone(),
+                ordinal: param.ordinal,
+                value: value.or_else(|| param.value.clone()),
+            }
+        })
+        .collect();
+    QueryRequest {
+        params,
+        ..request.clone()
+    }
+}
+
+fn redact_value(value: &prost_types::Any) -> prost_types::Any {
+    let value = Value::from_any(value).unwrap_or(Value::Bytes(Vec::new()));
+    redact_all(0, &value).to_any()
+}
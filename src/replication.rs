@@ -0,0 +1,593 @@
+//! Applying replication messages to embedded replicas.
+//!
+//! The HA server publishes every committed transaction to a NATS JetStream
+//! stream as a JSON change set: the database file it changed and a list of
+//! changes. A change is a row `INSERT`, `UPDATE` or `DELETE` identified by
+//! rowid, or a `SQL` statement with its arguments, such as DDL:
+//!
+//! ```json
+//! {"filename": "app.db", "changes": [
+//!   {"operation": "INSERT", "table": "users", "columns": ["id", "name"],
+//!    "new_rowid": 7, "new_values": [7, "alice"]},
+//!   {"operation": "SQL", "command": "CREATE INDEX users_name ON users (name)"}
+//! ]}
+//! ```
+//!
+//! A change set is applied to the replica in one transaction, together with
+//! its stream sequence, recorded in the [`REPLICATION_TABLE`] and as the
+//! txseq of the `ha_stats` row the replica was downloaded with.
+//!
+//! Row changes address rows by rowid, so they cannot be applied to `WITHOUT
+//! ROWID` tables and fail instead.
+
+use crate::cache::identifiers;
+use crate::embedded_replicas::REPLICATION_TABLE;
+use crate::error::{Error, Result};
+use crate::script::quote_identifier;
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+
+/// The changes of one transaction on the leader.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChangeSet {
+    /// Database file the transaction changed
+    pub(crate) filename: String,
+    pub(crate) changes: Vec<Change>,
+}
+
+/// One change of a [`ChangeSet`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Change {
+    Insert {
+        table: String,
+        columns: Vec<String>,
+        rowid: i64,
+        values: Vec<SqliteValue>,
+    },
+    Update {
+        table: String,
+        columns: Vec<String>,
+        old_rowid: i64,
+        new_rowid: i64,
+        values: Vec<SqliteValue>,
+    },
+    Delete {
+        table: String,
+        rowid: i64,
+    },
+    Sql {
+        command: String,
+        args: Vec<SqliteValue>,
+    },
+}
+
+impl ChangeSet {
+    /// Parse a replication message.
+    pub(crate) fn parse(payload: &[u8]) -> Result<Self> {
+        let message: JsonValue = serde_json::from_slice(payload).map_err(invalid)?;
+        let changes = match message.get("changes") {
+            Some(JsonValue::Array(changes)) => changes
+                .iter()
+                .map(Change::parse)
+                .collect::<Result<Vec<_>>>()?,
+            Some(JsonValue::Null) | None => Vec::new(),
+            Some(other) => return Err(invalid(format!("changes is {}", other))),
+        };
+        Ok(Self {
+            filename: text(&message, "filename"),
+            changes,
+        })
+    }
+
+    /// Name of the replica file the change set applies to.
+    pub(crate) fn file_name(&self) -> &str {
+        self.filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(&self.filename)
+    }
+
+    /// Apply the changes to `conn` in one transaction, recording `seq` as
+    /// the replica's txseq.
+    pub(crate) fn apply(&self, conn: &mut Connection, seq: u64) -> Result<()> {
+        let tx = conn.transaction()?;
+        let mut checked = HashSet::new();
+        for change in &self.changes {
+            if let Some(table) = change.table() {
+                if checked.insert(table) && without_rowid(&tx, table)? {
+                    return Err(invalid(format!(
+                        "{} is a WITHOUT ROWID table, which row changes cannot address",
+                        table
+                    )));
+                }
+            }
+            change.apply(&tx)?;
+        }
+        record_position(&tx, seq as i64)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Lowercased names of the tables the change set changed and of the
+    /// views of `conn` reading them, or `None` if it ran SQL, which may
+    /// change anything.
+    pub(crate) fn changed_tables(&self, conn: &Connection) -> Result<Option<HashSet<String>>> {
+        let mut tables = HashSet::new();
+        for change in &self.changes {
+            match change {
+                Change::Insert { table, .. }
+                | Change::Update { table, .. }
+                | Change::Delete { table, .. } => tables.insert(table.to_lowercase()),
+                Change::Sql { .. } => return Ok(None),
+            };
+        }
+        if tables.is_empty() {
+            return Ok(Some(tables));
+        }
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT lower(name), sql FROM sqlite_master WHERE type = 'view' AND sql IS NOT NULL",
+        )?;
+        let views = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|view| view.map(|(name, sql)| (name, identifiers(&sql))))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Views over views: repeat until no view is added
+        loop {
+            let before = tables.len();
+            for (name, names) in &views {
+                if !tables.contains(name) && !names.is_disjoint(&tables) {
+                    tables.insert(name.clone());
+                }
+            }
+            if tables.len() == before {
+                return Ok(Some(tables));
+            }
+        }
+    }
+}
+
+impl Change {
+    /// Name of the table a row change changes.
+    fn table(&self) -> Option<&str> {
+        match self {
+            Change::Insert { table, .. }
+            | Change::Update { table, .. }
+            | Change::Delete { table, .. } => Some(table),
+            Change::Sql { .. } => None,
+        }
+    }
+
+    fn parse(change: &JsonValue) -> Result<Self> {
+        let table = text(change, "table");
+        let columns = match change.get("columns") {
+            Some(JsonValue::Array(columns)) => columns
+                .iter()
+                .map(|c| c.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid(format!("columns of {} are not names", table)))?,
+            _ => Vec::new(),
+        };
+        let rowid = |key: &str| change.get(key).and_then(JsonValue::as_i64).unwrap_or(0);
+
+        Ok(match text(change, "operation").as_str() {
+            "INSERT" => Change::Insert {
+                values: values(change, "new_values", columns.len(), &table)?,
+                rowid: rowid("new_rowid"),
+                table,
+                columns,
+            },
+            "UPDATE" => Change::Update {
+                values: values(change, "new_values", columns.len(), &table)?,
+                old_rowid: rowid("old_rowid"),
+                new_rowid: rowid("new_rowid"),
+                table,
+                columns,
+            },
+            "DELETE" => Change::Delete {
+                rowid: rowid("old_rowid"),
+                table,
+            },
+            "SQL" => Change::Sql {
+                command: text(change, "command"),
+                args: match change.get("args") {
+                    Some(JsonValue::Array(args)) => args.iter().map(to_sqlite).collect(),
+                    _ => Vec::new(),
+                },
+            },
+            other => return Err(invalid(format!("unknown operation {:?}", other))),
+        })
+    }
+
+    fn apply(&self, conn: &Connection) -> Result<()> {
+        if let Change::Insert { table, columns, .. } | Change::Update { table, columns, .. } = self
+        {
+            if columns.is_empty() {
+                return Err(invalid(format!("change to {} has no columns", table)));
+            }
+        }
+        match self {
+            Change::Insert {
+                table,
+                columns,
+                rowid,
+                values,
+            } => {
+                let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+                let sql = format!(
+                    "INSERT OR REPLACE INTO {} (rowid, {}) VALUES (?{})",
+                    quote_identifier(table),
+                    names.join(", "),
+                    ", ?".repeat(columns.len())
+                );
+                conn.execute(
+                    &sql,
+                    params_from_iter(std::iter::once(&SqliteValue::Integer(*rowid)).chain(values)),
+                )?;
+            }
+            Change::Update {
+                table,
+                columns,
+                old_rowid,
+                new_rowid,
+                values,
+            } => {
+                let sets: Vec<String> = columns
+                    .iter()
+                    .map(|c| format!("{} = ?", quote_identifier(c)))
+                    .collect();
+                let sql = format!(
+                    "UPDATE {} SET rowid = ?, {} WHERE rowid = ?",
+                    quote_identifier(table),
+                    sets.join(", ")
+                );
+                let params = std::iter::once(SqliteValue::Integer(*new_rowid))
+                    .chain(values.iter().cloned())
+                    .chain(std::iter::once(SqliteValue::Integer(*old_rowid)));
+                conn.execute(&sql, params_from_iter(params))?;
+            }
+            Change::Delete { table, rowid } => {
+                let sql = format!("DELETE FROM {} WHERE rowid = ?", quote_identifier(table));
+                conn.execute(&sql, [rowid])?;
+            }
+            Change::Sql { command, args } if args.is_empty() => conn.execute_batch(command)?,
+            Change::Sql { command, args } => {
+                conn.execute(command, params_from_iter(args))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record `seq` as the position of the replica: in the [`REPLICATION_TABLE`],
+/// and as the txseq of the latest `ha_stats` row if the replica has the table.
+fn record_position(conn: &Connection, seq: i64) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+             id INTEGER PRIMARY KEY CHECK (id = 1),
+             stream_seq INTEGER NOT NULL,
+             applied_at INTEGER NOT NULL
+         )",
+        REPLICATION_TABLE
+    ))?;
+    conn.execute(
+        &format!(
+            "INSERT INTO {} (id, stream_seq, applied_at) VALUES (1, ?1, strftime('%s', 'now'))
+             ON CONFLICT (id) DO UPDATE SET
+                 stream_seq = excluded.stream_seq, applied_at = excluded.applied_at",
+            REPLICATION_TABLE
+        ),
+        [seq],
+    )?;
+
+    let has_stats = conn
+        .prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'ha_stats'")?
+        .exists([])?;
+    if !has_stats {
+        return Ok(());
+    }
+    let updated = conn.execute(
+        "UPDATE ha_stats SET received_seq = ?1, updated_at = strftime('%s', 'now')
+         WHERE rowid = (SELECT rowid FROM ha_stats ORDER BY updated_at DESC LIMIT 1)",
+        [seq],
+    )?;
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO ha_stats (received_seq, updated_at) VALUES (?1, strftime('%s', 'now'))",
+            [seq],
+        )?;
+    }
+    Ok(())
+}
+
+/// Check if `table` is a `WITHOUT ROWID` table of `conn`.
+fn without_rowid(conn: &Connection, table: &str) -> Result<bool> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT wr FROM pragma_table_list WHERE schema = 'main' AND name = ?1 COLLATE NOCASE",
+    )?;
+    let wr = stmt
+        .query_row([table], |row| row.get::<_, bool>(0))
+        .optional()?;
+    Ok(wr.unwrap_or(false))
+}
+
+fn text(object: &JsonValue, key: &str) -> String {
+    object
+        .get(key)
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Read the values of a row, one per column.
+fn values(change: &JsonValue, key: &str, columns: usize, table: &str) -> Result<Vec<SqliteValue>> {
+    let values = match change.get(key) {
+        Some(JsonValue::Array(values)) => values,
+        _ => return Err(invalid(format!("change to {} has no {}", table, key))),
+    };
+    if values.len() != columns {
+        return Err(invalid(format!(
+            "change to {} has {} values for {} columns",
+            table,
+            values.len(),
+            columns
+        )));
+    }
+    Ok(values.iter().map(to_sqlite).collect())
+}
+
+fn to_sqlite(value: &JsonValue) -> SqliteValue {
+    match value {
+        JsonValue::Null => SqliteValue::Null,
+        JsonValue::Bool(v) => SqliteValue::Integer(*v as i64),
+        JsonValue::Number(v) => match v.as_i64() {
+            Some(v) => SqliteValue::Integer(v),
+            None => SqliteValue::Real(v.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(v) => SqliteValue::Text(v.clone()),
+        other => SqliteValue::Text(other.to_string()),
+    }
+}
+
+fn invalid(e: impl std::fmt::Display) -> Error {
+    Error::Nats(format!("invalid replication message: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE ha_stats (received_seq INTEGER, updated_at INTEGER);
+             INSERT INTO ha_stats VALUES (4, 0);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn names(conn: &Connection) -> Vec<(i64, String)> {
+        conn.prepare("SELECT rowid, name FROM users ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn apply(conn: &mut Connection, json: &str, seq: u64) -> Result<()> {
+        ChangeSet::parse(json.as_bytes())?.apply(conn, seq)
+    }
+
+    #[test]
+    fn parse() {
+        let changes = ChangeSet::parse(
+            br#"{"filename": "/data/app.db", "changes": [
+                {"operation": "INSERT", "table": "users", "columns": ["id", "name"],
+                 "new_rowid": 7, "new_values": [7, "alice"]},
+                {"operation": "UPDATE", "table": "users", "columns": ["name"],
+                 "old_rowid": 7, "new_rowid": 8, "new_values": [null]},
+                {"operation": "DELETE", "table": "users", "old_rowid": 8},
+                {"operation": "SQL", "command": "SELECT ?", "args": [1.5, true]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(changes.file_name(), "app.db");
+        assert_eq!(
+            changes.changes,
+            [
+                Change::Insert {
+                    table: "users".into(),
+                    columns: vec!["id".into(), "name".into()],
+                    rowid: 7,
+                    values: vec![SqliteValue::Integer(7), SqliteValue::Text("alice".into())],
+                },
+                Change::Update {
+                    table: "users".into(),
+                    columns: vec!["name".into()],
+                    old_rowid: 7,
+                    new_rowid: 8,
+                    values: vec![SqliteValue::Null],
+                },
+                Change::Delete {
+                    table: "users".into(),
+                    rowid: 8,
+                },
+                Change::Sql {
+                    command: "SELECT ?".into(),
+                    args: vec![SqliteValue::Real(1.5), SqliteValue::Integer(1)],
+                },
+            ]
+        );
+
+        let invalid: &[&[u8]] = &[
+            b"not json",
+            br#"{"changes": {}}"#,
+            br#"{"changes": [{"operation": "MERGE"}]}"#,
+            br#"{"changes": [{"operation": "INSERT", "table": "t", "columns": ["a"]}]}"#,
+            br#"{"changes": [{"operation": "INSERT", "table": "t", "columns": ["a"],
+                 "new_values": [1, 2]}]}"#,
+            br#"{"changes": [{"operation": "UPDATE", "table": "t", "columns": [1],
+                 "new_values": [1]}]}"#,
+        ];
+        for payload in invalid {
+            assert!(
+                ChangeSet::parse(payload).is_err(),
+                "{}",
+                String::from_utf8_lossy(payload)
+            );
+        }
+    }
+
+    #[test]
+    fn apply_row_changes() {
+        let mut conn = open();
+        apply(
+            &mut conn,
+            r#"{"changes": [
+                {"operation": "INSERT", "table": "users", "columns": ["id", "name"],
+                 "new_rowid": 1, "new_values": [1, "alice"]},
+                {"operation": "INSERT", "table": "users", "columns": ["id", "name"],
+                 "new_rowid": 2, "new_values": [2, "bob"]}
+            ]}"#,
+            5,
+        )
+        .unwrap();
+        assert_eq!(names(&conn), [(1, "alice".into()), (2, "bob".into())]);
+
+        apply(
+            &mut conn,
+            r#"{"changes": [
+                {"operation": "UPDATE", "table": "users", "columns": ["id", "name"],
+                 "old_rowid": 1, "new_rowid": 3, "new_values": [3, "carol"]},
+                {"operation": "DELETE", "table": "users", "old_rowid": 2}
+            ]}"#,
+            6,
+        )
+        .unwrap();
+        assert_eq!(names(&conn), [(3, "carol".into())]);
+    }
+
+    #[test]
+    fn apply_records_the_position() {
+        let mut conn = open();
+        apply(&mut conn, r#"{"changes": []}"#, 5).unwrap();
+        apply(&mut conn, r#"{"changes": []}"#, 6).unwrap();
+
+        let seq: i64 = conn
+            .query_row(
+                &format!("SELECT stream_seq FROM {}", REPLICATION_TABLE),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(seq, 6);
+        // The row of ha_stats is updated in place
+        let stats: Vec<i64> = conn
+            .prepare("SELECT received_seq FROM ha_stats")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(stats, [6]);
+
+        // A replica without ha_stats is not given one
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply(&mut conn, r#"{"changes": []}"#, 1).unwrap();
+        let tables: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name = 'ha_stats'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[test]
+    fn apply_sql() {
+        let mut conn = open();
+        apply(
+            &mut conn,
+            r#"{"changes": [
+                {"operation": "SQL", "command": "ALTER TABLE users ADD COLUMN email TEXT"},
+                {"operation": "SQL", "command": "INSERT INTO users (name, email) VALUES (?, ?)",
+                 "args": ["alice", "alice@example.com"]}
+            ]}"#,
+            5,
+        )
+        .unwrap();
+        let email: String = conn
+            .query_row("SELECT email FROM users WHERE name = 'alice'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(email, "alice@example.com");
+    }
+
+    #[test]
+    fn apply_rejects_changes_it_cannot_address() {
+        let mut conn = open();
+        conn.execute_batch("CREATE TABLE tags (name TEXT PRIMARY KEY) WITHOUT ROWID")
+            .unwrap();
+        let rejected = [
+            r#"{"changes": [
+                {"operation": "INSERT", "table": "users", "columns": ["id", "name"],
+                 "new_rowid": 1, "new_values": [1, "alice"]},
+                {"operation": "INSERT", "table": "tags", "columns": ["name"],
+                 "new_rowid": 1, "new_values": ["red"]}
+            ]}"#,
+            r#"{"changes": [{"operation": "DELETE", "table": "TAGS", "old_rowid": 1}]}"#,
+            r#"{"changes": [{"operation": "INSERT", "table": "users", "columns": [],
+                 "new_rowid": 1, "new_values": []}]}"#,
+            r#"{"changes": [{"operation": "UPDATE", "table": "users", "columns": [],
+                 "old_rowid": 1, "new_rowid": 1, "new_values": []}]}"#,
+        ];
+        for json in rejected {
+            assert!(apply(&mut conn, json, 5).is_err(), "{}", json);
+        }
+        let e = apply(&mut conn, rejected[1], 5).unwrap_err();
+        assert!(e.to_string().contains("WITHOUT ROWID"), "{}", e);
+
+        // Nothing of a rejected change set is applied
+        assert_eq!(names(&conn), []);
+        let seq: i64 = conn
+            .query_row("SELECT received_seq FROM ha_stats", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(seq, 4);
+    }
+
+    #[test]
+    fn changed_tables() {
+        let conn = open();
+        conn.execute_batch(
+            "CREATE VIEW named AS SELECT * FROM users WHERE name IS NOT NULL;
+             CREATE VIEW counted AS SELECT count(*) FROM named;
+             CREATE TABLE posts (id INTEGER PRIMARY KEY);",
+        )
+        .unwrap();
+        let changes = ChangeSet::parse(
+            br#"{"changes": [{"operation": "DELETE", "table": "Users", "old_rowid": 1}]}"#,
+        )
+        .unwrap();
+        let tables = changes.changed_tables(&conn).unwrap().unwrap();
+        let expected: HashSet<String> = ["users", "named", "counted"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(tables, expected);
+
+        let changes =
+            ChangeSet::parse(br#"{"changes": [{"operation": "SQL", "command": "VACUUM"}]}"#)
+                .unwrap();
+        assert_eq!(changes.changed_tables(&conn).unwrap(), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Read routing decisions and aggregated routing counters.
+//!
+//! Every read issued through an [`HAConnection`](crate::HAConnection) is
+//! answered from the query cache, routed to a local embedded replica or sent
+//! to the HA server, possibly sharing an identical read in flight or hedged
+//! with a second read. The [`RoutingDecision`] records where it went and
+//! why; it is attached to the returned
+//! [`ExecutionResult`](crate::client::ExecutionResult), passed to the audit
+//! hook, and counted in [`RoutingStats`].
+//!
+//! How stale a replica a read accepts is set by its [`Consistency`], per
+//! connection and per query.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How up to date the data a read sees must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// Always read from the HA server
+    Strong,
+    /// Read from the embedded replica when it has applied every transaction
+    /// this connection has seen, or has been missing transactions for less
+    /// than the given time
+    ///
+    /// The connection's `max_replica_lag` and `max_replica_lag_txseq` widen
+    /// the bound. With neither set, the default,
+    /// `BoundedStaleness(Duration::ZERO)`, only reads a replica that has
+    /// caught up with this connection's writes.
+    BoundedStaleness(Duration),
+    /// Read from the embedded replica whenever one is loaded, however far
+    /// behind it is
+    Eventual,
+}
+
+impl Default for Consistency {
+    fn default() -> Self {
+        Consistency::BoundedStaleness(Duration::ZERO)
+    }
+}
+
+/// Where a statement was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Sent to the HA server
+    Primary,
+    /// Served from a local embedded replica
+    Replica,
+    /// Served from the client-side query cache
+    Cache,
+}
+
+/// Why a statement was routed where it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteReason {
+    /// The replica is at or past the last seen txseq
+    ReplicaFresh,
+    /// The replica is behind the last seen txseq
+    ReplicaStale {
+        /// Number of transactions the replica is behind
+        behind: i64,
+    },
+    /// The replica is behind the last seen txseq, but within what the
+    /// read's consistency allows
+    ReplicaWithinBound {
+        /// Number of transactions the replica is behind
+        behind: i64,
+    },
+    /// The read asked for strong consistency
+    Strong,
+    /// The replication feed is down, so the replica may be missing
+    /// transactions
+    ReplicationDown,
+    /// A transaction is open on this connection
+    InTransaction,
+    /// The statement was classified as a write
+    Write,
+    /// No embedded replica is loaded for the current catalog
+    NoReplica,
+    /// A cached result is at or past the last seen txseq
+    CacheHit,
+    /// Shared the result of an identical read already sent to the HA server
+    Coalesced,
+    /// Streamed reads always go to the HA server
+    Streamed,
+    /// A hedged read sent to the HA server answered first
+    Hedged,
+}
+
+impl fmt::Display for RouteReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteReason::ReplicaFresh => write!(f, "replica fresh"),
+            RouteReason::ReplicaStale { behind } => write!(f, "replica stale by {}", behind),
+            RouteReason::ReplicaWithinBound { behind } => {
+                write!(f, "replica within bound, stale by {}", behind)
+            }
+            RouteReason::Strong => write!(f, "strong consistency"),
+            RouteReason::ReplicationDown => write!(f, "replication down"),
+            RouteReason::InTransaction => write!(f, "in transaction"),
+            RouteReason::Write => write!(f, "write"),
+            RouteReason::NoReplica => write!(f, "no replica loaded"),
+            RouteReason::CacheHit => write!(f, "cache hit"),
+            RouteReason::Coalesced => write!(f, "coalesced"),
+            RouteReason::Streamed => write!(f, "streamed"),
+            RouteReason::Hedged => write!(f, "hedged"),
+        }
+    }
+}
+
+/// Where a statement was routed and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutingDecision {
+    /// Where the statement was executed
+    pub route: Route,
+    /// Why it was executed there
+    pub reason: RouteReason,
+}
+
+impl RoutingDecision {
+    /// Route to the HA server for the given reason.
+    pub fn primary(reason: RouteReason) -> Self {
+        Self {
+            route: Route::Primary,
+            reason,
+        }
+    }
+
+    /// Route to a fresh local replica.
+    pub fn replica() -> Self {
+        Self {
+            route: Route::Replica,
+            reason: RouteReason::ReplicaFresh,
+        }
+    }
+
+    /// Route to a local replica that is `behind` the last seen txseq.
+    pub fn stale_replica(behind: i64) -> Self {
+        Self {
+            route: Route::Replica,
+            reason: RouteReason::ReplicaWithinBound { behind },
+        }
+    }
+
+    /// Answer from the query cache.
+    pub fn cache() -> Self {
+        Self {
+            route: Route::Cache,
+            reason: RouteReason::CacheHit,
+        }
+    }
+}
+
+/// Aggregated routing counters, shared by all connections of a data source.
+#[derive(Debug, Default)]
+pub struct RoutingStats {
+    replica_fresh: AtomicU64,
+    replica_stale: AtomicU64,
+    replica_within_bound: AtomicU64,
+    strong: AtomicU64,
+    replication_down: AtomicU64,
+    in_transaction: AtomicU64,
+    write: AtomicU64,
+    no_replica: AtomicU64,
+    cache_hit: AtomicU64,
+    coalesced: AtomicU64,
+    streamed: AtomicU64,
+    hedged: AtomicU64,
+}
+
+/// Point-in-time copy of [`RoutingStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoutingCounts {
+    /// Reads served by a fresh replica
+    pub replica_fresh: u64,
+    /// Reads sent to the server because the replica was stale
+    pub replica_stale: u64,
+    /// Reads served by a stale replica within the read's consistency bound
+    pub replica_within_bound: u64,
+    /// Reads sent to the server because they asked for strong consistency
+    pub strong: u64,
+    /// Reads sent to the server because the replication feed was down
+    pub replication_down: u64,
+    /// Reads sent to the server because a transaction was open
+    pub in_transaction: u64,
+    /// Statements sent to the server because they were classified as writes
+    pub write: u64,
+    /// Reads sent to the server because no replica was loaded
+    pub no_replica: u64,
+    /// Reads answered from the query cache
+    pub cache_hit: u64,
+    /// Reads that shared the result of an identical read in flight
+    pub coalesced: u64,
+    /// Reads streamed from the server
+    pub streamed: u64,
+    /// Reads answered by a hedged read sent to the server
+    pub hedged: u64,
+}
+
+impl RoutingStats {
+    /// Create zeroed counters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a routing decision.
+    pub fn record(&self, decision: &RoutingDecision) {
+        let counter = match decision.reason {
+            RouteReason::ReplicaFresh => &self.replica_fresh,
+            RouteReason::ReplicaStale { .. } => &self.replica_stale,
+            RouteReason::ReplicaWithinBound { .. } => &self.replica_within_bound,
+            RouteReason::Strong => &self.strong,
+            RouteReason::ReplicationDown => &self.replication_down,
+            RouteReason::InTransaction => &self.in_transaction,
+            RouteReason::Write => &self.write,
+            RouteReason::NoReplica => &self.no_replica,
+            RouteReason::CacheHit => &self.cache_hit,
+            RouteReason::Coalesced => &self.coalesced,
+            RouteReason::Streamed => &self.streamed,
+            RouteReason::Hedged => &self.hedged,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the counters.
+    pub fn snapshot(&self) -> RoutingCounts {
+        RoutingCounts {
+            replica_fresh: self.replica_fresh.load(Ordering::Relaxed),
+            replica_stale: self.replica_stale.load(Ordering::Relaxed),
+            replica_within_bound: self.replica_within_bound.load(Ordering::Relaxed),
+            strong: self.strong.load(Ordering::Relaxed),
+            replication_down: self.replication_down.load(Ordering::Relaxed),
+            in_transaction: self.in_transaction.load(Ordering::Relaxed),
+            write: self.write.load(Ordering::Relaxed),
+            no_replica: self.no_replica.load(Ordering::Relaxed),
+            cache_hit: self.cache_hit.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            streamed: self.streamed.load(Ordering::Relaxed),
+            hedged: self.hedged.load(Ordering::Relaxed),
+        }
+    }
+}
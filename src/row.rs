@@ -0,0 +1,585 @@
+//! Mapping result rows to and from Rust types.
+//!
+//! [`FromRow`] builds a value from a [`Row`] by column name and [`ToParams`]
+//! turns a value into statement parameters. With the `derive` feature both
+//! can be derived for structs with named fields; a field maps to the column
+//! of the same name unless it carries `#[litesql(rename = "column")]`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "derive")]
+//! # mod example {
+//! use litesql_ha::row::{FromRow, ToParams};
+//! use litesql_ha::HAConnection;
+//!
+//! #[derive(Debug, FromRow, ToParams)]
+//! struct User {
+//!     id: i64,
+//!     #[litesql(rename = "full_name")]
+//!     name: Option<String>,
+//! }
+//!
+//! async fn example(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let user = User { id: 1, name: None };
+//!     conn.execute("INSERT INTO users (id, full_name) VALUES (?, ?)", &user.to_params())
+//!         .await?;
+//!     let users: Vec<User> = conn.query_as("SELECT * FROM users", &[]).await?;
+//!     Ok(())
+//! }
+//! # }
+//! ```
+
+use crate::datetime::{from_unix, parse_iso8601};
+use crate::error::{Error, Result};
+use crate::value::Value;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[cfg(feature = "derive")]
+pub use litesql_ha_macros::{FromRow, ToParams};
+
+/// Conversion from a result value.
+///
+/// Numbers convert between types only when no information is lost: an
+/// `Int64` becomes an `i32` if it is in range, a `Double` becomes an `i64`
+/// if it is a whole number and an `f32` if it is exact as one. Types implementing this also implement
+/// `TryFrom<Value>` and `TryFrom<&Value>`, as does `Option` of them.
+pub trait FromValue: Sized {
+    /// Convert a value, failing if it has an incompatible type.
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+/// Implement `TryFrom<Value>` and `TryFrom<&Value>` for types and `Option`s
+/// of them through [`FromValue`].
+///
+/// `Option<T>` can't be covered generically, since `Option<Value>` already
+/// converts from `Value` through `From`.
+macro_rules! try_from_value {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl TryFrom<Value> for $ty {
+                type Error = Error;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    <$ty as FromValue>::from_value(&value)
+                }
+            }
+
+            impl TryFrom<&Value> for $ty {
+                type Error = Error;
+
+                fn try_from(value: &Value) -> Result<Self> {
+                    <$ty as FromValue>::from_value(value)
+                }
+            }
+
+            impl TryFrom<Value> for Option<$ty> {
+                type Error = Error;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    <Option<$ty> as FromValue>::from_value(&value)
+                }
+            }
+
+            impl TryFrom<&Value> for Option<$ty> {
+                type Error = Error;
+
+                fn try_from(value: &Value) -> Result<Self> {
+                    <Option<$ty> as FromValue>::from_value(value)
+                }
+            }
+        )*
+    };
+}
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub(crate) use try_from_value;
+
+/// Construction from a result row.
+pub trait FromRow: Sized {
+    /// Build a value from the columns of a row.
+    fn from_row(row: &Row<'_>) -> Result<Self>;
+}
+
+/// Conversion into statement parameters.
+pub trait ToParams {
+    /// Get the parameters, in field order.
+    fn to_params(&self) -> Vec<Value>;
+}
+
+/// A borrowed result row with its column names.
+#[derive(Debug, Clone, Copy)]
+pub struct Row<'a> {
+    columns: &'a [String],
+    values: &'a [Value],
+}
+
+impl<'a> Row<'a> {
+    /// Create a row from column names and values.
+    pub fn new(columns: &'a [String], values: &'a [Value]) -> Self {
+        Self { columns, values }
+    }
+
+    /// Get the value of a column by name.
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T> {
+        self.try_get(column)?
+            .ok_or_else(|| Error::TypeConversion(format!("no column named {}", column)))
+    }
+
+    /// Get the value of a column by name, or `None` if there is no such
+    /// column.
+    pub fn try_get<T: FromValue>(&self, column: &str) -> Result<Option<T>> {
+        let Some(index) = self.columns.iter().position(|c| c == column) else {
+            return Ok(None);
+        };
+        self.get_index(index).map(Some).map_err(|e| match e {
+            Error::TypeConversion(message) => {
+                Error::TypeConversion(format!("column {}: {}", column, message))
+            }
+            e => e,
+        })
+    }
+
+    /// Get the value of a column by position.
+    pub fn get_index<T: FromValue>(&self, index: usize) -> Result<T> {
+        let value = self
+            .values
+            .get(index)
+            .ok_or_else(|| Error::TypeConversion(format!("column index {} out of range", index)))?;
+        T::from_value(value)
+    }
+
+    /// Get the value of a column by position, or `None` if it is out of
+    /// range.
+    pub fn try_get_index<T: FromValue>(&self, index: usize) -> Result<Option<T>> {
+        self.values.get(index).map(T::from_value).transpose()
+    }
+
+    /// Map the row to `T`.
+    pub fn to<T: FromRow>(&self) -> Result<T> {
+        T::from_row(self)
+    }
+
+    /// Get the column names.
+    pub fn columns(&self) -> &'a [String] {
+        self.columns
+    }
+
+    /// Get the values.
+    pub fn values(&self) -> &'a [Value] {
+        self.values
+    }
+}
+
+/// An owned result row, as yielded by a [`RowStream`](crate::client::RowStream).
+///
+/// Rows of the same result share their column names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedRow {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl OwnedRow {
+    /// Create a row from shared column names and values.
+    pub fn new(columns: Arc<[String]>, values: Vec<Value>) -> Self {
+        Self { columns, values }
+    }
+
+    /// Borrow the row.
+    pub fn as_row(&self) -> Row<'_> {
+        Row::new(&self.columns, &self.values)
+    }
+
+    /// Get the value of a column by name.
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T> {
+        self.as_row().get(column)
+    }
+
+    /// Get the value of a column by name, or `None` if there is no such
+    /// column.
+    pub fn try_get<T: FromValue>(&self, column: &str) -> Result<Option<T>> {
+        self.as_row().try_get(column)
+    }
+
+    /// Get the value of a column by position.
+    pub fn get_index<T: FromValue>(&self, index: usize) -> Result<T> {
+        self.as_row().get_index(index)
+    }
+
+    /// Get the value of a column by position, or `None` if it is out of
+    /// range.
+    pub fn try_get_index<T: FromValue>(&self, index: usize) -> Result<Option<T>> {
+        self.as_row().try_get_index(index)
+    }
+
+    /// Map the row to `T`.
+    pub fn to<T: FromRow>(&self) -> Result<T> {
+        T::from_row(&self.as_row())
+    }
+
+    /// Get the column names.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Get the values.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Take the values.
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+}
+
+/// A column name tagged with the Rust type of its values.
+pub struct Column<T> {
+    name: &'static str,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Column<T> {
+    /// Create a column.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _type: PhantomData,
+        }
+    }
+
+    /// Get the column name.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T: FromValue> Column<T> {
+    /// Get the value of this column from a row.
+    pub fn get(&self, row: &Row<'_>) -> Result<T> {
+        row.get(self.name)
+    }
+}
+
+impl<T> Clone for Column<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Column<T> {}
+
+impl<T> fmt::Debug for Column<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Column").field(&self.name).finish()
+    }
+}
+
+impl<T> fmt::Display for Column<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+fn mismatch<T>(expected: &str, value: &Value) -> Result<T> {
+    Err(Error::TypeConversion(format!(
+        "expected {}, got {:?}",
+        expected, value
+    )))
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int64(v) => Ok(*v),
+            Value::Int32(v) => Ok(*v as i64),
+            Value::Bool(v) => Ok(*v as i64),
+            Value::Double(v) => whole_number(*v),
+            Value::Float(v) => whole_number(*v as f64),
+            other => mismatch("integer", other),
+        }
+    }
+}
+
+/// Convert a float holding a whole number in range to an `i64`.
+fn whole_number(v: f64) -> Result<i64> {
+    // 2^63 is exact as a float, unlike `i64::MAX`.
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if v.fract() == 0.0 && (-LIMIT..LIMIT).contains(&v) {
+        Ok(v as i64)
+    } else {
+        Err(Error::TypeConversion(format!("{} is not an i64", v)))
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        let v = i64::from_value(value)?;
+        i32::try_from(v).map_err(|_| Error::TypeConversion(format!("{} out of range for i32", v)))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(v) => Ok(*v),
+            Value::Int64(v) => Ok(*v != 0),
+            Value::Int32(v) => Ok(*v != 0),
+            other => mismatch("boolean", other),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Double(v) => Ok(*v),
+            Value::Float(v) => Ok(*v as f64),
+            Value::Int64(v) => {
+                let f = *v as f64;
+                // Integers past 2^53 may not survive the conversion.
+                if f as i128 == *v as i128 {
+                    Ok(f)
+                } else {
+                    Err(Error::TypeConversion(format!(
+                        "{} is not exact as an f64",
+                        v
+                    )))
+                }
+            }
+            Value::Int32(v) => Ok(*v as f64),
+            other => mismatch("number", other),
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Float(v) => Ok(*v),
+            other => {
+                let v = f64::from_value(other)?;
+                let f = v as f32;
+                // Out of range values become infinite, others lose precision.
+                if f as f64 == v || v.is_nan() {
+                    Ok(f)
+                } else {
+                    Err(Error::TypeConversion(format!(
+                        "{} is not exact as an f32",
+                        v
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(v) => Ok(v.clone()),
+            other => mismatch("text", other),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl FromValue for serde_json::Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Json(v) => Ok(v.clone()),
+            Value::String(v) => serde_json::from_str(v)
+                .map_err(|e| Error::TypeConversion(format!("invalid JSON: {}", e))),
+            Value::Null => Ok(serde_json::Value::Null),
+            Value::Bool(v) => Ok((*v).into()),
+            Value::Int32(v) => Ok((*v).into()),
+            Value::Int64(v) => Ok((*v).into()),
+            Value::Double(v) => Ok((*v).into()),
+            Value::Float(v) => Ok((*v).into()),
+            other => mismatch("JSON", other),
+        }
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl FromValue for rust_decimal::Decimal {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Decimal(v) => Ok(*v),
+            Value::String(v) => rust_decimal::Decimal::from_str_exact(v.trim())
+                .map_err(|e| Error::TypeConversion(format!("invalid decimal {:?}: {}", v, e))),
+            Value::Int32(v) => Ok((*v).into()),
+            Value::Int64(v) => Ok((*v).into()),
+            // Replicas return REAL for numeric columns holding fractions.
+            Value::Double(v) => rust_decimal::Decimal::try_from(*v)
+                .map_err(|e| Error::TypeConversion(format!("invalid decimal {}: {}", v, e))),
+            other => mismatch("decimal", other),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bytes(v) => Ok(v.clone()),
+            Value::String(v) => Ok(v.clone().into_bytes()),
+            other => mismatch("blob", other),
+        }
+    }
+}
+
+impl FromValue for SystemTime {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Timestamp(v) => Ok(*v),
+            // Seconds since the Unix epoch, as `TimestampStorage::UnixEpoch`
+            // stores them.
+            Value::Int32(_) | Value::Int64(_) => {
+                let seconds = i64::from_value(value)?;
+                from_unix(seconds, 0).ok_or_else(|| {
+                    Error::TypeConversion(format!("timestamp out of range: {}", seconds))
+                })
+            }
+            Value::String(v) => parse_iso8601(v).ok_or_else(|| {
+                Error::TypeConversion(format!("invalid ISO-8601 timestamp: {:?}", v))
+            }),
+            other => mismatch("timestamp", other),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+try_from_value!(i64, i32, bool, f64, f32, String, Vec<u8>, SystemTime);
+#[cfg(feature = "json")]
+try_from_value!(serde_json::Value);
+#[cfg(feature = "decimal")]
+try_from_value!(rust_decimal::Decimal);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(i64::try_from(Value::Int64(i64::MIN)).unwrap(), i64::MIN);
+        assert_eq!(i64::try_from(Value::Int64(i64::MAX)).unwrap(), i64::MAX);
+        assert_eq!(
+            i64::try_from(Value::Int32(i32::MIN)).unwrap(),
+            -2_147_483_648
+        );
+        assert_eq!(i64::try_from(Value::Bool(true)).unwrap(), 1);
+
+        let i32_cases = [
+            (i32::MIN as i64 - 1, None),
+            (i32::MIN as i64, Some(i32::MIN)),
+            (-1, Some(-1)),
+            (i32::MAX as i64, Some(i32::MAX)),
+            (i32::MAX as i64 + 1, None),
+            (i64::MIN, None),
+            (i64::MAX, None),
+        ];
+        for (v, expected) in i32_cases {
+            assert_eq!(i32::try_from(Value::Int64(v)).ok(), expected, "{}", v);
+        }
+        assert!(i64::try_from(Value::String("1".into())).is_err());
+        assert!(i32::try_from(Value::Null).is_err());
+        assert_eq!(Option::<i32>::try_from(Value::Null).unwrap(), None);
+        assert!(Option::<i32>::try_from(Value::Int64(1 << 40)).is_err());
+    }
+
+    #[test]
+    fn floats_to_integers() {
+        // 2^63, the first float past i64::MAX
+        const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+        let cases = [
+            (0.0, Some(0)),
+            (-0.0, Some(0)),
+            (42.0, Some(42)),
+            (-LIMIT, Some(i64::MIN)),
+            (LIMIT, None),
+            (-LIMIT * 2.0, None),
+            (0.5, None),
+            (-1.5, None),
+            (f64::NAN, None),
+            (f64::INFINITY, None),
+            (f64::NEG_INFINITY, None),
+        ];
+        for (v, expected) in cases {
+            assert_eq!(i64::try_from(Value::Double(v)).ok(), expected, "{}", v);
+        }
+        assert_eq!(i64::try_from(Value::Float(3.0)).unwrap(), 3);
+        assert!(i64::try_from(Value::Float(f32::NAN)).is_err());
+        assert!(i32::try_from(Value::Double(2_147_483_648.0)).is_err());
+        assert_eq!(
+            i32::try_from(Value::Double(-2_147_483_648.0)).unwrap(),
+            i32::MIN
+        );
+    }
+
+    #[test]
+    fn integers_to_floats() {
+        const EXACT: i64 = 1 << 53;
+        let cases = [
+            (EXACT, Some(EXACT as f64)),
+            (-EXACT, Some(-EXACT as f64)),
+            (EXACT + 1, None),
+            (-EXACT - 1, None),
+            (i64::MIN, Some(-9_223_372_036_854_775_808.0)),
+            (i64::MAX, None),
+        ];
+        for (v, expected) in cases {
+            assert_eq!(f64::try_from(Value::Int64(v)).ok(), expected, "{}", v);
+        }
+        assert_eq!(
+            f64::try_from(Value::Int32(i32::MAX)).unwrap(),
+            2_147_483_647.0
+        );
+        assert_eq!(f32::try_from(Value::Int64(1 << 24)).unwrap(), 16_777_216.0);
+        assert!(f32::try_from(Value::Int64((1 << 24) + 1)).is_err());
+        assert!(f64::try_from(Value::Bool(true)).is_err());
+    }
+
+    #[test]
+    fn doubles_to_floats() {
+        let cases = [
+            (0.5, Some(0.5)),
+            (f32::MAX as f64, Some(f32::MAX)),
+            (f32::MIN as f64, Some(f32::MIN)),
+            (f32::MAX as f64 * 2.0, None),
+            (f32::MIN as f64 * 2.0, None),
+            (0.1, None),
+            (f64::INFINITY, Some(f32::INFINITY)),
+        ];
+        for (v, expected) in cases {
+            assert_eq!(f32::try_from(Value::Double(v)).ok(), expected, "{}", v);
+        }
+        assert!(f32::try_from(Value::Double(f64::NAN)).unwrap().is_nan());
+        assert!(f64::try_from(Value::Float(f32::NAN)).unwrap().is_nan());
+        assert_eq!(f64::try_from(Value::Float(0.1)).unwrap(), 0.1f32 as f64);
+    }
+
+    #[test]
+    fn booleans() {
+        assert!(bool::try_from(Value::Int64(-1)).unwrap());
+        assert!(!bool::try_from(Value::Int32(0)).unwrap());
+        assert!(bool::try_from(Value::Double(1.0)).is_err());
+        assert!(bool::try_from(&Value::Bool(true)).unwrap());
+    }
+}
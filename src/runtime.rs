@@ -0,0 +1,307 @@
+//! Async runtime integration.
+//!
+//! All direct use of the tokio runtime (spawning, timers, filesystem and
+//! socket access, I/O traits, task-locals, `select!` and the runtime owned by
+//! the blocking wrappers) goes through this module so that the rest of the
+//! crate is runtime-agnostic apart from the tonic transport. Only the
+//! `tokio::sync` primitives and `tokio-stream` adapters, which run on any
+//! executor, are used directly. The gRPC channel itself needs a tokio
+//! reactor; applications on async-std or smol should run the client inside a
+//! tokio compatibility layer such as `async-compat`.
+//!
+//! On `wasm32-unknown-unknown`, enabled by the `wasm` feature, there is no
+//! tokio reactor: tasks run on the JavaScript event loop, timers and
+//! [`Instant`] come from `wasmtimer`, and calls go over the gRPC-web channel
+//! of the [`grpc_web`](crate::grpc_web) module. Embedded replicas, replica
+//! downloads and DNS discovery need a filesystem or sockets and are not
+//! available there; deadlines are `wasmtimer::std::Instant`s.
+//!
+//! Every long-lived task spawned by this crate goes through [`spawn_named`] so
+//! it shows up under a stable name in tokio-console. Names are only attached
+//! when the `tokio-console` feature is enabled and the crate is built with
+//! `RUSTFLAGS="--cfg tokio_unstable"`; otherwise tasks are spawned anonymously.
+//!
+//! Background tasks and their lifecycles:
+//!
+//! - `litesql-ha::txseq-updater` — started by [`EmbeddedReplicasManager::load`]
+//!   and runs until [`EmbeddedReplicasManager::close`], which signals it and
+//!   waits for it to exit, or until the manager is dropped. The replicator
+//!   and the other replica tasks below stop the same way.
+//! - `litesql-ha::replica-download` — started when a catalog without a
+//!   replica is read and replicas are downloaded on demand; it exits once
+//!   the replica is downloaded and attached, or the download fails, or is
+//!   cancelled by [`EmbeddedReplicasManager::close`].
+//! - `litesql-ha::replica-reprovision` — started when SQLite finds a replica
+//!   corrupt; it moves the replica aside and exits once a fresh snapshot is
+//!   downloaded and attached, or the download fails, or is cancelled by
+//!   [`EmbeddedReplicasManager::close`].
+//! - `litesql-ha::replica-maintenance` — started by
+//!   [`EmbeddedReplicasManager::start_maintenance`] when a maintenance
+//!   interval is set, and runs until [`EmbeddedReplicasManager::close`] or
+//!   until the manager is dropped.
+//! - `litesql-ha::replica-watcher` — started by
+//!   [`EmbeddedReplicasManager::watch_directory`] and runs until
+//!   [`EmbeddedReplicasManager::close`] or until the manager is dropped.
+//! - `litesql-ha::replica-takeover` — started by
+//!   [`EmbeddedReplicasManager::start_takeover`] in a process following a
+//!   shared replicas directory, and runs until it takes the directory over,
+//!   until [`EmbeddedReplicasManager::close`] or until the manager is
+//!   dropped.
+//! - `litesql-ha::rollback` — started when a
+//!   [`Transaction`](crate::Transaction) is dropped without being committed or
+//!   rolled back; it sends `ROLLBACK` and is awaited by the next statement on
+//!   the connection.
+//! - `litesql-ha::hedge` — started for a read served by an embedded
+//!   replica when reads are hedged; it sends the read to the HA server after
+//!   the hedge delay and exits when the server answers, or is aborted when
+//!   the replica answers first.
+//! - `litesql-ha::discovery` — started by [`HAClient::new`] when the
+//!   servers are discovered from DNS; it resolves them again periodically
+//!   and exits once the client is dropped.
+//! - `litesql-ha::pipeline` — started by [`HAClient::pipeline`] to read the
+//!   responses of a pipelined `Query` stream; it exits when the
+//!   [`Pipeline`] is dropped and its pending queries have been answered.
+//! - `litesql-ha::mock-server` — the test server started by
+//!   [`MockServer::start`] and one task per open `Query` stream; they run
+//!   until [`MockServer::shutdown`] or until the server is dropped.
+//!
+//! [`EmbeddedReplicasManager::load`]: crate::EmbeddedReplicasManager::load
+//! [`EmbeddedReplicasManager::close`]: crate::EmbeddedReplicasManager::close
+//! [`EmbeddedReplicasManager::start_maintenance`]: crate::EmbeddedReplicasManager::start_maintenance
+//! [`EmbeddedReplicasManager::watch_directory`]: crate::EmbeddedReplicasManager::watch_directory
+//! [`HAClient::new`]: crate::HAClient::new
+//! [`HAClient::pipeline`]: crate::HAClient::pipeline
+//! [`Pipeline`]: crate::Pipeline
+//! [`MockServer::start`]: crate::test_util::MockServer::start
+//! [`MockServer::shutdown`]: crate::test_util::MockServer::shutdown
+
+use std::future::Future;
+#[cfg(target_arch = "wasm32")]
+use std::pin::Pin;
+#[cfg(target_arch = "wasm32")]
+use std::sync::Arc;
+#[cfg(target_arch = "wasm32")]
+use std::task::{Context, Poll};
+#[cfg(feature = "embedded-replicas")]
+use std::time::Duration;
+#[cfg(target_arch = "wasm32")]
+use tokio::sync::{oneshot, Notify};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "embedded-replicas")]
+pub(crate) use tokio::fs::{
+    copy, create_dir_all, read_to_string, remove_file, rename, try_exists, File, OpenOptions,
+};
+#[cfg(any(feature = "csv", feature = "websocket"))]
+pub(crate) use tokio::io::AsyncWrite;
+#[cfg(any(feature = "csv", feature = "embedded-replicas"))]
+pub(crate) use tokio::io::AsyncWriteExt;
+#[cfg(feature = "csv")]
+pub(crate) use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+#[cfg(feature = "websocket")]
+pub(crate) use tokio::io::{AsyncRead, ReadBuf};
+#[cfg(feature = "embedded-replicas")]
+pub(crate) use tokio::io::{AsyncReadExt, AsyncSeekExt};
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::net::lookup_host;
+#[cfg(feature = "test-util")]
+pub(crate) use tokio::net::TcpListener;
+#[cfg(feature = "websocket")]
+pub(crate) use tokio::net::TcpStream;
+#[cfg(feature = "blocking")]
+pub(crate) use tokio::runtime::Runtime;
+#[cfg(feature = "embedded-replicas")]
+pub(crate) use tokio::task::spawn_blocking;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::task::JoinHandle;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::time::{sleep, timeout};
+pub(crate) use tokio::{select, task_local};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasmtimer::std::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasmtimer::tokio::{sleep, timeout};
+
+/// Name of the embedded replicas txseq updater task.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const TXSEQ_UPDATER: &str = "litesql-ha::txseq-updater";
+
+/// Name of the task applying NATS replication messages to the replicas.
+#[cfg(feature = "nats")]
+pub(crate) const REPLICATOR: &str = "litesql-ha::replicator";
+
+/// Name of the task downloading a missing replica on demand.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const REPLICA_DOWNLOAD: &str = "litesql-ha::replica-download";
+
+/// Name of the task quarantining a corrupt replica and downloading it again.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const REPLICA_REPROVISION: &str = "litesql-ha::replica-reprovision";
+
+/// Name of the task attaching the files added to a watched replicas
+/// directory.
+#[cfg(feature = "watch")]
+pub(crate) const REPLICA_WATCHER: &str = "litesql-ha::replica-watcher";
+
+/// Name of the task compacting and evicting embedded replicas.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const REPLICA_MAINTENANCE: &str = "litesql-ha::replica-maintenance";
+
+/// Name of the task taking over a shared replicas directory.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) const REPLICA_TAKEOVER: &str = "litesql-ha::replica-takeover";
+
+/// Name of the task rolling back a dropped transaction.
+pub(crate) const ROLLBACK: &str = "litesql-ha::rollback";
+
+/// Name of the task sending a hedged replica read to the HA server.
+pub(crate) const HEDGE: &str = "litesql-ha::hedge";
+
+/// Name of the task resolving the servers again.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const DISCOVERY: &str = "litesql-ha::discovery";
+
+/// Name of the task reading the responses of a pipeline.
+pub(crate) const PIPELINE: &str = "litesql-ha::pipeline";
+
+/// Name of the mock server tasks.
+#[cfg(feature = "test-util")]
+pub(crate) const MOCK_SERVER: &str = "litesql-ha::mock-server";
+
+/// Spawn a background task with the given name.
+#[cfg(all(feature = "tokio-console", tokio_unstable, not(target_arch = "wasm32")))]
+pub(crate) fn spawn_named<F>(name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("failed to spawn background task")
+}
+
+/// Spawn a background task with the given name.
+#[cfg(not(any(all(feature = "tokio-console", tokio_unstable), target_arch = "wasm32")))]
+pub(crate) fn spawn_named<F>(_name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future)
+}
+
+/// Spawn a background task on the JavaScript event loop.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn_named<F>(_name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, output) = oneshot::channel();
+    let abort = Arc::new(Notify::new());
+    let aborted = abort.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        tokio::select! {
+            output = future => {
+                let _ = sender.send(output);
+            }
+            _ = aborted.notified() => {}
+        }
+    });
+    JoinHandle { output, abort }
+}
+
+/// Spawn a background task if called from within a runtime.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn try_spawn_named<F>(name: &'static str, future: F) -> Option<JoinHandle<F::Output>>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::runtime::Handle::try_current()
+        .ok()
+        .map(|_| spawn_named(name, future))
+}
+
+/// Spawn a background task; the JavaScript event loop is always running.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn try_spawn_named<F>(name: &'static str, future: F) -> Option<JoinHandle<F::Output>>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Some(spawn_named(name, future))
+}
+
+/// Handle of a task spawned on the JavaScript event loop.
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct JoinHandle<T> {
+    output: oneshot::Receiver<T>,
+    abort: Arc<Notify>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> JoinHandle<T> {
+    /// Stop the task at its next await point.
+    pub(crate) fn abort(&self) {
+        self.abort.notify_one();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.output).poll(cx).map_err(|_| JoinError)
+    }
+}
+
+/// Error of a task aborted before it finished.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+pub(crate) struct JoinError;
+
+#[cfg(target_arch = "wasm32")]
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("task was aborted")
+    }
+}
+
+/// A periodic timer whose first tick completes immediately.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) struct Interval(tokio::time::Interval);
+
+#[cfg(feature = "embedded-replicas")]
+impl Interval {
+    /// Create a timer that ticks every `period`.
+    pub(crate) fn new(period: Duration) -> Self {
+        Self(tokio::time::interval(period))
+    }
+
+    /// Wait for the next tick.
+    pub(crate) async fn tick(&mut self) {
+        self.0.tick().await;
+    }
+}
+
+/// Build the runtime driving the calls of the blocking wrappers, with one
+/// worker thread named `thread_name`.
+#[cfg(feature = "blocking")]
+pub(crate) fn new_runtime(thread_name: &str) -> std::io::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name(thread_name)
+        .enable_all()
+        .build()
+}
+
+/// Create a resolver for SRV records from the system configuration.
+#[cfg(feature = "dns-srv")]
+pub(crate) fn srv_resolver(
+) -> Result<hickory_resolver::TokioAsyncResolver, hickory_resolver::error::ResolveError> {
+    hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()
+}
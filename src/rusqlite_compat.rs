@@ -0,0 +1,788 @@
+//! rusqlite-style API for incremental migration.
+//!
+//! Enabled by the `rusqlite-compat` feature. [`Connection`] has the
+//! synchronous methods most rusqlite code calls, with rusqlite's shapes:
+//! `execute`, `execute_batch`, `query_row`, `prepare` and the
+//! [`Statement`] methods `query`, `query_map`, `query_row`, `exists` and
+//! `insert`, `transaction`, `last_insert_rowid` and `changes`. Parameters
+//! are given as with rusqlite: `()`, a tuple, an array, [`params!`],
+//! [`named_params!`] or [`params_from_iter`]. Calls go through the blocking
+//! client, so the usual routing applies.
+//!
+//! A module moves over by switching its `use rusqlite::...` lines to this
+//! module, with these differences:
+//!
+//! - [`Connection::open`] takes a server URL rather than a file path.
+//! - Errors are [`Error`]s, and closures given rows return this crate's
+//!   [`Result`]. `query_row` with no row fails with [`Error::RowCount`],
+//!   which [`OptionalExtension::optional`] turns into `None`.
+//! - Row values are read as [`FromValue`] types, and parameters are
+//!   [`ToSql`] values of this module.
+//! - Column names are known once a statement has returned a result.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::rusqlite_compat::{params, Connection, OptionalExtension};
+//!
+//! fn main() -> litesql_ha::Result<()> {
+//!     let mut conn = Connection::open("litesql://localhost:8080/app.db")?;
+//!
+//!     conn.execute("INSERT INTO users (name, age) VALUES (?1, ?2)", params!["ann", 30])?;
+//!     let id = conn.last_insert_rowid();
+//!
+//!     let name: Option<String> = conn
+//!         .query_row("SELECT name FROM users WHERE id = ?1", [id], |row| row.get(0))
+//!         .optional()?;
+//!
+//!     let tx = conn.transaction()?;
+//!     let mut stmt = tx.prepare("SELECT id, name FROM users WHERE age >= ?1")?;
+//!     let adults = stmt
+//!         .query_map((18,), |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>("name")?)))?
+//!         .collect::<litesql_ha::Result<Vec<_>>>()?;
+//!     drop(stmt);
+//!     tx.commit()?;
+//!     println!("{:?} {:?}", name, adults);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::blocking;
+use crate::client::{ExecuteResult, ExecutionResult};
+use crate::connection::HAConnectionOptions;
+use crate::error::{Error, Result};
+use crate::row::{FromValue, OwnedRow};
+use crate::script::split_statements;
+use crate::value::Value;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub use crate::transaction::TransactionBehavior;
+
+/// Query timeout in seconds of connections opened by URL, as for a data
+/// source.
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// Build positional parameters from values of different types, as
+/// rusqlite's `params!` does.
+#[macro_export]
+macro_rules! params {
+    () => {
+        &[] as &[&dyn $crate::rusqlite_compat::ToSql]
+    };
+    ($($param:expr),+ $(,)?) => {
+        &[$(&$param as &dyn $crate::rusqlite_compat::ToSql),+]
+            as &[&dyn $crate::rusqlite_compat::ToSql]
+    };
+}
+
+/// Build named parameters, as rusqlite's `named_params!` does.
+#[macro_export]
+macro_rules! named_params {
+    () => {
+        &[] as &[(&str, &dyn $crate::rusqlite_compat::ToSql)]
+    };
+    ($($name:literal: $param:expr),+ $(,)?) => {
+        &[$(($name, &$param as &dyn $crate::rusqlite_compat::ToSql)),+]
+            as &[(&str, &dyn $crate::rusqlite_compat::ToSql)]
+    };
+}
+
+pub use crate::{named_params, params};
+
+/// A value that can be bound to a statement parameter.
+pub trait ToSql {
+    /// Convert to a parameter value.
+    fn to_sql(&self) -> Result<Value>;
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn to_sql(&self) -> Result<Value> {
+        (**self).to_sql()
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql(&self) -> Result<Value> {
+        self.as_ref().map_or(Ok(Value::Null), ToSql::to_sql)
+    }
+}
+
+impl ToSql for str {
+    fn to_sql(&self) -> Result<Value> {
+        Ok(self.into())
+    }
+}
+
+impl ToSql for [u8] {
+    fn to_sql(&self) -> Result<Value> {
+        Ok(self.into())
+    }
+}
+
+macro_rules! to_sql_from {
+    ($($type:ty),+ $(,)?) => {
+        $(
+            impl ToSql for $type {
+                fn to_sql(&self) -> Result<Value> {
+                    Ok(self.clone().into())
+                }
+            }
+        )+
+    };
+}
+
+to_sql_from!(Value, bool, i32, i64, f32, f64, String, Vec<u8>, SystemTime);
+#[cfg(feature = "json")]
+to_sql_from!(serde_json::Value);
+#[cfg(feature = "decimal")]
+to_sql_from!(rust_decimal::Decimal);
+#[cfg(feature = "chrono")]
+to_sql_from!(
+    chrono::DateTime<chrono::Utc>,
+    chrono::NaiveDate,
+    chrono::NaiveTime
+);
+#[cfg(feature = "time")]
+to_sql_from!(time::OffsetDateTime, time::Date, time::Time);
+
+macro_rules! to_sql_widened {
+    ($($type:ty => $wide:ty),+ $(,)?) => {
+        $(
+            impl ToSql for $type {
+                fn to_sql(&self) -> Result<Value> {
+                    Ok(<$wide>::from(*self).into())
+                }
+            }
+        )+
+    };
+}
+
+to_sql_widened!(i8 => i32, i16 => i32, u8 => i32, u16 => i32, u32 => i64);
+
+macro_rules! to_sql_checked {
+    ($($type:ty),+ $(,)?) => {
+        $(
+            impl ToSql for $type {
+                fn to_sql(&self) -> Result<Value> {
+                    i64::try_from(*self).map(Value::from).map_err(|_| {
+                        Error::InvalidParameter(format!("{} does not fit in an INTEGER", self))
+                    })
+                }
+            }
+        )+
+    };
+}
+
+to_sql_checked!(isize, u64, usize);
+
+/// Values bound to a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bindings {
+    /// Values bound by position
+    Positional(Vec<Value>),
+    /// Values bound by parameter name, prefix included
+    Named(Vec<(String, Value)>),
+}
+
+/// The parameters of a statement.
+pub trait Params {
+    /// Convert to the values bound to the statement.
+    fn bind(self) -> Result<Bindings>;
+}
+
+impl Params for () {
+    fn bind(self) -> Result<Bindings> {
+        Ok(Bindings::Positional(Vec::new()))
+    }
+}
+
+impl<T: ToSql> Params for &[T] {
+    fn bind(self) -> Result<Bindings> {
+        self.iter()
+            .map(ToSql::to_sql)
+            .collect::<Result<_>>()
+            .map(Bindings::Positional)
+    }
+}
+
+// `[]` is the empty array of this impl, rather than one of every type.
+impl Params for [&dyn ToSql; 0] {
+    fn bind(self) -> Result<Bindings> {
+        Ok(Bindings::Positional(Vec::new()))
+    }
+}
+
+macro_rules! array_params {
+    ($($len:literal),+) => {
+        $(
+            impl<T: ToSql> Params for [T; $len] {
+                fn bind(self) -> Result<Bindings> {
+                    self.as_slice().bind()
+                }
+            }
+        )+
+    };
+}
+
+array_params!(
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26,
+    27, 28, 29, 30, 31, 32
+);
+
+impl<T: ToSql, const N: usize> Params for &[T; N] {
+    fn bind(self) -> Result<Bindings> {
+        self.as_slice().bind()
+    }
+}
+
+impl Params for &[(&str, &dyn ToSql)] {
+    fn bind(self) -> Result<Bindings> {
+        self.iter()
+            .map(|(name, value)| Ok((name.to_string(), value.to_sql()?)))
+            .collect::<Result<_>>()
+            .map(Bindings::Named)
+    }
+}
+
+macro_rules! tuple_params {
+    ($($name:ident),+) => {
+        impl<$($name: ToSql),+> Params for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn bind(self) -> Result<Bindings> {
+                let ($($name,)+) = self;
+                Ok(Bindings::Positional(vec![$($name.to_sql()?),+]))
+            }
+        }
+    };
+}
+
+tuple_params!(A);
+tuple_params!(A, B);
+tuple_params!(A, B, C);
+tuple_params!(A, B, C, D);
+tuple_params!(A, B, C, D, E);
+tuple_params!(A, B, C, D, E, F);
+tuple_params!(A, B, C, D, E, F, G);
+tuple_params!(A, B, C, D, E, F, G, H);
+tuple_params!(A, B, C, D, E, F, G, H, I);
+tuple_params!(A, B, C, D, E, F, G, H, I, J);
+tuple_params!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_params!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// Positional parameters taken from an iterator; see [`params_from_iter`].
+#[derive(Debug, Clone)]
+pub struct ParamsFromIter<I>(I);
+
+/// Bind the values of `iter` by position.
+pub fn params_from_iter<I>(iter: I) -> ParamsFromIter<I>
+where
+    I: IntoIterator,
+    I::Item: ToSql,
+{
+    ParamsFromIter(iter)
+}
+
+impl<I> Params for ParamsFromIter<I>
+where
+    I: IntoIterator,
+    I::Item: ToSql,
+{
+    fn bind(self) -> Result<Bindings> {
+        self.0
+            .into_iter()
+            .map(|value| value.to_sql())
+            .collect::<Result<_>>()
+            .map(Bindings::Positional)
+    }
+}
+
+/// Borrow named values as the connection takes them.
+fn named(values: &[(String, Value)]) -> Vec<(&str, Value)> {
+    values
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect()
+}
+
+/// Turns a missing row into `None`, as rusqlite's extension of the same
+/// name does.
+pub trait OptionalExtension<T> {
+    /// Get `None` instead of an [`Error::RowCount`] of 0.
+    fn optional(self) -> Result<Option<T>>;
+}
+
+impl<T> OptionalExtension<T> for Result<T> {
+    fn optional(self) -> Result<Option<T>> {
+        match self {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::RowCount(0)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Connection with rusqlite's methods.
+pub struct Connection {
+    inner: blocking::HAConnection,
+    last_insert_rowid: AtomicI64,
+    changes: AtomicU64,
+}
+
+impl Connection {
+    /// Open a connection to the server at `url`.
+    pub fn open(url: &str) -> Result<Self> {
+        Self::open_with(HAConnectionOptions {
+            url: url.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            ..Default::default()
+        })
+    }
+
+    /// Open a connection with `options`.
+    pub fn open_with(options: HAConnectionOptions) -> Result<Self> {
+        blocking::HAConnection::connect(options).map(Self::from_connection)
+    }
+
+    /// Wrap an existing blocking connection, e.g. one from a data source.
+    pub fn from_connection(inner: blocking::HAConnection) -> Self {
+        Self {
+            inner,
+            last_insert_rowid: AtomicI64::new(0),
+            changes: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the wrapped blocking connection.
+    pub fn inner(&self) -> &blocking::HAConnection {
+        &self.inner
+    }
+
+    /// Execute a statement, returning the number of rows changed.
+    pub fn execute<P: Params>(&self, sql: &str, params: P) -> Result<usize> {
+        let result = match params.bind()? {
+            Bindings::Positional(values) => self.inner.execute(sql, &values)?,
+            Bindings::Named(values) => self.inner.execute_named(sql, &named(&values))?,
+        };
+        Ok(self.record(&result))
+    }
+
+    /// Execute the statements of `sql`, separated by semicolons, without
+    /// parameters.
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        let statements = split_statements(sql);
+        let batch: Vec<(&str, &[Value])> = statements
+            .iter()
+            .map(|statement| (statement.as_str(), &[][..]))
+            .collect();
+        self.inner.execute_batch(&batch)?;
+        Ok(())
+    }
+
+    /// Execute a query and map its first row with `f`; fails with
+    /// [`Error::RowCount`] if it returns no row.
+    pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let result = match params.bind()? {
+            Bindings::Positional(values) => self.inner.query(sql, &values)?,
+            Bindings::Named(values) => self.inner.query_named(sql, &named(&values))?,
+        };
+        first_row(result, f)
+    }
+
+    /// Prepare a statement.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        Ok(Statement {
+            conn: self,
+            inner: self.inner.prepare(sql)?,
+            columns: Vec::new(),
+        })
+    }
+
+    /// Prepare a statement; statements are not cached, so this is the same
+    /// as [`prepare`](Self::prepare).
+    pub fn prepare_cached(&self, sql: &str) -> Result<Statement<'_>> {
+        self.prepare(sql)
+    }
+
+    /// Begin a deferred transaction.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>> {
+        Transaction::new(self, TransactionBehavior::Deferred)
+    }
+
+    /// Begin a transaction with `behavior`.
+    pub fn transaction_with_behavior(
+        &mut self,
+        behavior: TransactionBehavior,
+    ) -> Result<Transaction<'_>> {
+        Transaction::new(self, behavior)
+    }
+
+    /// Begin a deferred transaction without borrowing the connection
+    /// mutably; beginning another one fails while it is open.
+    pub fn unchecked_transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::new(self, TransactionBehavior::Deferred)
+    }
+
+    /// Get the rowid of the last row inserted by this connection.
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.last_insert_rowid.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of rows changed by the last statement executed.
+    pub fn changes(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+
+    /// Check if no transaction is open.
+    pub fn is_autocommit(&self) -> bool {
+        self.inner.auto_commit()
+    }
+
+    /// Close the connection, handing it back with the error if that fails.
+    // Mirrors `rusqlite::Connection::close`
+    #[allow(clippy::result_large_err)]
+    pub fn close(self) -> std::result::Result<(), (Connection, Error)> {
+        match self.inner.close() {
+            Ok(()) => Ok(()),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    /// Remember the outcome of a statement for `changes` and
+    /// `last_insert_rowid`.
+    fn record(&self, result: &ExecuteResult) -> usize {
+        let changes = u64::try_from(result.rows_affected).unwrap_or(0);
+        self.changes.store(changes, Ordering::Relaxed);
+        if changes > 0 {
+            self.last_insert_rowid
+                .store(result.last_insert_rowid, Ordering::Relaxed);
+        }
+        changes as usize
+    }
+}
+
+/// Map the first row of `result` with `f`.
+fn first_row<T, F>(result: ExecutionResult, f: F) -> Result<T>
+where
+    F: FnOnce(&Row<'_>) -> Result<T>,
+{
+    let mut rows = Rows::new(result);
+    match rows.next()? {
+        Some(row) => f(row),
+        None => Err(Error::RowCount(0)),
+    }
+}
+
+/// Prepared statement with rusqlite's methods.
+pub struct Statement<'conn> {
+    conn: &'conn Connection,
+    inner: blocking::Statement<'conn>,
+    /// Column names of the last result
+    columns: Vec<String>,
+}
+
+impl Statement<'_> {
+    /// Execute the statement, returning the number of rows changed.
+    pub fn execute<P: Params>(&mut self, params: P) -> Result<usize> {
+        let result = match params.bind()? {
+            Bindings::Positional(values) => self.inner.execute(&values)?,
+            Bindings::Named(values) => self.inner.execute_named(&named(&values))?,
+        };
+        Ok(self.conn.record(&result))
+    }
+
+    /// Execute an INSERT of one row, returning its rowid; fails with
+    /// [`Error::RowCount`] if it changed another number of rows.
+    pub fn insert<P: Params>(&mut self, params: P) -> Result<i64> {
+        match self.execute(params)? {
+            1 => Ok(self.conn.last_insert_rowid()),
+            changes => Err(Error::RowCount(changes)),
+        }
+    }
+
+    /// Execute the statement as a query.
+    pub fn query<P: Params>(&mut self, params: P) -> Result<Rows<'_>> {
+        let result = match params.bind()? {
+            Bindings::Positional(values) => self.inner.query(&values)?,
+            Bindings::Named(values) => self.inner.query_named(&named(&values))?,
+        };
+        self.columns.clone_from(&result.columns);
+        Ok(Rows::new(result))
+    }
+
+    /// Execute the statement as a query and map each row with `f`.
+    pub fn query_map<T, P, F>(&mut self, params: P, f: F) -> Result<MappedRows<'_, F>>
+    where
+        P: Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        Ok(self.query(params)?.mapped(f))
+    }
+
+    /// Execute the statement as a query and map its first row with `f`;
+    /// fails with [`Error::RowCount`] if it returns no row.
+    pub fn query_row<T, P, F>(&mut self, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut rows = self.query(params)?;
+        match rows.next()? {
+            Some(row) => f(row),
+            None => Err(Error::RowCount(0)),
+        }
+    }
+
+    /// Check if the statement returns at least one row.
+    pub fn exists<P: Params>(&mut self, params: P) -> Result<bool> {
+        Ok(self.query(params)?.next()?.is_some())
+    }
+
+    /// Get the number of parameters the statement expects.
+    pub fn parameter_count(&self) -> usize {
+        self.inner.parameter_count()
+    }
+
+    /// Get the column names, once the statement has returned a result.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(String::as_str).collect()
+    }
+
+    /// Get the number of columns, once the statement has returned a result.
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+}
+
+/// The rows of a query.
+pub struct Rows<'stmt> {
+    columns: Arc<[String]>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+    current: Option<Row<'stmt>>,
+}
+
+impl<'stmt> Rows<'stmt> {
+    fn new(result: ExecutionResult) -> Self {
+        Self {
+            columns: result.columns.into(),
+            rows: result.rows.into_iter(),
+            current: None,
+        }
+    }
+
+    /// Get the next row, or `None` after the last one.
+    // The row borrows from `self`, which `Iterator` cannot express.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<&Row<'stmt>>> {
+        self.current = self.rows.next().map(|values| Row {
+            inner: OwnedRow::new(self.columns.clone(), values),
+            _statement: PhantomData,
+        });
+        Ok(self.current.as_ref())
+    }
+
+    /// Map each row with `f`.
+    pub fn mapped<T, F>(self, f: F) -> MappedRows<'stmt, F>
+    where
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        MappedRows { rows: self, map: f }
+    }
+}
+
+/// The rows of a query, each mapped by a function.
+pub struct MappedRows<'stmt, F> {
+    rows: Rows<'stmt>,
+    map: F,
+}
+
+impl<T, F> Iterator for MappedRows<'_, F>
+where
+    F: FnMut(&Row<'_>) -> Result<T>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        match self.rows.next() {
+            Ok(Some(row)) => Some((self.map)(row)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A result row with rusqlite's accessors.
+#[derive(Debug)]
+pub struct Row<'stmt> {
+    inner: OwnedRow,
+    _statement: PhantomData<&'stmt ()>,
+}
+
+impl Row<'_> {
+    /// Get the value of a column by position or name.
+    pub fn get<I: RowIndex, T: FromValue>(&self, index: I) -> Result<T> {
+        let row = self.inner.as_row();
+        row.get_index(index.index(row.columns())?)
+    }
+
+    /// Get the value of a column by position or name; panics if it is
+    /// missing or of another type.
+    pub fn get_unwrap<I: RowIndex, T: FromValue>(&self, index: I) -> T {
+        self.get(index).expect("column of the requested type")
+    }
+
+    /// Borrow the row as this crate's [`Row`](crate::row::Row).
+    pub fn as_row(&self) -> crate::row::Row<'_> {
+        self.inner.as_row()
+    }
+}
+
+/// A column position or name.
+pub trait RowIndex {
+    /// Get the position of the column among `columns`.
+    fn index(&self, columns: &[String]) -> Result<usize>;
+}
+
+impl RowIndex for usize {
+    fn index(&self, columns: &[String]) -> Result<usize> {
+        if *self < columns.len() {
+            Ok(*self)
+        } else {
+            Err(Error::TypeConversion(format!(
+                "column index {} out of range",
+                self
+            )))
+        }
+    }
+}
+
+impl RowIndex for &str {
+    fn index(&self, columns: &[String]) -> Result<usize> {
+        columns
+            .iter()
+            .position(|column| column == self)
+            .ok_or_else(|| Error::TypeConversion(format!("no column named {}", self)))
+    }
+}
+
+/// Transaction that rolls back when dropped without being committed, with
+/// the methods of the connection.
+pub struct Transaction<'conn> {
+    conn: &'conn Connection,
+    finished: bool,
+}
+
+impl<'conn> Transaction<'conn> {
+    fn new(conn: &'conn Connection, behavior: TransactionBehavior) -> Result<Self> {
+        conn.inner.begin_transaction_with(behavior)?;
+        Ok(Self {
+            conn,
+            finished: false,
+        })
+    }
+
+    /// Commit the transaction.
+    pub fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        self.conn.inner.commit()
+    }
+
+    /// Roll back the transaction.
+    pub fn rollback(mut self) -> Result<()> {
+        self.finished = true;
+        self.conn.inner.rollback()
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.conn.inner.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positional(values: Vec<Value>) -> Bindings {
+        Bindings::Positional(values)
+    }
+
+    #[test]
+    fn bindings() {
+        let none: Option<&str> = None;
+        let cases = [
+            (().bind(), positional(vec![])),
+            ([].bind(), positional(vec![])),
+            (params![].bind(), positional(vec![])),
+            (
+                params!["a", 1, 2i64, 1.5, true, none, vec![1u8]].bind(),
+                positional(vec![
+                    Value::String("a".into()),
+                    Value::Int32(1),
+                    Value::Int64(2),
+                    Value::Double(1.5),
+                    Value::Bool(true),
+                    Value::Null,
+                    Value::Bytes(vec![1]),
+                ]),
+            ),
+            (
+                (7u8, 8u32, -1i8, 2.5f32).bind(),
+                positional(vec![
+                    Value::Int32(7),
+                    Value::Int64(8),
+                    Value::Int32(-1),
+                    Value::Float(2.5),
+                ]),
+            ),
+            (
+                [1usize, 2].bind(),
+                positional(vec![Value::Int64(1), Value::Int64(2)]),
+            ),
+            (
+                params_from_iter(vec!["x", "y"]).bind(),
+                positional(vec![Value::String("x".into()), Value::String("y".into())]),
+            ),
+            (
+                named_params! {":a": 1, "@b": "b"}.bind(),
+                Bindings::Named(vec![
+                    (":a".into(), Value::Int32(1)),
+                    ("@b".into(), Value::String("b".into())),
+                ]),
+            ),
+        ];
+        for (i, (bound, expected)) in cases.into_iter().enumerate() {
+            assert_eq!(bound.unwrap(), expected, "case {i}");
+        }
+
+        assert!(matches!([u64::MAX].bind(), Err(Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn optional() {
+        assert_eq!(Ok::<_, Error>(1).optional().unwrap(), Some(1));
+        assert_eq!(Err::<i32, _>(Error::RowCount(0)).optional().unwrap(), None);
+        assert!(matches!(
+            Err::<i32, _>(Error::RowCount(2)).optional(),
+            Err(Error::RowCount(2))
+        ));
+    }
+}
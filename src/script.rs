@@ -0,0 +1,115 @@
+//! SQL script helpers.
+
+/// Split a SQL script into statements.
+///
+/// Semicolons inside string literals, quoted identifiers, comments and
+/// `CREATE TRIGGER ... BEGIN ... END` bodies do not end a statement.
+#[cfg(any(
+    feature = "migrations",
+    feature = "fixtures",
+    feature = "rusqlite-compat"
+))]
+pub(crate) fn split_statements(script: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut word = String::new();
+    let mut first_words: Vec<String> = Vec::new();
+    let mut depth = 0usize;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                end_word(&mut word, &mut first_words, &mut depth);
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                for c in chars.by_ref() {
+                    current.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                end_word(&mut word, &mut first_words, &mut depth);
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        current.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                end_word(&mut word, &mut first_words, &mut depth);
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                current.push(' ');
+            }
+            ';' => {
+                end_word(&mut word, &mut first_words, &mut depth);
+                if depth > 0 {
+                    current.push(c);
+                    continue;
+                }
+                let statement = current.trim();
+                if !statement.is_empty() {
+                    statements.push(statement.to_string());
+                }
+                current.clear();
+                first_words.clear();
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                word.push(c);
+                current.push(c);
+            }
+            c => {
+                end_word(&mut word, &mut first_words, &mut depth);
+                current.push(c);
+            }
+        }
+    }
+
+    let statement = current.trim();
+    if !statement.is_empty() {
+        statements.push(statement.to_string());
+    }
+    statements
+}
+
+/// Finish the current word, tracking keywords that open and close trigger
+/// bodies.
+#[cfg(any(
+    feature = "migrations",
+    feature = "fixtures",
+    feature = "rusqlite-compat"
+))]
+fn end_word(word: &mut String, first_words: &mut Vec<String>, depth: &mut usize) {
+    if word.is_empty() {
+        return;
+    }
+    let upper = word.to_ascii_uppercase();
+    if first_words.len() < 3 {
+        first_words.push(upper.clone());
+    }
+    let is_trigger = first_words.first().map(String::as_str) == Some("CREATE")
+        && first_words.iter().any(|w| w == "TRIGGER");
+    if is_trigger {
+        match upper.as_str() {
+            "BEGIN" | "CASE" => *depth += 1,
+            "END" => *depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    word.clear();
+}
+
+/// Quote an identifier for use in generated SQL.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
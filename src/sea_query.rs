@@ -0,0 +1,118 @@
+//! Running sea-query statements.
+//!
+//! Enabled by the `sea-query` feature.
+//! [`HAConnection::query_sea`](crate::HAConnection::query_sea) and
+//! [`HAConnection::execute_sea`](crate::HAConnection::execute_sea) build a
+//! sea-query statement in the SQLite dialect and run it with its values as
+//! parameters, so the usual routing applies. Schema statements have no
+//! values: run `statement.to_string(SqliteQueryBuilder)` with
+//! [`HAConnection::execute`](crate::HAConnection::execute).
+//!
+//! Values convert as their Rust types do: unsigned integers become
+//! [`Value::Int64`] when they fit, characters become text. Dates, times,
+//! JSON and decimals convert when this crate's `chrono`, `time`, `json` or
+//! `decimal` feature is enabled; any other value fails the statement with
+//! [`Error::InvalidParameter`].
+//!
+//! SeaORM's `DatabaseConnection` is not supported: its proxy connection
+//! reads each column as the exact sea-query type of the entity field, which
+//! the untyped values returned by the server cannot match.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::HAConnection;
+//! use sea_query::{Expr, Query};
+//!
+//! async fn adults(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let select = Query::select()
+//!         .columns(["id", "name"])
+//!         .from("users")
+//!         .and_where(Expr::col("age").gte(18))
+//!         .to_owned();
+//!     let result = conn.query_sea(&select).await?;
+//!     for row in &result {
+//!         let name: Option<String> = row.try_get("name")?;
+//!         println!("{:?}", name);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+use crate::value::Value;
+use ::sea_query::{QueryStatementWriter, SqliteQueryBuilder};
+
+/// Build `statement` in the SQLite dialect, returning its SQL and
+/// parameters.
+pub fn build<S: QueryStatementWriter>(statement: &S) -> Result<(String, Vec<Value>)> {
+    let (sql, values) = statement.build(SqliteQueryBuilder);
+    let params = values
+        .into_iter()
+        .map(Value::try_from)
+        .collect::<Result<Vec<_>>>()?;
+    Ok((sql, params))
+}
+
+impl TryFrom<::sea_query::Value> for Value {
+    type Error = Error;
+
+    fn try_from(value: ::sea_query::Value) -> Result<Self> {
+        use ::sea_query::Value as V;
+        Ok(match value {
+            V::Bool(v) => v.into(),
+            V::TinyInt(v) => v.map(i32::from).into(),
+            V::SmallInt(v) => v.map(i32::from).into(),
+            V::Int(v) => v.into(),
+            V::BigInt(v) => v.into(),
+            V::TinyUnsigned(v) => v.map(i32::from).into(),
+            V::SmallUnsigned(v) => v.map(i32::from).into(),
+            V::Unsigned(v) => v.map(i64::from).into(),
+            V::BigUnsigned(v) => v
+                .map(|v| {
+                    i64::try_from(v).map_err(|_| {
+                        Error::InvalidParameter(format!("{} does not fit in an INTEGER", v))
+                    })
+                })
+                .transpose()?
+                .into(),
+            V::Float(v) => v.into(),
+            V::Double(v) => v.into(),
+            V::String(v) => v.map(|v| *v).into(),
+            V::Char(v) => v.map(String::from).into(),
+            V::Bytes(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "json")]
+            V::Json(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "decimal")]
+            V::Decimal(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoDate(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoTime(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoDateTime(v) => v.map(|v| v.and_utc()).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoDateTimeUtc(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoDateTimeLocal(v) => v.map(|v| v.to_utc()).into(),
+            #[cfg(feature = "chrono")]
+            V::ChronoDateTimeWithTimeZone(v) => v.map(|v| v.to_utc()).into(),
+            #[cfg(feature = "time")]
+            V::TimeDate(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "time")]
+            V::TimeTime(v) => v.map(|v| *v).into(),
+            #[cfg(feature = "time")]
+            V::TimeDateTime(v) => v.map(|v| v.assume_utc()).into(),
+            #[cfg(feature = "time")]
+            V::TimeDateTimeWithTimeZone(v) => v.map(|v| *v).into(),
+            // Types enabled in sea-query by other crates.
+            #[allow(unreachable_patterns)]
+            other => {
+                return Err(Error::InvalidParameter(format!(
+                    "Unsupported sea-query value: {:?}",
+                    other
+                )))
+            }
+        })
+    }
+}
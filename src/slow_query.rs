@@ -0,0 +1,116 @@
+//! Slow query log.
+//!
+//! A [`SlowQueryLog`] reports every statement that takes longer than its
+//! threshold, wherever it ran, as a [`SlowQuery`]: to its callback, or as a
+//! `tracing` warning when it has none. Statements are reported by their
+//! [`fingerprint`], so literal values never reach the log.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HADataSource, SlowQueryLog};
+//! use std::time::Duration;
+//!
+//! let mut ds = HADataSource::default();
+//! ds.set_slow_query_log(
+//!     SlowQueryLog::new(Duration::from_millis(200)).with_callback(|query| {
+//!         eprintln!(
+//!             "slow query ({:?} via {:?}): {}",
+//!             query.duration, query.routing.route, query.fingerprint
+//!         );
+//!     }),
+//! );
+//! ```
+
+use crate::fingerprint::fingerprint;
+use crate::hooks::{QueryEvent, QueryHook, QueryOutcome};
+use crate::routing::RoutingDecision;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// A statement that took longer than the slow query threshold.
+#[derive(Debug, Clone)]
+pub struct SlowQuery {
+    /// The statement's fingerprint
+    pub fingerprint: String,
+    /// Time taken to execute the statement
+    pub duration: Duration,
+    /// Number of parameters bound
+    pub parameter_count: usize,
+    /// Where the statement was executed and why
+    pub routing: RoutingDecision,
+    /// Whether the statement failed
+    pub failed: bool,
+}
+
+/// Function called with each slow query.
+pub type SlowQueryCallback = Arc<dyn Fn(&SlowQuery) + Send + Sync>;
+
+/// Slow query configuration: a threshold plus an optional callback.
+#[derive(Clone)]
+pub struct SlowQueryLog {
+    threshold: Duration,
+    callback: Option<SlowQueryCallback>,
+}
+
+impl SlowQueryLog {
+    /// Log statements taking longer than `threshold` as `tracing` warnings.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            callback: None,
+        }
+    }
+
+    /// Call `callback` with slow statements instead of logging them.
+    pub fn with_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SlowQuery) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Get the threshold.
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+}
+
+impl QueryHook for SlowQueryLog {
+    fn after_query(&self, query: &QueryEvent<'_>, outcome: &QueryOutcome<'_>) {
+        if outcome.latency <= self.threshold {
+            return;
+        }
+        let slow = SlowQuery {
+            fingerprint: fingerprint(query.sql),
+            duration: outcome.latency,
+            parameter_count: query.parameters.len(),
+            routing: outcome.routing,
+            failed: outcome.result.is_err(),
+        };
+        match self.callback {
+            Some(ref callback) => callback(&slow),
+            None => warn!(
+                fingerprint = %slow.fingerprint,
+                duration = ?slow.duration,
+                parameters = slow.parameter_count,
+                route = ?slow.routing.route,
+                reason = %slow.routing.reason,
+                failed = slow.failed,
+                "slow query"
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for SlowQueryLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowQueryLog")
+            .field("threshold", &self.threshold)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
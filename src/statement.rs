@@ -0,0 +1,134 @@
+//! Client-side prepared statements.
+//!
+//! The `Query` RPC has no prepare step, so the SQL text still travels with
+//! every execution. A [`Statement`] counts its placeholders once and rejects
+//! parameter sets of the wrong size before they reach the server, keeps the
+//! column names of its first result, and lets reads served by an embedded
+//! replica reuse the compiled SQLite statement.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAConnection, Value};
+//!
+//! async fn example(conn: &HAConnection) -> litesql_ha::Result<()> {
+//!     let stmt = conn.prepare("SELECT name FROM users WHERE id = ?")?;
+//!     for id in 1..=1000 {
+//!         let result = stmt.query(&[Value::Int64(id)]).await?;
+//!         println!("{:?}", result.rows);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::client::{ExecuteResult, ExecutionResult};
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::params::Placeholders;
+use crate::value::Value;
+use parking_lot::Mutex;
+
+/// A statement prepared on a connection, executable with different parameters.
+pub struct Statement<'conn> {
+    conn: &'conn HAConnection,
+    sql: String,
+    placeholders: Placeholders,
+    columns: Mutex<Option<Vec<String>>>,
+}
+
+impl<'conn> Statement<'conn> {
+    pub(crate) fn new(conn: &'conn HAConnection, sql: &str) -> Result<Self> {
+        if sql.trim().is_empty() {
+            return Err(Error::InvalidParameter("SQL cannot be empty".to_string()));
+        }
+        Ok(Self {
+            conn,
+            sql: sql.to_string(),
+            placeholders: Placeholders::parse(sql),
+            columns: Mutex::new(None),
+        })
+    }
+
+    /// Get the SQL text.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Get the number of parameters the statement expects.
+    pub fn parameter_count(&self) -> usize {
+        self.placeholders.len()
+    }
+
+    /// Get the column names, once the statement has returned a result.
+    pub fn columns(&self) -> Option<Vec<String>> {
+        self.columns.lock().clone()
+    }
+
+    /// Execute the statement as a SELECT query.
+    pub async fn query(&self, params: &[Value]) -> Result<ExecutionResult> {
+        self.check_params(params)?;
+        let result = self.conn.query(&self.sql, params).await?;
+        Ok(self.with_columns(result))
+    }
+
+    /// Execute the statement as an INSERT/UPDATE/DELETE statement.
+    pub async fn execute(&self, params: &[Value]) -> Result<ExecuteResult> {
+        self.check_params(params)?;
+        self.conn.execute(&self.sql, params).await
+    }
+
+    /// Execute the statement as any SQL statement.
+    pub async fn run(&self, params: &[Value]) -> Result<ExecutionResult> {
+        self.check_params(params)?;
+        let result = self.conn.run(&self.sql, params).await?;
+        Ok(self.with_columns(result))
+    }
+
+    /// Execute the statement as a SELECT query with named parameters.
+    pub async fn query_named(&self, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.query(&self.placeholders.bind(params)?).await
+    }
+
+    /// Execute the statement as an INSERT/UPDATE/DELETE statement with named
+    /// parameters.
+    pub async fn execute_named(&self, params: &[(&str, Value)]) -> Result<ExecuteResult> {
+        self.execute(&self.placeholders.bind(params)?).await
+    }
+
+    /// Execute the statement as any SQL statement with named parameters.
+    pub async fn run_named(&self, params: &[(&str, Value)]) -> Result<ExecutionResult> {
+        self.run(&self.placeholders.bind(params)?).await
+    }
+
+    fn check_params(&self, params: &[Value]) -> Result<()> {
+        if params.len() != self.placeholders.len() {
+            return Err(Error::InvalidParameter(format!(
+                "expected {} parameters, got {}",
+                self.placeholders.len(),
+                params.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remember the columns of the first result and fill them in when a
+    /// later response leaves them out.
+    fn with_columns(&self, mut result: ExecutionResult) -> ExecutionResult {
+        let mut columns = self.columns.lock();
+        match *columns {
+            Some(ref cached) if result.columns.is_empty() => result.columns = cached.clone(),
+            None if !result.columns.is_empty() => *columns = Some(result.columns.clone()),
+            _ => {}
+        }
+        result
+    }
+}
+
+impl std::fmt::Debug for Statement<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Statement")
+            .field("sql", &self.sql)
+            .field("parameter_count", &self.placeholders.len())
+            .finish()
+    }
+}
@@ -0,0 +1,212 @@
+//! Tracing spans following the OpenTelemetry semantic conventions.
+//!
+//! Every operation runs in a [`tracing`] span whose fields use the
+//! OpenTelemetry database and messaging conventions, so a subscriber such as
+//! `tracing-opentelemetry` exports them as OTel spans:
+//!
+//! | Span | Kind | Covers |
+//! |---|---|---|
+//! | `litesql.query` | client | A statement or batch sent to the HA server |
+//! | `litesql.replica_read` | client | A read served by an embedded replica |
+//! | `litesql.download` | client | A replica snapshot download |
+//! | `litesql.replicate` | consumer | A NATS replication message applied to a replica |
+//!
+//! Each carries `db.system` (`sqlite`) and `db.name` (the replication ID);
+//! statements also carry `db.operation` and `db.statement`, cut to
+//! [`STATEMENT_LIMIT`] bytes, and calls to the server `net.peer.name` and
+//! `net.peer.port`. Failures set `otel.status_code` to `ERROR` with the
+//! error as `otel.status_message`. Parameters are never recorded.
+//!
+//! With the `opentelemetry` feature, the trace context of the active span is
+//! sent with every call as gRPC metadata, so the server's spans join the
+//! client's trace. It is written by the application's global propagator,
+//! which OpenTelemetry leaves a no-op until one is installed:
+//!
+//! ```ignore
+//! opentelemetry::global::set_text_map_propagator(
+//!     opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+//! );
+//! ```
+
+use crate::error::Error;
+use crate::proto::QueryResponse;
+use std::fmt;
+use tracing::field::Empty;
+use tracing::{info_span, Span};
+
+/// Bytes of a statement recorded as `db.statement`.
+pub const STATEMENT_LIMIT: usize = 1024;
+
+/// The server a client calls, as recorded on its spans.
+#[derive(Debug, Clone)]
+pub(crate) struct Peer {
+    pub(crate) name: String,
+    pub(crate) port: u16,
+}
+
+/// Span of `sql` sent to the HA server.
+pub(crate) fn query_span(peer: &Peer, replication_id: &str, sql: &str) -> Span {
+    let operation = operation(sql);
+    info_span!(
+        "litesql.query",
+        otel.name = %span_name(&operation, replication_id),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        db.system = "sqlite",
+        db.name = %replication_id,
+        db.operation = %operation,
+        db.statement = statement(sql),
+        net.peer.name = %peer.name,
+        net.peer.port = peer.port,
+    )
+}
+
+/// Span of a batch of `statements` sent to the HA server together.
+pub(crate) fn batch_span<'a>(
+    peer: &Peer,
+    replication_id: &str,
+    statements: impl Iterator<Item = &'a str>,
+) -> Span {
+    info_span!(
+        "litesql.query",
+        otel.name = %span_name("BATCH", replication_id),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        db.system = "sqlite",
+        db.name = %replication_id,
+        db.operation = "BATCH",
+        db.statement = statement(&statements.collect::<Vec<_>>().join(";\n")),
+        net.peer.name = %peer.name,
+        net.peer.port = peer.port,
+    )
+}
+
+/// Span of `sql` read from the embedded replica of `replication_id`.
+#[cfg(feature = "embedded-replicas")]
+pub(crate) fn replica_read_span(replication_id: &str, sql: &str) -> Span {
+    let operation = operation(sql);
+    info_span!(
+        "litesql.replica_read",
+        otel.name = %span_name(&operation, replication_id),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        db.system = "sqlite",
+        db.name = %replication_id,
+        db.operation = %operation,
+        db.statement = statement(sql),
+    )
+}
+
+/// Span of a download of the replica of `replication_id`.
+pub(crate) fn download_span(peer: &Peer, replication_id: &str) -> Span {
+    info_span!(
+        "litesql.download",
+        otel.name = %format!("download {}", replication_id),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        db.system = "sqlite",
+        db.name = %replication_id,
+        net.peer.name = %peer.name,
+        net.peer.port = peer.port,
+    )
+}
+
+/// Span of replication message `seq` from `subject` applied to the replica
+/// of `replication_id`.
+#[cfg(feature = "nats")]
+pub(crate) fn replicate_span(replication_id: &str, subject: &str, seq: u64) -> Span {
+    info_span!(
+        "litesql.replicate",
+        otel.name = %format!("{} process", subject),
+        otel.kind = "consumer",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        db.system = "sqlite",
+        db.name = %replication_id,
+        messaging.system = "nats",
+        messaging.operation = "process",
+        messaging.destination.name = %subject,
+        messaging.message.id = seq,
+    )
+}
+
+/// Mark `span` as failed with `error`.
+pub(crate) fn fail(span: &Span, error: &dyn fmt::Display) {
+    span.record("otel.status_code", "ERROR");
+    span.record("otel.status_message", tracing::field::display(error));
+}
+
+/// Mark `span` as failed if `response` is an error or reports one.
+pub(crate) fn record_response(span: &Span, response: Result<&QueryResponse, &Error>) {
+    match response {
+        Ok(response) if !response.error.is_empty() => fail(span, &response.error),
+        Ok(_) => {}
+        Err(e) => fail(span, e),
+    }
+}
+
+/// The trace context of the current span, captured before work moves to
+/// another task.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn context() -> opentelemetry::Context {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    Span::current().context()
+}
+
+/// Write `context` into the metadata of a call with the global propagator.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn inject(
+    context: &opentelemetry::Context,
+    metadata: &mut tonic::metadata::MetadataMap,
+) {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(context, &mut MetadataInjector(metadata))
+    });
+}
+
+#[cfg(feature = "opentelemetry")]
+struct MetadataInjector<'a>(&'a mut tonic::metadata::MetadataMap);
+
+#[cfg(feature = "opentelemetry")]
+impl opentelemetry::propagation::Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        let key = tonic::metadata::MetadataKey::from_bytes(key.as_bytes());
+        if let (Ok(key), Ok(value)) = (key, value.parse()) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// The first keyword of `sql`, upper-cased.
+fn operation(sql: &str) -> String {
+    sql.split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(';')
+        .to_ascii_uppercase()
+}
+
+fn span_name(operation: impl fmt::Display, replication_id: &str) -> String {
+    if replication_id.is_empty() {
+        operation.to_string()
+    } else {
+        format!("{} {}", operation, replication_id)
+    }
+}
+
+/// `sql` cut to [`STATEMENT_LIMIT`] bytes on a character boundary.
+fn statement(sql: &str) -> &str {
+    if sql.len() <= STATEMENT_LIMIT {
+        return sql;
+    }
+    let mut end = STATEMENT_LIMIT;
+    while !sql.is_char_boundary(end) {
+        end -= 1;
+    }
+    &sql[..end]
+}
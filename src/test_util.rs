@@ -0,0 +1,763 @@
+//! In-process mock HA server for tests.
+//!
+//! Enabled by the `test-util` feature. [`MockServer`] implements the
+//! `sql.v1.DatabaseService` gRPC service on a local port, backed by one
+//! in-memory SQLite database per replication ID, so applications can test
+//! against this crate without a running cluster.
+//!
+//! Failures can be injected with [`MockServer::fail_next`]; each queued
+//! [`Failure`] is consumed by the next `Query` call.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::test_util::MockServer;
+//! use litesql_ha::{HADataSource, HADataSourceOptions};
+//!
+//! # async fn example() -> litesql_ha::Result<()> {
+//! let server = MockServer::start().await?;
+//! server.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")?;
+//!
+//! let ds = HADataSource::new(HADataSourceOptions {
+//!     url: server.url(),
+//!     ..Default::default()
+//! });
+//! let conn = ds.get_connection().await?;
+//! conn.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+//!     .await?;
+//!
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::proto::database_service_server::{DatabaseService, DatabaseServiceServer};
+use crate::proto::{
+    DownloadRequest, DownloadResponse, FramesRequest, FramesResponse, LatestSnapshotRequest,
+    LatestSnapshotResponse, Page, QueryRequest, QueryResponse, QueryType, ReplicationIDsResponse,
+    ResultSet, Row,
+};
+use crate::runtime::{self, JoinHandle, TcpListener};
+use crate::value::Value;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use rusqlite::{params_from_iter, Connection, ToSql};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Replication ID of the database created by [`MockServer::start`].
+pub const DEFAULT_DATABASE: &str = "test";
+
+/// Metadata key carrying the leader address on a redirect.
+pub const LEADER_METADATA_KEY: &str = crate::client::LEADER_METADATA_KEY;
+
+/// Size of the chunks streamed by `Download` and `LatestSnapshot`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Snapshots kept for ranged `Download` calls.
+const SNAPSHOTS_KEPT: usize = 8;
+
+/// Snapshots of each database kept to send the pages changed since them.
+const HISTORY_KEPT: usize = 16;
+
+/// A failure injected into the next `Query` call.
+#[derive(Debug, Clone)]
+pub enum Failure {
+    /// Delay the response, so the client hits its timeout.
+    Timeout(Duration),
+    /// Reject the call as a follower pointing at the given leader address.
+    LeaderRedirect(String),
+    /// Reject the call as a follower naming its leader only in the message,
+    /// `not leader, leader is <text>`, as servers without the metadata do.
+    LeaderMessage(String),
+    /// Answer with a query error.
+    Error(String),
+    /// Fail the call with `UNAVAILABLE`, as if the node were down.
+    Unavailable,
+}
+
+struct MockDatabase {
+    conn: Connection,
+    txseq: i64,
+    /// Snapshots taken so far, by txseq, for `Frames` calls
+    history: BTreeMap<i64, Arc<Vec<u8>>>,
+}
+
+/// A snapshot kept for ranged downloads.
+struct KeptSnapshot {
+    id: String,
+    data: Arc<Vec<u8>>,
+    /// SHA-256 of `data`
+    checksum: Vec<u8>,
+}
+
+struct MockState {
+    databases: DashMap<String, Arc<Mutex<MockDatabase>>>,
+    failures: Mutex<VecDeque<Failure>>,
+    queries: Mutex<Vec<String>>,
+    rows_per_response: Mutex<Option<usize>>,
+    query_streams: AtomicUsize,
+    token: Mutex<Option<String>>,
+    snapshots: Mutex<VecDeque<Arc<KeptSnapshot>>>,
+    next_snapshot: AtomicU64,
+    corrupt_downloads: AtomicUsize,
+    omit_frame_checksums: AtomicBool,
+}
+
+// tonic calls fail with an unboxed `Status`, as the helpers below do
+#[allow(clippy::result_large_err)]
+impl MockState {
+    fn database(
+        &self,
+        replication_id: &str,
+    ) -> std::result::Result<Arc<Mutex<MockDatabase>>, Status> {
+        let name = if replication_id.is_empty() {
+            DEFAULT_DATABASE
+        } else {
+            replication_id
+        };
+        self.databases
+            .get(name)
+            .map(|db| db.value().clone())
+            .ok_or_else(|| Status::not_found(format!("unknown replication id: {}", name)))
+    }
+
+    /// Keep a snapshot for later ranged downloads.
+    fn keep_snapshot(&self, replication_id: &str, data: Arc<Vec<u8>>) -> Arc<KeptSnapshot> {
+        let snapshot = Arc::new(KeptSnapshot {
+            id: format!(
+                "{}-{}",
+                replication_id,
+                self.next_snapshot.fetch_add(1, Ordering::Relaxed)
+            ),
+            checksum: Sha256::digest(data.as_slice()).to_vec(),
+            data,
+        });
+        let mut snapshots = self.snapshots.lock();
+        if snapshots.len() == SNAPSHOTS_KEPT {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot.clone());
+        snapshot
+    }
+
+    fn kept_snapshot(&self, id: &str) -> std::result::Result<Arc<KeptSnapshot>, Status> {
+        self.snapshots
+            .lock()
+            .iter()
+            .find(|kept| kept.id == id)
+            .cloned()
+            .ok_or_else(|| Status::failed_precondition(format!("snapshot {} is gone", id)))
+    }
+}
+
+/// In-process implementation of the HA server.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start a server with a single empty database named [`DEFAULT_DATABASE`].
+    pub async fn start() -> Result<Self> {
+        Self::with_databases(&[DEFAULT_DATABASE]).await
+    }
+
+    /// Start a server with one empty database per replication ID.
+    pub async fn with_databases(replication_ids: &[&str]) -> Result<Self> {
+        let state = Arc::new(MockState {
+            databases: DashMap::new(),
+            failures: Mutex::new(VecDeque::new()),
+            queries: Mutex::new(Vec::new()),
+            rows_per_response: Mutex::new(None),
+            query_streams: AtomicUsize::new(0),
+            token: Mutex::new(None),
+            snapshots: Mutex::new(VecDeque::new()),
+            next_snapshot: AtomicU64::new(0),
+            corrupt_downloads: AtomicUsize::new(0),
+            omit_frame_checksums: AtomicBool::new(false),
+        });
+        for id in replication_ids {
+            let db = MockDatabase {
+                conn: Connection::open_in_memory()?,
+                txseq: 0,
+                history: BTreeMap::new(),
+            };
+            state
+                .databases
+                .insert(id.to_string(), Arc::new(Mutex::new(db)));
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let service = DatabaseServiceServer::new(MockService {
+            state: state.clone(),
+        });
+        // Only used for clients accepting them
+        #[cfg(feature = "gzip")]
+        let service = service.send_compressed(CompressionEncoding::Gzip);
+        #[cfg(feature = "zstd")]
+        let service = service.send_compressed(CompressionEncoding::Zstd);
+        let handle = runtime::spawn_named(runtime::MOCK_SERVER, async move {
+            let result = Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+            if let Err(e) = result {
+                tracing::error!("Mock server stopped: {}", e);
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown_tx: Some(shutdown_tx),
+            handle: Some(handle),
+        })
+    }
+
+    /// Get the address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Get the URL of the default database.
+    pub fn url(&self) -> String {
+        self.url_for(DEFAULT_DATABASE)
+    }
+
+    /// Get the URL of a database.
+    pub fn url_for(&self, replication_id: &str) -> String {
+        format!("litesql://{}/{}", self.addr, replication_id)
+    }
+
+    /// Run SQL directly against the default database, e.g. to seed data.
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.execute_batch_on(DEFAULT_DATABASE, sql)
+    }
+
+    /// Run SQL directly against a database, e.g. to seed data.
+    pub fn execute_batch_on(&self, replication_id: &str, sql: &str) -> Result<()> {
+        let db = self
+            .state
+            .database(replication_id)
+            .map_err(|e| Error::InvalidParameter(e.message().to_string()))?;
+        let mut db = db.lock();
+        db.conn.execute_batch(sql)?;
+        // The snapshot at the txseq no longer shows the database
+        let txseq = db.txseq;
+        db.history.remove(&txseq);
+        Ok(())
+    }
+
+    /// Forget the snapshots taken of a database so far, so that `Frames`
+    /// calls report its history as pruned.
+    pub fn prune_history(&self, replication_id: &str) {
+        if let Some(db) = self.state.databases.get(replication_id) {
+            db.lock().history.clear();
+        }
+    }
+
+    /// Get the current transaction sequence number of a database.
+    pub fn txseq(&self, replication_id: &str) -> Option<i64> {
+        self.state
+            .databases
+            .get(replication_id)
+            .map(|db| db.lock().txseq)
+    }
+
+    /// Queue a failure for the next `Query` call.
+    pub fn fail_next(&self, failure: Failure) {
+        self.state.failures.lock().push_back(failure);
+    }
+
+    /// Drop all queued failures.
+    pub fn clear_failures(&self) {
+        self.state.failures.lock().clear();
+    }
+
+    /// Split results over several `Query` responses of at most `rows` rows
+    /// each, as the server does for large results; `None` sends one response.
+    pub fn set_rows_per_response(&self, rows: Option<usize>) {
+        *self.state.rows_per_response.lock() = rows.filter(|r| *r > 0);
+    }
+
+    /// Flip a byte in the data sent by the next `count` `Download` calls,
+    /// leaving their checksums as they were.
+    pub fn corrupt_downloads(&self, count: usize) {
+        self.state.corrupt_downloads.store(count, Ordering::Relaxed);
+    }
+
+    /// Send `Frames` responses without the checksum of the snapshot, as
+    /// servers predating it do.
+    pub fn omit_frame_checksums(&self, omit: bool) {
+        self.state
+            .omit_frame_checksums
+            .store(omit, Ordering::Relaxed);
+    }
+
+    /// Get the SQL of every query received so far, in order.
+    pub fn queries(&self) -> Vec<String> {
+        self.state.queries.lock().clone()
+    }
+
+    /// Reject calls without `Bearer <token>` as `UNAUTHENTICATED`; `None`
+    /// accepts every call.
+    pub fn require_token(&self, token: Option<&str>) {
+        *self.state.token.lock() = token.map(str::to_string);
+    }
+
+    /// Get the number of `Query` streams opened so far.
+    pub fn query_streams(&self) -> usize {
+        self.state.query_streams.load(Ordering::Relaxed)
+    }
+
+    /// Stop the server and wait for it to exit.
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+struct MockService {
+    state: Arc<MockState>,
+}
+
+#[allow(clippy::result_large_err)]
+impl MockService {
+    fn authenticate<T>(&self, request: &Request<T>) -> std::result::Result<(), Status> {
+        let Some(ref token) = *self.state.token.lock() else {
+            return Ok(());
+        };
+        let expected = format!("Bearer {}", token);
+        match request.metadata().get("authorization") {
+            Some(value) if value.as_bytes() == expected.as_bytes() => Ok(()),
+            _ => Err(Status::unauthenticated("invalid token")),
+        }
+    }
+
+    async fn query_one(&self, request: QueryRequest) -> std::result::Result<QueryResponse, Status> {
+        self.state.queries.lock().push(request.sql.clone());
+
+        let failure = self.state.failures.lock().pop_front();
+        match failure {
+            Some(Failure::Timeout(delay)) => runtime::sleep(delay).await,
+            Some(Failure::LeaderRedirect(leader)) => {
+                let mut status = Status::unavailable(format!("not leader, leader is {}", leader));
+                if let Ok(value) = MetadataValue::try_from(leader.as_str()) {
+                    status.metadata_mut().insert(LEADER_METADATA_KEY, value);
+                }
+                return Err(status);
+            }
+            Some(Failure::LeaderMessage(text)) => {
+                return Err(Status::unavailable(format!(
+                    "not leader, leader is {}",
+                    text
+                )));
+            }
+            Some(Failure::Error(error)) => {
+                return Ok(QueryResponse {
+                    error,
+                    ..Default::default()
+                })
+            }
+            Some(Failure::Unavailable) => return Err(Status::unavailable("node unavailable")),
+            None => {}
+        }
+
+        let db = self.state.database(&request.replication_id)?;
+        let mut db = db.lock();
+        match Self::run(&mut db, &request) {
+            Ok(response) => Ok(response),
+            Err(e) => Ok(QueryResponse {
+                error: e.to_string(),
+                txseq: db.txseq,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn run(db: &mut MockDatabase, request: &QueryRequest) -> Result<QueryResponse> {
+        let mut params = Vec::with_capacity(request.params.len());
+        for param in &request.params {
+            let value = match param.value {
+                Some(ref any) => Value::from_any(any)?,
+                None => Value::Null,
+            };
+            params.push(HAConnection::value_to_sqlite(&value));
+        }
+        let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut stmt = db.conn.prepare(&request.sql)?;
+        let is_query = match request.r#type() {
+            QueryType::ExecQuery => true,
+            QueryType::ExecUpdate => false,
+            QueryType::Unspecified => stmt.readonly() && stmt.column_count() > 0,
+        };
+
+        if !is_query {
+            let rows_affected = stmt.execute(params_from_iter(param_refs.iter()))? as i64;
+            drop(stmt);
+            db.txseq += 1;
+            return Ok(QueryResponse {
+                rows_affected,
+                last_insert_rowid: db.conn.last_insert_rowid(),
+                txseq: db.txseq,
+                ..Default::default()
+            });
+        }
+
+        let column_count = stmt.column_count();
+        let columns: Vec<String> = (0..column_count)
+            .map(|i| stmt.column_name(i).unwrap_or("").to_string())
+            .collect();
+
+        let mut rows = Vec::new();
+        let mut result = stmt.query(params_from_iter(param_refs.iter()))?;
+        while let Some(row) = result.next()? {
+            let mut values = Vec::with_capacity(column_count);
+            for i in 0..column_count {
+                let value: rusqlite::types::Value = row.get(i)?;
+                values.push(HAConnection::sqlite_to_value(value).to_any());
+            }
+            rows.push(Row { values });
+        }
+        drop(result);
+        drop(stmt);
+
+        Ok(QueryResponse {
+            result_set: Some(ResultSet { columns, rows }),
+            txseq: db.txseq,
+            ..Default::default()
+        })
+    }
+
+    /// Serialize a database to bytes, with an `ha_stats` table recording its
+    /// txseq so it can be loaded as an embedded replica.
+    ///
+    /// The snapshot is kept in the database's history, and taken again only
+    /// once the database changed.
+    fn snapshot(&self, replication_id: &str) -> std::result::Result<Arc<Vec<u8>>, Status> {
+        let db = self.state.database(replication_id)?;
+        let mut db = db.lock();
+        Self::take_snapshot(&mut db)
+    }
+
+    fn take_snapshot(db: &mut MockDatabase) -> std::result::Result<Arc<Vec<u8>>, Status> {
+        if let Some(data) = db.history.get(&db.txseq) {
+            return Ok(data.clone());
+        }
+        let data = Arc::new(Self::serialize(db)?);
+        if db.history.len() == HISTORY_KEPT {
+            db.history.pop_first();
+        }
+        let txseq = db.txseq;
+        db.history.insert(txseq, data.clone());
+        Ok(data)
+    }
+
+    fn serialize(db: &MockDatabase) -> std::result::Result<Vec<u8>, Status> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "litesql-ha-mock-{}-{}.db",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = (|| -> Result<Vec<u8>> {
+            db.conn
+                .execute("VACUUM INTO ?1", [path.to_string_lossy().as_ref()])?;
+            let copy = Connection::open(&path)?;
+            copy.execute_batch(
+                "CREATE TABLE IF NOT EXISTS ha_stats (received_seq INTEGER, updated_at INTEGER)",
+            )?;
+            copy.execute(
+                "INSERT INTO ha_stats (received_seq, updated_at) VALUES (?1, strftime('%s', 'now'))",
+                [db.txseq],
+            )?;
+            drop(copy);
+            Ok(std::fs::read(&path)?)
+        })();
+        let _ = std::fs::remove_file(&path);
+
+        result.map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl DatabaseService for MockService {
+    type QueryStream = ReceiverStream<std::result::Result<QueryResponse, Status>>;
+    type DownloadStream = ReceiverStream<std::result::Result<DownloadResponse, Status>>;
+    type LatestSnapshotStream = ReceiverStream<std::result::Result<LatestSnapshotResponse, Status>>;
+    type FramesStream = ReceiverStream<std::result::Result<FramesResponse, Status>>;
+
+    async fn query(
+        &self,
+        request: Request<Streaming<QueryRequest>>,
+    ) -> std::result::Result<Response<Self::QueryStream>, Status> {
+        self.state.query_streams.fetch_add(1, Ordering::Relaxed);
+        self.authenticate(&request)?;
+
+        // Delay before answering so that client deadlines fire.
+        let delay = {
+            let mut failures = self.state.failures.lock();
+            match failures.front() {
+                Some(Failure::Timeout(delay)) => {
+                    let delay = *delay;
+                    failures.pop_front();
+                    Some(delay)
+                }
+                _ => None,
+            }
+        };
+        if let Some(delay) = delay {
+            runtime::sleep(delay).await;
+        }
+
+        let mut stream = request.into_inner();
+        let service = MockService {
+            state: self.state.clone(),
+        };
+        let (tx, rx) = mpsc::channel(16);
+
+        runtime::spawn_named(runtime::MOCK_SERVER, async move {
+            loop {
+                let request = match stream.message().await {
+                    Ok(Some(request)) => request,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                };
+                let response = service.query_one(request).await;
+                let failed = response.is_err();
+                let rows_per_response = *service.state.rows_per_response.lock();
+                let responses = match (response, rows_per_response) {
+                    (Ok(response), Some(rows)) => split_response(response, rows),
+                    (response, _) => vec![response],
+                };
+                for response in responses {
+                    if tx.send(response).await.is_err() {
+                        return;
+                    }
+                }
+                if failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn download(
+        &self,
+        request: Request<DownloadRequest>,
+    ) -> std::result::Result<Response<Self::DownloadStream>, Status> {
+        self.authenticate(&request)?;
+        let request = request.into_inner();
+        let snapshot = if request.snapshot_id.is_empty() {
+            let data = self.snapshot(&request.replication_id)?;
+            self.state.keep_snapshot(&request.replication_id, data)
+        } else {
+            self.state.kept_snapshot(&request.snapshot_id)?
+        };
+        let data = &snapshot.data;
+
+        let start = usize::try_from(request.offset)
+            .ok()
+            .filter(|&offset| offset <= data.len())
+            .ok_or_else(|| Status::out_of_range(format!("offset {}", request.offset)))?;
+        let end = match usize::try_from(request.length) {
+            Ok(0) => data.len(),
+            Ok(length) => start.saturating_add(length).min(data.len()),
+            Err(_) => {
+                return Err(Status::invalid_argument(format!(
+                    "length {}",
+                    request.length
+                )))
+            }
+        };
+        let range = &data[start..end];
+        let mut checksum = Sha256::digest(range).to_vec();
+        let corrupt = self
+            .state
+            .corrupt_downloads
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        let chunks = range.chunks(CHUNK_SIZE).count();
+        let (tx, rx) = mpsc::channel(chunks.max(1));
+        for (i, chunk) in range.chunks(CHUNK_SIZE).enumerate() {
+            let mut chunk = chunk.to_vec();
+            if corrupt && i == 0 {
+                chunk[0] ^= 0xff;
+            }
+            let _ = tx.try_send(Ok(DownloadResponse {
+                data: chunk,
+                offset: (start + i * CHUNK_SIZE) as i64,
+                size: data.len() as i64,
+                snapshot_id: snapshot.id.clone(),
+                checksum: std::mem::take(&mut checksum),
+                snapshot_checksum: snapshot.checksum.clone(),
+            }));
+        }
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn latest_snapshot(
+        &self,
+        request: Request<LatestSnapshotRequest>,
+    ) -> std::result::Result<Response<Self::LatestSnapshotStream>, Status> {
+        self.authenticate(&request)?;
+        let data = self.snapshot(&request.into_inner().replication_id)?;
+        let chunks = data.chunks(CHUNK_SIZE).count();
+        let (tx, rx) = mpsc::channel(chunks.max(1));
+        for chunk in data.chunks(CHUNK_SIZE) {
+            let _ = tx.try_send(Ok(LatestSnapshotResponse {
+                data: chunk.to_vec(),
+            }));
+        }
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn frames(
+        &self,
+        request: Request<FramesRequest>,
+    ) -> std::result::Result<Response<Self::FramesStream>, Status> {
+        self.authenticate(&request)?;
+        let request = request.into_inner();
+        let db = self.state.database(&request.replication_id)?;
+        let (txseq, base, data) = {
+            let mut db = db.lock();
+            let data = Self::take_snapshot(&mut db)?;
+            (db.txseq, db.history.get(&request.txseq).cloned(), data)
+        };
+        let Some(base) = base else {
+            let (tx, rx) = mpsc::channel(1);
+            let _ = tx.try_send(Ok(FramesResponse {
+                pruned: true,
+                ..Default::default()
+            }));
+            return Ok(Response::new(ReceiverStream::new(rx)));
+        };
+
+        // Page 1, holding the header, is always sent
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        let pages: Vec<Page> = data
+            .chunks(page_size)
+            .enumerate()
+            .filter(|(i, page)| {
+                *i == 0 || base.get(i * page_size..(i + 1) * page_size) != Some(page)
+            })
+            .map(|(i, page)| Page {
+                number: i as i64 + 1,
+                data: page.to_vec(),
+            })
+            .collect();
+        let checksum = if self.state.omit_frame_checksums.load(Ordering::Relaxed) {
+            Vec::new()
+        } else {
+            Sha256::digest(data.as_slice()).to_vec()
+        };
+        let per_message = (CHUNK_SIZE / page_size).max(1);
+        let messages = pages.chunks(per_message).count();
+        let (tx, rx) = mpsc::channel(messages);
+        for pages in pages.chunks(per_message) {
+            let _ = tx.try_send(Ok(FramesResponse {
+                pruned: false,
+                txseq,
+                page_size: page_size as i64,
+                page_count: (data.len() / page_size) as i64,
+                pages: pages.to_vec(),
+                checksum: checksum.clone(),
+            }));
+        }
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn replication_i_ds(
+        &self,
+        request: Request<()>,
+    ) -> std::result::Result<Response<ReplicationIDsResponse>, Status> {
+        self.authenticate(&request)?;
+        let mut replication_id: Vec<String> = self
+            .state
+            .databases
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        replication_id.sort();
+        Ok(Response::new(ReplicationIDsResponse { replication_id }))
+    }
+}
+
+/// Split a response into messages of at most `rows` rows; the first carries
+/// the column names and the affected row count.
+#[allow(clippy::result_large_err)]
+fn split_response(
+    mut response: QueryResponse,
+    rows: usize,
+) -> Vec<std::result::Result<QueryResponse, Status>> {
+    let Some(result_set) = response.result_set.take() else {
+        return vec![Ok(response)];
+    };
+    if result_set.rows.len() <= rows {
+        response.result_set = Some(result_set);
+        return vec![Ok(response)];
+    }
+
+    let mut columns = Some(result_set.columns);
+    let mut rows_affected = Some(response.rows_affected);
+    result_set
+        .rows
+        .chunks(rows)
+        .map(|chunk| {
+            Ok(QueryResponse {
+                result_set: Some(ResultSet {
+                    columns: columns.take().unwrap_or_default(),
+                    rows: chunk.to_vec(),
+                }),
+                rows_affected: rows_affected.take().unwrap_or_default(),
+                txseq: response.txseq,
+                error: String::new(),
+                last_insert_rowid: response.last_insert_rowid,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+//! Docker-based integration test helpers.
+//!
+//! Enabled by the `testcontainers` feature. [`HAContainer`] starts the
+//! LiteSQL HA server in Docker, optionally together with a NATS JetStream
+//! server on a shared network, waits until the server answers gRPC calls and
+//! hands out a data source configured for it. The containers are removed when
+//! the [`HAContainer`] is dropped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::testcontainers::HAContainer;
+//!
+//! # async fn example() -> litesql_ha::Result<()> {
+//! let ha = HAContainer::start().await?;
+//! let conn = ha.data_source().get_connection().await?;
+//! conn.execute("CREATE TABLE users (id INTEGER PRIMARY KEY)", &[]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::{HAClient, HAClientOptions};
+use crate::datasource::{HADataSource, HADataSourceOptions};
+use crate::error::{Error, Result};
+use crate::runtime;
+use ::testcontainers::core::{ContainerPort, IntoContainerPort, WaitFor};
+use ::testcontainers::runners::AsyncRunner;
+use ::testcontainers::{ContainerAsync, GenericImage, ImageExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default LiteSQL HA server image.
+pub const DEFAULT_IMAGE: &str = "ghcr.io/litesql/ha";
+
+/// Default LiteSQL HA server image tag.
+pub const DEFAULT_TAG: &str = "latest";
+
+/// gRPC port of the LiteSQL HA server inside the container.
+pub const GRPC_PORT: u16 = 8080;
+
+/// NATS client port inside the container.
+pub const NATS_PORT: u16 = 4222;
+
+/// Options for [`HAContainer::start_with`].
+#[derive(Debug, Clone)]
+pub struct HAContainerOptions {
+    /// LiteSQL HA server image
+    pub image: String,
+    /// LiteSQL HA server image tag
+    pub tag: String,
+    /// Database (replication ID) the data source connects to
+    pub database: String,
+    /// Extra environment variables for the LiteSQL HA server
+    pub env: Vec<(String, String)>,
+    /// Start a NATS JetStream container next to the server
+    pub nats: bool,
+    /// NATS image and tag
+    pub nats_image: (String, String),
+    /// Environment variable passing the NATS URL to the server
+    pub nats_url_env: String,
+    /// How long to wait for the server to become ready
+    pub startup_timeout: Duration,
+}
+
+impl Default for HAContainerOptions {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_IMAGE.to_string(),
+            tag: DEFAULT_TAG.to_string(),
+            database: String::new(),
+            env: Vec::new(),
+            nats: false,
+            nats_image: ("nats".to_string(), "2.10".to_string()),
+            nats_url_env: "NATS_URL".to_string(),
+            startup_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A running LiteSQL HA server, and optionally NATS, in Docker.
+pub struct HAContainer {
+    server: ContainerAsync<GenericImage>,
+    nats: Option<ContainerAsync<GenericImage>>,
+    url: String,
+    replication_url: Option<String>,
+}
+
+impl HAContainer {
+    /// Start the server with default options.
+    pub async fn start() -> Result<Self> {
+        Self::start_with(HAContainerOptions::default()).await
+    }
+
+    /// Start the server, and NATS if requested, and wait until it is ready.
+    pub async fn start_with(options: HAContainerOptions) -> Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let suffix = format!(
+            "{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let network = format!("litesql-ha-{}", suffix);
+
+        let (nats, replication_url, nats_env) = if options.nats {
+            let name = format!("litesql-ha-nats-{}", suffix);
+            let (image, tag) = options.nats_image.clone();
+            let container = GenericImage::new(image, tag)
+                .with_exposed_port(NATS_PORT.tcp())
+                .with_wait_for(WaitFor::message_on_stderr("Server is ready"))
+                .with_cmd(["-js"])
+                .with_network(network.clone())
+                .with_container_name(name.clone())
+                .start()
+                .await?;
+            let host = container.get_host().await?;
+            let port = container.get_host_port_ipv4(NATS_PORT).await?;
+            let env = (
+                options.nats_url_env.clone(),
+                format!("nats://{}:{}", name, NATS_PORT),
+            );
+            (
+                Some(container),
+                Some(format!("nats://{}:{}", host, port)),
+                Some(env),
+            )
+        } else {
+            (None, None, None)
+        };
+
+        let mut request = GenericImage::new(options.image.clone(), options.tag.clone())
+            .with_exposed_port(ContainerPort::Tcp(GRPC_PORT))
+            .with_network(network)
+            .with_startup_timeout(options.startup_timeout);
+        for (key, value) in options.env.iter().chain(nats_env.iter()) {
+            request = request.with_env_var(key.clone(), value.clone());
+        }
+        let server = request.start().await?;
+
+        let host = server.get_host().await?;
+        let port = server.get_host_port_ipv4(GRPC_PORT).await?;
+        let url = format!("litesql://{}:{}/{}", host, port, options.database);
+
+        wait_until_ready(&url, options.startup_timeout).await?;
+
+        Ok(Self {
+            server,
+            nats,
+            url,
+            replication_url,
+        })
+    }
+
+    /// Get the URL of the server.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get the host-reachable NATS URL, when NATS was started.
+    pub fn replication_url(&self) -> Option<&str> {
+        self.replication_url.as_deref()
+    }
+
+    /// Get data source options pointing at the containers.
+    pub fn data_source_options(&self) -> HADataSourceOptions {
+        HADataSourceOptions {
+            url: self.url.clone(),
+            replication_url: self.replication_url.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a data source pointing at the containers.
+    pub fn data_source(&self) -> HADataSource {
+        HADataSource::new(self.data_source_options())
+    }
+
+    /// Get the server container.
+    pub fn server(&self) -> &ContainerAsync<GenericImage> {
+        &self.server
+    }
+
+    /// Get the NATS container, when NATS was started.
+    pub fn nats(&self) -> Option<&ContainerAsync<GenericImage>> {
+        self.nats.as_ref()
+    }
+}
+
+/// Poll the server until a gRPC call succeeds.
+async fn wait_until_ready(url: &str, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let options = HAClientOptions {
+            url: url.to_string(),
+            timeout: 5,
+            ..Default::default()
+        };
+        let attempt = match HAClient::new(options).await {
+            Ok(client) => client.get_replication_ids().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        match attempt {
+            Ok(()) => return Ok(()),
+            Err(e) if Instant::now() >= deadline => {
+                return Err(Error::Container(format!(
+                    "server at {} not ready after {:?}: {}",
+                    url, timeout, e
+                )))
+            }
+            Err(_) => runtime::sleep(Duration::from_millis(250)).await,
+        }
+    }
+}
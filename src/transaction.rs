@@ -0,0 +1,393 @@
+//! Transaction guard.
+//!
+//! [`HAConnection::transaction`] begins a transaction and returns a
+//! [`Transaction`] that must be finished with [`commit`](Transaction::commit)
+//! or [`rollback`](Transaction::rollback). A transaction dropped without
+//! either, for example because the task panicked or returned early with `?`,
+//! is rolled back.
+//!
+//! [`Transaction::savepoint`] opens a nested [`Savepoint`] scope that is
+//! released or rolled back on its own, and rolled back to when dropped.
+//! [`HAConnection::savepoint`] does the same whether or not a transaction is
+//! open, so a layer can take a savepoint without knowing about its callers;
+//! the outermost savepoint begins the transaction and releasing it commits.
+//!
+//! [`HAConnection::run_transaction`] runs a closure in a transaction and
+//! commits it, running the whole closure again in a new transaction when the
+//! database is busy or the server unavailable. The closure must therefore be
+//! safe to run more than once.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAConnection, Value};
+//!
+//! async fn transfer(conn: &HAConnection, from: i64, to: i64) -> litesql_ha::Result<()> {
+//!     let tx = conn.transaction().await?;
+//!     tx.execute("UPDATE accounts SET balance = balance - 10 WHERE id = ?", &[Value::Int64(from)])
+//!         .await?;
+//!     tx.execute("UPDATE accounts SET balance = balance + 10 WHERE id = ?", &[Value::Int64(to)])
+//!         .await?;
+//!     tx.commit().await
+//! }
+//!
+//! async fn transfer_with_retry(conn: &HAConnection, from: i64, to: i64) -> litesql_ha::Result<()> {
+//!     conn.run_transaction(|tx| {
+//!         Box::pin(async move {
+//!             tx.execute("UPDATE accounts SET balance = balance - 10 WHERE id = ?", &[Value::Int64(from)])
+//!                 .await?;
+//!             tx.execute("UPDATE accounts SET balance = balance + 10 WHERE id = ?", &[Value::Int64(to)])
+//!                 .await?;
+//!             Ok(())
+//!         })
+//!     })
+//!     .await
+//! }
+//! ```
+
+use crate::client::{ExecuteResult, ExecutionResult};
+use crate::connection::HAConnection;
+use crate::error::{Error, Result};
+use crate::row::{FromRow, FromValue, OwnedRow};
+use crate::statement::Statement;
+use crate::value::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tracing::debug;
+
+/// Future returned by the closure of [`HAConnection::run_transaction`].
+pub type TransactionFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// When a transaction takes its locks, as chosen by its `BEGIN` statement.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionBehavior {
+    /// Take locks on first read or write (`BEGIN`)
+    #[default]
+    Deferred,
+    /// Take the write lock at once (`BEGIN IMMEDIATE`), so the transaction
+    /// cannot fail upgrading from a read to a write
+    Immediate,
+    /// Take the write lock at once and keep other connections from reading
+    /// (`BEGIN EXCLUSIVE`)
+    Exclusive,
+}
+
+impl TransactionBehavior {
+    pub(crate) fn begin_sql(self) -> &'static str {
+        match self {
+            TransactionBehavior::Deferred => "BEGIN",
+            TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
+            TransactionBehavior::Exclusive => "BEGIN EXCLUSIVE",
+        }
+    }
+}
+
+/// Options for [`HAConnection::run_transaction_with`].
+#[derive(Debug, Clone)]
+pub struct TransactionOptions {
+    /// How each attempt begins its transaction
+    pub behavior: TransactionBehavior,
+    /// Times to run the closure again after a busy or unavailable error
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub retry_delay: Duration,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            behavior: TransactionBehavior::default(),
+            max_retries: 3,
+            retry_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+/// An open transaction that rolls back when dropped without being committed.
+pub struct Transaction<'conn> {
+    conn: &'conn HAConnection,
+    finished: bool,
+}
+
+impl<'conn> Transaction<'conn> {
+    pub(crate) fn new(conn: &'conn HAConnection) -> Self {
+        Self {
+            conn,
+            finished: false,
+        }
+    }
+
+    /// Execute a SELECT query.
+    pub async fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.conn.query(sql, params).await
+    }
+
+    /// Execute a SELECT query and map each row to `T` by column name.
+    pub async fn query_as<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
+        self.conn.query_as(sql, params).await
+    }
+
+    /// Execute a SELECT query that must return exactly one row.
+    pub async fn query_one(&self, sql: &str, params: &[Value]) -> Result<OwnedRow> {
+        self.conn.query_one(sql, params).await
+    }
+
+    /// Execute a SELECT query that returns at most one row.
+    pub async fn query_optional(&self, sql: &str, params: &[Value]) -> Result<Option<OwnedRow>> {
+        self.conn.query_optional(sql, params).await
+    }
+
+    /// Execute a SELECT query that must return exactly one row and get its
+    /// first column as `T`.
+    pub async fn query_scalar<T: FromValue>(&self, sql: &str, params: &[Value]) -> Result<T> {
+        self.conn.query_scalar(sql, params).await
+    }
+
+    /// Execute a SELECT query and deserialize each row into `T` with serde.
+    #[cfg(feature = "serde")]
+    pub async fn query_de<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+        params: &[Value],
+    ) -> Result<Vec<T>> {
+        self.conn.query_de(sql, params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement.
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.conn.execute(sql, params).await
+    }
+
+    /// Execute any SQL statement.
+    pub async fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.conn.run(sql, params).await
+    }
+
+    /// Execute several statements in one round trip.
+    pub async fn execute_batch(
+        &self,
+        statements: &[(&str, &[Value])],
+    ) -> Result<Vec<ExecutionResult>> {
+        self.conn.execute_batch(statements).await
+    }
+
+    /// Execute one statement once per parameter set, in one round trip.
+    pub async fn execute_many<I, P>(&self, sql: &str, param_sets: I) -> Result<Vec<ExecuteResult>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[Value]>,
+    {
+        self.conn.execute_many(sql, param_sets).await
+    }
+
+    /// Execute a SELECT query with named parameters.
+    pub async fn query_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecutionResult> {
+        self.conn.query_named(sql, params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with named parameters.
+    pub async fn execute_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecuteResult> {
+        self.conn.execute_named(sql, params).await
+    }
+
+    /// Prepare a statement for repeated execution inside the transaction.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'conn>> {
+        self.conn.prepare(sql)
+    }
+
+    /// Set a savepoint inside the transaction.
+    pub async fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        self.conn.savepoint(name).await
+    }
+
+    /// Get the connection the transaction runs on.
+    pub fn connection(&self) -> &'conn HAConnection {
+        self.conn
+    }
+
+    /// Commit the transaction; it is rolled back if the commit fails.
+    pub async fn commit(mut self) -> Result<()> {
+        let result = self.conn.commit().await;
+        self.finished = result.is_ok();
+        result
+    }
+
+    /// Roll back the transaction.
+    pub async fn rollback(mut self) -> Result<()> {
+        let result = self.conn.rollback().await;
+        self.finished = result.is_ok();
+        result
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            debug!("Rolling back transaction dropped without commit");
+            self.conn.rollback_in_background(None);
+        }
+    }
+}
+
+impl std::fmt::Debug for Transaction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transaction")
+            .field("catalog", &self.conn.catalog())
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+/// A savepoint that is rolled back to when dropped without being released.
+pub struct Savepoint<'a> {
+    conn: &'a HAConnection,
+    name: String,
+    outermost: bool,
+    finished: bool,
+}
+
+impl<'a> Savepoint<'a> {
+    /// Create an unstarted savepoint; dropping it does nothing until
+    /// [`started`](Self::started) is called.
+    pub(crate) fn new(conn: &'a HAConnection, name: &str, outermost: bool) -> Result<Self> {
+        if name.is_empty() {
+            return Err(Error::InvalidParameter(
+                "Savepoint name cannot be empty".to_string(),
+            ));
+        }
+        Ok(Self {
+            conn,
+            name: name.to_string(),
+            outermost,
+            finished: true,
+        })
+    }
+
+    /// Mark the savepoint as set on the server.
+    pub(crate) fn started(mut self) -> Self {
+        self.finished = false;
+        self
+    }
+
+    /// Get the savepoint name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check if this savepoint began the transaction.
+    pub fn is_outermost(&self) -> bool {
+        self.outermost
+    }
+
+    pub(crate) fn quoted_name(&self) -> String {
+        format!("\"{}\"", self.name.replace('"', "\"\""))
+    }
+
+    /// Execute a SELECT query.
+    pub async fn query(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.conn.query(sql, params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement.
+    pub async fn execute(&self, sql: &str, params: &[Value]) -> Result<ExecuteResult> {
+        self.conn.execute(sql, params).await
+    }
+
+    /// Execute any SQL statement.
+    pub async fn run(&self, sql: &str, params: &[Value]) -> Result<ExecutionResult> {
+        self.conn.run(sql, params).await
+    }
+
+    /// Execute a SELECT query with named parameters.
+    pub async fn query_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecutionResult> {
+        self.conn.query_named(sql, params).await
+    }
+
+    /// Execute an INSERT/UPDATE/DELETE statement with named parameters.
+    pub async fn execute_named(
+        &self,
+        sql: &str,
+        params: &[(&str, Value)],
+    ) -> Result<ExecuteResult> {
+        self.conn.execute_named(sql, params).await
+    }
+
+    /// Set a nested savepoint.
+    pub async fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        self.conn.savepoint(name).await
+    }
+
+    /// Get the connection the savepoint was set on.
+    pub fn connection(&self) -> &'a HAConnection {
+        self.conn
+    }
+
+    /// Release the savepoint, keeping its changes; releasing the outermost
+    /// savepoint commits the transaction.
+    pub async fn release(mut self) -> Result<()> {
+        let result = self
+            .conn
+            .execute(&format!("RELEASE {}", self.quoted_name()), &[])
+            .await;
+        self.finish(result).await
+    }
+
+    /// Undo the changes made since the savepoint and release it.
+    pub async fn rollback(mut self) -> Result<()> {
+        let mut result = self
+            .conn
+            .execute(&format!("ROLLBACK TO {}", self.quoted_name()), &[])
+            .await;
+        if result.is_ok() {
+            result = self
+                .conn
+                .execute(&format!("RELEASE {}", self.quoted_name()), &[])
+                .await;
+        }
+        self.finish(result).await
+    }
+
+    async fn finish(&mut self, result: Result<ExecuteResult>) -> Result<()> {
+        result?;
+        self.finished = true;
+        if self.outermost {
+            self.conn.end_transaction().await;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            debug!(
+                "Rolling back to savepoint {} dropped without release",
+                self.name
+            );
+            self.conn.rollback_in_background(Some(self));
+        }
+    }
+}
+
+impl std::fmt::Debug for Savepoint<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Savepoint")
+            .field("name", &self.name)
+            .field("outermost", &self.outermost)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
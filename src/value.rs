@@ -25,6 +25,16 @@ pub enum Value {
     Bytes(Vec<u8>),
     /// Timestamp
     Timestamp(SystemTime),
+    /// JSON document, sent as text
+    ///
+    /// Embedded replicas return text columns declared `JSON` as this variant.
+    /// The server does not report declared types, so JSON read from it
+    /// arrives as [`Value::String`]; both convert to `serde_json::Value`.
+    #[cfg(feature = "json")]
+    Json(serde_json::Value),
+    /// Exact decimal number, sent as text
+    #[cfg(feature = "decimal")]
+    Decimal(rust_decimal::Decimal),
 }
 
 impl Value {
@@ -75,16 +85,7 @@ impl Value {
                     value: buf,
                 }
             }
-            Value::String(v) => {
-                let bytes = v.as_bytes();
-                let mut buf = vec![0x0a];
-                encode_varint_usize(&mut buf, bytes.len());
-                buf.extend_from_slice(bytes);
-                Any {
-                    type_url: "type.googleapis.com/google.protobuf.StringValue".to_string(),
-                    value: buf,
-                }
-            }
+            Value::String(v) => Self::string_any(v),
             Value::Bytes(v) => {
                 let mut buf = vec![0x0a];
                 encode_varint_usize(&mut buf, v.len());
@@ -109,6 +110,21 @@ impl Value {
                     value: buf,
                 }
             }
+            #[cfg(feature = "json")]
+            Value::Json(v) => Self::string_any(&v.to_string()),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(v) => Self::string_any(&v.to_string()),
+        }
+    }
+
+    fn string_any(v: &str) -> Any {
+        let bytes = v.as_bytes();
+        let mut buf = vec![0x0a];
+        encode_varint_usize(&mut buf, bytes.len());
+        buf.extend_from_slice(bytes);
+        Any {
+            type_url: "type.googleapis.com/google.protobuf.StringValue".to_string(),
+            value: buf,
         }
     }
 
@@ -199,7 +215,7 @@ impl Value {
     }
 }
 
-fn encode_varint(buf: &mut Vec<u8>, mut value: i64) {
+fn encode_varint(buf: &mut Vec<u8>, value: i64) {
     let mut v = if value < 0 {
         (value as u64).wrapping_neg().wrapping_neg()
     } else {
@@ -291,3 +307,29 @@ impl From<&[u8]> for Value {
         Value::Bytes(v.to_vec())
     }
 }
+
+impl From<SystemTime> for Value {
+    fn from(v: SystemTime) -> Self {
+        Value::Timestamp(v)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Value> for Value {
+    fn from(v: serde_json::Value) -> Self {
+        Value::Json(v)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl From<rust_decimal::Decimal> for Value {
+    fn from(v: rust_decimal::Decimal) -> Self {
+        Value::Decimal(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
@@ -0,0 +1,165 @@
+//! WebSocket transport.
+//!
+//! Proxies that terminate HTTP/2, or only let HTTP/1.1 and WebSocket
+//! upgrades through, keep gRPC from reaching the server. A `ws://` or
+//! `wss://` server URL instead tunnels the client's HTTP/2 connection
+//! through a WebSocket: every call runs unchanged inside binary WebSocket
+//! messages, to a gateway in front of the HA server that relays them to its
+//! gRPC port.
+//!
+//! The WebSocket is opened at the root of the URL's host, on port 80 or 443
+//! unless the URL has one; the URL's path is the database, as with
+//! `litesql://` URLs. `wss://` checks the server's certificate against the
+//! system's root certificates, so [`TlsOptions`](crate::TlsOptions) do not
+//! apply, and needs the `tls` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use litesql_ha::{HAClient, HAClientOptions};
+//!
+//! # async fn example() -> litesql_ha::Result<()> {
+//! let client = HAClient::new(HAClientOptions {
+//!     url: "wss://gateway.example.com/app.db".to_string(),
+//!     ..Default::default()
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::KeepaliveOptions;
+use crate::error::{Error, Result};
+use crate::runtime::{AsyncRead, AsyncWrite, ReadBuf, TcpStream};
+use futures_sink::Sink;
+use hyper_util::rt::TokioIo;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio_stream::Stream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tonic::codegen::http::Uri;
+use tonic::transport::{Channel, Endpoint};
+use url::Url;
+
+/// Check if `uri` is reached through a WebSocket.
+pub(crate) fn is_websocket(uri: &Uri) -> bool {
+    matches!(uri.scheme_str(), Some("ws" | "wss"))
+}
+
+/// Build the endpoint of the server behind the WebSocket gateway at `url`.
+///
+/// The endpoint dials the gateway, while the calls it carries are addressed
+/// to the server as plain HTTP/2.
+pub(crate) fn endpoint(url: &Url, tls: bool, keepalive: &KeepaliveOptions) -> Result<Endpoint> {
+    let secure = tls || url.scheme() == "wss";
+    #[cfg(not(feature = "tls"))]
+    if secure {
+        return Err(Error::InvalidParameter(
+            "wss:// URLs require the `tls` feature".to_string(),
+        ));
+    }
+    let host = url.host_str().unwrap_or("localhost");
+    let port = url.port().unwrap_or(if secure { 443 } else { 80 });
+    let scheme = if secure { "wss" } else { "ws" };
+
+    let origin = Uri::try_from(format!("http://{}:{}", host, port))
+        .map_err(|e| Error::InvalidParameter(e.to_string()))?;
+    let mut endpoint = Endpoint::from_shared(format!("{}://{}:{}/", scheme, host, port))?
+        .origin(origin)
+        .keep_alive_timeout(keepalive.timeout)
+        .keep_alive_while_idle(keepalive.while_idle);
+    if let Some(interval) = keepalive.interval {
+        endpoint = endpoint.http2_keep_alive_interval(interval);
+    }
+    Ok(endpoint)
+}
+
+/// Open a channel through the WebSocket of `endpoint`, dialled on its first
+/// call.
+pub(crate) fn connect_lazy(endpoint: &Endpoint) -> Channel {
+    endpoint.connect_with_connector_lazy(tower::service_fn(dial))
+}
+
+/// Open a channel through the WebSocket of `endpoint`.
+pub(crate) async fn connect(endpoint: &Endpoint) -> Result<Channel> {
+    Ok(endpoint
+        .connect_with_connector(tower::service_fn(dial))
+        .await?)
+}
+
+/// Open the WebSocket at `uri`.
+async fn dial(uri: Uri) -> io::Result<TokioIo<Tunnel>> {
+    let (ws, _) = tokio_tungstenite::connect_async(uri.to_string())
+        .await
+        .map_err(io::Error::other)?;
+    Ok(TokioIo::new(Tunnel {
+        ws,
+        read: Vec::new(),
+        read_pos: 0,
+    }))
+}
+
+/// A WebSocket carrying a byte stream in binary messages.
+struct Tunnel {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The last message received, read up to `read_pos`
+    read: Vec<u8>,
+    read_pos: usize,
+}
+
+impl AsyncRead for Tunnel {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read_pos < this.read.len() {
+                let n = buf.remaining().min(this.read.len() - this.read_pos);
+                buf.put_slice(&this.read[this.read_pos..this.read_pos + n]);
+                this.read_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            // Pings are answered by tungstenite itself.
+            match ready!(Pin::new(&mut this.ws).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => {
+                    this.read = data;
+                    this.read_pos = 0;
+                }
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ws = &mut self.get_mut().ws;
+        ready!(Pin::new(&mut *ws).poll_ready(cx)).map_err(io::Error::other)?;
+        Pin::new(ws)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(io::Error::other)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().ws)
+            .poll_flush(cx)
+            .map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().ws)
+            .poll_close(cx)
+            .map_err(io::Error::other)
+    }
+}
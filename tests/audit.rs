@@ -0,0 +1,96 @@
+mod common;
+
+use litesql_ha::audit::redact_all;
+use litesql_ha::{AuditEvent, Auditor, HAConnection, HAConnectionOptions, Result, Value};
+use std::sync::{Arc, Mutex};
+
+/// Connect with an auditor collecting its events.
+async fn connect(
+    server: &litesql_ha::test_util::MockServer,
+    masked: bool,
+) -> Result<(HAConnection, Arc<Mutex<Vec<AuditEvent>>>)> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let auditor = Auditor::new(move |event: &AuditEvent| sink.lock().unwrap().push(event.clone()));
+    let auditor = if masked {
+        auditor.with_masker(redact_all)
+    } else {
+        auditor
+    };
+    let conn = HAConnection::new(HAConnectionOptions {
+        auditor: Some(auditor),
+        ..common::options(server)
+    })
+    .await?;
+    Ok((conn, events))
+}
+
+#[tokio::test]
+async fn masked_values_never_reach_the_audit_record() -> Result<()> {
+    let server = common::start().await?;
+    let (conn, events) = connect(&server, true).await?;
+
+    conn.execute(
+        "INSERT INTO users (name) VALUES (?)",
+        &["alice@example.com".into()],
+    )
+    .await?;
+    conn.execute_named(
+        "INSERT INTO users (id, name) VALUES (:id, :name)",
+        &[("id", 42.into()), ("name", "bob@example.com".into())],
+    )
+    .await?;
+    conn.execute(
+        "INSERT INTO users (id, name) VALUES (4242, 'carol@example.com')",
+        &[],
+    )
+    .await?;
+    let count = conn
+        .query(
+            "SELECT count(*) FROM users WHERE name <> 'dave@example.com'",
+            &[],
+        )
+        .await?;
+    assert_eq!(count.rows[0][0], Value::Int64(3));
+
+    let events = events.lock().unwrap().clone();
+    let sql: Vec<&str> = events.iter().map(|e| e.sql.as_str()).collect();
+    assert_eq!(
+        sql,
+        [
+            "INSERT INTO users (name) VALUES (?)",
+            "INSERT INTO users (id, name) VALUES (:id, :name)",
+            "INSERT INTO users (id, name) VALUES (?, ?)",
+            "SELECT count(*) FROM users WHERE name <> ?",
+        ]
+    );
+    assert_eq!(events[0].parameters, [Value::String("***".into())]);
+    assert_eq!(
+        events[1].parameters,
+        [Value::String("***".into()), Value::String("***".into())]
+    );
+    for event in &events {
+        let record = format!("{:?}", event);
+        assert!(!record.contains("example.com"), "{}", record);
+    }
+
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn unmasked_statements_are_recorded_as_written() -> Result<()> {
+    let server = common::start().await?;
+    let (conn, events) = connect(&server, false).await?;
+
+    let sql = "INSERT INTO users (id, name) VALUES (?, 'alice@example.com')";
+    conn.execute(sql, &[7i64.into()]).await?;
+
+    let events = events.lock().unwrap().clone();
+    assert_eq!(events[0].sql, sql);
+    assert_eq!(events[0].parameters, [Value::Int64(7)]);
+    assert!(events[0].outcome.is_success());
+
+    server.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses some of the helpers
+#![allow(dead_code)]
+
+use litesql_ha::test_util::MockServer;
+use litesql_ha::{HAConnection, HAConnectionOptions, Result};
+
+/// Options connecting to `server`.
+pub fn options(server: &MockServer) -> HAConnectionOptions {
+    HAConnectionOptions {
+        url: server.url(),
+        timeout: 30,
+        ..Default::default()
+    }
+}
+
+/// Start a server with an empty `users` table.
+pub async fn start() -> Result<MockServer> {
+    let server = MockServer::start().await?;
+    server.execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")?;
+    Ok(server)
+}
+
+/// Connect to `server` with the default options.
+pub async fn connect(server: &MockServer) -> Result<HAConnection> {
+    HAConnection::new(options(server)).await
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use litesql_ha::export::ExportFormat;
+use litesql_ha::import::{csv_to_table, CsvImportOptions};
+use litesql_ha::{HAConnection, Result, Value};
+
+async fn users(conn: &HAConnection) -> Result<Vec<Vec<Value>>> {
+    Ok(conn
+        .query("SELECT id, name FROM users ORDER BY id", &[])
+        .await?
+        .rows)
+}
+
+#[tokio::test]
+async fn quoted_fields_empty_strings_and_nulls() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let csv = "id,name\n1,\"Smith, \"\"Al\"\"\nJr.\"\n2,\n3,\"\"\n4,\\N\n5,\"\\N\"\n";
+    let options = CsvImportOptions {
+        null: Some("\\N".to_string()),
+        ..CsvImportOptions::new("users")
+    };
+    let progress = csv_to_table(&conn, csv.as_bytes(), &options).await?;
+    assert_eq!(progress.rows, 5);
+    assert_eq!(
+        users(&conn).await?,
+        [
+            vec![Value::Int64(1), "Smith, \"Al\"\nJr.".into()],
+            vec![Value::Int64(2), "".into()],
+            vec![Value::Int64(3), "".into()],
+            vec![Value::Int64(4), Value::Null],
+            vec![Value::Int64(5), "\\N".into()],
+        ]
+    );
+
+    // An empty NULL marker tells empty fields from quoted empty strings
+    let csv = "id,name\n6,\n7,\"\"\n";
+    let options = CsvImportOptions {
+        null: Some(String::new()),
+        ..CsvImportOptions::new("users")
+    };
+    csv_to_table(&conn, csv.as_bytes(), &options).await?;
+    let rows = users(&conn).await?;
+    assert_eq!(rows[5], [Value::Int64(6), Value::Null]);
+    assert_eq!(rows[6], [Value::Int64(7), "".into()]);
+
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_must_match_the_columns() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    // Batches before the bad record stay committed
+    let csv = "id,name\n1,alice\n2,bob,extra\n3,carol\n";
+    let options = CsvImportOptions {
+        batch_size: 1,
+        ..CsvImportOptions::new("users")
+    };
+    let error = csv_to_table(&conn, csv.as_bytes(), &options)
+        .await
+        .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("line 3 has 3 fields, expected 2"),
+        "{}",
+        error
+    );
+    assert_eq!(users(&conn).await?, [vec![Value::Int64(1), "alice".into()]]);
+
+    // Columns given without a header; dave's batch is not committed
+    let options = CsvImportOptions {
+        columns: vec!["name".to_string()],
+        has_header: false,
+        ..CsvImportOptions::new("users")
+    };
+    let error = csv_to_table(&conn, "dave\n4,erin\n".as_bytes(), &options)
+        .await
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("line 2 has 2 fields, expected 1"));
+
+    // Columns given override the header, which must still be there
+    let options = CsvImportOptions {
+        columns: vec!["name".to_string()],
+        ..CsvImportOptions::new("users")
+    };
+    csv_to_table(&conn, "ignored\nfrank\n".as_bytes(), &options).await?;
+    assert_eq!(
+        users(&conn).await?.last(),
+        Some(&vec![Value::Int64(2), "frank".into()])
+    );
+    assert!(
+        csv_to_table(&conn, "".as_bytes(), &CsvImportOptions::new("users"))
+            .await
+            .is_err()
+    );
+
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn exported_csv_imports_the_same_rows() -> Result<()> {
+    let server = common::start().await?;
+    server.execute_batch(
+        "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT, data BLOB);
+         CREATE TABLE copy (id INTEGER PRIMARY KEY, body TEXT, data BLOB);",
+    )?;
+    let conn = common::connect(&server).await?;
+    let bodies = [
+        Value::String("plain".into()),
+        Value::String("a,b".into()),
+        Value::String("say \"hi\"".into()),
+        Value::String("line 1\nline 2".into()),
+        Value::String("crlf\r\n".into()),
+        Value::String(String::new()),
+        Value::Null,
+    ];
+    for body in &bodies {
+        conn.execute(
+            "INSERT INTO notes (body, data) VALUES (?, x'00ff')",
+            std::slice::from_ref(body),
+        )
+        .await?;
+    }
+
+    let mut csv = Vec::new();
+    let rows = conn
+        .query_export("SELECT * FROM notes", &[], ExportFormat::Csv, &mut csv)
+        .await?;
+    assert_eq!(rows, bodies.len() as u64);
+
+    // NULL is exported as an empty field, the empty string as `""`
+    let options = CsvImportOptions {
+        null: Some(String::new()),
+        ..CsvImportOptions::new("copy")
+    };
+    csv_to_table(&conn, csv.as_slice(), &options).await?;
+    let copied = conn
+        .query("SELECT body, data FROM copy ORDER BY id", &[])
+        .await?;
+    let copied: Vec<(Value, Value)> = copied
+        .rows
+        .into_iter()
+        .map(|row| (row[0].clone(), row[1].clone()))
+        .collect();
+    // Blobs come back as their hex text
+    let expected: Vec<(Value, Value)> = bodies
+        .into_iter()
+        .map(|body| (body, Value::String("00ff".into())))
+        .collect();
+    assert_eq!(copied, expected);
+
+    server.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use diesel::sql_types::{BigInt, Text};
+use diesel::QueryableByName;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use litesql_ha::diesel_async::HAAsyncDieselConnection;
+
+#[derive(QueryableByName)]
+struct User {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+async fn count(conn: &mut HAAsyncDieselConnection) -> diesel::QueryResult<i64> {
+    let count: Count = diesel::sql_query("SELECT count(*) AS count FROM users")
+        .get_result(conn)
+        .await?;
+    Ok(count.count)
+}
+
+#[tokio::test]
+async fn statements_run_on_the_server() -> Result<(), Box<dyn std::error::Error>> {
+    let server = common::start().await?;
+    let mut conn = HAAsyncDieselConnection::from_connection(common::connect(&server).await?);
+
+    let inserted = diesel::sql_query("INSERT INTO users (name) VALUES (?)")
+        .bind::<Text, _>("alice")
+        .execute(&mut conn)
+        .await?;
+    assert_eq!(inserted, 1);
+
+    let users: Vec<User> = diesel::sql_query("SELECT name FROM users")
+        .load(&mut conn)
+        .await?;
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].name, "alice");
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_transaction_rolls_back() -> Result<(), Box<dyn std::error::Error>> {
+    let server = common::start().await?;
+    let mut conn = HAAsyncDieselConnection::from_connection(common::connect(&server).await?);
+
+    let result = conn
+        .transaction::<(), diesel::result::Error, _>(async |conn| {
+            diesel::sql_query("INSERT INTO users (name) VALUES ('bob')")
+                .execute(conn)
+                .await?;
+            assert_eq!(count(conn).await?, 1);
+            Err(diesel::result::Error::RollbackTransaction)
+        })
+        .await;
+    assert!(result.is_err());
+    assert!(conn.inner().auto_commit());
+    assert_eq!(count(&mut conn).await?, 0);
+    assert!(server.queries().iter().any(|sql| sql == "ROLLBACK"));
+    server.shutdown().await;
+    Ok(())
+}
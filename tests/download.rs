@@ -0,0 +1,108 @@
+mod common;
+
+use litesql_ha::download::DownloadOptions;
+use litesql_ha::embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
+use litesql_ha::test_util::{MockServer, DEFAULT_DATABASE};
+use litesql_ha::{Error, HAClient, HAClientOptions, HAConnection, HAConnectionOptions};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Count the users of the replica downloaded into `directory`.
+fn count_users(directory: &Path) -> Result<i64> {
+    let conn = rusqlite::Connection::open(directory.join(DEFAULT_DATABASE))?;
+    Ok(conn.query_row("SELECT count(*) FROM users", [], |row| row.get(0))?)
+}
+
+async fn client(url: String) -> Result<HAClient> {
+    Ok(HAClient::new(HAClientOptions {
+        url,
+        ..Default::default()
+    })
+    .await?)
+}
+
+#[tokio::test]
+async fn corrupt_chunk_is_fetched_again() -> Result<()> {
+    let server = common::start().await?;
+    server.execute_batch("INSERT INTO users (name) VALUES ('alice'), ('bob')")?;
+    let client = client(server.url()).await?;
+    let directory = tempfile::tempdir()?;
+
+    server.corrupt_downloads(1);
+    client
+        .download_replica(directory.path(), DEFAULT_DATABASE, false)
+        .await?;
+    assert_eq!(count_users(directory.path())?, 2);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn download_resumes_after_checksum_mismatch() -> Result<()> {
+    let server = common::start().await?;
+    server.execute_batch(
+        "INSERT INTO users (name)
+         WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+         SELECT printf('user %d', i) FROM n",
+    )?;
+    let client = client(server.url()).await?;
+    let directory = tempfile::tempdir()?;
+    let options = DownloadOptions::new().with_chunk_size(8 * 1024);
+
+    server.corrupt_downloads(usize::MAX);
+    let result = client
+        .download_replica_with(directory.path(), DEFAULT_DATABASE, false, &options)
+        .await;
+    assert!(matches!(result, Err(Error::ChecksumMismatch(_))));
+    assert!(!directory.path().join(DEFAULT_DATABASE).exists());
+
+    server.corrupt_downloads(0);
+    client
+        .download_replica_with(directory.path(), DEFAULT_DATABASE, false, &options)
+        .await?;
+    assert_eq!(count_users(directory.path())?, 2000);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn set_catalog_downloads_the_replica_in_the_background() -> Result<()> {
+    let server = MockServer::with_databases(&[DEFAULT_DATABASE, "other"]).await?;
+    let directory = tempfile::tempdir()?;
+    let manager = Arc::new(EmbeddedReplicasManager::new());
+    manager.set_download_client(Arc::new(client(server.url()).await?));
+    manager
+        .load(ReplicaOptions {
+            directory: directory.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await?;
+    let conn = HAConnection::new(HAConnectionOptions {
+        url: server.url(),
+        embedded_replicas_dir: Some(directory.path().display().to_string()),
+        // Without a NATS URL the replicas are not replicated
+        replication_url: Some(String::new()),
+        download_replica_on_switch: true,
+        replicas_manager: Some(manager.clone()),
+        ..Default::default()
+    })
+    .await?;
+
+    conn.set_catalog("other")?;
+    assert_eq!(conn.catalog(), "other");
+    for _ in 0..100 {
+        if manager.get_replica("other").is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(manager.get_replica("other").is_some());
+    assert!(manager.get_replica(DEFAULT_DATABASE).is_none());
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use litesql_ha::migrations::{Migration, Migrator, MIGRATIONS_TABLE};
+use litesql_ha::{Error, HAConnection, Result, Value};
+
+fn migrations() -> Vec<Migration> {
+    // Given out of order; each depends on the one before
+    vec![
+        Migration::new(3, "seed_posts", "INSERT INTO posts (user_id, title) VALUES (1, 'hi')"),
+        Migration::new(
+            1,
+            "create_accounts",
+            "CREATE TABLE accounts (id INTEGER PRIMARY KEY, name TEXT); \
+             INSERT INTO accounts (name) VALUES ('alice')",
+        )
+        .with_down("DROP TABLE accounts"),
+        Migration::new(
+            2,
+            "create_posts",
+            "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER REFERENCES accounts, title TEXT)",
+        )
+        .with_down("DROP TABLE posts"),
+    ]
+}
+
+async fn count(conn: &HAConnection, table: &str) -> Result<i64> {
+    let result = conn
+        .query(&format!("SELECT count(*) FROM {}", table), &[])
+        .await?;
+    match result.rows.first().and_then(|row| row.first()) {
+        Some(Value::Int64(n)) => Ok(*n),
+        other => Err(Error::TypeConversion(format!("not a count: {:?}", other))),
+    }
+}
+
+async fn versions(migrator: &Migrator, conn: &HAConnection) -> Result<Vec<i64>> {
+    Ok(migrator
+        .applied(conn)
+        .await?
+        .iter()
+        .map(|applied| applied.version)
+        .collect())
+}
+
+#[tokio::test]
+async fn migrations_run_in_version_order() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let migrator = Migrator::new(migrations());
+    let versions_in_order: Vec<i64> = migrator.migrations().iter().map(|m| m.version()).collect();
+    assert_eq!(versions_in_order, [1, 2, 3]);
+
+    let report = migrator.run(&conn).await?;
+    assert_eq!(report.applied, [1, 2, 3]);
+    assert!(!report.dry_run);
+    assert_eq!(versions(&migrator, &conn).await?, [1, 2, 3]);
+    assert_eq!(count(&conn, "posts").await?, 1);
+
+    let applied = migrator.applied(&conn).await?;
+    for (applied, migration) in applied.iter().zip(migrator.migrations()) {
+        assert_eq!(applied.name, migration.name());
+        assert_eq!(applied.checksum, migration.checksum());
+    }
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn rerunning_applies_only_new_migrations() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let migrator = Migrator::new(migrations());
+    migrator.run(&conn).await?;
+    let report = migrator.run(&conn).await?;
+    assert!(report.applied.is_empty(), "{:?}", report);
+    assert_eq!(count(&conn, "accounts").await?, 1);
+    assert_eq!(count(&conn, "posts").await?, 1);
+
+    let mut more = migrations();
+    more.push(Migration::new(
+        4,
+        "seed_accounts",
+        "INSERT INTO accounts (name) VALUES ('bob')",
+    ));
+    let migrator = Migrator::new(more);
+    assert_eq!(migrator.run(&conn).await?.applied, [4]);
+    assert!(migrator.run(&conn).await?.applied.is_empty());
+    assert_eq!(count(&conn, "accounts").await?, 2);
+    assert_eq!(versions(&migrator, &conn).await?, [1, 2, 3, 4]);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn modified_migrations_are_rejected() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+    Migrator::new(migrations().into_iter().filter(|m| m.version() < 3))
+        .run(&conn)
+        .await?;
+
+    // Version 2 edited after it was applied, and version 3 still pending
+    let mut edited = migrations();
+    edited[2] = Migration::new(
+        2,
+        "create_posts",
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT)",
+    );
+    let migrator = Migrator::new(edited);
+    for result in [
+        migrator.run(&conn).await.map(|_| ()),
+        migrator.pending(&conn).await.map(|_| ()),
+        migrator.undo(&conn, 0).await.map(|_| ()),
+    ] {
+        match result {
+            Err(Error::Migration(message)) => {
+                assert!(message.contains("migration 2 (create_posts)"), "{message}");
+                assert!(message.contains("modified"), "{message}");
+            }
+            other => panic!("expected a checksum error, got {:?}", other),
+        }
+    }
+    assert_eq!(count(&conn, "posts").await?, 0);
+    assert_eq!(versions(&migrator, &conn).await?, [1, 2]);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_migrations_are_not_recorded() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let mut broken = migrations();
+    broken[0] = Migration::new(3, "seed_posts", "INSERT INTO missing VALUES (1)");
+    let migrator = Migrator::new(broken);
+    match migrator.run(&conn).await {
+        Err(Error::Migration(message)) => assert!(message.contains("migration 3"), "{message}"),
+        other => panic!("expected a failed migration, got {:?}", other),
+    }
+    assert_eq!(versions(&migrator, &conn).await?, [1, 2]);
+    assert!(conn.auto_commit());
+
+    let migrator = Migrator::new(migrations());
+    assert_eq!(migrator.run(&conn).await?.applied, [3]);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn dry_runs_and_undo() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let dry = Migrator::new(migrations()).with_dry_run(true);
+    let report = dry.run(&conn).await?;
+    assert_eq!(report.applied, [1, 2, 3]);
+    assert!(report.dry_run);
+    assert!(dry.applied(&conn).await?.is_empty());
+    assert!(!server
+        .queries()
+        .iter()
+        .any(|sql| sql.contains(MIGRATIONS_TABLE) && sql.starts_with("CREATE")));
+
+    let migrator = Migrator::new(migrations().into_iter().filter(|m| m.version() < 3));
+    migrator.run(&conn).await?;
+    assert_eq!(migrator.undo(&conn, 1).await?.reverted, [2]);
+    assert_eq!(versions(&migrator, &conn).await?, [1]);
+    assert!(conn.query("SELECT * FROM posts", &[]).await.is_err());
+    assert_eq!(migrator.undo(&conn, 0).await?.reverted, [1]);
+    assert!(migrator.applied(&conn).await?.is_empty());
+    server.shutdown().await;
+    Ok(())
+}
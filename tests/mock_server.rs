@@ -0,0 +1,49 @@
+mod common;
+
+use litesql_ha::test_util::{Failure, DEFAULT_DATABASE};
+use litesql_ha::{Result, Value};
+
+#[tokio::test]
+async fn queries_run_against_the_database() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    conn.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+        .await?;
+    let result = conn.query("SELECT name FROM users", &[]).await?;
+    assert_eq!(result.rows, vec![vec![Value::String("alice".into())]]);
+    assert_eq!(
+        server.queries(),
+        [
+            "INSERT INTO users (name) VALUES (?)",
+            "SELECT name FROM users"
+        ]
+    );
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn writes_advance_the_txseq() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let before = server.txseq(DEFAULT_DATABASE).unwrap_or_default();
+    conn.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+        .await?;
+    assert!(server.txseq(DEFAULT_DATABASE).unwrap_or_default() > before);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn injected_failure_fails_only_the_next_query() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    server.fail_next(Failure::Error("disk I/O error".into()));
+    assert!(conn.query("SELECT 1", &[]).await.is_err());
+    conn.query("SELECT 1", &[]).await?;
+    server.shutdown().await;
+    Ok(())
+}
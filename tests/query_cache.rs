@@ -0,0 +1,105 @@
+mod common;
+
+use litesql_ha::{
+    Consistency, Error, HAConnection, HAConnectionOptions, QueryCache, QueryCacheOptions,
+    QueryOptions, Result, Value,
+};
+use std::sync::Arc;
+
+const COUNT: &str = "SELECT count(*) FROM users";
+
+async fn count(conn: &HAConnection) -> Result<i64> {
+    let result = conn.query(COUNT, &[]).await?;
+    match result.rows.first().and_then(|row| row.first()) {
+        Some(Value::Int64(n)) => Ok(*n),
+        other => Err(Error::TypeConversion(format!("not a count: {:?}", other))),
+    }
+}
+
+async fn insert(conn: &HAConnection, name: &str) -> Result<()> {
+    conn.execute("INSERT INTO users (name) VALUES (?)", &[name.into()])
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_invalidates_cached_reads() -> Result<()> {
+    let server = common::start().await?;
+    let cache = Arc::new(QueryCache::new(QueryCacheOptions::default()));
+    let options = HAConnectionOptions {
+        query_cache: Some(cache.clone()),
+        ..common::options(&server)
+    };
+    let reader = HAConnection::new(options.clone()).await?;
+    let writer = HAConnection::new(options).await?;
+    let sent = || server.queries().iter().filter(|sql| *sql == COUNT).count();
+
+    // Results read before any transaction carry no txseq to check them by
+    insert(&writer, "alice").await?;
+    assert_eq!(count(&reader).await?, 1);
+    assert_eq!(count(&reader).await?, 1);
+    assert_eq!(sent(), 1);
+    assert_eq!(cache.snapshot().hits, 1);
+
+    // The write advances the txseq past the cached result
+    insert(&writer, "bob").await?;
+    assert_eq!(count(&reader).await?, 2);
+    assert_eq!(sent(), 2);
+    assert!(cache.snapshot().invalidations >= 1);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_in_a_transaction_are_not_cached() -> Result<()> {
+    let server = common::start().await?;
+    let conn = HAConnection::new(HAConnectionOptions {
+        query_cache: Some(Arc::new(QueryCache::new(QueryCacheOptions::default()))),
+        ..common::options(&server)
+    })
+    .await?;
+
+    conn.begin_transaction().await?;
+    conn.query(COUNT, &[]).await?;
+    conn.query(COUNT, &[]).await?;
+    conn.commit().await?;
+    let sent = server.queries().iter().filter(|sql| *sql == COUNT).count();
+    assert_eq!(sent, 2);
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn eventual_reads_from_a_lagging_follower_are_not_cached() -> Result<()> {
+    let leader = common::start().await?;
+    // The follower never sees the writes made on the leader
+    let follower = common::start().await?;
+    let cache = Arc::new(QueryCache::new(QueryCacheOptions::default()));
+    let conn = HAConnection::new(HAConnectionOptions {
+        query_cache: Some(cache.clone()),
+        read_endpoints: vec![follower.url()],
+        ..common::options(&leader)
+    })
+    .await?;
+    insert(&conn, "alice").await?;
+
+    let eventual = QueryOptions::default().with_consistency(Consistency::Eventual);
+    for _ in 0..2 {
+        let result = conn.query_with(COUNT, &[], &eventual).await?;
+        assert_eq!(result.into_scalar::<i64>()?, 0);
+    }
+    let sent = follower
+        .queries()
+        .iter()
+        .filter(|sql| *sql == COUNT)
+        .count();
+    assert_eq!(sent, 2);
+    let strong = QueryOptions::default().with_consistency(Consistency::Strong);
+    let result = conn.query_with(COUNT, &[], &strong).await?;
+    assert_eq!(result.into_scalar::<i64>()?, 1);
+    assert_eq!(cache.snapshot().hits, 0);
+
+    leader.shutdown().await;
+    follower.shutdown().await;
+    Ok(())
+}
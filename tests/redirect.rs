@@ -0,0 +1,59 @@
+mod common;
+
+use litesql_ha::test_util::{Failure, MockServer};
+use litesql_ha::Result;
+
+const INSERT: &str = "INSERT INTO users (name) VALUES (?)";
+
+#[tokio::test]
+async fn write_refused_by_a_follower_is_retried_on_the_leader() -> Result<()> {
+    let leader = common::start().await?;
+    let follower = common::start().await?;
+    let conn = common::connect(&follower).await?;
+
+    let inserts =
+        |server: &MockServer| server.queries().iter().filter(|sql| *sql == INSERT).count();
+
+    follower.fail_next(Failure::LeaderRedirect(leader.addr().to_string()));
+    conn.execute(INSERT, &["alice".into()]).await?;
+    assert_eq!(inserts(&follower), 1);
+    assert_eq!(inserts(&leader), 1);
+
+    // Later statements go straight to the leader
+    conn.execute(INSERT, &["bob".into()]).await?;
+    let count: i64 = conn.query_scalar("SELECT count(*) FROM users", &[]).await?;
+    assert_eq!(count, 2);
+    assert_eq!(inserts(&follower), 1);
+    leader.shutdown().await;
+    follower.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn leader_named_only_in_the_message_ends_at_the_address() -> Result<()> {
+    let leader = common::start().await?;
+    let follower = common::start().await?;
+    let conn = common::connect(&follower).await?;
+
+    let inserts =
+        |server: &MockServer| server.queries().iter().filter(|sql| *sql == INSERT).count();
+
+    follower.fail_next(Failure::LeaderMessage(format!(
+        "{} (term 7)",
+        leader.addr()
+    )));
+    conn.execute(INSERT, &["alice".into()]).await?;
+    assert_eq!(inserts(&leader), 1);
+
+    // Text that is no address is not followed, and the follower is kept
+    let conn = common::connect(&follower).await?;
+    follower.fail_next(Failure::LeaderMessage("unknown (election)".to_string()));
+    assert!(conn.execute(INSERT, &["bob".into()]).await.is_err());
+    conn.execute(INSERT, &["carol".into()]).await?;
+    // alice's and bob's refused inserts, and carol's
+    assert_eq!(inserts(&follower), 3);
+    assert_eq!(inserts(&leader), 1);
+    leader.shutdown().await;
+    follower.shutdown().await;
+    Ok(())
+}
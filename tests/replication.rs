@@ -0,0 +1,65 @@
+mod common;
+
+use litesql_ha::embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
+use litesql_ha::test_util::DEFAULT_DATABASE;
+use litesql_ha::{HAClient, HAClientOptions};
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// A change set inserting user `id`.
+fn insert(id: i64, name: &str) -> Vec<u8> {
+    format!(
+        r#"{{"filename": "{}", "changes": [{{"operation": "INSERT", "table": "users",
+            "columns": ["id", "name"], "new_rowid": {}, "new_values": [{}, "{}"]}}]}}"#,
+        DEFAULT_DATABASE, id, id, name
+    )
+    .into_bytes()
+}
+
+#[tokio::test]
+async fn messages_are_applied_once() -> Result<()> {
+    let server = common::start().await?;
+    server.execute_batch("INSERT INTO users (id, name) VALUES (1, 'alice')")?;
+    let directory = tempfile::tempdir()?;
+    let manager = EmbeddedReplicasManager::new();
+    manager.set_download_client(Arc::new(
+        HAClient::new(HAClientOptions {
+            url: server.url(),
+            ..Default::default()
+        })
+        .await?,
+    ));
+    manager
+        .load(ReplicaOptions {
+            directory: directory.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await?;
+    let replica = manager.download_replica(DEFAULT_DATABASE).await?;
+    let next = replica.get_txseq() as u64 + 1;
+
+    assert!(manager.apply_message(&insert(2, "bob"), next).await?);
+    assert_eq!(replica.get_txseq(), next as i64);
+
+    // A redelivered message, or one from before the replica, is skipped
+    assert!(!manager.apply_message(&insert(2, "bob"), next).await?);
+    assert!(!manager.apply_message(&insert(3, "carol"), next - 1).await?);
+    assert_eq!(replica.get_txseq(), next as i64);
+
+    assert!(manager.apply_message(&insert(4, "dave"), next + 1).await?);
+    let names: Vec<String> = {
+        let conn = replica.create_connection()?;
+        let mut stmt = conn.prepare("SELECT name FROM users ORDER BY id")?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+        names
+    };
+    assert_eq!(names, ["alice", "bob", "dave"]);
+    assert_eq!(replica.get_txseq(), next as i64 + 1);
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
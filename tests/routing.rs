@@ -0,0 +1,77 @@
+mod common;
+
+use litesql_ha::embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
+use litesql_ha::test_util::DEFAULT_DATABASE;
+use litesql_ha::{
+    Consistency, HAClient, HAClientOptions, HAConnection, HAConnectionOptions, QueryOptions, Route,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const COUNT: &str = "SELECT count(*) FROM users";
+
+fn bounded(bound: Duration) -> QueryOptions {
+    QueryOptions::default().with_consistency(Consistency::BoundedStaleness(bound))
+}
+
+/// Read the user count with `options`, returning it and where it was read.
+async fn count(conn: &HAConnection, options: &QueryOptions) -> Result<(i64, Route)> {
+    let result = conn.query_with(COUNT, &[], options).await?;
+    let route = result.routing.ok_or("no routing decision")?.route;
+    Ok((result.into_scalar()?, route))
+}
+
+#[tokio::test]
+async fn stale_replica_serves_reads_within_the_bound() -> Result<()> {
+    let server = common::start().await?;
+    let directory = tempfile::tempdir()?;
+    let manager = Arc::new(EmbeddedReplicasManager::new());
+    manager.set_download_client(Arc::new(
+        HAClient::new(HAClientOptions {
+            url: server.url(),
+            ..Default::default()
+        })
+        .await?,
+    ));
+    manager
+        .load(ReplicaOptions {
+            directory: directory.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await?;
+    manager.download_replica(DEFAULT_DATABASE).await?;
+    let conn = HAConnection::new(HAConnectionOptions {
+        embedded_replicas_dir: Some(directory.path().display().to_string()),
+        // Without a NATS URL the replica is not replicated
+        replication_url: Some(String::new()),
+        replicas_manager: Some(manager.clone()),
+        ..common::options(&server)
+    })
+    .await?;
+
+    assert_eq!(
+        count(&conn, &bounded(Duration::ZERO)).await?,
+        (0, Route::Replica)
+    );
+
+    // The replica misses the write from now on
+    conn.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+        .await?;
+    let long = bounded(Duration::from_secs(60));
+    assert_eq!(count(&conn, &long).await?, (0, Route::Replica));
+    assert_eq!(
+        count(&conn, &bounded(Duration::ZERO)).await?,
+        (1, Route::Primary)
+    );
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let short = bounded(Duration::from_millis(50));
+    assert_eq!(count(&conn, &short).await?, (1, Route::Primary));
+    assert_eq!(count(&conn, &long).await?, (0, Route::Replica));
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
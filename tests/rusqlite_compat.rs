@@ -0,0 +1,261 @@
+//! The rusqlite-style API gives the results rusqlite gives on the same
+//! statements.
+
+use litesql_ha::rusqlite_compat::{
+    named_params, params, params_from_iter, Connection, OptionalExtension,
+};
+use litesql_ha::test_util::MockServer;
+use litesql_ha::Error;
+use tokio::runtime::Runtime;
+
+const SCHEMA: &str =
+    "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT, qty INTEGER, price REAL, data BLOB)";
+
+type Item = (
+    i64,
+    Option<String>,
+    Option<i64>,
+    Option<f64>,
+    Option<Vec<u8>>,
+);
+
+/// A server, a connection to it and an in-memory rusqlite database, both
+/// with the `items` table.
+struct Both {
+    conn: Connection,
+    sqlite: rusqlite::Connection,
+    _server: MockServer,
+    _runtime: Runtime,
+}
+
+fn start() -> Both {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start()).unwrap();
+    let conn = Connection::open(&server.url()).unwrap();
+    conn.execute_batch(SCHEMA).unwrap();
+    let sqlite = rusqlite::Connection::open_in_memory().unwrap();
+    sqlite.execute_batch(SCHEMA).unwrap();
+    Both {
+        conn,
+        sqlite,
+        _server: server,
+        _runtime: runtime,
+    }
+}
+
+impl Both {
+    /// Get the rows of `items` from both databases.
+    fn items(&self) -> (Vec<Item>, Vec<Item>) {
+        let sql = "SELECT id, name, qty, price, data FROM items ORDER BY id";
+        let ours = self
+            .conn
+            .prepare(sql)
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<litesql_ha::Result<Vec<Item>>>()
+            .unwrap();
+        let theirs = self
+            .sqlite
+            .prepare(sql)
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<Item>>>()
+            .unwrap();
+        (ours, theirs)
+    }
+}
+
+#[test]
+fn parameters_bind_as_with_rusqlite() {
+    let both = start();
+    let insert = "INSERT INTO items (name, qty, price, data) VALUES (?1, ?2, ?3, ?4)";
+    let none: Option<i64> = None;
+
+    let changes = (
+        both.conn
+            .execute(insert, params!["a", 1, 1.5, vec![0u8, 255]])
+            .unwrap(),
+        both.sqlite
+            .execute(insert, rusqlite::params!["a", 1, 1.5, vec![0u8, 255]])
+            .unwrap(),
+    );
+    assert_eq!(changes, (1, 1));
+    both.conn.execute(insert, ("b", 2u8, 2.5f32, none)).unwrap();
+    both.sqlite
+        .execute(insert, ("b", 2u8, 2.5f32, none))
+        .unwrap();
+    let numbers = "INSERT INTO items (qty, price) VALUES (?1, ?2)";
+    both.conn.execute(numbers, [3u32, 4]).unwrap();
+    both.sqlite.execute(numbers, [3u32, 4]).unwrap();
+    let names = "INSERT INTO items (name) VALUES (?1), (?2)";
+    both.conn
+        .execute(names, params_from_iter(["d", "e"]))
+        .unwrap();
+    both.sqlite
+        .execute(names, rusqlite::params_from_iter(["d", "e"]))
+        .unwrap();
+
+    let named = "INSERT INTO items (name, qty) VALUES (:name, :qty)";
+    both.conn
+        .execute(named, named_params! {":qty": 7i64, ":name": "h"})
+        .unwrap();
+    both.sqlite
+        .execute(named, rusqlite::named_params! {":qty": 7i64, ":name": "h"})
+        .unwrap();
+    both.conn
+        .execute(named, params![None::<String>, true])
+        .unwrap();
+    both.sqlite
+        .execute(named, rusqlite::params![None::<String>, true])
+        .unwrap();
+
+    let (ours, theirs) = both.items();
+    assert_eq!(ours.len(), 7);
+    assert_eq!(ours, theirs);
+
+    assert!(matches!(
+        both.conn.execute(insert, params!["i", u64::MAX, 0, 0]),
+        Err(Error::InvalidParameter(_))
+    ));
+    assert!(both
+        .sqlite
+        .execute(insert, rusqlite::params!["i", u64::MAX, 0, 0])
+        .is_err());
+}
+
+#[test]
+fn query_row_maps_the_first_row_or_fails_without_one() {
+    let both = start();
+    let insert = "INSERT INTO items (name, qty) VALUES (?1, ?2)";
+    for (name, qty) in [("a", 3), ("b", 1), ("c", 2)] {
+        both.conn.execute(insert, (name, qty)).unwrap();
+        both.sqlite.execute(insert, (name, qty)).unwrap();
+    }
+
+    let sql = "SELECT name, qty FROM items WHERE qty >= ?1 ORDER BY qty";
+    let ours: (String, i64) = both
+        .conn
+        .query_row(sql, [2], |row| Ok((row.get(0)?, row.get("qty")?)))
+        .unwrap();
+    let theirs: (String, i64) = both
+        .sqlite
+        .query_row(sql, [2], |row| Ok((row.get(0)?, row.get("qty")?)))
+        .unwrap();
+    assert_eq!(ours, ("c".to_string(), 2));
+    assert_eq!(ours, theirs);
+
+    let ours = both.conn.query_row(sql, [9], |row| row.get::<_, String>(0));
+    let theirs = both
+        .sqlite
+        .query_row(sql, [9], |row| row.get::<_, String>(0));
+    assert!(matches!(ours, Err(Error::RowCount(0))), "{ours:?}");
+    assert!(
+        matches!(theirs, Err(rusqlite::Error::QueryReturnedNoRows)),
+        "{theirs:?}"
+    );
+
+    let ours = both
+        .conn
+        .query_row(sql, [9], |row| row.get::<_, String>(0))
+        .optional()
+        .unwrap();
+    let theirs = rusqlite::OptionalExtension::optional(
+        both.sqlite
+            .query_row(sql, [9], |row| row.get::<_, String>(0)),
+    )
+    .unwrap();
+    assert_eq!(ours, None);
+    assert_eq!(ours, theirs);
+
+    let mut stmt = both.conn.prepare(sql).unwrap();
+    let ours = stmt.query_row([1], |row| row.get::<_, String>(0)).unwrap();
+    assert_eq!(ours, "b");
+    assert!(matches!(
+        stmt.query_row([9], |row| row.get::<_, String>(0)),
+        Err(Error::RowCount(0))
+    ));
+}
+
+#[test]
+fn query_map_maps_every_row() {
+    let both = start();
+    let insert = "INSERT INTO items (name, price) VALUES (?1, ?2)";
+    for (name, price) in [("a", Some(1.25)), ("b", None), ("c", Some(-3.0))] {
+        both.conn.execute(insert, (name, price)).unwrap();
+        both.sqlite.execute(insert, (name, price)).unwrap();
+    }
+
+    let sql = "SELECT name, price FROM items WHERE name <> ?1 ORDER BY name DESC";
+    for excluded in ["b", "z"] {
+        let ours = both
+            .conn
+            .prepare(sql)
+            .unwrap()
+            .query_map([excluded], |row| {
+                Ok((row.get::<_, String>("name")?, row.get::<_, Option<f64>>(1)?))
+            })
+            .unwrap()
+            .collect::<litesql_ha::Result<Vec<_>>>()
+            .unwrap();
+        let theirs = both
+            .sqlite
+            .prepare(sql)
+            .unwrap()
+            .query_map([excluded], |row| {
+                Ok((row.get::<_, String>("name")?, row.get::<_, Option<f64>>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(ours, theirs, "{excluded}");
+    }
+
+    let mut stmt = both.conn.prepare("SELECT name FROM items WHERE 0").unwrap();
+    assert_eq!(
+        stmt.query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .count(),
+        0
+    );
+    assert!(!stmt.exists([]).unwrap());
+}
+
+#[test]
+fn changes_and_rowids_match() {
+    let both = start();
+    let insert = "INSERT INTO items (name) VALUES (?1)";
+    let ours = both.conn.prepare(insert).unwrap().insert(["a"]).unwrap();
+    let theirs = both.sqlite.prepare(insert).unwrap().insert(["a"]).unwrap();
+    assert_eq!(ours, theirs);
+    both.conn.execute(insert, ["b"]).unwrap();
+    both.sqlite.execute(insert, ["b"]).unwrap();
+    assert_eq!(
+        both.conn.last_insert_rowid(),
+        both.sqlite.last_insert_rowid()
+    );
+
+    let update = "UPDATE items SET qty = 1";
+    assert_eq!(
+        both.conn.execute(update, []).unwrap(),
+        both.sqlite.execute(update, []).unwrap()
+    );
+    assert_eq!(both.conn.changes(), both.sqlite.changes());
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use litesql_ha::embedded_replicas::{EmbeddedReplicasManager, ReplicaOptions};
+use litesql_ha::test_util::{MockServer, DEFAULT_DATABASE};
+use litesql_ha::{HAClient, HAClientOptions, HAConnection, ReplicaSync};
+use std::path::Path;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+async fn client(server: &MockServer) -> Result<HAClient> {
+    Ok(HAClient::new(HAClientOptions {
+        url: server.url(),
+        ..Default::default()
+    })
+    .await?)
+}
+
+/// Load replicas from `directory`, downloading the default database.
+async fn manager(server: &MockServer, directory: &Path) -> Result<EmbeddedReplicasManager> {
+    let manager = EmbeddedReplicasManager::new();
+    manager.set_download_client(Arc::new(client(server).await?));
+    manager
+        .load(ReplicaOptions {
+            directory: directory.to_path_buf(),
+            ..Default::default()
+        })
+        .await?;
+    manager.download_replica(DEFAULT_DATABASE).await?;
+    Ok(manager)
+}
+
+async fn insert(conn: &HAConnection, name: &str) -> Result<()> {
+    conn.execute("INSERT INTO users (name) VALUES (?)", &[name.into()])
+        .await?;
+    Ok(())
+}
+
+fn count_users(manager: &EmbeddedReplicasManager) -> Result<i64> {
+    let replica = manager.get_replica(DEFAULT_DATABASE).ok_or("no replica")?;
+    let conn = replica.create_connection()?;
+    Ok(conn.query_row("SELECT count(*) FROM users", [], |row| row.get(0))?)
+}
+
+#[tokio::test]
+async fn sync_applies_the_pages_changed_since_the_replica() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+    let directory = tempfile::tempdir()?;
+    let manager = manager(&server, directory.path()).await?;
+
+    insert(&conn, "alice").await?;
+    insert(&conn, "bob").await?;
+    let sync = manager.sync_replica(DEFAULT_DATABASE).await?;
+    assert!(matches!(sync, ReplicaSync::Incremental(pages) if pages > 0));
+    assert_eq!(count_users(&manager)?, 2);
+    let replica = manager.get_replica(DEFAULT_DATABASE).ok_or("no replica")?;
+    assert_eq!(Some(replica.get_txseq()), server.txseq(DEFAULT_DATABASE));
+
+    // Synced again from the snapshot it was patched to
+    insert(&conn, "carol").await?;
+    let sync = manager.sync_replica(DEFAULT_DATABASE).await?;
+    assert!(matches!(sync, ReplicaSync::Incremental(pages) if pages > 0));
+    assert_eq!(count_users(&manager)?, 3);
+    assert_eq!(
+        manager.sync_replica(DEFAULT_DATABASE).await?,
+        ReplicaSync::Incremental(0)
+    );
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn pruned_history_downloads_a_snapshot() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+    let directory = tempfile::tempdir()?;
+    let manager = manager(&server, directory.path()).await?;
+
+    insert(&conn, "alice").await?;
+    server.prune_history(DEFAULT_DATABASE);
+    assert_eq!(
+        manager.sync_replica(DEFAULT_DATABASE).await?,
+        ReplicaSync::Snapshot
+    );
+    assert_eq!(count_users(&manager)?, 1);
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn frames_without_a_checksum_download_a_snapshot() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+    let directory = tempfile::tempdir()?;
+    let manager = manager(&server, directory.path()).await?;
+
+    insert(&conn, "alice").await?;
+    server.omit_frame_checksums(true);
+    assert_eq!(
+        manager.sync_replica(DEFAULT_DATABASE).await?,
+        ReplicaSync::Snapshot
+    );
+    assert_eq!(count_users(&manager)?, 1);
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
+
+#[cfg(feature = "nats")]
+#[tokio::test]
+async fn replica_changed_by_replication_downloads_a_snapshot() -> Result<()> {
+    let server = common::start().await?;
+    server.execute_batch("CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT)")?;
+    let conn = common::connect(&server).await?;
+    let directory = tempfile::tempdir()?;
+    let manager = manager(&server, directory.path()).await?;
+
+    // The server keeps its snapshot at the txseq the replica replicates to
+    insert(&conn, "alice").await?;
+    let elsewhere = tempfile::tempdir()?;
+    client(&server)
+        .await?
+        .download_replica(elsewhere.path(), DEFAULT_DATABASE, false)
+        .await?;
+    let message = format!(
+        r#"{{"filename": "{}", "changes": [{{"operation": "INSERT", "table": "users",
+            "columns": ["id", "name"], "new_rowid": 1, "new_values": [1, "alice"]}},
+            {{"operation": "INSERT", "table": "tags", "columns": ["id", "name"],
+            "new_rowid": 1, "new_values": [1, "local"]}}]}}"#,
+        DEFAULT_DATABASE
+    );
+    assert!(manager.apply_message(message.as_bytes(), 1).await?);
+
+    // Patching the users' pages leaves the tag only the replica has
+    insert(&conn, "bob").await?;
+    assert_eq!(
+        manager.sync_replica(DEFAULT_DATABASE).await?,
+        ReplicaSync::Snapshot
+    );
+    assert_eq!(count_users(&manager)?, 2);
+    let replica = manager.get_replica(DEFAULT_DATABASE).ok_or("no replica")?;
+    let tags: i64 =
+        replica
+            .create_connection()?
+            .query_row("SELECT count(*) FROM tags", [], |row| row.get(0))?;
+    assert_eq!(tags, 0);
+
+    manager.close().await;
+    server.shutdown().await;
+    Ok(())
+}
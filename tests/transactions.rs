@@ -0,0 +1,72 @@
+mod common;
+
+use litesql_ha::{Error, HAConnection, HAConnectionOptions, Result, Value};
+
+async fn count_users(conn: &HAConnection) -> Result<i64> {
+    let result = conn.query("SELECT count(*) FROM users", &[]).await?;
+    match result.rows.first().and_then(|row| row.first()) {
+        Some(Value::Int64(n)) => Ok(*n),
+        other => Err(Error::TypeConversion(format!("not a count: {:?}", other))),
+    }
+}
+
+#[tokio::test]
+async fn dropped_transaction_rolls_back() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    {
+        let tx = conn.transaction().await?;
+        tx.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+            .await?;
+    }
+
+    assert_eq!(count_users(&conn).await?, 0);
+    assert!(conn.auto_commit());
+    assert!(server.queries().iter().any(|sql| sql == "ROLLBACK"));
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn committed_transaction_is_kept() -> Result<()> {
+    let server = common::start().await?;
+    let conn = common::connect(&server).await?;
+
+    let tx = conn.transaction().await?;
+    tx.execute("INSERT INTO users (name) VALUES (?)", &["alice".into()])
+        .await?;
+    tx.commit().await?;
+
+    assert_eq!(count_users(&conn).await?, 1);
+    assert!(!server.queries().iter().any(|sql| sql == "ROLLBACK"));
+    server.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn sticky_transaction_stays_on_one_stream() -> Result<()> {
+    let server = common::start().await?;
+    let conn = HAConnection::new(HAConnectionOptions {
+        sticky_transactions: true,
+        ..common::options(&server)
+    })
+    .await?;
+
+    let before = server.query_streams();
+    conn.begin_transaction().await?;
+    for name in ["alice", "bob", "carol"] {
+        conn.execute("INSERT INTO users (name) VALUES (?)", &[name.into()])
+            .await?;
+    }
+    let count = count_users(&conn).await?;
+    conn.commit().await?;
+    assert_eq!(count, 3);
+    assert_eq!(server.query_streams() - before, 1);
+
+    // The next statement is outside the transaction, on a stream of its own
+    conn.execute("DELETE FROM users", &[]).await?;
+    assert_eq!(server.query_streams() - before, 2);
+    server.shutdown().await;
+    Ok(())
+}